@@ -4,4 +4,4 @@ import type { EventInner } from "./EventInner";
 import type { EventLevel } from "./EventLevel";
 import type { Snowflake } from "./Snowflake";
 
-export interface ClientEvent { event_inner: EventInner, details: string, snowflake: Snowflake, level: EventLevel, caused_by: CausedBy, }
\ No newline at end of file
+export interface ClientEvent { event_inner: EventInner, details: string, snowflake: Snowflake, level: EventLevel, caused_by: CausedBy, correlation_id?: string, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ParsedCommandOutput } from "./ParsedCommandOutput";
+
+export interface CommandResult { output: string | null, parsed: ParsedCommandOutput | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ConsoleBufferLimits { max_lines: number, max_bytes: number, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface ConsoleLogSettings { enabled: boolean, max_bytes: bigint, max_files: number, }
\ No newline at end of file
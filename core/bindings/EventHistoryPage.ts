@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ClientEvent } from "./ClientEvent";
+
+export interface EventHistoryPage { events: Array<ClientEvent>, next_cursor: bigint | null, }
\ No newline at end of file
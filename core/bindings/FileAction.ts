@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type FileAction = "list" | "read" | "edit" | "download" | "upload" | "copy" | "move" | "delete" | "zip" | "unzip";
\ No newline at end of file
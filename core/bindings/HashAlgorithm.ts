@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type HashAlgorithm = "md5" | "sha1" | "sha256";
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { InstanceActor } from "./InstanceActor";
+
+export interface InstanceAudit { created_by: InstanceActor | null, last_modified_by: InstanceActor | null, last_modified_at: bigint | null, }
\ No newline at end of file
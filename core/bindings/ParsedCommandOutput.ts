@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type ParsedCommandOutput = { "command": "list", online: number, max: number, players: Array<string>, } | { "command": "seed", seed: string, } | { "command": "whitelist_list", players: Array<string>, };
\ No newline at end of file
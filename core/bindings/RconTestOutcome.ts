@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export type RconTestOutcome = "connected" | "disabled" | "misconfigured" | "not_running" | "connection_refused" | "wrong_password" | "timed_out" | "unreachable";
\ No newline at end of file
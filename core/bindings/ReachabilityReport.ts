@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ReachabilityStatus } from "./ReachabilityStatus";
+
+export interface ReachabilityReport { port: number, listening_locally: boolean, status: ReachabilityStatus, public_address: string | null, message: string | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface RedactedRemoteBackupTarget { endpoint: string, bucket: string, region: string, access_key_id: string, prefix: string | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SearchTarget } from "./SearchTarget";
+
+export interface SearchRequest { query: string, glob: string | null, case_sensitive: boolean, target: SearchTarget, max_results: number | null, }
\ No newline at end of file
@@ -240,7 +240,11 @@ impl User {
     }
 
     pub fn can_view_event(&self, event: impl AsRef<Event>) -> bool {
-        match &event.as_ref().event_inner {
+        self.can_view_event_inner(&event.as_ref().event_inner)
+    }
+
+    pub fn can_view_event_inner(&self, event_inner: &EventInner) -> bool {
+        match event_inner {
             EventInner::InstanceEvent(event) => {
                 self.can_perform_action(&UserAction::ViewInstance(event.instance_uuid.clone()))
             }
@@ -269,6 +273,48 @@ impl User {
     }
 }
 
+/// A requester resolved once for a request, along with the safe mode at that time.
+///
+/// Batch operations check every target against it instead of going back to the users manager
+/// and the global settings for each one
+#[derive(Clone)]
+pub struct AuthorizedUser {
+    user: User,
+    safe_mode: bool,
+}
+
+impl AuthorizedUser {
+    pub fn new(user: User, safe_mode: bool) -> Self {
+        Self { user, safe_mode }
+    }
+
+    pub fn try_action(&self, action: &UserAction) -> Result<(), Error> {
+        self.user.try_action(action, self.safe_mode)
+    }
+
+    /// Check every action of a batch, fails on the first one that is denied
+    pub fn try_actions<'a>(
+        &self,
+        actions: impl IntoIterator<Item = &'a UserAction>,
+    ) -> Result<(), Error> {
+        actions
+            .into_iter()
+            .try_for_each(|action| self.try_action(action))
+    }
+
+    pub fn into_user(self) -> User {
+        self.user
+    }
+}
+
+impl std::ops::Deref for AuthorizedUser {
+    type Target = User;
+
+    fn deref(&self) -> &User {
+        &self.user
+    }
+}
+
 pub enum UserAction {
     // instance specific actions:
     ViewInstance(InstanceUuid),
@@ -447,6 +493,7 @@ impl UsersManager {
                     details: "".to_string(),
                     snowflake: Snowflake::default(),
                     caused_by,
+                    correlation_id: None,
                 });
                 Ok(())
             }
@@ -473,6 +520,7 @@ impl UsersManager {
                         details: "".to_string(),
                         snowflake: Snowflake::default(),
                         caused_by,
+                        correlation_id: None,
                     });
                 }
             }
@@ -514,6 +562,7 @@ impl UsersManager {
                     details: "".to_string(),
                     snowflake: Snowflake::default(),
                     caused_by,
+                    correlation_id: None,
                 });
                 Ok(())
             }
@@ -555,6 +604,7 @@ impl UsersManager {
                         details: "".to_string(),
                         snowflake: Snowflake::default(),
                         caused_by,
+                        correlation_id: None,
                     });
                     Ok(())
                 }
@@ -613,6 +663,7 @@ impl UsersManager {
                     details: "".to_string(),
                     snowflake: Snowflake::default(),
                     caused_by: caused_by.clone(),
+                    correlation_id: None,
                 });
                 self.logout_user(uid, caused_by).await
             }
@@ -662,6 +713,7 @@ impl UsersManager {
                     details: "".to_string(),
                     snowflake: Snowflake::default(),
                     caused_by,
+                    correlation_id: None,
                 });
                 Ok(())
             }
@@ -691,6 +743,12 @@ impl UsersManager {
         })
     }
 
+    /// Resolve the requester once, to be reused for every check of the request
+    pub fn try_authorize(&self, token: &str, safe_mode: bool) -> Result<AuthorizedUser, Error> {
+        self.try_auth_or_err(token)
+            .map(|user| AuthorizedUser::new(user, safe_mode))
+    }
+
     pub fn login(
         &self,
         username: impl AsRef<str>,
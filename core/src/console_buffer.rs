@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::events::{Event, EventInner, InstanceEventInner};
+use crate::sidecar::{read_sidecar, write_sidecar};
+
+/// Absent while the defaults are used
+pub const CONSOLE_BUFFER_LIMITS_FILE_NAME: &str = ".lodestone_console.json";
+
+const MAX_LINES_RANGE: std::ops::RangeInclusive<usize> = 1..=100_000;
+const MAX_BYTES_RANGE: std::ops::RangeInclusive<usize> = 1024..=64 * 1024 * 1024;
+
+/// How much console output is kept in memory for an instance, whichever limit is hit first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ConsoleBufferLimits {
+    pub max_lines: usize,
+    pub max_bytes: usize,
+}
+
+impl Default for ConsoleBufferLimits {
+    fn default() -> Self {
+        Self {
+            max_lines: 1024,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl ConsoleBufferLimits {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !MAX_LINES_RANGE.contains(&self.max_lines) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "max_lines must be between {} and {}",
+                    MAX_LINES_RANGE.start(),
+                    MAX_LINES_RANGE.end()
+                ),
+            });
+        }
+        if !MAX_BYTES_RANGE.contains(&self.max_bytes) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "max_bytes must be between {} and {}",
+                    MAX_BYTES_RANGE.start(),
+                    MAX_BYTES_RANGE.end()
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Size of the text of a console event, other events don't count towards the byte limit
+fn console_event_size(event: &Event) -> usize {
+    match &event.event_inner {
+        EventInner::InstanceEvent(instance_event) => match &instance_event.instance_event_inner {
+            InstanceEventInner::InstanceOutput { message }
+            | InstanceEventInner::SystemMessage { message } => message.len(),
+            InstanceEventInner::PlayerMessage {
+                player,
+                player_message,
+            } => player.len() + player_message.len(),
+            _ => 0,
+        },
+        _ => 0,
+    }
+}
+
+/// Console output of an instance, the oldest lines are dropped once a limit is exceeded
+#[derive(Debug, Clone, Default)]
+pub struct ConsoleBuffer {
+    events: VecDeque<Event>,
+    bytes: usize,
+    limits: ConsoleBufferLimits,
+}
+
+impl ConsoleBuffer {
+    pub fn new(limits: ConsoleBufferLimits) -> Self {
+        Self {
+            events: VecDeque::new(),
+            bytes: 0,
+            limits,
+        }
+    }
+
+    pub fn limits(&self) -> ConsoleBufferLimits {
+        self.limits
+    }
+
+    /// Lowering the limits trims the buffer right away
+    pub fn set_limits(&mut self, limits: ConsoleBufferLimits) {
+        self.limits = limits;
+        self.trim();
+    }
+
+    pub fn push(&mut self, event: Event) {
+        self.bytes += console_event_size(&event);
+        self.events.push_back(event);
+        self.trim();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+    }
+
+    // the newest line is always kept, even if it is larger than the byte limit on its own
+    fn trim(&mut self) {
+        while self.events.len() > 1
+            && (self.events.len() > self.limits.max_lines || self.bytes > self.limits.max_bytes)
+        {
+            if let Some(event) = self.events.pop_front() {
+                self.bytes -= console_event_size(&event);
+            }
+        }
+    }
+}
+
+/// Missing or unreadable files read as the defaults
+pub async fn read_console_buffer_limits(path_to_instance: &Path) -> ConsoleBufferLimits {
+    read_sidecar(
+        path_to_instance,
+        CONSOLE_BUFFER_LIMITS_FILE_NAME,
+        "console buffer limits",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn write_console_buffer_limits(
+    path_to_instance: &Path,
+    limits: &ConsoleBufferLimits,
+) -> Result<(), Error> {
+    write_sidecar(
+        path_to_instance,
+        CONSOLE_BUFFER_LIMITS_FILE_NAME,
+        "console buffer limits",
+        limits,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::InstanceUuid;
+
+    fn output(line: &str) -> Event {
+        Event::new_instance_output(
+            InstanceUuid::from("INSTANCE_test".to_string()),
+            "test".to_string(),
+            line.to_string(),
+        )
+    }
+
+    fn messages(buffer: &ConsoleBuffer) -> Vec<String> {
+        buffer
+            .iter()
+            .map(|event| match &event.event_inner {
+                EventInner::InstanceEvent(instance_event) => {
+                    match &instance_event.instance_event_inner {
+                        InstanceEventInner::InstanceOutput { message } => message.clone(),
+                        _ => unreachable!(),
+                    }
+                }
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_buffer_caps_lines_and_keeps_newest() {
+        let mut buffer = ConsoleBuffer::new(ConsoleBufferLimits {
+            max_lines: 3,
+            max_bytes: 1024,
+        });
+        for i in 0..10 {
+            buffer.push(output(&format!("line {i}")));
+        }
+        assert_eq!(messages(&buffer), vec!["line 7", "line 8", "line 9"]);
+
+        buffer.set_limits(ConsoleBufferLimits {
+            max_lines: 2,
+            max_bytes: 1024,
+        });
+        assert_eq!(messages(&buffer), vec!["line 8", "line 9"]);
+    }
+
+    #[test]
+    fn test_buffer_caps_bytes_and_keeps_newest() {
+        let mut buffer = ConsoleBuffer::new(ConsoleBufferLimits {
+            max_lines: 1000,
+            max_bytes: 25,
+        });
+        for i in 0..10 {
+            buffer.push(output(&format!("0123456789 {i}")));
+        }
+        // 12 bytes a line
+        assert_eq!(buffer.iter().count(), 2);
+        assert_eq!(messages(&buffer), vec!["0123456789 8", "0123456789 9"]);
+
+        let huge = "x".repeat(100);
+        buffer.push(output(&huge));
+        assert_eq!(messages(&buffer), vec![huge]);
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_persisted_and_validated() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(
+            read_console_buffer_limits(path).await,
+            ConsoleBufferLimits::default()
+        );
+        let limits = ConsoleBufferLimits {
+            max_lines: 200,
+            max_bytes: 64 * 1024,
+        };
+        limits.validate().unwrap();
+        write_console_buffer_limits(path, &limits).await.unwrap();
+        assert_eq!(read_console_buffer_limits(path).await, limits);
+
+        assert!(ConsoleBufferLimits {
+            max_lines: 0,
+            ..limits
+        }
+        .validate()
+        .is_err());
+        assert!(ConsoleBufferLimits {
+            max_bytes: usize::MAX,
+            ..limits
+        }
+        .validate()
+        .is_err());
+    }
+}
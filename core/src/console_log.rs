@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Mutex};
+use tokio::task::JoinHandle;
+use tracing::error;
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::events::{Event, EventInner, InstanceEventInner};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+use crate::types::InstanceUuid;
+
+/// Absent while the console isn't persisted
+pub const CONSOLE_LOG_SETTINGS_FILE_NAME: &str = ".lodestone_console_log.json";
+/// Directory of the persisted console, relative to the instance directory. Hidden from listings
+/// and can't be deleted through the file API
+pub const CONSOLE_LOG_DIR: &str = "lodestone";
+/// The live file, rotated files are suffixed `.1` (newest) to `.<max_files>`
+pub const CONSOLE_LOG_FILE_NAME: &str = "console.log";
+
+const MAX_BYTES_RANGE: std::ops::RangeInclusive<u64> = 1024..=1024 * 1024 * 1024;
+const MAX_FILES_RANGE: std::ops::RangeInclusive<u32> = 1..=20;
+
+/// Whether the console output of an instance is persisted to disk, and how much of it is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ConsoleLogSettings {
+    pub enabled: bool,
+    /// size a file is rotated at
+    pub max_bytes: u64,
+    /// rotated files kept next to the live one, the oldest is deleted past it
+    pub max_files: u32,
+}
+
+impl Default for ConsoleLogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_bytes: 8 * 1024 * 1024,
+            max_files: 3,
+        }
+    }
+}
+
+impl ConsoleLogSettings {
+    pub fn validate(&self) -> Result<(), Error> {
+        if !MAX_BYTES_RANGE.contains(&self.max_bytes) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "max_bytes must be between {} and {}",
+                    MAX_BYTES_RANGE.start(),
+                    MAX_BYTES_RANGE.end()
+                ),
+            });
+        }
+        if !MAX_FILES_RANGE.contains(&self.max_files) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "max_files must be between {} and {}",
+                    MAX_FILES_RANGE.start(),
+                    MAX_FILES_RANGE.end()
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Whether `path` is the console log directory of the instance at `root`, or in it
+pub fn is_in_console_log_dir(root: &Path, path: &Path) -> bool {
+    path.starts_with(root.join(CONSOLE_LOG_DIR))
+}
+
+/// Line of server output of a console event, other console events aren't persisted
+pub fn console_output_line(event: &Event) -> Option<&str> {
+    match &event.event_inner {
+        EventInner::InstanceEvent(instance_event) => match &instance_event.instance_event_inner {
+            InstanceEventInner::InstanceOutput { message } => Some(message),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The file the console output of an instance is appended to, rotated once it reaches the size
+/// cap. Only ever written from the writer task of its [`ConsoleLog`]
+#[derive(Debug)]
+struct ConsoleLogFile {
+    dir: PathBuf,
+    settings: ConsoleLogSettings,
+    // opened on the first line after a start or a rotation
+    file: Option<(BufWriter<File>, u64)>,
+}
+
+impl ConsoleLogFile {
+    fn new(path_to_instance: &Path, settings: ConsoleLogSettings) -> Self {
+        Self {
+            dir: path_to_instance.join(CONSOLE_LOG_DIR),
+            settings,
+            file: None,
+        }
+    }
+
+    fn set_settings(&mut self, settings: ConsoleLogSettings) -> Result<(), Error> {
+        self.settings = settings;
+        if !settings.enabled {
+            self.close()?;
+        }
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        self.dir.join(format!("{CONSOLE_LOG_FILE_NAME}.{n}"))
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if let Some((file, _)) = &mut self.file {
+            file.flush().context("Failed to write to the console log")?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Error> {
+        self.flush()?;
+        self.file = None;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> Result<(), Error> {
+        self.close()?;
+        let oldest = self.rotated_path(self.settings.max_files);
+        if oldest.exists() {
+            std::fs::remove_file(&oldest)
+                .context(format!("Failed to remove {}", oldest.display()))?;
+        }
+        for n in (1..self.settings.max_files).rev() {
+            let from = self.rotated_path(n);
+            if from.exists() {
+                std::fs::rename(&from, self.rotated_path(n + 1))
+                    .context(format!("Failed to rotate {}", from.display()))?;
+            }
+        }
+        let live = self.dir.join(CONSOLE_LOG_FILE_NAME);
+        std::fs::rename(&live, self.rotated_path(1))
+            .context(format!("Failed to rotate {}", live.display()))?;
+        Ok(())
+    }
+
+    /// Append a line, does nothing unless the log is enabled. The newest line is always written,
+    /// even if it is larger than the cap on its own
+    fn append(&mut self, line: &str) -> Result<(), Error> {
+        if !self.settings.enabled {
+            return Ok(());
+        }
+        let line_len = line.len() as u64 + 1;
+        if let Some((_, size)) = &self.file {
+            if *size > 0 && size + line_len > self.settings.max_bytes {
+                self.rotate()?;
+            }
+        }
+        let (mut file, size) = match self.file.take() {
+            Some(file) => file,
+            None => {
+                std::fs::create_dir_all(&self.dir)
+                    .context(format!("Failed to create {}", self.dir.display()))?;
+                let path = self.dir.join(CONSOLE_LOG_FILE_NAME);
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .context(format!("Failed to open {}", path.display()))?;
+                let size = file
+                    .metadata()
+                    .context(format!("Failed to read metadata of {}", path.display()))?
+                    .len();
+                (BufWriter::new(file), size)
+            }
+        };
+        writeln!(file, "{line}").context("Failed to write to the console log")?;
+        self.file = Some((file, size + line_len));
+        Ok(())
+    }
+
+    fn apply(&mut self, command: WriterCommand) -> Result<(), Error> {
+        match command {
+            WriterCommand::Line(line) => self.append(&line),
+            WriterCommand::Settings(settings) => self.set_settings(settings),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WriterCommand {
+    Line(String),
+    Settings(ConsoleLogSettings),
+}
+
+/// Console output of an instance persisted to disk. Lines are queued to a writer task, which
+/// writes whatever is queued on a blocking thread and flushes it, so a slow disk never holds up
+/// the caller
+#[derive(Debug)]
+pub struct ConsoleLog {
+    settings: ConsoleLogSettings,
+    commands: mpsc::UnboundedSender<WriterCommand>,
+    writer: JoinHandle<()>,
+}
+
+impl ConsoleLog {
+    pub fn new(path_to_instance: &Path, settings: ConsoleLogSettings) -> Self {
+        let (commands, mut queued) = mpsc::unbounded_channel();
+        let mut file = ConsoleLogFile::new(path_to_instance, settings);
+        let writer = tokio::task::spawn(async move {
+            while let Some(command) = queued.recv().await {
+                let mut batch = vec![command];
+                while let Ok(command) = queued.try_recv() {
+                    batch.push(command);
+                }
+                let written = tokio::task::spawn_blocking(move || {
+                    for command in batch {
+                        file.apply(command).map_err(Error::log).ok();
+                    }
+                    file.flush().map_err(Error::log).ok();
+                    file
+                })
+                .await;
+                match written {
+                    Ok(written) => file = written,
+                    Err(e) => {
+                        error!("Console log writer stopped: {e}");
+                        return;
+                    }
+                }
+            }
+        });
+        Self {
+            settings,
+            commands,
+            writer,
+        }
+    }
+
+    pub fn settings(&self) -> ConsoleLogSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ConsoleLogSettings) {
+        self.settings = settings;
+        self.commands.send(WriterCommand::Settings(settings)).ok();
+    }
+
+    /// Queue a line, does nothing unless the log is enabled
+    pub fn append(&self, line: &str) {
+        if self.settings.enabled {
+            self.commands
+                .send(WriterCommand::Line(line.to_string()))
+                .ok();
+        }
+    }
+
+    /// Write the lines still queued and stop the writer
+    pub async fn close(self) {
+        drop(self.commands);
+        self.writer
+            .await
+            .map_err(|e| error!("Console log writer stopped: {e}"))
+            .ok();
+    }
+}
+
+/// Stop persisting the console of an instance, e.g. once it is deleted. The lines already queued
+/// are written first
+pub async fn forget_console_log(
+    console_logs: &Mutex<HashMap<InstanceUuid, ConsoleLog>>,
+    uuid: &InstanceUuid,
+) {
+    let console_log = console_logs.lock().await.remove(uuid);
+    if let Some(console_log) = console_log {
+        console_log.close().await;
+    }
+}
+
+/// Persist the console of an instance moved to `path_to_instance` there from now on
+pub async fn move_console_log(
+    console_logs: &Mutex<HashMap<InstanceUuid, ConsoleLog>>,
+    uuid: &InstanceUuid,
+    path_to_instance: &Path,
+) {
+    let console_log = console_logs.lock().await.remove(uuid);
+    if let Some(console_log) = console_log {
+        let settings = console_log.settings();
+        console_log.close().await;
+        console_logs
+            .lock()
+            .await
+            .insert(uuid.clone(), ConsoleLog::new(path_to_instance, settings));
+    }
+}
+
+/// Missing or unreadable files read as the defaults, the console isn't persisted
+pub async fn read_console_log_settings(path_to_instance: &Path) -> ConsoleLogSettings {
+    read_sidecar(
+        path_to_instance,
+        CONSOLE_LOG_SETTINGS_FILE_NAME,
+        "console log settings",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn write_console_log_settings(
+    path_to_instance: &Path,
+    settings: &ConsoleLogSettings,
+) -> Result<(), Error> {
+    if *settings == ConsoleLogSettings::default() {
+        return remove_sidecar(path_to_instance, CONSOLE_LOG_SETTINGS_FILE_NAME).await;
+    }
+    write_sidecar(
+        path_to_instance,
+        CONSOLE_LOG_SETTINGS_FILE_NAME,
+        "console log settings",
+        settings,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: PathBuf) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_lines_are_persisted_and_rotated_at_the_cap() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let dir = root.join(CONSOLE_LOG_DIR);
+        let mut log = ConsoleLogFile::new(root, ConsoleLogSettings::default());
+        log.append("not persisted").unwrap();
+        assert!(!dir.exists());
+
+        let event = Event::new_instance_output(
+            InstanceUuid::from("INSTANCE_test".to_string()),
+            "test".to_string(),
+            "[12:00:00] [Server thread/INFO]: Done (3.2s)!".to_string(),
+        );
+        // 10 bytes a line
+        log.set_settings(ConsoleLogSettings {
+            enabled: true,
+            max_bytes: 30,
+            max_files: 2,
+        })
+        .unwrap();
+        log.append(console_output_line(&event).unwrap()).unwrap();
+        log.flush().unwrap();
+        assert_eq!(
+            read(dir.join(CONSOLE_LOG_FILE_NAME)),
+            "[12:00:00] [Server thread/INFO]: Done (3.2s)!\n"
+        );
+        // the first line, over the cap on its own, was rotated out of the kept files
+        for i in 0..7 {
+            log.append(&format!("line {i:04}")).unwrap();
+        }
+        log.flush().unwrap();
+        assert_eq!(read(dir.join(CONSOLE_LOG_FILE_NAME)), "line 0006\n");
+        assert_eq!(
+            read(dir.join("console.log.1")),
+            "line 0003\nline 0004\nline 0005\n"
+        );
+        assert_eq!(
+            read(dir.join("console.log.2")),
+            "line 0000\nline 0001\nline 0002\n"
+        );
+        assert!(!dir.join("console.log.3").exists());
+
+        // history survives a restart of the daemon, appended to where it was
+        let mut log = ConsoleLogFile::new(root, log.settings);
+        log.append("line 0007").unwrap();
+        log.flush().unwrap();
+        assert_eq!(
+            read(dir.join(CONSOLE_LOG_FILE_NAME)),
+            "line 0006\nline 0007\n"
+        );
+
+        assert!(is_in_console_log_dir(root, &dir));
+        assert!(is_in_console_log_dir(root, &dir.join("console.log.1")));
+        assert!(!is_in_console_log_dir(root, &root.join("logs/latest.log")));
+    }
+
+    #[tokio::test]
+    async fn test_queued_lines_are_written_by_the_writer_task() {
+        let (old, new) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let uuid = InstanceUuid::from("INSTANCE_test".to_string());
+        let settings = ConsoleLogSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        let console_log = ConsoleLog::new(old.path(), settings);
+        for i in 0..100 {
+            console_log.append(&format!("line {i:04}"));
+        }
+        let console_logs = Mutex::new(HashMap::from([(uuid.clone(), console_log)]));
+
+        // the queued lines land in the old directory, the next ones in the new
+        move_console_log(&console_logs, &uuid, new.path()).await;
+        let log_path = |root: &Path| root.join(CONSOLE_LOG_DIR).join(CONSOLE_LOG_FILE_NAME);
+        assert_eq!(read(log_path(old.path())).lines().count(), 100);
+        console_logs
+            .lock()
+            .await
+            .get(&uuid)
+            .unwrap()
+            .append("line 0100");
+        forget_console_log(&console_logs, &uuid).await;
+        assert!(console_logs.lock().await.is_empty());
+        assert_eq!(read(log_path(new.path())), "line 0100\n");
+    }
+
+    #[tokio::test]
+    async fn test_settings_are_persisted_and_validated() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(
+            read_console_log_settings(path).await,
+            ConsoleLogSettings::default()
+        );
+        let settings = ConsoleLogSettings {
+            enabled: true,
+            ..Default::default()
+        };
+        settings.validate().unwrap();
+        write_console_log_settings(path, &settings).await.unwrap();
+        assert_eq!(read_console_log_settings(path).await, settings);
+
+        assert!(ConsoleLogSettings {
+            max_bytes: 0,
+            ..settings
+        }
+        .validate()
+        .is_err());
+        assert!(ConsoleLogSettings {
+            max_files: 0,
+            ..settings
+        }
+        .validate()
+        .is_err());
+
+        write_console_log_settings(path, &ConsoleLogSettings::default())
+            .await
+            .unwrap();
+        assert!(!path.join(CONSOLE_LOG_SETTINGS_FILE_NAME).exists());
+    }
+}
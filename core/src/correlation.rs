@@ -0,0 +1,121 @@
+use std::future::Future;
+
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+use crate::util::rand_alphanumeric;
+
+/// Response header carrying the correlation id of the request
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// Correlation id of the request being handled by the current task, if any
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Run `future` under the correlation id and span of the current request, for work a handler
+/// spawns that outlives it. Outside of a request the future is returned as is
+pub fn in_current_request<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let id = current_correlation_id();
+    let future = future.instrument(tracing::Span::current());
+    async move {
+        match id {
+            Some(id) => CORRELATION_ID.scope(id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// Give every request a correlation id, returned in the `x-correlation-id` header. Events sent
+/// while handling the request carry the id, and so do the log lines through the `request` span
+pub async fn correlate<B>(request: Request<B>, next: Next<B>) -> Response {
+    let id = rand_alphanumeric(16);
+    let span = tracing::info_span!("request", correlation_id = %id);
+    let mut response = CORRELATION_ID
+        .scope(id.clone(), next.run(request).instrument(span))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(CORRELATION_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{extract, routing::put, Router};
+
+    use super::*;
+    use crate::event_broadcaster::EventBroadcaster;
+    use crate::events::{new_fs_event, CausedBy, FSOperation, FSTarget};
+
+    async fn write_and_report(extract::State(event_broadcaster): extract::State<EventBroadcaster>) {
+        event_broadcaster.send(new_fs_event(
+            FSOperation::Write,
+            FSTarget::File("server.properties".into()),
+            CausedBy::System,
+        ));
+        // work outliving the handler
+        tokio::spawn(in_current_request(async move {
+            event_broadcaster.send(new_fs_event(
+                FSOperation::Write,
+                FSTarget::File("ops.json".into()),
+                CausedBy::System,
+            ));
+        }))
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_on_header_and_events() {
+        let (event_broadcaster, mut rx) = EventBroadcaster::new(16);
+        let app = Router::new()
+            .route("/instance/fs/write", put(write_and_report))
+            .with_state(event_broadcaster.clone())
+            .layer(axum::middleware::from_fn(correlate));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://127.0.0.1:{}/instance/fs/write",
+            listener.local_addr().unwrap().port()
+        );
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+
+        let client = reqwest::Client::new();
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let response = client.put(&url).send().await.unwrap();
+            let id = response.headers()[CORRELATION_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            assert_eq!(id.len(), 16);
+            // the event of the handler then the one of the task it spawned
+            for _ in 0..2 {
+                assert_eq!(rx.recv().await.unwrap().correlation_id, Some(id.clone()));
+            }
+            ids.push(id);
+        }
+        // every request gets its own id
+        assert_ne!(ids[0], ids[1]);
+
+        // events sent outside of a request have none
+        event_broadcaster.send(new_fs_event(
+            FSOperation::Read,
+            FSTarget::File("eula.txt".into()),
+            CausedBy::System,
+        ));
+        assert_eq!(rx.recv().await.unwrap().correlation_id, None);
+    }
+}
@@ -1,10 +1,18 @@
 use crate::{
-    error::Error, events::EventQuery, output_types::ClientEvent, prelude::LODESTONE_EPOCH_MIL,
+    error::Error,
+    events::{EventQuery, EventType},
+    output_types::ClientEvent,
+    prelude::LODESTONE_EPOCH_MIL,
+    types::InstanceUuid,
 };
 
-use color_eyre::eyre::Context;
-use sqlx::sqlite::SqlitePool;
+use color_eyre::eyre::{eyre, Context};
+use serde::Serialize;
+use sqlx::{sqlite::SqlitePool, QueryBuilder, Row, Sqlite};
 use tracing::error;
+use ts_rs::TS;
+
+use super::write::EVENT_KIND_EXPRESSION;
 
 // TODO clean up all unwraps
 
@@ -68,6 +76,91 @@ FROM ClientEvents"#
     Ok(filtered)
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct EventHistoryQuery {
+    pub instance: Option<InstanceUuid>,
+    pub kind: Option<EventType>,
+    /// unix timestamp in milliseconds
+    pub since: Option<i64>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<i64>,
+    pub limit: u32,
+}
+
+/// A page of the event history, oldest first
+#[derive(Serialize, Debug, TS)]
+#[ts(export)]
+pub struct EventHistoryPage {
+    pub events: Vec<ClientEvent>,
+    /// passed as the cursor to get the next page, `None` once the history is exhausted
+    pub next_cursor: Option<i64>,
+}
+
+/// Page through the persisted events, every filter is served by an index of the table.
+///
+/// Events `visible` rejects are left out of the page, so a page can hold fewer events than
+/// the limit while more follow
+pub async fn query_event_history(
+    pool: &SqlitePool,
+    query: &EventHistoryQuery,
+    visible: impl Fn(&ClientEvent) -> bool,
+) -> Result<EventHistoryPage, Error> {
+    let mut connection = pool
+        .acquire()
+        .await
+        .context("Failed to aquire connection to db")?;
+    let mut builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new("SELECT id, event_value FROM ClientEvents WHERE id > ");
+    builder.push_bind(query.cursor.unwrap_or(0));
+    if let Some(instance) = &query.instance {
+        builder
+            .push(" AND instance_id = ")
+            .push_bind(instance.to_string());
+    }
+    if let Some(kind) = &query.kind {
+        let kind = serde_json::to_value(kind)
+            .ok()
+            .and_then(|kind| kind.as_str().map(|kind| kind.to_owned()))
+            .ok_or_else(|| eyre!("Failed to serialize event kind"))?;
+        builder
+            .push(format!(" AND {EVENT_KIND_EXPRESSION} = "))
+            .push_bind(kind);
+    }
+    if let Some(since) = query.since {
+        let since = (since - LODESTONE_EPOCH_MIL.with(|p| *p)).max(0) << 22;
+        builder.push(" AND snowflake >= ").push_bind(since);
+    }
+    // one more row than asked tells if there is a next page
+    builder
+        .push(" ORDER BY id LIMIT ")
+        .push_bind(query.limit as i64 + 1);
+    let mut rows = builder
+        .build()
+        .fetch_all(&mut connection)
+        .await
+        .context("Failed to fetch events")?;
+
+    let has_more = rows.len() > query.limit as usize;
+    rows.truncate(query.limit as usize);
+    let next_cursor = match rows.last() {
+        Some(row) if has_more => Some(row.try_get::<i64, _>("id").context("Invalid event id")?),
+        _ => None,
+    };
+    let mut events = Vec::new();
+    for row in rows {
+        let event_value: String = row.try_get("event_value").context("Invalid event")?;
+        match serde_json::from_str::<ClientEvent>(&event_value) {
+            Ok(client_event) if visible(&client_event) => events.push(client_event),
+            Ok(_) => {}
+            Err(_) => error!("Failed to parse client event: {}", event_value),
+        }
+    }
+    Ok(EventHistoryPage {
+        events,
+        next_cursor,
+    })
+}
+
 #[cfg(test)]
 #[allow(unused_imports)]
 mod tests {
@@ -109,6 +202,7 @@ mod tests {
             snowflake,
             level: EventLevel::Info,
             caused_by: CausedBy::System,
+            correlation_id: None,
         };
 
         // let row_1_result = sqlx::query!(
@@ -127,6 +221,135 @@ mod tests {
         // let row_1 = row_1_result.unwrap();
     }
 
+    fn instance_output(instance: &str, message: &str) -> ClientEvent {
+        ClientEvent {
+            event_inner: EventInner::InstanceEvent(crate::events::InstanceEvent {
+                instance_uuid: InstanceUuid::from(instance.to_string()),
+                instance_name: instance.to_string(),
+                instance_event_inner: crate::events::InstanceEventInner::InstanceOutput {
+                    message: message.to_string(),
+                },
+            }),
+            details: message.to_string(),
+            snowflake: Snowflake::new(),
+            level: EventLevel::Info,
+            caused_by: CausedBy::System,
+            correlation_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_history_filters_and_pages() {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        init_client_events_table(&pool).await.unwrap();
+        for i in 0..5 {
+            crate::db::write::write_client_event(
+                &pool,
+                instance_output("survival", &format!("[Server thread/INFO]: tick {i}")),
+            )
+            .await
+            .unwrap();
+            crate::db::write::write_client_event(&pool, instance_output("creative", "joined"))
+                .await
+                .unwrap();
+        }
+        for path in [
+            "/instances/survival/ops.json",
+            "/instances/creative/ops.json",
+        ] {
+            let fs_event = ClientEvent {
+                event_inner: EventInner::FSEvent(FSEvent {
+                    operation: FSOperation::Write,
+                    target: FSTarget::File(PathBuf::from(path)),
+                }),
+                details: String::new(),
+                snowflake: Snowflake::new(),
+                level: EventLevel::Info,
+                caused_by: CausedBy::System,
+                correlation_id: None,
+            };
+            crate::db::write::write_client_event(&pool, fs_event)
+                .await
+                .unwrap();
+        }
+
+        // the survival instance, two at a time
+        let mut query = EventHistoryQuery {
+            instance: Some(InstanceUuid::from("survival".to_string())),
+            limit: 2,
+            ..Default::default()
+        };
+        let mut messages = Vec::new();
+        let mut pages = 0;
+        loop {
+            let page = query_event_history(&pool, &query, |_| true).await.unwrap();
+            pages += 1;
+            assert!(page.events.len() <= 2);
+            messages.extend(page.events.into_iter().map(|event| event.details));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(pages, 3);
+        assert_eq!(
+            messages,
+            (0..5)
+                .map(|i| format!("[Server thread/INFO]: tick {i}"))
+                .collect::<Vec<_>>()
+        );
+
+        let query = EventHistoryQuery {
+            kind: Some(crate::events::EventType::FSEvent),
+            limit: 10,
+            ..Default::default()
+        };
+        let page = query_event_history(&pool, &query, |_| true).await.unwrap();
+        assert_eq!(page.events.len(), 2);
+        assert!(page
+            .events
+            .iter()
+            .all(|event| matches!(event.event_inner, EventInner::FSEvent(_))));
+        assert_eq!(page.next_cursor, None);
+
+        let query = EventHistoryQuery {
+            instance: Some(InstanceUuid::from("creative".to_string())),
+            kind: Some(crate::events::EventType::InstanceEvent),
+            limit: 10,
+            ..Default::default()
+        };
+        let page = query_event_history(&pool, &query, |_| true).await.unwrap();
+        assert_eq!(page.events.len(), 5);
+        // events the requester can't see are left out
+        let page = query_event_history(&pool, &query, |_| false).await.unwrap();
+        assert!(page.events.is_empty());
+
+        let query = EventHistoryQuery {
+            since: Some(chrono::Utc::now().timestamp_millis() + 60_000),
+            limit: 10,
+            ..Default::default()
+        };
+        let page = query_event_history(&pool, &query, |_| true).await.unwrap();
+        assert!(page.events.is_empty());
+
+        let plan: Vec<String> = sqlx::query(
+            "EXPLAIN QUERY PLAN SELECT id FROM ClientEvents WHERE id > 0 AND instance_id = 'survival' ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.get::<String, _>("detail"))
+        .collect();
+        assert!(plan
+            .iter()
+            .any(|detail| detail.contains("ClientEventsInstance")));
+    }
+
     // TODO should properly implement tests, with dummy values
     // #[tokio::test]
     // async fn test_read() {
@@ -49,7 +49,10 @@ pub async fn write_event_to_db_task(mut event_receiver: Receiver<Event>, sqlite_
     }
 }
 
-async fn write_client_event(pool: &SqlitePool, client_event: ClientEvent) -> Result<i64, Error> {
+pub(super) async fn write_client_event(
+    pool: &SqlitePool,
+    client_event: ClientEvent,
+) -> Result<i64, Error> {
     let mut connection = pool
         .acquire()
         .await
@@ -100,9 +103,26 @@ pub async fn init_client_events_table(pool: &SqlitePool) -> Result<(), Error> {
     .await
     .context("Failed to create table")?;
 
+    // the event history is filtered by instance, kind and time then paged by id
+    for index in CLIENT_EVENTS_INDEXES {
+        sqlx::query(index)
+            .execute(&mut connection)
+            .await
+            .context("Failed to create index")?;
+    }
+
     Ok(())
 }
 
+// must be the expression of the ClientEventsKind index for the index to be used
+pub(super) const EVENT_KIND_EXPRESSION: &str = "json_extract(event_value, '$.event_inner.type')";
+
+const CLIENT_EVENTS_INDEXES: [&str; 3] = [
+    "CREATE INDEX IF NOT EXISTS ClientEventsInstance ON ClientEvents (instance_id, id)",
+    "CREATE INDEX IF NOT EXISTS ClientEventsKind ON ClientEvents (json_extract(event_value, '$.event_inner.type'), id)",
+    "CREATE INDEX IF NOT EXISTS ClientEventsSnowflake ON ClientEvents (snowflake)",
+];
+
 #[cfg(test)]
 #[allow(unused_imports)]
 
@@ -143,6 +163,7 @@ mod tests {
             snowflake,
             level: EventLevel::Info,
             caused_by: CausedBy::System,
+            correlation_id: None,
         };
         let write_result = write_client_event(&pool, dummy_event.clone()).await;
         assert!(write_result.is_ok());
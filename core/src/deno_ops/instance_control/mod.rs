@@ -17,6 +17,14 @@ use crate::{
     types::InstanceUuid,
 };
 
+/// Macros keep running while read-only, but the changes they make to instances are refused
+fn refuse_macro_changes() -> Result<(), anyhow::Error> {
+    app_state()
+        .read_only
+        .refuse_changes()
+        .context("Lodestone is read-only")
+}
+
 #[op]
 fn instance_exists(instance_uuid: InstanceUuid) -> bool {
     app_state().instances.contains_key(&instance_uuid)
@@ -37,10 +45,16 @@ async fn start_instance(
     task_pid: MacroPID,
     block: bool,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
+        .map(|instance| instance.value().clone())
         .ok_or(anyhow::anyhow!("Instance not found"))?;
+    let _hold = app_state()
+        .instance_holds
+        .hold(&instance_uuid, "started")
+        .context("Failed to start instance")?;
     instance
         .start(
             CausedBy::Macro {
@@ -58,6 +72,7 @@ async fn stop_instance(
     task_pid: MacroPID,
     block: bool,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -79,6 +94,7 @@ async fn restart_instance(
     task_pid: MacroPID,
     block: bool,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -99,6 +115,7 @@ async fn kill_instance(
     instance_uuid: InstanceUuid,
     task_pid: MacroPID,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -127,6 +144,7 @@ async fn send_command(
     command: String,
     task_pid: MacroPID,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -236,6 +254,7 @@ async fn get_instance_path(instance_uuid: InstanceUuid) -> Result<String, anyhow
 
 #[op]
 async fn set_instance_name(instance_uuid: InstanceUuid, name: String) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -252,6 +271,7 @@ async fn set_instance_description(
     instance_uuid: InstanceUuid,
     description: String,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -265,6 +285,7 @@ async fn set_instance_description(
 
 #[op]
 async fn set_instance_port(instance_uuid: InstanceUuid, port: u32) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -281,6 +302,7 @@ async fn set_instance_auto_start(
     instance_uuid: InstanceUuid,
     auto_start: bool,
 ) -> Result<(), anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -313,6 +335,7 @@ async fn try_send_rcon_command(
     instance_uuid: InstanceUuid,
     command: String,
 ) -> Result<Option<String>, anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
@@ -330,6 +353,7 @@ async fn send_rcon_command(
     instance_uuid: InstanceUuid,
     command: String,
 ) -> Result<String, anyhow::Error> {
+    refuse_macro_changes()?;
     let instance = app_state()
         .instances
         .get(&instance_uuid)
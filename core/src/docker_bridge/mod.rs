@@ -21,6 +21,8 @@ use crate::{
     error::Error,
     event_broadcaster::EventBroadcaster,
     events::Event,
+    instance_audit::InstanceAudit,
+    instance_crashes::InstanceCrashes,
     traits::{t_configurable::GameType, t_server::State, InstanceInfo},
     types::InstanceUuid,
 };
@@ -161,10 +163,16 @@ impl DockerBridge {
                 path: "/no_peek/Volume".to_string(),
                 auto_start: false,
                 restart_on_crash: false,
+                locked: false,
+                depends_on: Vec::new(),
+                stop_command: None,
                 state: State::from_docker_state_string(&container.state.unwrap()),
                 player_count: None,
                 max_player_count: None,
                 player_list: None,
+                audit: InstanceAudit::default(),
+                crashes: InstanceCrashes::default(),
+                busy: false,
             };
             ret.push(instance);
         }
@@ -19,6 +19,9 @@ pub enum ErrorKind {
     BadRequest,
     PermissionDenied,
     Unauthorized,
+    Conflict,
+    /// the operation didn't finish in time, e.g. on a stalled network filesystem
+    Timeout,
     External,
     Internal,
 }
@@ -41,6 +44,55 @@ impl Error {
     }
 }
 
+/// A problem with a single field of a request
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, TS)]
+#[ts(export)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// All the field problems of a request, so a form can show them at once
+#[derive(Debug, Clone)]
+pub struct FieldErrors(pub Vec<FieldError>);
+
+impl Display for FieldErrors {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid fields:")?;
+        for (i, error) in self.0.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{} ({})", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FieldErrors {}
+
+impl Error {
+    pub fn fields(errors: Vec<FieldError>) -> Error {
+        Error {
+            kind: ErrorKind::BadRequest,
+            source: Report::new(FieldErrors(errors)),
+        }
+    }
+
+    pub fn field_errors(&self) -> Option<&[FieldError]> {
+        self.source
+            .downcast_ref::<FieldErrors>()
+            .map(|errors| errors.0.as_slice())
+    }
+}
+
 impl Error {
     pub fn ts_syntax_error(context: &str) -> Error {
         Error {
@@ -58,6 +110,8 @@ impl Display for ErrorKind {
             ErrorKind::BadRequest => write!(f, "Bad Request"),
             ErrorKind::PermissionDenied => write!(f, "Permission Denied"),
             ErrorKind::Unauthorized => write!(f, "Unauthorized"),
+            ErrorKind::Conflict => write!(f, "Conflict"),
+            ErrorKind::Timeout => write!(f, "Timeout"),
             ErrorKind::Internal => write!(f, "Internal Error"),
             ErrorKind::External => write!(f, "External Error")
         }
@@ -69,10 +123,15 @@ impl Serialize for Error {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("Error", 2)?;
+        let field_errors = self.field_errors();
+        let mut state =
+            serializer.serialize_struct("Error", if field_errors.is_some() { 3 } else { 2 })?;
         state.serialize_field("kind", &self.kind)?;
         let vec: Vec<String> = self.source.chain().map(|cause| cause.to_string()).collect();
         state.serialize_field("causes", &vec)?;
+        if let Some(field_errors) = field_errors {
+            state.serialize_field("fields", field_errors)?;
+        }
         state.end()
     }
 }
@@ -87,6 +146,35 @@ fn test_error_serialization() {
     assert_eq!(json, r#"{"kind":"NotFound","causes":["Test"]}"#);
 }
 
+#[test]
+fn test_field_errors_serialization() {
+    let error = Error::fields(vec![
+        FieldError::new("name", "Name cannot be empty"),
+        FieldError::new("port", "Value is too large"),
+    ]);
+    assert!(matches!(error.kind, ErrorKind::BadRequest));
+    assert_eq!(error.field_errors().unwrap().len(), 2);
+    let json: serde_json::Value = serde_json::to_value(&error).unwrap();
+    assert_eq!(
+        json["fields"],
+        json!([
+            {"field": "name", "message": "Name cannot be empty"},
+            {"field": "port", "message": "Value is too large"}
+        ])
+    );
+}
+
+#[test]
+fn test_unsupported_operation_response() {
+    let response = Error {
+        kind: ErrorKind::UnsupportedOperation,
+        source: Report::msg("Maintenance mode is only supported for Minecraft instances"),
+    }
+    .into_response();
+    // the instance exists, it just can't do that
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         let status = match self.kind {
@@ -95,6 +183,8 @@ impl IntoResponse for Error {
             ErrorKind::BadRequest => StatusCode::BAD_REQUEST,
             ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
             ErrorKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorKind::Conflict => StatusCode::CONFLICT,
+            ErrorKind::Timeout => StatusCode::REQUEST_TIMEOUT,
             ErrorKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
             ErrorKind::External => StatusCode::BAD_GATEWAY,
         };
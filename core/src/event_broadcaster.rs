@@ -4,6 +4,7 @@ use tokio::sync::broadcast::{Receiver, Sender};
 use tracing::error;
 
 use crate::{
+    correlation::current_correlation_id,
     events::{Event, EventInner, InstanceEvent, InstanceEventInner},
     traits::{t_player::Player, t_server::State},
     types::InstanceUuid,
@@ -33,7 +34,11 @@ impl EventBroadcaster {
         (Self { event_tx }, rx)
     }
 
-    pub fn send(&self, event: Event) {
+    /// Send an event, stamped with the correlation id of the request being handled if any
+    pub fn send(&self, mut event: Event) {
+        if event.correlation_id.is_none() {
+            event.correlation_id = current_correlation_id();
+        }
         if let Err(e) = self.event_tx.send(event) {
             error!("Failed to send event: {e}");
         }
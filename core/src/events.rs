@@ -194,6 +194,7 @@ impl From<MacroEvent> for Event {
             caused_by: CausedBy::Macro {
                 macro_pid: val.macro_pid,
             },
+            correlation_id: None,
         }
     }
 }
@@ -256,7 +257,10 @@ pub enum PlayitggRunnerEventInner {
 pub enum FSOperation {
     Read,
     Write,
-    Move { source: PathBuf },
+    Move {
+        source: PathBuf,
+        destination: PathBuf,
+    },
     Create,
     Delete,
     Upload,
@@ -283,10 +287,28 @@ pub fn new_fs_event(operation: FSOperation, target: FSTarget, caused_by: CausedB
         snowflake: Snowflake::default(),
         event_inner: EventInner::FSEvent(FSEvent { operation, target }),
         caused_by,
+        correlation_id: None,
     }
 }
 
-#[derive(Serialize, Deserialize, TS)]
+/// The target of a move is where the file ended up, the operation also carries where it came from
+pub fn new_fs_move_event(source: PathBuf, destination: PathBuf, caused_by: CausedBy) -> Event {
+    let target = if destination.is_dir() {
+        FSTarget::Directory(destination.clone())
+    } else {
+        FSTarget::File(destination.clone())
+    };
+    new_fs_event(
+        FSOperation::Move {
+            source,
+            destination,
+        },
+        target,
+        caused_by,
+    )
+}
+
+#[derive(Serialize, Deserialize, TS, Clone)]
 #[serde(transparent)]
 #[ts(export)]
 pub struct ProgressionEventID(Snowflake);
@@ -363,6 +385,8 @@ pub struct Event {
     pub details: String,
     pub snowflake: Snowflake,
     pub caused_by: CausedBy,
+    /// id of the api request the event was emitted for, see [`crate::correlation`]
+    pub correlation_id: Option<String>,
 }
 
 pub trait IntoEvent {
@@ -396,6 +420,7 @@ impl From<&ClientEvent> for Event {
             details: client_event.details.clone(),
             snowflake: client_event.snowflake,
             caused_by: client_event.caused_by.clone(),
+            correlation_id: client_event.correlation_id.clone(),
         }
     }
 }
@@ -407,6 +432,13 @@ impl AsRef<Event> for Event {
 }
 
 impl Event {
+    /// Attribute an event built without a cause, like the updates and end of a progression
+    /// started by a user
+    pub fn with_caused_by(mut self, caused_by: CausedBy) -> Self {
+        self.caused_by = caused_by;
+        self
+    }
+
     pub fn is_event_console_message(&self) -> bool {
         match &self.event_inner {
             EventInner::InstanceEvent(instance_event) => matches!(
@@ -459,6 +491,7 @@ impl Event {
                 instance_event_inner: InstanceEventInner::InstanceOutput { message: output },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 
@@ -480,6 +513,7 @@ impl Event {
                 },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 
@@ -499,6 +533,7 @@ impl Event {
                 },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 
@@ -516,6 +551,7 @@ impl Event {
                 instance_event_inner: InstanceEventInner::StateTransition { to: new_state },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
     #[must_use]
@@ -539,6 +575,7 @@ impl Event {
                     },
                 }),
                 caused_by,
+                correlation_id: None,
             },
             event_id,
         )
@@ -560,6 +597,7 @@ impl Event {
                 },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 
@@ -581,6 +619,7 @@ impl Event {
                 },
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 
@@ -594,6 +633,7 @@ impl Event {
                 macro_event_inner: MacroEventInner::Detach,
             }),
             caused_by: CausedBy::System,
+            correlation_id: None,
         }
     }
 }
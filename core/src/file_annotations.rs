@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+
+use color_eyre::eyre::eyre;
+use tokio::sync::Mutex;
+
+use crate::error::{Error, ErrorKind};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+
+/// Maps paths relative to the instance to their note
+pub const FILE_ANNOTATIONS_FILE_NAME: &str = ".lodestone_annotations.json";
+
+pub const MAX_ANNOTATION_LENGTH: usize = 500;
+
+pub type FileAnnotations = BTreeMap<String, String>;
+
+// every change is a read-modify-write of the whole sidecar
+static ANNOTATIONS_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Key of a path in the sidecar, `/` separated so it's the same on every platform
+fn annotation_key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let components = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    if components.is_empty() {
+        None
+    } else {
+        Some(components.join("/"))
+    }
+}
+
+/// Missing or unreadable sidecars read as empty
+pub async fn read_file_annotations(root: &Path) -> FileAnnotations {
+    read_sidecar(root, FILE_ANNOTATIONS_FILE_NAME, "file annotations")
+        .await
+        .unwrap_or_default()
+}
+
+async fn write_file_annotations(root: &Path, annotations: &FileAnnotations) -> Result<(), Error> {
+    if annotations.is_empty() {
+        return remove_sidecar(root, FILE_ANNOTATIONS_FILE_NAME).await;
+    }
+    write_sidecar(
+        root,
+        FILE_ANNOTATIONS_FILE_NAME,
+        "file annotations",
+        annotations,
+    )
+    .await
+}
+
+/// Note of a file listed in the instance, if any
+pub fn annotation_of<'a>(
+    annotations: &'a FileAnnotations,
+    root: &Path,
+    path: &Path,
+) -> Option<&'a String> {
+    annotations.get(&annotation_key(root, path)?)
+}
+
+pub async fn get_file_annotation(root: &Path, path: &Path) -> Option<String> {
+    annotation_of(&read_file_annotations(root).await, root, path).cloned()
+}
+
+/// Set the note of a file, `None` or an empty note removes it
+pub async fn set_file_annotation(
+    root: &Path,
+    path: &Path,
+    annotation: Option<String>,
+) -> Result<(), Error> {
+    let key = annotation_key(root, path).ok_or_else(|| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Only files inside the instance can be annotated"),
+    })?;
+    let annotation = annotation.filter(|annotation| !annotation.trim().is_empty());
+    if let Some(annotation) = &annotation {
+        if !path.exists() {
+            return Err(Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("File not found"),
+            });
+        }
+        if annotation.chars().count() > MAX_ANNOTATION_LENGTH {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Annotation cannot be longer than {MAX_ANNOTATION_LENGTH} characters"
+                ),
+            });
+        }
+    }
+    let _lock = ANNOTATIONS_LOCK.lock().await;
+    let mut annotations = read_file_annotations(root).await;
+    match annotation {
+        Some(annotation) => annotations.insert(key, annotation),
+        None => annotations.remove(&key),
+    };
+    write_file_annotations(root, &annotations).await
+}
+
+/// Carry the notes of a moved file, or of everything under a moved directory, to the new path
+pub async fn move_file_annotations(
+    root: &Path,
+    source: &Path,
+    destination: &Path,
+) -> Result<(), Error> {
+    let (Some(source), Some(destination)) = (
+        annotation_key(root, source),
+        annotation_key(root, destination),
+    ) else {
+        return Ok(());
+    };
+    let _lock = ANNOTATIONS_LOCK.lock().await;
+    let mut annotations = read_file_annotations(root).await;
+    let moved: Vec<String> = annotations
+        .keys()
+        .filter(|key| **key == source || key.starts_with(&format!("{source}/")))
+        .cloned()
+        .collect();
+    if moved.is_empty() {
+        return Ok(());
+    }
+    for key in moved {
+        if let Some(annotation) = annotations.remove(&key) {
+            annotations.insert(format!("{destination}{}", &key[source.len()..]), annotation);
+        }
+    }
+    write_file_annotations(root, &annotations).await
+}
+
+/// Drop the notes of a deleted file or directory, so a new file at the same path starts clean
+pub async fn remove_file_annotations(root: &Path, path: &Path) -> Result<(), Error> {
+    let Some(removed) = annotation_key(root, path) else {
+        return Ok(());
+    };
+    let _lock = ANNOTATIONS_LOCK.lock().await;
+    let mut annotations = read_file_annotations(root).await;
+    let before = annotations.len();
+    annotations.retain(|key, _| *key != removed && !key.starts_with(&format!("{removed}/")));
+    if annotations.len() == before {
+        return Ok(());
+    }
+    write_file_annotations(root, &annotations).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_set_list_and_move_annotations() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let mods = root.join("mods");
+        std::fs::create_dir(&mods).unwrap();
+        let patched = mods.join("patched.jar");
+        std::fs::write(&patched, b"jar").unwrap();
+        std::fs::write(mods.join("other.jar"), b"jar").unwrap();
+
+        set_file_annotation(
+            root,
+            &patched,
+            Some("don't update, it's patched".to_string()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            get_file_annotation(root, &patched).await.as_deref(),
+            Some("don't update, it's patched")
+        );
+        let annotations = read_file_annotations(root).await;
+        assert_eq!(annotations.len(), 1);
+        assert!(annotation_of(&annotations, root, &mods.join("other.jar")).is_none());
+
+        // renaming the file
+        let renamed = mods.join("patched-1.2.jar");
+        std::fs::rename(&patched, &renamed).unwrap();
+        move_file_annotations(root, &patched, &renamed)
+            .await
+            .unwrap();
+        assert!(get_file_annotation(root, &patched).await.is_none());
+        assert!(get_file_annotation(root, &renamed).await.is_some());
+
+        // moving the directory it's in
+        let disabled = root.join("disabled_mods");
+        std::fs::rename(&mods, &disabled).unwrap();
+        move_file_annotations(root, &mods, &disabled).await.unwrap();
+        let moved = disabled.join("patched-1.2.jar");
+        assert_eq!(
+            read_file_annotations(root).await.keys().collect::<Vec<_>>(),
+            vec!["disabled_mods/patched-1.2.jar"]
+        );
+
+        remove_file_annotations(root, &disabled).await.unwrap();
+        assert!(get_file_annotation(root, &moved).await.is_none());
+        assert!(!root.join(FILE_ANNOTATIONS_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn test_annotation_validation() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let file = root.join("server.properties");
+
+        let err = set_file_annotation(root, &file, Some("note".to_string()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotFound));
+
+        std::fs::write(&file, b"").unwrap();
+        let err = set_file_annotation(root, &file, Some("a".repeat(MAX_ANNOTATION_LENGTH + 1)))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        let err = set_file_annotation(root, root, Some("root".to_string()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+
+        set_file_annotation(root, &file, Some("note".to_string()))
+            .await
+            .unwrap();
+        set_file_annotation(root, &file, Some("  ".to_string()))
+            .await
+            .unwrap();
+        assert!(get_file_annotation(root, &file).await.is_none());
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+use crate::types::InstanceUuid;
+
+/// Absent while the instance is unlimited
+pub const FS_OP_LIMITS_FILE_NAME: &str = ".lodestone_fs_ops.json";
+
+const MAX_CONCURRENT_FS_OPS_RANGE: std::ops::RangeInclusive<u32> = 1..=64;
+
+/// How many heavy fs operations (copy, zip, unzip) of an instance can run at once
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FsOpLimits {
+    /// `None` lets every operation run right away
+    pub max_concurrent_fs_ops: Option<u32>,
+}
+
+impl FsOpLimits {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.max_concurrent_fs_ops {
+            Some(max) if !MAX_CONCURRENT_FS_OPS_RANGE.contains(&max) => Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "max_concurrent_fs_ops must be between {} and {}",
+                    MAX_CONCURRENT_FS_OPS_RANGE.start(),
+                    MAX_CONCURRENT_FS_OPS_RANGE.end()
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Per instance semaphores bounding the heavy fs operations running at once
+#[derive(Clone, Default)]
+pub struct FsOpLimiter {
+    semaphores: Arc<std::sync::Mutex<HashMap<InstanceUuid, Arc<Semaphore>>>>,
+}
+
+impl FsOpLimiter {
+    /// Operations already running keep their slot, the new limit applies to the ones started after
+    pub fn set_limits(&self, uuid: InstanceUuid, limits: FsOpLimits) {
+        let mut semaphores = self.semaphores.lock().unwrap();
+        match limits.max_concurrent_fs_ops {
+            Some(max) => {
+                semaphores.insert(uuid, Arc::new(Semaphore::new(max as usize)));
+            }
+            None => {
+                semaphores.remove(&uuid);
+            }
+        }
+    }
+
+    /// Wait for a slot on the instance, `on_queued` is called first if the operation has to wait.
+    ///
+    /// The slot is held until the returned permit is dropped, `None` when the instance is unlimited
+    pub async fn acquire(
+        &self,
+        uuid: &InstanceUuid,
+        on_queued: impl FnOnce(),
+    ) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.semaphores.lock().unwrap().get(uuid).cloned()?;
+        match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                on_queued();
+                semaphore.acquire_owned().await.ok()
+            }
+        }
+    }
+}
+
+/// Missing or unreadable files read as unlimited
+pub async fn read_fs_op_limits(path_to_instance: &Path) -> FsOpLimits {
+    read_sidecar(
+        path_to_instance,
+        FS_OP_LIMITS_FILE_NAME,
+        "fs operation limits",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn write_fs_op_limits(path_to_instance: &Path, limits: &FsOpLimits) -> Result<(), Error> {
+    if limits.max_concurrent_fs_ops.is_none() {
+        return remove_sidecar(path_to_instance, FS_OP_LIMITS_FILE_NAME).await;
+    }
+    write_sidecar(
+        path_to_instance,
+        FS_OP_LIMITS_FILE_NAME,
+        "fs operation limits",
+        limits,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_operations_respect_instance_limit() {
+        let limiter = FsOpLimiter::default();
+        let slow_storage = InstanceUuid::from("INSTANCE_slow".to_string());
+        let other = InstanceUuid::from("INSTANCE_other".to_string());
+        limiter.set_limits(
+            slow_storage.clone(),
+            FsOpLimits {
+                max_concurrent_fs_ops: Some(2),
+            },
+        );
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..6)
+            .map(|_| {
+                let limiter = limiter.clone();
+                let uuid = slow_storage.clone();
+                let running = running.clone();
+                let max_running = max_running.clone();
+                let queued = queued.clone();
+                tokio::spawn(async move {
+                    let _permit = limiter
+                        .acquire(&uuid, || {
+                            queued.fetch_add(1, Ordering::SeqCst);
+                        })
+                        .await;
+                    let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_running.fetch_max(now_running, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        // another instance isn't held back by the slow one
+        let other_permits = [
+            limiter.acquire(&other, || panic!("queued")).await,
+            limiter.acquire(&other, || panic!("queued")).await,
+            limiter.acquire(&other, || panic!("queued")).await,
+        ];
+        assert!(other_permits.iter().all(|permit| permit.is_none()));
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+        assert_eq!(queued.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_limits_are_persisted_and_validated() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(read_fs_op_limits(path).await, FsOpLimits::default());
+        let limits = FsOpLimits {
+            max_concurrent_fs_ops: Some(1),
+        };
+        limits.validate().unwrap();
+        write_fs_op_limits(path, &limits).await.unwrap();
+        assert_eq!(read_fs_op_limits(path).await, limits);
+        write_fs_op_limits(path, &FsOpLimits::default())
+            .await
+            .unwrap();
+        assert!(!path.join(FS_OP_LIMITS_FILE_NAME).exists());
+
+        assert!(FsOpLimits {
+            max_concurrent_fs_ops: Some(0)
+        }
+        .validate()
+        .is_err());
+    }
+}
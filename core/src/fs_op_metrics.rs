@@ -0,0 +1,202 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use futures::Stream;
+use tracing::info;
+
+use crate::types::InstanceUuid;
+
+/// Target of the samples, so they can be filtered or routed apart from the rest of the logs.
+/// Under `lodestone_core` so the log filters let them through
+pub const FS_OP_METRICS_TARGET: &str = "lodestone_core::fs_op";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsOpKind {
+    Copy,
+    Upload,
+    Download,
+    Zip,
+    Unzip,
+    Fetch,
+}
+
+impl FsOpKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FsOpKind::Copy => "copy",
+            FsOpKind::Upload => "upload",
+            FsOpKind::Download => "download",
+            FsOpKind::Zip => "zip",
+            FsOpKind::Unzip => "unzip",
+            FsOpKind::Fetch => "fetch",
+        }
+    }
+}
+
+/// Times a heavy fs operation, the sample is emitted when the timer is dropped so operations
+/// bailing out early are recorded too, as unsuccessful unless `succeeded` was called
+#[derive(Debug)]
+pub struct FsOpTimer {
+    kind: FsOpKind,
+    instance_uuid: Option<InstanceUuid>,
+    started: Instant,
+    bytes: u64,
+    success: bool,
+}
+
+impl FsOpTimer {
+    pub fn start(kind: FsOpKind, instance_uuid: Option<InstanceUuid>) -> Self {
+        Self {
+            kind,
+            instance_uuid,
+            started: Instant::now(),
+            bytes: 0,
+            success: false,
+        }
+    }
+
+    pub fn add_bytes(&mut self, bytes: u64) {
+        self.bytes += bytes;
+    }
+
+    pub fn set_bytes(&mut self, bytes: u64) {
+        self.bytes = bytes;
+    }
+
+    pub fn succeeded(&mut self) {
+        self.success = true;
+    }
+}
+
+impl Drop for FsOpTimer {
+    fn drop(&mut self) {
+        info!(
+            target: FS_OP_METRICS_TARGET,
+            operation = self.kind.as_str(),
+            instance_uuid = self.instance_uuid.as_ref().map(|uuid| uuid.as_ref()),
+            bytes = self.bytes,
+            duration_ms = self.started.elapsed().as_millis() as u64,
+            success = self.success,
+            "fs operation completed"
+        );
+    }
+}
+
+/// Body of a download, timed until the whole file has been streamed or the client goes away
+pub struct TimedStream<S> {
+    inner: S,
+    timer: FsOpTimer,
+}
+
+impl<S> TimedStream<S> {
+    pub fn new(inner: S, timer: FsOpTimer) -> Self {
+        Self { inner, timer }
+    }
+}
+
+impl<S, B, E> Stream for TimedStream<S>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+{
+    type Item = Result<B, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.inner).poll_next(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(chunk))) => self.timer.add_bytes(chunk.as_ref().len() as u64),
+            Poll::Ready(None) => self.timer.succeeded(),
+            _ => {}
+        }
+        poll
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    use super::*;
+    use crate::instance_log_level::file_log_filter;
+
+    // records the fields of every fs operation sample it sees
+    #[derive(Clone, Default)]
+    struct CollectLayer(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+    struct FieldVisitor<'a>(&'a mut HashMap<String, String>);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for CollectLayer {
+        fn on_event(&self, event: &Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+            if event.metadata().target() != FS_OP_METRICS_TARGET {
+                return;
+            }
+            let mut fields = HashMap::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            self.0.lock().unwrap().push(fields);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_emits_size_and_duration() {
+        let collected = CollectLayer::default();
+        // through the filter of the log file, as set up at startup
+        let subscriber =
+            tracing_subscriber::registry().with(collected.clone().with_filter(file_log_filter()));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut timer = FsOpTimer::start(
+            FsOpKind::Upload,
+            Some(InstanceUuid::from("INSTANCE_survival".to_string())),
+        );
+        for chunk in [&b"first chunk"[..], &b"second"[..]] {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            timer.add_bytes(chunk.len() as u64);
+        }
+        timer.succeeded();
+        drop(timer);
+
+        // a download the client abandoned half way
+        let chunks: Vec<Result<Vec<u8>, std::io::Error>> = vec![Ok(vec![0; 16]), Ok(vec![0; 16])];
+        let mut download = TimedStream::new(
+            futures::stream::iter(chunks),
+            FsOpTimer::start(FsOpKind::Download, None),
+        );
+        download.next().await;
+        drop(download);
+
+        let samples = collected.0.lock().unwrap().clone();
+        assert_eq!(samples.len(), 2);
+        let upload = &samples[0];
+        assert_eq!(upload["operation"], "upload");
+        assert_eq!(upload["instance_uuid"], "INSTANCE_survival");
+        assert_eq!(upload["bytes"], "17");
+        assert!(upload["duration_ms"].parse::<u64>().unwrap() >= 10);
+        assert_eq!(upload["success"], "true");
+
+        let download = &samples[1];
+        assert_eq!(download["operation"], "download");
+        assert!(!download.contains_key("instance_uuid"));
+        assert_eq!(download["bytes"], "16");
+        assert_eq!(download["success"], "false");
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use color_eyre::eyre::Context;
@@ -15,6 +16,54 @@ pub struct GlobalSettingsData {
     pub domain: Option<String>,
     #[serde(default)]
     pub playit_enabled: bool,
+    /// External service used to check if instances are reachable from the internet,
+    /// `None` keeps the check local only
+    #[serde(default)]
+    pub reachability_probe_url: Option<String>,
+    /// Most source paths a single copy or zip request may list
+    #[serde(default = "default_max_fs_request_paths")]
+    pub max_fs_request_paths: u32,
+    /// How long download keys and leftover temporary files are kept, in seconds
+    #[serde(default = "default_temp_retention_secs")]
+    pub temp_retention_secs: u64,
+    /// How long reading or listing instance files may take before the request gives up, in
+    /// seconds
+    #[serde(default = "default_fs_read_timeout_secs")]
+    pub fs_read_timeout_secs: u64,
+    /// Where server jars are downloaded from, for networks that can't reach the official sources
+    #[serde(default)]
+    pub jar_mirrors: JarMirrors,
+    /// Hosts files can be fetched from into instances, `*.example.com` allows its subdomains.
+    /// Empty disables fetching
+    #[serde(default)]
+    pub fetch_allowed_hosts: Vec<String>,
+    /// Named directories instances can be migrated into, e.g. on other disks, besides the
+    /// instances directory
+    #[serde(default)]
+    pub storage_pools: BTreeMap<String, PathBuf>,
+}
+
+/// Base urls replacing the scheme and host of each flavour's official download source,
+/// `None` downloads from the official source
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, TS)]
+#[ts(export)]
+pub struct JarMirrors {
+    pub vanilla: Option<String>,
+    pub fabric: Option<String>,
+    pub paper: Option<String>,
+    pub forge: Option<String>,
+}
+
+fn default_max_fs_request_paths() -> u32 {
+    10_000
+}
+
+fn default_temp_retention_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_fs_read_timeout_secs() -> u64 {
+    30
 }
 
 impl Default for GlobalSettingsData {
@@ -24,6 +73,13 @@ impl Default for GlobalSettingsData {
             safe_mode: true,
             domain: None,
             playit_enabled: true,
+            reachability_probe_url: None,
+            max_fs_request_paths: default_max_fs_request_paths(),
+            temp_retention_secs: default_temp_retention_secs(),
+            fs_read_timeout_secs: default_fs_read_timeout_secs(),
+            jar_mirrors: JarMirrors::default(),
+            fetch_allowed_hosts: Vec::new(),
+            storage_pools: BTreeMap::new(),
         }
     }
 }
@@ -165,6 +221,135 @@ impl GlobalSettings {
     pub fn playit_enabled(&self) -> bool {
         self.global_settings_data.playit_enabled
     }
+
+    pub async fn set_reachability_probe_url(
+        &mut self,
+        reachability_probe_url: Option<String>,
+    ) -> Result<(), Error> {
+        let old_reachability_probe_url = self.global_settings_data.reachability_probe_url.clone();
+        self.global_settings_data.reachability_probe_url = reachability_probe_url;
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.reachability_probe_url = old_reachability_probe_url;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn reachability_probe_url(&self) -> Option<String> {
+        self.global_settings_data.reachability_probe_url.clone()
+    }
+
+    pub async fn set_max_fs_request_paths(
+        &mut self,
+        max_fs_request_paths: u32,
+    ) -> Result<(), Error> {
+        let old_max_fs_request_paths = self.global_settings_data.max_fs_request_paths;
+        self.global_settings_data.max_fs_request_paths = max_fs_request_paths;
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.max_fs_request_paths = old_max_fs_request_paths;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn max_fs_request_paths(&self) -> u32 {
+        self.global_settings_data.max_fs_request_paths
+    }
+
+    pub async fn set_temp_retention_secs(&mut self, temp_retention_secs: u64) -> Result<(), Error> {
+        let old_temp_retention_secs = self.global_settings_data.temp_retention_secs;
+        self.global_settings_data.temp_retention_secs = temp_retention_secs;
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.temp_retention_secs = old_temp_retention_secs;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn temp_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.global_settings_data.temp_retention_secs)
+    }
+
+    pub async fn set_fs_read_timeout_secs(
+        &mut self,
+        fs_read_timeout_secs: u64,
+    ) -> Result<(), Error> {
+        let old_fs_read_timeout_secs = self.global_settings_data.fs_read_timeout_secs;
+        self.global_settings_data.fs_read_timeout_secs = fs_read_timeout_secs;
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.fs_read_timeout_secs = old_fs_read_timeout_secs;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn fs_read_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.global_settings_data.fs_read_timeout_secs)
+    }
+
+    pub async fn set_jar_mirrors(&mut self, jar_mirrors: JarMirrors) -> Result<(), Error> {
+        let old_jar_mirrors = self.global_settings_data.jar_mirrors.clone();
+        self.global_settings_data.jar_mirrors = jar_mirrors;
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.jar_mirrors = old_jar_mirrors;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn jar_mirrors(&self) -> JarMirrors {
+        self.global_settings_data.jar_mirrors.clone()
+    }
+
+    pub async fn set_fetch_allowed_hosts(
+        &mut self,
+        fetch_allowed_hosts: Vec<String>,
+    ) -> Result<(), Error> {
+        let old_fetch_allowed_hosts = std::mem::replace(
+            &mut self.global_settings_data.fetch_allowed_hosts,
+            fetch_allowed_hosts,
+        );
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.fetch_allowed_hosts = old_fetch_allowed_hosts;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn fetch_allowed_hosts(&self) -> Vec<String> {
+        self.global_settings_data.fetch_allowed_hosts.clone()
+    }
+
+    pub async fn set_storage_pools(
+        &mut self,
+        storage_pools: BTreeMap<String, PathBuf>,
+    ) -> Result<(), Error> {
+        let old_storage_pools =
+            std::mem::replace(&mut self.global_settings_data.storage_pools, storage_pools);
+        match self.write_to_file().await {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                self.global_settings_data.storage_pools = old_storage_pools;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn storage_pools(&self) -> BTreeMap<String, PathBuf> {
+        self.global_settings_data.storage_pools.clone()
+    }
 }
 
 impl AsRef<GlobalSettingsData> for GlobalSettings {
@@ -1,6 +1,15 @@
+use crate::auth::user::UserAction;
+use crate::error::{Error, ErrorKind};
+use crate::reachability::{check_reachability, HttpProbe, ReachabilityProbe, ReachabilityReport};
 use crate::traits::t_configurable::TConfigurable;
-use crate::{port_manager::PortStatus, AppState};
+use crate::types::InstanceUuid;
+use crate::{
+    port_manager::{instance_port_conflicts, PortConflict, PortStatus},
+    AppState,
+};
 use axum::{extract::Path, routing::get, Json, Router};
+use axum_auth::AuthBearer;
+use color_eyre::eyre::eyre;
 /// Check the status of a port
 /// Note: this function is not cheap
 pub async fn get_port_status(
@@ -24,9 +33,66 @@ pub async fn is_name_in_use(
     Json(false)
 }
 
+/// Check whether the port of an instance can be reached from outside.
+/// The external probe only runs when the owner configured a probe url
+pub async fn get_instance_reachability(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<ReachabilityReport>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::ViewInstance(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let port = instance.port().await;
+    drop(instance);
+    let probe = state
+        .global_settings
+        .lock()
+        .await
+        .reachability_probe_url()
+        .map(HttpProbe::new);
+    Ok(Json(
+        check_reachability(
+            port,
+            probe.as_ref().map(|probe| probe as &dyn ReachabilityProbe),
+        )
+        .await,
+    ))
+}
+
+/// Ports shared by more than one instance, limited to the instances the requester can view
+pub async fn get_port_conflicts(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Vec<PortConflict>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    Ok(Json(
+        instance_port_conflicts(&state.instances)
+            .await
+            .into_iter()
+            .filter(|conflict| {
+                conflict.instances.iter().all(|uuid| {
+                    requester.can_perform_action(&UserAction::ViewInstance(uuid.clone()))
+                })
+            })
+            .collect(),
+    ))
+}
+
 pub fn get_checks_routes(state: AppState) -> Router {
     Router::new()
         .route("/check/port/:port", get(get_port_status))
         .route("/check/name/:name", get(is_name_in_use))
+        .route("/check/port_conflicts", get(get_port_conflicts))
+        .route(
+            "/instance/:uuid/reachability",
+            get(get_instance_reachability),
+        )
         .with_state(state)
 }
@@ -1,7 +1,16 @@
 use std::env;
 
-use crate::{prelude::VERSION, AppState};
-use axum::{routing::get, Json, Router};
+use crate::{
+    error::{Error, ErrorKind},
+    prelude::VERSION,
+    AppState,
+};
+use axum::{
+    routing::{get, put},
+    Json, Router,
+};
+use axum_auth::AuthBearer;
+use color_eyre::eyre::eyre;
 use serde::{Deserialize, Serialize};
 use sysinfo::{CpuExt, DiskExt, System, SystemExt};
 
@@ -19,6 +28,8 @@ pub struct CoreInfo {
     uuid: String,
     core_name: String,
     up_since: i64,
+    /// changes are refused for maintenance, reads still work
+    read_only: bool,
 }
 
 pub async fn get_core_info(
@@ -50,11 +61,30 @@ pub async fn get_core_info(
         core_name: state.global_settings.lock().await.core_name(),
         uuid: state.uuid.clone(),
         up_since: state.up_since,
+        read_only: state.read_only.is_on(),
     })
 }
 
+/// Turn the read-only maintenance mode on or off, owner only
+pub async fn set_read_only(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(read_only): Json<bool>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Only the owner can change the read-only mode"),
+        });
+    }
+    state.read_only.set(read_only);
+    Ok(Json(()))
+}
+
 pub fn get_core_info_routes(state: AppState) -> Router {
     Router::new()
         .route("/info", get(get_core_info))
+        .route("/info/read_only", put(set_read_only))
         .with_state(state)
 }
@@ -8,18 +8,31 @@ use axum::{
 };
 use axum_auth::AuthBearer;
 
-use color_eyre::eyre::eyre;
+use color_eyre::eyre::{eyre, Context};
 use futures::{SinkExt, StreamExt};
-use ringbuffer::{AllocRingBuffer, RingBufferExt};
+use ringbuffer::RingBufferExt;
 use tracing::{debug, error};
 
+use crate::console_buffer::{write_console_buffer_limits, ConsoleBufferLimits};
+use crate::console_log::{
+    forget_console_log, read_console_log_settings, write_console_log_settings, ConsoleLog,
+    ConsoleLogSettings,
+};
+use crate::instance_log_rotation::LIVE_LOG_PATH;
+use crate::merged_logs::{
+    merge_log_tails, tail_lines, LogTail, MERGED_LOG_DEFAULT_LINES, MERGED_LOG_MAX_LINES,
+};
 use crate::output_types::ClientEvent;
+use crate::traits::t_configurable::TConfigurable;
 use crate::types::InstanceUuid;
 use crate::{
-    auth::{user::UsersManager, user_id::UserId},
-    db::read::search_events,
+    auth::{
+        user::{UserAction, UsersManager},
+        user_id::UserId,
+    },
+    db::read::{query_event_history, search_events, EventHistoryPage, EventHistoryQuery},
     error::{Error, ErrorKind},
-    events::EventQuery,
+    events::{EventQuery, EventType},
 };
 
 use crate::{
@@ -99,6 +112,111 @@ pub async fn get_event_search(
     search_events(&state.sqlite_pool, query).await.map(Json)
 }
 
+const EVENT_HISTORY_DEFAULT_LIMIT: u32 = 100;
+const EVENT_HISTORY_MAX_LIMIT: u32 = 1000;
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct EventHistoryParams {
+    instance: Option<InstanceUuid>,
+    kind: Option<EventType>,
+    /// unix timestamp in milliseconds
+    since: Option<i64>,
+    cursor: Option<i64>,
+    limit: Option<u32>,
+}
+
+/// Persisted events, filtered by instance, kind and time and paged with the returned cursor
+pub async fn get_event_history(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Query(params): Query<EventHistoryParams>,
+) -> Result<Json<EventHistoryPage>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    let limit = params.limit.unwrap_or(EVENT_HISTORY_DEFAULT_LIMIT);
+    if limit == 0 || limit > EVENT_HISTORY_MAX_LIMIT {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Limit must be between 1 and {EVENT_HISTORY_MAX_LIMIT}"),
+        });
+    }
+    let query = EventHistoryQuery {
+        instance: params.instance,
+        kind: params.kind,
+        since: params.since,
+        cursor: params.cursor,
+        limit,
+    };
+    query_event_history(&state.sqlite_pool, &query, |event| {
+        requester.can_view_event_inner(&event.event_inner)
+    })
+    .await
+    .map(Json)
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct MergedLogQuery {
+    /// comma separated uuids of the instances
+    instances: String,
+    lines: Option<usize>,
+}
+
+/// The most recent lines of the logs of several instances, interleaved by time and prefixed with
+/// the name of their instance. Instances without a log yet are left out
+pub async fn get_merged_log_tail(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Query(query): Query<MergedLogQuery>,
+) -> Result<Json<Vec<String>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    let lines = query.lines.unwrap_or(MERGED_LOG_DEFAULT_LINES);
+    if lines == 0 || lines > MERGED_LOG_MAX_LINES {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Lines must be between 1 and {MERGED_LOG_MAX_LINES}"),
+        });
+    }
+    let uuids: Vec<InstanceUuid> = query
+        .instances
+        .split(',')
+        .filter(|uuid| !uuid.is_empty())
+        .map(|uuid| InstanceUuid::from(uuid.to_string()))
+        .collect();
+    if uuids.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("No instance selected"),
+        });
+    }
+    let safe_mode = state.global_settings.lock().await.safe_mode();
+    let mut tails = Vec::new();
+    for uuid in uuids {
+        requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()), safe_mode)?;
+        let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
+        let label = instance.name().await;
+        let log = instance.path().await.join(LIVE_LOG_PATH);
+        drop(instance);
+        let modified = match tokio::fs::metadata(&log).await {
+            Ok(metadata) => metadata
+                .modified()
+                .map(|modified| chrono::DateTime::<chrono::Local>::from(modified).naive_local())
+                .unwrap_or_else(|_| chrono::Local::now().naive_local()),
+            Err(_) => continue,
+        };
+        let lines = tokio::task::spawn_blocking(move || tail_lines(&log, lines))
+            .await
+            .context("Failed to spawn blocking task")??;
+        tails.push(LogTail {
+            label,
+            lines,
+            modified,
+        });
+    }
+    Ok(Json(merge_log_tails(&tails, lines)))
+}
+
 pub async fn get_console_buffer(
     axum::extract::State(state): axum::extract::State<AppState>,
     AuthBearer(token): AuthBearer,
@@ -119,20 +237,127 @@ pub async fn get_console_buffer(
             .lock()
             .await
             .get(&uuid)
-            .unwrap_or(&AllocRingBuffer::new())
-            .iter()
-            .filter(|event| match &event.event_inner {
-                EventInner::InstanceEvent(instance_event) => {
-                    (instance_event.instance_uuid == uuid || uuid == "all")
-                        && requester.can_view_event(event)
-                }
-                _ => false,
+            .map(|buffer| {
+                buffer
+                    .iter()
+                    .filter(|event| match &event.event_inner {
+                        EventInner::InstanceEvent(instance_event) => {
+                            (instance_event.instance_uuid == uuid || uuid == "all")
+                                && requester.can_view_event(event)
+                        }
+                        _ => false,
+                    })
+                    .cloned()
+                    .collect()
             })
-            .cloned()
-            .collect(),
+            .unwrap_or_default(),
+    ))
+}
+
+pub async fn get_console_buffer_limits(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Path(uuid): Path<InstanceUuid>,
+) -> Result<Json<ConsoleBufferLimits>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessConsole(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    Ok(Json(
+        state
+            .console_out_buffer
+            .lock()
+            .await
+            .get(&uuid)
+            .map(|buffer| buffer.limits())
+            .unwrap_or_default(),
     ))
 }
 
+/// The limits are saved with the instance, a smaller limit drops the oldest lines right away
+pub async fn set_console_buffer_limits(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Path(uuid): Path<InstanceUuid>,
+    Json(limits): Json<ConsoleBufferLimits>,
+) -> Result<Json<ConsoleBufferLimits>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    limits.validate()?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_console_buffer_limits(&path, &limits).await?;
+    state
+        .console_out_buffer
+        .lock()
+        .await
+        .entry(uuid)
+        .or_default()
+        .set_limits(limits);
+    Ok(Json(limits))
+}
+
+pub async fn get_console_log_settings(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Path(uuid): Path<InstanceUuid>,
+) -> Result<Json<ConsoleLogSettings>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_console_log_settings(&path).await))
+}
+
+/// Persisting the console is opt-in, the lines are appended from the next one on
+pub async fn set_console_log_settings(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Path(uuid): Path<InstanceUuid>,
+    Json(settings): Json<ConsoleLogSettings>,
+) -> Result<Json<ConsoleLogSettings>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    settings.validate()?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_console_log_settings(&path, &settings).await?;
+    if settings.enabled {
+        state
+            .console_logs
+            .lock()
+            .await
+            .entry(uuid)
+            .or_insert_with(|| ConsoleLog::new(&path, settings))
+            .set_settings(settings);
+    } else {
+        forget_console_log(&state.console_logs, &uuid).await;
+    }
+    Ok(Json(settings))
+}
+
 #[derive(Deserialize)]
 pub struct WebsocketQuery {
     token: String,
@@ -291,8 +516,18 @@ pub fn get_events_routes(state: AppState) -> Router {
     Router::new()
         .route("/events/:uuid/stream", get(event_stream))
         .route("/events/:uuid/buffer", get(get_event_buffer))
+        .route("/events", get(get_event_history))
         .route("/events/search", get(get_event_search))
+        .route("/logs/merged", get(get_merged_log_tail))
         .route("/instance/:uuid/console/stream", get(console_stream))
         .route("/instance/:uuid/console/buffer", get(get_console_buffer))
+        .route(
+            "/instance/:uuid/console/buffer/limits",
+            get(get_console_buffer_limits).put(set_console_buffer_limits),
+        )
+        .route(
+            "/instance/:uuid/console/log",
+            get(get_console_log_settings).put(set_console_log_settings),
+        )
         .with_state(state)
 }
@@ -5,38 +5,57 @@ use axum::{
     body::{Bytes, StreamBody},
     extract::{Multipart, Path},
     http,
+    response::{AppendHeaders, IntoResponse, Response},
     routing::{delete, get, put},
     Json, Router,
 };
 use axum_auth::AuthBearer;
 
 use color_eyre::eyre::{eyre, Context};
-use headers::{HeaderMap, HeaderName};
+use headers::HeaderMap;
 use reqwest::header::CONTENT_LENGTH;
 use serde::{Deserialize, Serialize};
 
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use tokio_util::io::ReaderStream;
 use ts_rs::TS;
 
 use crate::{
     auth::user::UserAction,
     error::{Error, ErrorKind},
-    events::{new_fs_event, CausedBy, Event, FSOperation, FSTarget},
+    events::{new_fs_event, new_fs_move_event, CausedBy, Event, FSOperation, FSTarget},
+    fs_op_metrics::{FsOpKind, FsOpTimer, TimedStream},
     util::{list_dir, rand_alphanumeric, zip_files},
     AppState,
 };
 
-use super::util::decode_base64;
+use super::util::{decode_base64, parse_range, RangeRequest};
 use crate::prelude::path_to_tmp;
 use tempfile::TempDir;
 
 pub enum DownloadableFile {
     NormalFile(PathBuf),
     ZippedFile((PathBuf, TempDir)),
+    /// a gzipped file decompressed for the download
+    DecompressedFile((PathBuf, TempDir)),
 }
 
-#[derive(Debug, Serialize, Deserialize, TS)]
+/// A download key, pruned by the janitor once it's older than the temp retention
+pub struct DownloadKey {
+    pub file: DownloadableFile,
+    pub created_at: std::time::Instant,
+}
+
+impl From<DownloadableFile> for DownloadKey {
+    fn from(file: DownloadableFile) -> Self {
+        Self {
+            file,
+            created_at: std::time::Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, TS, Clone, Copy, PartialEq, Eq)]
 #[ts(export)]
 pub enum FileType {
     File,
@@ -55,16 +74,29 @@ pub struct FileEntry {
     pub creation_time: Option<u64>,
     pub modification_time: Option<u64>,
     pub file_type: FileType,
+    /// note left on the file by an operator, only set for instance files
+    #[serde(default)]
+    pub annotation: Option<String>,
+    /// another entry of the same directory has the same name ignoring case, only set for
+    /// instance files
+    #[serde(default)]
+    pub case_collision: bool,
+    /// whether the requester is denied modifying the entry, only set for instance files
+    #[serde(default)]
+    pub is_protected: Option<bool>,
+    /// guessed from the extension, only set for instance files that are regular files
+    #[serde(default)]
+    pub mime_type: Option<String>,
 }
 
 impl From<&std::path::Path> for FileEntry {
     fn from(path: &std::path::Path) -> Self {
-        let file_type = if path.is_dir() {
-            FileType::Directory
-        } else if path.is_file() {
-            FileType::File
-        } else {
-            FileType::Unknown
+        // a single stat for the whole entry, listings build one per directory entry
+        let metadata = path.metadata().ok();
+        let file_type = match &metadata {
+            Some(m) if m.is_dir() => FileType::Directory,
+            Some(m) if m.is_file() => FileType::File,
+            _ => FileType::Unknown,
         };
         Self {
             name: path
@@ -75,11 +107,10 @@ impl From<&std::path::Path> for FileEntry {
                 .file_name()
                 .map(|s| s.to_string_lossy().into_owned())
                 .unwrap_or_default(),
-            size: if path.is_file() {
-                path.metadata().ok().map(|m| m.len())
-            } else {
-                None
-            },
+            size: metadata
+                .as_ref()
+                .filter(|m| m.is_file())
+                .map(|m| m.len()),
             file_stem: path
                 .file_stem()
                 .map(|s| s.to_string_lossy().into_owned())
@@ -87,18 +118,22 @@ impl From<&std::path::Path> for FileEntry {
             extension: path.extension().map(|s| s.to_string_lossy().into_owned()),
             // unix timestamp
             // if we cant get the time, return none
-            creation_time: path
-                .metadata()
-                .ok()
+            creation_time: metadata
+                .as_ref()
                 .and_then(|m| m.created().ok())
-                .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
-            modification_time: path
-                .metadata()
-                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
+            modification_time: metadata
+                .as_ref()
                 .and_then(|m| m.modified().ok())
-                .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs()),
 
             file_type,
+            annotation: None,
+            case_collision: false,
+            is_protected: None,
+            mime_type: None,
         }
     }
 }
@@ -280,11 +315,9 @@ async fn move_file(
         user_name: requester.username,
     };
 
-    state.event_broadcaster.send(new_fs_event(
-        FSOperation::Move {
-            source: PathBuf::from(&path_source),
-        },
-        FSTarget::File(PathBuf::from(path_source)),
+    state.event_broadcaster.send(new_fs_move_event(
+        PathBuf::from(path_source),
+        PathBuf::from(path_dest),
         caused_by,
     ));
 
@@ -437,7 +470,7 @@ async fn download_file(
         .download_urls
         .lock()
         .await
-        .insert(key.clone(), downloadable_file);
+        .insert(key.clone(), downloadable_file.into());
     let caused_by = CausedBy::User {
         user_id: requester.uid,
         user_name: requester.username.clone(),
@@ -494,6 +527,7 @@ async fn upload_file(
         },
     );
     state.event_broadcaster.send(progression_start_event);
+    let mut timer = FsOpTimer::start(FsOpKind::Upload, None);
 
     while let Ok(Some(mut field)) = multipart.next_field().await {
         let name = field
@@ -508,14 +542,17 @@ async fn upload_file(
             // add a postfix to the file name
             let mut postfix = 1;
             // get the file name without the extension
-            let file_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+            let file_name = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let extension = path
+                .extension()
+                .map(|extension| format!(".{}", extension.to_string_lossy()))
+                .unwrap_or_default();
             loop {
-                let new_path = path.with_file_name(format!(
-                    "{}_{}.{}",
-                    file_name,
-                    postfix,
-                    path.extension().unwrap().to_str().unwrap()
-                ));
+                let new_path =
+                    path.with_file_name(format!("{}_{}{}", file_name, postfix, extension));
                 if !new_path.exists() {
                     break new_path;
                 }
@@ -553,6 +590,7 @@ async fn upload_file(
                     format!("Uploading {name}"),
                     chunk.len() as f64,
                 ));
+            timer.add_bytes(chunk.len() as u64);
             file.write_all(&chunk).await.map_err(|_| {
                 std::fs::remove_file(&path).ok();
                 eyre!("Failed to write chunk")
@@ -569,6 +607,7 @@ async fn upload_file(
             caused_by,
         ));
     }
+    timer.succeeded();
     state
         .event_broadcaster
         .send(Event::new_progression_event_end(
@@ -581,29 +620,38 @@ async fn upload_file(
     Ok(Json(()))
 }
 
+/// Serve a file by its download key. A `Range` header resumes an interrupted download, the
+/// requested bytes are streamed from the file
 async fn download(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(key): Path<String>,
-) -> Result<
-    (
-        [(HeaderName, String); 3],
-        StreamBody<ReaderStream<tokio::fs::File>>,
-    ),
-    Error,
-> {
+    request_headers: HeaderMap,
+) -> Result<Response, Error> {
     if let Some(downloadable_file) = state.download_urls.lock().await.get(&key) {
-        let path = match downloadable_file {
+        let path = match &downloadable_file.file {
             DownloadableFile::NormalFile(path) => path,
             DownloadableFile::ZippedFile((path, _)) => path,
+            DownloadableFile::DecompressedFile((path, _)) => path,
         };
 
-        let file = tokio::fs::File::open(&path)
+        let mut file = tokio::fs::File::open(&path)
             .await
             .context(format!("Failed to open file {}", path.display()))?;
-
-        let headers = [
+        let len = file
+            .metadata()
+            .await
+            .context(format!("Failed to read metadata of {}", path.display()))?
+            .len();
+
+        let range = parse_range(
+            request_headers
+                .get(http::header::RANGE)
+                .and_then(|range| range.to_str().ok()),
+            len,
+        );
+        let mut headers = vec![
             (
-                http::header::CONTENT_DISPOSITION,
+                http::header::CONTENT_TYPE,
                 "application/octet-stream".to_string(),
             ),
             (
@@ -615,19 +663,37 @@ async fn download(
                         .unwrap_or_else(|| "unknown".to_string())
                 ),
             ),
-            if let Ok(metadata) = file.metadata().await {
-                (http::header::CONTENT_LENGTH, metadata.len().to_string())
-            } else {
-                // if we can't get the file size, we just don't set the header
-                // but the rust compiler enforces array length to be known at compile time
-                // so we just set a dummy header
-                (http::header::ACCEPT_LANGUAGE, "*".to_string())
-            },
+            (http::header::ACCEPT_RANGES, "bytes".to_string()),
         ];
-        let stream = ReaderStream::new(file);
+        let (status, start, body_len) = match range {
+            RangeRequest::Full => (http::StatusCode::OK, 0, len),
+            RangeRequest::Partial(range) => {
+                headers.push((
+                    http::header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{len}", range.start, range.end),
+                ));
+                (http::StatusCode::PARTIAL_CONTENT, range.start, range.size())
+            }
+            RangeRequest::Unsatisfiable => {
+                return Ok((
+                    http::StatusCode::RANGE_NOT_SATISFIABLE,
+                    [(http::header::CONTENT_RANGE, format!("bytes */{len}"))],
+                )
+                    .into_response());
+            }
+        };
+        headers.push((http::header::CONTENT_LENGTH, body_len.to_string()));
+
+        file.seek(std::io::SeekFrom::Start(start))
+            .await
+            .context(format!("Failed to seek in file {}", path.display()))?;
+        let stream = TimedStream::new(
+            ReaderStream::new(file.take(body_len)),
+            FsOpTimer::start(FsOpKind::Download, None),
+        );
         let body = StreamBody::new(stream);
 
-        Ok((headers, body))
+        Ok((status, AppendHeaders(headers), body).into_response())
     } else {
         Err(Error {
             kind: ErrorKind::NotFound,
@@ -5,7 +5,14 @@ use axum::{
 use axum_auth::AuthBearer;
 use color_eyre::eyre::eyre;
 
-use crate::{error::ErrorKind, AppState, Error, GlobalSettingsData};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::{
+    error::ErrorKind, global_settings::JarMirrors, janitor::MIN_TEMP_RETENTION_SECS,
+    prelude::path_to_instances, storage_pools::validate_storage_pools, AppState, Error,
+    GlobalSettingsData,
+};
 
 pub async fn get_core_settings(
     axum::extract::State(state): axum::extract::State<AppState>,
@@ -134,6 +141,235 @@ pub async fn change_core_playit_enabled(
     Ok(())
 }
 
+pub async fn change_reachability_probe_url(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(new_url): Json<String>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the reachability probe"),
+        });
+    }
+    let new_url = new_url.trim();
+    if !new_url.is_empty() {
+        url::Url::parse(new_url)
+            .ok()
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+            .ok_or_else(|| Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Invalid probe url, expected an http(s) url"),
+            })?;
+    }
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_reachability_probe_url(if new_url.is_empty() {
+            None
+        } else {
+            Some(new_url.to_string())
+        })
+        .await?;
+    Ok(())
+}
+
+pub async fn change_max_fs_request_paths(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(max_fs_request_paths): Json<u32>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the path limit"),
+        });
+    }
+    if max_fs_request_paths == 0 {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path limit must be at least 1"),
+        });
+    }
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_max_fs_request_paths(max_fs_request_paths)
+        .await?;
+    Ok(())
+}
+
+pub async fn change_temp_retention_secs(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(temp_retention_secs): Json<u64>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the temporary file retention"),
+        });
+    }
+    // an archive can take a while to extract, don't prune its temporary files from under it
+    if temp_retention_secs < MIN_TEMP_RETENTION_SECS {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Retention must be at least {MIN_TEMP_RETENTION_SECS} seconds"),
+        });
+    }
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_temp_retention_secs(temp_retention_secs)
+        .await?;
+    Ok(())
+}
+
+pub async fn change_fs_read_timeout_secs(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(fs_read_timeout_secs): Json<u64>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the filesystem read timeout"),
+        });
+    }
+    if fs_read_timeout_secs == 0 {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Timeout must be at least 1 second"),
+        });
+    }
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_fs_read_timeout_secs(fs_read_timeout_secs)
+        .await?;
+    Ok(())
+}
+
+pub async fn change_jar_mirrors(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(jar_mirrors): Json<JarMirrors>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the jar mirrors"),
+        });
+    }
+    let normalize = |mirror: Option<String>| -> Result<Option<String>, Error> {
+        let Some(mirror) = mirror.map(|mirror| mirror.trim().trim_end_matches('/').to_string())
+        else {
+            return Ok(None);
+        };
+        if mirror.is_empty() {
+            return Ok(None);
+        }
+        url::Url::parse(&mirror)
+            .ok()
+            .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+            .filter(|url| url.query().is_none() && url.fragment().is_none())
+            .ok_or_else(|| Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Invalid mirror {mirror}, expected an http(s) base url"),
+            })?;
+        Ok(Some(mirror))
+    };
+    let jar_mirrors = JarMirrors {
+        vanilla: normalize(jar_mirrors.vanilla)?,
+        fabric: normalize(jar_mirrors.fabric)?,
+        paper: normalize(jar_mirrors.paper)?,
+        forge: normalize(jar_mirrors.forge)?,
+    };
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_jar_mirrors(jar_mirrors)
+        .await?;
+    Ok(())
+}
+
+pub async fn change_fetch_allowed_hosts(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(fetch_allowed_hosts): Json<Vec<String>>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the allowed fetch hosts"),
+        });
+    }
+    let mut hosts: Vec<String> = Vec::new();
+    for host in fetch_allowed_hosts {
+        let host = host.trim().to_ascii_lowercase();
+        if host.is_empty() {
+            continue;
+        }
+        let domain = host.strip_prefix("*.").unwrap_or(&host);
+        if domain.is_empty()
+            || domain.starts_with('.')
+            || domain.ends_with('.')
+            || !domain
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+        {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Invalid host {host}, expected a host name like cdn.example.com or *.example.com"),
+            });
+        }
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_fetch_allowed_hosts(hosts)
+        .await?;
+    Ok(())
+}
+
+/// Replace the storage pools. Instances in a removed pool stay where they are, relocated
+pub async fn change_storage_pools(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(storage_pools): Json<BTreeMap<String, PathBuf>>,
+) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Not authorized to change the storage pools"),
+        });
+    }
+    validate_storage_pools(&storage_pools, path_to_instances())?;
+    state
+        .global_settings
+        .lock()
+        .await
+        .set_storage_pools(storage_pools)
+        .await?;
+    Ok(())
+}
+
 pub fn get_global_settings_routes(state: AppState) -> Router {
     Router::new()
         .route("/global_settings", get(get_core_settings))
@@ -144,5 +380,27 @@ pub fn get_global_settings_routes(state: AppState) -> Router {
             "/global_settings/playit_enabled",
             put(change_core_playit_enabled),
         )
+        .route(
+            "/global_settings/reachability_probe_url",
+            put(change_reachability_probe_url),
+        )
+        .route(
+            "/global_settings/max_fs_request_paths",
+            put(change_max_fs_request_paths),
+        )
+        .route(
+            "/global_settings/temp_retention_secs",
+            put(change_temp_retention_secs),
+        )
+        .route(
+            "/global_settings/fs_read_timeout_secs",
+            put(change_fs_read_timeout_secs),
+        )
+        .route("/global_settings/jar_mirrors", put(change_jar_mirrors))
+        .route(
+            "/global_settings/fetch_allowed_hosts",
+            put(change_fetch_allowed_hosts),
+        )
+        .route("/global_settings/storage_pools", put(change_storage_pools))
         .with_state(state)
 }
@@ -1,53 +1,138 @@
-use axum::routing::{delete, get, post};
+use std::future::Future;
+use std::io::Seek;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use axum::body::StreamBody;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::routing::{delete, get, post, put};
 use axum::Router;
-use axum::{extract::Path, Json};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query},
+    Json,
+};
 use axum_auth::AuthBearer;
 
 use bollard::container::ListContainersOptions;
 use bollard::Docker;
 use color_eyre::eyre::{eyre, Context};
+use headers::HeaderName;
+use indexmap::IndexMap;
 use serde::Deserialize;
-use tracing::{error, info};
+use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
-use crate::auth::user::UserAction;
-use crate::error::{Error, ErrorKind};
+use crate::auth::user::{AuthorizedUser, UserAction};
+use crate::console_log::{forget_console_log, move_console_log};
+use crate::error::{Error, ErrorKind, FieldError};
+use crate::event_broadcaster::EventBroadcaster;
 use crate::events::{CausedBy, Event, ProgressionEndValue, ProgressionStartValue};
+use crate::instance_audit::record_instance_creation;
+use crate::instance_dependencies::forget_dependency;
+use crate::instance_export::{
+    export_size, read_export_manifest, read_exportable_config, write_export, write_import_config,
+    ExportManifest,
+};
+use crate::instance_relocation::{
+    copy_dir_verified, read_instance_locations, relocation_target, set_instance_location,
+    InstanceHold,
+};
+use crate::instance_seed::{
+    find_staged_archive, is_seed_archive, seed_from_archive, staged_archive_dir,
+};
+use crate::storage_pools::{available_space, ensure_space, pool_of, pool_root};
 
 use crate::implementations::generic;
 use crate::traits::t_configurable::GameType;
 
-use crate::implementations::minecraft::MinecraftInstance;
-use crate::prelude::{path_to_instances, GameInstance};
-use crate::traits::t_configurable::manifest::SetupValue;
+use crate::implementations::minecraft::{
+    ensure_jre, FlavourKind, MinecraftInstance, RestoreConfig,
+};
+use crate::port_manager::PortManager;
+use crate::prelude::{
+    path_to_binaries, path_to_instances, path_to_stores, path_to_tmp, GameInstance,
+};
+use crate::traits::t_configurable::manifest::{SetupManifest, SetupValue};
 use crate::traits::t_configurable::Game::Generic;
 use crate::traits::{t_configurable::TConfigurable, t_server::TServer, InstanceInfo, TInstance};
-use crate::types::{DotLodestoneConfig, InstanceUuid};
+use crate::types::{DotLodestoneConfig, FailedInstanceLoad, InstanceUuid};
+use crate::util::{
+    archive_entry_count, format_byte_download, rand_alphanumeric, tree_size, ProgressThrottle,
+};
 use crate::{implementations::minecraft, traits::t_server::State, AppState};
 
+use super::instance_fs::receive_upload_file;
 use super::instance_setup_configs::HandlerGameType;
+use super::util::{authorize, ensure_unlocked};
 
 pub async fn get_instance_list(
     axum::extract::State(state): axum::extract::State<AppState>,
     AuthBearer(token): AuthBearer,
 ) -> Result<Json<Vec<InstanceInfo>>, Error> {
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    let mut list_of_configs: Vec<InstanceInfo> = Vec::new();
-
-    for instance in state.instances.iter() {
-        if requester.can_perform_action(&UserAction::ViewInstance(instance.uuid().await)) {
-            list_of_configs.push(instance.get_instance_info().await);
-        }
-    }
+    // cloned out of the map so a slow instance doesn't keep it locked
+    let instances: Vec<(InstanceUuid, GameInstance)> = state
+        .instances
+        .iter()
+        .filter(|entry| {
+            requester.can_perform_action(&UserAction::ViewInstance(entry.key().clone()))
+        })
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    let mut list_of_configs: Vec<InstanceInfo> =
+        futures::future::join_all(instances.iter().map(|(uuid, instance)| {
+            instance_info_or_busy(
+                uuid.clone(),
+                instance.get_instance_info(),
+                INSTANCE_INFO_TIMEOUT,
+            )
+        }))
+        .await;
     let docker_bridge = state.docker_bridge.clone();
     let vec = docker_bridge.list_containers().await.unwrap_or_default();
 
-    list_of_configs.extend(vec);
+    list_of_configs.extend(
+        vec.into_iter().filter(|info| {
+            requester.can_perform_action(&UserAction::ViewInstance(info.uuid.clone()))
+        }),
+    );
 
     list_of_configs.sort_by(|a, b| a.creation_time.cmp(&b.creation_time));
 
     Ok(Json(list_of_configs))
 }
 
+/// How long listing waits on a single instance before reporting it as busy
+const INSTANCE_INFO_TIMEOUT: Duration = Duration::from_millis(500);
+
+async fn instance_info_or_busy(
+    uuid: InstanceUuid,
+    info: impl Future<Output = InstanceInfo>,
+    timeout: Duration,
+) -> InstanceInfo {
+    match tokio::time::timeout(timeout, info).await {
+        Ok(info) => info,
+        Err(_) => {
+            warn!("Instance {uuid} took too long to report its info, listing it as busy");
+            InstanceInfo::busy(uuid)
+        }
+    }
+}
+
+/// Instances that failed to load on startup, so they can be repaired or removed
+pub async fn get_failed_instance_list(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Vec<FailedInstanceLoad>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::DeleteInstance,
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    Ok(Json(state.failed_instances.lock().await.clone()))
+}
+
 pub async fn get_instance_info(
     Path(uuid): Path<InstanceUuid>,
     axum::extract::State(state): axum::extract::State<AppState>,
@@ -67,64 +152,390 @@ pub async fn get_instance_info(
     Ok(Json(instance.get_instance_info().await))
 }
 
-pub async fn create_minecraft_instance(
+/// Absolute root of an instance, the permission is checked first so unknown uuids aren't
+/// revealed to users who couldn't see the path anyway
+async fn resolve_instance_root(
+    requester: &AuthorizedUser,
+    root: Option<PathBuf>,
+) -> Result<PathBuf, Error> {
+    requester.try_action(&UserAction::WriteGlobalFile)?;
+    let root = root.ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    Ok(tokio::fs::canonicalize(&root).await.unwrap_or(root))
+}
+
+/// Where the instance lives on the host, it reveals the host layout so it needs `WriteGlobalFile`
+pub async fn get_instance_path(
+    Path(uuid): Path<InstanceUuid>,
     axum::extract::State(state): axum::extract::State<AppState>,
     AuthBearer(token): AuthBearer,
-    Path(game_type): Path<HandlerGameType>,
-    Json(manifest_value): Json<SetupValue>,
-) -> Result<Json<InstanceUuid>, Error> {
+) -> Result<Json<PathBuf>, Error> {
+    let requester = authorize(&state, &token).await?;
+    let root = match state.instances.get(&uuid) {
+        Some(instance) => Some(instance.path().await),
+        None => None,
+    };
+    Ok(Json(resolve_instance_root(&requester, root).await?))
+}
+
+/// Stage an archive to seed a new instance with, the returned key is passed to `create_instance`
+pub async fn stage_instance_archive(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    mut multipart: Multipart,
+) -> Result<Json<String>, Error> {
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     requester.try_action(
         &UserAction::CreateInstance,
         state.global_settings.lock().await.safe_mode(),
     )?;
-    let mut perm = requester.permissions;
-
-    let mut instance_uuid = InstanceUuid::default();
+    let field = multipart
+        .next_field()
+        .await
+        .context("Failed to read multipart field")?
+        .ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Missing archive"),
+        })?;
+    let name = sanitize_filename::sanitize(field.file_name().ok_or_else(|| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Missing file name"),
+    })?);
+    if !is_seed_archive(std::path::Path::new(&name)) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Only zip and tar.gz archives can seed an instance"),
+        });
+    }
+    let key = rand_alphanumeric(16);
+    let dir = staged_archive_dir(&key)?;
+    crate::util::fs::create_dir_all(&dir).await?;
+    if let Err(e) =
+        receive_upload_file(field, &dir.join(&name), &CancellationToken::new(), |_| {}).await
+    {
+        crate::util::fs::remove_dir_all(&dir).await.ok();
+        return Err(e);
+    }
+    Ok(Json(key))
+}
 
-    for entry in state.instances.iter() {
-        if let Some(uuid) = entry.key().as_ref().get(0..8) {
-            if uuid == &instance_uuid.no_prefix()[0..8] {
-                instance_uuid = InstanceUuid::default();
+/// Extract the staged archive into the directory of a newly created instance, with its own
+/// progression since the creation one is already complete once the server is set up
+async fn seed_instance_and_report(
+    event_broadcaster: &EventBroadcaster,
+    caused_by: &CausedBy,
+    archive: std::path::PathBuf,
+    path_to_instance: std::path::PathBuf,
+) -> Result<(), Error> {
+    let archive_name = archive
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let total = {
+        let archive = archive.clone();
+        tokio::task::spawn_blocking(move || archive_entry_count(archive))
+            .await
+            .ok()
+            .flatten()
+    };
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Extracting {archive_name}"),
+        total.map(|total| total as f64),
+        None,
+        caused_by.clone(),
+    );
+    event_broadcaster.send(progression_start_event);
+    let mut on_entry = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let caused_by = caused_by.clone();
+        let mut throttle = total.map(|total| (total, ProgressThrottle::new(Some(total))));
+        move |done: u64| {
+            if let Some((total, throttle)) = throttle.as_mut() {
+                if let Some(progressed) = throttle.report(done) {
+                    event_broadcaster.send(
+                        Event::new_progression_event_update(
+                            &event_id,
+                            format!("Extracting {done}/{total} entries"),
+                            progressed as f64,
+                        )
+                        .with_caused_by(caused_by.clone()),
+                    );
+                }
             }
         }
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        seed_from_archive(&archive, &path_to_instance, &mut on_entry)
+    })
+    .await
+    .context("Failed to extract archive")
+    .map_err(Error::from)
+    .and_then(|result| result);
+    event_broadcaster.send(
+        match &result {
+            Ok(_) => Event::new_progression_event_end(
+                event_id,
+                true,
+                Some(&format!("Extracted {archive_name}")),
+                None,
+            ),
+            Err(e) => Event::new_progression_event_end(
+                event_id,
+                false,
+                Some(&format!("Extracting {archive_name} failed: {e}")),
+                None,
+            ),
+        }
+        .with_caused_by(caused_by.clone()),
+    );
+    result.map(|_| ())
+}
+
+fn name_conflict_errors(setup_value: &SetupValue, instance_names: &[String]) -> Vec<FieldError> {
+    let name = setup_value.name.trim();
+    if instance_names
+        .iter()
+        .any(|existing| existing.trim().eq_ignore_ascii_case(name))
+    {
+        vec![FieldError::new(
+            "name",
+            format!("An instance named {name} already exists"),
+        )]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Problems the setup value has with the instances that already exist, a name taken by another
+/// instance or a port that isn't free
+fn conflict_errors(
+    setup_value: &SetupValue,
+    instance_names: &[String],
+    port_manager: &PortManager,
+) -> Vec<FieldError> {
+    let mut errors = name_conflict_errors(setup_value, instance_names);
+    let port = setup_value
+        .get_unique_setting("port")
+        .and_then(|setting| setting.get_value())
+        .and_then(|value| value.try_as_unsigned_integer().ok());
+    if let Some(port) = port {
+        let status = port_manager.port_status(port);
+        if status.is_allocated {
+            errors.push(FieldError::new(
+                "port",
+                format!("Port {port} is used by another instance"),
+            ));
+        } else if status.is_in_use {
+            errors.push(FieldError::new("port", format!("Port {port} is in use")));
+        }
+    }
+    errors
+}
+
+/// Every check `create_minecraft_instance` makes before creating anything, all the problems
+/// with the request are reported at once as field errors
+async fn check_minecraft_setup(
+    game_type: HandlerGameType,
+    setup_value: &SetupValue,
+) -> Result<(FlavourKind, SetupManifest), Error> {
+    let flavour: FlavourKind = match game_type.try_into() {
+        Ok(flavour) => flavour,
+        Err(e) => {
+            let mut errors = vec![FieldError::new("game_type", e.source.to_string())];
+            errors.extend(setup_value.name_errors());
+            return Err(Error::fields(errors));
+        }
+    };
+    let manifest = MinecraftInstance::setup_manifest(&flavour).await?;
+    let errors = MinecraftInstance::setup_value_errors(&manifest, setup_value);
+    if errors.is_empty() {
+        Ok((flavour, manifest))
+    } else {
+        Err(Error::fields(errors))
+    }
+}
+
+async fn instance_names(state: &AppState) -> Vec<String> {
+    let instances: Vec<GameInstance> = state
+        .instances
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    let mut instance_names = Vec::with_capacity(instances.len());
+    for instance in instances {
+        instance_names.push(instance.name().await);
+    }
+    instance_names
+}
+
+/// The checks `check_minecraft_setup` makes, made on the config of an imported instance. Its
+/// port isn't checked, a taken port is replaced by a free one on import
+async fn check_import_config(config: &RestoreConfig) -> Result<(), Error> {
+    let flavour = FlavourKind::from(&config.flavour);
+    if matches!(flavour, FlavourKind::Spigot) {
+        return Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("Spigot instances can't be imported"),
+        });
+    }
+    let manifest = MinecraftInstance::setup_manifest(&flavour).await?;
+    let setup_value = MinecraftInstance::setup_value_of(config);
+    let errors = MinecraftInstance::setup_value_errors(&manifest, &setup_value);
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::fields(errors))
+    }
+}
+
+/// The field errors of a check, other errors (e.g. the versions couldn't be fetched) are
+/// returned as is
+fn into_field_errors<T>(checked: Result<T, Error>) -> Result<Vec<FieldError>, Error> {
+    match checked {
+        Ok(_) => Ok(Vec::new()),
+        Err(e) => match e.field_errors() {
+            Some(errors) => Ok(errors.to_vec()),
+            None => Err(e),
+        },
     }
+}
 
-    let instance_uuid = instance_uuid;
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidateInstanceRequest {
+    game_type: HandlerGameType,
+    setup_value: SetupValue,
+}
 
-    let flavour = game_type.try_into()?;
+/// Dry run of `create_minecraft_instance` for live form validation: the problems creating the
+/// instance would be refused for, and a name or port already taken, which creating allows but
+/// the form can warn about. Nothing is created, an empty list means the request is valid
+pub async fn validate_minecraft_instance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<ValidateInstanceRequest>,
+) -> Result<Json<Vec<FieldError>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::CreateInstance,
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let checked = check_minecraft_setup(request.game_type, &request.setup_value).await;
+    let mut problems = into_field_errors(checked)?;
+    problems.extend(conflict_errors(
+        &request.setup_value,
+        &instance_names(&state).await,
+        &*state.port_manager.lock().await,
+    ));
+    Ok(Json(problems))
+}
 
-    let setup_config = MinecraftInstance::construct_setup_config(manifest_value, flavour).await?;
+/// Uuid for a new instance whose first 8 characters, used in its directory name, aren't those of
+/// an existing instance
+fn new_instance_uuid(state: &AppState) -> InstanceUuid {
+    loop {
+        let instance_uuid = InstanceUuid::default();
+        let no_prefix = instance_uuid.no_prefix();
+        let prefix = &no_prefix[0..8];
+        if !state
+            .instances
+            .iter()
+            .any(|entry| entry.key().no_prefix().get(0..8) == Some(prefix))
+        {
+            return instance_uuid;
+        }
+    }
+}
 
-    let setup_path = path_to_instances().join(format!(
+/// Directory of a new instance. The name comes from the user, so it's sanitized before it ends
+/// up in a path
+fn new_instance_path(name: &str, instance_uuid: &InstanceUuid) -> PathBuf {
+    path_to_instances().join(format!(
         "{}-{}",
-        setup_config.name,
+        sanitize_filename::sanitize(name),
         &instance_uuid.no_prefix()[0..8]
-    ));
+    ))
+}
+
+/// Run the part of an instance creation that writes to its new directory, removing the directory
+/// if it fails so nothing half set up is left behind and a retry starts clean
+async fn set_up_or_clean_up<T>(
+    setup_path: &std::path::Path,
+    setup: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    let result = setup.await;
+    if result.is_err() {
+        if let Err(e) = crate::util::fs::remove_dir_all(setup_path).await {
+            error!(
+                "Failed to remove {} after instance creation failed: {e}",
+                setup_path.display()
+            );
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SeedArchiveQuery {
+    /// key of an archive staged with `stage_instance_archive`
+    archive: Option<String>,
+}
+
+pub async fn create_minecraft_instance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Path(game_type): Path<HandlerGameType>,
+    Query(seed): Query<SeedArchiveQuery>,
+    Json(manifest_value): Json<SetupValue>,
+) -> Result<Json<InstanceUuid>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::CreateInstance,
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let mut perm = requester.permissions;
+    let seed_archive = seed
+        .archive
+        .as_deref()
+        .map(find_staged_archive)
+        .transpose()?;
+
+    let instance_uuid = new_instance_uuid(&state);
+
+    let (flavour, manifest) = check_minecraft_setup(game_type, &manifest_value).await?;
+    let setup_config =
+        MinecraftInstance::construct_setup_config(manifest_value, flavour, &manifest)?;
+
+    let setup_path = new_instance_path(&setup_config.name, &instance_uuid);
 
     tokio::fs::create_dir_all(&setup_path)
         .await
         .context("Failed to create instance directory")?;
 
     let dot_lodestone_config = DotLodestoneConfig::new(instance_uuid.clone(), game_type.into());
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
 
-    // write dot lodestone config
-
-    tokio::fs::write(
-        setup_path.join(".lodestone_config"),
-        serde_json::to_string_pretty(&dot_lodestone_config).unwrap(),
-    )
-    .await
-    .context("Failed to write .lodestone_config file")?;
+    set_up_or_clean_up(&setup_path, async {
+        // write dot lodestone config
+        tokio::fs::write(
+            setup_path.join(".lodestone_config"),
+            serde_json::to_string_pretty(&dot_lodestone_config).unwrap(),
+        )
+        .await
+        .context("Failed to write .lodestone_config file")?;
+        record_instance_creation(&setup_path, &caused_by).await
+    })
+    .await?;
 
     tokio::task::spawn({
         let uuid = instance_uuid.clone();
         let instance_name = setup_config.name.clone();
         let event_broadcaster = state.event_broadcaster.clone();
-        let caused_by = CausedBy::User {
-            user_id: requester.uid.clone(),
-            user_name: requester.username.clone(),
-        };
         async move {
             let (progression_start_event, event_id) = Event::new_progression_event_start(
                 format!("Setting up Minecraft server {instance_name}"),
@@ -132,30 +543,50 @@ pub async fn create_minecraft_instance(
                 Some(ProgressionStartValue::InstanceCreation {
                     instance_uuid: uuid.clone(),
                 }),
-                caused_by,
+                caused_by.clone(),
             );
             event_broadcaster.send(progression_start_event);
-            let minecraft_instance = match minecraft::MinecraftInstance::new(
-                setup_config.clone(),
-                dot_lodestone_config,
-                setup_path.clone(),
-                &event_id,
-                state.event_broadcaster.clone(),
-                state.macro_executor.clone(),
-            )
-            .await
-            {
-                Ok(v) => {
-                    event_broadcaster.send(Event::new_progression_event_end(
-                        event_id,
-                        true,
-                        Some("Instance created successfully"),
-                        Some(ProgressionEndValue::InstanceCreation(
-                            v.get_instance_info().await,
-                        )),
-                    ));
-                    v
+            let setup = async {
+                let minecraft_instance = minecraft::MinecraftInstance::new(
+                    setup_config.clone(),
+                    dot_lodestone_config,
+                    setup_path.clone(),
+                    &event_id,
+                    state.event_broadcaster.clone(),
+                    state.macro_executor.clone(),
+                    state.global_settings.clone(),
+                )
+                .await?;
+                // seeded before the first start, the port stays the one reserved for the instance
+                if let Some(archive) = seed_archive {
+                    let staged_dir = archive.parent().map(|dir| dir.to_path_buf());
+                    let seeded = match seed_instance_and_report(
+                        &event_broadcaster,
+                        &caused_by,
+                        archive,
+                        setup_path.clone(),
+                    )
+                    .await
+                    {
+                        Ok(()) => {
+                            minecraft_instance
+                                .patch_server_properties(&IndexMap::from([(
+                                    "server-port".to_string(),
+                                    Some(setup_config.port.to_string()),
+                                )]))
+                                .await
+                        }
+                        Err(e) => Err(e),
+                    };
+                    if let Some(staged_dir) = staged_dir {
+                        crate::util::fs::remove_dir_all(staged_dir).await.ok();
+                    }
+                    seeded?;
                 }
+                Ok::<_, Error>(minecraft_instance)
+            };
+            let minecraft_instance = match set_up_or_clean_up(&setup_path, setup).await {
+                Ok(v) => v,
                 Err(e) => {
                     event_broadcaster.send(Event::new_progression_event_end(
                         event_id,
@@ -163,13 +594,17 @@ pub async fn create_minecraft_instance(
                         Some(&format!("Instance creation failed: {e}")),
                         None,
                     ));
-                    crate::util::fs::remove_dir_all(setup_path)
-                        .await
-                        .context("Failed to remove directory after instance creation failed")
-                        .unwrap();
                     return;
                 }
             };
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                true,
+                Some("Instance created successfully"),
+                Some(ProgressionEndValue::InstanceCreation(
+                    minecraft_instance.get_instance_info().await,
+                )),
+            ));
             let mut port_manager = state.port_manager.lock().await;
             port_manager.add_port(setup_config.port);
             perm.can_start_instance.insert(uuid.clone());
@@ -212,22 +647,9 @@ pub async fn create_generic_instance(
         &UserAction::CreateInstance,
         state.global_settings.lock().await.safe_mode(),
     )?;
-    let mut instance_uuid = InstanceUuid::default();
-    for entry in state.instances.iter() {
-        if let Some(uuid) = entry.key().as_ref().get(0..8) {
-            if uuid == &instance_uuid.no_prefix()[0..8] {
-                instance_uuid = InstanceUuid::default();
-            }
-        }
-    }
-
-    let instance_uuid = instance_uuid;
+    let instance_uuid = new_instance_uuid(&state);
 
-    let setup_path = path_to_instances().join(format!(
-        "{}-{}",
-        setup_config.setup_value.name,
-        &instance_uuid.no_prefix()[0..8]
-    ));
+    let setup_path = new_instance_path(&setup_config.setup_value.name, &instance_uuid);
 
     tokio::fs::create_dir_all(&setup_path)
         .await
@@ -236,19 +658,20 @@ pub async fn create_generic_instance(
     let dot_lodestone_config = DotLodestoneConfig::new(instance_uuid.clone(), GameType::Generic);
     let event_broadcaster = state.event_broadcaster.clone();
     tokio::task::spawn(async move {
+        let caused_by = CausedBy::User {
+            user_id: requester.uid,
+            user_name: requester.username,
+        };
         let (progression_start_event, event_id) = Event::new_progression_event_start(
             format!("Setting up instance {}", setup_config.setup_value.name),
             Some(100.0),
             Some(ProgressionStartValue::InstanceCreation {
                 instance_uuid: instance_uuid.clone(),
             }),
-            CausedBy::User {
-                user_id: requester.uid,
-                user_name: requester.username,
-            },
+            caused_by.clone(),
         );
         event_broadcaster.send(progression_start_event);
-        let instance = match generic::GenericInstance::new(
+        let setup = generic::GenericInstance::new(
             setup_config.url.into(),
             setup_path.clone(),
             dot_lodestone_config.clone(),
@@ -256,9 +679,8 @@ pub async fn create_generic_instance(
             &event_id,
             state.event_broadcaster.clone(),
             state.macro_executor.clone(),
-        )
-        .await
-        {
+        );
+        let instance = match set_up_or_clean_up(&setup_path, setup).await {
             Ok(v) => {
                 info!("Atom created successfully");
                 event_broadcaster.send(Event::new_progression_event_end(
@@ -279,11 +701,6 @@ pub async fn create_generic_instance(
                     Some(&format!("Instance creation failed: {e}")),
                     None,
                 ));
-                crate::util::fs::remove_dir_all(setup_path)
-                    .await
-                    .map_err(Error::log)
-                    .context("Failed to remove directory after instance creation failed")
-                    .unwrap();
                 return;
             }
         };
@@ -297,6 +714,10 @@ pub async fn create_generic_instance(
         .await
         .context("Failed to write .lodestone_config file")
         .unwrap();
+        record_instance_creation(&setup_path, &caused_by)
+            .await
+            .map_err(Error::log)
+            .ok();
 
         state
             .instances
@@ -306,71 +727,439 @@ pub async fn create_generic_instance(
     Ok(Json(()))
 }
 
-pub async fn delete_instance(
+/// Export the stopped instance as a zip of its files and a manifest of its config, to import it
+/// on another host. Progress is reported while the archive is built, then it is streamed
+pub async fn export_instance(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
     AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
+) -> Result<
+    (
+        [(HeaderName, String); 2],
+        StreamBody<ReaderStream<tokio::fs::File>>,
+    ),
+    Error,
+> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
+    if instance.state().await != State::Stopped {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance must be stopped before it is exported"),
+        });
+    }
+    let root = instance.path().await;
+    let instance_name = instance.name().await;
+    drop(instance);
+    let manifest = ExportManifest::new(read_exportable_config(&root).await?);
+
+    let total = {
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || export_size(&root))
+            .await
+            .context("Failed to size the export")?
+    };
+    let event_broadcaster = state.event_broadcaster.clone();
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Exporting instance {instance_name}"),
+        Some(total as f64),
+        None,
+        CausedBy::User {
+            user_id: requester.uid.clone(),
+            user_name: requester.username.clone(),
+        },
+    );
+    event_broadcaster.send(progression_start_event);
+    let mut on_progress = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let mut throttle = ProgressThrottle::new(Some(total));
+        move |archived: u64| {
+            if let Some(progressed) = throttle.report(archived) {
+                event_broadcaster.send(Event::new_progression_event_update(
+                    &event_id,
+                    format!("Archived {}", format_byte_download(archived, total)),
+                    progressed as f64,
+                ));
+            }
+        }
+    };
+    // unnamed, the archive is gone once streamed
+    let exported = tokio::task::spawn_blocking(move || -> Result<std::fs::File, Error> {
+        let mut file = tempfile::tempfile_in(path_to_tmp())
+            .context("Failed to create temporary file for the export")?;
+        write_export(&root, &manifest, &mut file, &mut on_progress)?;
+        file.rewind().context("Failed to rewind the export")?;
+        Ok(file)
+    })
+    .await
+    .context("Failed to export instance")
+    .map_err(Error::from)
+    .and_then(|exported| exported);
+    let file = match exported {
+        Ok(file) => {
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                true,
+                Some("Instance exported"),
+                None,
+            ));
+            file
+        }
+        Err(e) => {
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                false,
+                Some(&format!("Export failed: {e}")),
+                None,
+            ));
+            return Err(e);
+        }
+    };
+
+    let headers = [
+        (CONTENT_TYPE, "application/zip".to_string()),
+        (
+            CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.zip\"",
+                sanitize_filename::sanitize(&instance_name)
+            ),
+        ),
+    ];
+    Ok((
+        headers,
+        StreamBody::new(ReaderStream::new(tokio::fs::File::from_std(file))),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportInstanceQuery {
+    /// key of the export staged with `stage_instance_archive`
+    archive: String,
+}
+
+/// Recreate an exported instance from its archive and manifest. The instance gets a new uuid,
+/// and another port if its own is taken on this host
+pub async fn import_instance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Query(query): Query<ImportInstanceQuery>,
+) -> Result<Json<InstanceUuid>, Error> {
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     requester.try_action(
-        &UserAction::DeleteInstance,
+        &UserAction::CreateInstance,
         state.global_settings.lock().await.safe_mode(),
     )?;
+    let mut perm = requester.permissions;
+    let archive = find_staged_archive(&query.archive)?;
+    let manifest = {
+        let archive = archive.clone();
+        tokio::task::spawn_blocking(move || {
+            read_export_manifest(
+                std::fs::File::open(&archive)
+                    .context(format!("Failed to open {}", archive.display()))?,
+            )
+        })
+        .await
+        .context("Failed to read the export manifest")??
+    };
+
+    let instance_uuid = new_instance_uuid(&state);
+
+    let mut config = manifest.into_import_config();
+    check_import_config(&config).await?;
+    let exported_port = config.port;
+    config.port = state.port_manager.lock().await.allocate(exported_port);
+    let setup_path = new_instance_path(&config.name, &instance_uuid);
+    if let Err(e) = tokio::fs::create_dir_all(&setup_path)
+        .await
+        .context("Failed to create instance directory")
+    {
+        state.port_manager.lock().await.deallocate(config.port);
+        return Err(e.into());
+    }
+
     let caused_by = CausedBy::User {
         user_id: requester.uid.clone(),
         user_name: requester.username.clone(),
     };
-    if let Some((_, instance)) = state.instances.remove(&uuid) {
-        if !(instance.state().await == State::Stopped) {
-            state.instances.insert(uuid.clone(), instance);
-            Err(Error {
-                kind: ErrorKind::BadRequest,
-                source: eyre!("Instance must be stopped before deletion"),
-            })
-        } else {
-            let (progression_event_start, event_id) = Event::new_progression_event_start(
-                format!("Deleting instance {}", instance.name().await),
+    tokio::task::spawn({
+        let uuid = instance_uuid.clone();
+        let event_broadcaster = state.event_broadcaster.clone();
+        async move {
+            let (progression_start_event, event_id) = Event::new_progression_event_start(
+                format!("Importing Minecraft server {}", config.name),
                 Some(10.0),
-                None,
-                caused_by,
+                Some(ProgressionStartValue::InstanceCreation {
+                    instance_uuid: uuid.clone(),
+                }),
+                caused_by.clone(),
             );
-            let event_broadcaster = state.event_broadcaster.clone();
-            event_broadcaster.send(progression_event_start);
-            if let Err(e) =
-                tokio::fs::remove_file(instance.path().await.join(".lodestone_config")).await
-            {
-                event_broadcaster.send(Event::new_progression_event_end(
-                    event_id,
-                    false,
-                    Some("Failed to delete .lodestone_config. Instance not deleted"),
-                    None,
-                ));
-                state.instances.insert(uuid.clone(), instance);
-                return Err::<Json<()>, std::io::Error>(e)
-                    .context("Failed to delete .lodestone_config file. Instance not deleted")
-                    .map_err(Into::into);
-            }
-
-            state
-                .port_manager
-                .lock()
+            event_broadcaster.send(progression_start_event);
+            let port = config.port;
+            let setup = async {
+                seed_instance_and_report(
+                    &event_broadcaster,
+                    &caused_by,
+                    archive.clone(),
+                    setup_path.clone(),
+                )
+                .await?;
+                // the runtime isn't part of the export
+                config.jre_major_version = ensure_jre(
+                    &config.version,
+                    path_to_binaries(),
+                    "1/1",
+                    &event_id,
+                    &event_broadcaster,
+                )
+                .await?;
+                write_import_config(&setup_path, &config).await?;
+                let dot_lodestone_config =
+                    DotLodestoneConfig::new(uuid.clone(), GameType::MinecraftJava);
+                tokio::fs::write(
+                    setup_path.join(".lodestone_config"),
+                    serde_json::to_string_pretty(&dot_lodestone_config).unwrap(),
+                )
                 .await
-                .deallocate(instance.port().await);
-            let instance_path = instance.path().await;
-            // if instance is generic
-            if let GameInstance::GenericInstance(i) = instance {
-                i.destruct().await;
+                .context("Failed to write .lodestone_config file")?;
+                record_instance_creation(&setup_path, &caused_by).await?;
+                let minecraft_instance = MinecraftInstance::restore(
+                    setup_path.clone(),
+                    dot_lodestone_config,
+                    state.event_broadcaster.clone(),
+                    state.macro_executor.clone(),
+                    state.global_settings.clone(),
+                )
+                .await?;
+                if port != exported_port {
+                    minecraft_instance
+                        .patch_server_properties(&IndexMap::from([(
+                            "server-port".to_string(),
+                            Some(port.to_string()),
+                        )]))
+                        .await?;
+                }
+                Ok::<_, Error>(minecraft_instance)
             };
-            let res = crate::util::fs::remove_dir_all(instance_path).await;
-            match &res {
-                Ok(_) => event_broadcaster.send(Event::new_progression_event_end(
-                    event_id,
-                    true,
-                    Some("Instance deleted successfully"),
-                    Some(ProgressionEndValue::InstanceDelete {
-                        instance_uuid: uuid.clone(),
-                    }),
+            let result = set_up_or_clean_up(&setup_path, setup).await;
+            if let Some(staged_dir) = archive.parent() {
+                crate::util::fs::remove_dir_all(staged_dir).await.ok();
+            }
+            let minecraft_instance = match result {
+                Ok(v) => v,
+                Err(e) => {
+                    state.port_manager.lock().await.deallocate(port);
+                    event_broadcaster.send(Event::new_progression_event_end(
+                        event_id,
+                        false,
+                        Some(&format!("Instance import failed: {e}")),
+                        None,
+                    ));
+                    return;
+                }
+            };
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                true,
+                Some("Instance imported successfully"),
+                Some(ProgressionEndValue::InstanceCreation(
+                    minecraft_instance.get_instance_info().await,
                 )),
+            ));
+            perm.can_start_instance.insert(uuid.clone());
+            perm.can_stop_instance.insert(uuid.clone());
+            perm.can_view_instance.insert(uuid.clone());
+            perm.can_read_instance_file.insert(uuid.clone());
+            perm.can_write_instance_file.insert(uuid.clone());
+            // ignore errors since we don't care if the permissions update fails
+            let _ = state
+                .users_manager
+                .write()
+                .await
+                .update_permissions(&requester.uid, perm, CausedBy::System)
+                .await
+                .map_err(|e| {
+                    error!("Failed to update permissions: {:?}", e);
+                    e
+                });
+            state
+                .instances
+                .insert(uuid.clone(), minecraft_instance.into());
+        }
+    });
+    Ok(Json(instance_uuid))
+}
+
+/// How long a forced deletion waits for the server to stop before killing it
+const FORCE_STOP_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a killed server gets to be reported as stopped
+const KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+pub struct DeleteInstanceQuery {
+    /// stop the instance first instead of refusing to delete a running one
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// Checks run before an instance is deleted, a locked instance is refused even when forced
+async fn prepare_deletion(
+    instance: &(impl TServer + TConfigurable),
+    force: bool,
+    caused_by: CausedBy,
+    timeout: Duration,
+) -> Result<(), Error> {
+    ensure_unlocked(instance, "deleting it").await?;
+    stop_for_deletion(instance, force, caused_by, timeout).await
+}
+
+/// Make sure the instance is stopped before it is deleted. Without `force` a running instance
+/// is refused, with it the instance is stopped, and killed if it doesn't stop within `timeout`
+async fn stop_for_deletion(
+    instance: &impl TServer,
+    force: bool,
+    caused_by: CausedBy,
+    timeout: Duration,
+) -> Result<(), Error> {
+    if instance.state().await == State::Stopped {
+        return Ok(());
+    }
+    if !force {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Instance must be stopped before deletion"),
+        });
+    }
+    match tokio::time::timeout(timeout, instance.stop(caused_by.clone(), true)).await {
+        Ok(Ok(())) if instance.state().await == State::Stopped => return Ok(()),
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => warn!("Failed to stop instance before deletion, killing it: {e}"),
+        Err(_) => warn!(
+            "Instance did not stop within {}s, killing it",
+            timeout.as_secs()
+        ),
+    }
+    // kill may report an error even though the instance ends up stopped
+    if let Err(e) = instance.kill(caused_by).await {
+        warn!("Failed to kill instance before deletion: {e}");
+    }
+    tokio::time::timeout(KILL_TIMEOUT, async {
+        while instance.state().await != State::Stopped {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    })
+    .await
+    .map_err(|_| Error {
+        kind: ErrorKind::Internal,
+        source: eyre!("Failed to stop instance, it was not deleted"),
+    })
+}
+
+pub async fn delete_instance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    Query(query): Query<DeleteInstanceQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    let safe_mode = state.global_settings.lock().await.safe_mode();
+    requester.try_action(&UserAction::DeleteInstance, safe_mode)?;
+    if query.force {
+        requester.try_action(&UserAction::StopInstance(uuid.clone()), safe_mode)?;
+    }
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    // cloned out of the map so it isn't locked while the server shuts down
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|entry| entry.value().clone());
+    if let Some(instance) = instance {
+        prepare_deletion(
+            &instance,
+            query.force,
+            caused_by.clone(),
+            FORCE_STOP_TIMEOUT,
+        )
+        .await?;
+    }
+    if let Some((_, instance)) = state.instances.remove(&uuid) {
+        if !(instance.state().await == State::Stopped) {
+            state.instances.insert(uuid.clone(), instance);
+            Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Instance must be stopped before deletion"),
+            })
+        } else {
+            let (progression_event_start, event_id) = Event::new_progression_event_start(
+                format!("Deleting instance {}", instance.name().await),
+                Some(10.0),
+                None,
+                caused_by,
+            );
+            let event_broadcaster = state.event_broadcaster.clone();
+            event_broadcaster.send(progression_event_start);
+            if let Err(e) =
+                tokio::fs::remove_file(instance.path().await.join(".lodestone_config")).await
+            {
+                event_broadcaster.send(Event::new_progression_event_end(
+                    event_id,
+                    false,
+                    Some("Failed to delete .lodestone_config. Instance not deleted"),
+                    None,
+                ));
+                state.instances.insert(uuid.clone(), instance);
+                return Err::<Json<()>, std::io::Error>(e)
+                    .context("Failed to delete .lodestone_config file. Instance not deleted")
+                    .map_err(Into::into);
+            }
+
+            state
+                .port_manager
+                .lock()
+                .await
+                .deallocate(instance.port().await);
+            forget_dependency(&state.instances, &uuid).await;
+            // written out before the directory goes, the writer would recreate it otherwise
+            forget_console_log(&state.console_logs, &uuid).await;
+            let instance_path = instance.path().await;
+            // if instance is generic
+            if let GameInstance::GenericInstance(i) = instance {
+                i.destruct().await;
+            };
+            let res = crate::util::fs::remove_dir_all(instance_path).await;
+            match &res {
+                Ok(_) => {
+                    if read_instance_locations(path_to_stores())
+                        .await
+                        .contains_key(&uuid)
+                    {
+                        set_instance_location(path_to_stores(), &uuid, None)
+                            .await
+                            .map_err(Error::log)
+                            .ok();
+                    }
+                    event_broadcaster.send(Event::new_progression_event_end(
+                        event_id,
+                        true,
+                        Some("Instance deleted successfully"),
+                        Some(ProgressionEndValue::InstanceDelete {
+                            instance_uuid: uuid.clone(),
+                        }),
+                    ))
+                }
                 Err(e) => {
                     event_broadcaster.send(Event::new_progression_event_end(
                         event_id,
@@ -392,15 +1181,899 @@ pub async fn delete_instance(
     }
 }
 
+#[derive(Deserialize)]
+pub struct RelocateInstanceRequest {
+    /// absolute directory on the host the instance directory is moved into
+    pub new_root: PathBuf,
+}
+
+/// Move the instance directory under `new_root`, e.g. on another disk. The directory is copied
+/// then verified, the instance is switched over and only then is the old directory removed.
+/// A failed relocation leaves the instance where it was
+pub async fn relocate_instance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<RelocateInstanceRequest>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::WriteGlobalFile,
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
+    ensure_unlocked(&instance, "relocating it").await?;
+    // held until the relocation is over, the instance can't be started meanwhile
+    let hold = state.instance_holds.hold(&uuid, "relocated")?;
+    if instance.state().await != State::Stopped {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance must be stopped before relocation"),
+        });
+    }
+    let old_path = instance.path().await;
+    let new_path = relocation_target(&old_path, &request.new_root)?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let instance_name = instance.name().await;
+    tokio::task::spawn(relocate_and_report(
+        state,
+        uuid,
+        instance_name,
+        old_path,
+        new_path,
+        caused_by,
+        hold,
+    ));
+    Ok(Json(()))
+}
+
+/// Storage pool the instance is in, `None` for an instance relocated outside of every pool
+pub async fn get_instance_pool(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Option<String>>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ViewInstance(uuid.clone()))?;
+    let path = state
+        .instances
+        .get(&uuid)
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?
+        .path()
+        .await;
+    let pools = state.global_settings.lock().await.storage_pools();
+    Ok(Json(pool_of(&pools, &path_to_instances(), &path)))
+}
+
+#[derive(Deserialize)]
+pub struct MigrateInstanceRequest {
+    pub pool: String,
+}
+
+/// Relocate the instance into a storage pool, once checked that the pool has room for it
+pub async fn migrate_instance_to_pool(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<MigrateInstanceRequest>,
+) -> Result<Json<()>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteGlobalFile)?;
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
+    ensure_unlocked(&instance, "migrating it").await?;
+    let hold = state.instance_holds.hold(&uuid, "relocated")?;
+    if instance.state().await != State::Stopped {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance must be stopped before migration"),
+        });
+    }
+    let old_path = instance.path().await;
+    let pools = state.global_settings.lock().await.storage_pools();
+    if pool_of(&pools, &path_to_instances(), &old_path).as_deref() == Some(request.pool.as_str()) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Instance is already in pool {}", request.pool),
+        });
+    }
+    let root = pool_root(&pools, &path_to_instances(), &request.pool)?;
+    let new_path = relocation_target(&old_path, &root)?;
+    let (required, available) = {
+        let (old_path, root) = (old_path.clone(), root.clone());
+        tokio::task::spawn_blocking(move || (tree_size([&old_path]).bytes, available_space(&root)))
+            .await
+            .context("Failed to check the space left in the pool")?
+    };
+    ensure_space(&request.pool, required, available)?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let instance_name = instance.name().await;
+    tokio::task::spawn(relocate_and_report(
+        state,
+        uuid,
+        instance_name,
+        old_path,
+        new_path,
+        caused_by,
+        hold,
+    ));
+    Ok(Json(()))
+}
+
+/// Copy the stopped instance to `new_path`, switch it over then remove `old_path`, reporting
+/// progress. A failure leaves the instance at `old_path`. `_hold` keeps the instance from being
+/// started until it is done
+async fn relocate_and_report(
+    state: AppState,
+    uuid: InstanceUuid,
+    instance_name: String,
+    old_path: PathBuf,
+    new_path: PathBuf,
+    caused_by: CausedBy,
+    _hold: InstanceHold,
+) {
+    let event_broadcaster = state.event_broadcaster.clone();
+    let total = {
+        let old_path = old_path.clone();
+        tokio::task::spawn_blocking(move || tree_size([&old_path]).bytes)
+            .await
+            .ok()
+    };
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Relocating instance {instance_name}"),
+        total.map(|total| total as f64),
+        None,
+        caused_by.clone(),
+    );
+    event_broadcaster.send(progression_start_event);
+    let on_progress = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let caused_by = caused_by.clone();
+        let mut throttle = ProgressThrottle::new(total);
+        move |copied: u64, file: &std::path::Path| {
+            if let Some(progressed) = throttle.report(copied) {
+                event_broadcaster.send(
+                    Event::new_progression_event_update(
+                        &event_id,
+                        format!("Copied {}", file.display()),
+                        progressed as f64,
+                    )
+                    .with_caused_by(caused_by.clone()),
+                );
+            }
+        }
+    };
+    let copied = {
+        let (old_path, new_path) = (old_path.clone(), new_path.clone());
+        tokio::task::spawn_blocking(move || copy_dir_verified(&old_path, &new_path, on_progress))
+            .await
+            .context("Failed to copy instance")
+            .map_err(Error::from)
+            .and_then(|copied| copied)
+    };
+    let switched = match copied {
+        Ok(_) => {
+            let switched = switch_to_relocated(&state, &uuid, new_path.clone()).await;
+            if switched.is_err() {
+                crate::util::fs::remove_dir_all(&new_path)
+                    .await
+                    .map_err(Error::log)
+                    .ok();
+            }
+            switched
+        }
+        Err(e) => Err(e),
+    };
+    match switched {
+        Ok(()) => {
+            // the instance runs from the copy already, a leftover is only logged
+            if let Err(e) = crate::util::fs::remove_dir_all(&old_path).await {
+                warn!(
+                    "Instance relocated but failed to remove {}: {e}",
+                    old_path.display()
+                );
+            }
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                true,
+                Some(&format!("Instance relocated to {}", new_path.display())),
+                None,
+            ));
+        }
+        Err(e) => {
+            error!("Failed to relocate instance {uuid}: {e}");
+            event_broadcaster.send(Event::new_progression_event_end(
+                event_id,
+                false,
+                Some(&format!("Instance relocation failed: {e}")),
+                None,
+            ));
+        }
+    }
+}
+
+/// Replace the instance with one restored from its copy at `new_path`, the caller holds the
+/// instance so it can't be started meanwhile. On failure the original is put back
+async fn switch_to_relocated(
+    state: &AppState,
+    uuid: &InstanceUuid,
+    new_path: PathBuf,
+) -> Result<(), Error> {
+    let (_, instance) = state.instances.remove(uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance was deleted during relocation"),
+    })?;
+    if instance.state().await != State::Stopped {
+        state.instances.insert(uuid.clone(), instance);
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance was started during relocation"),
+        });
+    }
+    let previous_location = read_instance_locations(path_to_stores()).await.remove(uuid);
+    let relocated = async {
+        let dot_lodestone_config: DotLodestoneConfig = serde_json::from_slice(
+            &tokio::fs::read(new_path.join(".lodestone_config"))
+                .await
+                .context("Failed to read .lodestone_config file")?,
+        )
+        .context("Failed to parse .lodestone_config file")?;
+        // instances moved back into the instances directory are found without the store
+        let location =
+            (new_path.parent() != Some(path_to_instances().as_path())).then(|| new_path.clone());
+        set_instance_location(path_to_stores(), uuid, location).await?;
+        crate::restore_instance(
+            new_path.clone(),
+            &dot_lodestone_config,
+            state.event_broadcaster.clone(),
+            state.macro_executor.clone(),
+            state.global_settings.clone(),
+        )
+        .await
+    }
+    .await;
+    match relocated {
+        Ok(relocated) => {
+            move_console_log(&state.console_logs, uuid, &new_path).await;
+            state.instances.insert(uuid.clone(), relocated);
+            if let GameInstance::GenericInstance(instance) = instance {
+                instance.destruct().await;
+            }
+            Ok(())
+        }
+        Err(e) => {
+            set_instance_location(path_to_stores(), uuid, previous_location)
+                .await
+                .map_err(Error::log)
+                .ok();
+            state.instances.insert(uuid.clone(), instance);
+            Err(e)
+        }
+    }
+}
+
 pub fn get_instance_routes(state: AppState) -> Router {
     Router::new()
         .route("/instance/list", get(get_instance_list))
+        .route("/instance/failed", get(get_failed_instance_list))
         .route(
             "/instance/create/:game_type",
             post(create_minecraft_instance),
         )
+        .route(
+            "/instance/create/archive",
+            put(stage_instance_archive).layer(DefaultBodyLimit::disable()),
+        )
         .route("/instance/create_generic", post(create_generic_instance))
+        .route("/instance/import", post(import_instance))
+        .route("/instance/validate", post(validate_minecraft_instance))
         .route("/instance/:uuid", delete(delete_instance))
         .route("/instance/:uuid/info", get(get_instance_info))
+        .route("/instance/:uuid/path", get(get_instance_path))
+        .route("/instance/:uuid/relocate", post(relocate_instance))
+        .route("/instance/:uuid/export", post(export_instance))
+        .route(
+            "/instance/:uuid/pool",
+            get(get_instance_pool).post(migrate_instance_to_pool),
+        )
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::test_util::FakeInstance;
+
+    #[tokio::test]
+    async fn test_listing_marks_held_instance_busy() {
+        let free = InstanceUuid::from("INSTANCE_free".to_string());
+        let held = InstanceUuid::from("INSTANCE_held".to_string());
+        let lock = Arc::new(Mutex::new(()));
+        // a long operation holding the instance's lock
+        let _guard = lock.lock().await;
+
+        let read_info = |uuid: InstanceUuid| {
+            let lock = lock.clone();
+            async move {
+                if uuid == "INSTANCE_held" {
+                    let _ = lock.lock().await;
+                }
+                InstanceInfo {
+                    name: "survival".to_string(),
+                    busy: false,
+                    ..InstanceInfo::busy(uuid)
+                }
+            }
+        };
+        let list = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join_all([free.clone(), held.clone()].map(|uuid| {
+                instance_info_or_busy(uuid.clone(), read_info(uuid), Duration::from_millis(50))
+            })),
+        )
+        .await
+        .expect("listing blocked on the held instance");
+
+        assert_eq!(list[0].uuid, free);
+        assert!(!list[0].busy);
+        assert_eq!(list[0].name, "survival");
+        assert_eq!(list[1].uuid, held);
+        assert!(list[1].busy);
+    }
+
+    #[tokio::test]
+    async fn test_locked_instance_refuses_deletion_until_unlocked() {
+        let server = FakeInstance::new("survival", State::Running);
+        server.set_locked(true).await.unwrap();
+        // not even when forced, and the server keeps running
+        let err = prepare_deletion(&server, true, CausedBy::System, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(err.to_string().contains("locked"));
+        assert_eq!(server.state().await, State::Running);
+
+        server.set_locked(false).await.unwrap();
+        prepare_deletion(&server, true, CausedBy::System, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(server.state().await, State::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_running_instance_is_refused_without_force() {
+        let server = FakeInstance::new("survival", State::Running);
+        let err = stop_for_deletion(&server, false, CausedBy::System, Duration::from_secs(1))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert_eq!(server.state().await, State::Running);
+    }
+
+    #[tokio::test]
+    async fn test_forced_deletion_stops_then_kills() {
+        let server = FakeInstance::new("survival", State::Running);
+        stop_for_deletion(&server, true, CausedBy::System, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(server.state().await, State::Stopped);
+        assert!(!server.killed.load(std::sync::atomic::Ordering::SeqCst));
+
+        let hung = FakeInstance {
+            completes: false,
+            ..FakeInstance::new("hung", State::Running)
+        };
+        stop_for_deletion(&hung, true, CausedBy::System, Duration::from_millis(50))
+            .await
+            .unwrap();
+        assert_eq!(hung.state().await, State::Stopped);
+        assert!(hung.killed.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_listing_shows_only_viewable_instances() {
+        use crate::auth::{permission::UserPermission, user::User};
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let survival = restore_with_fake_java(&temp.path().join("survival"), None).await;
+        let creative = restore_with_fake_java(&temp.path().join("creative"), None).await;
+        let survival_uuid = survival.uuid().await;
+        let creative_uuid = creative.uuid().await;
+        let state = test_app_state(temp.path(), vec![survival.into(), creative.into()]).await;
+
+        let listed = |token: String| {
+            let state = state.clone();
+            async move {
+                let Json(list) = get_instance_list(axum::extract::State(state), AuthBearer(token))
+                    .await
+                    .unwrap();
+                let mut uuids: Vec<InstanceUuid> = list.into_iter().map(|info| info.uuid).collect();
+                uuids.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+                uuids
+            }
+        };
+
+        // other grants on an instance don't list it, only viewing it does
+        let mut permissions = UserPermission::new();
+        permissions.can_view_instance.insert(survival_uuid.clone());
+        permissions
+            .can_read_instance_file
+            .insert(creative_uuid.clone());
+        let scoped = add_test_user(&state, "alice", permissions).await;
+        assert_eq!(listed(scoped).await, vec![survival_uuid.clone()]);
+
+        let nobody = add_test_user(&state, "bob", UserPermission::new()).await;
+        assert!(listed(nobody).await.is_empty());
+
+        let admin = User::new(
+            "carol".to_string(),
+            "password",
+            false,
+            true,
+            UserPermission::new(),
+        );
+        let admin_token = admin.create_jwt().unwrap().to_string();
+        state
+            .users_manager
+            .write()
+            .await
+            .add_user(admin, CausedBy::System)
+            .await
+            .unwrap();
+        let mut all = vec![survival_uuid, creative_uuid];
+        all.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        assert_eq!(listed(admin_token).await, all);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_deleted_instance_is_dropped_from_its_dependents() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let proxy = restore_with_fake_java(&temp.path().join("proxy"), None).await;
+        let lobby = restore_with_fake_java(&temp.path().join("lobby"), None).await;
+        let lobby_uuid = lobby.uuid().await;
+        std::fs::write(lobby.path().await.join(".lodestone_config"), "{}").unwrap();
+        proxy
+            .set_depends_on(vec![lobby_uuid.clone()])
+            .await
+            .unwrap();
+
+        let state = test_app_state(temp.path(), vec![proxy.clone().into(), lobby.into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_delete_instance = true;
+        let token = add_test_user(&state, "alice", permissions).await;
+        delete_instance(
+            axum::extract::State(state.clone()),
+            Path(lobby_uuid.clone()),
+            Query(DeleteInstanceQuery { force: false }),
+            AuthBearer(token),
+        )
+        .await
+        .unwrap();
+        assert!(!state.instances.contains_key(&lobby_uuid));
+        assert!(proxy.depends_on().await.is_empty());
+        // kept across a restart of the core
+        let config: RestoreConfig = serde_json::from_str(
+            &std::fs::read_to_string(proxy.path().await.join(".lodestone_minecraft_config.json"))
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(config.depends_on.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_instance_path_needs_global_write() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("survival-1a2b");
+        std::fs::create_dir(&root).unwrap();
+
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let privileged = AuthorizedUser::new(
+            User::new("alice".to_string(), "password", false, false, permissions),
+            false,
+        );
+        assert_eq!(
+            resolve_instance_root(&privileged, Some(root.clone()))
+                .await
+                .unwrap(),
+            root.canonicalize().unwrap()
+        );
+        let err = resolve_instance_root(&privileged, None).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotFound));
+
+        // an admin without the global permission, even for an instance that doesn't exist
+        let admin = AuthorizedUser::new(
+            User::new(
+                "bob".to_string(),
+                "password",
+                false,
+                true,
+                UserPermission::new(),
+            ),
+            false,
+        );
+        let err = resolve_instance_root(&admin, Some(root)).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        let err = resolve_instance_root(&admin, None).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+    }
+
+    fn setup_value(name: &str, port: u32, min_ram: u32, max_ram: u32) -> SetupValue {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "description": null,
+            "auto_start": false,
+            "restart_on_crash": false,
+            "setting_sections": {
+                "section_1": { "settings": {
+                    "version": { "value": { "type": "Enum", "value": "1.20.1" } },
+                    "port": { "value": { "type": "UnsignedInteger", "value": port } },
+                } },
+                "section_2": { "settings": {
+                    "min_ram": { "value": { "type": "UnsignedInteger", "value": min_ram } },
+                    "max_ram": { "value": { "type": "UnsignedInteger", "value": max_ram } },
+                    "cmd_args": { "value": null },
+                } },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validation_reports_what_create_refuses() {
+        let manifest = MinecraftInstance::setup_manifest_with_versions(vec![
+            "1.20.1".to_string(),
+            "1.19.4".to_string(),
+        ]);
+        let port_manager = PortManager::new(std::collections::HashSet::from([25565]));
+        let instance_names = vec!["Survival".to_string()];
+        let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken_port = taken.local_addr().unwrap().port() as u32;
+        let free_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port() as u32
+        };
+
+        let valid = setup_value("creative", free_port, 1024, 2048);
+        assert!(MinecraftInstance::setup_value_errors(&manifest, &valid).is_empty());
+        assert!(conflict_errors(&valid, &instance_names, &port_manager).is_empty());
+        MinecraftInstance::construct_setup_config(valid, FlavourKind::Vanilla, &manifest).unwrap();
+
+        let invalid = setup_value(" survival", 25565, 4096, 2048);
+        let errors = MinecraftInstance::setup_value_errors(&manifest, &invalid);
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["min_ram"]);
+        // what creating it would be refused for, through the same check
+        assert_eq!(
+            into_field_errors(Err::<(), _>(Error::fields(errors.clone()))).unwrap(),
+            errors
+        );
+        let err = MinecraftInstance::construct_setup_config(
+            invalid.clone(),
+            FlavourKind::Vanilla,
+            &manifest,
+        )
+        .unwrap_err();
+        assert_eq!(err.field_errors().unwrap(), &errors[..]);
+        // the taken name and port are only reported by the validation
+        let conflicts = conflict_errors(&invalid, &instance_names, &port_manager);
+        let fields: Vec<&str> = conflicts.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec!["name", "port"]);
+        assert!(conflicts[1].message.contains("another instance"));
+
+        let in_use = setup_value("creative", taken_port, 1024, 2048);
+        assert_eq!(
+            conflict_errors(&in_use, &instance_names, &port_manager),
+            vec![FieldError::new(
+                "port",
+                format!("Port {taken_port} is in use")
+            )]
+        );
+
+        // failures that aren't about the request are not problems to show on the form
+        let err = into_field_errors::<()>(Err(Error {
+            kind: ErrorKind::External,
+            source: eyre!("Failed to get minecraft versions"),
+        }))
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::External));
+    }
+
+    #[tokio::test]
+    async fn test_refused_creation_creates_nothing() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let state = test_app_state(temp.path(), vec![]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_create_instance = true;
+        let token = add_test_user(&state, "alice", permissions).await;
+        let name = "refused".repeat(20);
+        let free_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port() as u32
+        };
+        let setup = || setup_value(&name, free_port, 1024, 2048);
+
+        let err = create_minecraft_instance(
+            axum::extract::State(state.clone()),
+            AuthBearer(token.clone()),
+            Path(HandlerGameType::MinecraftBedrock),
+            Query(SeedArchiveQuery { archive: None }),
+            Json(setup()),
+        )
+        .await
+        .unwrap_err();
+        let fields: Vec<&str> = err
+            .field_errors()
+            .unwrap()
+            .iter()
+            .map(|e| e.field.as_str())
+            .collect();
+        assert_eq!(fields, vec!["game_type", "name"]);
+        assert!(state.instances.is_empty());
+        let created = std::fs::read_dir(path_to_instances())
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok())
+                    .any(|entry| entry.file_name().to_string_lossy().starts_with(&name))
+            })
+            .unwrap_or(false);
+        assert!(!created);
+
+        // the validation reports the same problems
+        let Json(problems) = validate_minecraft_instance(
+            axum::extract::State(state.clone()),
+            AuthBearer(token),
+            Json(ValidateInstanceRequest {
+                game_type: HandlerGameType::MinecraftBedrock,
+                setup_value: setup(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(problems, err.field_errors().unwrap());
+    }
+
+    #[tokio::test]
+    #[ignore = "fetches the Minecraft versions"]
+    async fn test_failed_instance_setup_leaves_no_directory() {
+        use crate::auth::permission::UserPermission;
+        use crate::global_settings::JarMirrors;
+        use crate::test_util::{add_test_user, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let state = test_app_state(temp.path(), vec![]).await;
+        // the paths are shared by every test, the directories may be gone with the test that set them
+        std::fs::create_dir_all(path_to_instances()).unwrap();
+        // the runtime is already there, only the server jar is downloaded
+        std::fs::create_dir_all(path_to_binaries().join("java").join("jre17")).unwrap();
+        // and it can't be, so the instance fails to set up halfway
+        state
+            .global_settings
+            .lock()
+            .await
+            .set_jar_mirrors(JarMirrors {
+                vanilla: Some("http://127.0.0.1:1".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut permissions = UserPermission::new();
+        permissions.can_create_instance = true;
+        let token = add_test_user(&state, "alice", permissions).await;
+        let free_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port() as u32
+        };
+
+        let Json(uuid) = create_minecraft_instance(
+            axum::extract::State(state.clone()),
+            AuthBearer(token),
+            Path(HandlerGameType::MinecraftJavaVanilla),
+            Query(SeedArchiveQuery { archive: None }),
+            Json(setup_value("survival", free_port, 1024, 2048)),
+        )
+        .await
+        .unwrap();
+        let setup_path = new_instance_path("survival", &uuid);
+        for _ in 0..300 {
+            if !setup_path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert!(!setup_path.exists());
+        assert!(!state.instances.contains_key(&uuid));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_migrating_an_instance_moves_it_to_the_pool() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let (temp, fast) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let old_path = instance.path().await;
+        std::fs::write(
+            old_path.join(".lodestone_config"),
+            serde_json::to_string(&DotLodestoneConfig::new(
+                uuid.clone(),
+                GameType::MinecraftJava,
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        std::fs::write(old_path.join("server.properties"), "motd=hi\n").unwrap();
+        let state = test_app_state(temp.path(), vec![instance.into()]).await;
+        // the paths are shared by every test, the directories may be gone with the test that set them
+        std::fs::create_dir_all(path_to_stores()).unwrap();
+        std::fs::create_dir_all(path_to_instances()).unwrap();
+        let pools =
+            std::collections::BTreeMap::from([("fast".to_string(), fast.path().to_path_buf())]);
+        state
+            .global_settings
+            .lock()
+            .await
+            .set_storage_pools(pools)
+            .await
+            .unwrap();
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        permissions.can_view_instance.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+
+        migrate_instance_to_pool(
+            axum::extract::State(state.clone()),
+            Path(uuid.clone()),
+            AuthBearer(token.clone()),
+            Json(MigrateInstanceRequest {
+                pool: "fast".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let new_path = fast.path().join(old_path.file_name().unwrap());
+        for _ in 0..50 {
+            let path = state.instances.get(&uuid).unwrap().path().await;
+            if path == new_path {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        assert_eq!(state.instances.get(&uuid).unwrap().path().await, new_path);
+        assert_eq!(
+            std::fs::read_to_string(new_path.join("server.properties")).unwrap(),
+            "motd=hi\n"
+        );
+        assert!(!old_path.exists());
+        let Json(pool) = get_instance_pool(
+            axum::extract::State(state.clone()),
+            Path(uuid.clone()),
+            AuthBearer(token),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pool.as_deref(), Some("fast"));
+        assert_eq!(
+            read_instance_locations(path_to_stores()).await.get(&uuid),
+            Some(&new_path)
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "fetches the Minecraft versions and a JRE"]
+    async fn test_imported_instance_leaves_host_settings_behind() {
+        use crate::auth::permission::UserPermission;
+        use crate::implementations::minecraft::Flavour;
+        use crate::test_util::{add_test_user, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let state = test_app_state(temp.path(), vec![]).await;
+        // the paths are shared by every test, the directories may be gone with the test that set them
+        std::fs::create_dir_all(path_to_instances()).unwrap();
+        let mut permissions = UserPermission::new();
+        permissions.can_create_instance = true;
+        let token = add_test_user(&state, "alice", permissions).await;
+
+        // exported on another host, where it was locked, had started and used its own java
+        let source = tempfile::tempdir().unwrap();
+        std::fs::write(source.path().join("server.properties"), "motd=hi\n").unwrap();
+        let manifest = ExportManifest::new(RestoreConfig {
+            name: "../survival".to_string(),
+            version: "1.20.1".to_string(),
+            flavour: Flavour::Vanilla,
+            description: String::new(),
+            cmd_args: Vec::new(),
+            java_cmd: Some("/opt/java/bin/java".to_string()),
+            port: 25570,
+            min_ram: 1024,
+            max_ram: 2048,
+            auto_start: false,
+            restart_on_crash: false,
+            backup_period: None,
+            jre_major_version: 17,
+            has_started: true,
+            custom_jar_path: Some("paper.jar".to_string()),
+            locked: true,
+            depends_on: vec![InstanceUuid::from("INSTANCE_proxy".to_string())],
+            stop_command: "stop".to_string(),
+        });
+        let key = rand_alphanumeric(16);
+        let staged = staged_archive_dir(&key).unwrap();
+        std::fs::create_dir_all(&staged).unwrap();
+        write_export(
+            source.path(),
+            &manifest,
+            std::fs::File::create(staged.join("survival.zip")).unwrap(),
+            &mut |_| {},
+        )
+        .unwrap();
+
+        let Json(uuid) = import_instance(
+            axum::extract::State(state.clone()),
+            AuthBearer(token),
+            Query(ImportInstanceQuery { archive: key }),
+        )
+        .await
+        .unwrap();
+        for _ in 0..600 {
+            if state.instances.contains_key(&uuid) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        let path = state.instances.get(&uuid).unwrap().path().await;
+
+        assert_eq!(path.parent(), Some(path_to_instances().as_path()));
+        assert!(std::fs::read_to_string(path.join("server.properties"))
+            .unwrap()
+            .contains("motd=hi"));
+        let config = read_exportable_config(&path).await.unwrap();
+        assert_eq!(config.name, "../survival");
+        assert_eq!(config.java_cmd, None);
+        assert_eq!(config.custom_jar_path, None);
+        assert!(!config.locked);
+        assert!(!config.has_started);
+        assert!(config.depends_on.is_empty());
+    }
+}
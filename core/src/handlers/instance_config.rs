@@ -1,22 +1,35 @@
 use axum::{
     extract::Path,
-    routing::{get, put},
+    routing::{get, patch, put},
     Json, Router,
 };
 use axum_auth::AuthBearer;
 use color_eyre::eyre::eyre;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
 
 use crate::{
     auth::user::UserAction,
     error::{Error, ErrorKind},
-    traits::t_configurable::{
-        manifest::{ConfigurableManifest, ConfigurableValue},
-        TConfigurable,
+    events::CausedBy,
+    instance_audit::record_instance_modification,
+    instance_dependencies::{dependency_graph, validate_dependencies},
+    port_manager::{warn_port_conflicts, PortManager},
+    prelude::GameInstance,
+    traits::{
+        t_configurable::{
+            manifest::{ConfigurableManifest, ConfigurableValue},
+            TConfigurable,
+        },
+        t_server::{State, TServer},
     },
     types::InstanceUuid,
     AppState,
 };
 
+use super::util::ensure_unlocked;
+
 pub async fn get_instance_configurable_manifest(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
@@ -73,6 +86,17 @@ pub async fn set_instance_setting(
     instance
         .update_configurable(&section_id, &setting_id, value)
         .await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    // the instance has to be released before all of them are looked at
+    drop(instance);
+    warn_port_conflicts(&state.instances).await;
 
     Ok(Json(()))
 }
@@ -88,15 +112,112 @@ pub async fn set_instance_name(
         &UserAction::AccessSetting(uuid.clone()),
         state.global_settings.lock().await.safe_mode(),
     )?;
-    state
-        .instances
-        .get(&uuid)
-        .ok_or_else(|| Error {
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.set_name(new_name).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// Locking protects the instance from removal, world deletion and version changes, both ways
+/// need the permission to delete instances
+pub async fn set_instance_locked(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(locked): Json<bool>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    let safe_mode = state.global_settings.lock().await.safe_mode();
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()), safe_mode)?;
+    requester.try_action(&UserAction::DeleteInstance, safe_mode)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.set_locked(locked).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// The instances that must be running before this one starts, rejected if one doesn't exist or
+/// if they would depend on this instance in turn
+pub async fn set_instance_depends_on(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(depends_on): Json<Vec<InstanceUuid>>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    if !state.instances.contains_key(&uuid) {
+        return Err(Error {
             kind: ErrorKind::NotFound,
             source: eyre!("Instance not found"),
-        })?
-        .set_name(new_name)
-        .await?;
+        });
+    }
+    let graph = dependency_graph(&state.instances).await;
+    validate_dependencies(&graph, &uuid, &depends_on)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.set_depends_on(depends_on).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// Console command the instance is stopped with, checked to be a single non-empty line
+pub async fn set_instance_stop_command(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(stop_command): Json<String>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.set_stop_command(stop_command).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
     Ok(Json(()))
 }
 
@@ -111,16 +232,92 @@ pub async fn set_instance_description(
         &UserAction::AccessSetting(uuid.clone()),
         state.global_settings.lock().await.safe_mode(),
     )?;
-    state
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.set_description(new_description).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// The port an instance moved from and to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct PortChange {
+    pub previous_port: u32,
+    pub port: u32,
+}
+
+/// Move a stopped instance to `port`. Its allocation moves with it, and back if the instance
+/// fails to save the new port
+async fn change_port(
+    instance: &(impl TServer + TConfigurable),
+    port_manager: &mut PortManager,
+    port: u32,
+) -> Result<PortChange, Error> {
+    if instance.state().await != State::Stopped {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance must be stopped to change its port"),
+        });
+    }
+    let previous_port = instance.port().await;
+    if port == previous_port {
+        return Ok(PortChange {
+            previous_port,
+            port,
+        });
+    }
+    port_manager.reallocate(previous_port, port)?;
+    if let Err(e) = instance.set_port(port).await {
+        port_manager.reallocate(port, previous_port).ok();
+        return Err(e);
+    }
+    Ok(PortChange {
+        previous_port,
+        port,
+    })
+}
+
+/// Change the port of a stopped instance, for Minecraft both the stored config and the
+/// `server-port` of server.properties
+pub async fn set_instance_port(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(port): Json<u32>,
+) -> Result<Json<PortChange>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state
         .instances
         .get(&uuid)
+        .map(|entry| entry.value().clone())
         .ok_or_else(|| Error {
             kind: ErrorKind::NotFound,
             source: eyre!("Instance not found"),
-        })?
-        .set_description(new_description)
-        .await?;
-    Ok(Json(()))
+        })?;
+    let change = change_port(&instance, &mut *state.port_manager.lock().await, port).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(change))
 }
 
 pub async fn change_version(
@@ -133,15 +330,59 @@ pub async fn change_version(
         &UserAction::AccessSetting(uuid.clone()),
         state.global_settings.lock().await.safe_mode(),
     )?;
-    state
-        .instances
-        .get(&uuid)
-        .ok_or_else(|| Error {
-            kind: ErrorKind::NotFound,
-            source: eyre!("Instance not found"),
-        })?
-        .change_version(new_version)
-        .await?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    ensure_unlocked(&*instance, "changing its version").await?;
+    instance.change_version(new_version).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// Set (or delete with `null`) only the given keys of server.properties, the rest of the file
+/// is left untouched
+pub async fn patch_server_properties(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(patch): Json<IndexMap<String, Option<String>>>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    match instance.value() {
+        GameInstance::MinecraftInstance(minecraft) => {
+            minecraft.patch_server_properties(&patch).await?
+        }
+        GameInstance::GenericInstance(_) => {
+            return Err(Error {
+                kind: ErrorKind::UnsupportedOperation,
+                source: eyre!("Only Minecraft instances have a server.properties file"),
+            })
+        }
+    }
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
     Ok(Json(()))
 }
 
@@ -159,5 +400,78 @@ pub fn get_instance_config_routes(state: AppState) -> Router {
         )
         .route("/instance/:uuid/name", put(set_instance_name))
         .route("/instance/:uuid/description", put(set_instance_description))
+        .route("/instance/:uuid/locked", put(set_instance_locked))
+        .route("/instance/:uuid/depends_on", put(set_instance_depends_on))
+        .route(
+            "/instance/:uuid/stop_command",
+            put(set_instance_stop_command),
+        )
+        .route("/instance/:uuid/port", put(set_instance_port))
+        .route(
+            "/instance/:uuid/server-properties",
+            patch(patch_server_properties),
+        )
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::test_util::FakeInstance;
+
+    fn free_port() -> u32 {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port() as u32
+    }
+
+    #[tokio::test]
+    async fn test_port_change_checks_other_instances_and_host() {
+        let (port, other_instance_port) = (free_port(), free_port());
+        let instance = FakeInstance::new("survival", State::Stopped);
+        instance.set_port(port).await.unwrap();
+        let mut port_manager = PortManager::new(HashSet::from([port, other_instance_port]));
+
+        let new_port = free_port();
+        let change = change_port(&instance, &mut port_manager, new_port)
+            .await
+            .unwrap();
+        assert_eq!(
+            change,
+            PortChange {
+                previous_port: port,
+                port: new_port
+            }
+        );
+        assert_eq!(instance.port().await, new_port);
+        assert!(port_manager.port_status(new_port).is_allocated);
+        assert!(!port_manager.port_status(port).is_allocated);
+
+        // another instance's port, then a port something else on the host listens on
+        let err = change_port(&instance, &mut port_manager, other_instance_port)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken_port = listener.local_addr().unwrap().port() as u32;
+        let err = change_port(&instance, &mut port_manager, taken_port)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(err.to_string().contains("in use"));
+        let err = change_port(&instance, &mut port_manager, 70000)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert_eq!(instance.port().await, new_port);
+        assert!(port_manager.port_status(new_port).is_allocated);
+
+        instance.start(CausedBy::System, false).await.unwrap();
+        let err = change_port(&instance, &mut port_manager, free_port())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert_eq!(instance.port().await, new_port);
+    }
+}
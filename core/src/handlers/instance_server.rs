@@ -1,27 +1,274 @@
 use axum::{
-    extract::Path,
-    routing::{get, post, put},
+    extract::{Path, Query},
+    routing::{delete, get, post, put},
     Router,
 };
 
 use axum::Json;
 use axum_auth::AuthBearer;
 
-use color_eyre::eyre::eyre;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context};
+use serde::Deserialize;
 use serde_json::{json, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::Instant;
+use tracing::debug;
 
 use crate::{
     auth::user::UserAction,
+    console_log::console_output_line,
     error::{Error, ErrorKind},
-    events::CausedBy,
+    events::{CausedBy, Event},
+    implementations::minecraft::{
+        command_output::{console_message, CommandResult, KnownCommand},
+        rcon::{test_rcon, RconTest, RCON_TEST_TIMEOUT},
+    },
+    instance_audit::record_instance_modification,
+    instance_crashes,
+    instance_dependencies::{
+        resolve_dependencies, wait_for_dependencies, DEPENDENCY_START_TIMEOUT,
+    },
+    instance_log_level::{instance_log_levels, InstanceLogLevel},
+    instance_log_rotation::{rotate_log, RotatedLog, LIVE_LOG_PATH},
+    instance_start_log::{read_last_start_log, StartLog},
+    prelude::GameInstance,
     types::InstanceUuid,
 };
 
 use crate::{
-    traits::{t_configurable::TConfigurable, t_server::TServer},
+    traits::{
+        t_configurable::TConfigurable,
+        t_server::{State, TServer},
+    },
     AppState,
 };
 
+/// How long a start has to take effect before the request fails. Reaching `Running` can take
+/// minutes, a start only has to get the instance out of `Stopped`
+pub const STATE_TRANSITION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long a stop has to reach `Stopped` unless the request asks otherwise, saving a large
+/// world on shutdown can take minutes
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(300);
+const MAX_STOP_TIMEOUT: Duration = Duration::from_secs(3600);
+const STATE_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// A command's console output is over once the server printed nothing for this long
+const COMMAND_OUTPUT_QUIET: Duration = Duration::from_millis(300);
+const COMMAND_OUTPUT_MAX_WAIT: Duration = Duration::from_secs(2);
+const RCON_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn is_started(state: State) -> bool {
+    matches!(state, State::Starting | State::Running)
+}
+
+/// Whether an instance in `state` is to be started, not if it already is starting or running.
+/// Only a stopped instance can be, any other state is a conflict
+fn needs_start(state: State) -> Result<bool, Error> {
+    match state {
+        state if is_started(state) => Ok(false),
+        State::Stopped => Ok(true),
+        State::Stopping => Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance is stopping, start it once it is stopped"),
+        }),
+        state => Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!(
+                "Instance is in the {} state, it can only be started once stopped",
+                state.to_string()
+            ),
+        }),
+    }
+}
+
+/// Poll the state of the instance until `reached` holds for it
+async fn wait_for_state(
+    instance: &(impl TServer + ?Sized),
+    expected: &str,
+    reached: impl Fn(State) -> bool,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let wait = async {
+        loop {
+            let state = instance.state().await;
+            if reached(state) {
+                return Ok(());
+            }
+            if state == State::Error {
+                return Err(Error {
+                    kind: ErrorKind::Internal,
+                    source: eyre!("Instance errored instead of becoming {expected}"),
+                });
+            }
+            tokio::time::sleep(STATE_POLL_INTERVAL).await;
+        }
+    };
+    match tokio::time::timeout(timeout, wait).await {
+        Ok(result) => result,
+        Err(_) => Err(Error {
+            kind: ErrorKind::Timeout,
+            source: eyre!(
+                "Instance didn't become {expected} within {} seconds, it is {}",
+                timeout.as_secs(),
+                instance.state().await.to_string()
+            ),
+        }),
+    }
+}
+
+/// Start the instance unless it already is starting or running, and wait for the start to take
+/// effect. Returns whether it was started
+async fn ensure_started(
+    instance: &(impl TServer + ?Sized),
+    caused_by: CausedBy,
+    timeout: Duration,
+) -> Result<bool, Error> {
+    if !needs_start(instance.state().await)? {
+        return Ok(false);
+    }
+    instance.start(caused_by, false).await?;
+    wait_for_state(instance, "Starting or Running", is_started, timeout).await?;
+    Ok(true)
+}
+
+/// Stop the instance unless it already is stopped, and wait for it to be stopped. An instance
+/// already stopping is only waited for. Returns whether it was stopped
+async fn ensure_stopped(
+    instance: &(impl TServer + ?Sized),
+    caused_by: CausedBy,
+    timeout: Duration,
+) -> Result<bool, Error> {
+    match instance.state().await {
+        State::Stopped => return Ok(false),
+        State::Stopping => {}
+        _ => instance.stop(caused_by, false).await?,
+    }
+    wait_for_state(
+        instance,
+        "Stopped",
+        |state| state == State::Stopped,
+        timeout,
+    )
+    .await?;
+    Ok(true)
+}
+
+#[derive(Deserialize, Default)]
+pub struct StopQuery {
+    /// seconds to wait for the instance to be stopped, `STOP_TIMEOUT` by default
+    timeout_secs: Option<u64>,
+}
+
+impl StopQuery {
+    fn timeout(&self) -> Result<Duration, Error> {
+        let Some(timeout_secs) = self.timeout_secs else {
+            return Ok(STOP_TIMEOUT);
+        };
+        let timeout = Duration::from_secs(timeout_secs);
+        if timeout.is_zero() || timeout > MAX_STOP_TIMEOUT {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Stop timeout must be between 1 and {} seconds",
+                    MAX_STOP_TIMEOUT.as_secs()
+                ),
+            });
+        }
+        Ok(timeout)
+    }
+}
+
+/// Launch the instance, held against relocations until the start took effect. An instance
+/// already starting or running is left as is
+async fn start_held(
+    state: &AppState,
+    uuid: &InstanceUuid,
+    instance: &GameInstance,
+    caused_by: CausedBy,
+) -> Result<(), Error> {
+    // a relocation can't begin until the start took effect
+    let _hold = state.instance_holds.hold(uuid, "started")?;
+    // before the port check, the port of a running or stopping instance is in use by itself
+    if !needs_start(instance.state().await)? {
+        return Ok(());
+    }
+    let port = instance.port().await;
+    if state.port_manager.lock().await.port_status(port).is_in_use {
+        return Err(Error {
+            kind: ErrorKind::Internal,
+            source: eyre!("Port {} is in use", port),
+        });
+    }
+    if ensure_started(instance, caused_by.clone(), STATE_TRANSITION_TIMEOUT).await? {
+        record_instance_modification(&instance.path().await, &caused_by)
+            .await
+            .map_err(Error::log)
+            .ok();
+    }
+    Ok(())
+}
+
+/// Start the instance once the instances it depends on are running. Waiting for them can take
+/// minutes, so it is done in a progression of its own and this returns once it began
+async fn start_after_dependencies(
+    state: &AppState,
+    uuid: InstanceUuid,
+    instance: GameInstance,
+    caused_by: CausedBy,
+) -> Result<(), Error> {
+    let dependencies = resolve_dependencies(&state.instances, instance.depends_on().await).await?;
+    let mut waiting_for = Vec::new();
+    for dependency in dependencies {
+        if dependency.instance.state().await != State::Running {
+            waiting_for.push(dependency);
+        }
+    }
+    if waiting_for.is_empty() {
+        return start_held(state, &uuid, &instance, caused_by).await;
+    }
+    let names: Vec<&str> = waiting_for.iter().map(|d| d.name.as_str()).collect();
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!(
+            "Starting {} once {} running",
+            instance.name().await,
+            names.join(", ")
+        ),
+        None,
+        None,
+        caused_by.clone(),
+    );
+    state.event_broadcaster.send(progression_start_event);
+    let state = state.clone();
+    tokio::spawn(async move {
+        let result = async {
+            wait_for_dependencies(
+                &waiting_for,
+                &state.event_broadcaster,
+                DEPENDENCY_START_TIMEOUT,
+            )
+            .await?;
+            start_held(&state, &uuid, &instance, caused_by).await
+        }
+        .await;
+        let progression_end_event = match result {
+            Ok(()) => {
+                Event::new_progression_event_end(event_id, true, Some("Instance started"), None)
+            }
+            Err(e) => Event::new_progression_event_end(
+                event_id,
+                false,
+                Some(&format!("Instance start failed: {e}")),
+                None,
+            ),
+        };
+        state.event_broadcaster.send(progression_end_event);
+    });
+    Ok(())
+}
+
+/// Starting an instance that is already starting or running succeeds without doing anything.
+/// An instance whose dependencies aren't running yet is started in a progression once they are
 pub async fn start_instance(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
@@ -41,29 +288,26 @@ pub async fn start_instance(
         user_id: requester.uid.clone(),
         user_name: requester.username.clone(),
     };
-    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
-        kind: ErrorKind::NotFound,
-        source: eyre!("Instance not found"),
-    })?;
-    let port = instance.port().await;
-
-    // check if port is already in use
-    if state.port_manager.lock().await.port_status(port).is_in_use {
-        return Err(Error {
-            kind: ErrorKind::Internal,
-            source: eyre!("Port {} is in use", port),
-        });
-    }
-
-    instance.start(caused_by, false).await?;
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|instance| instance.value().clone())
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
+    start_after_dependencies(&state, uuid, instance, caused_by).await?;
     Ok(Json(()))
 }
 
+/// Stopping an instance that is already stopped succeeds without doing anything
 pub async fn stop_instance(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
+    Query(stop_query): Query<StopQuery>,
     AuthBearer(token): AuthBearer,
 ) -> Result<Json<()>, Error> {
+    let stop_timeout = stop_query.timeout()?;
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     requester.try_action(
         &UserAction::StopInstance(uuid.clone()),
@@ -78,23 +322,32 @@ pub async fn stop_instance(
         user_id: requester.uid.clone(),
         user_name: requester.username.clone(),
     };
-    state
+    let instance = state
         .instances
         .get(&uuid)
+        .map(|instance| instance.value().clone())
         .ok_or_else(|| Error {
             kind: ErrorKind::NotFound,
             source: eyre!("Instance not found"),
-        })?
-        .stop(caused_by, false)
-        .await?;
+        })?;
+    if ensure_stopped(&instance, caused_by.clone(), stop_timeout).await? {
+        record_instance_modification(&instance.path().await, &caused_by)
+            .await
+            .map_err(Error::log)
+            .ok();
+    }
     Ok(Json(()))
 }
 
+/// A stop followed by a start. A stopped instance is only started. The start goes through the
+/// same checks as a start of its own, and waits for the dependencies the same way
 pub async fn restart_instance(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
+    Query(stop_query): Query<StopQuery>,
     AuthBearer(token): AuthBearer,
 ) -> Result<Json<()>, Error> {
+    let stop_timeout = stop_query.timeout()?;
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     let safe_mode = state.global_settings.lock().await.safe_mode();
 
@@ -110,12 +363,17 @@ pub async fn restart_instance(
         user_id: requester.uid.clone(),
         user_name: requester.username.clone(),
     };
-    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
-        kind: ErrorKind::NotFound,
-        source: eyre!("Instance not found"),
-    })?;
+    let instance = state
+        .instances
+        .get(&uuid)
+        .map(|instance| instance.value().clone())
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        })?;
 
-    instance.restart(caused_by, false).await?;
+    ensure_stopped(&instance, caused_by.clone(), stop_timeout).await?;
+    start_after_dependencies(&state, uuid, instance, caused_by).await?;
     Ok(Json(()))
 }
 
@@ -138,24 +396,55 @@ pub async fn kill_instance(
         docker_bridge.kill_container(&uuid).await?;
         return Ok(Json(json!("ok")));
     }
-    state
-        .instances
-        .get(&uuid)
-        .ok_or_else(|| Error {
-            kind: ErrorKind::NotFound,
-            source: eyre!("Instance not found"),
-        })?
-        .kill(caused_by)
-        .await?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    instance.kill(caused_by.clone()).await?;
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
     Ok(Json(json!("ok")))
 }
 
+/// Console lines of the instance received until it printed nothing for `quiet`, for `max_wait`
+/// at most. Lines of other instances and other events are skipped
+async fn collect_console_output(
+    events: &mut broadcast::Receiver<Event>,
+    uuid: &InstanceUuid,
+    quiet: Duration,
+    max_wait: Duration,
+) -> Vec<String> {
+    let deadline = Instant::now() + max_wait;
+    let mut quiet_until = Instant::now() + quiet;
+    let mut lines = Vec::new();
+    loop {
+        let event = match tokio::time::timeout_at(quiet_until.min(deadline), events.recv()).await {
+            Ok(Ok(event)) => event,
+            Ok(Err(RecvError::Lagged(_))) => continue,
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        };
+        if event.get_instance_uuid().as_ref() != Some(uuid) {
+            continue;
+        }
+        if let Some(line) = console_output_line(&event) {
+            lines.push(console_message(line));
+            quiet_until = Instant::now() + quiet;
+        }
+    }
+    lines
+}
+
+/// Send a command to the console and return what the server printed after it. The known
+/// commands only read, their output is taken over RCON when it's connected since nothing else
+/// gets mixed in there, and parsed
 pub async fn send_command(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
     AuthBearer(token): AuthBearer,
     Json(command): Json<String>,
-) -> Result<Json<()>, Error> {
+) -> Result<Json<CommandResult>, Error> {
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     requester.try_action(
         &UserAction::AccessConsole(uuid.clone()),
@@ -165,23 +454,55 @@ pub async fn send_command(
         user_id: requester.uid.clone(),
         user_name: requester.username.clone(),
     };
-    state
+    let instance = state
         .instances
         .get(&uuid)
+        .map(|entry| entry.value().clone())
         .ok_or_else(|| Error {
             kind: ErrorKind::NotFound,
             source: eyre!("Instance not found"),
-        })?
-        .send_command(&command, caused_by)
+        })?;
+    // subscribed first, the output can come right after the command
+    let mut events = state.event_broadcaster.subscribe();
+    instance.send_command(&command, caused_by).await?;
+    let console_output = collect_console_output(
+        &mut events,
+        &uuid,
+        COMMAND_OUTPUT_QUIET,
+        COMMAND_OUTPUT_MAX_WAIT,
+    )
+    .await;
+    let console_output = (!console_output.is_empty()).then(|| console_output.join("\n"));
+    let Some(known) = KnownCommand::from_command(&command) else {
+        return Ok(Json(CommandResult {
+            output: console_output,
+            parsed: None,
+        }));
+    };
+    if let GameInstance::MinecraftInstance(minecraft) = &instance {
+        // a hung server would otherwise keep the connection, and every later command, waiting
+        match tokio::time::timeout(
+            RCON_COMMAND_TIMEOUT,
+            minecraft.send_rcon(command.trim().trim_start_matches('/')),
+        )
         .await
-        .map(|_| Json(()))
+        {
+            Ok(Ok(output)) => return Ok(Json(known.result(output))),
+            Ok(Err(e)) => debug!("Failed to capture the output of {command} over RCON: {e}"),
+            Err(_) => debug!("Capturing the output of {command} over RCON timed out"),
+        }
+    }
+    Ok(Json(match console_output {
+        Some(output) => known.result(output),
+        None => CommandResult::default(),
+    }))
 }
 
 pub async fn get_instance_state(
     axum::extract::State(state): axum::extract::State<AppState>,
     Path(uuid): Path<InstanceUuid>,
     AuthBearer(token): AuthBearer,
-) -> Result<Json<Value>, Error> {
+) -> Result<Json<State>, Error> {
     let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
     if !requester.can_perform_action(&UserAction::ViewInstance(uuid.clone())) {
         return Err(Error {
@@ -191,10 +512,9 @@ pub async fn get_instance_state(
     }
     if uuid.to_string().starts_with("DOCKER-") {
         let docker_bridge = state.docker_bridge.clone();
-        let state = docker_bridge.get_container_state(&uuid).await?;
-        return Ok(Json(json!(state)));
+        return Ok(Json(docker_bridge.get_container_state(&uuid).await?));
     }
-    Ok(Json(json!(
+    Ok(Json(
         state
             .instances
             .get(&uuid)
@@ -203,8 +523,226 @@ pub async fn get_instance_state(
                 source: eyre!("Instance not found"),
             })?
             .state()
-            .await
-    )))
+            .await,
+    ))
+}
+
+/// Log level of the instance's supervisor, `None` when it follows the global level
+pub async fn get_instance_log_level(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Option<InstanceLogLevel>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::ViewInstance(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    if !state.instances.contains_key(&uuid) {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        });
+    }
+    Ok(Json(instance_log_levels().get(&uuid)))
+}
+
+/// Change the log level of the instance's supervisor at runtime, `null` resets it to the global level
+pub async fn set_instance_log_level(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(level): Json<Option<InstanceLogLevel>>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    if !state.instances.contains_key(&uuid) {
+        return Err(Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Instance not found"),
+        });
+    }
+    instance_log_levels().set(&uuid, level);
+    Ok(Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct MaintenanceRequest {
+    pub enabled: bool,
+    /// kick message and motd while in maintenance, a default one is used if omitted
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+/// Only ops can join an instance in maintenance, disabling it restores the previous whitelist and motd
+pub async fn set_instance_maintenance(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid.clone(),
+        user_name: requester.username.clone(),
+    };
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    match instance.value() {
+        GameInstance::MinecraftInstance(minecraft) => {
+            minecraft
+                .set_maintenance(request.enabled, request.message, caused_by.clone())
+                .await?
+        }
+        GameInstance::GenericInstance(_) => {
+            return Err(Error {
+                kind: ErrorKind::UnsupportedOperation,
+                source: eyre!("Maintenance mode is only supported for Minecraft instances"),
+            })
+        }
+    }
+    record_instance_modification(&instance.path().await, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+/// Connect and authenticate to the RCON server of an instance with the credentials of
+/// server.properties, without running any command
+pub async fn test_instance_rcon(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<RconTest>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let minecraft = match instance.value() {
+        GameInstance::MinecraftInstance(minecraft) => minecraft.clone(),
+        GameInstance::GenericInstance(_) => {
+            return Err(Error {
+                kind: ErrorKind::UnsupportedOperation,
+                source: eyre!("RCON is only supported for Minecraft instances"),
+            })
+        }
+    };
+    drop(instance);
+    let settings = minecraft.rcon_settings().await;
+    if settings.enabled && minecraft.state().await != State::Running {
+        return Ok(Json(RconTest::not_running()));
+    }
+    Ok(Json(
+        test_rcon("localhost", &settings, RCON_TEST_TIMEOUT).await,
+    ))
+}
+
+/// Output of the last start that exited before the server was up, `None` once a start succeeds
+pub async fn get_last_start_log(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Option<StartLog>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessConsole(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    Ok(Json(read_last_start_log(&path).await))
+}
+
+/// Reset the crash counter of an instance, e.g. once the cause of the crashes is fixed
+pub async fn reset_instance_crashes(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::AccessSetting(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    drop(instance);
+    instance_crashes::reset_instance_crashes(&path).await?;
+    let caused_by = CausedBy::User {
+        user_id: requester.uid,
+        user_name: requester.username,
+    };
+    record_instance_modification(&path, &caused_by)
+        .await
+        .map_err(Error::log)
+        .ok();
+    Ok(Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct RotateLogQuery {
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+/// Archive the live log of an instance and truncate it, the server can keep running
+pub async fn rotate_instance_log(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    Query(query): Query<RotateLogQuery>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<RotatedLog>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::WriteInstanceFile(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let path = instance.path().await;
+    let instance_state = instance.state().await;
+    drop(instance);
+    // the server holds the log open without letting others truncate it
+    if cfg!(windows) && instance_state != State::Stopped {
+        return Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("Logs can only be rotated while the server is stopped on Windows"),
+        });
+    }
+    let log = path.join(LIVE_LOG_PATH);
+    let mut rotated = tokio::task::spawn_blocking(move || rotate_log(&log, query.gzip))
+        .await
+        .context("Failed to rotate log")??;
+    rotated.archive = rotated
+        .archive
+        .strip_prefix(&path)
+        .map(|archive| archive.to_path_buf())
+        .unwrap_or(rotated.archive);
+    Ok(Json(rotated))
 }
 
 pub fn get_instance_server_routes(state: AppState) -> Router {
@@ -215,5 +753,220 @@ pub fn get_instance_server_routes(state: AppState) -> Router {
         .route("/instance/:uuid/kill", put(kill_instance))
         .route("/instance/:uuid/console", post(send_command))
         .route("/instance/:uuid/state", get(get_instance_state))
+        .route("/instance/:uuid/last-start-log", get(get_last_start_log))
+        .route("/instance/:uuid/logs/rotate", post(rotate_instance_log))
+        .route("/instance/:uuid/crashes", delete(reset_instance_crashes))
+        .route("/instance/:uuid/rcon/test", get(test_instance_rcon))
+        .route(
+            "/instance/:uuid/maintenance",
+            post(set_instance_maintenance),
+        )
+        .route(
+            "/instance/:uuid/log_level",
+            get(get_instance_log_level).put(set_instance_log_level),
+        )
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::test_util::FakeInstance;
+
+    fn fake_server(state: State, completes: bool) -> FakeInstance {
+        FakeInstance {
+            completes,
+            ..FakeInstance::new("survival", state)
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    #[tokio::test]
+    async fn test_double_start_and_double_stop_are_no_ops() {
+        let server = fake_server(State::Stopped, true);
+        assert!(ensure_started(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap());
+        assert!(!ensure_started(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap());
+        *server.state.lock().unwrap() = State::Running;
+        assert!(!ensure_started(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap());
+        assert_eq!(server.calls.load(Ordering::SeqCst), 1);
+
+        assert!(ensure_stopped(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap());
+        assert!(!ensure_stopped(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap());
+        assert_eq!(server.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(server.state().await, State::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_transition_that_does_not_complete_times_out() {
+        let server = fake_server(State::Running, false);
+        let err = ensure_stopped(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+        assert!(err.to_string().contains("Stopping"));
+        // still stopping, it is waited for again rather than stopped twice
+        let err = ensure_stopped(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+        assert_eq!(server.calls.load(Ordering::SeqCst), 1);
+        // and can't be started until it is stopped
+        let err = ensure_started(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+
+        let server = fake_server(State::Stopped, false);
+        let err = ensure_started(&server, CausedBy::System, TIMEOUT)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+    }
+
+    #[test]
+    fn test_only_a_stopped_instance_needs_starting() {
+        assert!(needs_start(State::Stopped).unwrap());
+        assert!(!needs_start(State::Starting).unwrap());
+        assert!(!needs_start(State::Running).unwrap());
+        // refused before the port check, which a stopping instance would fail on its own port
+        for state in [State::Stopping, State::Error] {
+            let err = needs_start(state).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::Conflict));
+        }
+    }
+
+    #[test]
+    fn test_stop_timeout_defaults_and_is_bounded() {
+        assert_eq!(StopQuery::default().timeout().unwrap(), STOP_TIMEOUT);
+        let query = |timeout_secs| StopQuery {
+            timeout_secs: Some(timeout_secs),
+        };
+        assert_eq!(query(600).timeout().unwrap(), Duration::from_secs(600));
+        assert!(query(0).timeout().is_err());
+        assert!(query(MAX_STOP_TIMEOUT.as_secs() + 1).timeout().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_restart_checks_the_port_like_a_start() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let path = instance.path().await;
+        let _taken =
+            std::net::TcpListener::bind(("0.0.0.0", instance.port().await as u16)).unwrap();
+        let state = test_app_state(temp.path(), vec![instance.into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_start_instance.insert(uuid.clone());
+        permissions.can_stop_instance.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+
+        let err = restart_instance(
+            axum::extract::State(state),
+            Path(uuid),
+            Query(StopQuery::default()),
+            AuthBearer(token),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("in use"), "{err}");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!path.join("java_args.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_start_is_refused_while_the_instance_is_relocated() {
+        use crate::auth::permission::UserPermission;
+        use crate::test_util::{add_test_user, restore_with_fake_java, test_app_state};
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        let uuid = instance.uuid().await;
+        let path = instance.path().await;
+        let state = test_app_state(temp.path(), vec![instance.into()]).await;
+        let mut permissions = UserPermission::new();
+        permissions.can_start_instance.insert(uuid.clone());
+        let token = add_test_user(&state, "alice", permissions).await;
+
+        let relocation = state.instance_holds.hold(&uuid, "relocated").unwrap();
+        let err = start_instance(
+            axum::extract::State(state.clone()),
+            Path(uuid.clone()),
+            AuthBearer(token),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(err.to_string().contains("being relocated"), "{err}");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!path.join("java_args.txt").exists());
+
+        // the relocation is over, a start can hold the instance again
+        drop(relocation);
+        state.instance_holds.hold(&uuid, "started").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_console_output_of_the_instance_is_collected_until_quiet() {
+        use crate::event_broadcaster::EventBroadcaster;
+
+        let (event_broadcaster, _rx) = EventBroadcaster::new(64);
+        let mut events = event_broadcaster.subscribe();
+        let survival = InstanceUuid::from("INSTANCE_survival".to_string());
+        let lobby = InstanceUuid::from("INSTANCE_lobby".to_string());
+        let output = |uuid: &InstanceUuid, line: &str| {
+            Event::new_instance_output(uuid.clone(), uuid.to_string(), line.to_string())
+        };
+        event_broadcaster.send(output(
+            &survival,
+            "[12:00:00] [Server thread/INFO]: There are 1/10 players online:",
+        ));
+        event_broadcaster.send(output(&lobby, "[12:00:00] [Server thread/INFO]: Hi"));
+        event_broadcaster.send(output(&survival, "Notch"));
+        tokio::spawn({
+            let event_broadcaster = event_broadcaster.clone();
+            let survival = survival.clone();
+            async move {
+                // printed after the server went quiet, not part of the output
+                tokio::time::sleep(Duration::from_millis(500)).await;
+                event_broadcaster.send(output(&survival, "[12:00:01] [Server thread/INFO]: Late"));
+            }
+        });
+
+        let lines = collect_console_output(
+            &mut events,
+            &survival,
+            Duration::from_millis(100),
+            Duration::from_secs(2),
+        )
+        .await;
+        assert_eq!(lines, vec!["There are 1/10 players online:", "Notch"]);
+        assert_eq!(
+            KnownCommand::List.result(lines.join("\n")).parsed,
+            Some(
+                crate::implementations::minecraft::command_output::ParsedCommandOutput::List {
+                    online: 1,
+                    max: 10,
+                    players: vec!["Notch".to_string()],
+                }
+            )
+        );
+    }
+}
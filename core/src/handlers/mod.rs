@@ -16,6 +16,7 @@ pub mod instance_server;
 pub mod instance_setup_configs;
 pub mod monitor;
 pub mod playitgg;
+pub mod remote_backup;
 pub mod setup;
 pub mod system;
 pub mod users;
@@ -0,0 +1,203 @@
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
+use axum_auth::AuthBearer;
+use color_eyre::eyre::eyre;
+use serde::Deserialize;
+
+use crate::{
+    auth::user::UserAction,
+    error::{Error, ErrorKind},
+    events::{CausedBy, Event, ProgressionEndValue},
+    prelude::path_to_stores,
+    remote_backup::{
+        read_remote_backup_target, remove_remote_backup_target, upload_to_remote_target,
+        write_remote_backup_target, RedactedRemoteBackupTarget, RemoteBackupTarget,
+    },
+    types::InstanceUuid,
+    util::{
+        format_byte_download, operation_cancelled, scoped_join_win_safe, zip_files_async,
+        ProgressThrottle,
+    },
+    AppState,
+};
+
+/// Where the local archives are kept, relative to the instance
+const BACKUP_DIR_NAME: &str = "backups";
+
+async fn require_owner(state: &AppState, token: &str) -> Result<(), Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(token)?;
+    if !requester.is_owner {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!("Only the owner can manage the remote backup target"),
+        });
+    }
+    Ok(())
+}
+
+pub async fn get_remote_backup_target(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Option<RedactedRemoteBackupTarget>>, Error> {
+    require_owner(&state, &token).await?;
+    Ok(Json(
+        read_remote_backup_target(path_to_stores())
+            .await?
+            .map(|target| target.redacted()),
+    ))
+}
+
+pub async fn set_remote_backup_target(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+    Json(target): Json<RemoteBackupTarget>,
+) -> Result<Json<RedactedRemoteBackupTarget>, Error> {
+    require_owner(&state, &token).await?;
+    target.validate()?;
+    write_remote_backup_target(path_to_stores(), &target).await?;
+    Ok(Json(target.redacted()))
+}
+
+pub async fn delete_remote_backup_target(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<()>, Error> {
+    require_owner(&state, &token).await?;
+    remove_remote_backup_target(path_to_stores()).await?;
+    Ok(Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct RemoteBackupRequest {
+    #[serde(default = "default_world_relative_path")]
+    pub world_relative_path: String,
+}
+
+fn default_world_relative_path() -> String {
+    "world".to_string()
+}
+
+/// Archive a world folder into the instance's backups directory and push the archive to the
+/// remote target. Returns the path of the local archive, which is kept whatever the outcome
+pub async fn push_remote_backup(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(uuid): Path<InstanceUuid>,
+    AuthBearer(token): AuthBearer,
+    Json(request): Json<RemoteBackupRequest>,
+) -> Result<Json<String>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    requester.try_action(
+        &UserAction::WriteInstanceFile(uuid.clone()),
+        state.global_settings.lock().await.safe_mode(),
+    )?;
+    let instance = state.instances.get(&uuid).ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("Instance not found"),
+    })?;
+    let root = instance.path().await;
+    drop(instance);
+    let target = read_remote_backup_target(path_to_stores())
+        .await?
+        .ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("No remote backup target is configured"),
+        })?;
+
+    let world = scoped_join_win_safe(&root, &request.world_relative_path)?;
+    if !world.is_dir() || world == root {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("World must be a folder inside the instance"),
+        });
+    }
+    let world_name = world
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "world".to_string());
+    let archive_name = format!(
+        "{world_name}-{}.zip",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    );
+    let archive_relative_path = format!("{BACKUP_DIR_NAME}/{archive_name}");
+    let archive = root.join(BACKUP_DIR_NAME).join(&archive_name);
+    crate::util::fs::create_dir_all(root.join(BACKUP_DIR_NAME)).await?;
+    let key = target.object_key(&format!("{uuid}/{archive_name}"));
+
+    let event_broadcaster = state.event_broadcaster.clone();
+    let (progression_start_event, event_id) = Event::new_progression_event_start(
+        format!("Backing up {world_name} to {}", target.bucket),
+        None,
+        None,
+        CausedBy::User {
+            user_id: requester.uid.clone(),
+            user_name: requester.username.clone(),
+        },
+    );
+    let session =
+        state
+            .upload_sessions
+            .register(event_id.inner(), uuid.clone(), requester.uid.clone());
+    event_broadcaster.send(progression_start_event);
+
+    tokio::spawn(async move {
+        let result: Result<(), Error> = async {
+            zip_files_async(&[&world], &archive, false, |_| {}, session.cancel.clone()).await?;
+            let size = tokio::fs::metadata(&archive)
+                .await
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+            let mut throttle = ProgressThrottle::new(Some(size));
+            let event_broadcaster = event_broadcaster.clone();
+            let event_id = event_id.clone();
+            let upload = upload_to_remote_target(&target, &archive, &key, move |sent| {
+                if let Some(progressed) = throttle.report(sent) {
+                    event_broadcaster.send(Event::new_progression_event_update(
+                        &event_id,
+                        format!("Uploading {}", format_byte_download(sent, size)),
+                        progressed as f64,
+                    ));
+                }
+            });
+            // dropping the request stops the upload, the target never completes the object
+            tokio::select! {
+                _ = session.cancel.cancelled() => Err(operation_cancelled()),
+                result = upload => result,
+            }
+        }
+        .await;
+        let (success, message) = match result {
+            Ok(()) => (true, format!("Backed up {world_name} to {key}")),
+            Err(e) => (
+                false,
+                format!("Remote backup of {world_name} failed, the local archive is kept: {e}"),
+            ),
+        };
+        event_broadcaster.send(Event::new_progression_event_end(
+            event_id,
+            success,
+            Some(&message),
+            Some(ProgressionEndValue::FSOperationCompleted {
+                instance_uuid: uuid,
+                success,
+                message: message.clone(),
+            }),
+        ));
+    });
+
+    Ok(Json(archive_relative_path))
+}
+
+pub fn get_remote_backup_routes(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/remote_backup/target",
+            get(get_remote_backup_target)
+                .put(set_remote_backup_target)
+                .delete(delete_remote_backup_target),
+        )
+        .route("/instance/:uuid/backup/remote", post(push_remote_backup))
+        .with_state(state)
+}
@@ -1,9 +1,24 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
 use axum::{routing::get, Json, Router};
+use axum_auth::AuthBearer;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sysinfo::{CpuExt, CpuRefreshKind, DiskExt, SystemExt};
 
 use tokio::time::sleep;
 
+use crate::auth::user::UserAction;
+use crate::error::Error;
+use crate::prelude::{path_to_instances, GameInstance};
+use crate::traits::t_configurable::TConfigurable;
+use crate::traits::t_player::TPlayerManagement;
+use crate::traits::t_server::{MonitorReport, State, TServer};
+use crate::types::InstanceUuid;
+use crate::util::tree_size;
 use crate::AppState;
 
 // Since MemInfo is not serializable, we need to create a new struct that is serializable.
@@ -63,10 +78,412 @@ pub async fn get_cpu_info(
     })
 }
 
+/// How long a single instance gets to report before it's counted as unresponsive
+const SUMMARY_INSTANCE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a measured size of the instances directory is reused for
+const DISK_USED_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Size of a directory, measured again once it is older than the max age. One measurement runs
+/// at a time and is kept once done, even if the request that started it stopped waiting
+#[derive(Default)]
+struct DiskUsedCache {
+    measured: std::sync::Mutex<Option<(Instant, u64)>>,
+    measuring: AtomicBool,
+}
+
+impl DiskUsedCache {
+    /// The size of `path`, the last one measured if a new measurement doesn't finish in
+    /// `timeout`
+    async fn get(
+        &'static self,
+        path: PathBuf,
+        max_age: Duration,
+        timeout: Duration,
+    ) -> Option<u64> {
+        let cached = *self.measured.lock().unwrap();
+        match cached {
+            Some((measured_at, size)) if measured_at.elapsed() < max_age => return Some(size),
+            _ => {}
+        }
+        if self.measuring.swap(true, Ordering::SeqCst) {
+            return cached.map(|(_, size)| size);
+        }
+        // a measurement that panicked or never ran doesn't keep the next ones from starting
+        let measuring = MeasuringGuard(&self.measuring);
+        let measurement = tokio::task::spawn_blocking(move || {
+            let _measuring = measuring;
+            let size = tree_size([&path]).bytes;
+            *self.measured.lock().unwrap() = Some((Instant::now(), size));
+            size
+        });
+        match tokio::time::timeout(timeout, measurement).await {
+            Ok(Ok(size)) => Some(size),
+            _ => cached.map(|(_, size)| size),
+        }
+    }
+}
+
+/// Clears the measuring flag of a `DiskUsedCache` once dropped
+struct MeasuringGuard(&'static AtomicBool);
+
+impl Drop for MeasuringGuard {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+static INSTANCES_DISK_USED: Lazy<DiskUsedCache> = Lazy::new(DiskUsedCache::default);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SystemSummary {
+    pub instance_count: u32,
+    /// Instances that responded in time, keyed by state
+    pub instances_by_state: BTreeMap<String, u32>,
+    pub unresponsive_instances: u32,
+    /// Memory used by running servers, in bytes
+    pub memory_used: u64,
+    /// CPU used by running servers, summed across them
+    pub cpu_used: f32,
+    /// Players on the running servers that reported a player count
+    pub player_count: u32,
+    /// Size of the instances directory, measured up to `DISK_USED_MAX_AGE` ago. `None` if it
+    /// couldn't be measured in time yet
+    pub instances_disk_used: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct InstanceSample {
+    state: State,
+    monitor: MonitorReport,
+    player_count: Option<u32>,
+}
+
+// `None` if the instance didn't answer within the timeout
+async fn sample_instance<I>(instance: &I, timeout: Duration) -> Option<InstanceSample>
+where
+    I: TServer + TPlayerManagement + Sync,
+{
+    tokio::time::timeout(timeout, async {
+        let state = instance.state().await;
+        if state != State::Running {
+            return InstanceSample {
+                state,
+                monitor: MonitorReport::default(),
+                player_count: None,
+            };
+        }
+        let (monitor, player_count) = tokio::join!(instance.monitor(), instance.get_player_count());
+        InstanceSample {
+            state,
+            monitor,
+            player_count: player_count.ok(),
+        }
+    })
+    .await
+    .ok()
+}
+
+fn summarize(
+    samples: &[Option<InstanceSample>],
+    instances_disk_used: Option<u64>,
+) -> SystemSummary {
+    let mut summary = SystemSummary {
+        instance_count: samples.len() as u32,
+        instances_by_state: BTreeMap::new(),
+        unresponsive_instances: 0,
+        memory_used: 0,
+        cpu_used: 0.0,
+        player_count: 0,
+        instances_disk_used,
+    };
+    for sample in samples {
+        let Some(sample) = sample else {
+            summary.unresponsive_instances += 1;
+            continue;
+        };
+        *summary
+            .instances_by_state
+            .entry(sample.state.to_string())
+            .or_default() += 1;
+        if sample.state == State::Running {
+            summary.memory_used += sample.monitor.memory_usage.unwrap_or_default();
+            summary.cpu_used += sample.monitor.cpu_usage.unwrap_or_default();
+            summary.player_count += sample.player_count.unwrap_or_default();
+        }
+    }
+    summary
+}
+
+pub async fn get_system_summary(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<SystemSummary>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    // cloned out of the map so a slow instance doesn't keep it locked
+    let instances: Vec<GameInstance> = state
+        .instances
+        .iter()
+        .filter(|entry| {
+            requester.can_perform_action(&UserAction::ViewInstance(entry.key().clone()))
+        })
+        .map(|entry| entry.value().clone())
+        .collect();
+    let disk_used = INSTANCES_DISK_USED.get(
+        path_to_instances(),
+        DISK_USED_MAX_AGE,
+        SUMMARY_INSTANCE_TIMEOUT,
+    );
+    let (samples, disk_used) = tokio::join!(
+        futures::future::join_all(
+            instances
+                .iter()
+                .map(|instance| sample_instance(instance, SUMMARY_INSTANCE_TIMEOUT))
+        ),
+        disk_used
+    );
+    Ok(Json(summarize(&samples, disk_used)))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ManagedProcess {
+    pub instance_uuid: InstanceUuid,
+    pub instance_name: String,
+    pub pid: u32,
+    /// State of the instance, a process of a stopped instance is an orphan
+    pub state: State,
+    /// Memory used by the process, in bytes
+    pub memory_usage: Option<u64>,
+    pub cpu_usage: Option<f32>,
+    /// Unix timestamp the process started at, in seconds
+    pub start_time: Option<u64>,
+}
+
+// `None` if the instance has no process, or didn't answer within the timeout
+async fn managed_process<I>(
+    instance: &I,
+    instance_uuid: InstanceUuid,
+    instance_name: String,
+    timeout: Duration,
+) -> Option<ManagedProcess>
+where
+    I: TServer + Sync,
+{
+    tokio::time::timeout(timeout, async {
+        let pid = instance.pid().await?;
+        let (state, monitor) = tokio::join!(instance.state(), instance.monitor());
+        Some(ManagedProcess {
+            instance_uuid,
+            instance_name,
+            pid,
+            state,
+            memory_usage: monitor.memory_usage,
+            cpu_usage: monitor.cpu_usage,
+            start_time: monitor.start_time,
+        })
+    })
+    .await
+    .ok()
+    .flatten()
+}
+
+/// Processes spawned for the instances, from the handles Lodestone keeps rather than the OS
+/// process list
+pub async fn get_system_processes(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AuthBearer(token): AuthBearer,
+) -> Result<Json<Vec<ManagedProcess>>, Error> {
+    let requester = state.users_manager.read().await.try_auth_or_err(&token)?;
+    // cloned out of the map so a slow instance doesn't keep it locked
+    let instances: Vec<(InstanceUuid, GameInstance)> = state
+        .instances
+        .iter()
+        .filter(|entry| {
+            requester.can_perform_action(&UserAction::ViewInstance(entry.key().clone()))
+        })
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    let mut processes: Vec<ManagedProcess> =
+        futures::future::join_all(instances.into_iter().map(|(uuid, instance)| async move {
+            let name = instance.name().await;
+            managed_process(&instance, uuid, name, SUMMARY_INSTANCE_TIMEOUT).await
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+    processes.sort_by(|a, b| a.instance_name.cmp(&b.instance_name));
+    Ok(Json(processes))
+}
+
 pub fn get_system_routes(state: AppState) -> Router {
     Router::new()
         .route("/system/ram", get(get_ram))
         .route("/system/disk", get(get_disk))
         .route("/system/cpu", get(get_cpu_info))
+        .route("/system/summary", get(get_system_summary))
+        .route("/system/processes", get(get_system_processes))
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::FakeInstance;
+
+    /// An instance reporting fixed numbers
+    fn instance(
+        state: State,
+        memory_usage: u64,
+        cpu_usage: f32,
+        player_count: Option<u32>,
+    ) -> FakeInstance {
+        FakeInstance {
+            monitor: MonitorReport {
+                memory_usage: Some(memory_usage),
+                cpu_usage: Some(cpu_usage),
+                ..Default::default()
+            },
+            player_count,
+            ..FakeInstance::new("survival", state)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_summary_aggregates_instances() {
+        let instances = vec![
+            instance(State::Running, 1024 * 1024 * 1024, 12.5, Some(3)),
+            instance(State::Running, 512 * 1024 * 1024, 30.0, Some(5)),
+            // running but rcon and query are down
+            instance(State::Running, 256 * 1024 * 1024, 2.5, None),
+            // stopped servers don't add to the usage even if they report something
+            instance(State::Stopped, 4096, 99.0, Some(100)),
+            instance(State::Starting, 0, 0.0, None),
+            FakeInstance {
+                hangs: true,
+                ..instance(State::Running, 4096, 99.0, Some(100))
+            },
+        ];
+        let samples = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join_all(
+                instances
+                    .iter()
+                    .map(|instance| sample_instance(instance, Duration::from_millis(50))),
+            ),
+        )
+        .await
+        .expect("summary blocked on the unresponsive instance");
+
+        let summary = summarize(&samples, Some(10_000));
+        assert_eq!(summary.instance_count, 6);
+        assert_eq!(
+            summary.instances_by_state,
+            BTreeMap::from([
+                ("Running".to_string(), 3),
+                ("Starting".to_string(), 1),
+                ("Stopped".to_string(), 1),
+            ])
+        );
+        assert_eq!(summary.unresponsive_instances, 1);
+        assert_eq!(summary.memory_used, (1024 + 512 + 256) * 1024 * 1024);
+        assert_eq!(summary.cpu_used, 45.0);
+        assert_eq!(summary.player_count, 8);
+        assert_eq!(summary.instances_disk_used, Some(10_000));
+    }
+    #[tokio::test]
+    async fn test_disk_used_is_measured_once_per_max_age() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("server.jar"), [0_u8; 1000]).unwrap();
+        let cache: &'static DiskUsedCache = Box::leak(Box::default());
+        let disk_used =
+            |max_age| cache.get(temp.path().to_path_buf(), max_age, Duration::from_secs(5));
+
+        assert_eq!(disk_used(Duration::from_secs(60)).await, Some(1000));
+        std::fs::write(temp.path().join("server.properties"), [0_u8; 24]).unwrap();
+        assert_eq!(disk_used(Duration::from_secs(60)).await, Some(1000));
+        assert_eq!(disk_used(Duration::ZERO).await, Some(1024));
+
+        // a measurement still running isn't started again, the last size is reported meanwhile
+        cache.measuring.store(true, Ordering::SeqCst);
+        std::fs::remove_file(temp.path().join("server.jar")).unwrap();
+        assert_eq!(disk_used(Duration::ZERO).await, Some(1024));
+    }
+
+    #[tokio::test]
+    async fn test_processes_list_running_instances_with_pid() {
+        let instances = vec![
+            (
+                "INSTANCE_survival",
+                "Survival",
+                FakeInstance {
+                    pid: Some(4242),
+                    ..instance(State::Running, 1024 * 1024 * 1024, 12.5, Some(3))
+                },
+            ),
+            (
+                "INSTANCE_creative",
+                "Creative",
+                FakeInstance {
+                    pid: Some(4343),
+                    ..instance(State::Starting, 0, 0.0, None)
+                },
+            ),
+            // no process spawned
+            (
+                "INSTANCE_lobby",
+                "Lobby",
+                instance(State::Stopped, 0, 0.0, None),
+            ),
+            (
+                "INSTANCE_stuck",
+                "Stuck",
+                FakeInstance {
+                    hangs: true,
+                    pid: Some(4444),
+                    ..instance(State::Running, 0, 0.0, None)
+                },
+            ),
+        ];
+        let processes: Vec<ManagedProcess> = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join_all(instances.iter().map(|(uuid, name, instance)| {
+                managed_process(
+                    instance,
+                    InstanceUuid::from(uuid.to_string()),
+                    name.to_string(),
+                    Duration::from_millis(50),
+                )
+            })),
+        )
+        .await
+        .expect("listing blocked on the unresponsive instance")
+        .into_iter()
+        .flatten()
+        .collect();
+
+        assert_eq!(
+            processes,
+            vec![
+                ManagedProcess {
+                    instance_uuid: InstanceUuid::from("INSTANCE_survival".to_string()),
+                    instance_name: "Survival".to_string(),
+                    pid: 4242,
+                    state: State::Running,
+                    memory_usage: Some(1024 * 1024 * 1024),
+                    cpu_usage: Some(12.5),
+                    start_time: None,
+                },
+                ManagedProcess {
+                    instance_uuid: InstanceUuid::from("INSTANCE_creative".to_string()),
+                    instance_name: "Creative".to_string(),
+                    pid: 4343,
+                    state: State::Starting,
+                    memory_usage: Some(0),
+                    cpu_usage: Some(0.0),
+                    start_time: None,
+                },
+            ]
+        );
+    }
+}
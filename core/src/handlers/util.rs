@@ -1,6 +1,10 @@
-use color_eyre::eyre::Context;
+use color_eyre::eyre::{eyre, Context};
+use serde::Deserialize;
 
-use crate::error::Error;
+use crate::auth::user::AuthorizedUser;
+use crate::error::{Error, ErrorKind};
+use crate::traits::t_configurable::TConfigurable;
+use crate::AppState;
 
 pub fn parse_bearer_token(token: &str) -> Option<String> {
     let mut split = token.split_ascii_whitespace();
@@ -10,16 +14,312 @@ pub fn parse_bearer_token(token: &str) -> Option<String> {
     split.next().map(|s| s.to_string())
 }
 
+/// Authenticate the requester once, the result is checked against every target of a batch
+pub async fn authorize(state: &AppState, token: &str) -> Result<AuthorizedUser, Error> {
+    let safe_mode = state.global_settings.lock().await.safe_mode();
+    state
+        .users_manager
+        .read()
+        .await
+        .try_authorize(token, safe_mode)
+}
+
+pub fn instance_locked(action: &str) -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Instance is locked, unlock it before {action}"),
+    }
+}
+
+/// Refuse a destructive action on a locked instance
+pub async fn ensure_unlocked(instance: &impl TConfigurable, action: &str) -> Result<(), Error> {
+    if instance.locked().await {
+        return Err(instance_locked(action));
+    }
+    Ok(())
+}
+
+/// Decode a base64 path segment. Malformed base64, paths that aren't valid UTF-8 and NUL bytes
+/// are refused before they get near the filesystem
 pub fn decode_base64(input: &str) -> Result<String, Error> {
-    Ok(String::from_utf8(
-        base64::decode_engine(
+    let bytes = base64::decode_engine(
+        input,
+        &base64::engine::fast_portable::FastPortable::from(
+            &base64::alphabet::URL_SAFE,
+            base64::engine::fast_portable::NO_PAD,
+        ),
+    )
+    .map_err(|e| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Invalid base64 path: {e}"),
+    })?;
+    let path = String::from_utf8(bytes).map_err(|_| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Path is not valid UTF-8"),
+    })?;
+    check_path_chars(path)
+}
+
+// a NUL byte truncates the path or fails the syscall, depending on the platform
+fn check_path_chars(path: String) -> Result<String, Error> {
+    if path.contains('\0') {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path contains a NUL byte"),
+        });
+    }
+    Ok(path)
+}
+
+/// Plain (URL-encoded) alternative to the base64 path segments of the fs routes,
+/// e.g. `/instance/:uuid/fs/-/read?path=world/level.dat`
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct RelativePathQuery {
+    pub path: Option<String>,
+    /// destination of a move, replaces the second base64 segment
+    pub dest: Option<String>,
+}
+
+/// Resolve the relative path of a fs request.
+///
+/// `?path=` takes precedence when present, the base64 segment is decoded otherwise
+pub fn resolve_relative_path(
+    base64_relative_path: &str,
+    query: &RelativePathQuery,
+) -> Result<String, Error> {
+    match &query.path {
+        Some(path) => check_path_chars(path.clone()),
+        None => decode_base64(base64_relative_path),
+    }
+}
+
+/// Same as [`resolve_relative_path`] but for the destination of a move
+pub fn resolve_relative_path_dest(
+    base64_relative_path_dest: &str,
+    query: &RelativePathQuery,
+) -> Result<String, Error> {
+    match &query.dest {
+        Some(dest) => check_path_chars(dest.clone()),
+        None => decode_base64(base64_relative_path_dest),
+    }
+}
+
+/// Inclusive range of bytes of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What a `Range` header asks for of a file
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// no range, or one that can't be served as a single range, the whole file is sent
+    Full,
+    Partial(ByteRange),
+    /// the range starts past the end of the file
+    Unsatisfiable,
+}
+
+/// Parse a `Range` header for a file `len` bytes long. Only a single range of bytes is served,
+/// several ranges or a malformed header are ignored, as HTTP allows
+pub fn parse_range(header: Option<&str>, len: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    if end.contains(',') {
+        return RangeRequest::Full;
+    }
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        // the last `end` bytes
+        return match end.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if len == 0 => RangeRequest::Unsatisfiable,
+            Ok(suffix) => RangeRequest::Partial(ByteRange {
+                start: len.saturating_sub(suffix),
+                end: len - 1,
+            }),
+            Err(_) => RangeRequest::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+    let end = if end.is_empty() {
+        u64::MAX
+    } else {
+        match end.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return RangeRequest::Full,
+        }
+    };
+    if start >= len {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(ByteRange {
+        start,
+        end: end.min(len - 1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::scoped_join_win_safe;
+
+    fn encode_base64(input: &str) -> String {
+        base64::encode_engine(
             input,
             &base64::engine::fast_portable::FastPortable::from(
                 &base64::alphabet::URL_SAFE,
                 base64::engine::fast_portable::NO_PAD,
             ),
         )
-        .context("Failed to decode base64")?,
-    )
-    .context("Invalid UTF-8")?)
+    }
+
+    // decode a raw query string the same way the `Query` extractor does
+    fn query(raw: &str) -> RelativePathQuery {
+        let mut query = RelativePathQuery::default();
+        for (key, value) in url::form_urlencoded::parse(raw.as_bytes()) {
+            match key.as_ref() {
+                "path" => query.path = Some(value.into_owned()),
+                "dest" => query.dest = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        query
+    }
+
+    #[test]
+    fn test_base64_and_query_resolve_to_same_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("world/data")).unwrap();
+        std::fs::write(root.join("world/data/some file.txt"), "hello").unwrap();
+
+        let from_base64 = resolve_relative_path(
+            &encode_base64("world/data/some file.txt"),
+            &RelativePathQuery::default(),
+        )
+        .unwrap();
+        let from_query =
+            resolve_relative_path("-", &query("path=world%2Fdata%2Fsome%20file.txt")).unwrap();
+        assert_eq!(from_base64, from_query);
+
+        let path_base64 = scoped_join_win_safe(root, &from_base64).unwrap();
+        let path_query = scoped_join_win_safe(root, &from_query).unwrap();
+        assert_eq!(path_base64, path_query);
+        assert_eq!(
+            std::fs::read_to_string(&path_base64).unwrap(),
+            std::fs::read_to_string(&path_query).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_base64_and_query_traversal_is_scoped() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        let from_base64 = resolve_relative_path(
+            &encode_base64("../../etc/passwd"),
+            &RelativePathQuery::default(),
+        )
+        .unwrap();
+        let from_query = resolve_relative_path("-", &query("path=..%2F..%2Fetc%2Fpasswd")).unwrap();
+
+        let path_base64 = scoped_join_win_safe(root, from_base64).unwrap();
+        let path_query = scoped_join_win_safe(root, from_query).unwrap();
+        assert_eq!(path_base64, path_query);
+        assert!(path_query.starts_with(root));
+    }
+
+    #[test]
+    fn test_query_dest_overrides_segment() {
+        let query = query("path=a.txt&dest=backup%2Fa.txt");
+        assert_eq!(resolve_relative_path("-", &query).unwrap(), "a.txt");
+        assert_eq!(
+            resolve_relative_path_dest("-", &query).unwrap(),
+            "backup/a.txt"
+        );
+        assert!(resolve_relative_path("not base64!", &RelativePathQuery::default()).is_err());
+    }
+
+    #[test]
+    fn test_malformed_paths_are_bad_requests() {
+        for malformed in [
+            "not base64!",
+            // "world/\xff.dat"
+            "d29ybGQv_y5kYXQ",
+            // "world\0.dat"
+            "d29ybGQALmRhdA",
+        ] {
+            let err = resolve_relative_path(malformed, &RelativePathQuery::default()).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest), "{malformed}");
+        }
+        let err = resolve_relative_path("-", &query("path=world%00.dat")).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert_eq!(
+            decode_base64("d29ybGQvbGV2ZWwuZGF0").unwrap(),
+            "world/level.dat"
+        );
+    }
+
+    #[test]
+    fn test_parse_range() {
+        let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+        assert_eq!(parse_range(None, 1000), RangeRequest::Full);
+        assert_eq!(parse_range(Some("bytes=0-499"), 1000), partial(0, 499));
+        // resuming an interrupted download
+        assert_eq!(parse_range(Some("bytes=500-"), 1000), partial(500, 999));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), partial(900, 999));
+        assert_eq!(parse_range(Some("bytes=-5000"), 1000), partial(0, 999));
+        assert_eq!(parse_range(Some("bytes=900-5000"), 1000), partial(900, 999));
+        assert_eq!(parse_range(Some("bytes=999-999"), 1000), partial(999, 999));
+        assert_eq!(
+            ByteRange {
+                start: 900,
+                end: 999
+            }
+            .size(),
+            100
+        );
+
+        assert_eq!(
+            parse_range(Some("bytes=1000-"), 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=-0"), 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(
+            parse_range(Some("bytes=0-"), 0),
+            RangeRequest::Unsatisfiable
+        );
+
+        for ignored in [
+            "bytes=0-99,200-299",
+            "bytes=500-100",
+            "bytes=abc-",
+            "bytes=-",
+            "items=0-99",
+            "bytes 0-99",
+        ] {
+            assert_eq!(
+                parse_range(Some(ignored), 1000),
+                RangeRequest::Full,
+                "{ignored}"
+            );
+        }
+    }
 }
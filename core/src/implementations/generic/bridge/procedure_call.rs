@@ -328,6 +328,23 @@ impl From<ErrorIR> for Error {
     }
 }
 
+#[test]
+fn test_unsupported_error_ir_is_not_implemented() {
+    use axum::response::IntoResponse;
+
+    let ir: ErrorIR = serde_json::from_value(serde_json::json!({
+        "kind": "UnsupportedOperation",
+        "source": "Sending commands is unsupported for this instance",
+    }))
+    .unwrap();
+    let error = Error::from(ir);
+    assert!(matches!(error.kind, ErrorKind::UnsupportedOperation));
+    assert_eq!(
+        error.into_response().status(),
+        axum::http::StatusCode::NOT_IMPLEMENTED
+    );
+}
+
 #[derive(Debug, Clone, TS, Deserialize)]
 // #[ts(export_to = "src/implementation/generic/js/main/libs")]
 // #[ts(export)]
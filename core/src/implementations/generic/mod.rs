@@ -12,6 +12,8 @@ use crate::{
     error::Error,
     event_broadcaster::EventBroadcaster,
     events::{CausedBy, ProgressionEventID},
+    instance_audit::read_instance_audit,
+    instance_crashes::read_instance_crashes,
     macro_executor::{self, MacroExecutor, MacroPID, SpawnResult, WorkerOptionGenerator},
     traits::{
         t_configurable::{
@@ -288,10 +290,16 @@ impl TInstance for GenericInstance {
             path: self.path().await.display().to_string(),
             auto_start: self.auto_start().await,
             restart_on_crash: self.restart_on_crash().await,
+            locked: self.locked().await,
+            depends_on: self.depends_on().await,
+            stop_command: self.stop_command().await,
             state: self.state().await,
             player_count: self.get_player_count().await.ok(),
             max_player_count: self.get_max_player_count().await.ok(),
             player_list: self.get_player_list().await.ok(),
+            audit: read_instance_audit(&self.path().await).await,
+            crashes: read_instance_crashes(&self.path().await).await,
+            busy: false,
         }
     }
 }
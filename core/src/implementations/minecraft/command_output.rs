@@ -0,0 +1,217 @@
+use fancy_regex::Regex;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::line_parser::parse_system_msg;
+
+/// Output of a well-known command, parsed so clients don't each have to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(tag = "command", rename_all = "snake_case")]
+#[ts(export)]
+pub enum ParsedCommandOutput {
+    List {
+        online: u32,
+        max: u32,
+        players: Vec<String>,
+    },
+    Seed {
+        /// as a string since seeds don't fit in a JavaScript number
+        seed: String,
+    },
+    WhitelistList {
+        players: Vec<String>,
+    },
+}
+
+/// Result of a console command, the output is only captured for the known commands
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct CommandResult {
+    /// `None` when the server printed nothing in time, the output shows up in the console either
+    /// way
+    pub output: Option<String>,
+    pub parsed: Option<ParsedCommandOutput>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownCommand {
+    List,
+    Seed,
+    WhitelistList,
+}
+
+impl KnownCommand {
+    /// The known command `command` is, with or without its leading slash
+    pub fn from_command(command: &str) -> Option<Self> {
+        let command = command.trim().trim_start_matches('/').to_lowercase();
+        match command.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["list"] | ["list", "uuids"] => Some(Self::List),
+            ["seed"] => Some(Self::Seed),
+            ["whitelist", "list"] => Some(Self::WhitelistList),
+            _ => None,
+        }
+    }
+
+    pub fn parse(self, output: &str) -> Option<ParsedCommandOutput> {
+        lazy_static! {
+            static ref FORMATTING: Regex = Regex::new(r"§.").unwrap();
+            // "There are 2 of a max of 20 players online: Steve, Alex", "There are 2/20 players
+            // online:" before 1.13
+            static ref LIST: Regex =
+                Regex::new(r"(?s)^There are (\d+) (?:of a max of |/)(\d+) players online:(.*)$")
+                    .unwrap();
+            // "Seed: [-4172144997902289642]", without brackets before 1.13
+            static ref SEED: Regex = Regex::new(r"^Seed: \[?(-?\d+)\]?$").unwrap();
+            static ref WHITELIST: Regex =
+                Regex::new(r"(?s)^There (?:are|is) (\d+) whitelisted players?(?:\(s\))?:(.*)$")
+                    .unwrap();
+        }
+        let output = FORMATTING.replace_all(output, "");
+        let output = output.trim();
+        match self {
+            Self::List => {
+                let captures = LIST.captures(output).ok()??;
+                Some(ParsedCommandOutput::List {
+                    online: captures.get(1)?.as_str().parse().ok()?,
+                    max: captures.get(2)?.as_str().parse().ok()?,
+                    players: player_names(captures.get(3)?.as_str()),
+                })
+            }
+            Self::Seed => {
+                let captures = SEED.captures(output).ok()??;
+                let seed: i64 = captures.get(1)?.as_str().parse().ok()?;
+                Some(ParsedCommandOutput::Seed {
+                    seed: seed.to_string(),
+                })
+            }
+            Self::WhitelistList => {
+                if output == "There are no whitelisted players" {
+                    return Some(ParsedCommandOutput::WhitelistList {
+                        players: Vec::new(),
+                    });
+                }
+                let captures = WHITELIST.captures(output).ok()??;
+                Some(ParsedCommandOutput::WhitelistList {
+                    players: player_names(captures.get(2)?.as_str()),
+                })
+            }
+        }
+    }
+
+    pub fn result(self, output: String) -> CommandResult {
+        CommandResult {
+            parsed: self.parse(&output),
+            output: Some(output),
+        }
+    }
+}
+
+/// Message of a console line without the time and thread the server logs it with, lines
+/// continuing a message have neither
+pub fn console_message(line: &str) -> String {
+    parse_system_msg(line).unwrap_or_else(|| line.to_string())
+}
+
+/// Names separated by commas or lines, `list uuids` adds the uuid after each name
+fn player_names(names: &str) -> Vec<String> {
+    names
+        .split([',', '\n'])
+        .filter_map(|name| name.split(" (").next())
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_command_outputs_are_parsed() {
+        let list = KnownCommand::from_command("/list").unwrap();
+        assert_eq!(list, KnownCommand::List);
+        assert_eq!(
+            list.parse("There are 2 of a max of 20 players online: Steve, Alex"),
+            Some(ParsedCommandOutput::List {
+                online: 2,
+                max: 20,
+                players: vec!["Steve".to_string(), "Alex".to_string()],
+            })
+        );
+        assert_eq!(
+            list.parse("There are 0 of a max of 20 players online: "),
+            Some(ParsedCommandOutput::List {
+                online: 0,
+                max: 20,
+                players: vec![],
+            })
+        );
+        // before 1.13, and with uuids
+        assert_eq!(
+            list.parse("There are 1/10 players online:\nNotch"),
+            Some(ParsedCommandOutput::List {
+                online: 1,
+                max: 10,
+                players: vec!["Notch".to_string()],
+            })
+        );
+        assert_eq!(
+            KnownCommand::from_command("list uuids").unwrap().parse(
+                "There are 1 of a max of 20 players online: Steve (8667ba71-b85a-4004-af54-457a9734eed7)"
+            ),
+            Some(ParsedCommandOutput::List {
+                online: 1,
+                max: 20,
+                players: vec!["Steve".to_string()],
+            })
+        );
+
+        let seed = KnownCommand::from_command("seed").unwrap();
+        let parsed = seed.parse("Seed: [-4172144997902289642]");
+        assert_eq!(
+            parsed,
+            Some(ParsedCommandOutput::Seed {
+                seed: "-4172144997902289642".to_string()
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(parsed).unwrap(),
+            serde_json::json!({"command": "seed", "seed": "-4172144997902289642"})
+        );
+        assert_eq!(
+            seed.parse("Seed: 1234"),
+            Some(ParsedCommandOutput::Seed {
+                seed: "1234".to_string()
+            })
+        );
+        assert_eq!(seed.parse("Unknown or incomplete command"), None);
+
+        let whitelist = KnownCommand::from_command("/Whitelist  list").unwrap();
+        assert_eq!(
+            whitelist.parse("There are 2 whitelisted player(s): §eSteve§r, Alex"),
+            Some(ParsedCommandOutput::WhitelistList {
+                players: vec!["Steve".to_string(), "Alex".to_string()],
+            })
+        );
+        assert_eq!(
+            whitelist.parse("There are no whitelisted players"),
+            Some(ParsedCommandOutput::WhitelistList { players: vec![] })
+        );
+
+        // unknown commands keep just the raw output
+        assert_eq!(KnownCommand::from_command("say hi"), None);
+        assert_eq!(KnownCommand::from_command("whitelist add Steve"), None);
+        let result = list.result("Players online: Steve".to_string());
+        assert_eq!(result.output.as_deref(), Some("Players online: Steve"));
+        assert_eq!(result.parsed, None);
+
+        // as printed to the console
+        assert_eq!(
+            console_message("[12:00:00] [Server thread/INFO]: There are 1/10 players online:"),
+            "There are 1/10 players online:"
+        );
+        assert_eq!(console_message("Notch"), "Notch");
+    }
+}
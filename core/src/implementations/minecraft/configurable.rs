@@ -13,9 +13,10 @@ use crate::traits::t_configurable::{Game, TConfigurable};
 use crate::traits::t_server::State;
 
 use crate::types::InstanceUuid;
-use crate::util::download_file;
 
-use super::util::{get_fabric_jar_url, get_paper_jar_url, get_vanilla_jar_url};
+use super::util::{
+    download_server_jar, get_fabric_jar, get_paper_jar, get_vanilla_jar, validate_stop_command,
+};
 use super::MinecraftInstance;
 
 #[async_trait]
@@ -60,6 +61,14 @@ impl TConfigurable for MinecraftInstance {
         self.config.lock().await.restart_on_crash
     }
 
+    async fn locked(&self) -> bool {
+        self.config.lock().await.locked
+    }
+
+    async fn depends_on(&self) -> Vec<InstanceUuid> {
+        self.config.lock().await.depends_on.clone()
+    }
+
     async fn set_name(&self, name: String) -> Result<(), Error> {
         if name.is_empty() {
             return Err(Error {
@@ -102,6 +111,25 @@ impl TConfigurable for MinecraftInstance {
         self.write_config_to_file().await
     }
 
+    async fn set_locked(&self, locked: bool) -> Result<(), Error> {
+        self.config.lock().await.locked = locked;
+        self.write_config_to_file().await
+    }
+
+    async fn set_depends_on(&self, depends_on: Vec<InstanceUuid>) -> Result<(), Error> {
+        self.config.lock().await.depends_on = depends_on;
+        self.write_config_to_file().await
+    }
+
+    async fn stop_command(&self) -> Option<String> {
+        Some(self.config.lock().await.stop_command.clone())
+    }
+
+    async fn set_stop_command(&self, stop_command: String) -> Result<(), Error> {
+        self.config.lock().await.stop_command = validate_stop_command(&stop_command)?;
+        self.write_config_to_file().await
+    }
+
     async fn set_restart_on_crash(&self, restart_on_crash: bool) -> Result<(), Error> {
         self.config.lock().await.restart_on_crash = restart_on_crash;
         self.auto_start
@@ -119,34 +147,41 @@ impl TConfigurable for MinecraftInstance {
         if version == self.config.lock().await.version {
             return Ok(());
         }
-        let (url, _) = match self.config.lock().await.flavour {
-            super::Flavour::Vanilla => get_vanilla_jar_url(&version).await.ok_or_else(|| {
-                let error_msg =
-                    format!("Cannot get the vanilla jar version for version {}", version);
-                Error {
-                    kind: ErrorKind::BadRequest,
-                    source: eyre!(error_msg),
-                }
-            })?,
-            super::Flavour::Fabric { .. } => get_fabric_jar_url(&version, &None, &None)
+        let jar_mirrors = self.global_settings.lock().await.jar_mirrors();
+        let server_jar = match self.config.lock().await.flavour {
+            super::Flavour::Vanilla => get_vanilla_jar(&version, jar_mirrors.vanilla.as_deref())
                 .await
                 .ok_or_else(|| {
                     let error_msg =
-                        format!("Cannot get the fabric jar version for version {}", version);
+                        format!("Cannot get the vanilla jar version for version {}", version);
                     Error {
                         kind: ErrorKind::BadRequest,
                         source: eyre!(error_msg),
                     }
                 })?,
+            super::Flavour::Fabric { .. } => {
+                get_fabric_jar(&version, &None, &None, jar_mirrors.fabric.as_deref())
+                    .await
+                    .ok_or_else(|| {
+                        let error_msg =
+                            format!("Cannot get the fabric jar version for version {}", version);
+                        Error {
+                            kind: ErrorKind::BadRequest,
+                            source: eyre!(error_msg),
+                        }
+                    })?
+            }
             super::Flavour::Paper { .. } => {
-                get_paper_jar_url(&version, &None).await.ok_or_else(|| {
-                    let error_msg =
-                        format!("Cannot get the paper jar version for version {}", version);
-                    Error {
-                        kind: ErrorKind::BadRequest,
-                        source: eyre!(error_msg),
-                    }
-                })?
+                get_paper_jar(&version, &None, jar_mirrors.paper.as_deref())
+                    .await
+                    .ok_or_else(|| {
+                        let error_msg =
+                            format!("Cannot get the paper jar version for version {}", version);
+                        Error {
+                            kind: ErrorKind::BadRequest,
+                            source: eyre!(error_msg),
+                        }
+                    })?
             }
             super::Flavour::Spigot => todo!(),
             super::Flavour::Forge { .. } => {
@@ -158,12 +193,11 @@ impl TConfigurable for MinecraftInstance {
         };
         let lodestone_tmp = path_to_tmp().clone();
         let temp_dir = tempfile::tempdir_in(lodestone_tmp).context("Failed to create temp dir")?;
-        download_file(
-            &url,
+        download_server_jar(
+            &server_jar,
             temp_dir.path(),
-            Some("server.jar"),
+            "server.jar",
             &Box::new(|_| {}),
-            true,
         )
         .await?;
         let jar_path = temp_dir.path().join("server.jar");
@@ -277,6 +311,8 @@ pub(super) enum CmdArgSetting {
     MaxRam(u32),
     JavaCmd(String),
     Args(Vec<String>),
+    /// empty when the flavour's jar is used
+    CustomJarPath(String),
 }
 
 impl CmdArgSetting {
@@ -289,6 +325,7 @@ impl CmdArgSetting {
             CmdArgSetting::MaxRam(_) => "max_ram",
             CmdArgSetting::JavaCmd(_) => "java_cmd",
             CmdArgSetting::Args(_) => "cmd_args",
+            CmdArgSetting::CustomJarPath(_) => "custom_jar_path",
         }
     }
     pub fn get_name(&self) -> &'static str {
@@ -297,6 +334,7 @@ impl CmdArgSetting {
             CmdArgSetting::MaxRam(_) => "Maximum RAM",
             CmdArgSetting::JavaCmd(_) => "Java command",
             CmdArgSetting::Args(_) => "Command line arguments",
+            CmdArgSetting::CustomJarPath(_) => "Custom server jar",
         }
     }
     pub fn get_description(&self) -> &'static str {
@@ -309,6 +347,9 @@ impl CmdArgSetting {
             }
             CmdArgSetting::JavaCmd(_) => "The command to use to run the java executable",
             CmdArgSetting::Args(_) => "The command line arguments to pass to the server",
+            CmdArgSetting::CustomJarPath(_) => {
+                "Path of a server jar relative to the instance root, leave empty to use the flavour's jar"
+            }
         }
     }
     pub fn from_key_val(key: &str, val: &str) -> Result<Self, Error> {
@@ -323,6 +364,7 @@ impl CmdArgSetting {
             "cmd_args" => Ok(CmdArgSetting::Args(
                 val.split(' ').map(|s| s.to_string()).collect(),
             )),
+            "custom_jar_path" => Ok(CmdArgSetting::CustomJarPath(val.to_string())),
             _ => Err(Error {
                 kind: ErrorKind::BadRequest,
                 source: eyre!("Invalid key"),
@@ -330,7 +372,10 @@ impl CmdArgSetting {
         }
     }
     pub fn is_key_valid(key: &str) -> bool {
-        matches!(key, "min_ram" | "max_ram" | "java_cmd" | "cmd_args")
+        matches!(
+            key,
+            "min_ram" | "max_ram" | "java_cmd" | "cmd_args" | "custom_jar_path"
+        )
     }
 }
 
@@ -383,6 +428,18 @@ impl From<CmdArgSetting> for SettingManifest {
                 false,
                 true,
             ),
+            CmdArgSetting::CustomJarPath(ref custom_jar_path) => {
+                SettingManifest::new_optional_value(
+                    value.get_identifier().to_owned(),
+                    value.get_name().to_owned(),
+                    value.get_description().to_owned(),
+                    Some(ConfigurableValue::String(custom_jar_path.to_owned())),
+                    ConfigurableValueType::String { regex: None },
+                    None,
+                    false,
+                    true,
+                )
+            }
         }
     }
 }
@@ -420,6 +477,13 @@ impl TryFrom<SettingManifest> for CmdArgSetting {
                     .map(|s| s.to_string())
                     .collect(),
             )),
+            "custom_jar_path" => Ok(CmdArgSetting::CustomJarPath(
+                value
+                    .get_value()
+                    .context("Expected a value")?
+                    .try_as_string()?
+                    .to_owned(),
+            )),
             _ => Err(Error {
                 kind: ErrorKind::BadRequest,
                 source: eyre!("Invalid key"),
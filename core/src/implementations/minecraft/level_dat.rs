@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+
+/// Largest `level.dat` read, compressed and once decompressed. Vanilla ones are a few KiB
+pub const LEVEL_DAT_MAX_SIZE: u64 = 1024 * 1024;
+pub const LEVEL_DAT_MAX_DECOMPRESSED_SIZE: u64 = 16 * 1024 * 1024;
+/// Same nesting limit as the game
+const NBT_MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Compound(HashMap<String, Tag>),
+    /// lists and byte, int and long arrays are read past, no summary field needs them
+    Skipped,
+}
+
+impl Tag {
+    fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(tags) => tags.get(key),
+            _ => None,
+        }
+    }
+
+    fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(v) => Some(v as i64),
+            Tag::Short(v) => Some(v as i64),
+            Tag::Int(v) => Some(v as i64),
+            Tag::Long(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// How a game rule value is shown, they are strings up to 1.21 and typed after
+    fn to_game_rule_value(&self) -> Option<String> {
+        match self {
+            Tag::String(v) => Some(v.clone()),
+            Tag::Byte(v) => Some((*v != 0).to_string()),
+            Tag::Float(v) => Some(v.to_string()),
+            Tag::Double(v) => Some(v.to_string()),
+            tag => tag.as_i64().map(|v| v.to_string()),
+        }
+    }
+}
+
+struct NbtReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> NbtReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < len {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Unexpected end of NBT data"),
+            });
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn length(&mut self) -> Result<usize, Error> {
+        let len = i32::from_be_bytes(self.array()?);
+        // a negative length is read as empty, like the game does
+        Ok(len.max(0) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = u16::from_be_bytes(self.array()?) as usize;
+        // modified UTF-8 only differs for NUL and supplementary characters
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+
+    fn skip_array(&mut self, element_size: usize) -> Result<Tag, Error> {
+        let len = self.length()?;
+        self.take(len.checked_mul(element_size).ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("NBT array is too long"),
+        })?)?;
+        Ok(Tag::Skipped)
+    }
+
+    fn payload(&mut self, tag_type: u8, depth: usize) -> Result<Tag, Error> {
+        if depth > NBT_MAX_DEPTH {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("NBT is nested deeper than {NBT_MAX_DEPTH} levels"),
+            });
+        }
+        Ok(match tag_type {
+            1 => Tag::Byte(i8::from_be_bytes(self.array()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => self.skip_array(1)?,
+            8 => Tag::String(self.string()?),
+            9 => {
+                let element_type = self.array::<1>()?[0];
+                let len = self.length()?;
+                // elements without a payload would make the length unbounded
+                if element_type == 0 && len > 0 {
+                    return Err(Error {
+                        kind: ErrorKind::BadRequest,
+                        source: eyre!("Invalid NBT list of end tags"),
+                    });
+                }
+                for _ in 0..len {
+                    self.payload(element_type, depth + 1)?;
+                }
+                Tag::Skipped
+            }
+            10 => {
+                let mut tags = HashMap::new();
+                loop {
+                    let tag_type = self.array::<1>()?[0];
+                    if tag_type == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    tags.insert(name, self.payload(tag_type, depth + 1)?);
+                }
+                Tag::Compound(tags)
+            }
+            11 => self.skip_array(4)?,
+            12 => self.skip_array(8)?,
+            tag_type => {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("Unknown NBT tag type {tag_type}"),
+                })
+            }
+        })
+    }
+
+    /// The named root tag of an NBT file
+    fn root(&mut self) -> Result<Tag, Error> {
+        let tag_type = self.array::<1>()?[0];
+        self.string()?;
+        self.payload(tag_type, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct SpawnPoint {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+/// Key fields of a world's `level.dat`, `None` for a field the file doesn't have
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct LevelSummary {
+    pub level_name: Option<String>,
+    /// as a string since seeds don't fit in a JavaScript number
+    pub seed: Option<String>,
+    pub spawn: Option<SpawnPoint>,
+    /// name of the game version that last saved the world, e.g. "1.20.1"
+    pub version: Option<String>,
+    pub data_version: Option<i32>,
+    pub hardcore: Option<bool>,
+    pub game_rules: BTreeMap<String, String>,
+}
+
+impl LevelSummary {
+    fn from_root(root: &Tag) -> Result<Self, Error> {
+        let data = root.get("Data").ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Not a level.dat, it has no Data tag"),
+        })?;
+        let int = |key: &str| {
+            data.get(key)
+                .and_then(Tag::as_i64)
+                .and_then(|v| i32::try_from(v).ok())
+        };
+        // moved into WorldGenSettings in 1.16
+        let seed = data
+            .get("WorldGenSettings")
+            .and_then(|settings| settings.get("seed"))
+            .or_else(|| data.get("RandomSeed"))
+            .and_then(Tag::as_i64);
+        let spawn = match (int("SpawnX"), int("SpawnY"), int("SpawnZ")) {
+            (Some(x), Some(y), Some(z)) => Some(SpawnPoint { x, y, z }),
+            _ => None,
+        };
+        let game_rules = match data.get("GameRules") {
+            Some(Tag::Compound(rules)) => rules
+                .iter()
+                .filter_map(|(rule, value)| Some((rule.clone(), value.to_game_rule_value()?)))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        Ok(Self {
+            level_name: data
+                .get("LevelName")
+                .and_then(Tag::as_str)
+                .map(String::from),
+            seed: seed.map(|seed| seed.to_string()),
+            spawn,
+            version: data
+                .get("Version")
+                .and_then(|version| version.get("Name"))
+                .and_then(Tag::as_str)
+                .map(String::from),
+            data_version: int("DataVersion"),
+            hardcore: data.get("hardcore").and_then(Tag::as_i64).map(|v| v != 0),
+            game_rules,
+        })
+    }
+}
+
+/// Summarize the content of a `level.dat`, gzipped as the game writes it or not
+fn summarize_level_dat(content: &[u8], max_decompressed_size: u64) -> Result<LevelSummary, Error> {
+    let too_large = || Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!(
+            "level.dat is larger than {} bytes once decompressed",
+            max_decompressed_size
+        ),
+    };
+    let decompressed;
+    let nbt = if content.starts_with(&[0x1f, 0x8b]) {
+        let mut buf = Vec::new();
+        GzDecoder::new(content)
+            .take(max_decompressed_size + 1)
+            .read_to_end(&mut buf)
+            .map_err(|e| Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Failed to decompress level.dat: {e}"),
+            })?;
+        decompressed = buf;
+        &decompressed[..]
+    } else {
+        content
+    };
+    if nbt.len() as u64 > max_decompressed_size {
+        return Err(too_large());
+    }
+    LevelSummary::from_root(&NbtReader { bytes: nbt }.root()?)
+}
+
+pub async fn read_level_summary(path: &Path) -> Result<LevelSummary, Error> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .context(format!("Failed to read {}", path.display()))?
+        .len();
+    if size > LEVEL_DAT_MAX_SIZE {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("level.dat is larger than {LEVEL_DAT_MAX_SIZE} bytes"),
+        });
+    }
+    let content = tokio::fs::read(path)
+        .await
+        .context(format!("Failed to read {}", path.display()))?;
+    tokio::task::spawn_blocking(move || {
+        summarize_level_dat(&content, LEVEL_DAT_MAX_DECOMPRESSED_SIZE)
+    })
+    .await
+    .context("Failed to parse level.dat")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_level_dat_summary() {
+        let summary = read_level_summary(Path::new("testdata/sample_level.dat"))
+            .await
+            .unwrap();
+        assert_eq!(
+            summary,
+            LevelSummary {
+                level_name: Some("Survival".to_string()),
+                seed: Some("-4172144997902289642".to_string()),
+                spawn: Some(SpawnPoint {
+                    x: 112,
+                    y: 64,
+                    z: -48
+                }),
+                version: Some("1.20.1".to_string()),
+                data_version: Some(3465),
+                hardcore: Some(false),
+                game_rules: BTreeMap::from([
+                    ("doDaylightCycle".to_string(), "true".to_string()),
+                    ("keepInventory".to_string(), "false".to_string()),
+                    ("randomTickSpeed".to_string(), "3".to_string()),
+                ]),
+            }
+        );
+
+        let content = std::fs::read("testdata/sample_level.dat").unwrap();
+        let mut decompressed = Vec::new();
+        GzDecoder::new(&content[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        // uncompressed level.dat files are read too
+        assert_eq!(
+            summarize_level_dat(&decompressed, LEVEL_DAT_MAX_DECOMPRESSED_SIZE).unwrap(),
+            summary
+        );
+        let err = summarize_level_dat(&content, decompressed.len() as u64 - 1).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(err.to_string().contains("decompressed"));
+
+        let err = summarize_level_dat(
+            &decompressed[..decompressed.len() / 2],
+            LEVEL_DAT_MAX_DECOMPRESSED_SIZE,
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        // a list claiming billions of elements it doesn't have
+        let err = summarize_level_dat(
+            &[10, 0, 0, 9, 0, 1, b'L', 0, 0x7f, 0xff, 0xff, 0xff],
+            LEVEL_DAT_MAX_DECOMPRESSED_SIZE,
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        let err =
+            summarize_level_dat(b"server-port=25565", LEVEL_DAT_MAX_DECOMPRESSED_SIZE).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+}
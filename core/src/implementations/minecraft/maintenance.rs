@@ -0,0 +1,247 @@
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::util::replace_properties;
+use super::MinecraftInstance;
+use crate::error::{Error, ErrorKind};
+use crate::events::CausedBy;
+use crate::traits::t_player::{TPlayer, TPlayerManagement};
+use crate::traits::t_server::{State, TServer};
+
+/// What the instance looked like before maintenance, removed once it is restored
+const MAINTENANCE_BACKUP_FILE_NAME: &str = ".lodestone_maintenance.json";
+
+pub const DEFAULT_MAINTENANCE_MESSAGE: &str = "Server is under maintenance, please come back later";
+
+/// Properties changed while in maintenance, the whitelist is emptied so only ops can join
+fn maintenance_properties(message: &str) -> [(&'static str, String); 3] {
+    [
+        ("white-list", "true".to_string()),
+        ("enforce-whitelist", "true".to_string()),
+        ("motd", message.to_string()),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct MaintenanceBackup {
+    message: String,
+    /// previous value of each changed property, `None` if it wasn't set
+    properties: Vec<(String, Option<String>)>,
+    /// previous content of whitelist.json, `None` if there was none
+    whitelist: Option<String>,
+}
+
+async fn read_optional(path: &Path) -> Result<Option<String>, Error> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+            .context(format!("Failed to read {}", path.display()))
+            .map_err(Error::from),
+    }
+}
+
+pub async fn is_in_maintenance(path_to_instance: &Path) -> bool {
+    path_to_instance.join(MAINTENANCE_BACKUP_FILE_NAME).exists()
+}
+
+/// Close the server to everyone but ops, the previous settings are kept to be restored later
+async fn enable_maintenance(path_to_instance: &Path, message: &str) -> Result<(), Error> {
+    if is_in_maintenance(path_to_instance).await {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance is already in maintenance"),
+        });
+    }
+    let path_to_properties = path_to_instance.join("server.properties");
+    let path_to_whitelist = path_to_instance.join("whitelist.json");
+    let properties = read_optional(&path_to_properties)
+        .await?
+        .unwrap_or_default();
+    let updates = maintenance_properties(message).map(|(key, value)| (key, Some(value)));
+    let (properties, previous) = replace_properties(&properties, &updates);
+    let backup = MaintenanceBackup {
+        message: message.to_string(),
+        properties: previous,
+        whitelist: read_optional(&path_to_whitelist).await?,
+    };
+
+    // the backup is written first so a failure below can still be undone
+    crate::util::fs::write_all(
+        path_to_instance.join(MAINTENANCE_BACKUP_FILE_NAME),
+        serde_json::to_string_pretty(&backup).context("Failed to serialize maintenance backup")?,
+    )
+    .await?;
+    crate::util::fs::write_all(&path_to_whitelist, "[]").await?;
+    crate::util::fs::write_all(&path_to_properties, properties).await
+}
+
+/// Restore the settings saved by `enable_maintenance`, returns them
+async fn disable_maintenance(path_to_instance: &Path) -> Result<MaintenanceBackup, Error> {
+    let path_to_backup = path_to_instance.join(MAINTENANCE_BACKUP_FILE_NAME);
+    let backup: MaintenanceBackup =
+        serde_json::from_str(&read_optional(&path_to_backup).await?.ok_or_else(|| Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("Instance is not in maintenance"),
+        })?)
+        .context("Invalid maintenance backup")?;
+    let path_to_properties = path_to_instance.join("server.properties");
+    let path_to_whitelist = path_to_instance.join("whitelist.json");
+
+    let properties = read_optional(&path_to_properties)
+        .await?
+        .unwrap_or_default();
+    let updates: Vec<(&str, Option<String>)> = backup
+        .properties
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .collect();
+    let (properties, _) = replace_properties(&properties, &updates);
+    crate::util::fs::write_all(&path_to_properties, properties).await?;
+    match &backup.whitelist {
+        Some(whitelist) => crate::util::fs::write_all(&path_to_whitelist, whitelist).await?,
+        None if path_to_whitelist.exists() => {
+            crate::util::fs::remove_file(&path_to_whitelist).await?
+        }
+        None => {}
+    }
+    crate::util::fs::remove_file(&path_to_backup).await?;
+    Ok(backup)
+}
+
+/// Names of the ops of the server, they can still join during maintenance
+async fn op_names(path_to_instance: &Path) -> Vec<String> {
+    let Ok(Some(ops)) = read_optional(&path_to_instance.join("ops.json")).await else {
+        return Vec::new();
+    };
+    serde_json::from_str::<Vec<serde_json::Value>>(&ops)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|op| op["name"].as_str().map(|name| name.to_string()))
+        .collect()
+}
+
+impl MinecraftInstance {
+    pub async fn set_maintenance(
+        &self,
+        enabled: bool,
+        message: Option<String>,
+        caused_by: CausedBy,
+    ) -> Result<(), Error> {
+        let message = message.unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string());
+        if message.contains(['\n', '\r']) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Maintenance message must be on a single line"),
+            });
+        }
+        let previous_whitelist = if enabled {
+            enable_maintenance(&self.path_to_instance, &message).await?;
+            None
+        } else {
+            let backup = disable_maintenance(&self.path_to_instance).await?;
+            Some(
+                backup
+                    .properties
+                    .iter()
+                    .any(|(key, value)| key == "white-list" && value.as_deref() == Some("true")),
+            )
+        };
+        self.read_properties()
+            .await
+            .context("Failed to read properties")?;
+
+        // a running server picks the changes up from the console, the motd is shown after a restart
+        if self.state().await != State::Running {
+            return Ok(());
+        }
+        let mut commands = vec!["whitelist reload".to_string()];
+        match previous_whitelist {
+            None => {
+                commands.push("whitelist on".to_string());
+                let ops = op_names(&self.path_to_instance).await;
+                for player in self.get_player_list().await.unwrap_or_default() {
+                    let name = player.get_name();
+                    if !ops.contains(&name) {
+                        commands.push(format!("kick {name} {message}"));
+                    }
+                }
+            }
+            Some(false) => commands.push("whitelist off".to_string()),
+            Some(true) => {}
+        }
+        for command in commands {
+            if let Err(e) = self.send_command(&command, caused_by.clone()).await {
+                warn!("Failed to send maintenance command {command}: {e}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_maintenance_swaps_and_restores_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let properties = "#Minecraft server properties\nserver-port=25565\nmotd=A Minecraft Server\nwhite-list=false\nmax-players=20";
+        let whitelist = r#"[{"uuid":"069a79f4-44e9-4726-a5be-fca90e38aaf5","name":"Notch"}]"#;
+        std::fs::write(root.join("server.properties"), properties).unwrap();
+        std::fs::write(root.join("whitelist.json"), whitelist).unwrap();
+
+        enable_maintenance(root, "Back in 10 minutes")
+            .await
+            .unwrap();
+        assert!(is_in_maintenance(root).await);
+        let swapped = std::fs::read_to_string(root.join("server.properties")).unwrap();
+        assert!(swapped.contains("white-list=true"));
+        assert!(swapped.contains("enforce-whitelist=true"));
+        assert!(swapped.contains("motd=Back in 10 minutes"));
+        assert!(swapped.contains("max-players=20"));
+        assert!(!swapped.contains("motd=A Minecraft Server"));
+        assert_eq!(
+            std::fs::read_to_string(root.join("whitelist.json")).unwrap(),
+            "[]"
+        );
+        let err = enable_maintenance(root, "again").await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+
+        disable_maintenance(root).await.unwrap();
+        assert!(!is_in_maintenance(root).await);
+        // wasn't set before maintenance, so it's gone again
+        assert_eq!(
+            std::fs::read_to_string(root.join("server.properties")).unwrap(),
+            properties
+        );
+        assert_eq!(
+            std::fs::read_to_string(root.join("whitelist.json")).unwrap(),
+            whitelist
+        );
+        let err = disable_maintenance(root).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_without_whitelist_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(root.join("server.properties"), "server-port=25565").unwrap();
+
+        enable_maintenance(root, DEFAULT_MAINTENANCE_MESSAGE)
+            .await
+            .unwrap();
+        assert!(root.join("whitelist.json").exists());
+        disable_maintenance(root).await.unwrap();
+        assert!(!root.join("whitelist.json").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join("server.properties")).unwrap(),
+            "server-port=25565"
+        );
+    }
+}
@@ -1,11 +1,16 @@
+pub mod command_output;
 pub mod configurable;
 pub mod fabric;
 mod forge;
+pub mod level_dat;
 mod line_parser;
 pub mod r#macro;
+mod maintenance;
 mod paper;
 pub mod player;
 mod players_manager;
+mod preflight;
+pub mod rcon;
 pub mod server;
 pub mod util;
 mod vanilla;
@@ -33,9 +38,10 @@ use tracing::error;
 use tokio;
 use ts_rs::TS;
 
-use crate::error::Error;
+use crate::error::{Error, FieldError};
 use crate::event_broadcaster::EventBroadcaster;
 use crate::events::{Event, ProgressionEventID};
+use crate::global_settings::GlobalSettings;
 use crate::macro_executor::{MacroExecutor, MacroPID};
 use crate::prelude::path_to_binaries;
 use crate::traits::t_configurable::PathBuf;
@@ -59,7 +65,10 @@ use self::fabric::get_fabric_minecraft_versions;
 use self::forge::get_forge_minecraft_versions;
 use self::paper::get_paper_minecraft_versions;
 use self::players_manager::PlayersManager;
-use self::util::{get_jre_url, get_server_jar_url, read_properties_from_path};
+use self::util::{
+    apply_properties_patch, download_server_jar, get_jre_url, get_server_jar,
+    initial_server_properties, read_properties_from_path, validate_world_generation, LEVEL_TYPES,
+};
 use self::vanilla::get_vanilla_minecraft_versions;
 
 #[derive(Debug, Clone, TS, Serialize, Deserialize, PartialEq)]
@@ -150,6 +159,15 @@ pub struct SetupConfig {
     pub auto_start: Option<bool>,
     pub restart_on_crash: Option<bool>,
     pub backup_period: Option<u32>,
+    /// server jar relative to the instance root, replaces the flavour's jar
+    #[serde(default)]
+    pub custom_jar_path: Option<String>,
+    /// `level-type` written to server.properties before the first start
+    #[serde(default)]
+    pub level_type: Option<String>,
+    /// `generator-settings` written to server.properties before the first start
+    #[serde(default)]
+    pub generator_settings: Option<String>,
 }
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RestoreConfig {
@@ -167,7 +185,26 @@ pub struct RestoreConfig {
     pub backup_period: Option<u32>,
     pub jre_major_version: u64,
     pub has_started: bool,
+    /// server jar relative to the instance root, replaces the flavour's jar
+    #[serde(default)]
+    pub custom_jar_path: Option<String>,
+    /// destructive operations are refused until the instance is unlocked
+    #[serde(default)]
+    pub locked: bool,
+    /// instances that must be running before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<InstanceUuid>,
+    /// console command shutting the server down gracefully, e.g. `end` on a BungeeCord proxy
+    #[serde(default = "default_stop_command")]
+    pub stop_command: String,
 }
+
+pub const DEFAULT_STOP_COMMAND: &str = "stop";
+
+fn default_stop_command() -> String {
+    DEFAULT_STOP_COMMAND.to_string()
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct MinecraftInstance {
@@ -195,9 +232,10 @@ pub struct MinecraftInstance {
     players_manager: Arc<Mutex<PlayersManager>>,
     configurable_manifest: Arc<Mutex<ConfigurableManifest>>,
     macro_executor: MacroExecutor,
-    rcon_conn: Arc<Mutex<Option<rcon::Connection<tokio::net::TcpStream>>>>,
+    rcon_conn: Arc<Mutex<Option<::rcon::Connection<tokio::net::TcpStream>>>>,
     macro_name_to_last_run: Arc<Mutex<HashMap<String, i64>>>,
     pid_to_task_entry: Arc<Mutex<IndexMap<MacroPID, TaskEntry>>>,
+    global_settings: Arc<Mutex<GlobalSettings>>,
 }
 
 #[tokio::test]
@@ -209,6 +247,84 @@ async fn test_setup_manifest() {
     println!("{manifest_json_string}");
 }
 
+/// Download the JRE Minecraft `version` runs on unless it already is, returns its major version
+pub async fn ensure_jre(
+    version: &str,
+    path_to_runtimes: &std::path::Path,
+    step: &str,
+    progression_event_id: &ProgressionEventID,
+    event_broadcaster: &EventBroadcaster,
+) -> Result<u64, Error> {
+    let (url, jre_major_version) = get_jre_url(version)
+        .await
+        .context("Could not get JRE URL")?;
+    if !path_to_runtimes
+        .join("java")
+        .join(format!("jre{}", jre_major_version))
+        .exists()
+    {
+        let downloaded = download_file(
+            &url,
+            &path_to_runtimes.join("java"),
+            None,
+            {
+                let event_broadcaster = event_broadcaster.clone();
+                &move |dl| {
+                    if let Some(total) = dl.total {
+                        event_broadcaster.send(Event::new_progression_event_update(
+                            progression_event_id,
+                            format!(
+                                "{step}: Downloading JRE {}",
+                                format_byte_download(dl.downloaded, total)
+                            ),
+                            (dl.step as f64 / total as f64) * 4.0,
+                        ));
+                    }
+                }
+            },
+            true,
+        )
+        .await?;
+
+        let unzipped_content = unzip_file_async(
+            &downloaded,
+            UnzipOption::ToDir(path_to_runtimes.join("java")),
+        )
+        .await?;
+        if unzipped_content.len() != 1 {
+            return Err(eyre!(
+                "Expected only one file in the JRE archive, got {}",
+                unzipped_content.len()
+            )
+            .into());
+        }
+
+        tokio::fs::remove_file(&downloaded).await.context(format!(
+            "Could not remove downloaded JRE file {}",
+            downloaded.display()
+        ))?;
+
+        tokio::fs::rename(
+            unzipped_content.iter().last().unwrap(),
+            path_to_runtimes
+                .join("java")
+                .join(format!("jre{}", jre_major_version)),
+        )
+        .await
+        .context(format!(
+            "Could not rename JRE directory {}",
+            unzipped_content.iter().last().unwrap().display()
+        ))?;
+    } else {
+        event_broadcaster.send(Event::new_progression_event_update(
+            progression_event_id,
+            format!("{step}: JRE already downloaded"),
+            4.0,
+        ));
+    }
+    Ok(jre_major_version)
+}
+
 impl MinecraftInstance {
     pub async fn setup_manifest(flavour: &FlavourKind) -> Result<SetupManifest, Error> {
         let versions = match flavour {
@@ -219,7 +335,11 @@ impl MinecraftInstance {
             FlavourKind::Forge => get_forge_minecraft_versions().await,
         }
         .context("Failed to get minecraft versions")?;
+        Ok(Self::setup_manifest_with_versions(versions))
+    }
 
+    /// The setup manifest offering `versions`, the first one is the default
+    pub fn setup_manifest_with_versions(versions: Vec<String>) -> SetupManifest {
         let version_setting = SettingManifest::new_value_with_type(
             "version".to_string(),
             "Version".to_string(),
@@ -276,6 +396,42 @@ impl MinecraftInstance {
             true,
         );
 
+        let custom_jar_path_setting = SettingManifest::new_optional_value(
+            "custom_jar_path".to_string(),
+            "Custom Server Jar".to_string(),
+            "Path of a server jar relative to the instance root, used instead of downloading one"
+                .to_string(),
+            None,
+            ConfigurableValueType::String { regex: None },
+            None,
+            false,
+            true,
+        );
+
+        let level_type_setting = SettingManifest::new_optional_value(
+            "level_type".to_string(),
+            "World Type".to_string(),
+            "The type of world to generate on the first start".to_string(),
+            None,
+            ConfigurableValueType::Enum {
+                options: LEVEL_TYPES.iter().map(|s| s.to_string()).collect(),
+            },
+            None,
+            false,
+            true,
+        );
+
+        let generator_settings_setting = SettingManifest::new_optional_value(
+            "generator_settings".to_string(),
+            "Generator Settings".to_string(),
+            "Settings of the world generator, e.g. the layers of a flat world".to_string(),
+            None,
+            ConfigurableValueType::String { regex: None },
+            None,
+            false,
+            true,
+        );
+
         let mut section_1_map = IndexMap::new();
 
         section_1_map.insert("version".to_string(), version_setting);
@@ -289,6 +445,12 @@ impl MinecraftInstance {
 
         section_2_map.insert("cmd_args".to_string(), command_line_args_setting);
 
+        section_2_map.insert("custom_jar_path".to_string(), custom_jar_path_setting);
+
+        section_2_map.insert("level_type".to_string(), level_type_setting);
+
+        section_2_map.insert("generator_settings".to_string(), generator_settings_setting);
+
         let section_1 = SectionManifest::new(
             "section_1".to_string(),
             "Basic Settings".to_string(),
@@ -308,18 +470,101 @@ impl MinecraftInstance {
         sections.insert("section_1".to_string(), section_1);
         sections.insert("section_2".to_string(), section_2);
 
-        Ok(SetupManifest {
+        SetupManifest {
             setting_sections: sections,
-        })
+        }
+    }
+
+    /// The setup value creating an instance with `config` would have been given, to check an
+    /// imported config the way a creation is checked
+    pub fn setup_value_of(config: &RestoreConfig) -> SetupValue {
+        let mut settings = vec![
+            (
+                "section_1",
+                "version",
+                ConfigurableValue::Enum(config.version.clone()),
+            ),
+            (
+                "section_1",
+                "port",
+                ConfigurableValue::UnsignedInteger(config.port),
+            ),
+            (
+                "section_2",
+                "min_ram",
+                ConfigurableValue::UnsignedInteger(config.min_ram),
+            ),
+            (
+                "section_2",
+                "max_ram",
+                ConfigurableValue::UnsignedInteger(config.max_ram),
+            ),
+            (
+                "section_2",
+                "cmd_args",
+                ConfigurableValue::String(config.cmd_args.join(" ")),
+            ),
+        ];
+        if let Some(custom_jar_path) = &config.custom_jar_path {
+            settings.push((
+                "section_2",
+                "custom_jar_path",
+                ConfigurableValue::String(custom_jar_path.clone()),
+            ));
+        }
+        SetupValue::from_settings(
+            config.name.clone(),
+            Some(config.description.clone()),
+            config.auto_start,
+            config.restart_on_crash,
+            settings,
+        )
     }
 
-    pub async fn construct_setup_config(
+    /// Every problem with `setup_value`, the fields against the manifest then the RAM range and
+    /// the world generation
+    pub fn setup_value_errors(
+        manifest: &SetupManifest,
+        setup_value: &SetupValue,
+    ) -> Vec<FieldError> {
+        let mut errors = manifest.field_errors(setup_value);
+        let setting = |setting_id: &str| {
+            setup_value
+                .get_unique_setting(setting_id)
+                .and_then(|setting| setting.get_value())
+        };
+        let ram =
+            |setting_id: &str| setting(setting_id).and_then(|v| v.try_as_unsigned_integer().ok());
+        if let (Some(min_ram), Some(max_ram)) = (ram("min_ram"), ram("max_ram")) {
+            if min_ram > max_ram {
+                errors.push(FieldError::new(
+                    "min_ram",
+                    format!(
+                        "Minimum RAM ({min_ram}) cannot be more than the maximum RAM ({max_ram})"
+                    ),
+                ));
+            }
+        }
+        let level_type = setting("level_type").and_then(|v| v.try_as_enum().ok());
+        if let Err(e) = validate_world_generation(level_type.map(|s| s.as_str()), None) {
+            errors.push(FieldError::new("level_type", e.source.to_string()));
+        }
+        let generator_settings = setting("generator_settings").and_then(|v| v.try_as_string().ok());
+        if let Err(e) = validate_world_generation(None, generator_settings.map(|s| s.as_str())) {
+            errors.push(FieldError::new("generator_settings", e.source.to_string()));
+        }
+        errors
+    }
+
+    pub fn construct_setup_config(
         setup_value: SetupValue,
         flavour: FlavourKind,
+        manifest: &SetupManifest,
     ) -> Result<SetupConfig, Error> {
-        Self::setup_manifest(&flavour)
-            .await?
-            .validate_setup_value(&setup_value)?;
+        let errors = Self::setup_value_errors(manifest, &setup_value);
+        if !errors.is_empty() {
+            return Err(Error::fields(errors));
+        }
 
         // ALL of the following unwraps are safe because we just validated the manifest value
         let description = setup_value.description.clone();
@@ -368,6 +613,32 @@ impl MinecraftInstance {
             .map(|s| s.to_string())
             .collect();
 
+        let custom_jar_path = setup_value
+            .get_unique_setting("custom_jar_path")
+            .and_then(|setting| setting.get_value())
+            .map(|v| {
+                v.try_as_string().map_err(|e| {
+                    Error::fields(vec![FieldError::new(
+                        "custom_jar_path",
+                        e.source.to_string(),
+                    )])
+                })
+            })
+            .transpose()?
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty());
+
+        let level_type = setup_value
+            .get_unique_setting("level_type")
+            .and_then(|setting| setting.get_value())
+            .map(|v| v.try_as_enum().unwrap().to_string());
+
+        let generator_settings = setup_value
+            .get_unique_setting("generator_settings")
+            .and_then(|setting| setting.get_value())
+            .map(|v| v.try_as_string().unwrap().trim().to_string())
+            .filter(|settings| !settings.is_empty());
+
         Ok(SetupConfig {
             name,
             description,
@@ -380,6 +651,9 @@ impl MinecraftInstance {
             auto_start: Some(setup_value.auto_start),
             restart_on_crash: Some(setup_value.restart_on_crash),
             backup_period: None,
+            custom_jar_path,
+            level_type,
+            generator_settings,
         })
     }
 
@@ -396,6 +670,13 @@ impl MinecraftInstance {
         cmd_args_config_map.insert(max_ram.get_identifier().to_owned(), max_ram.into());
         let java_cmd = CmdArgSetting::JavaCmd(java_cmd);
         cmd_args_config_map.insert(java_cmd.get_identifier().to_owned(), java_cmd.into());
+        let custom_jar_path = CmdArgSetting::CustomJarPath(
+            restore_config.custom_jar_path.clone().unwrap_or_default(),
+        );
+        cmd_args_config_map.insert(
+            custom_jar_path.get_identifier().to_owned(),
+            custom_jar_path.into(),
+        );
 
         let cmd_line_section_manifest = SectionManifest::new(
             CmdArgSetting::get_section_id().to_string(),
@@ -433,6 +714,7 @@ impl MinecraftInstance {
         progression_event_id: &ProgressionEventID,
         event_broadcaster: EventBroadcaster,
         macro_executor: MacroExecutor,
+        global_settings: Arc<Mutex<GlobalSettings>>,
     ) -> Result<MinecraftInstance, Error> {
         let path_to_config = path_to_instance.join(".lodestone_minecraft_config.json");
         let path_to_eula = path_to_instance.join("eula.txt");
@@ -455,7 +737,15 @@ impl MinecraftInstance {
             .and(tokio::fs::create_dir_all(&path_to_resources.join("defaults")).await)
             .and(tokio::fs::write(&path_to_eula, "#generated by Lodestone\neula=true").await)
             .and(
-                tokio::fs::write(&path_to_properties, format!("server-port={}", config.port)).await,
+                tokio::fs::write(
+                    &path_to_properties,
+                    initial_server_properties(
+                        config.port,
+                        config.level_type.as_deref(),
+                        config.generator_settings.as_deref(),
+                    ),
+                )
+                .await,
             )
             .context("Could not create some files or directories for instance")
             .map_err(|e| {
@@ -464,97 +754,48 @@ impl MinecraftInstance {
             })?;
 
         // Step 2: Download JRE
-        let (url, jre_major_version) = get_jre_url(config.version.as_str())
-            .await
-            .context("Could not get JRE URL")?;
-        if !path_to_runtimes
-            .join("java")
-            .join(format!("jre{}", jre_major_version))
-            .exists()
-        {
-            let downloaded = download_file(
-                &url,
-                &path_to_runtimes.join("java"),
-                None,
-                {
-                    let event_broadcaster = event_broadcaster.clone();
-                    &move |dl| {
-                        if let Some(total) = dl.total {
-                            event_broadcaster.send(Event::new_progression_event_update(
-                                progression_event_id,
-                                format!(
-                                    "2/4: Downloading JRE {}",
-                                    format_byte_download(dl.downloaded, total)
-                                ),
-                                (dl.step as f64 / total as f64) * 4.0,
-                            ));
-                        }
-                    }
-                },
-                true,
-            )
-            .await?;
-
-            let unzipped_content = unzip_file_async(
-                &downloaded,
-                UnzipOption::ToDir(path_to_runtimes.join("java")),
-            )
-            .await?;
-            if unzipped_content.len() != 1 {
-                return Err(eyre!(
-                    "Expected only one file in the JRE archive, got {}",
-                    unzipped_content.len()
-                )
-                .into());
-            }
-
-            tokio::fs::remove_file(&downloaded).await.context(format!(
-                "Could not remove downloaded JRE file {}",
-                downloaded.display()
-            ))?;
+        let jre_major_version = ensure_jre(
+            &config.version,
+            &path_to_runtimes,
+            "2/4",
+            progression_event_id,
+            &event_broadcaster,
+        )
+        .await?;
 
-            tokio::fs::rename(
-                unzipped_content.iter().last().unwrap(),
-                path_to_runtimes
-                    .join("java")
-                    .join(format!("jre{}", jre_major_version)),
-            )
-            .await
-            .context(format!(
-                "Could not rename JRE directory {}",
-                unzipped_content.iter().last().unwrap().display()
-            ))?;
-        } else {
+        // Step 3: Download server.jar
+        let flavour_name = config.flavour.to_string();
+        // a custom jar is brought by the user, there is nothing to download or install
+        let (server_jar, flavour) = if config.custom_jar_path.is_some() {
             event_broadcaster.send(Event::new_progression_event_update(
                 progression_event_id,
-                "2/4: JRE already downloaded",
-                4.0,
+                "3/4: Using custom server jar, skipping download",
+                3.0,
             ));
-        }
-
-        // Step 3: Download server.jar
-        let flavour_name = config.flavour.to_string();
-        let (jar_url, flavour) = get_server_jar_url(config.version.as_str(), &config.flavour)
-            .await
-            .ok_or_else({
-                || {
-                    eyre!(
-                        "Could not find a {} server.jar for version {}",
-                        flavour_name,
-                        config.version
-                    )
-                }
-            })?;
+            (None, config.flavour.clone())
+        } else {
+            let jar_mirrors = global_settings.lock().await.jar_mirrors();
+            let server_jar = get_server_jar(config.version.as_str(), &config.flavour, &jar_mirrors)
+                .await
+                .ok_or_else({
+                    || {
+                        eyre!(
+                            "Could not find a {} server.jar for version {}",
+                            flavour_name,
+                            config.version
+                        )
+                    }
+                })?;
+            let flavour = server_jar.flavour.clone();
+            (Some(server_jar), flavour)
+        };
         let jar_name = match flavour {
             Flavour::Forge { .. } => "forge-installer.jar",
             _ => "server.jar",
         };
 
-        download_file(
-            jar_url.as_str(),
-            &path_to_instance,
-            Some(jar_name),
-            {
+        if let Some(server_jar) = server_jar {
+            download_server_jar(&server_jar, &path_to_instance, jar_name, {
                 let event_broadcaster = event_broadcaster.clone();
                 &move |dl| {
                     if let Some(total) = dl.total {
@@ -581,10 +822,9 @@ impl MinecraftInstance {
                         ));
                     }
                 }
-            },
-            true,
-        )
-        .await?;
+            })
+            .await?;
+        }
         let jre = path_to_runtimes
             .join("java")
             .join(format!("jre{}", jre_major_version))
@@ -595,7 +835,7 @@ impl MinecraftInstance {
             })
             .join("java");
         // Step 3 (part 2): Forge Setup
-        if let Flavour::Forge { .. } = flavour.clone() {
+        if config.custom_jar_path.is_none() && matches!(flavour, Flavour::Forge { .. }) {
             event_broadcaster.send(Event::new_progression_event_update(
                 progression_event_id,
                 "3/4: Installing Forge Server",
@@ -653,6 +893,10 @@ impl MinecraftInstance {
             jre_major_version,
             has_started: false,
             java_cmd: Some(jre.to_string_lossy().to_string()),
+            custom_jar_path: config.custom_jar_path,
+            locked: false,
+            depends_on: Vec::new(),
+            stop_command: default_stop_command(),
         };
         // create config file
         tokio::fs::write(
@@ -671,6 +915,7 @@ impl MinecraftInstance {
             dot_lodestone_config,
             event_broadcaster,
             macro_executor,
+            global_settings,
         )
         .await
     }
@@ -680,6 +925,7 @@ impl MinecraftInstance {
         dot_lodestone_config: DotLodestoneConfig,
         event_broadcaster: EventBroadcaster,
         macro_executor: MacroExecutor,
+        global_settings: Arc<Mutex<GlobalSettings>>,
     ) -> Result<MinecraftInstance, Error> {
         let path_to_config = path_to_instance.join(".lodestone_minecraft_config.json");
         let restore_config: RestoreConfig =
@@ -743,6 +989,7 @@ impl MinecraftInstance {
             configurable_manifest,
             macro_name_to_last_run: Arc::new(Mutex::new(HashMap::new())),
             pid_to_task_entry: Arc::new(Mutex::new(IndexMap::new())),
+            global_settings,
         };
         instance
             .read_properties()
@@ -828,6 +1075,51 @@ impl MinecraftInstance {
         Ok(())
     }
 
+    /// Apply changes to server.properties in one go, unspecified keys and comments are kept.
+    /// The manifest stays locked throughout so concurrent patches don't overwrite each other
+    pub async fn patch_server_properties(
+        &self,
+        patch: &IndexMap<String, Option<String>>,
+    ) -> Result<(), Error> {
+        let mut lock = self.configurable_manifest.lock().await;
+        let properties = match tokio::fs::read_to_string(&self.path_to_properties).await {
+            Ok(properties) => properties,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                return Err(e)
+                    .context(format!(
+                        "Failed to read properties file at {}",
+                        &self.path_to_properties.display()
+                    ))
+                    .map_err(Error::from)
+            }
+        };
+        let properties = apply_properties_patch(&properties, patch)?;
+        // written next to the file then renamed, a reader never sees half a patch
+        let path_to_temp = self.path_to_properties.with_extension("properties.tmp");
+        crate::util::fs::write_all(&path_to_temp, properties).await?;
+        crate::util::fs::rename(&path_to_temp, &self.path_to_properties).await?;
+
+        // rebuilt from the file since deleted keys have to leave the manifest too
+        lock.clear_section(ServerPropertySetting::get_section_id());
+        for (key, value) in read_properties_from_path(&self.path_to_properties)
+            .await?
+            .iter()
+        {
+            match ServerPropertySetting::from_key_val(key, value) {
+                Ok(setting) => {
+                    let _ =
+                        lock.set_setting(ServerPropertySetting::get_section_id(), setting.into());
+                }
+                Err(e) => error!(
+                    "Failed to parse property {} with value {}: {}",
+                    key, value, e
+                ),
+            }
+        }
+        Ok(())
+    }
+
     async fn sync_configurable_to_restore_config(&self) {
         let mut config_lock = self.config.lock().await;
 
@@ -884,9 +1176,16 @@ impl MinecraftInstance {
                 .expect("Programming error, value is not a string")
                 .to_owned(),
         );
+
+        config_lock.custom_jar_path = configurable_map
+            .get(CmdArgSetting::CustomJarPath(Default::default()).get_identifier())
+            .and_then(|setting| setting.get_value())
+            .and_then(|value| value.try_as_string().ok())
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty());
     }
 
-    pub fn get_rcon(&self) -> Arc<Mutex<Option<rcon::Connection<tokio::net::TcpStream>>>> {
+    pub fn get_rcon(&self) -> Arc<Mutex<Option<::rcon::Connection<tokio::net::TcpStream>>>> {
         self.rcon_conn.clone()
     }
 
@@ -42,6 +42,7 @@ impl PlayersManager {
             caused_by: CausedBy::Instance {
                 instance_uuid: self.instance_uuid.clone(),
             },
+            correlation_id: None,
         });
     }
 
@@ -62,6 +63,7 @@ impl PlayersManager {
                 caused_by: CausedBy::Instance {
                     instance_uuid: self.instance_uuid.clone(),
                 },
+                correlation_id: None,
             });
         }
     }
@@ -97,6 +99,7 @@ impl PlayersManager {
             caused_by: CausedBy::Instance {
                 instance_uuid: self.instance_uuid.clone(),
             },
+            correlation_id: None,
         });
         self.players.clear();
     }
@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+
+use tracing::warn;
+
+use crate::error::FieldError;
+
+/// Everything the preflight looks at, gathered before the server is started
+#[derive(Debug, Clone)]
+pub struct PreflightContext {
+    pub path_to_instance: PathBuf,
+    /// jar passed to `-jar`, `None` when the launch target is resolved another way (Forge)
+    pub server_jar: Option<PathBuf>,
+    pub java: PathBuf,
+    pub port: u32,
+    pub port_available: bool,
+    pub min_ram: u32,
+    pub max_ram: u32,
+    /// in bytes
+    pub host_memory: u64,
+    pub jvm_args: Vec<String>,
+}
+
+const GARBAGE_COLLECTORS: [&str; 6] = [
+    "-XX:+UseG1GC",
+    "-XX:+UseZGC",
+    "-XX:+UseParallelGC",
+    "-XX:+UseSerialGC",
+    "-XX:+UseShenandoahGC",
+    "-XX:+UseConcMarkSweepGC",
+];
+
+async fn is_eula_accepted(path_to_instance: &Path) -> bool {
+    tokio::fs::read_to_string(path_to_instance.join("eula.txt"))
+        .await
+        .map(|eula| {
+            eula.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.starts_with('#'))
+                .filter_map(|line| line.split_once('='))
+                .any(|(key, value)| {
+                    key.trim() == "eula" && value.trim().eq_ignore_ascii_case("true")
+                })
+        })
+        .unwrap_or(false)
+}
+
+async fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = tokio::fs::metadata(path).await else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// The java command as the OS would resolve it, a bare name like `java` is looked up in `PATH`
+async fn resolve_java(java: &Path) -> Option<PathBuf> {
+    let candidates = if cfg!(windows) && java.extension().is_none() {
+        vec![java.to_path_buf(), java.with_extension("exe")]
+    } else {
+        vec![java.to_path_buf()]
+    };
+    let dirs = if java.components().count() > 1 {
+        vec![PathBuf::new()]
+    } else {
+        std::env::split_paths(&std::env::var_os("PATH")?).collect()
+    };
+    for dir in dirs {
+        for candidate in &candidates {
+            let path = dir.join(candidate);
+            if is_executable(&path).await {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// JVM arguments setting the memory on their own. Plenty of existing instances have them, and
+/// they come after the instance's settings so the JVM uses them
+fn memory_overrides(jvm_args: &[String]) -> impl Iterator<Item = &String> {
+    jvm_args.iter().filter(|arg| {
+        arg.starts_with("-Xmx") || arg.starts_with("-Xms") || arg.starts_with("-XX:MaxRAM")
+    })
+}
+
+/// JVM arguments that fight the jar settings of the instance, or each other
+fn conflicting_jvm_args(jvm_args: &[String]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    for arg in jvm_args {
+        if arg == "-jar" {
+            conflicts.push("-jar overrides the server jar of the instance".to_string());
+        }
+    }
+    let collectors: Vec<&str> = jvm_args
+        .iter()
+        .map(|arg| arg.as_str())
+        .filter(|arg| GARBAGE_COLLECTORS.contains(arg))
+        .collect();
+    if collectors.len() > 1 {
+        conflicts.push(format!(
+            "only one garbage collector can be selected, got {}",
+            collectors.join(" ")
+        ));
+    }
+    let mut properties: Vec<(&str, &str)> = Vec::new();
+    for arg in jvm_args {
+        let Some((key, value)) = arg.strip_prefix("-D").and_then(|p| p.split_once('=')) else {
+            continue;
+        };
+        match properties.iter().find(|(seen, _)| *seen == key) {
+            Some((_, seen_value)) if *seen_value != value => {
+                conflicts.push(format!("-D{key} is set to both {seen_value} and {value}"))
+            }
+            Some(_) => {}
+            None => properties.push((key, value)),
+        }
+    }
+    conflicts
+}
+
+/// Check the instance can be launched, every failed check is reported rather than the first.
+/// Memory overrides in the JVM arguments are only warned about
+pub async fn run_preflight(context: &PreflightContext) -> Vec<FieldError> {
+    for arg in memory_overrides(&context.jvm_args) {
+        warn!("JVM argument {arg} overrides the memory settings of the instance");
+    }
+    let mut failures = Vec::new();
+    if let Some(server_jar) = &context.server_jar {
+        let is_file = tokio::fs::metadata(server_jar)
+            .await
+            .map_or(false, |metadata| metadata.is_file());
+        if !is_file {
+            failures.push(FieldError::new(
+                "server_jar",
+                format!("Server jar {} not found", server_jar.display()),
+            ));
+        }
+    }
+    if !is_eula_accepted(&context.path_to_instance).await {
+        failures.push(FieldError::new(
+            "eula",
+            "The EULA has not been accepted, set eula=true in eula.txt",
+        ));
+    }
+    if resolve_java(&context.java).await.is_none() {
+        failures.push(FieldError::new(
+            "java",
+            format!(
+                "Java at {} was not found or is not executable",
+                context.java.display()
+            ),
+        ));
+    }
+    if !context.port_available {
+        failures.push(FieldError::new(
+            "port",
+            format!("Port {} is already in use", context.port),
+        ));
+    }
+    let host_memory_mb = context.host_memory / 1024 / 1024;
+    if context.min_ram > context.max_ram {
+        failures.push(FieldError::new(
+            "ram",
+            format!(
+                "Minimum RAM ({}MB) is above the maximum RAM ({}MB)",
+                context.min_ram, context.max_ram
+            ),
+        ));
+    } else if context.max_ram as u64 > host_memory_mb {
+        failures.push(FieldError::new(
+            "ram",
+            format!(
+                "Maximum RAM ({}MB) is more than the host has ({host_memory_mb}MB)",
+                context.max_ram
+            ),
+        ));
+    }
+    for conflict in conflicting_jvm_args(&context.jvm_args) {
+        failures.push(FieldError::new("jvm_args", conflict));
+    }
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_preflight_reports_every_failure() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::write(root.join("eula.txt"), "#By changing the setting below to TRUE you are indicating your agreement to our EULA\neula=false\n").unwrap();
+
+        let context = PreflightContext {
+            path_to_instance: root.to_path_buf(),
+            server_jar: Some(root.join("server.jar")),
+            java: root.join("jre17/bin/java"),
+            port: 25565,
+            port_available: false,
+            min_ram: 1024,
+            max_ram: 64 * 1024 * 1024,
+            host_memory: 8 * 1024 * 1024 * 1024,
+            jvm_args: vec![
+                "-Xmx2G".to_string(),
+                "-XX:+UseG1GC".to_string(),
+                "-XX:+UseZGC".to_string(),
+                "-Dfile.encoding=UTF-8".to_string(),
+                "-Dfile.encoding=ISO-8859-1".to_string(),
+            ],
+        };
+        let failures = run_preflight(&context).await;
+        let fields: Vec<&str> = failures.iter().map(|f| f.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "server_jar",
+                "eula",
+                "java",
+                "port",
+                "ram",
+                "jvm_args",
+                "jvm_args"
+            ]
+        );
+
+        // the same instance once fixed
+        std::fs::write(root.join("eula.txt"), "eula=true\n").unwrap();
+        std::fs::write(root.join("server.jar"), "").unwrap();
+        std::fs::create_dir_all(root.join("jre17/bin")).unwrap();
+        std::fs::write(root.join("jre17/bin/java"), "").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(
+                root.join("jre17/bin/java"),
+                std::fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+        }
+        let context = PreflightContext {
+            port_available: true,
+            max_ram: 4096,
+            // a memory override alone doesn't keep the instance from starting
+            jvm_args: vec![
+                "-Xmx2G".to_string(),
+                "-XX:+UseG1GC".to_string(),
+                "-Dfile.encoding=UTF-8".to_string(),
+            ],
+            ..context
+        };
+        assert_eq!(run_preflight(&context).await, vec![]);
+
+        let context = PreflightContext {
+            min_ram: 8192,
+            ..context
+        };
+        let failures = run_preflight(&context).await;
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].field, "ram");
+    }
+}
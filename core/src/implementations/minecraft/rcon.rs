@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use super::MinecraftInstance;
+
+/// How long connecting and authenticating may take before the test gives up
+pub const RCON_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The RCON settings of server.properties, `None` when a setting is missing or invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RconSettings {
+    pub enabled: bool,
+    pub password: Option<String>,
+    pub port: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+#[ts(export)]
+pub enum RconTestOutcome {
+    Connected,
+    Disabled,
+    /// enabled without a password or port
+    Misconfigured,
+    NotRunning,
+    ConnectionRefused,
+    WrongPassword,
+    TimedOut,
+    /// any other network error
+    Unreachable,
+}
+
+/// Result of connecting and authenticating to RCON, no command is run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct RconTest {
+    pub success: bool,
+    pub outcome: RconTestOutcome,
+    pub message: String,
+}
+
+impl RconTest {
+    fn new(outcome: RconTestOutcome, message: impl Into<String>) -> Self {
+        Self {
+            success: outcome == RconTestOutcome::Connected,
+            outcome,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_running() -> Self {
+        Self::new(
+            RconTestOutcome::NotRunning,
+            "The server must be running to accept RCON connections",
+        )
+    }
+}
+
+/// Connect and authenticate to the RCON server on `host`, the connection is closed right after
+pub async fn test_rcon(host: &str, settings: &RconSettings, timeout: Duration) -> RconTest {
+    if !settings.enabled {
+        return RconTest::new(
+            RconTestOutcome::Disabled,
+            "RCON is disabled, set enable-rcon to true in server.properties",
+        );
+    }
+    let (Some(password), Some(port)) = (settings.password.as_deref(), settings.port) else {
+        return RconTest::new(
+            RconTestOutcome::Misconfigured,
+            "RCON needs both rcon.password and rcon.port set in server.properties",
+        );
+    };
+    if password.is_empty() {
+        return RconTest::new(
+            RconTestOutcome::Misconfigured,
+            "RCON is enabled without a password, set rcon.password in server.properties",
+        );
+    }
+    let connecting = <rcon::Connection<tokio::net::TcpStream>>::builder()
+        .enable_minecraft_quirks(true)
+        .connect(&format!("{host}:{port}"), password);
+    match tokio::time::timeout(timeout, connecting).await {
+        Ok(Ok(_)) => RconTest::new(
+            RconTestOutcome::Connected,
+            format!("Connected and authenticated to RCON on port {port}"),
+        ),
+        Ok(Err(rcon::Error::Auth)) => RconTest::new(
+            RconTestOutcome::WrongPassword,
+            "RCON refused the password, check rcon.password in server.properties",
+        ),
+        Ok(Err(rcon::Error::Io(e))) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            RconTest::new(
+                RconTestOutcome::ConnectionRefused,
+                format!("Nothing is listening for RCON on port {port}, check rcon.port"),
+            )
+        }
+        Ok(Err(e)) => RconTest::new(
+            RconTestOutcome::Unreachable,
+            format!("Failed to connect to RCON on port {port}: {e}"),
+        ),
+        Err(_) => RconTest::new(
+            RconTestOutcome::TimedOut,
+            format!(
+                "RCON on port {port} didn't answer within {}s",
+                timeout.as_secs()
+            ),
+        ),
+    }
+}
+
+impl MinecraftInstance {
+    pub async fn rcon_settings(&self) -> RconSettings {
+        let manifest = self.configurable_manifest.lock().await;
+        let value = |key: &str| {
+            manifest
+                .get_unique_setting_key(key)
+                .and_then(|setting| setting.get_value())
+                .cloned()
+        };
+        RconSettings {
+            enabled: value("enable-rcon")
+                .and_then(|v| v.try_as_boolean().ok())
+                .unwrap_or(false),
+            password: value("rcon.password").and_then(|v| v.try_as_string().ok().cloned()),
+            port: value("rcon.port").and_then(|v| v.try_as_unsigned_integer().ok()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    const PASSWORD: &str = "hunter2";
+
+    async fn read_packet(stream: &mut tokio::net::TcpStream) -> (i32, String) {
+        let length = stream.read_i32_le().await.unwrap();
+        let id = stream.read_i32_le().await.unwrap();
+        let _packet_type = stream.read_i32_le().await.unwrap();
+        let mut body = vec![0; length as usize - 8];
+        stream.read_exact(&mut body).await.unwrap();
+        body.truncate(body.len() - 2);
+        (id, String::from_utf8(body).unwrap())
+    }
+
+    /// Answers the login like a Minecraft server, `silent` never answers at all
+    async fn serve_mock_rcon(silent: bool) -> u32 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as u32;
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let (id, password) = read_packet(&mut stream).await;
+                    if silent {
+                        return futures::future::pending().await;
+                    }
+                    let id = if password == PASSWORD { id } else { -1 };
+                    let mut response = Vec::new();
+                    response.extend(10_i32.to_le_bytes());
+                    response.extend(id.to_le_bytes());
+                    // auth response
+                    response.extend(2_i32.to_le_bytes());
+                    response.extend([0, 0]);
+                    stream.write_all(&response).await.unwrap();
+                });
+            }
+        });
+        port
+    }
+
+    fn settings(enabled: bool, password: &str, port: u32) -> RconSettings {
+        RconSettings {
+            enabled,
+            password: Some(password.to_string()),
+            port: Some(port),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rcon_test_outcomes() {
+        let port = serve_mock_rcon(false).await;
+        let test = |settings: RconSettings| async move {
+            test_rcon("localhost", &settings, Duration::from_secs(2)).await
+        };
+
+        let connected = test(settings(true, PASSWORD, port)).await;
+        assert!(connected.success);
+        assert_eq!(connected.outcome, RconTestOutcome::Connected);
+
+        let wrong_password = test(settings(true, "password", port)).await;
+        assert!(!wrong_password.success);
+        assert_eq!(wrong_password.outcome, RconTestOutcome::WrongPassword);
+
+        assert_eq!(
+            test(settings(false, PASSWORD, port)).await.outcome,
+            RconTestOutcome::Disabled
+        );
+        assert_eq!(
+            test(settings(true, "", port)).await.outcome,
+            RconTestOutcome::Misconfigured
+        );
+
+        let closed_port = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port() as u32
+        };
+        assert_eq!(
+            test(settings(true, PASSWORD, closed_port)).await.outcome,
+            RconTestOutcome::ConnectionRefused
+        );
+
+        let silent_port = serve_mock_rcon(true).await;
+        let timed_out = test_rcon(
+            "localhost",
+            &settings(true, PASSWORD, silent_port),
+            Duration::from_millis(200),
+        )
+        .await;
+        assert_eq!(timed_out.outcome, RconTestOutcome::TimedOut);
+    }
+}
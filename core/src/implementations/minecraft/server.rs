@@ -7,14 +7,22 @@ use sysinfo::{Pid, PidExt, ProcessExt, SystemExt};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 
-use crate::error::{Error, ErrorKind};
+use crate::error::Error;
 use crate::events::{CausedBy, Event, EventInner, InstanceEvent, InstanceEventInner};
 use crate::implementations::minecraft::line_parser::{
     parse_player_joined, parse_player_left, parse_player_msg, parse_server_started,
     parse_system_msg, PlayerMessage,
 };
 use crate::implementations::minecraft::player::MinecraftPlayer;
-use crate::implementations::minecraft::util::name_to_uuid;
+use crate::implementations::minecraft::preflight::{run_preflight, PreflightContext};
+use crate::implementations::minecraft::util::{
+    name_to_uuid, resolve_custom_jar_path, send_stop_command,
+};
+use crate::instance_crashes::{is_crash, record_instance_crash};
+use crate::instance_log_level::instance_span;
+use crate::instance_start_log::{
+    clear_last_start_log, read_last_start_log, write_last_start_log, StartLogCapture,
+};
 use crate::macro_executor::{DefaultWorkerOptionGenerator, SpawnResult};
 use crate::traits::t_configurable::TConfigurable;
 use crate::traits::t_macro::TaskEntry;
@@ -25,33 +33,78 @@ use crate::util::{dont_spawn_terminal, list_dir};
 
 use super::r#macro::resolve_macro_invocation;
 use super::{Flavour, ForgeBuildVersion, MinecraftInstance};
-use tracing::{error, info, warn};
+use tracing::{error, info, warn, Instrument};
 
 #[async_trait::async_trait]
 impl TServer for MinecraftInstance {
     async fn start(&self, cause_by: CausedBy, block: bool) -> Result<(), Error> {
         let config = self.config.lock().await.clone();
-        self.state.lock().await.try_transition(
-            StateAction::UserStart,
-            Some(&|state| {
-                self.event_broadcaster.send(Event {
-                    event_inner: EventInner::InstanceEvent(InstanceEvent {
-                        instance_name: config.name.clone(),
-                        instance_uuid: self.uuid.clone(),
-                        instance_event_inner: InstanceEventInner::StateTransition { to: state },
-                    }),
-                    snowflake: Snowflake::default(),
-                    details: "Starting server".to_string(),
-                    caused_by: cause_by.clone(),
-                });
-            }),
-        )?;
+        let custom_jar = config
+            .custom_jar_path
+            .as_deref()
+            .map(|custom_jar_path| resolve_custom_jar_path(&self.path_to_instance, custom_jar_path))
+            .transpose()?;
 
-        if !port_scanner::local_port_available(config.port as u16) {
-            return Err(Error {
-                kind: ErrorKind::Internal,
-                source: eyre!("Port {} is already in use", config.port),
+        let jre = if let Some(jre) = &config.java_cmd {
+            PathBuf::from(jre)
+        } else {
+            self.path_to_runtimes
+                .join("java")
+                .join(format!("jre{}", config.jre_major_version))
+                .join(if std::env::consts::OS == "macos" {
+                    "Contents/Home/bin"
+                } else {
+                    "bin"
+                })
+                .join("java")
+        };
+
+        let on_transit = |state: State| {
+            self.event_broadcaster.send(Event {
+                event_inner: EventInner::InstanceEvent(InstanceEvent {
+                    instance_name: config.name.clone(),
+                    instance_uuid: self.uuid.clone(),
+                    instance_event_inner: InstanceEventInner::StateTransition { to: state },
+                }),
+                snowflake: Snowflake::default(),
+                details: "Starting server".to_string(),
+                caused_by: cause_by.clone(),
+                correlation_id: None,
             });
+        };
+        self.state
+            .lock()
+            .await
+            .try_transition(StateAction::UserStart, Some(&on_transit))?;
+
+        // checked once the instance is starting, a failed preflight never launches the JVM
+        let preflight_failures = run_preflight(&PreflightContext {
+            path_to_instance: self.path_to_instance.clone(),
+            server_jar: match (&custom_jar, &config.flavour) {
+                (Some(custom_jar), _) => Some(custom_jar.clone()),
+                (None, Flavour::Forge { .. }) => None,
+                (None, _) => Some(self.path_to_instance.join("server.jar")),
+            },
+            java: jre.clone(),
+            port: config.port,
+            port_available: port_scanner::local_port_available(config.port as u16),
+            min_ram: config.min_ram,
+            max_ram: config.max_ram,
+            host_memory: self.system.lock().await.total_memory(),
+            jvm_args: config
+                .cmd_args
+                .iter()
+                .filter(|s| !s.is_empty())
+                .cloned()
+                .collect(),
+        })
+        .await;
+        if !preflight_failures.is_empty() {
+            self.state
+                .lock()
+                .await
+                .try_transition(StateAction::InstanceStop, Some(&on_transit))?;
+            return Err(Error::fields(preflight_failures));
         }
 
         let prelaunch = resolve_macro_invocation(&self.path_to_instance, "prelaunch");
@@ -99,20 +152,6 @@ impl TServer for MinecraftInstance {
             );
         }
 
-        let jre = if let Some(jre) = &config.java_cmd {
-            PathBuf::from(jre)
-        } else {
-            self.path_to_runtimes
-                .join("java")
-                .join(format!("jre{}", config.jre_major_version))
-                .join(if std::env::consts::OS == "macos" {
-                    "Contents/Home/bin"
-                } else {
-                    "bin"
-                })
-                .join("java")
-        };
-
         let mut server_start_command = Command::new(&jre);
         let server_start_command = server_start_command
             .arg(format!("-Xmx{}M", config.max_ram))
@@ -125,8 +164,9 @@ impl TServer for MinecraftInstance {
                     .collect::<Vec<&String>>(),
             );
 
-        let server_start_command = match &config.flavour {
-            Flavour::Forge { build_version } => {
+        let server_start_command = match (&custom_jar, &config.flavour) {
+            (Some(custom_jar), _) => server_start_command.arg("-jar").arg(custom_jar),
+            (None, Flavour::Forge { build_version }) => {
                 let ForgeBuildVersion(build_version) = build_version
                     .as_ref()
                     .ok_or_else(|| eyre!("Forge version not found"))?;
@@ -195,7 +235,7 @@ impl TServer for MinecraftInstance {
                         .arg(&self.path_to_instance.join(server_jar_name))
                 }
             }
-            _ => server_start_command
+            (None, _) => server_start_command
                 .arg("-jar")
                 .arg(&self.path_to_instance.join("server.jar")),
         };
@@ -240,8 +280,10 @@ impl TServer for MinecraftInstance {
                     let uuid = __self.uuid.clone();
                     let name = config.name.clone();
                     let players_manager = __self.players_manager.clone();
+                    let supervisor_span = instance_span(&uuid);
                     async move {
                         let mut did_start = false;
+                        let mut start_log = StartLogCapture::default();
 
                         let mut stdout_reader = BufReader::new(stdout);
                         let mut stderr_reader = BufReader::new(stderr);
@@ -279,6 +321,9 @@ impl TServer for MinecraftInstance {
                             if let Ok(line) = line_res {
                                 if let Some(line) = line {
                                     let line = String::from_utf8_lossy(&line).to_string();
+                                    if !did_start {
+                                        start_log.push(&line);
+                                    }
                                     if !is_stdout {
                                         // info!("[{}] {}", name, line);
                                         warn!("[{}] {}", name, line);
@@ -295,6 +340,7 @@ impl TServer for MinecraftInstance {
                                         details: "".to_string(),
                                         snowflake: Snowflake::default(),
                                         caused_by: CausedBy::System,
+                                        correlation_id: None,
                                     });
 
                                     if parse_server_started(&line) && !did_start {
@@ -320,11 +366,16 @@ impl TServer for MinecraftInstance {
                                                 snowflake: Snowflake::default(),
                                                 details: "Starting server".to_string(),
                                                 caused_by: cause_by.clone(),
+                                                correlation_id: None,
                                             });
                                                 }),
                                             )
                                             .unwrap();
                                         info!("[{}] Instance started", name);
+                                        clear_last_start_log(&__self.path_to_instance)
+                                            .await
+                                            .map_err(Error::log)
+                                            .ok();
 
                                         if let (Some(true), Some(rcon_psw), Some(rcon_port)) = {
                                             let lock = __self.configurable_manifest.lock().await;
@@ -400,6 +451,7 @@ impl TServer for MinecraftInstance {
                                             details: "".to_string(),
                                             snowflake: Snowflake::default(),
                                             caused_by: CausedBy::System,
+                                            correlation_id: None,
                                         });
                                         if let Some(player_name) = parse_player_joined(&system_msg)
                                         {
@@ -434,6 +486,7 @@ impl TServer for MinecraftInstance {
                                             details: "".to_string(),
                                             snowflake: Snowflake::default(),
                                             caused_by: CausedBy::System,
+                                            correlation_id: None,
                                         });
                                     }
                                 } else {
@@ -442,6 +495,17 @@ impl TServer for MinecraftInstance {
                             }
                         }
                         info!("Instance {} process shutdown", name);
+                        let crashed = is_crash(did_start, *__self.state.lock().await);
+                        // written before the transition so a blocking start can include it
+                        if !did_start {
+                            write_last_start_log(
+                                &__self.path_to_instance,
+                                &std::mem::take(&mut start_log).into_start_log(),
+                            )
+                            .await
+                            .map_err(Error::log)
+                            .ok();
+                        }
                         __self
                             .state
                             .lock()
@@ -460,13 +524,23 @@ impl TServer for MinecraftInstance {
                                         details: "Instance stopping as server process exited"
                                             .to_string(),
                                         caused_by: cause_by.clone(),
+                                        correlation_id: None,
                                     });
                                 }),
                             )
                             .unwrap();
                         __self.players_manager.lock().await.clear(name);
                         __self.rcon_conn.lock().await.take();
+                        if crashed {
+                            let crash_count = record_instance_crash(&__self.path_to_instance)
+                                .await
+                                .map_err(Error::log)
+                                .map(|crashes| crashes.crash_count)
+                                .unwrap_or_default();
+                            warn!("[{}] Instance crashed, {} crash(es) so far", config.name, crash_count);
+                        }
                     }
+                    .instrument(supervisor_span)
                 });
                 self.config.lock().await.has_started = true;
                 self.write_config_to_file().await?;
@@ -485,8 +559,12 @@ impl TServer for MinecraftInstance {
                                 if to == State::Running {
                                     return Ok(()); // Instance started successfully
                                 } else if to == State::Stopped {
+                                    let output = read_last_start_log(&self.path_to_instance)
+                                        .await
+                                        .map(|start_log| start_log.tail(10))
+                                        .unwrap_or_default();
                                     return Err(eyre!(
-                                        "Instance exited unexpectedly before starting"
+                                        "Instance exited unexpectedly before starting:\n{output}"
                                     )
                                     .into());
                                 }
@@ -500,6 +578,12 @@ impl TServer for MinecraftInstance {
             }
             Err(e) => {
                 error!("Failed to start server, {}", e);
+                let mut start_log = StartLogCapture::default();
+                start_log.push(&format!("Failed to start server, {e}"));
+                write_last_start_log(&self.path_to_instance, &start_log.into_start_log())
+                    .await
+                    .map_err(Error::log)
+                    .ok();
                 self.state
                     .lock()
                     .await
@@ -517,6 +601,7 @@ impl TServer for MinecraftInstance {
                                 snowflake: Snowflake::default(),
                                 details: "Starting server".to_string(),
                                 caused_by: cause_by.clone(),
+                                correlation_id: None,
                             });
                         }),
                     )
@@ -541,26 +626,24 @@ impl TServer for MinecraftInstance {
                     snowflake: Snowflake::default(),
                     details: "Stopping server".to_string(),
                     caused_by: cause_by.clone(),
+                    correlation_id: None,
                 });
             }),
         )?;
         let name = config.name.clone();
         let _uuid = self.uuid.clone();
-        self.stdin
-            .lock()
-            .await
-            .as_mut()
-            .ok_or_else(|| {
+        send_stop_command(
+            self.stdin.lock().await.as_mut().ok_or_else(|| {
                 error!("[{}] Failed to stop instance: stdin not available", name);
                 eyre!("Failed to stop instance: stdin not available")
-            })?
-            .write_all(b"stop\n")
-            .await
-            .context("Failed to write to stdin")
-            .map_err(|e| {
-                error!("[{}] Failed to stop instance: {}", name, e);
-                e
-            })?;
+            })?,
+            &config.stop_command,
+        )
+        .await
+        .map_err(|e| {
+            error!("[{}] Failed to stop instance: {}", name, e);
+            e
+        })?;
         self.rcon_conn.lock().await.take();
         let mut rx = self.event_broadcaster.subscribe();
         let instance_uuid = self.uuid.clone();
@@ -610,6 +693,22 @@ impl TServer for MinecraftInstance {
             warn!("[{}] Instance is already stopped", config.name.clone());
             return Err(eyre!("Instance is already stopped").into());
         }
+        // out of running first so the process exiting isn't taken for a crash
+        self.state
+            .lock()
+            .await
+            .try_transition(
+                StateAction::UserStop,
+                Some(&|state| {
+                    self.event_broadcaster
+                        .send(Event::new_instance_state_transition(
+                            self.uuid.clone(),
+                            config.name.clone(),
+                            state,
+                        ));
+                }),
+            )
+            .ok();
         if let Some(process) = self.process.lock().await.as_mut() {
             process
                 .kill()
@@ -648,7 +747,8 @@ impl TServer for MinecraftInstance {
         } else {
             match self.stdin.lock().await.as_mut() {
                 Some(stdin) => match {
-                    if command == "stop" {
+                    // the configured stop command stops the server however it is sent
+                    if command == config.stop_command {
                         self.state.lock().await.try_new_state(
                             StateAction::UserStop,
                             Some(&|state| {
@@ -663,6 +763,7 @@ impl TServer for MinecraftInstance {
                                     snowflake: Snowflake::default(),
                                     details: "Starting server".to_string(),
                                     caused_by: cause_by.clone(),
+                                    correlation_id: None,
                                 });
                             }),
                         )?;
@@ -715,4 +816,182 @@ impl TServer for MinecraftInstance {
             MonitorReport::default()
         }
     }
+
+    async fn pid(&self) -> Option<u32> {
+        self.process.lock().await.as_ref().and_then(|p| p.id())
+    }
+}
+
+#[cfg(test)]
+impl MinecraftInstance {
+    /// Put the instance in `state` without a server process, for tests of what depends on it
+    pub(crate) async fn set_state_for_test(&self, state: State) {
+        *self.state.lock().await = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    #[cfg(unix)]
+    use crate::test_util::restore_with_fake_java;
+
+    #[cfg(unix)]
+    async fn read_java_args(path_to_instance: &Path) -> String {
+        for _ in 0..50 {
+            if let Ok(args) =
+                tokio::fs::read_to_string(path_to_instance.join("java_args.txt")).await
+            {
+                if args.ends_with("nogui\n") {
+                    return args;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("java was never launched");
+    }
+
+    #[cfg(unix)]
+    async fn wait_for_state(instance: &MinecraftInstance, state: State) {
+        for _ in 0..50 {
+            if instance.state().await == state {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("instance never got to {state:?}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_server_exiting_while_running_is_counted_as_a_crash() {
+        use crate::instance_crashes::read_instance_crashes;
+
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        std::fs::write(instance.path_to_instance.join("server.jar"), "").unwrap();
+
+        instance.start(CausedBy::System, false).await.unwrap();
+        wait_for_state(&instance, State::Running).await;
+        let pid = instance.pid().await.unwrap();
+        let killed = std::process::Command::new("kill")
+            .args(["-9", &pid.to_string()])
+            .status()
+            .unwrap();
+        assert!(killed.success());
+        wait_for_state(&instance, State::Stopped).await;
+
+        // recorded once the supervisor has seen the process exit
+        for _ in 0..50 {
+            if read_instance_crashes(&instance.path_to_instance)
+                .await
+                .crash_count
+                == 1
+            {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("the crash was never counted");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_start_launches_custom_jar() {
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), Some("jars/custom.jar")).await;
+        std::fs::create_dir_all(instance.path_to_instance.join("jars")).unwrap();
+        std::fs::write(instance.path_to_instance.join("jars/custom.jar"), "").unwrap();
+
+        instance.start(CausedBy::System, false).await.unwrap();
+
+        let args = read_java_args(&instance.path_to_instance).await;
+        let custom_jar = instance.path_to_instance.join("jars").join("custom.jar");
+        assert!(
+            args.contains(&format!("-jar {} nogui", custom_jar.display())),
+            "{args}"
+        );
+        assert!(!args.contains("server.jar"), "{args}");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failed_preflight_never_launches_java() {
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        std::fs::write(instance.path_to_instance.join("server.jar"), "").unwrap();
+        std::fs::write(instance.path_to_instance.join("eula.txt"), "eula=false\n").unwrap();
+
+        let err = instance.start(CausedBy::System, false).await.unwrap_err();
+        let fields: Vec<&str> = err
+            .field_errors()
+            .unwrap()
+            .iter()
+            .map(|failure| failure.field.as_str())
+            .collect();
+        assert_eq!(fields, vec!["eula"]);
+        assert_eq!(instance.state().await, State::Stopped);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!instance.path_to_instance.join("java_args.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_starting_a_running_instance_is_refused_before_the_preflight() {
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        // would fail the preflight
+        std::fs::write(instance.path_to_instance.join("eula.txt"), "eula=false\n").unwrap();
+        instance.set_state_for_test(State::Running).await;
+
+        let err = instance.start(CausedBy::System, false).await.unwrap_err();
+        assert!(err.field_errors().is_none());
+        assert!(err.to_string().contains("already running"), "{err}");
+        assert_eq!(instance.state().await, State::Running);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_sends_the_configured_stop_command() {
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        std::fs::write(instance.path_to_instance.join("server.jar"), "").unwrap();
+        instance.config.lock().await.stop_command = "end".to_string();
+
+        instance.start(CausedBy::System, false).await.unwrap();
+        wait_for_state(&instance, State::Running).await;
+        instance.stop(CausedBy::System, false).await.unwrap();
+        wait_for_state(&instance, State::Stopped).await;
+        assert_eq!(
+            std::fs::read_to_string(instance.path_to_instance.join("stdin.txt")).unwrap(),
+            "end\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_command_sent_from_the_console_is_a_user_stop() {
+        let temp = tempfile::tempdir().unwrap();
+        let instance = restore_with_fake_java(temp.path(), None).await;
+        std::fs::write(instance.path_to_instance.join("server.jar"), "").unwrap();
+        instance.config.lock().await.stop_command = "end".to_string();
+
+        instance.start(CausedBy::System, false).await.unwrap();
+        wait_for_state(&instance, State::Running).await;
+        instance
+            .send_command("end", CausedBy::System)
+            .await
+            .unwrap();
+        assert_eq!(instance.state().await, State::Stopping);
+        wait_for_state(&instance, State::Stopped).await;
+        // stopped on purpose, not a crash
+        assert_eq!(
+            crate::instance_crashes::read_instance_crashes(&instance.path_to_instance)
+                .await
+                .crash_count,
+            0
+        );
+    }
 }
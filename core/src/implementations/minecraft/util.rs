@@ -1,13 +1,21 @@
 use color_eyre::eyre::{eyre, Context, ContextCompat};
 use indexmap::IndexMap;
 use serde_json::{self, Value};
-use std::{collections::BTreeMap, path::Path, str::FromStr};
-use tokio::io::AsyncBufReadExt;
+use sha2::Digest;
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
 
+use super::configurable::ServerPropertySetting;
 use super::{
     FabricInstallerVersion, FabricLoaderVersion, Flavour, ForgeBuildVersion, PaperBuildVersion,
 };
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
+use crate::global_settings::JarMirrors;
+use crate::util::{download_file, scoped_join_win_safe, DownloadProgress};
 
 pub async fn read_properties_from_path(
     path_to_properties: &Path,
@@ -50,24 +58,92 @@ pub async fn read_properties_from_path(
     Ok(ret)
 }
 
+/// Hash published alongside a server jar by its source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JarHash {
+    Sha1(String),
+    Sha256(String),
+}
+
+/// Where to download a server jar from, with the flavour updated with version information
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerJar {
+    pub url: String,
+    pub flavour: Flavour,
+    pub hash: Option<JarHash>,
+}
+
+/// Point an official download url at a mirror by replacing its scheme and host with the
+/// mirror's base url, the path is kept as is
+pub fn mirror_url(url: &str, mirror: Option<&str>) -> String {
+    let Some(mirror) = mirror else {
+        return url.to_string();
+    };
+    let Some(path_start) = url
+        .find("://")
+        .map(|scheme_end| scheme_end + 3)
+        .map(|host_start| {
+            url[host_start..]
+                .find('/')
+                .map_or(url.len(), |i| host_start + i)
+        })
+    else {
+        return url.to_string();
+    };
+    format!("{}{}", mirror.trim_end_matches('/'), &url[path_start..])
+}
+
 // Returns the jar url and the updated flavour with version information
 pub async fn get_server_jar_url(version: &str, flavour: &Flavour) -> Option<(String, Flavour)> {
+    get_server_jar(version, flavour, &JarMirrors::default())
+        .await
+        .map(|jar| (jar.url, jar.flavour))
+}
+
+pub async fn get_server_jar(
+    version: &str,
+    flavour: &Flavour,
+    mirrors: &JarMirrors,
+) -> Option<ServerJar> {
     match flavour {
-        Flavour::Vanilla => get_vanilla_jar_url(version).await,
+        Flavour::Vanilla => get_vanilla_jar(version, mirrors.vanilla.as_deref()).await,
         Flavour::Fabric {
             loader_version,
             installer_version,
-        } => get_fabric_jar_url(version, loader_version, installer_version).await,
-        Flavour::Paper { build_version } => get_paper_jar_url(version, build_version).await,
+        } => {
+            get_fabric_jar(
+                version,
+                loader_version,
+                installer_version,
+                mirrors.fabric.as_deref(),
+            )
+            .await
+        }
+        Flavour::Paper { build_version } => {
+            get_paper_jar(version, build_version, mirrors.paper.as_deref()).await
+        }
         Flavour::Spigot => todo!(),
-        Flavour::Forge { build_version } => get_forge_jar_url(version, build_version).await.ok(),
+        Flavour::Forge { build_version } => {
+            get_forge_jar(version, build_version, mirrors.forge.as_deref())
+                .await
+                .ok()
+        }
     }
 }
 
 pub async fn get_vanilla_jar_url(version: &str) -> Option<(String, Flavour)> {
+    get_vanilla_jar(version, None)
+        .await
+        .map(|jar| (jar.url, jar.flavour))
+}
+
+pub async fn get_vanilla_jar(version: &str, mirror: Option<&str>) -> Option<ServerJar> {
     let client = reqwest::Client::new();
     let response_text = client
-        .get("https://launchermeta.mojang.com/mc/game/version_manifest.json")
+        .get(mirror_url(
+            "https://launchermeta.mojang.com/mc/game/version_manifest.json",
+            mirror,
+        ))
         .send()
         .await
         .ok()?
@@ -90,18 +166,28 @@ pub async fn get_vanilla_jar_url(version: &str) -> Option<(String, Flavour)> {
         })?
         .get("url")?
         .as_str()?;
-    let response: serde_json::Value =
-        serde_json::from_str(&client.get(url).send().await.ok()?.text().await.ok()?).ok()?;
+    let response: serde_json::Value = serde_json::from_str(
+        &client
+            .get(mirror_url(url, mirror))
+            .send()
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()?,
+    )
+    .ok()?;
     if response["downloads"]["server"]["url"] == serde_json::Value::Null {
         return None;
     }
 
-    Some((
-        response["downloads"]["server"]["url"]
-            .to_string()
-            .replace('\"', ""),
-        Flavour::Vanilla,
-    ))
+    Some(ServerJar {
+        url: mirror_url(response["downloads"]["server"]["url"].as_str()?, mirror),
+        flavour: Flavour::Vanilla,
+        hash: response["downloads"]["server"]["sha1"]
+            .as_str()
+            .map(|sha1| JarHash::Sha1(sha1.to_string())),
+    })
 }
 
 pub async fn get_fabric_jar_url(
@@ -109,6 +195,23 @@ pub async fn get_fabric_jar_url(
     fabric_loader_version: &Option<FabricLoaderVersion>,
     fabric_installer_version: &Option<FabricInstallerVersion>,
 ) -> Option<(String, Flavour)> {
+    get_fabric_jar(
+        version,
+        fabric_loader_version,
+        fabric_installer_version,
+        None,
+    )
+    .await
+    .map(|jar| (jar.url, jar.flavour))
+}
+
+// fabric doesn't publish hashes of the server launchers it builds
+pub async fn get_fabric_jar(
+    version: &str,
+    fabric_loader_version: &Option<FabricLoaderVersion>,
+    fabric_installer_version: &Option<FabricInstallerVersion>,
+    mirror: Option<&str>,
+) -> Option<ServerJar> {
     let mut loader_version = String::new();
     let mut installer_version = String::new();
     let client = reqwest::Client::new();
@@ -118,24 +221,28 @@ pub async fn get_fabric_jar_url(
     {
         loader_version = l.to_string();
         installer_version = i.to_string();
-        return Some((
-            format!(
-                "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
-                version, loader_version, installer_version
+        return Some(ServerJar {
+            url: mirror_url(
+                &format!(
+                    "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
+                    version, loader_version, installer_version
+                ),
+                mirror,
             ),
-            Flavour::Fabric {
+            flavour: Flavour::Fabric {
                 loader_version: Some(FabricLoaderVersion(loader_version)),
                 installer_version: Some(FabricInstallerVersion(installer_version)),
             },
-        ));
+            hash: None,
+        });
     }
 
     if fabric_loader_version.is_none() {
         loader_version = serde_json::Value::from_str(
             client
-                .get(format!(
-                    "https://meta.fabricmc.net/v2/versions/loader/{}",
-                    version
+                .get(mirror_url(
+                    &format!("https://meta.fabricmc.net/v2/versions/loader/{}", version),
+                    mirror,
                 ))
                 .send()
                 .await
@@ -199,7 +306,10 @@ pub async fn get_fabric_jar_url(
     if fabric_installer_version.is_none() {
         installer_version = serde_json::Value::from_str(
             client
-                .get("https://meta.fabricmc.net/v2/versions/installer")
+                .get(mirror_url(
+                    "https://meta.fabricmc.net/v2/versions/installer",
+                    mirror,
+                ))
                 .send()
                 .await
                 .ok()?
@@ -245,28 +355,45 @@ pub async fn get_fabric_jar_url(
         .as_str()?
         .to_string();
     }
-    Some((
-        format!(
-            "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
-            version, loader_version, installer_version
+    Some(ServerJar {
+        url: mirror_url(
+            &format!(
+                "https://meta.fabricmc.net/v2/versions/loader/{}/{}/{}/server/jar",
+                version, loader_version, installer_version
+            ),
+            mirror,
         ),
-        Flavour::Fabric {
+        flavour: Flavour::Fabric {
             loader_version: Some(FabricLoaderVersion(loader_version)),
             installer_version: Some(FabricInstallerVersion(installer_version)),
         },
-    ))
+        hash: None,
+    })
 }
 
 pub async fn get_paper_jar_url(
     version: &str,
     paper_build_version: &Option<PaperBuildVersion>,
 ) -> Option<(String, Flavour)> {
+    get_paper_jar(version, paper_build_version, None)
+        .await
+        .map(|jar| (jar.url, jar.flavour))
+}
+
+pub async fn get_paper_jar(
+    version: &str,
+    paper_build_version: &Option<PaperBuildVersion>,
+    mirror: Option<&str>,
+) -> Option<ServerJar> {
     let client = reqwest::Client::new();
 
     let builds_text = client
-        .get(format!(
-            "https://api.papermc.io/v2/projects/paper/versions/{}/builds/",
-            version
+        .get(mirror_url(
+            &format!(
+                "https://api.papermc.io/v2/projects/paper/versions/{}/builds/",
+                version
+            ),
+            mirror,
         ))
         .send()
         .await
@@ -297,33 +424,51 @@ pub async fn get_paper_jar_url(
             })?
     };
     let build_version = build.get("build")?.as_i64()?;
+    let application = build.get("downloads")?.get("application")?;
 
-    Some((
-        format!(
-            "https://api.papermc.io/v2/projects/paper/versions/{}/builds/{}/downloads/{}",
-            version,
-            build_version,
-            build
-                .get("downloads")?
-                .get("application")?
-                .get("name")?
-                .as_str()?,
+    Some(ServerJar {
+        url: mirror_url(
+            &format!(
+                "https://api.papermc.io/v2/projects/paper/versions/{}/builds/{}/downloads/{}",
+                version,
+                build_version,
+                application.get("name")?.as_str()?,
+            ),
+            mirror,
         ),
-        Flavour::Paper {
+        flavour: Flavour::Paper {
             build_version: Some(PaperBuildVersion(build_version)),
         },
-    ))
+        hash: application
+            .get("sha256")
+            .and_then(|sha256| sha256.as_str())
+            .map(|sha256| JarHash::Sha256(sha256.to_string())),
+    })
 }
 
 pub async fn get_forge_jar_url(
     version: &str,
     forge_build_version: &Option<ForgeBuildVersion>,
 ) -> Result<(String, Flavour), Error> {
+    get_forge_jar(version, forge_build_version, None)
+        .await
+        .map(|jar| (jar.url, jar.flavour))
+}
+
+// the installer is checked by the hash forge's maven publishes next to it
+pub async fn get_forge_jar(
+    version: &str,
+    forge_build_version: &Option<ForgeBuildVersion>,
+    mirror: Option<&str>,
+) -> Result<ServerJar, Error> {
     let client = reqwest::Client::new();
 
     let response: BTreeMap<String, Vec<String>> = serde_json::from_str(
         client
-            .get("https://files.minecraftforge.net/net/minecraftforge/forge/maven-metadata.json")
+            .get(mirror_url(
+                "https://files.minecraftforge.net/net/minecraftforge/forge/maven-metadata.json",
+                mirror,
+            ))
             .send()
             .await
             .context("Failed to get forge versions, http request failed")?
@@ -344,15 +489,81 @@ pub async fn get_forge_jar_url(
             .context("Failed to get forge versions, no builds found")?
     };
 
-    Ok((
-        format!(
+    let url = mirror_url(
+        &format!(
             "https://maven.minecraftforge.net/net/minecraftforge/forge/{}/forge-{}-installer.jar",
             build, build
         ),
-        Flavour::Forge {
+        mirror,
+    );
+    let hash = match client.get(format!("{url}.sha1")).send().await {
+        Ok(response) if response.status().is_success() => response
+            .text()
+            .await
+            .ok()
+            .and_then(|sha1| sha1.split_whitespace().next().map(str::to_string))
+            .map(JarHash::Sha1),
+        _ => None,
+    };
+
+    Ok(ServerJar {
+        url,
+        flavour: Flavour::Forge {
             build_version: Some(ForgeBuildVersion(build.to_string())),
         },
-    ))
+        hash,
+    })
+}
+
+/// Check a downloaded jar against the hash published by its source, the jar is removed if it
+/// doesn't match so a corrupted or tampered jar is never started
+pub async fn verify_jar_hash(path: &Path, expected: &JarHash) -> Result<(), Error> {
+    let file = path.to_owned();
+    let is_sha1 = matches!(expected, JarHash::Sha1(_));
+    let actual = tokio::task::spawn_blocking(move || -> Result<String, Error> {
+        let mut file = std::fs::File::open(&file)
+            .context(format!("Failed to open {} to hash it", file.display()))?;
+        Ok(if is_sha1 {
+            let mut hasher = sha1::Sha1::new();
+            std::io::copy(&mut file, &mut hasher).context("Failed to hash the jar")?;
+            hex::encode(hasher.finalize())
+        } else {
+            let mut hasher = sha2::Sha256::new();
+            std::io::copy(&mut file, &mut hasher).context("Failed to hash the jar")?;
+            hex::encode(hasher.finalize())
+        })
+    })
+    .await
+    .context("Failed to hash the jar")??;
+    let (algorithm, expected) = match expected {
+        JarHash::Sha1(expected) => ("sha1", expected),
+        JarHash::Sha256(expected) => ("sha256", expected),
+    };
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        let _ = tokio::fs::remove_file(path).await;
+        return Err(Error {
+            kind: ErrorKind::External,
+            source: eyre!(
+                "Downloaded jar {} does not match the published {algorithm} {expected}, got {actual}",
+                path.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Download a server jar into `path`, verified against its hash when the source publishes one
+pub async fn download_server_jar(
+    jar: &ServerJar,
+    path: &Path,
+    name: &str,
+    on_download: &(dyn Fn(DownloadProgress) + Send + Sync),
+) -> Result<PathBuf, Error> {
+    let downloaded = download_file(&jar.url, path, Some(name), on_download, true).await?;
+    if let Some(hash) = &jar.hash {
+        verify_jar_hash(&downloaded, hash).await?;
+    }
+    Ok(downloaded)
 }
 
 pub async fn get_jre_url(version: &str) -> Option<(String, u64)> {
@@ -440,10 +651,206 @@ pub async fn name_to_uuid(name: impl AsRef<str>) -> Option<String> {
     Some(res["id"].as_str()?.to_owned())
 }
 
+/// Resolve the custom server jar of an instance, the path must be relative to the instance root
+/// and point to an existing file
+pub fn resolve_custom_jar_path(
+    path_to_instance: &Path,
+    custom_jar_path: &str,
+) -> Result<PathBuf, Error> {
+    if !Path::new(custom_jar_path)
+        .components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "Custom server jar path {} must be relative to the instance root",
+                custom_jar_path
+            ),
+        });
+    }
+    let jar_path = scoped_join_win_safe(path_to_instance, custom_jar_path)?;
+    if !jar_path.is_file() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Custom server jar {} does not exist", custom_jar_path),
+        });
+    }
+    Ok(jar_path)
+}
+
+/// Check a stop command before it is saved, it is written to the console as a single line
+pub fn validate_stop_command(stop_command: &str) -> Result<String, Error> {
+    let stop_command = stop_command.trim();
+    if stop_command.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Stop command cannot be empty"),
+        });
+    }
+    if stop_command.contains(['\n', '\r']) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Stop command must be a single line"),
+        });
+    }
+    Ok(stop_command.to_string())
+}
+
+/// Ask the server to shut down gracefully through its console
+pub async fn send_stop_command(
+    stdin: &mut (impl AsyncWrite + Unpin),
+    stop_command: &str,
+) -> Result<(), Error> {
+    stdin
+        .write_all(format!("{stop_command}\n").as_bytes())
+        .await
+        .context("Failed to write to stdin")?;
+    stdin.flush().await.context("Failed to write to stdin")?;
+    Ok(())
+}
+
+/// `level-type` values understood by vanilla servers, old and new versions alike
+pub const LEVEL_TYPES: [&str; 4] = ["default", "flat", "largebiomes", "amplified"];
+
+/// Check the world generator settings chosen at creation before anything is written
+pub fn validate_world_generation(
+    level_type: Option<&str>,
+    generator_settings: Option<&str>,
+) -> Result<(), Error> {
+    if let Some(level_type) = level_type {
+        if !LEVEL_TYPES.contains(&level_type) {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Unknown level type {}, expected one of {}",
+                    level_type,
+                    LEVEL_TYPES.join(", ")
+                ),
+            });
+        }
+    }
+    if generator_settings.map_or(false, |settings| settings.contains(['\n', '\r'])) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Generator settings must be on a single line"),
+        });
+    }
+    Ok(())
+}
+
+/// `server.properties` written when the instance is created, the server fills in the rest
+/// on its first start
+pub fn initial_server_properties(
+    port: u32,
+    level_type: Option<&str>,
+    generator_settings: Option<&str>,
+) -> String {
+    let mut properties = format!("server-port={}", port);
+    if let Some(level_type) = level_type {
+        properties.push_str(&format!("\nlevel-type={}", level_type));
+    }
+    if let Some(generator_settings) = generator_settings {
+        properties.push_str(&format!("\ngenerator-settings={}", generator_settings));
+    }
+    properties
+}
+
+/// Set the given properties in the content of a server.properties file, `None` removes the
+/// property. Other lines are kept as is. Returns the new content and the previous values
+pub fn replace_properties(
+    content: &str,
+    updates: &[(&str, Option<String>)],
+) -> (String, Vec<(String, Option<String>)>) {
+    let mut previous: Vec<(String, Option<String>)> = updates
+        .iter()
+        .map(|(key, _)| (key.to_string(), None))
+        .collect();
+    let mut replaced = vec![false; updates.len()];
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let update = line
+            .split_once('=')
+            .filter(|_| !line.starts_with('#'))
+            .and_then(|(key, value)| {
+                let i = updates.iter().position(|(k, _)| *k == key.trim())?;
+                Some((i, value))
+            });
+        let Some((i, value)) = update else {
+            lines.push(line.to_string());
+            continue;
+        };
+        previous[i].1 = Some(value.to_string());
+        if let (Some(new_value), false) = (&updates[i].1, replaced[i]) {
+            lines.push(format!("{}={}", updates[i].0, new_value));
+        }
+        replaced[i] = true;
+    }
+    for ((key, value), replaced) in updates.iter().zip(replaced) {
+        if let (Some(value), false) = (value, replaced) {
+            lines.push(format!("{key}={value}"));
+        }
+    }
+    let mut patched = lines.join("\n");
+    if content.ends_with('\n') {
+        patched.push('\n');
+    }
+    (patched, previous)
+}
+
+/// Apply a set of changes to the content of a server.properties file, `None` deletes the key.
+/// Known keys must parse to their type, nothing is changed if any of them doesn't
+pub fn apply_properties_patch(
+    content: &str,
+    patch: &IndexMap<String, Option<String>>,
+) -> Result<String, Error> {
+    for (key, value) in patch {
+        if key.is_empty()
+            || key.trim() != key
+            || key.starts_with('#')
+            || key.contains(['=', '\n', '\r'])
+        {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Invalid property key {:?}", key),
+            });
+        }
+        // the port is tracked in the instance's config as well
+        if key == "server-port" {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("server-port cannot be patched, change the instance's port instead"),
+            });
+        }
+        if let Some(value) = value {
+            if value.contains(['\n', '\r']) {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("Value of {} must be on a single line", key),
+                });
+            }
+            ServerPropertySetting::from_key_val(key, value).map_err(|e| Error {
+                kind: ErrorKind::BadRequest,
+                source: e.source,
+            })?;
+        }
+    }
+    let updates: Vec<(&str, Option<String>)> = patch
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.clone()))
+        .collect();
+    Ok(replace_properties(content, &updates).0)
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::error::ErrorKind;
     use crate::minecraft::{
-        util::{get_forge_jar_url, get_server_jar_url},
+        util::{
+            apply_properties_patch, get_forge_jar_url, get_server_jar_url,
+            initial_server_properties, read_properties_from_path, resolve_custom_jar_path,
+            send_stop_command, validate_stop_command, validate_world_generation,
+        },
         FabricInstallerVersion, FabricLoaderVersion, Flavour, ForgeBuildVersion, PaperBuildVersion,
     };
     use tokio;
@@ -513,6 +920,94 @@ mod tests {
         assert_eq!(super::get_paper_jar_url("1.19.3bruh", &None).await, None);
     }
 
+    #[tokio::test]
+    async fn test_server_jar_is_fetched_from_mirror_and_verified() {
+        use crate::global_settings::JarMirrors;
+        use crate::minecraft::util::{download_server_jar, get_server_jar, mirror_url, JarHash};
+        use axum::{routing::get, Json, Router};
+        use sha2::Digest;
+
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        crate::prelude::init_paths(temp_lodestone_path.path().to_path_buf());
+
+        const JAR: &[u8] = b"server jar served by the mirror";
+        let sha1 = hex::encode(sha1::Sha1::digest(JAR));
+        let manifest = serde_json::json!({ "versions": [
+            { "id": "1.19.4", "url": "https://piston-meta.mojang.com/v1/packages/1a2b/1.19.4.json" },
+            { "id": "1.19.3", "url": "https://piston-meta.mojang.com/v1/packages/3c4d/1.19.3.json" },
+        ]});
+        let intact = serde_json::json!({ "downloads": { "server": {
+            "url": "https://piston-data.mojang.com/v1/objects/1a2b/server.jar",
+            "sha1": sha1,
+        }}});
+        let tampered = serde_json::json!({ "downloads": { "server": {
+            "url": "https://piston-data.mojang.com/v1/objects/3c4d/server.jar",
+            "sha1": "0000000000000000000000000000000000000000",
+        }}});
+        let mock_mirror = Router::new()
+            .route(
+                "/mc/game/version_manifest.json",
+                get(move || async move { Json(manifest) }),
+            )
+            .route(
+                "/v1/packages/1a2b/1.19.4.json",
+                get(move || async move { Json(intact) }),
+            )
+            .route(
+                "/v1/packages/3c4d/1.19.3.json",
+                get(move || async move { Json(tampered) }),
+            )
+            .route("/v1/objects/1a2b/server.jar", get(|| async { JAR }))
+            .route("/v1/objects/3c4d/server.jar", get(|| async { JAR }));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mirror = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(mock_mirror.into_make_service()),
+        );
+        let mirrors = JarMirrors {
+            vanilla: Some(mirror.clone()),
+            ..Default::default()
+        };
+
+        let server_jar = get_server_jar("1.19.4", &Flavour::Vanilla, &mirrors)
+            .await
+            .unwrap();
+        assert_eq!(
+            server_jar.url,
+            format!("{mirror}v1/objects/1a2b/server.jar")
+        );
+        assert_eq!(server_jar.hash, Some(JarHash::Sha1(sha1)));
+        let instance = tempfile::tempdir().unwrap();
+        let downloaded = download_server_jar(&server_jar, instance.path(), "server.jar", &|_| {})
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(downloaded).unwrap(), JAR);
+
+        let server_jar = get_server_jar("1.19.3", &Flavour::Vanilla, &mirrors)
+            .await
+            .unwrap();
+        let instance = tempfile::tempdir().unwrap();
+        let err = download_server_jar(&server_jar, instance.path(), "server.jar", &|_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::External));
+        assert!(!instance.path().join("server.jar").exists());
+
+        assert_eq!(
+            mirror_url(
+                "https://api.papermc.io/v2/projects/paper/versions/1.19/builds/",
+                Some("https://mirror.example.com/papermc")
+            ),
+            "https://mirror.example.com/papermc/v2/projects/paper/versions/1.19/builds/"
+        );
+        assert_eq!(
+            mirror_url("https://meta.fabricmc.net/v2/versions/installer", None),
+            "https://meta.fabricmc.net/v2/versions/installer"
+        );
+    }
+
     #[tokio::test]
     async fn test_get_forge_jar_url() {
         get_forge_jar_url("1.18.2", &None).await.unwrap();
@@ -545,4 +1040,122 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn test_resolve_custom_jar_path() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("jars")).unwrap();
+        std::fs::write(root.join("jars/patched-server.jar"), "jar").unwrap();
+
+        let jar = resolve_custom_jar_path(root, "jars/patched-server.jar").unwrap();
+        assert!(jar.starts_with(root));
+        assert_eq!(std::fs::read_to_string(jar).unwrap(), "jar");
+
+        let missing = resolve_custom_jar_path(root, "jars/missing.jar").unwrap_err();
+        assert!(matches!(missing.kind, ErrorKind::BadRequest));
+        // a directory is not a jar
+        assert!(resolve_custom_jar_path(root, "jars").is_err());
+        assert!(resolve_custom_jar_path(root, "../jars/patched-server.jar").is_err());
+        assert!(resolve_custom_jar_path(root, "/etc/passwd").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_configured_stop_command_is_sent() {
+        // configs saved before the field existed stop with `stop`
+        let config: crate::minecraft::RestoreConfig = serde_json::from_value(serde_json::json!({
+            "name": "proxy",
+            "version": "1.20.1",
+            "flavour": "vanilla",
+            "description": "",
+            "cmd_args": [],
+            "java_cmd": null,
+            "port": 25577,
+            "min_ram": 512,
+            "max_ram": 1024,
+            "auto_start": false,
+            "restart_on_crash": false,
+            "backup_period": null,
+            "jre_major_version": 17,
+            "has_started": false
+        }))
+        .unwrap();
+        assert_eq!(config.stop_command, "stop");
+
+        let mut stdin = Vec::new();
+        send_stop_command(&mut stdin, &validate_stop_command(" end ").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(stdin, b"end\n");
+
+        for invalid in ["", "   ", "end\nop Steve"] {
+            let err = validate_stop_command(invalid).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest), "{invalid:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_initial_server_properties_with_generator_settings() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("server.properties");
+        let generator_settings = r#"{"layers":[{"block":"minecraft:bedrock","height":1},{"block":"minecraft:grass_block","height":1}],"biome":"minecraft:plains"}"#;
+        validate_world_generation(Some("flat"), Some(generator_settings)).unwrap();
+        std::fs::write(
+            &path,
+            initial_server_properties(25565, Some("flat"), Some(generator_settings)),
+        )
+        .unwrap();
+
+        let properties = read_properties_from_path(&path).await.unwrap();
+        assert_eq!(properties.get("server-port").unwrap(), "25565");
+        assert_eq!(properties.get("level-type").unwrap(), "flat");
+        assert_eq!(
+            properties.get("generator-settings").unwrap(),
+            generator_settings
+        );
+
+        // nothing chosen, the server picks its defaults
+        assert_eq!(
+            initial_server_properties(25565, None, None),
+            "server-port=25565"
+        );
+    }
+
+    #[test]
+    fn test_validate_world_generation() {
+        assert!(validate_world_generation(None, None).is_ok());
+        assert!(validate_world_generation(Some("amplified"), None).is_ok());
+        let err = validate_world_generation(Some("superflat"), None).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(validate_world_generation(Some("flat"), Some("{}\nmotd=hacked")).is_err());
+    }
+
+    #[test]
+    fn test_apply_properties_patch() {
+        let properties = "#Minecraft server properties\nserver-port=25565\nmotd=A Minecraft Server\npvp=true\nmax-players=20\n";
+        let patch = indexmap::indexmap! {
+            "motd".to_string() => Some("Patched".to_string()),
+            "max-players".to_string() => Some("50".to_string()),
+            "pvp".to_string() => None,
+            "difficulty".to_string() => Some("hard".to_string()),
+        };
+        assert_eq!(
+            apply_properties_patch(properties, &patch).unwrap(),
+            "#Minecraft server properties\nserver-port=25565\nmotd=Patched\nmax-players=50\ndifficulty=hard\n"
+        );
+
+        // one bad value rejects the whole patch
+        let patch = indexmap::indexmap! {
+            "motd".to_string() => Some("Patched".to_string()),
+            "max-players".to_string() => Some("lots".to_string()),
+        };
+        assert!(matches!(
+            apply_properties_patch(properties, &patch).unwrap_err().kind,
+            ErrorKind::BadRequest
+        ));
+        let patch = indexmap::indexmap! { "server-port".to_string() => Some("25566".to_string()) };
+        assert!(apply_properties_patch(properties, &patch).is_err());
+        let patch = indexmap::indexmap! { "motd".to_string() => Some("a\nb=c".to_string()) };
+        assert!(apply_properties_patch(properties, &patch).is_err());
+    }
 }
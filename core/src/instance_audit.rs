@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::sidecar::{read_sidecar, write_sidecar};
+use crate::{auth::user_id::UserId, error::Error, events::CausedBy};
+
+/// Kept apart from the game's own config
+pub const INSTANCE_AUDIT_FILE_NAME: &str = ".lodestone_audit.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct InstanceActor {
+    pub user_id: UserId,
+    pub user_name: String,
+}
+
+impl InstanceActor {
+    /// Only users are recorded, changes made by macros or the system are not attributed
+    pub fn from_caused_by(caused_by: &CausedBy) -> Option<Self> {
+        match caused_by {
+            CausedBy::User { user_id, user_name } => Some(Self {
+                user_id: user_id.clone(),
+                user_name: user_name.clone(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Who created an instance and who last changed its config or state
+#[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq, Eq, Default)]
+#[ts(export)]
+pub struct InstanceAudit {
+    #[serde(default)]
+    pub created_by: Option<InstanceActor>,
+    #[serde(default)]
+    pub last_modified_by: Option<InstanceActor>,
+    /// unix timestamp in seconds
+    #[serde(default)]
+    pub last_modified_at: Option<i64>,
+}
+
+/// Missing or unreadable audit files read as empty, instances created before it existed have none
+pub async fn read_instance_audit(path_to_instance: &Path) -> InstanceAudit {
+    read_sidecar(path_to_instance, INSTANCE_AUDIT_FILE_NAME, "audit file")
+        .await
+        .unwrap_or_default()
+}
+
+async fn write_instance_audit(path_to_instance: &Path, audit: &InstanceAudit) -> Result<(), Error> {
+    write_sidecar(
+        path_to_instance,
+        INSTANCE_AUDIT_FILE_NAME,
+        "instance audit",
+        audit,
+    )
+    .await
+}
+
+pub async fn record_instance_creation(
+    path_to_instance: &Path,
+    caused_by: &CausedBy,
+) -> Result<(), Error> {
+    let Some(actor) = InstanceActor::from_caused_by(caused_by) else {
+        return Ok(());
+    };
+    write_instance_audit(
+        path_to_instance,
+        &InstanceAudit {
+            created_by: Some(actor.clone()),
+            last_modified_by: Some(actor),
+            last_modified_at: Some(chrono::Utc::now().timestamp()),
+        },
+    )
+    .await
+}
+
+pub async fn record_instance_modification(
+    path_to_instance: &Path,
+    caused_by: &CausedBy,
+) -> Result<(), Error> {
+    let Some(actor) = InstanceActor::from_caused_by(caused_by) else {
+        return Ok(());
+    };
+    let mut audit = read_instance_audit(path_to_instance).await;
+    audit.last_modified_by = Some(actor);
+    audit.last_modified_at = Some(chrono::Utc::now().timestamp());
+    write_instance_audit(path_to_instance, &audit).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(name: &str) -> CausedBy {
+        CausedBy::User {
+            user_id: UserId::from(format!("USER_{name}")),
+            user_name: name.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_creation_and_modification_are_recorded() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(read_instance_audit(path).await, InstanceAudit::default());
+
+        record_instance_creation(path, &user("alice"))
+            .await
+            .unwrap();
+        let audit = read_instance_audit(path).await;
+        let alice = InstanceActor::from_caused_by(&user("alice"));
+        assert_eq!(audit.created_by, alice);
+        assert_eq!(audit.last_modified_by, alice);
+        let created_at = audit.last_modified_at.unwrap();
+
+        record_instance_modification(path, &user("bob"))
+            .await
+            .unwrap();
+        let audit = read_instance_audit(path).await;
+        assert_eq!(audit.created_by, alice);
+        assert_eq!(
+            audit.last_modified_by,
+            InstanceActor::from_caused_by(&user("bob"))
+        );
+        assert!(audit.last_modified_at.unwrap() >= created_at);
+
+        // changes not made by a user keep the last user
+        record_instance_modification(path, &CausedBy::System)
+            .await
+            .unwrap();
+        assert_eq!(
+            read_instance_audit(path)
+                .await
+                .last_modified_by
+                .unwrap()
+                .user_name,
+            "bob"
+        );
+    }
+}
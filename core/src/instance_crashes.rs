@@ -0,0 +1,92 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::Error;
+use crate::sidecar::{read_sidecar, write_sidecar};
+use crate::traits::t_server::State;
+
+/// Absent until the instance first crashes
+pub const INSTANCE_CRASHES_FILE_NAME: &str = ".lodestone_crashes.json";
+
+/// How many times the server crashed since the counter was last reset
+#[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq, Eq, Default)]
+#[ts(export)]
+pub struct InstanceCrashes {
+    #[serde(default)]
+    pub crash_count: u32,
+    /// unix timestamp in seconds
+    #[serde(default)]
+    pub last_crash_at: Option<i64>,
+}
+
+/// A server process exiting is a crash if the server had started and no stop was asked for,
+/// stopping and killing move the instance out of `Running` first
+pub fn is_crash(did_start: bool, state_at_exit: State) -> bool {
+    did_start && state_at_exit == State::Running
+}
+
+pub async fn read_instance_crashes(path_to_instance: &Path) -> InstanceCrashes {
+    read_sidecar(
+        path_to_instance,
+        INSTANCE_CRASHES_FILE_NAME,
+        "crash counter",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn record_instance_crash(path_to_instance: &Path) -> Result<InstanceCrashes, Error> {
+    let mut crashes = read_instance_crashes(path_to_instance).await;
+    crashes.crash_count += 1;
+    crashes.last_crash_at = Some(chrono::Utc::now().timestamp());
+    write_sidecar(
+        path_to_instance,
+        INSTANCE_CRASHES_FILE_NAME,
+        "crash counter",
+        &crashes,
+    )
+    .await?;
+    Ok(crashes)
+}
+
+pub async fn reset_instance_crashes(path_to_instance: &Path) -> Result<(), Error> {
+    crate::util::fs::remove_file(path_to_instance.join(INSTANCE_CRASHES_FILE_NAME)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_crashes_are_counted_until_reset() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(
+            read_instance_crashes(path).await,
+            InstanceCrashes::default()
+        );
+
+        // the server exiting on its own while running, then once more after being restarted
+        assert!(is_crash(true, State::Running));
+        let first = record_instance_crash(path).await.unwrap();
+        assert_eq!(first.crash_count, 1);
+        let second = record_instance_crash(path).await.unwrap();
+        assert_eq!(second.crash_count, 2);
+        assert!(second.last_crash_at.unwrap() >= first.last_crash_at.unwrap());
+        assert_eq!(read_instance_crashes(path).await, second);
+
+        // stopped, killed or failing to start
+        assert!(!is_crash(true, State::Stopping));
+        assert!(!is_crash(false, State::Starting));
+
+        reset_instance_crashes(path).await.unwrap();
+        assert!(!path.join(INSTANCE_CRASHES_FILE_NAME).exists());
+        assert_eq!(
+            read_instance_crashes(path).await,
+            InstanceCrashes::default()
+        );
+        reset_instance_crashes(path).await.unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use color_eyre::eyre::eyre;
+use dashmap::DashMap;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use crate::error::{Error, ErrorKind};
+use crate::event_broadcaster::EventBroadcaster;
+use crate::events::{EventInner, InstanceEvent, InstanceEventInner};
+use crate::prelude::GameInstance;
+use crate::traits::t_configurable::TConfigurable;
+use crate::traits::t_server::{State, TServer};
+use crate::types::InstanceUuid;
+
+/// How long a start waits for the instances it depends on to be running, in total
+pub const DEPENDENCY_START_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// An instance that must be running before another one starts
+pub struct Dependency<I> {
+    pub uuid: InstanceUuid,
+    pub name: String,
+    pub instance: I,
+}
+
+/// The dependencies of every instance, keyed by instance
+pub async fn dependency_graph(
+    instances: &DashMap<InstanceUuid, GameInstance>,
+) -> HashMap<InstanceUuid, Vec<InstanceUuid>> {
+    // cloned out of the map so it isn't locked while the configs are read
+    let instances: Vec<(InstanceUuid, GameInstance)> = instances
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+    let mut graph = HashMap::new();
+    for (uuid, instance) in instances {
+        graph.insert(uuid, instance.depends_on().await);
+    }
+    graph
+}
+
+/// The chain of dependencies leading back to `uuid` if it depended on `depends_on`,
+/// e.g. `[proxy, lobby, proxy]`
+fn find_dependency_cycle(
+    graph: &HashMap<InstanceUuid, Vec<InstanceUuid>>,
+    uuid: &InstanceUuid,
+    depends_on: &[InstanceUuid],
+) -> Option<Vec<InstanceUuid>> {
+    fn reaches(
+        graph: &HashMap<InstanceUuid, Vec<InstanceUuid>>,
+        current: &InstanceUuid,
+        target: &InstanceUuid,
+        chain: &mut Vec<InstanceUuid>,
+        visited: &mut HashSet<InstanceUuid>,
+    ) -> bool {
+        if current == target {
+            return true;
+        }
+        if !visited.insert(current.clone()) {
+            return false;
+        }
+        chain.push(current.clone());
+        for next in graph.get(current).into_iter().flatten() {
+            if reaches(graph, next, target, chain, visited) {
+                return true;
+            }
+        }
+        chain.pop();
+        false
+    }
+
+    let mut visited = HashSet::new();
+    for dependency in depends_on {
+        let mut chain = vec![uuid.clone()];
+        if reaches(graph, dependency, uuid, &mut chain, &mut visited) {
+            chain.push(uuid.clone());
+            return Some(chain);
+        }
+    }
+    None
+}
+
+/// Check `uuid` can depend on `depends_on`, every dependency must exist and none can lead back
+/// to `uuid`
+pub fn validate_dependencies(
+    graph: &HashMap<InstanceUuid, Vec<InstanceUuid>>,
+    uuid: &InstanceUuid,
+    depends_on: &[InstanceUuid],
+) -> Result<(), Error> {
+    if let Some(unknown) = depends_on
+        .iter()
+        .find(|dependency| !graph.contains_key(dependency))
+    {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Instance {unknown} not found"),
+        });
+    }
+    if let Some(cycle) = find_dependency_cycle(graph, uuid, depends_on) {
+        let cycle: Vec<&str> = cycle.iter().map(|uuid| uuid.as_ref()).collect();
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Dependency cycle: {}", cycle.join(" -> ")),
+        });
+    }
+    Ok(())
+}
+
+/// Drop a deleted instance from what the remaining instances depend on, so their starts don't
+/// wait on an instance that no longer exists
+pub async fn forget_dependency(
+    instances: &DashMap<InstanceUuid, GameInstance>,
+    deleted: &InstanceUuid,
+) {
+    let instances: Vec<GameInstance> = instances
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    for instance in instances {
+        let depends_on = instance.depends_on().await;
+        if !depends_on.contains(deleted) {
+            continue;
+        }
+        let depends_on = depends_on
+            .into_iter()
+            .filter(|dependency| dependency != deleted)
+            .collect();
+        if let Err(e) = instance.set_depends_on(depends_on).await {
+            warn!(
+                "Failed to remove deleted instance {deleted} from the dependencies of {}: {e}",
+                instance.uuid().await
+            );
+        }
+    }
+}
+
+/// Look up the instances `depends_on` names
+pub async fn resolve_dependencies(
+    instances: &DashMap<InstanceUuid, GameInstance>,
+    depends_on: Vec<InstanceUuid>,
+) -> Result<Vec<Dependency<GameInstance>>, Error> {
+    let mut dependencies = Vec::with_capacity(depends_on.len());
+    for uuid in depends_on {
+        let instance = instances
+            .get(&uuid)
+            .map(|instance| instance.value().clone())
+            .ok_or_else(|| Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("Instance {uuid} this instance depends on no longer exists"),
+            })?;
+        dependencies.push(Dependency {
+            name: instance.name().await,
+            uuid,
+            instance,
+        });
+    }
+    Ok(dependencies)
+}
+
+fn is_state_transition_of(event_inner: &EventInner, uuid: &InstanceUuid) -> bool {
+    matches!(
+        event_inner,
+        EventInner::InstanceEvent(InstanceEvent {
+            instance_uuid,
+            instance_event_inner: InstanceEventInner::StateTransition { .. },
+            ..
+        }) if instance_uuid == uuid
+    )
+}
+
+// the state is looked at again on every state transition of the instance
+async fn wait_until_running<I: TServer + Sync>(
+    uuid: &InstanceUuid,
+    instance: &I,
+    event_broadcaster: &EventBroadcaster,
+) {
+    let mut rx = event_broadcaster.subscribe();
+    while instance.state().await != State::Running {
+        loop {
+            match rx.recv().await {
+                Ok(event) if is_state_transition_of(&event.event_inner, uuid) => break,
+                Ok(_) => continue,
+                // a transition may have been missed
+                Err(RecvError::Lagged(_)) => break,
+                Err(RecvError::Closed) => return futures::future::pending().await,
+            }
+        }
+    }
+}
+
+/// Wait for every dependency to be running, giving up after `timeout`
+pub async fn wait_for_dependencies<I: TServer + Sync>(
+    dependencies: &[Dependency<I>],
+    event_broadcaster: &EventBroadcaster,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    for dependency in dependencies {
+        let running = wait_until_running(&dependency.uuid, &dependency.instance, event_broadcaster);
+        if tokio::time::timeout_at(deadline, running).await.is_err() {
+            return Err(Error {
+                kind: ErrorKind::Timeout,
+                source: eyre!(
+                    "{} wasn't running after {}s, the instance depends on it",
+                    dependency.name,
+                    timeout.as_secs()
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::events::CausedBy;
+    use crate::test_util::FakeInstance;
+
+    fn uuid(name: &str) -> InstanceUuid {
+        InstanceUuid::from(format!("INSTANCE_{name}"))
+    }
+
+    #[tokio::test]
+    async fn test_dependents_start_after_their_dependencies() {
+        let (event_broadcaster, _rx) = EventBroadcaster::new(64);
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let instance = |name: &str| FakeInstance {
+            event_broadcaster: Some(event_broadcaster.clone()),
+            started: started.clone(),
+            ..FakeInstance::new(name, State::Stopped)
+        };
+        let (proxy, lobby, survival) = (instance("proxy"), instance("lobby"), instance("survival"));
+        let dependency = |instance: &FakeInstance| Dependency {
+            uuid: instance.uuid.clone(),
+            name: instance.uuid.to_string(),
+            instance: instance.clone(),
+        };
+
+        // all auto started at once, the proxy needs both backends
+        let start_after = |instance: FakeInstance, dependencies: Vec<Dependency<FakeInstance>>| {
+            let event_broadcaster = event_broadcaster.clone();
+            tokio::spawn(async move {
+                wait_for_dependencies(&dependencies, &event_broadcaster, Duration::from_secs(5))
+                    .await?;
+                instance.start(CausedBy::System, false).await
+            })
+        };
+        let proxy_start = start_after(
+            proxy.clone(),
+            vec![dependency(&lobby), dependency(&survival)],
+        );
+        let lobby_start = start_after(lobby.clone(), vec![dependency(&survival)]);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(started.lock().unwrap().is_empty());
+        survival.start(CausedBy::System, false).await.unwrap();
+        lobby_start.await.unwrap().unwrap();
+        proxy_start.await.unwrap().unwrap();
+        assert_eq!(
+            *started.lock().unwrap(),
+            vec![uuid("survival"), uuid("lobby"), uuid("proxy")]
+        );
+
+        // a dependency that never comes up
+        let backend = instance("backend");
+        let err = wait_for_dependencies(
+            &[dependency(&backend)],
+            &event_broadcaster,
+            Duration::from_millis(100),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Timeout));
+        assert!(err.to_string().contains("INSTANCE_backend"));
+    }
+
+    #[test]
+    fn test_dependency_cycles_are_rejected() {
+        let graph = HashMap::from([
+            (uuid("proxy"), vec![uuid("lobby"), uuid("survival")]),
+            (uuid("lobby"), vec![uuid("survival")]),
+            (uuid("survival"), vec![]),
+            (uuid("creative"), vec![]),
+        ]);
+        validate_dependencies(&graph, &uuid("creative"), &[uuid("lobby")]).unwrap();
+        validate_dependencies(&graph, &uuid("proxy"), &[uuid("lobby")]).unwrap();
+
+        let err = validate_dependencies(&graph, &uuid("survival"), &[uuid("proxy")]).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        assert!(err.to_string().contains(
+            "INSTANCE_survival -> INSTANCE_proxy -> INSTANCE_lobby -> INSTANCE_survival"
+        ));
+        let err =
+            validate_dependencies(&graph, &uuid("creative"), &[uuid("creative")]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("INSTANCE_creative -> INSTANCE_creative"));
+        let err = validate_dependencies(&graph, &uuid("proxy"), &[uuid("missing")]).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, ErrorKind};
+use crate::implementations::minecraft::RestoreConfig;
+use crate::sidecar::is_sidecar_name;
+use crate::util::tree_size;
+
+/// Manifest at the root of an export. Named like the other sidecars so seeding an instance from
+/// the archive leaves it out
+pub const EXPORT_MANIFEST_FILE_NAME: &str = ".lodestone_export.json";
+/// Bumped when a manifest stops being readable by older versions
+pub const EXPORT_FORMAT_VERSION: u32 = 1;
+const MINECRAFT_CONFIG_FILE_NAME: &str = ".lodestone_minecraft_config.json";
+
+/// What it takes to recreate an exported instance on another host, next to its files
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportManifest {
+    pub format_version: u32,
+    pub exported_at: i64,
+    pub config: RestoreConfig,
+}
+
+impl ExportManifest {
+    pub fn new(config: RestoreConfig) -> Self {
+        Self {
+            format_version: EXPORT_FORMAT_VERSION,
+            exported_at: chrono::Utc::now().timestamp(),
+            config,
+        }
+    }
+
+    /// Config of the instance recreated from the export. Its dependencies, java command and
+    /// custom jar belong to the host it was exported from, and it starts out unlocked and never
+    /// started here
+    pub fn into_import_config(self) -> RestoreConfig {
+        RestoreConfig {
+            java_cmd: None,
+            custom_jar_path: None,
+            locked: false,
+            has_started: false,
+            depends_on: Vec::new(),
+            ..self.config
+        }
+    }
+}
+
+/// Config of the Minecraft instance at `root`, the only kind an export can recreate
+pub async fn read_exportable_config(root: &Path) -> Result<RestoreConfig, Error> {
+    let path = root.join(MINECRAFT_CONFIG_FILE_NAME);
+    if !tokio::fs::metadata(&path)
+        .await
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(false)
+    {
+        return Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("Only Minecraft instances can be exported"),
+        });
+    }
+    let content = tokio::fs::read(&path)
+        .await
+        .context(format!("Failed to read config at {}", path.display()))?;
+    Ok(serde_json::from_slice(&content).context(format!(
+        "Failed to deserialize config at {}",
+        path.display()
+    ))?)
+}
+
+pub async fn write_import_config(root: &Path, config: &RestoreConfig) -> Result<(), Error> {
+    let path = root.join(MINECRAFT_CONFIG_FILE_NAME);
+    tokio::fs::write(
+        &path,
+        serde_json::to_string_pretty(config).context("Failed to serialize config")?,
+    )
+    .await
+    .context(format!("Failed to write config at {}", path.display()))?;
+    Ok(())
+}
+
+/// Files and directories of the instance the export holds. The lodestone sidecars at its root
+/// are state of this host, the manifest carries what's needed of them
+fn exported_entries(root: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(root)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.depth() > 1 || !is_sidecar_name(entry.file_name()))
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Total size of the files an export of `root` holds
+pub fn export_size(root: &Path) -> u64 {
+    let exported = std::fs::read_dir(root)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| !is_sidecar_name(&entry.file_name()))
+        .map(|entry| entry.path());
+    tree_size(exported).bytes
+}
+
+/// Write the zip export of the instance at `root` to `dest`: the manifest, then the instance
+/// files relative to the instance directory. `on_progress` gets the bytes archived so far
+pub fn write_export(
+    root: &Path,
+    manifest: &ExportManifest,
+    dest: impl Write + Seek,
+    on_progress: &mut dyn FnMut(u64),
+) -> Result<(), Error> {
+    let mut writer = zip::ZipWriter::new(dest);
+    let options = zip::write::FileOptions::default().unix_permissions(0o775);
+    writer
+        .start_file(EXPORT_MANIFEST_FILE_NAME, options)
+        .context("Failed to add the manifest to the export")?;
+    writer
+        .write_all(
+            serde_json::to_string_pretty(manifest)
+                .context("Failed to serialize the manifest")?
+                .as_bytes(),
+        )
+        .context("Failed to add the manifest to the export")?;
+
+    let mut archived = 0;
+    for path in exported_entries(root) {
+        let name = path
+            .strip_prefix(root)
+            .context(format!("Failed to strip prefix for {}", path.display()))?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if path.is_dir() {
+            writer
+                .add_directory(name, options)
+                .context(format!("Failed to add {} to the export", path.display()))?;
+            continue;
+        }
+        let mut file =
+            std::fs::File::open(&path).context(format!("Failed to open {}", path.display()))?;
+        let len = file
+            .metadata()
+            .context(format!("Failed to get metadata for {}", path.display()))?
+            .len();
+        writer
+            .start_file(name, options.large_file(len > u32::MAX as u64))
+            .context(format!("Failed to add {} to the export", path.display()))?;
+        archived += std::io::copy(&mut file, &mut writer)
+            .context(format!("Failed to add {} to the export", path.display()))?;
+        on_progress(archived);
+    }
+    writer.finish().context("Failed to finish the export")?;
+    Ok(())
+}
+
+/// Manifest of the export `archive`, refused if it isn't an export or is from a newer version
+pub fn read_export_manifest(archive: impl Read + Seek) -> Result<ExportManifest, Error> {
+    let not_an_export = || Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Not a Lodestone export, the archive has no manifest"),
+    };
+    let mut archive = zip::ZipArchive::new(archive).map_err(|_| not_an_export())?;
+    let manifest = archive
+        .by_name(EXPORT_MANIFEST_FILE_NAME)
+        .map_err(|_| not_an_export())?;
+    let manifest: ExportManifest = serde_json::from_reader(manifest).map_err(|e| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Invalid export manifest: {e}"),
+    })?;
+    if manifest.format_version > EXPORT_FORMAT_VERSION {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "The export is from a newer version of Lodestone (format {}), update to import it",
+                manifest.format_version
+            ),
+        });
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::implementations::minecraft::{Flavour, MinecraftInstance};
+    use crate::instance_seed::seed_from_archive;
+    use crate::prelude::init_paths;
+    use crate::types::InstanceUuid;
+
+    fn config() -> RestoreConfig {
+        RestoreConfig {
+            name: "survival".to_string(),
+            version: "1.20.1".to_string(),
+            flavour: Flavour::Vanilla,
+            description: "Friends only".to_string(),
+            cmd_args: vec!["nogui".to_string()],
+            java_cmd: None,
+            port: 25570,
+            min_ram: 2048,
+            max_ram: 6144,
+            auto_start: true,
+            restart_on_crash: true,
+            backup_period: Some(30),
+            jre_major_version: 17,
+            has_started: true,
+            custom_jar_path: None,
+            locked: false,
+            depends_on: vec![InstanceUuid::from("INSTANCE_proxy".to_string())],
+            stop_command: "end".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_imported_config_is_checked_like_a_setup() {
+        let manifest = MinecraftInstance::setup_manifest_with_versions(vec!["1.20.1".to_string()]);
+        let config = ExportManifest::new(config()).into_import_config();
+        let errors = |config: &RestoreConfig| {
+            MinecraftInstance::setup_value_errors(
+                &manifest,
+                &MinecraftInstance::setup_value_of(config),
+            )
+        };
+        assert!(errors(&config).is_empty());
+
+        let invalid = RestoreConfig {
+            name: " ".to_string(),
+            version: "0.1".to_string(),
+            min_ram: 8192,
+            ..config
+        };
+        let fields: Vec<String> = errors(&invalid).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["name", "version", "min_ram"]);
+    }
+
+    #[tokio::test]
+    async fn test_export_is_imported_with_an_equivalent_config() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+
+        let source = tempfile::tempdir().unwrap();
+        let root = source.path();
+        write_import_config(root, &config()).await.unwrap();
+        std::fs::write(root.join(".lodestone_config"), "{}").unwrap();
+        std::fs::write(root.join("server.properties"), "server-port=25570\n").unwrap();
+        std::fs::create_dir_all(root.join("world/region")).unwrap();
+        std::fs::write(root.join("world/region/r.0.0.mca"), vec![7_u8; 8192]).unwrap();
+        std::fs::create_dir(root.join("plugins")).unwrap();
+
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive = archive_dir.path().join("survival.zip");
+        let mut progress = Vec::new();
+        let manifest = ExportManifest::new(read_exportable_config(root).await.unwrap());
+        write_export(
+            root,
+            &manifest,
+            std::fs::File::create(&archive).unwrap(),
+            &mut |archived| progress.push(archived),
+        )
+        .unwrap();
+        assert_eq!(progress.last(), Some(&export_size(root)));
+        assert_eq!(export_size(root), 8192 + 18);
+
+        // on the other host
+        let manifest = read_export_manifest(std::fs::File::open(&archive).unwrap()).unwrap();
+        let target = tempfile::tempdir().unwrap();
+        let imported = target.path();
+        let seeded = seed_from_archive(&archive, imported, &mut |_| {}).unwrap();
+        write_import_config(imported, &manifest.into_import_config())
+            .await
+            .unwrap();
+
+        assert_eq!(seeded.len(), 3);
+        assert!(!imported.join(EXPORT_MANIFEST_FILE_NAME).exists());
+        assert!(!imported.join(".lodestone_config").exists());
+        assert!(imported.join("plugins").is_dir());
+        assert_eq!(
+            std::fs::read(imported.join("world/region/r.0.0.mca")).unwrap(),
+            vec![7_u8; 8192]
+        );
+        let expected = RestoreConfig {
+            has_started: false,
+            depends_on: Vec::new(),
+            ..config()
+        };
+        assert_eq!(
+            serde_json::to_value(read_exportable_config(imported).await.unwrap()).unwrap(),
+            serde_json::to_value(expected).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_only_exports_are_imported() {
+        let temp = tempfile::tempdir().unwrap();
+        let err = read_exportable_config(temp.path()).await.unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::UnsupportedOperation));
+
+        let archive = temp.path().join("world.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        writer
+            .start_file("level.dat", zip::write::FileOptions::default())
+            .unwrap();
+        writer.finish().unwrap();
+        let err = read_export_manifest(std::fs::File::open(&archive).unwrap()).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+
+        let mut manifest = serde_json::to_value(ExportManifest::new(config())).unwrap();
+        manifest["format_version"] = (EXPORT_FORMAT_VERSION + 1).into();
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+        writer
+            .start_file(
+                EXPORT_MANIFEST_FILE_NAME,
+                zip::write::FileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(manifest.to_string().as_bytes()).unwrap();
+        writer.finish().unwrap();
+        let err = read_export_manifest(std::fs::File::open(&archive).unwrap()).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{span, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::registry::LookupSpan;
+use ts_rs::TS;
+
+use crate::types::InstanceUuid;
+
+/// Name of the span the supervisor of an instance runs in
+pub const INSTANCE_SPAN_NAME: &str = "instance";
+
+const INSTANCE_UUID_FIELD: &str = "instance_uuid";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum InstanceLogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<InstanceLogLevel> for LevelFilter {
+    fn from(level: InstanceLogLevel) -> Self {
+        match level {
+            InstanceLogLevel::Error => LevelFilter::ERROR,
+            InstanceLogLevel::Warn => LevelFilter::WARN,
+            InstanceLogLevel::Info => LevelFilter::INFO,
+            InstanceLogLevel::Debug => LevelFilter::DEBUG,
+            InstanceLogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Log level overrides of individual instances, kept in memory only
+#[derive(Clone, Default)]
+pub struct InstanceLogLevels {
+    levels: Arc<RwLock<HashMap<String, InstanceLogLevel>>>,
+}
+
+impl InstanceLogLevels {
+    pub fn get(&self, uuid: &InstanceUuid) -> Option<InstanceLogLevel> {
+        self.get_by_str(uuid.as_ref())
+    }
+
+    /// `None` resets the instance to the global level
+    pub fn set(&self, uuid: &InstanceUuid, level: Option<InstanceLogLevel>) {
+        {
+            let mut levels = self.levels.write().unwrap();
+            match level {
+                Some(level) => levels.insert(uuid.as_ref().to_owned(), level),
+                None => levels.remove(uuid.as_ref()),
+            };
+        }
+        // callsites disabled under the previous levels are asked again
+        tracing::callsite::rebuild_interest_cache();
+    }
+
+    fn get_by_str(&self, uuid: &str) -> Option<InstanceLogLevel> {
+        self.levels.read().unwrap().get(uuid).copied()
+    }
+
+    /// The most verbose level of any instance, `None` without overrides
+    fn max_level(&self) -> Option<LevelFilter> {
+        self.levels
+            .read()
+            .unwrap()
+            .values()
+            .map(|level| LevelFilter::from(*level))
+            .max()
+    }
+}
+
+static INSTANCE_LOG_LEVELS: Lazy<InstanceLogLevels> = Lazy::new(InstanceLogLevels::default);
+
+pub fn instance_log_levels() -> &'static InstanceLogLevels {
+    &INSTANCE_LOG_LEVELS
+}
+
+/// Span to run the supervisor of an instance in, so its logs follow the instance's level
+pub fn instance_span(uuid: &InstanceUuid) -> span::Span {
+    tracing::info_span!(INSTANCE_SPAN_NAME, instance_uuid = uuid.as_ref())
+}
+
+/// uuid of the instance a span belongs to, stored in the span's extensions
+struct InstanceScope(String);
+
+#[derive(Default)]
+struct InstanceUuidVisitor(Option<String>);
+
+impl Visit for InstanceUuidVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == INSTANCE_UUID_FIELD {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == INSTANCE_UUID_FIELD {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Filter of the stdout layer set up at startup
+pub fn stdout_log_filter() -> InstanceLogFilter {
+    InstanceLogFilter::new(if cfg!(debug_assertions) {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    })
+}
+
+/// Filter of the log file layer set up at startup
+pub fn file_log_filter() -> InstanceLogFilter {
+    InstanceLogFilter::new(LevelFilter::DEBUG)
+}
+
+fn is_lodestone_target(meta: &Metadata<'_>) -> bool {
+    meta.target().starts_with("lodestone_core")
+}
+
+/// Per-layer log filter.
+///
+/// Only `lodestone_core` logs pass, those inside an instance span use that instance's level if
+/// it has one, `default` otherwise
+pub struct InstanceLogFilter {
+    default: LevelFilter,
+    levels: InstanceLogLevels,
+}
+
+impl InstanceLogFilter {
+    pub fn new(default: LevelFilter) -> Self {
+        Self::with_levels(default, instance_log_levels().clone())
+    }
+
+    pub fn with_levels(default: LevelFilter, levels: InstanceLogLevels) -> Self {
+        Self { default, levels }
+    }
+
+    fn level_in_scope<S>(&self, cx: &Context<'_, S>) -> LevelFilter
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        cx.lookup_current()
+            .into_iter()
+            .flat_map(|span| span.scope())
+            .find_map(|span| {
+                span.extensions()
+                    .get::<InstanceScope>()
+                    .map(|InstanceScope(uuid)| self.levels.get_by_str(uuid))
+            })
+            .flatten()
+            .map(LevelFilter::from)
+            .unwrap_or(self.default)
+    }
+}
+
+impl<S> Filter<S> for InstanceLogFilter
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn enabled(&self, meta: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        if !is_lodestone_target(meta) {
+            return false;
+        }
+        // instance spans are always recorded, otherwise their scope can't be looked up
+        if meta.is_span() && meta.name() == INSTANCE_SPAN_NAME {
+            return true;
+        }
+        *meta.level() <= self.level_in_scope(cx)
+    }
+
+    fn callsite_enabled(&self, meta: &'static Metadata<'static>) -> Interest {
+        if !is_lodestone_target(meta) {
+            return Interest::never();
+        }
+        if meta.is_span() && meta.name() == INSTANCE_SPAN_NAME {
+            return Interest::always();
+        }
+        if *meta.level() > self.max_level_hint().unwrap_or(self.default) {
+            return Interest::never();
+        }
+        // depends on the instance the event is logged in
+        Interest::sometimes()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(
+            self.levels
+                .max_level()
+                .map_or(self.default, |level| level.max(self.default)),
+        )
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != INSTANCE_SPAN_NAME {
+            return;
+        }
+        let mut visitor = InstanceUuidVisitor::default();
+        attrs.record(&mut visitor);
+        if let (Some(uuid), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(InstanceScope(uuid));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tracing::Event;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    // records the level and instance of every event it sees
+    #[derive(Clone, Default)]
+    struct CollectLayer(Arc<Mutex<Vec<(tracing::Level, Option<String>)>>>);
+
+    impl<S> Layer<S> for CollectLayer
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_event(&self, event: &Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+            let instance = ctx.event_scope(event).and_then(|scope| {
+                scope.from_root().find_map(|span| {
+                    span.extensions()
+                        .get::<InstanceScope>()
+                        .map(|s| s.0.clone())
+                })
+            });
+            self.0
+                .lock()
+                .unwrap()
+                .push((*event.metadata().level(), instance));
+        }
+    }
+
+    #[test]
+    fn test_startup_filters_drop_other_targets() {
+        let collected = CollectLayer::default();
+        let subscriber = tracing_subscriber::registry()
+            .with(collected.clone().with_filter(stdout_log_filter()))
+            .with(CollectLayer::default().with_filter(file_log_filter()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(target: "hyper::proto", "connection reset");
+            tracing::info!(target: "sqlx::query", "select 1");
+            tracing::trace!(target: "hyper::proto", "frame read");
+            tracing::info!("lodestone_core info");
+            tracing::trace!("lodestone_core trace");
+        });
+        let levels: Vec<tracing::Level> = collected
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(level, _)| *level)
+            .collect();
+        assert_eq!(levels, vec![tracing::Level::INFO]);
+    }
+
+    #[test]
+    fn test_instance_level_does_not_leak_to_other_instances() {
+        let levels = InstanceLogLevels::default();
+        let collected = CollectLayer::default();
+        let subscriber = tracing_subscriber::registry().with(collected.clone().with_filter(
+            InstanceLogFilter::with_levels(LevelFilter::INFO, levels.clone()),
+        ));
+        let noisy = InstanceUuid::from("noisy".to_string());
+        let quiet = InstanceUuid::from("quiet".to_string());
+
+        tracing::subscriber::with_default(subscriber, || {
+            let log_in = |uuid: &InstanceUuid| {
+                instance_span(uuid).in_scope(|| {
+                    tracing::debug!("supervisor detail");
+                    tracing::info!("supervisor info");
+                })
+            };
+
+            log_in(&noisy);
+            log_in(&quiet);
+            assert_eq!(collected.0.lock().unwrap().len(), 2);
+            collected.0.lock().unwrap().clear();
+
+            levels.set(&noisy, Some(InstanceLogLevel::Debug));
+            log_in(&noisy);
+            log_in(&quiet);
+            tracing::debug!("outside of any instance");
+            let events = collected.0.lock().unwrap().clone();
+            assert_eq!(
+                events,
+                vec![
+                    (tracing::Level::DEBUG, Some("noisy".to_string())),
+                    (tracing::Level::INFO, Some("noisy".to_string())),
+                    (tracing::Level::INFO, Some("quiet".to_string())),
+                ]
+            );
+            collected.0.lock().unwrap().clear();
+
+            levels.set(&noisy, None);
+            log_in(&noisy);
+            assert_eq!(
+                collected.0.lock().unwrap().clone(),
+                vec![(tracing::Level::INFO, Some("noisy".to_string()))]
+            );
+        });
+    }
+}
@@ -0,0 +1,172 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use flate2::write::GzEncoder;
+use serde::Serialize;
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+
+/// Log written by the server, relative to the instance directory
+pub const LIVE_LOG_PATH: &str = "logs/latest.log";
+
+// bytes appended by the server while copying are caught up this many times before truncating
+const MAX_CATCH_UP_ROUNDS: usize = 8;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, TS)]
+#[ts(export)]
+pub struct RotatedLog {
+    /// archive the log was copied to, next to the live log
+    pub archive: PathBuf,
+    /// size of the log that was archived, before compression
+    pub bytes: u64,
+}
+
+// `latest-<timestamp>.log(.gz)`, suffixed when rotated more than once in the same second
+fn create_archive(dir: &Path, gzip: bool) -> Result<(PathBuf, File), Error> {
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let extension = if gzip { "log.gz" } else { "log" };
+    for attempt in 0..100 {
+        let name = match attempt {
+            0 => format!("latest-{timestamp}.{extension}"),
+            n => format!("latest-{timestamp}-{n}.{extension}"),
+        };
+        let path = dir.join(name);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .context(format!("Failed to create log archive {}", path.display()))
+                    .map_err(Into::into)
+            }
+        }
+    }
+    Err(Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Too many log archives for {timestamp}, try again later"),
+    })
+}
+
+/// Copy the live log to a timestamped archive next to it then truncate it, the way
+/// `copytruncate` does, so a server holding the log open keeps writing to the same file.
+///
+/// Lines the server writes while the log is being copied are caught up before truncating, only
+/// a write landing between the last catch up and the truncation can be lost. A server not
+/// appending in append mode resumes at its previous offset, the gap reads back as zeroes
+pub fn rotate_log(log: &Path, gzip: bool) -> Result<RotatedLog, Error> {
+    let mut live = match std::fs::OpenOptions::new().read(true).write(true).open(log) {
+        Ok(live) => live,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error {
+                kind: ErrorKind::NotFound,
+                source: eyre!("No log to rotate at {}", log.display()),
+            })
+        }
+        Err(e) => {
+            return Err(e)
+                .context(format!("Failed to open log {}", log.display()))
+                .map_err(Into::into)
+        }
+    };
+    let dir = log.parent().ok_or_else(|| Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Log {} has no parent directory", log.display()),
+    })?;
+    let (archive, archive_file) = create_archive(dir, gzip)?;
+
+    let copy = |live: &mut File, archive: &mut dyn Write| -> Result<u64, Error> {
+        let mut copied = 0;
+        for _ in 0..MAX_CATCH_UP_ROUNDS {
+            let len = live
+                .metadata()
+                .context("Failed to read log metadata")?
+                .len();
+            if len <= copied {
+                break;
+            }
+            live.seek(SeekFrom::Start(copied))
+                .context("Failed to seek in log")?;
+            copied += std::io::copy(&mut live.by_ref().take(len - copied), archive)
+                .context("Failed to copy log to archive")?;
+        }
+        Ok(copied)
+    };
+    let result = if gzip {
+        let mut encoder = GzEncoder::new(archive_file, flate2::Compression::default());
+        copy(&mut live, &mut encoder).and_then(|copied| {
+            encoder
+                .finish()
+                .and_then(|file| file.sync_all())
+                .context("Failed to write log archive")?;
+            Ok(copied)
+        })
+    } else {
+        let mut archive_file = archive_file;
+        copy(&mut live, &mut archive_file).and_then(|copied| {
+            archive_file
+                .sync_all()
+                .context("Failed to write log archive")?;
+            Ok(copied)
+        })
+    };
+    let bytes = match result {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            // the live log is left as is
+            let _ = std::fs::remove_file(&archive);
+            return Err(e);
+        }
+    };
+    live.set_len(0).context("Failed to truncate log")?;
+    Ok(RotatedLog { archive, bytes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_and_truncate_closed_log() {
+        let temp = tempfile::tempdir().unwrap();
+        let logs = temp.path().join("logs");
+        std::fs::create_dir(&logs).unwrap();
+        let log = temp.path().join(LIVE_LOG_PATH);
+        let content = "[12:00:00] [Server thread/INFO]: Done (3.2s)!\n".repeat(100);
+        std::fs::write(&log, &content).unwrap();
+
+        let rotated = rotate_log(&log, false).unwrap();
+        assert_eq!(rotated.bytes, content.len() as u64);
+        assert_eq!(rotated.archive.parent().unwrap(), logs);
+        assert_eq!(std::fs::read_to_string(&rotated.archive).unwrap(), content);
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), 0);
+
+        // rotated again, gzipped this time
+        std::fs::write(&log, "[12:00:01] [Server thread/INFO]: Stopping server\n").unwrap();
+        let gzipped = rotate_log(&log, true).unwrap();
+        assert_ne!(gzipped.archive, rotated.archive);
+        assert!(gzipped.archive.to_string_lossy().ends_with(".log.gz"));
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(File::open(&gzipped.archive).unwrap())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(
+            decoded,
+            "[12:00:01] [Server thread/INFO]: Stopping server\n"
+        );
+        assert_eq!(std::fs::metadata(&log).unwrap().len(), 0);
+        assert_eq!(std::fs::read_to_string(&rotated.archive).unwrap(), content);
+
+        std::fs::remove_file(&log).unwrap();
+        assert!(matches!(
+            rotate_log(&log, false).unwrap_err().kind,
+            ErrorKind::NotFound
+        ));
+    }
+}
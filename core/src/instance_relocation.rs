@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use color_eyre::eyre::{eyre, Context};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
+use walkdir::WalkDir;
+
+use crate::error::{Error, ErrorKind};
+use crate::types::InstanceUuid;
+use crate::util::operation_cancelled;
+
+/// Store of the instances living outside of the instances directory, e.g. relocated to another
+/// disk. They are restored from there on startup
+pub const INSTANCE_LOCATIONS_FILE_NAME: &str = "instance_locations.json";
+
+// every change is a read-modify-write of the whole store
+static INSTANCE_LOCATIONS_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// Directory of every instance kept outside of the instances directory
+pub async fn read_instance_locations(path_to_stores: &Path) -> HashMap<InstanceUuid, PathBuf> {
+    match tokio::fs::read(path_to_stores.join(INSTANCE_LOCATIONS_FILE_NAME)).await {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+            warn!("Invalid instance locations, relocated instances won't be restored: {e}");
+            HashMap::new()
+        }),
+        Err(_) => HashMap::new(),
+    }
+}
+
+/// Record where an instance lives, `None` for an instance back in the instances directory
+pub async fn set_instance_location(
+    path_to_stores: &Path,
+    uuid: &InstanceUuid,
+    location: Option<PathBuf>,
+) -> Result<(), Error> {
+    let _lock = INSTANCE_LOCATIONS_LOCK.lock().await;
+    let mut locations = read_instance_locations(path_to_stores).await;
+    match location {
+        Some(location) => locations.insert(uuid.clone(), location),
+        None => locations.remove(uuid),
+    };
+    crate::util::fs::write_all(
+        path_to_stores.join(INSTANCE_LOCATIONS_FILE_NAME),
+        serde_json::to_string_pretty(&locations)
+            .context("Failed to serialize instance locations")?,
+    )
+    .await
+}
+
+/// Instances held by a relocation or a start. A relocation holds the instance from its copy to
+/// the switch over, a start only while it gets the instance out of `Stopped`, so neither begins
+/// while the other is underway
+#[derive(Clone, Default)]
+pub struct InstanceHolds {
+    holds: Arc<std::sync::Mutex<HashMap<InstanceUuid, &'static str>>>,
+}
+
+impl InstanceHolds {
+    /// Hold the instance until the returned guard is dropped, `held_for` says what for in the
+    /// conflict the next holder gets, e.g. "relocated"
+    pub fn hold(&self, uuid: &InstanceUuid, held_for: &'static str) -> Result<InstanceHold, Error> {
+        let mut holds = self.holds.lock().unwrap();
+        if let Some(held_for) = holds.get(uuid) {
+            return Err(Error {
+                kind: ErrorKind::Conflict,
+                source: eyre!("Instance is being {held_for}, try again once it is done"),
+            });
+        }
+        holds.insert(uuid.clone(), held_for);
+        Ok(InstanceHold {
+            holds: self.clone(),
+            uuid: uuid.clone(),
+        })
+    }
+}
+
+pub struct InstanceHold {
+    holds: InstanceHolds,
+    uuid: InstanceUuid,
+}
+
+impl Drop for InstanceHold {
+    fn drop(&mut self) {
+        self.holds.holds.lock().unwrap().remove(&self.uuid);
+    }
+}
+
+/// Where the instance at `path_to_instance` goes when relocated under `new_root`, the directory
+/// keeps its name
+pub fn relocation_target(path_to_instance: &Path, new_root: &Path) -> Result<PathBuf, Error> {
+    if !new_root.is_absolute() || !new_root.is_dir() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("{} is not an existing directory", new_root.display()),
+        });
+    }
+    let dir_name = path_to_instance.file_name().ok_or_else(|| Error {
+        kind: ErrorKind::Internal,
+        source: eyre!("Instance path {} has no name", path_to_instance.display()),
+    })?;
+    let new_root = new_root
+        .canonicalize()
+        .context(format!("Failed to resolve {}", new_root.display()))?;
+    let path_to_instance_resolved = path_to_instance
+        .canonicalize()
+        .unwrap_or_else(|_| path_to_instance.to_path_buf());
+    if new_root.starts_with(&path_to_instance_resolved) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("An instance can't be relocated inside of itself"),
+        });
+    }
+    let target = new_root.join(dir_name);
+    if target.exists() {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("{} already exists", target.display()),
+        });
+    }
+    Ok(target)
+}
+
+/// Copy the directory `src` to `dest`, which must not exist yet, then check every file made it
+/// with its size. `on_progress` is called with the bytes copied so far after each file. `dest`
+/// is removed if anything fails, `src` is only read
+pub fn copy_dir_verified(
+    src: &Path,
+    dest: &Path,
+    on_progress: impl FnMut(u64, &Path),
+) -> Result<u64, Error> {
+    copy_dir_verified_cancellable(src, dest, on_progress, &CancellationToken::new())
+}
+
+/// Same as [`copy_dir_verified`], stopping before the next file once `cancel` is cancelled. The
+/// partial copy is removed like for any other failure
+pub fn copy_dir_verified_cancellable(
+    src: &Path,
+    dest: &Path,
+    mut on_progress: impl FnMut(u64, &Path),
+    cancel: &CancellationToken,
+) -> Result<u64, Error> {
+    if dest.exists() {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("{} already exists", dest.display()),
+        });
+    }
+    let copied = copy_dir(src, dest, &mut on_progress, cancel).and_then(|copied| {
+        if file_sizes(src)? != file_sizes(dest)? {
+            return Err(Error {
+                kind: ErrorKind::Internal,
+                source: eyre!("The copy at {} doesn't match the original", dest.display()),
+            });
+        }
+        Ok(copied)
+    });
+    if copied.is_err() && dest.exists() {
+        std::fs::remove_dir_all(dest)
+            .map_err(|e| warn!("Failed to remove partial copy at {}: {e}", dest.display()))
+            .ok();
+    }
+    copied
+}
+
+/// Move `src` to `dest` across filesystems, where a rename can't: copy it with
+/// [`copy_dir_verified`], or the file alone, and only remove `src` once the copy checks out.
+/// A failed or cancelled copy leaves `src` as it was and no `dest`
+pub fn move_verified(
+    src: &Path,
+    dest: &Path,
+    mut on_progress: impl FnMut(u64, &Path),
+    cancel: &CancellationToken,
+) -> Result<u64, Error> {
+    if src.is_dir() {
+        let copied = copy_dir_verified_cancellable(src, dest, on_progress, cancel)?;
+        std::fs::remove_dir_all(src).context(format!(
+            "Copied to {} but failed to remove {}, both are kept",
+            dest.display(),
+            src.display()
+        ))?;
+        return Ok(copied);
+    }
+    if cancel.is_cancelled() {
+        return Err(operation_cancelled());
+    }
+    let copied = copy_file_verified(src, dest)?;
+    on_progress(copied, Path::new(src.file_name().unwrap_or_default()));
+    std::fs::remove_file(src).context(format!(
+        "Copied to {} but failed to remove {}, both are kept",
+        dest.display(),
+        src.display()
+    ))?;
+    Ok(copied)
+}
+
+fn copy_file_verified(src: &Path, dest: &Path) -> Result<u64, Error> {
+    if dest.exists() {
+        return Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!("{} already exists", dest.display()),
+        });
+    }
+    let copied = std::fs::copy(src, dest)
+        .context(format!("Failed to copy {}", src.display()))
+        .map_err(Error::from)
+        .and_then(|copied| {
+            let expected = std::fs::metadata(src)
+                .context(format!("Failed to read {}", src.display()))?
+                .len();
+            if copied != expected {
+                return Err(Error {
+                    kind: ErrorKind::Internal,
+                    source: eyre!("The copy at {} doesn't match the original", dest.display()),
+                });
+            }
+            Ok(copied)
+        });
+    if copied.is_err() && dest.exists() {
+        std::fs::remove_file(dest)
+            .map_err(|e| warn!("Failed to remove partial copy at {}: {e}", dest.display()))
+            .ok();
+    }
+    copied
+}
+
+fn copy_dir(
+    src: &Path,
+    dest: &Path,
+    on_progress: &mut impl FnMut(u64, &Path),
+    cancel: &CancellationToken,
+) -> Result<u64, Error> {
+    let mut copied = 0;
+    for entry in WalkDir::new(src).sort_by_file_name() {
+        let entry = entry.context(format!("Failed to read {}", src.display()))?;
+        let relative = entry.path().strip_prefix(src).unwrap_or(entry.path());
+        let target = dest.join(relative);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)
+                .context(format!("Failed to create directory {}", target.display()))?;
+        } else {
+            if cancel.is_cancelled() {
+                return Err(operation_cancelled());
+            }
+            copied += std::fs::copy(entry.path(), &target)
+                .context(format!("Failed to copy {}", relative.display()))?;
+            on_progress(copied, relative);
+        }
+    }
+    Ok(copied)
+}
+
+fn file_sizes(root: &Path) -> Result<BTreeMap<PathBuf, u64>, Error> {
+    let mut sizes = BTreeMap::new();
+    for entry in WalkDir::new(root) {
+        let entry = entry.context(format!("Failed to read {}", root.display()))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        let size = std::fs::metadata(entry.path())
+            .context(format!("Failed to read {}", entry.path().display()))?
+            .len();
+        sizes.insert(
+            entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or(entry.path())
+                .to_path_buf(),
+            size,
+        );
+    }
+    Ok(sizes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance_dir(root: &Path) -> PathBuf {
+        let path = root.join("survival-1a2b3c4d");
+        std::fs::create_dir_all(path.join("world/region")).unwrap();
+        std::fs::write(path.join(".lodestone_config"), "{}").unwrap();
+        std::fs::write(path.join("server.properties"), "server-port=25565\n").unwrap();
+        std::fs::write(path.join("world/region/r.0.0.mca"), vec![7_u8; 64 * 1024]).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_relocate_copies_and_records_location() {
+        let (old_disk, new_disk, stores) = (
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+        );
+        let path = instance_dir(old_disk.path());
+
+        let target = relocation_target(&path, new_disk.path()).unwrap();
+        assert_eq!(target.file_name(), path.file_name());
+        let mut reported = Vec::new();
+        let copied = copy_dir_verified(&path, &target, |copied, _| reported.push(copied)).unwrap();
+        assert_eq!(copied, 2 + 18 + 64 * 1024);
+        assert_eq!(reported.len(), 3);
+        assert_eq!(reported.last(), Some(&copied));
+        assert_eq!(
+            std::fs::read(target.join("world/region/r.0.0.mca")).unwrap(),
+            vec![7_u8; 64 * 1024]
+        );
+        // left for the caller to remove once the instance is switched over
+        assert!(path.join("server.properties").exists());
+
+        let uuid = InstanceUuid::from("INSTANCE_survival".to_string());
+        set_instance_location(stores.path(), &uuid, Some(target.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_instance_locations(stores.path()).await,
+            HashMap::from([(uuid.clone(), target.clone())])
+        );
+        set_instance_location(stores.path(), &uuid, None)
+            .await
+            .unwrap();
+        assert!(read_instance_locations(stores.path()).await.is_empty());
+
+        // taken, inside of the instance or not a directory
+        let err = relocation_target(&path, new_disk.path()).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        let err = relocation_target(&path, &path.join("world")).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+        let err = relocation_target(&path, Path::new("relative/disk")).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_location_changes_are_all_recorded() {
+        let stores = tempfile::tempdir().unwrap();
+        let locations: HashMap<_, _> = (0..16)
+            .map(|i| {
+                (
+                    InstanceUuid::from(format!("INSTANCE_{i}")),
+                    PathBuf::from(format!("/mnt/disk/instance-{i}")),
+                )
+            })
+            .collect();
+        futures::future::try_join_all(locations.iter().map(|(uuid, location)| {
+            set_instance_location(stores.path(), uuid, Some(location.clone()))
+        }))
+        .await
+        .unwrap();
+        assert_eq!(read_instance_locations(stores.path()).await, locations);
+    }
+
+    #[test]
+    fn test_held_instance_is_refused_until_released() {
+        let holds = InstanceHolds::default();
+        let uuid = InstanceUuid::from("INSTANCE_survival".to_string());
+        let other = InstanceUuid::from("INSTANCE_lobby".to_string());
+
+        let hold = holds.hold(&uuid, "relocated").unwrap();
+        let err = holds.hold(&uuid, "started").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert!(err.to_string().contains("being relocated"));
+        let _other = holds.hold(&other, "started").unwrap();
+
+        drop(hold);
+        holds.hold(&uuid, "started").unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_copy_leaves_original_intact() {
+        let (old_disk, new_disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let path = instance_dir(old_disk.path());
+        // copied after server.properties, can't be read
+        std::os::unix::fs::symlink(path.join("missing.jar"), path.join("startup.jar")).unwrap();
+
+        let target = relocation_target(&path, new_disk.path()).unwrap();
+        let mut reported = Vec::new();
+        let err = copy_dir_verified(&path, &target, |_, file| reported.push(file.to_path_buf()))
+            .unwrap_err();
+        assert!(err.to_string().contains("startup.jar"));
+        assert_eq!(
+            reported,
+            vec![
+                PathBuf::from(".lodestone_config"),
+                PathBuf::from("server.properties")
+            ]
+        );
+        assert!(!target.exists());
+        assert_eq!(std::fs::read_dir(new_disk.path()).unwrap().count(), 0);
+        assert_eq!(
+            std::fs::read_to_string(path.join("server.properties")).unwrap(),
+            "server-port=25565\n"
+        );
+        assert!(path.join("world/region/r.0.0.mca").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_move_keeps_source_until_copy_is_verified() {
+        let (old_disk, new_disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let path = instance_dir(old_disk.path());
+        std::os::unix::fs::symlink(path.join("missing.jar"), path.join("startup.jar")).unwrap();
+
+        let target = new_disk.path().join("survival-1a2b3c4d");
+        move_verified(&path, &target, |_, _| {}, &CancellationToken::new()).unwrap_err();
+        assert!(!target.exists());
+        assert!(path.join("world/region/r.0.0.mca").exists());
+
+        std::fs::remove_file(path.join("startup.jar")).unwrap();
+        let mut reported = Vec::new();
+        let moved = move_verified(
+            &path,
+            &target,
+            |copied, _| reported.push(copied),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(moved, 2 + 18 + 64 * 1024);
+        assert_eq!(reported.last(), Some(&moved));
+        assert!(!path.exists());
+        assert_eq!(
+            std::fs::read(target.join("world/region/r.0.0.mca")).unwrap(),
+            vec![7_u8; 64 * 1024]
+        );
+
+        let file = new_disk.path().join("server.properties");
+        move_verified(
+            &target.join("server.properties"),
+            &file,
+            |_, _| {},
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert!(!target.join("server.properties").exists());
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "server-port=25565\n"
+        );
+    }
+
+    #[test]
+    fn test_cancelled_move_leaves_source_and_no_partial_copy() {
+        let (old_disk, new_disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let path = instance_dir(old_disk.path());
+        let target = new_disk.path().join("survival-1a2b3c4d");
+
+        // cancelled once the first file is copied
+        let cancel = CancellationToken::new();
+        let err = move_verified(&path, &target, |_, _| cancel.cancel(), &cancel).unwrap_err();
+        assert!(err.to_string().contains("cancelled"));
+        assert!(!target.exists());
+        assert!(path.join("world/region/r.0.0.mca").exists());
+
+        let file = new_disk.path().join("server.properties");
+        move_verified(&path.join("server.properties"), &file, |_, _| {}, &cancel).unwrap_err();
+        assert!(!file.exists());
+        assert!(path.join("server.properties").exists());
+    }
+}
@@ -0,0 +1,221 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+
+use crate::error::{Error, ErrorKind};
+use crate::prelude::path_to_tmp;
+use crate::sidecar::is_sidecar_name;
+use crate::util::{unzip_file_with_progress, UnzipOption};
+
+const STAGED_ARCHIVE_DIR_PREFIX: &str = "staged-";
+
+const ARCHIVE_EXTENSIONS: [&str; 3] = ["zip", "gz", "tgz"];
+
+pub fn is_seed_archive(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map_or(false, |extension| ARCHIVE_EXTENSIONS.contains(&extension))
+}
+
+/// Directory in the temporary directory holding the archive staged under `key`.
+///
+/// Staged archives that are never used are removed by the janitor like any other temporary file
+pub fn staged_archive_dir(key: &str) -> Result<PathBuf, Error> {
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Invalid archive key"),
+        });
+    }
+    Ok(path_to_tmp().join(format!("{STAGED_ARCHIVE_DIR_PREFIX}{key}")))
+}
+
+/// The archive staged under `key`
+pub fn find_staged_archive(key: &str) -> Result<PathBuf, Error> {
+    let dir = staged_archive_dir(key)?;
+    std::fs::read_dir(&dir)
+        .ok()
+        .and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .find(|path| path.is_file() && is_seed_archive(path))
+        })
+        .ok_or_else(|| Error {
+            kind: ErrorKind::NotFound,
+            source: eyre!("Archive not found, it may have expired"),
+        })
+}
+
+// a server packaged with its directory, rather than a single directory meant for the instance
+fn looks_like_server_root(dir: &Path) -> bool {
+    dir.join("server.properties").is_file()
+        || dir.join("eula.txt").is_file()
+        || std::fs::read_dir(dir).map_or(false, |entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .any(|entry| entry.path().extension().map_or(false, |e| e == "jar"))
+        })
+}
+
+// move `source` to `destination`, directories are merged and files replace the existing ones
+fn move_merging(source: &Path, destination: &Path) -> Result<(), Error> {
+    if source.is_dir() && destination.is_dir() {
+        for entry in std::fs::read_dir(source)
+            .context(format!("Failed to read directory {}", source.display()))?
+        {
+            let entry = entry.context(format!("Failed to read directory {}", source.display()))?;
+            move_merging(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+    if destination.is_dir() {
+        std::fs::remove_dir_all(destination)
+            .context(format!("Failed to remove {}", destination.display()))?;
+    }
+    std::fs::rename(source, destination).context(format!(
+        "Failed to move {} to {}",
+        source.display(),
+        destination.display()
+    ))?;
+    Ok(())
+}
+
+/// Extract the archive into the directory of a newly created instance, overwriting the files
+/// generated by the setup. Returns the top level paths that were seeded.
+///
+/// Entries escaping the archive fail the extraction. An archive holding a whole server under a
+/// single directory has that directory's content seeded, and the lodestone sidecars of the
+/// instance are never replaced
+pub fn seed_from_archive(
+    archive: &Path,
+    path_to_instance: &Path,
+    on_entry: &mut dyn FnMut(u64),
+) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(path_to_tmp()).context("Failed to create temporary directory")?;
+    let extracted_dir = tempfile::tempdir_in(path_to_tmp())
+        .context("Failed to create temporary directory for extracting the archive")?;
+    let extracted = extracted_dir.path().join("contents");
+    unzip_file_with_progress(archive, UnzipOption::ToDir(extracted.clone()), on_entry)?;
+
+    let mut root = extracted;
+    let top_level: Vec<PathBuf> = std::fs::read_dir(&root)
+        .context("Failed to read the extracted archive")?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    if let [single] = top_level.as_slice() {
+        if single.is_dir() && looks_like_server_root(single) {
+            root = single.clone();
+        }
+    }
+
+    let mut seeded = Vec::new();
+    for entry in std::fs::read_dir(&root).context("Failed to read the extracted archive")? {
+        let entry = entry.context("Failed to read the extracted archive")?;
+        if is_sidecar_name(&entry.file_name()) {
+            continue;
+        }
+        let destination = path_to_instance.join(entry.file_name());
+        move_merging(&entry.path(), &destination)?;
+        seeded.push(destination);
+    }
+    Ok(seeded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::init_paths;
+    use crate::test_util::write_test_zip;
+
+    #[test]
+    fn test_create_from_archive_seeds_instance_directory() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+
+        let key = crate::util::rand_alphanumeric(16);
+        let staged = staged_archive_dir(&key).unwrap();
+        std::fs::create_dir_all(&staged).unwrap();
+        write_test_zip(
+            &staged.join("modpack.zip"),
+            &[
+                (
+                    "modpack/server.properties",
+                    b"motd=Seeded\nserver-port=25570\n",
+                ),
+                ("modpack/mods/fabric-api.jar", b"jar"),
+                ("modpack/config/mod/settings.toml", b"enabled = true"),
+                ("modpack/.lodestone_config", b"{}"),
+            ],
+        );
+        let archive = find_staged_archive(&key).unwrap();
+
+        // what the setup left in the instance directory
+        let instance = tempfile::tempdir().unwrap();
+        let path_to_instance = instance.path();
+        std::fs::write(path_to_instance.join(".lodestone_config"), "config").unwrap();
+        std::fs::write(path_to_instance.join("eula.txt"), "eula=true").unwrap();
+        std::fs::write(
+            path_to_instance.join("server.properties"),
+            "server-port=25565",
+        )
+        .unwrap();
+        std::fs::create_dir(path_to_instance.join("mods")).unwrap();
+        std::fs::write(path_to_instance.join("mods/lithium.jar"), "jar").unwrap();
+
+        let mut extracted_entries = 0;
+        let mut seeded = seed_from_archive(&archive, path_to_instance, &mut |entries| {
+            extracted_entries = entries
+        })
+        .unwrap();
+        seeded.sort();
+        assert_eq!(extracted_entries, 4);
+        assert_eq!(
+            seeded,
+            vec![
+                path_to_instance.join("config"),
+                path_to_instance.join("mods"),
+                path_to_instance.join("server.properties"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(path_to_instance.join("server.properties")).unwrap(),
+            "motd=Seeded\nserver-port=25570\n"
+        );
+        assert!(path_to_instance.join("mods/fabric-api.jar").is_file());
+        assert!(path_to_instance.join("mods/lithium.jar").is_file());
+        assert!(path_to_instance.join("config/mod/settings.toml").is_file());
+        assert!(path_to_instance.join("eula.txt").is_file());
+        assert_eq!(
+            std::fs::read_to_string(path_to_instance.join(".lodestone_config")).unwrap(),
+            "config"
+        );
+        assert!(!path_to_instance.join("modpack").exists());
+    }
+
+    #[test]
+    fn test_seeding_rejects_escaping_entries() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("evil.zip");
+        write_test_zip(
+            &archive,
+            &[("server.properties", b""), ("../../escaped.txt", b"")],
+        );
+        let instance = temp.path().join("instances/evil");
+        std::fs::create_dir_all(&instance).unwrap();
+        assert!(seed_from_archive(&archive, &instance, &mut |_| {}).is_err());
+        assert!(!temp.path().join("escaped.txt").exists());
+        assert!(!instance.join("server.properties").exists());
+
+        assert!(matches!(
+            staged_archive_dir("../instances").unwrap_err().kind,
+            ErrorKind::BadRequest
+        ));
+        assert!(matches!(
+            find_staged_archive("missing").unwrap_err().kind,
+            ErrorKind::NotFound
+        ));
+    }
+}
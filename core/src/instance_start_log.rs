@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::Error;
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+
+/// Only present while the last start has failed
+pub const LAST_START_LOG_FILE_NAME: &str = ".lodestone_last_start.json";
+
+/// Number of output lines kept from a failed start
+pub const START_LOG_LINES: usize = 50;
+
+/// Tail of the output of a start that exited before the server was up
+#[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct StartLog {
+    pub lines: Vec<String>,
+    /// unix timestamp in seconds
+    pub failed_at: i64,
+}
+
+/// Keeps the last `START_LOG_LINES` lines of stdout and stderr while an instance starts
+#[derive(Debug, Default)]
+pub struct StartLogCapture {
+    lines: VecDeque<String>,
+}
+
+impl StartLogCapture {
+    pub fn push(&mut self, line: &str) {
+        if self.lines.len() == START_LOG_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.trim_end().to_string());
+    }
+
+    pub fn into_start_log(self) -> StartLog {
+        StartLog {
+            lines: self.lines.into(),
+            failed_at: chrono::Utc::now().timestamp(),
+        }
+    }
+}
+
+impl StartLog {
+    /// The last few lines, to be appended to the error of a blocking start
+    pub fn tail(&self, count: usize) -> String {
+        self.lines[self.lines.len().saturating_sub(count)..].join("\n")
+    }
+}
+
+pub async fn read_last_start_log(path_to_instance: &Path) -> Option<StartLog> {
+    read_sidecar(path_to_instance, LAST_START_LOG_FILE_NAME, "last start log").await
+}
+
+pub async fn write_last_start_log(
+    path_to_instance: &Path,
+    start_log: &StartLog,
+) -> Result<(), Error> {
+    write_sidecar(
+        path_to_instance,
+        LAST_START_LOG_FILE_NAME,
+        "last start log",
+        start_log,
+    )
+    .await
+}
+
+/// Called once the server is up, the previous failure is no longer relevant
+pub async fn clear_last_start_log(path_to_instance: &Path) -> Result<(), Error> {
+    remove_sidecar(path_to_instance, LAST_START_LOG_FILE_NAME).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failed_start_output_is_retrievable() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert!(read_last_start_log(path).await.is_none());
+
+        // a server that never gets to "Done", like one refusing to run without the EULA
+        let mut capture = StartLogCapture::default();
+        for i in 0..START_LOG_LINES {
+            capture.push(&format!("[Server thread/INFO]: Loading line {i}\n"));
+        }
+        capture.push("[ServerMain/WARN]: Failed to load eula.txt\n");
+        capture.push("[ServerMain/INFO]: You need to agree to the EULA in order to run the server. Go to eula.txt for more info.\r\n");
+        write_last_start_log(path, &capture.into_start_log())
+            .await
+            .unwrap();
+
+        let start_log = read_last_start_log(path).await.unwrap();
+        assert_eq!(start_log.lines.len(), START_LOG_LINES);
+        assert_eq!(start_log.lines[0], "[Server thread/INFO]: Loading line 2");
+        assert_eq!(
+            start_log.tail(2),
+            "[ServerMain/WARN]: Failed to load eula.txt\n[ServerMain/INFO]: You need to agree to the EULA in order to run the server. Go to eula.txt for more info."
+        );
+
+        clear_last_start_log(path).await.unwrap();
+        assert!(read_last_start_log(path).await.is_none());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+use crate::global_settings::GlobalSettings;
+use crate::handlers::global_fs::{DownloadKey, DownloadableFile};
+
+/// Lowest retention that can be configured, long running extractions keep their temp files
+pub const MIN_TEMP_RETENTION_SECS: u64 = 10 * 60;
+
+const JANITOR_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Remove the download keys created more than `max_age` before `now`.
+///
+/// Dropping a zipped download removes its temporary directory as well
+pub fn prune_download_keys(
+    download_urls: &mut HashMap<String, DownloadKey>,
+    max_age: Duration,
+    now: Instant,
+) -> Vec<DownloadKey> {
+    let expired: Vec<String> = download_urls
+        .iter()
+        .filter(|(_, key)| now.saturating_duration_since(key.created_at) > max_age)
+        .map(|(key, _)| key.clone())
+        .collect();
+    expired
+        .iter()
+        .filter_map(|key| download_urls.remove(key))
+        .collect()
+}
+
+// the newest modification time of anything under the path, so a directory still being
+// written into isn't considered stale because of its own timestamp
+fn last_modified(path: &Path) -> Option<SystemTime> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .max()
+}
+
+/// Remove the entries of the temporary directory that haven't been modified for `max_age`,
+/// except the ones in `in_use`. Returns the removed paths
+pub fn prune_tmp_dir(tmp: &Path, max_age: Duration, in_use: &HashSet<PathBuf>) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(tmp) else {
+        return Vec::new();
+    };
+    let now = SystemTime::now();
+    let mut removed = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if in_use.iter().any(|used| used.starts_with(&path)) {
+            continue;
+        }
+        let Some(modified) = last_modified(&path) else {
+            continue;
+        };
+        if now.duration_since(modified).unwrap_or_default() <= max_age {
+            continue;
+        }
+        let result = if path.is_dir() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => removed.push(path),
+            Err(e) => warn!(
+                "Failed to remove stale temporary file {}: {e}",
+                path.display()
+            ),
+        }
+    }
+    removed
+}
+
+/// Periodically prune expired download keys and stale temporary files
+pub async fn run_janitor(
+    download_urls: Arc<Mutex<HashMap<String, DownloadKey>>>,
+    global_settings: Arc<Mutex<GlobalSettings>>,
+    tmp: PathBuf,
+) {
+    let mut interval = tokio::time::interval(JANITOR_INTERVAL);
+    loop {
+        interval.tick().await;
+        let max_age = global_settings.lock().await.temp_retention();
+
+        let (pruned, in_use) = {
+            let mut download_urls = download_urls.lock().await;
+            let pruned = prune_download_keys(&mut download_urls, max_age, Instant::now());
+            let in_use: HashSet<PathBuf> = download_urls
+                .values()
+                .filter_map(|key| match &key.file {
+                    DownloadableFile::ZippedFile((path, _))
+                    | DownloadableFile::DecompressedFile((path, _)) => Some(path.clone()),
+                    DownloadableFile::NormalFile(_) => None,
+                })
+                .collect();
+            (pruned, in_use)
+        };
+        if !pruned.is_empty() {
+            info!("Pruned {} expired download key(s)", pruned.len());
+        }
+        // removing the temporary directories of zipped downloads can block
+        let tmp = tmp.clone();
+        let removed = tokio::task::spawn_blocking(move || {
+            drop(pruned);
+            prune_tmp_dir(&tmp, max_age, &in_use)
+        })
+        .await
+        .unwrap_or_default();
+        for path in removed {
+            info!("Removed stale temporary file {}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_janitor_prunes_only_expired_artifacts() {
+        let temp = tempfile::tempdir().unwrap();
+        let tmp = temp.path();
+        let max_age = Duration::from_secs(60 * 60);
+
+        let start = Instant::now();
+        let stale_zip_dir = tempfile::tempdir_in(tmp).unwrap();
+        let stale_zip_path = stale_zip_dir.path().to_path_buf();
+        let mut download_urls = HashMap::from([
+            (
+                "expired".to_string(),
+                DownloadKey {
+                    file: DownloadableFile::ZippedFile((
+                        stale_zip_path.join("world.zip"),
+                        stale_zip_dir,
+                    )),
+                    created_at: start,
+                },
+            ),
+            (
+                "fresh".to_string(),
+                DownloadKey {
+                    file: DownloadableFile::NormalFile(PathBuf::from("server.properties")),
+                    created_at: start + Duration::from_secs(30 * 60),
+                },
+            ),
+        ]);
+        let pruned = prune_download_keys(
+            &mut download_urls,
+            max_age,
+            start + Duration::from_secs(61 * 60),
+        );
+        assert_eq!(pruned.len(), 1);
+        assert!(download_urls.contains_key("fresh"));
+        drop(pruned);
+        assert!(!stale_zip_path.exists());
+
+        let old = filetime::FileTime::from_system_time(
+            SystemTime::now() - Duration::from_secs(2 * 60 * 60),
+        );
+        // an orphaned extraction directory
+        let orphaned = tmp.join(".tmpOrphan");
+        std::fs::create_dir_all(orphaned.join("world/region")).unwrap();
+        std::fs::write(orphaned.join("world/region/r.0.0.mca"), "").unwrap();
+        for path in [
+            orphaned.join("world/region/r.0.0.mca"),
+            orphaned.join("world/region"),
+            orphaned.join("world"),
+            orphaned.clone(),
+        ] {
+            filetime::set_file_mtime(&path, old).unwrap();
+        }
+        // an old directory that is still being extracted into
+        let active = tmp.join(".tmpActive");
+        std::fs::create_dir_all(active.join("world")).unwrap();
+        std::fs::write(active.join("world/level.dat"), "").unwrap();
+        filetime::set_file_mtime(&active, old).unwrap();
+        // an old archive backing a download that hasn't expired yet
+        let downloading = tmp.join(".tmpDownload");
+        std::fs::create_dir(&downloading).unwrap();
+        std::fs::write(downloading.join("selection.zip"), "").unwrap();
+        filetime::set_file_mtime(downloading.join("selection.zip"), old).unwrap();
+        filetime::set_file_mtime(&downloading, old).unwrap();
+        let stale_file = tmp.join("upload.part");
+        std::fs::write(&stale_file, "").unwrap();
+        filetime::set_file_mtime(&stale_file, old).unwrap();
+        let fresh_file = tmp.join("fresh.part");
+        std::fs::write(&fresh_file, "").unwrap();
+
+        let in_use = HashSet::from([downloading.join("selection.zip")]);
+        let mut removed = prune_tmp_dir(tmp, max_age, &in_use);
+        removed.sort();
+        assert_eq!(removed, vec![orphaned, stale_file]);
+        assert!(active.exists());
+        assert!(downloading.exists());
+        assert!(fresh_file.exists());
+    }
+}
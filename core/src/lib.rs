@@ -21,7 +21,8 @@ use crate::{
         instance_macro::get_instance_macro_routes, instance_players::get_instance_players_routes,
         instance_server::get_instance_server_routes,
         instance_setup_configs::get_instance_setup_config_routes, monitor::get_monitor_routes,
-        playitgg::get_playitgg_routes, setup::get_setup_route, system::get_system_routes,
+        playitgg::get_playitgg_routes, remote_backup::get_remote_backup_routes,
+        setup::get_setup_route, system::get_system_routes,
         users::get_user_routes,
     },
     util::rand_alphanumeric,
@@ -34,16 +35,26 @@ use axum_server::tls_rustls::RustlsConfig;
 use clap::Parser;
 use color_eyre::eyre::Context;
 use color_eyre::Report;
+use console_buffer::{read_console_buffer_limits, ConsoleBuffer};
+use console_log::{console_output_line, read_console_log_settings, ConsoleLog};
+use correlation::{correlate, CORRELATION_ID_HEADER};
 use dashmap::DashMap;
 use error::Error;
 use events::{CausedBy, Event};
+use fs_op_limiter::{read_fs_op_limits, FsOpLimiter};
 use futures::Future;
 use global_settings::GlobalSettings;
 use implementations::{generic, minecraft};
+use instance_dependencies::{
+    resolve_dependencies, wait_for_dependencies, DEPENDENCY_START_TIMEOUT,
+};
+use instance_log_level::{file_log_filter, stdout_log_filter};
+use instance_relocation::InstanceHolds;
 use macro_executor::MacroExecutor;
 use playitgg::utils::is_valid_secret_key;
 use port_manager::PortManager;
 use prelude::GameInstance;
+use read_only::{refuse_changes_when_read_only, ReadOnlyMode};
 use reqwest::{header, Method};
 use ringbuffer::{AllocRingBuffer, RingBufferWrite};
 
@@ -70,13 +81,16 @@ use tower_http::{
 };
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::prelude::*;
-use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, EnvFilter};
+use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use traits::{t_configurable::TConfigurable, t_server::MonitorReport, t_server::TServer};
-use types::{DotLodestoneConfig, InstanceUuid};
+use types::{DotLodestoneConfig, FailedInstanceLoad, InstanceUuid};
 use uuid::Uuid;
 
 pub mod auth;
 mod command_console;
+mod console_buffer;
+mod console_log;
+mod correlation;
 pub mod db;
 mod deno_ops;
 mod docker_bridge;
@@ -84,27 +98,56 @@ pub mod error;
 mod event_broadcaster;
 mod events;
 mod extension;
+mod file_annotations;
+mod fs_op_limiter;
+mod fs_op_metrics;
 pub mod global_settings;
 mod handlers;
 pub mod implementations;
+mod instance_audit;
+mod instance_crashes;
+mod instance_dependencies;
+mod instance_export;
+mod instance_log_level;
+mod instance_log_rotation;
+mod instance_relocation;
+mod instance_seed;
+mod instance_start_log;
+mod janitor;
 pub mod macro_executor;
+mod merged_logs;
 mod migration;
 mod output_types;
 pub mod playitgg;
 mod port_manager;
 pub mod prelude;
+mod protected_paths;
+mod reachability;
+mod read_only;
+mod remote_backup;
+mod remote_fetch;
+mod sidecar;
+mod storage_pools;
 pub mod tauri_export;
+#[cfg(test)]
+mod test_util;
 mod traits;
 pub mod types;
+mod upload_name_policy;
 pub mod util;
-use handlers::global_fs::DownloadableFile;
+mod writable_paths;
+mod zip_stream;
+use handlers::global_fs::DownloadKey;
+use handlers::instance_fs::UploadSessions;
 
 #[derive(Clone)]
 pub struct AppState {
     instances: Arc<DashMap<InstanceUuid, GameInstance>>,
+    failed_instances: Arc<Mutex<Vec<FailedInstanceLoad>>>,
     users_manager: Arc<RwLock<UsersManager>>,
     events_buffer: Arc<Mutex<AllocRingBuffer<Event>>>,
-    console_out_buffer: Arc<Mutex<HashMap<InstanceUuid, AllocRingBuffer<Event>>>>,
+    console_out_buffer: Arc<Mutex<HashMap<InstanceUuid, ConsoleBuffer>>>,
+    console_logs: Arc<Mutex<HashMap<InstanceUuid, ConsoleLog>>>,
     monitor_buffer: Arc<Mutex<HashMap<InstanceUuid, AllocRingBuffer<MonitorReport>>>>,
     event_broadcaster: EventBroadcaster,
     uuid: String,
@@ -114,11 +157,15 @@ pub struct AppState {
     port_manager: Arc<Mutex<PortManager>>,
     first_time_setup_key: Arc<Mutex<Option<String>>>,
     playitgg_key: Arc<Mutex<Option<String>>>,
-    download_urls: Arc<Mutex<HashMap<String, DownloadableFile>>>,
+    download_urls: Arc<Mutex<HashMap<String, DownloadKey>>>,
+    upload_sessions: UploadSessions,
+    fs_op_limiter: FsOpLimiter,
+    instance_holds: InstanceHolds,
     macro_executor: MacroExecutor,
     sqlite_pool: sqlx::SqlitePool,
     docker_bridge: docker_bridge::DockerBridge,
     playit_keep_running: Arc<Mutex<Option<Arc<AtomicBool>>>>,
+    read_only: ReadOnlyMode,
 }
 
 impl AppState {
@@ -135,13 +182,52 @@ impl AppState {
     }
 }
 
-async fn restore_instances(
-    instances_path: &Path,
-    event_broadcaster: EventBroadcaster,
-    macro_executor: MacroExecutor,
-) -> Result<DashMap<InstanceUuid, GameInstance>, Error> {
-    let ret: DashMap<InstanceUuid, GameInstance> = DashMap::new();
+/// Read the `.lodestone_config` of the instance directory at `path` into `configs`,
+/// a config that can't be read or parsed is reported in `failed`
+fn scan_instance_config(
+    path: PathBuf,
+    configs: &mut Vec<(PathBuf, DotLodestoneConfig)>,
+    failed: &mut Vec<FailedInstanceLoad>,
+) {
+    let dot_lodestone_config_file = match std::fs::File::open(path.join(".lodestone_config")) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error while restoring instance {}, failed to read .lodestone_config file : {e}", path.display());
+            failed.push(FailedInstanceLoad {
+                path: path.display().to_string(),
+                uuid: None,
+                error: format!("Failed to read .lodestone_config file : {e}"),
+            });
+            return;
+        }
+    };
+    let dot_lodestone_config: DotLodestoneConfig = match serde_json::from_reader(
+        dot_lodestone_config_file,
+    ) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("Error while restoring instance {}, failed to parse .lodestone_config file : {e}", path.display());
+            failed.push(FailedInstanceLoad {
+                path: path.display().to_string(),
+                uuid: None,
+                error: format!("Failed to parse .lodestone_config file : {e}"),
+            });
+            return;
+        }
+    };
+    configs.push((path, dot_lodestone_config));
+}
 
+/// Read the `.lodestone_config` of every instance directory, and of the instances relocated
+/// outside of the instances directory,
+/// directories whose config can't be read or parsed are reported instead of aborting the whole load.
+/// Files and folders without a config in the instances directory aren't instances and are left out
+fn scan_instance_configs(
+    instances_path: &Path,
+    relocated: Vec<PathBuf>,
+) -> Result<(Vec<(PathBuf, DotLodestoneConfig)>, Vec<FailedInstanceLoad>), Error> {
+    let mut configs = Vec::new();
+    let mut failed = Vec::new();
     for entry in instances_path
         .read_dir()
         .context("Failed to read instances directory")?
@@ -153,77 +239,107 @@ async fn restore_instances(
                 continue;
             }
         };
-        let dot_lodestone_config_file = match std::fs::File::open(path.join(".lodestone_config")) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Error while restoring instance {}, failed to read .lodestone_config file : {e}", path.display());
-                continue;
-            }
-        };
-        let dot_lodestone_config: DotLodestoneConfig = match serde_json::from_reader(
-            dot_lodestone_config_file,
-        ) {
+        // a config that can't be looked at is still reported
+        if let Ok(false) = path.join(".lodestone_config").try_exists() {
+            continue;
+        }
+        scan_instance_config(path, &mut configs, &mut failed);
+    }
+    for path in relocated {
+        if path.is_dir() {
+            scan_instance_config(path, &mut configs, &mut failed);
+        } else {
+            error!(
+                "Error while restoring instance, relocated instance directory {} is missing",
+                path.display()
+            );
+            failed.push(FailedInstanceLoad {
+                path: path.display().to_string(),
+                uuid: None,
+                error: "Relocated instance directory is missing".to_string(),
+            });
+        }
+    }
+    Ok((configs, failed))
+}
+
+/// Restore the instance in the directory at `path`
+pub(crate) async fn restore_instance(
+    path: PathBuf,
+    dot_lodestone_config: &DotLodestoneConfig,
+    event_broadcaster: EventBroadcaster,
+    macro_executor: MacroExecutor,
+    global_settings: Arc<Mutex<GlobalSettings>>,
+) -> Result<GameInstance, Error> {
+    Ok(match dot_lodestone_config.game_type() {
+        GameType::MinecraftJava => minecraft::MinecraftInstance::restore(
+            path,
+            dot_lodestone_config.clone(),
+            event_broadcaster,
+            macro_executor,
+            global_settings,
+        )
+        .await?
+        .into(),
+        GameType::Generic => generic::GenericInstance::restore(
+            path,
+            dot_lodestone_config.clone(),
+            event_broadcaster,
+            macro_executor,
+        )
+        .await?
+        .into(),
+        GameType::MinecraftBedrock => todo!(),
+    })
+}
+
+async fn restore_instances(
+    instances_path: &Path,
+    event_broadcaster: EventBroadcaster,
+    macro_executor: MacroExecutor,
+    global_settings: Arc<Mutex<GlobalSettings>>,
+) -> Result<(DashMap<InstanceUuid, GameInstance>, Vec<FailedInstanceLoad>), Error> {
+    let ret: DashMap<InstanceUuid, GameInstance> = DashMap::new();
+    let relocated = instance_relocation::read_instance_locations(path_to_stores())
+        .await
+        .into_values()
+        .collect();
+    let (configs, mut failed) = scan_instance_configs(instances_path, relocated)?;
+
+    for (path, dot_lodestone_config) in configs {
+        debug!("restoring instance: {}", path.display());
+        let instance = match restore_instance(
+            path.to_owned(),
+            &dot_lodestone_config,
+            event_broadcaster.clone(),
+            macro_executor.clone(),
+            global_settings.clone(),
+        )
+        .await
+        {
             Ok(v) => v,
             Err(e) => {
-                error!("Error while restoring instance {}, failed to parse .lodestone_config file : {e}", path.display());
+                error!(
+                    "Error while restoring {:?} instance {} : {e}",
+                    dot_lodestone_config.game_type(),
+                    path.display()
+                );
+                failed.push(FailedInstanceLoad {
+                    path: path.display().to_string(),
+                    uuid: Some(dot_lodestone_config.uuid().to_owned()),
+                    error: e.to_string(),
+                });
                 continue;
             }
         };
-
-        debug!("restoring instance: {}", path.display());
-        let uuid_instance: (InstanceUuid, GameInstance) = match dot_lodestone_config.game_type() {
-            GameType::MinecraftJava => {
-                let instance = match minecraft::MinecraftInstance::restore(
-                    path.to_owned(),
-                    dot_lodestone_config.clone(),
-                    event_broadcaster.clone(),
-                    macro_executor.clone(),
-                )
-                .await
-                {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!(
-                            "Error while restoring Minecraft Java instance {} : {e}",
-                            path.display()
-                        );
-                        continue;
-                    }
-                };
-                debug!("Restored Minecraft Java instance successfully");
-                (dot_lodestone_config.uuid().to_owned(), instance.into())
-            }
-            GameType::Generic => {
-                let instance = match generic::GenericInstance::restore(
-                    path.to_owned(),
-                    dot_lodestone_config.clone(),
-                    event_broadcaster.clone(),
-                    macro_executor.clone(),
-                )
-                .await
-                {
-                    Ok(v) => v,
-                    Err(e) => {
-                        error!(
-                            "Error while restoring atom instance {} : {e}",
-                            path.display()
-                        );
-                        continue;
-                    }
-                };
-                debug!("Restored Generic instance successfully");
-                (dot_lodestone_config.uuid().to_owned(), instance.into())
-            }
-            GameType::MinecraftBedrock => todo!(),
-        };
-        let uuid = uuid_instance.0;
-        let instance = uuid_instance.1;
+        debug!("Restored instance successfully");
+        let uuid = dot_lodestone_config.uuid().to_owned();
         if ret.contains_key(&uuid) {
             warn!("UUID {} is repeated.", uuid.to_string());
         }
         ret.insert(uuid, instance);
     }
-    Ok(ret)
+    Ok((ret, failed))
 }
 
 fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
@@ -246,7 +362,8 @@ fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
             .with_thread_ids(false)
             // Don't display the event's target (module path)
             .with_target(true)
-            .with_writer(std::io::stdout);
+            .with_writer(std::io::stdout)
+            .with_filter(stdout_log_filter());
         let fmt_layer_file = tracing_subscriber::fmt::layer()
             // Use a more compact, abbreviated log format
             .compact()
@@ -259,12 +376,12 @@ fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
             // Don't display the event's target (module path)
             .with_target(true)
             .with_ansi(false)
-            .with_writer(non_blocking);
+            .with_writer(non_blocking)
+            .with_filter(file_log_filter());
 
         tracing_subscriber::registry()
             .with(fmt_layer_stdout)
             .with(fmt_layer_file)
-            .with(EnvFilter::from("lodestone_core=debug"))
             .init();
     }
 
@@ -282,7 +399,7 @@ fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
             // Don't display the event's target (module path)
             .with_target(false)
             .with_writer(std::io::stdout)
-            .with_filter(EnvFilter::from("lodestone_core=info"));
+            .with_filter(stdout_log_filter());
 
         let fmt_layer_file = tracing_subscriber::fmt::layer()
             // Use a more compact, abbreviated log format
@@ -297,7 +414,7 @@ fn setup_tracing() -> tracing_appender::non_blocking::WorkerGuard {
             .with_target(true)
             .with_ansi(false)
             .with_writer(non_blocking)
-            .with_filter(EnvFilter::from("lodestone_core=debug"));
+            .with_filter(file_log_filter());
 
         tracing_subscriber::registry()
             // .with(ErrorLayer::default())
@@ -485,6 +602,7 @@ pub async fn run(
     );
 
     global_settings.load_from_file().await?;
+    let global_settings = Arc::new(Mutex::new(global_settings));
 
     let first_time_setup_key = if !users_manager.as_ref().iter().any(|(_, user)| user.is_owner) {
         let key = rand_alphanumeric(16);
@@ -522,22 +640,46 @@ pub async fn run(
     };
 
     let macro_executor = MacroExecutor::new(tx.clone(), tokio::runtime::Handle::current());
-    let instances = restore_instances(&path_to_instances, tx.clone(), macro_executor.clone())
-        .await
-        .map_err(|_| Error {
-            kind: ErrorKind::Internal,
-            source: Report::msg("failed to restore instances"),
-        })?;
+    let (instances, failed_instances) = restore_instances(
+        &path_to_instances,
+        tx.clone(),
+        macro_executor.clone(),
+        global_settings.clone(),
+    )
+    .await
+    .map_err(|_| Error {
+        kind: ErrorKind::Internal,
+        source: Report::msg("failed to restore instances"),
+    })?;
 
     let mut allocated_ports = HashSet::new();
     for instance_entry in instances.iter() {
         allocated_ports.insert(instance_entry.value().port().await);
     }
+    port_manager::warn_port_conflicts(&instances).await;
+    let mut console_out_buffer = HashMap::new();
+    let mut console_logs = HashMap::new();
+    let fs_op_limiter = FsOpLimiter::default();
+    for instance_entry in instances.iter() {
+        let path = instance_entry.value().path().await;
+        let limits = read_console_buffer_limits(&path).await;
+        console_out_buffer.insert(instance_entry.key().clone(), ConsoleBuffer::new(limits));
+        let console_log_settings = read_console_log_settings(&path).await;
+        if console_log_settings.enabled {
+            console_logs.insert(
+                instance_entry.key().clone(),
+                ConsoleLog::new(&path, console_log_settings),
+            );
+        }
+        fs_op_limiter.set_limits(instance_entry.key().clone(), read_fs_op_limits(&path).await);
+    }
     let shared_state = AppState {
         instances: Arc::new(instances),
+        failed_instances: Arc::new(Mutex::new(failed_instances)),
         users_manager: Arc::new(RwLock::new(users_manager)),
         events_buffer: Arc::new(Mutex::new(AllocRingBuffer::with_capacity(512))),
-        console_out_buffer: Arc::new(Mutex::new(HashMap::new())),
+        console_out_buffer: Arc::new(Mutex::new(console_out_buffer)),
+        console_logs: Arc::new(Mutex::new(console_logs)),
         monitor_buffer: Arc::new(Mutex::new(HashMap::new())),
         event_broadcaster: tx.clone(),
         uuid: Uuid::new_v4().to_string(),
@@ -547,8 +689,12 @@ pub async fn run(
         playitgg_key: Arc::new(Mutex::new(playitgg_key)),
         system: Arc::new(Mutex::new(sysinfo::System::new_all())),
         download_urls: Arc::new(Mutex::new(HashMap::new())),
+        upload_sessions: UploadSessions::default(),
+        fs_op_limiter,
+        instance_holds: InstanceHolds::default(),
         playit_keep_running: Arc::new(Mutex::new(None)),
-        global_settings: Arc::new(Mutex::new(global_settings)),
+        read_only: ReadOnlyMode::default(),
+        global_settings,
         macro_executor,
         sqlite_pool: Pool::connect_with(
             SqliteConnectOptions::from_str(&format!(
@@ -577,23 +723,42 @@ pub async fn run(
     command_console::init(shared_state.clone());
     init_app_state(shared_state.clone());
 
-    for mut entry in shared_state.instances.iter_mut() {
-        let instance = entry.value_mut();
-        if instance.auto_start().await {
-            info!("Auto starting instance {}", instance.name().await);
-            if let Err(e) = instance.start(CausedBy::System, false).await {
-                error!(
-                    "Failed to start instance {}: {:?}",
-                    entry.value().name().await,
-                    e
-                );
-            }
+    let instances: Vec<GameInstance> = shared_state
+        .instances
+        .iter()
+        .map(|entry| entry.value().clone())
+        .collect();
+    for instance in instances {
+        if !instance.auto_start().await {
+            continue;
         }
+        // each instance waits on its own for the instances it depends on
+        let instances = shared_state.instances.clone();
+        let event_broadcaster = shared_state.event_broadcaster.clone();
+        let read_only = shared_state.read_only.clone();
+        tokio::spawn(async move {
+            let name = instance.name().await;
+            let result = async {
+                let dependencies =
+                    resolve_dependencies(&instances, instance.depends_on().await).await?;
+                wait_for_dependencies(&dependencies, &event_broadcaster, DEPENDENCY_START_TIMEOUT)
+                    .await?;
+                // read-only may have been turned on while waiting
+                read_only.refuse_changes()?;
+                info!("Auto starting instance {}", name);
+                instance.start(CausedBy::System, false).await
+            }
+            .await;
+            if let Err(e) = result {
+                error!("Failed to start instance {}: {:?}", name, e);
+            }
+        });
     }
 
     let event_buffer_task = {
         let event_buffer = shared_state.events_buffer.clone();
         let console_out_buffer = shared_state.console_out_buffer.clone();
+        let console_logs = shared_state.console_logs.clone();
         let mut event_receiver = tx.subscribe();
         async move {
             loop {
@@ -612,11 +777,20 @@ pub async fn run(
                 }
                 let event = result.unwrap();
                 if event.is_event_console_message() {
+                    if let Some(line) = console_output_line(&event) {
+                        if let Some(console_log) = console_logs
+                            .lock()
+                            .await
+                            .get_mut(&event.get_instance_uuid().unwrap())
+                        {
+                            console_log.append(line);
+                        }
+                    }
                     console_out_buffer
                         .lock()
                         .await
                         .entry(event.get_instance_uuid().unwrap())
-                        .or_insert_with(|| AllocRingBuffer::with_capacity(1024))
+                        .or_default()
                         .push(event.clone());
                 } else {
                     event_buffer.lock().await.push(event.clone());
@@ -647,6 +821,12 @@ pub async fn run(
         }
     };
 
+    let janitor_task = janitor::run_janitor(
+        shared_state.download_urls.clone(),
+        shared_state.global_settings.clone(),
+        path_to_tmp().clone(),
+    );
+
     let tls_config_result = RustlsConfig::from_pem_file(
         lodestone_path.join("tls").join("cert.pem"),
         lodestone_path.join("tls").join("key.pem"),
@@ -668,6 +848,7 @@ pub async fn run(
                         Method::OPTIONS,
                     ])
                     .allow_headers([header::ORIGIN, header::CONTENT_TYPE, header::AUTHORIZATION]) // Note I can't find X-Auth-Token but it was in the original rocket version, hope it's fine
+                    .expose_headers([header::HeaderName::from_static(CORRELATION_ID_HEADER)])
                     .allow_origin(Any);
 
                 let trace = TraceLayer::new_for_http();
@@ -692,6 +873,12 @@ pub async fn run(
                     .merge(get_gateway_routes(shared_state.clone()))
                     .merge(get_extension_routes(shared_state.clone()))
                     .merge(get_playitgg_routes(shared_state.clone()))
+                    .merge(get_remote_backup_routes(shared_state.clone()))
+                    .layer(axum::middleware::from_fn_with_state(
+                        shared_state.read_only.clone(),
+                        refuse_changes_when_read_only,
+                    ))
+                    .layer(axum::middleware::from_fn(correlate))
                     .layer(cors)
                     .layer(trace);
                 let app = Router::new().nest("/api/v1", api_routes);
@@ -741,6 +928,7 @@ pub async fn run(
                     _ = write_to_db_task => info!("Write to db task exited"),
                     _ = event_buffer_task => info!("Event buffer task exited"),
                     _ = monitor_report_task => info!("Monitor report task exited"),
+                    _ = janitor_task => info!("Janitor task exited"),
                     _ = shutdown_rx => info!("Shutdown signal received"),
                     _ = tokio::signal::ctrl_c() => info!("Ctrl+C received"),
                 }
@@ -808,3 +996,39 @@ pub async fn run(
         shutdown_tx,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_instance_configs_reports_corrupt_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let instances_path = temp.path();
+
+        let valid_path = instances_path.join("valid");
+        std::fs::create_dir_all(&valid_path).unwrap();
+        let valid_config =
+            DotLodestoneConfig::new(InstanceUuid::default(), GameType::MinecraftJava);
+        std::fs::write(
+            valid_path.join(".lodestone_config"),
+            serde_json::to_string(&valid_config).unwrap(),
+        )
+        .unwrap();
+
+        let corrupt_path = instances_path.join("corrupt");
+        std::fs::create_dir_all(&corrupt_path).unwrap();
+        std::fs::write(corrupt_path.join(".lodestone_config"), "{\"game_type\": 4").unwrap();
+
+        // stray files and folders next to the instances aren't instances
+        std::fs::write(instances_path.join("notes.txt"), "").unwrap();
+        std::fs::create_dir_all(instances_path.join("old_backups")).unwrap();
+
+        let (configs, failed) = scan_instance_configs(instances_path, vec![]).unwrap();
+        assert_eq!(configs, vec![(valid_path, valid_config)]);
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].path, corrupt_path.display().to_string());
+        assert_eq!(failed[0].uuid, None);
+        assert!(failed[0].error.contains(".lodestone_config"));
+    }
+}
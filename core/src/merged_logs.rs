@@ -0,0 +1,191 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::{NaiveDateTime, NaiveTime};
+use color_eyre::eyre::Context;
+
+use crate::error::Error;
+
+pub const MERGED_LOG_DEFAULT_LINES: usize = 200;
+pub const MERGED_LOG_MAX_LINES: usize = 2000;
+
+// read backwards from the end of a log in chunks this big until enough lines are found
+const TAIL_CHUNK_SIZE: u64 = 8 * 1024;
+
+/// The last `count` lines of the log at `path`, read from its end so a large log isn't loaded
+pub fn tail_lines(path: &Path, count: usize) -> Result<Vec<String>, Error> {
+    tail_lines_and_len(path, count).map(|(lines, _)| lines)
+}
+
+/// Like `tail_lines`, with the length of the log the lines were read up to, where following what
+/// is appended to it picks up
+pub fn tail_lines_and_len(path: &Path, count: usize) -> Result<(Vec<String>, u64), Error> {
+    let mut file =
+        std::fs::File::open(path).context(format!("Failed to open log {}", path.display()))?;
+    let len = file
+        .metadata()
+        .context(format!("Failed to read log {}", path.display()))?
+        .len();
+    let mut start = len;
+    let mut tail = Vec::new();
+    // one more line break than lines wanted, the first line read may be cut
+    while start > 0 && tail.iter().filter(|b| **b == b'\n').count() <= count {
+        let chunk_start = start.saturating_sub(TAIL_CHUNK_SIZE);
+        let mut chunk = vec![0; (start - chunk_start) as usize];
+        file.seek(SeekFrom::Start(chunk_start))
+            .and_then(|_| file.read_exact(&mut chunk))
+            .context(format!("Failed to read log {}", path.display()))?;
+        chunk.extend_from_slice(&tail);
+        tail = chunk;
+        start = chunk_start;
+    }
+    let tail = String::from_utf8_lossy(&tail);
+    let lines: Vec<&str> = tail.lines().collect();
+    Ok((
+        lines[lines.len().saturating_sub(count)..]
+            .iter()
+            .map(|line| line.to_string())
+            .collect(),
+        len,
+    ))
+}
+
+/// Time of day a server log line starts with, `[12:00:05] [Server thread/INFO]: ...` or
+/// `[12:00:05 INFO]: ...`
+fn line_time(line: &str) -> Option<NaiveTime> {
+    line.strip_prefix('[')
+        .and_then(|rest| rest.get(..8))
+        .and_then(|time| NaiveTime::parse_from_str(time, "%H:%M:%S").ok())
+}
+
+/// Date and time of each line of a log tail. Lines only carry a time of day, the date is worked
+/// back from `modified`, when the log was last written, a day earlier each time the clock goes
+/// past midnight. Lines without a time, e.g. of a stack trace, take the time of the line they
+/// continue
+fn timestamp_lines(lines: &[String], modified: NaiveDateTime) -> Vec<Option<NaiveDateTime>> {
+    let mut stamps = vec![None; lines.len()];
+    let mut date = modified.date();
+    let mut next_time = modified.time();
+    for (stamp, line) in stamps.iter_mut().zip(lines).rev() {
+        if let Some(time) = line_time(line) {
+            if time > next_time {
+                date = date.pred_opt().unwrap_or(date);
+            }
+            next_time = time;
+            *stamp = Some(date.and_time(time));
+        }
+    }
+    let first = stamps.iter().flatten().next().copied();
+    let mut previous = first;
+    for stamp in stamps.iter_mut() {
+        match stamp {
+            Some(stamp) => previous = Some(*stamp),
+            None => *stamp = previous,
+        }
+    }
+    stamps
+}
+
+/// Tail of a log to merge, `label` prefixes each of its lines
+pub struct LogTail {
+    pub label: String,
+    pub lines: Vec<String>,
+    pub modified: NaiveDateTime,
+}
+
+/// Interleave the tails by the time of their lines, prefixed with their label, and keep the
+/// last `count`. Lines at the same time stay grouped by log, in their order
+pub fn merge_log_tails(tails: &[LogTail], count: usize) -> Vec<String> {
+    let mut merged = Vec::new();
+    for (log_index, tail) in tails.iter().enumerate() {
+        let stamps = timestamp_lines(&tail.lines, tail.modified);
+        for (line_index, (line, stamp)) in tail.lines.iter().zip(stamps).enumerate() {
+            merged.push((stamp, log_index, line_index, line));
+        }
+    }
+    merged.sort_by_key(|(stamp, log_index, line_index, _)| (*stamp, *log_index, *line_index));
+    merged[merged.len().saturating_sub(count)..]
+        .iter()
+        .map(|(_, log_index, _, line)| format!("[{}] {line}", tails[*log_index].label))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn fixture(name: &str, label: &str, lines: usize) -> LogTail {
+        LogTail {
+            label: label.to_string(),
+            lines: tail_lines(Path::new("testdata").join(name).as_path(), lines).unwrap(),
+            modified: NaiveDate::from_ymd(2023, 6, 1).and_hms(12, 30, 0),
+        }
+    }
+
+    #[test]
+    fn test_merge_interleaves_by_time() {
+        let tails = [
+            fixture("proxy_latest.log", "proxy", 10),
+            fixture("survival_latest.log", "survival", 10),
+        ];
+        assert_eq!(tails[0].lines.len(), 6);
+
+        let merged = merge_log_tails(&tails, MERGED_LOG_DEFAULT_LINES);
+        assert_eq!(
+            merged,
+            vec![
+                "[proxy] [11:58:02] [main/INFO]: Loading Velocity 3.2.0",
+                "[proxy] [11:59:40] [Netty epoll Worker #1/INFO]: [connected player] Steve -> survival",
+                "[survival] [11:59:41] [Server thread/INFO]: Steve joined the game",
+                "[survival] [12:00:04] [Server thread/WARN]: Can't keep up! Is the server overloaded? Running 5012ms behind",
+                "[proxy] [12:00:05] [Netty epoll Worker #1/ERROR]: [server connection] Steve -> survival: exception encountered",
+                "[proxy] java.io.IOException: Connection reset by peer",
+                "[proxy] \tat io.netty.channel.unix.Errors.newIOException(Errors.java:117)",
+                "[survival] [12:00:05] [Server thread/INFO]: Steve lost connection: Disconnected",
+                "[proxy] [12:00:06] [Netty epoll Worker #1/INFO]: [connected player] Steve has disconnected",
+                "[survival] [12:00:07] [Server thread/INFO]: Steve left the game",
+            ]
+        );
+
+        // capped to the most recent lines, each log read from its end
+        let merged = merge_log_tails(&tails, 3);
+        assert_eq!(merged.len(), 3);
+        assert!(merged[2].starts_with("[survival] [12:00:07]"));
+        let tails = [
+            fixture("proxy_latest.log", "proxy", 1),
+            fixture("survival_latest.log", "survival", 1),
+        ];
+        assert_eq!(
+            merge_log_tails(&tails, MERGED_LOG_DEFAULT_LINES),
+            vec![
+                "[proxy] [12:00:06] [Netty epoll Worker #1/INFO]: [connected player] Steve has disconnected",
+                "[survival] [12:00:07] [Server thread/INFO]: Steve left the game",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lines_past_midnight_are_dated_back() {
+        let tails = [
+            LogTail {
+                label: "lobby".to_string(),
+                lines: vec![
+                    "[23:59:58] [Server thread/INFO]: Saving chunks".to_string(),
+                    "[00:00:02] [Server thread/INFO]: Alex joined the game".to_string(),
+                ],
+                modified: NaiveDate::from_ymd(2023, 6, 2).and_hms(0, 1, 0),
+            },
+            LogTail {
+                label: "survival".to_string(),
+                lines: vec!["[00:00:01 INFO]: Steve joined the game".to_string()],
+                modified: NaiveDate::from_ymd(2023, 6, 2).and_hms(0, 1, 0),
+            },
+        ];
+        let merged = merge_log_tails(&tails, MERGED_LOG_DEFAULT_LINES);
+        assert!(merged[0].starts_with("[lobby] [23:59:58]"));
+        assert!(merged[1].starts_with("[survival] [00:00:01"));
+        assert!(merged[2].starts_with("[lobby] [00:00:02]"));
+    }
+}
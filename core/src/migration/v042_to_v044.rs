@@ -4,7 +4,10 @@ use color_eyre::eyre::Context;
 use serde_json::{json, Value};
 use tracing::error;
 
-use crate::{error::Error, implementations::minecraft::RestoreConfig};
+use crate::{
+    error::Error,
+    implementations::minecraft::{RestoreConfig, DEFAULT_STOP_COMMAND},
+};
 
 use super::RestoreConfigV042;
 
@@ -25,6 +28,10 @@ impl From<RestoreConfigV042> for RestoreConfig {
             jre_major_version: config.jre_major_version,
             has_started: config.has_started,
             java_cmd: None,
+            custom_jar_path: None,
+            locked: false,
+            depends_on: Vec::new(),
+            stop_command: DEFAULT_STOP_COMMAND.to_string(),
         }
     }
 }
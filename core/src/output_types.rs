@@ -17,6 +17,8 @@ pub struct ClientEvent {
     pub snowflake: Snowflake,
     pub level: EventLevel,
     pub caused_by: CausedBy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
 }
 
 impl From<&Event> for ClientEvent {
@@ -59,6 +61,7 @@ impl From<&Event> for ClientEvent {
             snowflake: event.snowflake,
             level,
             caused_by: event.caused_by.clone(),
+            correlation_id: event.correlation_id.clone(),
         }
     }
 }
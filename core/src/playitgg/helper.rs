@@ -77,6 +77,7 @@ where
                 snowflake: Snowflake::default(),
                 details: "Starting runner".to_string(),
                 caused_by: CausedBy::System,
+                correlation_id: None,
             });
         }
 
@@ -208,6 +209,7 @@ where
                 snowflake: Snowflake::default(),
                 details: "Started".to_string(),
                 caused_by: CausedBy::System,
+                correlation_id: None,
             });
         }
 
@@ -156,6 +156,7 @@ pub async fn stop_cli(
                 snowflake: Snowflake::default(),
                 details: "Stopped".to_string(),
                 caused_by: CausedBy::System,
+                correlation_id: None,
             });
             keep_running.store(false, Ordering::SeqCst);
         }
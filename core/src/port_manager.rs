@@ -1,9 +1,18 @@
-use std::{collections::HashSet, net::SocketAddrV4};
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::SocketAddrV4,
+};
 
 use color_eyre::eyre::{eyre, Context};
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+use ts_rs::TS;
 
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
+use crate::prelude::GameInstance;
+use crate::traits::t_configurable::TConfigurable;
+use crate::types::InstanceUuid;
 
 pub struct PortManager {
     allocated_ports: HashSet<u32>,
@@ -51,6 +60,36 @@ impl PortManager {
         self.allocated_ports.remove(&port);
     }
 
+    /// Move an instance's allocation from `from` to `to`, refused if `to` belongs to another
+    /// instance or something else on the host listens on it
+    pub fn reallocate(&mut self, from: u32, to: u32) -> Result<(), Error> {
+        if to == from {
+            return Ok(());
+        }
+        if to == 0 || to > u16::MAX as u32 {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Port must be between 1 and {}", u16::MAX),
+            });
+        }
+        let status = self.port_status(to);
+        if status.is_allocated {
+            return Err(Error {
+                kind: ErrorKind::Conflict,
+                source: eyre!("Port {to} is used by another instance"),
+            });
+        }
+        if status.is_in_use {
+            return Err(Error {
+                kind: ErrorKind::Conflict,
+                source: eyre!("Port {to} is in use"),
+            });
+        }
+        self.deallocate(from);
+        self.add_port(to);
+        Ok(())
+    }
+
     pub async fn open_port(&self, port: u16) -> Result<(), Error> {
         tokio::task::spawn_blocking(move || {
             if let Ok(local_ip) = local_ip_address::local_ip() {
@@ -80,3 +119,88 @@ impl PortManager {
         .unwrap()
     }
 }
+
+/// A port configured on more than one instance, only one of them can start at a time
+#[derive(Debug, Serialize, Deserialize, Clone, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct PortConflict {
+    pub port: u32,
+    pub instances: Vec<InstanceUuid>,
+}
+
+/// Group the instances by port, instances that don't report a port (0) never conflict
+pub fn find_port_conflicts(
+    ports: impl IntoIterator<Item = (InstanceUuid, u32)>,
+) -> Vec<PortConflict> {
+    let mut by_port: BTreeMap<u32, Vec<InstanceUuid>> = BTreeMap::new();
+    for (uuid, port) in ports {
+        if port != 0 {
+            by_port.entry(port).or_default().push(uuid);
+        }
+    }
+    by_port
+        .into_iter()
+        .filter(|(_, instances)| instances.len() > 1)
+        .map(|(port, mut instances)| {
+            instances.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+            PortConflict { port, instances }
+        })
+        .collect()
+}
+
+pub async fn instance_port_conflicts(
+    instances: &DashMap<InstanceUuid, GameInstance>,
+) -> Vec<PortConflict> {
+    let mut ports = Vec::new();
+    for entry in instances.iter() {
+        ports.push((entry.key().clone(), entry.value().port().await));
+    }
+    find_port_conflicts(ports)
+}
+
+/// Run after the instances are loaded and whenever a config changes, so a conflict shows up
+/// in the logs before it shows up as a failed start
+pub async fn warn_port_conflicts(instances: &DashMap<InstanceUuid, GameInstance>) {
+    for conflict in instance_port_conflicts(instances).await {
+        warn!(
+            "Port {} is used by more than one instance: {}",
+            conflict.port,
+            conflict
+                .instances
+                .iter()
+                .map(|uuid| uuid.as_ref())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_conflicts_report_both_instances() {
+        let survival = InstanceUuid::from("INSTANCE_survival".to_string());
+        let creative = InstanceUuid::from("INSTANCE_creative".to_string());
+        let modded = InstanceUuid::from("INSTANCE_modded".to_string());
+        let generic = InstanceUuid::from("INSTANCE_generic".to_string());
+        let other_generic = InstanceUuid::from("INSTANCE_other_generic".to_string());
+
+        let conflicts = find_port_conflicts(vec![
+            (survival.clone(), 25565),
+            (modded, 25566),
+            (creative.clone(), 25565),
+            (generic, 0),
+            (other_generic, 0),
+        ]);
+        assert_eq!(
+            conflicts,
+            vec![PortConflict {
+                port: 25565,
+                instances: vec![creative, survival],
+            }]
+        );
+        assert!(find_port_conflicts(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+
+/// Absent while the instance protects the defaults
+pub const PROTECTED_PATHS_FILE_NAME: &str = ".lodestone_protected_paths.json";
+
+// what a Minecraft server runs or loads code from
+const DEFAULT_PROTECTED_EXTENSIONS: [&str; 10] = [
+    "jar",
+    "lua",
+    "sh",
+    "exe",
+    "bat",
+    "cmd",
+    "msi",
+    "lodestone_config",
+    "out",
+    "inf",
+];
+
+const DEFAULT_PROTECTED_DIR_NAMES: [&str; 1] = ["mods"];
+
+/// Files and directories of an instance only users with the global file permission may modify
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct ProtectedPaths {
+    /// without the leading dot, e.g. `jar`
+    pub extensions: Vec<String>,
+    /// protected wherever they are in the instance, e.g. `mods`
+    pub dir_names: Vec<String>,
+}
+
+impl Default for ProtectedPaths {
+    fn default() -> Self {
+        Self {
+            extensions: DEFAULT_PROTECTED_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            dir_names: DEFAULT_PROTECTED_DIR_NAMES
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl ProtectedPaths {
+    pub fn validate(&self) -> Result<(), Error> {
+        for ext in &self.extensions {
+            if ext.is_empty() || ext.starts_with('.') || ext.contains(['/', '\\']) {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("{ext:?} is not an extension, e.g. jar without the dot"),
+                });
+            }
+        }
+        for name in &self.dir_names {
+            if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("{name:?} is not a directory name"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn protects_extension(&self, ext: &str) -> bool {
+        self.extensions.iter().any(|protected| protected == ext)
+    }
+
+    pub fn protects_dir_name(&self, name: &str) -> bool {
+        self.dir_names.iter().any(|protected| protected == name)
+    }
+}
+
+pub async fn read_protected_paths(path_to_instance: &Path) -> ProtectedPaths {
+    // invalid protected paths read as the defaults rather than nothing protected, it is an
+    // access control
+    read_sidecar(
+        path_to_instance,
+        PROTECTED_PATHS_FILE_NAME,
+        "protected paths",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn write_protected_paths(
+    path_to_instance: &Path,
+    protected_paths: &ProtectedPaths,
+) -> Result<(), Error> {
+    if protected_paths == &ProtectedPaths::default() {
+        return remove_sidecar(path_to_instance, PROTECTED_PATHS_FILE_NAME).await;
+    }
+    write_sidecar(
+        path_to_instance,
+        PROTECTED_PATHS_FILE_NAME,
+        "protected paths",
+        protected_paths,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_protected_paths_persist_and_default() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let defaults = read_protected_paths(root).await;
+        assert!(defaults.protects_extension("jar"));
+        assert!(defaults.protects_dir_name("mods"));
+        assert!(!defaults.protects_extension("plr"));
+
+        // a Terraria server
+        let terraria = ProtectedPaths {
+            extensions: vec!["plr".to_string(), "wld".to_string(), "exe".to_string()],
+            dir_names: Vec::new(),
+        };
+        terraria.validate().unwrap();
+        write_protected_paths(root, &terraria).await.unwrap();
+        let read = read_protected_paths(root).await;
+        assert_eq!(read, terraria);
+        assert!(read.protects_extension("plr"));
+        assert!(!read.protects_extension("jar"));
+        assert!(!read.protects_dir_name("mods"));
+
+        // back to the defaults, the sidecar is removed
+        write_protected_paths(root, &ProtectedPaths::default())
+            .await
+            .unwrap();
+        assert!(!root.join(PROTECTED_PATHS_FILE_NAME).exists());
+
+        std::fs::write(root.join(PROTECTED_PATHS_FILE_NAME), "{").unwrap();
+        assert_eq!(read_protected_paths(root).await, ProtectedPaths::default());
+
+        for invalid in [
+            ProtectedPaths {
+                extensions: vec![".jar".to_string()],
+                dir_names: Vec::new(),
+            },
+            ProtectedPaths {
+                extensions: Vec::new(),
+                dir_names: vec!["world/region".to_string()],
+            },
+        ] {
+            assert!(matches!(
+                invalid.validate().unwrap_err().kind,
+                ErrorKind::BadRequest
+            ));
+        }
+    }
+}
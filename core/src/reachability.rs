@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use color_eyre::eyre::Context;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub enum ReachabilityStatus {
+    Reachable,
+    Unreachable,
+    /// the external probe is disabled or failed
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct ReachabilityReport {
+    pub port: u32,
+    /// whether anything accepts connections on the port from this machine
+    pub listening_locally: bool,
+    pub status: ReachabilityStatus,
+    /// public address of the core as seen by the probe
+    pub public_address: Option<String>,
+    pub message: Option<String>,
+}
+
+/// What the external probe service answers to `GET <probe_url>?port=<port>`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ProbeResponse {
+    pub reachable: bool,
+    pub address: Option<String>,
+}
+
+#[async_trait]
+pub trait ReachabilityProbe: Send + Sync {
+    async fn probe(&self, port: u32) -> Result<ProbeResponse, Error>;
+}
+
+/// Probe service reached over http, only used when the owner configured one
+pub struct HttpProbe {
+    url: String,
+}
+
+impl HttpProbe {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+#[async_trait]
+impl ReachabilityProbe for HttpProbe {
+    async fn probe(&self, port: u32) -> Result<ProbeResponse, Error> {
+        let external = |source| Error {
+            kind: ErrorKind::External,
+            source,
+        };
+        let response = reqwest::Client::new()
+            .get(&self.url)
+            .query(&[("port", port)])
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+            .context("Failed to reach the reachability probe")
+            .map_err(external)?;
+        response
+            .error_for_status_ref()
+            .context("Reachability probe returned an error")
+            .map_err(external)?;
+        response
+            .json::<ProbeResponse>()
+            .await
+            .context("Invalid response from the reachability probe")
+            .map_err(external)
+    }
+}
+
+pub async fn is_listening_locally(port: u32) -> bool {
+    let Ok(port) = u16::try_from(port) else {
+        return false;
+    };
+    matches!(
+        tokio::time::timeout(
+            Duration::from_secs(2),
+            tokio::net::TcpStream::connect(("127.0.0.1", port)),
+        )
+        .await,
+        Ok(Ok(_))
+    )
+}
+
+/// Build a report for the port, the probe is `None` when the external check is disabled
+pub async fn check_reachability(
+    port: u32,
+    probe: Option<&dyn ReachabilityProbe>,
+) -> ReachabilityReport {
+    let listening_locally = is_listening_locally(port).await;
+    let not_listening_hint = if listening_locally {
+        None
+    } else {
+        Some("Nothing is listening on this port locally, is the instance running?".to_string())
+    };
+    let Some(probe) = probe else {
+        return ReachabilityReport {
+            port,
+            listening_locally,
+            status: ReachabilityStatus::Unknown,
+            public_address: None,
+            message: Some(
+                not_listening_hint.unwrap_or_else(|| {
+                    "External reachability probe is disabled, set a probe url in the global settings to enable it".to_string()
+                }),
+            ),
+        };
+    };
+    match probe.probe(port).await {
+        Ok(ProbeResponse { reachable, address }) => ReachabilityReport {
+            port,
+            listening_locally,
+            status: if reachable {
+                ReachabilityStatus::Reachable
+            } else {
+                ReachabilityStatus::Unreachable
+            },
+            public_address: address,
+            message: if reachable {
+                None
+            } else {
+                not_listening_hint.or_else(|| {
+                    Some(
+                        "The port is open locally but can't be reached from outside, check your firewall and port forwarding"
+                            .to_string(),
+                    )
+                })
+            },
+        },
+        Err(e) => ReachabilityReport {
+            port,
+            listening_locally,
+            status: ReachabilityStatus::Unknown,
+            public_address: None,
+            message: Some(format!("Reachability probe failed: {}", e.source)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color_eyre::eyre::eyre;
+
+    // answers with the given response, or fails when there is none
+    struct MockProbe(Option<ProbeResponse>);
+
+    #[async_trait]
+    impl ReachabilityProbe for MockProbe {
+        async fn probe(&self, _port: u32) -> Result<ProbeResponse, Error> {
+            self.0.clone().ok_or_else(|| Error {
+                kind: ErrorKind::External,
+                source: eyre!("probe is down"),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reachability_outcomes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port() as u32;
+
+        let reachable = MockProbe(Some(ProbeResponse {
+            reachable: true,
+            address: Some("203.0.113.7".to_string()),
+        }));
+        let report = check_reachability(port, Some(&reachable)).await;
+        assert!(report.listening_locally);
+        assert_eq!(report.status, ReachabilityStatus::Reachable);
+        assert_eq!(report.public_address.as_deref(), Some("203.0.113.7"));
+        assert_eq!(report.message, None);
+
+        let unreachable = MockProbe(Some(ProbeResponse {
+            reachable: false,
+            address: Some("203.0.113.7".to_string()),
+        }));
+        let report = check_reachability(port, Some(&unreachable)).await;
+        assert_eq!(report.status, ReachabilityStatus::Unreachable);
+        assert!(report.message.unwrap().contains("firewall"));
+
+        let failing = MockProbe(None);
+        let report = check_reachability(port, Some(&failing)).await;
+        assert_eq!(report.status, ReachabilityStatus::Unknown);
+        assert!(report.message.unwrap().contains("probe is down"));
+
+        let report = check_reachability(port, None).await;
+        assert_eq!(report.status, ReachabilityStatus::Unknown);
+        assert_eq!(report.public_address, None);
+        assert!(report.message.unwrap().contains("disabled"));
+    }
+
+    #[tokio::test]
+    async fn test_not_listening_locally() {
+        // grab a free port and release it
+        let port = {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            listener.local_addr().unwrap().port() as u32
+        };
+        assert!(!is_listening_locally(port).await);
+        assert!(!is_listening_locally(u32::MAX).await);
+
+        let unreachable = MockProbe(Some(ProbeResponse {
+            reachable: false,
+            address: None,
+        }));
+        let report = check_reachability(port, Some(&unreachable)).await;
+        assert!(!report.listening_locally);
+        assert!(report.message.unwrap().contains("is the instance running"));
+    }
+}
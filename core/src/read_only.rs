@@ -0,0 +1,188 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use color_eyre::eyre::eyre;
+
+use crate::error::{Error, ErrorKind};
+
+/// Requests that change nothing, or are needed during maintenance, let through while
+/// read-only: turning the mode off, logging in and out, validating, searching and downloading,
+/// and cancelling work
+const ALLOWED_WHILE_READ_ONLY: [(Method, &str); 11] = [
+    (Method::PUT, "/info/read_only"),
+    (Method::POST, "/user/login"),
+    (Method::POST, "/user/logout/:uid"),
+    (Method::POST, "/instance/validate"),
+    (
+        Method::POST,
+        "/instance/:uuid/fs/:base64_relative_path/search",
+    ),
+    (Method::POST, "/instance/:uuid/export"),
+    (Method::PUT, "/instance/:uuid/fs/download-selection"),
+    (Method::DELETE, "/instance/:uuid/operations"),
+    (
+        Method::PUT,
+        "/instance/:uuid/fs/progression/:event_id/cancel",
+    ),
+    // aliases of the progression cancel route
+    (Method::DELETE, "/instance/:uuid/fs/upload/:event_id"),
+    (Method::DELETE, "/instance/:uuid/fs/operation/:event_id"),
+];
+
+/// Whether the daemon refuses every change, for maintenance windows. Kept in memory only, the
+/// daemon always starts writable
+#[derive(Debug, Clone, Default)]
+pub struct ReadOnlyMode(Arc<AtomicBool>);
+
+impl ReadOnlyMode {
+    pub fn is_on(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn set(&self, on: bool) {
+        self.0.store(on, Ordering::SeqCst);
+    }
+
+    /// For changes made outside of a request, e.g. auto-starts and macros, which the middleware
+    /// doesn't see
+    pub fn refuse_changes(&self) -> Result<(), Error> {
+        if self.is_on() {
+            Err(read_only_error())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Whether `path` is an instance of `route`, whose `:param` segments match any segment
+fn matches_route(route: &str, path: &str) -> bool {
+    let (route, path) = (route.split('/'), path.split('/'));
+    route.clone().count() == path.clone().count()
+        && route
+            .zip(path)
+            .all(|(expected, segment)| expected.starts_with(':') || expected == segment)
+}
+
+/// Everything but reads changes something, starting an instance or editing a config included
+fn is_mutating(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => false,
+        _ => !ALLOWED_WHILE_READ_ONLY
+            .iter()
+            .any(|(allowed, route)| allowed == method && matches_route(route, path)),
+    }
+}
+
+pub fn read_only_error() -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Lodestone is in read-only maintenance mode, changes are refused"),
+    }
+}
+
+/// Refuse the requests that would change something while the daemon is read-only, reads and
+/// listings go through
+pub async fn refuse_changes_when_read_only<B>(
+    State(read_only): State<ReadOnlyMode>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if read_only.is_on() && is_mutating(request.method(), request.uri().path()) {
+        return read_only_error().into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::StatusCode,
+        routing::{get, post, put},
+        Router,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_are_refused_while_read_only() {
+        let read_only = ReadOnlyMode::default();
+        let app = Router::new()
+            .route(
+                "/instance/fs/server.properties",
+                get(|| async { "motd=hi" }).put(|| async { "written" }),
+            )
+            .route("/info/read_only", put(|| async {}))
+            .route("/instance/validate", post(|| async { "valid" }))
+            .route("/instance/create", post(|| async { "created" }))
+            .layer(axum::middleware::from_fn_with_state(
+                read_only.clone(),
+                refuse_changes_when_read_only,
+            ));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        let client = reqwest::Client::new();
+        let file = format!("{url}/instance/fs/server.properties");
+
+        let response = client.put(&file).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        read_only.set(true);
+        let response = client.put(&file).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("read-only maintenance mode"));
+        let response = client.get(&file).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), "motd=hi");
+        // POSTs that only read go through, the others are refused
+        let response = client
+            .post(format!("{url}/instance/validate"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = client
+            .post(format!("{url}/instance/create"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert!(!is_mutating(&Method::POST, "/instance/abc/fs/-/search"));
+        // so does what's needed during maintenance: downloads and cancelling work
+        assert!(!is_mutating(&Method::POST, "/instance/abc/export"));
+        assert!(!is_mutating(
+            &Method::PUT,
+            "/instance/abc/fs/progression/42/cancel"
+        ));
+        assert!(!is_mutating(&Method::DELETE, "/instance/abc/operations"));
+        assert!(!is_mutating(&Method::POST, "/user/logout/alice"));
+        assert!(is_mutating(&Method::DELETE, "/instance/abc/fs/-/rm"));
+        assert!(read_only.refuse_changes().is_err());
+        // the mode can still be turned off
+        let response = client
+            .put(format!("{url}/info/read_only"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        read_only.set(false);
+        let response = client.put(&file).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(read_only.refuse_changes().is_ok());
+    }
+}
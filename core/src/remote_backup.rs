@@ -0,0 +1,479 @@
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context};
+use futures::StreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_LENGTH;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+
+/// Kept in the stores directory, readable by the core's user only
+pub const REMOTE_BACKUP_TARGET_FILE_NAME: &str = "remote_backup_target.json";
+
+/// An S3-compatible object store (AWS, MinIO, Backblaze B2, ...) that world archives are pushed to.
+/// Objects are addressed path-style as `{endpoint}/{bucket}/{key}`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RemoteBackupTarget {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// prepended to the object keys, without trailing slash
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+// the credentials must not end up in logs
+impl std::fmt::Debug for RemoteBackupTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteBackupTarget")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("access_key_id", &redact(&self.access_key_id))
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What is shown of a target, the secret is never sent back
+#[derive(Serialize, Deserialize, Clone, Debug, TS, PartialEq, Eq)]
+#[ts(export)]
+pub struct RedactedRemoteBackupTarget {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub prefix: Option<String>,
+}
+
+fn redact(secret: &str) -> String {
+    let visible: String = secret
+        .chars()
+        .skip(secret.chars().count().saturating_sub(4))
+        .collect();
+    if secret.chars().count() <= 8 {
+        "****".to_string()
+    } else {
+        format!("****{visible}")
+    }
+}
+
+impl RemoteBackupTarget {
+    pub fn redacted(&self) -> RedactedRemoteBackupTarget {
+        RedactedRemoteBackupTarget {
+            endpoint: self.endpoint.clone(),
+            bucket: self.bucket.clone(),
+            region: self.region.clone(),
+            access_key_id: redact(&self.access_key_id),
+            prefix: self.prefix.clone(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let endpoint = url::Url::parse(&self.endpoint).map_err(|e| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Invalid endpoint: {e}"),
+        })?;
+        if !matches!(endpoint.scheme(), "http" | "https") || endpoint.host_str().is_none() {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Endpoint must be an http(s) url"),
+            });
+        }
+        for (name, value) in [
+            ("bucket", &self.bucket),
+            ("region", &self.region),
+            ("access key id", &self.access_key_id),
+            ("secret access key", &self.secret_access_key),
+        ] {
+            if value.is_empty() {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("The {name} cannot be empty"),
+                });
+            }
+        }
+        if self.bucket.contains('/') {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Bucket name cannot contain '/'"),
+            });
+        }
+        Ok(())
+    }
+
+    /// Key of an object under the configured prefix
+    pub fn object_key(&self, name: &str) -> String {
+        match self.prefix.as_deref().map(|p| p.trim_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => format!("{prefix}/{name}"),
+            _ => name.to_string(),
+        }
+    }
+}
+
+pub async fn read_remote_backup_target(
+    path_to_stores: &Path,
+) -> Result<Option<RemoteBackupTarget>, Error> {
+    let path = path_to_stores.join(REMOTE_BACKUP_TARGET_FILE_NAME);
+    match tokio::fs::read(&path).await {
+        Ok(content) => Ok(Some(
+            serde_json::from_slice(&content).context("Invalid remote backup target")?,
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e)
+            .context(format!(
+                "Failed to read remote backup target at {}",
+                path.display()
+            ))
+            .map_err(Error::from),
+    }
+}
+
+pub async fn write_remote_backup_target(
+    path_to_stores: &Path,
+    target: &RemoteBackupTarget,
+) -> Result<(), Error> {
+    let path = path_to_stores.join(REMOTE_BACKUP_TARGET_FILE_NAME);
+    let content =
+        serde_json::to_string_pretty(target).context("Failed to serialize remote backup target")?;
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    // only the owner can read the credentials, from the moment the file exists
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(&path)
+        .await
+        .context(format!("Failed to open {}", path.display()))?;
+    // the mode is only applied to a new file, an existing one is restricted before it is written
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))
+            .await
+            .context(format!(
+                "Failed to restrict permissions of {}",
+                path.display()
+            ))?;
+    }
+    file.write_all(content.as_bytes())
+        .await
+        .context(format!("Failed to write to file at {}", path.display()))?;
+    file.flush()
+        .await
+        .context(format!("Failed to write to file at {}", path.display()))?;
+    Ok(())
+}
+
+pub async fn remove_remote_backup_target(path_to_stores: &Path) -> Result<(), Error> {
+    crate::util::fs::remove_file(path_to_stores.join(REMOTE_BACKUP_TARGET_FILE_NAME)).await
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// AWS signature version 4 signing key, derived from the secret for one day, region and service
+fn signing_key(secret_access_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let key = hmac_sha256(format!("AWS4{secret_access_key}").as_bytes(), date);
+    let key = hmac_sha256(&key, region);
+    let key = hmac_sha256(&key, service);
+    hmac_sha256(&key, "aws4_request")
+}
+
+/// Percent-encode every byte of a key except the unreserved characters and `/`
+fn encode_uri_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Url and headers of a signed `PutObject` request. The payload is not part of the
+/// signature so the archive can be streamed without being read twice
+fn signed_put_object(
+    target: &RemoteBackupTarget,
+    key: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Result<(url::Url, Vec<(&'static str, String)>), Error> {
+    let canonical_uri = format!(
+        "/{}/{}",
+        encode_uri_path(&target.bucket),
+        encode_uri_path(key)
+    );
+    let endpoint = url::Url::parse(&target.endpoint).context("Invalid endpoint")?;
+    let url = endpoint
+        .join(&format!(
+            "{}{canonical_uri}",
+            endpoint.path().trim_end_matches('/')
+        ))
+        .context("Invalid object url")?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!("Endpoint has no host"),
+            })
+        }
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let payload_hash = "UNSIGNED-PAYLOAD";
+    let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "PUT\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+        url.path()
+    );
+    let scope = format!("{date}/{}/s3/aws4_request", target.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let signature = hex::encode(hmac_sha256(
+        &signing_key(&target.secret_access_key, &date, &target.region, "s3"),
+        &string_to_sign,
+    ));
+    Ok((
+        url,
+        vec![
+            ("x-amz-content-sha256", payload_hash.to_string()),
+            ("x-amz-date", amz_date),
+            (
+                "authorization",
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                    target.access_key_id
+                ),
+            ),
+        ],
+    ))
+}
+
+/// Stream a local archive to the target, `on_progress` gets the number of bytes sent so far.
+/// The local archive is left as is whatever the outcome
+pub async fn upload_to_remote_target(
+    target: &RemoteBackupTarget,
+    archive: &Path,
+    key: &str,
+    mut on_progress: impl FnMut(u64) + Send + 'static,
+) -> Result<(), Error> {
+    let size = tokio::fs::metadata(archive)
+        .await
+        .context(format!("Failed to read metadata of {}", archive.display()))?
+        .len();
+    let file = tokio::fs::File::open(archive)
+        .await
+        .context(format!("Failed to open {}", archive.display()))?;
+    let mut sent = 0;
+    let body = ReaderStream::new(file).map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            sent += chunk.len() as u64;
+            on_progress(sent);
+        }
+        chunk
+    });
+
+    let (url, headers) = signed_put_object(target, key, chrono::Utc::now())?;
+    let mut request = reqwest::Client::new()
+        .put(url)
+        .header(CONTENT_LENGTH, size)
+        .body(reqwest::Body::wrap_stream(body));
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.send().await.map_err(|e| Error {
+        kind: ErrorKind::External,
+        source: eyre!("Failed to reach the backup target: {e}"),
+    })?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        return Err(Error {
+            kind: ErrorKind::External,
+            source: eyre!("Backup target rejected the upload with {status}: {body}"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        body::Bytes,
+        extract::{Path as AxumPath, State},
+        http::{HeaderMap, StatusCode},
+        routing::put,
+        Router,
+    };
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct ObjectStore {
+        objects: Arc<Mutex<Vec<(String, HeaderMap, Bytes)>>>,
+        fail: bool,
+    }
+
+    async fn put_object(
+        State(store): State<ObjectStore>,
+        AxumPath(path): AxumPath<String>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        if store.fail {
+            return StatusCode::INTERNAL_SERVER_ERROR;
+        }
+        store.objects.lock().unwrap().push((path, headers, body));
+        StatusCode::OK
+    }
+
+    fn serve(store: ObjectStore) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/*path", put(put_object))
+            .with_state(store);
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app.into_make_service()),
+        );
+        format!("http://{addr}")
+    }
+
+    fn target(endpoint: String) -> RemoteBackupTarget {
+        RemoteBackupTarget {
+            endpoint,
+            bucket: "backups".to_string(),
+            region: "us-east-1".to_string(),
+            access_key_id: "AKIDEXAMPLE1234".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            prefix: Some("lodestone/".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_signing_key() {
+        // example from the AWS signature version 4 documentation
+        assert_eq!(
+            hex::encode(signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam"
+            )),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn test_target_is_redacted() {
+        let target = target("https://s3.example.com".to_string());
+        let redacted = serde_json::to_string(&target.redacted()).unwrap();
+        assert!(!redacted.contains(&target.secret_access_key));
+        assert!(!redacted.contains(&target.access_key_id));
+        assert!(redacted.contains("****1234"));
+        let debug = format!("{target:?}");
+        assert!(!debug.contains(&target.secret_access_key));
+        assert!(!debug.contains(&target.access_key_id));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_target_is_only_readable_by_the_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(REMOTE_BACKUP_TARGET_FILE_NAME);
+        let mode = || std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        let target = target("https://s3.example.com".to_string());
+
+        write_remote_backup_target(temp.path(), &target)
+            .await
+            .unwrap();
+        assert_eq!(mode(), 0o600);
+        // a file left readable by others is restricted before the credentials are written
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_remote_backup_target(temp.path(), &target)
+            .await
+            .unwrap();
+        assert_eq!(mode(), 0o600);
+        assert_eq!(
+            read_remote_backup_target(temp.path()).await.unwrap(),
+            Some(target)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_upload_to_mocked_object_store() {
+        let store = ObjectStore::default();
+        let target = target(serve(store.clone()));
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("world.zip");
+        let content = vec![7u8; 300 * 1024];
+        std::fs::write(&archive, &content).unwrap();
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let key = target.object_key("instance/world 1.zip");
+        upload_to_remote_target(&target, &archive, &key, {
+            let progress = progress.clone();
+            move |sent| progress.lock().unwrap().push(sent)
+        })
+        .await
+        .unwrap();
+
+        let objects = store.objects.lock().unwrap();
+        let (path, headers, body) = &objects[0];
+        assert_eq!(path, "backups/lodestone/instance/world 1.zip");
+        assert_eq!(body.as_ref(), content.as_slice());
+        assert!(headers["authorization"]
+            .to_str()
+            .unwrap()
+            .starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE1234/"));
+        assert_eq!(headers["content-length"], content.len().to_string());
+
+        let progress = progress.lock().unwrap();
+        assert!(progress.len() > 1);
+        assert!(progress.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(*progress.last().unwrap(), content.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_keeps_local_archive() {
+        let store = ObjectStore {
+            fail: true,
+            ..Default::default()
+        };
+        let target = target(serve(store));
+        let temp = tempfile::tempdir().unwrap();
+        let archive = temp.path().join("world.zip");
+        std::fs::write(&archive, b"archive").unwrap();
+
+        let err = upload_to_remote_target(&target, &archive, "world.zip", |_| {})
+            .await
+            .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::External));
+        assert_eq!(std::fs::read(&archive).unwrap(), b"archive");
+    }
+}
@@ -0,0 +1,305 @@
+use std::path::Path;
+use std::time::Duration;
+
+use color_eyre::eyre::{eyre, Context};
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::error::{Error, ErrorKind};
+
+// a redirect chain longer than this is most likely a loop
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct FetchLimits {
+    pub max_bytes: u64,
+    /// for the whole transfer, not only the connection
+    pub timeout: Duration,
+}
+
+/// Whether the host of `url` is allowed, `*.example.com` allows every subdomain of
+/// `example.com` but not `example.com` itself. An empty allowlist allows nothing
+pub fn is_host_allowed(url: &url::Url, allowed_hosts: &[String]) -> bool {
+    let Some(host) = url.host_str().map(|host| host.to_ascii_lowercase()) else {
+        return false;
+    };
+    allowed_hosts.iter().any(|allowed| {
+        let allowed = allowed.to_ascii_lowercase();
+        match allowed.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .map_or(false, |subdomain| subdomain.ends_with('.')),
+            None => host == allowed,
+        }
+    })
+}
+
+fn parse_fetch_url(url: &str, allowed_hosts: &[String]) -> Result<url::Url, Error> {
+    let parsed = url::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Invalid url {url}, expected an http(s) url"),
+        })?;
+    if !is_host_allowed(&parsed, allowed_hosts) {
+        return Err(Error {
+            kind: ErrorKind::PermissionDenied,
+            source: eyre!(
+                "Fetching from {} is not allowed, the host must be in the allowed hosts",
+                parsed.host_str().unwrap_or_default()
+            ),
+        });
+    }
+    Ok(parsed)
+}
+
+fn too_large(max_bytes: u64) -> Error {
+    Error {
+        kind: ErrorKind::BadRequest,
+        source: eyre!("Remote file is larger than the {max_bytes} bytes limit"),
+    }
+}
+
+/// A remote file whose response has started, its body is not read yet
+pub struct RemoteFile {
+    response: reqwest::Response,
+    max_bytes: u64,
+}
+
+/// Request a remote file from an allowed host. Redirects are followed only to allowed hosts
+pub async fn open_remote_file(
+    url: &str,
+    allowed_hosts: &[String],
+    limits: FetchLimits,
+) -> Result<RemoteFile, Error> {
+    let url = parse_fetch_url(url, allowed_hosts)?;
+    let redirect_hosts = allowed_hosts.to_vec();
+    let client = reqwest::Client::builder()
+        .timeout(limits.timeout)
+        .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                attempt.error("Too many redirects")
+            } else if is_host_allowed(attempt.url(), &redirect_hosts) {
+                attempt.follow()
+            } else {
+                let host = attempt.url().host_str().unwrap_or_default().to_string();
+                attempt.error(format!("Redirect to {host} is not allowed"))
+            }
+        }))
+        .build()
+        .context("Failed to build http client")?;
+    let response = client
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| Error {
+            kind: ErrorKind::External,
+            source: eyre!("Failed to fetch {url}: {e}"),
+        })?;
+    if response.content_length().unwrap_or(0) > limits.max_bytes {
+        return Err(too_large(limits.max_bytes));
+    }
+    Ok(RemoteFile {
+        response,
+        max_bytes: limits.max_bytes,
+    })
+}
+
+impl RemoteFile {
+    /// Size announced by the remote, if any
+    pub fn content_length(&self) -> Option<u64> {
+        self.response.content_length()
+    }
+
+    /// Stream the body to `path`, `on_chunk` is called with the size of each chunk. The file is
+    /// downloaded next to `path` and only replaces it once complete, nothing is left behind
+    /// if the transfer fails or is cancelled
+    pub async fn save_to(
+        self,
+        path: &Path,
+        cancel: &CancellationToken,
+        mut on_chunk: impl FnMut(u64),
+    ) -> Result<u64, Error> {
+        let dir = path.parent().ok_or_else(|| Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("Path {} has no parent directory", path.display()),
+        })?;
+        let temp_path = tempfile::Builder::new()
+            .prefix(".fetch-")
+            .tempfile_in(dir)
+            .context("Failed to create temporary file")?
+            .into_temp_path();
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(&temp_path)
+            .await
+            .context("Failed to open temporary file")?;
+        let mut received = 0_u64;
+        let mut body = self.response.bytes_stream();
+        loop {
+            let chunk = tokio::select! {
+                _ = cancel.cancelled() => {
+                    return Err(Error {
+                        kind: ErrorKind::Conflict,
+                        source: eyre!("Fetch cancelled"),
+                    });
+                }
+                chunk = body.next() => chunk,
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            let chunk = chunk.map_err(|e| Error {
+                kind: ErrorKind::External,
+                source: eyre!("Failed to fetch remote file: {e}"),
+            })?;
+            received += chunk.len() as u64;
+            if received > self.max_bytes {
+                return Err(too_large(self.max_bytes));
+            }
+            file.write_all(&chunk)
+                .await
+                .context("Failed to write fetched file")?;
+            on_chunk(chunk.len() as u64);
+        }
+        file.sync_all()
+            .await
+            .context("Failed to write fetched file")?;
+        drop(file);
+        temp_path
+            .persist(path)
+            .context(format!("Failed to move fetched file to {}", path.display()))?;
+        Ok(received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{response::Redirect, routing::get, Router};
+
+    use super::*;
+
+    const LIMITS: FetchLimits = FetchLimits {
+        max_bytes: 1024,
+        timeout: Duration::from_secs(10),
+    };
+
+    fn serve_mock_source() -> u16 {
+        let source = Router::new()
+            .route(
+                "/config/lithium.properties",
+                get(|| async { "mixin.ai.pathing=false\n" }),
+            )
+            .route("/mods/huge.jar", get(|| async { vec![0_u8; 4096] }))
+            .route(
+                "/latest",
+                get(|| async { Redirect::temporary("/config/lithium.properties") }),
+            );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(source.into_make_service()),
+        );
+        port
+    }
+
+    #[tokio::test]
+    async fn test_fetch_from_allowed_host() {
+        let port = serve_mock_source();
+        let allowed = vec!["127.0.0.1".to_string()];
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("lithium.properties");
+        std::fs::write(&path, "old").unwrap();
+
+        let remote = open_remote_file(&format!("http://127.0.0.1:{port}/latest"), &allowed, LIMITS)
+            .await
+            .unwrap();
+        assert_eq!(remote.content_length(), Some(23));
+        let mut reported = 0;
+        let received = remote
+            .save_to(&path, &CancellationToken::new(), |len| reported += len)
+            .await
+            .unwrap();
+        assert_eq!((received, reported), (23, 23));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "mixin.ai.pathing=false\n"
+        );
+        assert_eq!(std::fs::read_dir(temp.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rejects_hosts_outside_allowlist() {
+        let port = serve_mock_source();
+        let url = format!("http://127.0.0.1:{port}/config/lithium.properties");
+
+        let err = open_remote_file(&url, &["cdn.modrinth.com".to_string()], LIMITS)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        let err = open_remote_file(&url, &[], LIMITS).await.err().unwrap();
+        assert!(matches!(err.kind, ErrorKind::PermissionDenied));
+        let err = open_remote_file("file:///etc/passwd", &["*".to_string()], LIMITS)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+
+        // redirected to a host that is not allowed
+        let redirecting = Router::new().route(
+            "/mod.jar",
+            get(move || async move {
+                Redirect::temporary(&format!(
+                    "http://localhost:{port}/config/lithium.properties"
+                ))
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let redirecting_port = listener.local_addr().unwrap().port();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(redirecting.into_make_service()),
+        );
+        let err = open_remote_file(
+            &format!("http://127.0.0.1:{redirecting_port}/mod.jar"),
+            &["127.0.0.1".to_string()],
+            LIMITS,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err.kind, ErrorKind::External));
+
+        // above the size cap
+        let err = open_remote_file(
+            &format!("http://127.0.0.1:{port}/mods/huge.jar"),
+            &["127.0.0.1".to_string()],
+            LIMITS,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(matches!(err.kind, ErrorKind::BadRequest));
+
+        let allowed = vec!["*.modrinth.com".to_string(), "github.com".to_string()];
+        for (url, expected) in [
+            ("https://cdn.modrinth.com/data/AANobbMI/lithium.jar", true),
+            ("https://CDN.Modrinth.com/data/AANobbMI/lithium.jar", true),
+            ("https://modrinth.com/mod/lithium", false),
+            ("https://evilmodrinth.com/lithium.jar", false),
+            ("https://github.com/CaffeineMC/lithium-fabric", true),
+            ("https://api.github.com/repos", false),
+        ] {
+            let url = url::Url::parse(url).unwrap();
+            assert_eq!(is_host_allowed(&url, &allowed), expected, "{url}");
+        }
+    }
+}
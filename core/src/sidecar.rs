@@ -0,0 +1,95 @@
+//! Files Lodestone keeps in an instance directory next to the game's own, named `.lodestone_*`.
+//!
+//! They hold settings and state of the instance on this host, so they are protected from users
+//! without the global file permission and left out of exports and seeds
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use color_eyre::eyre::Context;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::error::Error;
+
+const SIDECAR_PREFIX: &str = ".lodestone";
+
+/// Whether a file name is one of the sidecars, e.g. `.lodestone_config`
+pub fn is_sidecar_name(file_name: &OsStr) -> bool {
+    file_name.to_string_lossy().starts_with(SIDECAR_PREFIX)
+}
+
+pub fn is_sidecar(path: &Path) -> bool {
+    path.file_name().map_or(false, is_sidecar_name)
+}
+
+/// A sidecar of the instance, `None` while it is absent. An invalid sidecar is logged and read
+/// as absent, `description` names it in the warning
+pub async fn read_sidecar<T: DeserializeOwned>(
+    path_to_instance: &Path,
+    file_name: &str,
+    description: &str,
+) -> Option<T> {
+    let content = tokio::fs::read(path_to_instance.join(file_name))
+        .await
+        .ok()?;
+    serde_json::from_slice(&content)
+        .map_err(|e| {
+            warn!(
+                "Invalid {description} for instance at {}: {e}",
+                path_to_instance.display()
+            )
+        })
+        .ok()
+}
+
+pub async fn write_sidecar<T: Serialize>(
+    path_to_instance: &Path,
+    file_name: &str,
+    description: &str,
+    value: &T,
+) -> Result<(), Error> {
+    crate::util::fs::write_all(
+        path_to_instance.join(file_name),
+        serde_json::to_string_pretty(value)
+            .context(format!("Failed to serialize {description}"))?,
+    )
+    .await
+}
+
+/// Remove a sidecar, for settings back to what an absent sidecar reads as
+pub async fn remove_sidecar(path_to_instance: &Path, file_name: &str) -> Result<(), Error> {
+    crate::util::fs::remove_file(path_to_instance.join(file_name)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_sidecar_reads_as_absent() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let read = || read_sidecar::<BTreeMap<String, u32>>(root, ".lodestone_test.json", "test");
+        assert_eq!(read().await, None);
+
+        let value = BTreeMap::from([("limit".to_string(), 2)]);
+        write_sidecar(root, ".lodestone_test.json", "test", &value)
+            .await
+            .unwrap();
+        assert_eq!(read().await, Some(value));
+
+        std::fs::write(root.join(".lodestone_test.json"), "{\"limit\": ").unwrap();
+        assert_eq!(read().await, None);
+        remove_sidecar(root, ".lodestone_test.json").await.unwrap();
+        assert!(!root.join(".lodestone_test.json").exists());
+        remove_sidecar(root, ".lodestone_test.json").await.unwrap();
+
+        assert!(is_sidecar(&root.join(".lodestone_config")));
+        assert!(is_sidecar(&root.join(".lodestone_protected_paths.json")));
+        assert!(!is_sidecar(&root.join("lodestone_notes.txt")));
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Context};
+use sysinfo::{DiskExt, System, SystemExt};
+
+use crate::error::{Error, ErrorKind};
+use crate::util::format_byte;
+
+/// Pool of the instances directory, always there and not configurable
+pub const DEFAULT_POOL: &str = "default";
+
+/// Check the pools an owner configures: names are lowercase identifiers, roots are existing
+/// absolute directories that don't overlap each other or the instances directory, so every
+/// instance is in one pool at most
+pub fn validate_storage_pools(
+    pools: &BTreeMap<String, PathBuf>,
+    path_to_instances: &Path,
+) -> Result<(), Error> {
+    let path_to_instances = path_to_instances
+        .canonicalize()
+        .unwrap_or_else(|_| path_to_instances.to_path_buf());
+    let mut roots = vec![(DEFAULT_POOL, path_to_instances)];
+    for (name, root) in pools {
+        if name.is_empty()
+            || name == DEFAULT_POOL
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+        {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Invalid pool name {name}, use lowercase letters, digits, - and _, and not {DEFAULT_POOL}"
+                ),
+            });
+        }
+        if !root.is_absolute() || !root.is_dir() {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Root of pool {name}, {}, is not an existing directory",
+                    root.display()
+                ),
+            });
+        }
+        let root = root
+            .canonicalize()
+            .context(format!("Failed to resolve {}", root.display()))?;
+        if let Some((other, _)) = roots
+            .iter()
+            .find(|(_, other)| root.starts_with(other) || other.starts_with(&root))
+        {
+            return Err(Error {
+                kind: ErrorKind::BadRequest,
+                source: eyre!(
+                    "Root of pool {name}, {}, overlaps with pool {other}",
+                    root.display()
+                ),
+            });
+        }
+        roots.push((name.as_str(), root));
+    }
+    Ok(())
+}
+
+/// Directory the instances of the pool `name` live in
+pub fn pool_root(
+    pools: &BTreeMap<String, PathBuf>,
+    path_to_instances: &Path,
+    name: &str,
+) -> Result<PathBuf, Error> {
+    if name == DEFAULT_POOL {
+        return Ok(path_to_instances.to_path_buf());
+    }
+    pools.get(name).cloned().ok_or_else(|| Error {
+        kind: ErrorKind::NotFound,
+        source: eyre!("No storage pool named {name}"),
+    })
+}
+
+/// Pool the instance at `path_to_instance` is in, `None` for an instance relocated outside of
+/// every pool
+pub fn pool_of(
+    pools: &BTreeMap<String, PathBuf>,
+    path_to_instances: &Path,
+    path_to_instance: &Path,
+) -> Option<String> {
+    let parent = path_to_instance.parent()?;
+    if parent == path_to_instances {
+        return Some(DEFAULT_POOL.to_string());
+    }
+    pools
+        .iter()
+        .find(|(_, root)| parent == root.as_path())
+        .map(|(name, _)| name.clone())
+}
+
+/// Space left on the disk `path` is on, `None` if the disk isn't found
+pub fn available_space(path: &Path) -> Option<u64> {
+    let path = path.canonicalize().ok()?;
+    let mut system = System::new();
+    system.refresh_disks_list();
+    system
+        .disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Refuse a migration of `required` bytes into a pool with less space left. An unknown amount
+/// of space is let through, the copy fails cleanly if it runs out
+pub fn ensure_space(pool: &str, required: u64, available: Option<u64>) -> Result<(), Error> {
+    match available {
+        Some(available) if available < required => Err(Error {
+            kind: ErrorKind::Conflict,
+            source: eyre!(
+                "Pool {pool} has {} left, the instance needs {}",
+                format_byte(available),
+                format_byte(required)
+            ),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instances_are_found_in_their_pool() {
+        let (instances, fast, bulk) = (
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+        );
+        let pools = BTreeMap::from([
+            ("fast".to_string(), fast.path().to_path_buf()),
+            ("bulk".to_string(), bulk.path().to_path_buf()),
+        ]);
+        validate_storage_pools(&pools, instances.path()).unwrap();
+
+        for (pool, root) in [
+            ("fast", fast.path()),
+            ("bulk", bulk.path()),
+            (DEFAULT_POOL, instances.path()),
+        ] {
+            assert_eq!(pool_root(&pools, instances.path(), pool).unwrap(), root);
+            assert_eq!(
+                pool_of(&pools, instances.path(), &root.join("survival-1a2b3c4d")).as_deref(),
+                Some(pool)
+            );
+        }
+        assert_eq!(
+            pool_of(&pools, instances.path(), Path::new("/srv/other")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pools_are_validated_and_space_checked() {
+        let (instances, disk) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let err = pool_root(&BTreeMap::new(), instances.path(), "fast").unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::NotFound));
+        std::fs::create_dir_all(disk.path().join("nested")).unwrap();
+        std::fs::create_dir_all(instances.path().join("nested")).unwrap();
+
+        for pools in [
+            BTreeMap::from([("Fast".to_string(), disk.path().to_path_buf())]),
+            BTreeMap::from([(DEFAULT_POOL.to_string(), disk.path().to_path_buf())]),
+            BTreeMap::from([("fast".to_string(), PathBuf::from("relative/disk"))]),
+            BTreeMap::from([
+                ("fast".to_string(), disk.path().to_path_buf()),
+                ("bulk".to_string(), disk.path().to_path_buf()),
+            ]),
+            // overlapping roots, an instance would be in both pools
+            BTreeMap::from([
+                ("fast".to_string(), disk.path().to_path_buf()),
+                ("bulk".to_string(), disk.path().join("nested")),
+            ]),
+            BTreeMap::from([("fast".to_string(), instances.path().to_path_buf())]),
+            BTreeMap::from([("fast".to_string(), instances.path().join("nested"))]),
+        ] {
+            let err = validate_storage_pools(&pools, instances.path()).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest), "{pools:?}");
+        }
+
+        let err = ensure_space("bulk", 2048, Some(1024)).unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        ensure_space("bulk", 1024, Some(1024)).unwrap();
+        ensure_space("bulk", 2048, None).unwrap();
+    }
+}
@@ -0,0 +1,382 @@
+//! Fixtures shared by tests that need an instance or a whole `AppState`
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use ringbuffer::AllocRingBuffer;
+use sysinfo::SystemExt;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::auth::permission::UserPermission;
+use crate::auth::user::{User, UsersManager};
+use crate::error::{Error, ErrorKind};
+use crate::event_broadcaster::EventBroadcaster;
+use crate::events::{CausedBy, Event};
+use crate::fs_op_limiter::FsOpLimiter;
+use crate::global_settings::{GlobalSettings, GlobalSettingsData};
+use crate::handlers::instance_fs::UploadSessions;
+use crate::implementations::minecraft::{
+    Flavour, MinecraftInstance, RestoreConfig, DEFAULT_STOP_COMMAND,
+};
+use crate::instance_relocation::InstanceHolds;
+use crate::macro_executor::MacroExecutor;
+use crate::port_manager::PortManager;
+use crate::prelude::{init_paths, GameInstance};
+use crate::read_only::ReadOnlyMode;
+use crate::traits::t_configurable::manifest::{ConfigurableManifest, ConfigurableValue};
+use crate::traits::t_configurable::{Game, TConfigurable};
+use crate::traits::t_player::TPlayerManagement;
+use crate::traits::t_server::{MonitorReport, State, TServer};
+use crate::types::{DotLodestoneConfig, GameType, InstanceUuid};
+use crate::{docker_bridge, AppState};
+
+/// A minecraft instance in `root/instance` whose java is a script writing its arguments to
+/// `java_args.txt`. Once started it reports being done, and exits on the first line written to
+/// its stdin after appending it to `stdin.txt`
+#[cfg(unix)]
+pub async fn restore_with_fake_java(
+    root: &Path,
+    custom_jar_path: Option<&str>,
+) -> MinecraftInstance {
+    use std::os::unix::fs::PermissionsExt;
+
+    init_paths(root.join("lodestone"));
+    let path_to_instance = root.join("instance");
+    std::fs::create_dir_all(&path_to_instance).unwrap();
+    let java = root.join("java");
+    std::fs::write(
+        &java,
+        "#!/bin/sh\n\
+         echo \"$@\" > java_args.txt\n\
+         echo '[Server thread/INFO]: Done (0.1s)! For help, type \"help\"'\n\
+         read -r line\n\
+         echo \"$line\" >> stdin.txt\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&java, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(path_to_instance.join("eula.txt"), "eula=true\n").unwrap();
+
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port() as u32;
+    let restore_config = RestoreConfig {
+        name: "test".to_string(),
+        version: "1.20.1".to_string(),
+        flavour: Flavour::Vanilla,
+        description: "".to_string(),
+        cmd_args: vec![],
+        java_cmd: Some(java.to_string_lossy().to_string()),
+        port,
+        min_ram: 128,
+        max_ram: 256,
+        auto_start: false,
+        restart_on_crash: false,
+        backup_period: None,
+        jre_major_version: 17,
+        has_started: true,
+        custom_jar_path: custom_jar_path.map(|path| path.to_string()),
+        locked: false,
+        depends_on: vec![],
+        stop_command: DEFAULT_STOP_COMMAND.to_string(),
+    };
+    std::fs::write(
+        path_to_instance.join(".lodestone_minecraft_config.json"),
+        serde_json::to_string_pretty(&restore_config).unwrap(),
+    )
+    .unwrap();
+
+    let (event_broadcaster, _) = EventBroadcaster::new(10);
+    MinecraftInstance::restore(
+        path_to_instance,
+        DotLodestoneConfig::new(InstanceUuid::default(), GameType::MinecraftJava),
+        event_broadcaster.clone(),
+        MacroExecutor::new(event_broadcaster.clone(), tokio::runtime::Handle::current()),
+        Arc::new(Mutex::new(GlobalSettings::new(
+            root.join("global_settings.json"),
+            event_broadcaster,
+            GlobalSettingsData::default(),
+        ))),
+    )
+    .await
+    .unwrap()
+}
+
+/// The state the handlers run against, with `instances` and no users, stores are kept in `root`
+pub async fn test_app_state(root: &Path, instances: Vec<GameInstance>) -> AppState {
+    init_paths(root.join("lodestone"));
+    std::fs::create_dir_all(root.join("stores")).unwrap();
+    let (event_broadcaster, _) = EventBroadcaster::new(512);
+    let instance_map = DashMap::new();
+    for instance in instances {
+        instance_map.insert(instance.uuid().await, instance);
+    }
+    AppState {
+        instances: Arc::new(instance_map),
+        failed_instances: Arc::new(Mutex::new(Vec::new())),
+        users_manager: Arc::new(RwLock::new(UsersManager::new(
+            event_broadcaster.clone(),
+            HashMap::new(),
+            root.join("stores/users.json"),
+        ))),
+        events_buffer: Arc::new(Mutex::new(AllocRingBuffer::with_capacity(512))),
+        console_out_buffer: Arc::new(Mutex::new(HashMap::new())),
+        console_logs: Arc::new(Mutex::new(HashMap::new())),
+        monitor_buffer: Arc::new(Mutex::new(HashMap::new())),
+        event_broadcaster: event_broadcaster.clone(),
+        uuid: "test".to_string(),
+        up_since: chrono::Utc::now().timestamp(),
+        global_settings: Arc::new(Mutex::new(GlobalSettings::new(
+            root.join("global_settings.json"),
+            event_broadcaster.clone(),
+            GlobalSettingsData::default(),
+        ))),
+        system: Arc::new(Mutex::new(sysinfo::System::new())),
+        port_manager: Arc::new(Mutex::new(PortManager::new(HashSet::new()))),
+        first_time_setup_key: Arc::new(Mutex::new(None)),
+        playitgg_key: Arc::new(Mutex::new(None)),
+        download_urls: Arc::new(Mutex::new(HashMap::new())),
+        upload_sessions: UploadSessions::default(),
+        fs_op_limiter: FsOpLimiter::default(),
+        instance_holds: InstanceHolds::default(),
+        macro_executor: MacroExecutor::new(
+            event_broadcaster.clone(),
+            tokio::runtime::Handle::current(),
+        ),
+        sqlite_pool: sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap(),
+        docker_bridge: docker_bridge::DockerBridge::new(
+            event_broadcaster,
+            root.join("stores/docker_bridge.json"),
+        )
+        .await
+        .unwrap(),
+        playit_keep_running: Arc::new(Mutex::new(None)),
+        read_only: ReadOnlyMode::default(),
+    }
+}
+
+/// Add a user with `permissions` to the state, returns a token to authenticate as them
+pub async fn add_test_user(state: &AppState, name: &str, permissions: UserPermission) -> String {
+    let user = User::new(name.to_string(), "password", false, false, permissions);
+    let token = user.create_jwt().unwrap().to_string();
+    state
+        .users_manager
+        .write()
+        .await
+        .add_user(user, CausedBy::System)
+        .await
+        .unwrap();
+    token
+}
+
+/// A zip of `entries`, a name ending in `/` is a directory
+pub fn write_test_zip(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut writer = zip::ZipWriter::new(std::fs::File::create(path).unwrap());
+    for (name, content) in entries {
+        if name.ends_with('/') {
+            writer
+                .add_directory(*name, zip::write::FileOptions::default())
+                .unwrap();
+        } else {
+            writer
+                .start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut writer, content).unwrap();
+        }
+    }
+    writer.finish().unwrap();
+}
+
+/// An instance for code generic over the instance traits, its server is a state machine over
+/// plain fields. Clones share their state, so one can be handed out and looked at afterwards
+#[derive(Clone)]
+pub struct FakeInstance {
+    pub uuid: InstanceUuid,
+    pub name: String,
+    pub state: Arc<std::sync::Mutex<State>>,
+    pub port: Arc<std::sync::Mutex<u32>>,
+    /// where the instance would be, nothing is created there
+    pub path: PathBuf,
+    pub locked: Arc<AtomicBool>,
+    /// a start gets to `Running` and a stop to `Stopped`, otherwise a start does nothing and a
+    /// stop stays `Stopping`, like a server hung on shutdown
+    pub completes: bool,
+    /// `state` and `pid` never answer, like an instance held by a long operation
+    pub hangs: bool,
+    /// starts and stops asked for
+    pub calls: Arc<AtomicU32>,
+    pub killed: Arc<AtomicBool>,
+    /// the instances started, in order, shared between fakes that are started together
+    pub started: Arc<std::sync::Mutex<Vec<InstanceUuid>>>,
+    /// sent the state transitions of the instance
+    pub event_broadcaster: Option<EventBroadcaster>,
+    pub monitor: MonitorReport,
+    /// `None` for a server whose player count can't be queried
+    pub player_count: Option<u32>,
+    pub pid: Option<u32>,
+}
+
+impl FakeInstance {
+    /// A fake named `name`, with the uuid `INSTANCE_<name>`, whose starts and stops complete
+    pub fn new(name: &str, state: State) -> Self {
+        Self {
+            uuid: InstanceUuid::from(format!("INSTANCE_{name}")),
+            name: name.to_string(),
+            state: Arc::new(std::sync::Mutex::new(state)),
+            port: Arc::new(std::sync::Mutex::new(25565)),
+            path: std::env::temp_dir().join(format!("lodestone_fake_{name}")),
+            locked: Arc::new(AtomicBool::new(false)),
+            completes: true,
+            hangs: false,
+            calls: Arc::new(AtomicU32::new(0)),
+            killed: Arc::new(AtomicBool::new(false)),
+            started: Arc::new(std::sync::Mutex::new(Vec::new())),
+            event_broadcaster: None,
+            monitor: MonitorReport::default(),
+            player_count: None,
+            pid: None,
+        }
+    }
+
+    pub fn set_state(&self, state: State) {
+        *self.state.lock().unwrap() = state;
+        if let Some(event_broadcaster) = &self.event_broadcaster {
+            event_broadcaster.send(Event::new_instance_state_transition(
+                self.uuid.clone(),
+                self.name.clone(),
+                state,
+            ));
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TServer for FakeInstance {
+    async fn start(&self, _: CausedBy, _: bool) -> Result<(), Error> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.started.lock().unwrap().push(self.uuid.clone());
+        if self.completes {
+            self.set_state(State::Running);
+        }
+        Ok(())
+    }
+    async fn stop(&self, _: CausedBy, _: bool) -> Result<(), Error> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.set_state(if self.completes {
+            State::Stopped
+        } else {
+            State::Stopping
+        });
+        Ok(())
+    }
+    async fn restart(&self, _: CausedBy, _: bool) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn kill(&self, _: CausedBy) -> Result<(), Error> {
+        self.killed.store(true, Ordering::SeqCst);
+        self.set_state(State::Stopped);
+        Ok(())
+    }
+    async fn state(&self) -> State {
+        if self.hangs {
+            futures::future::pending().await
+        }
+        *self.state.lock().unwrap()
+    }
+    async fn send_command(&self, _: &str, _: CausedBy) -> Result<(), Error> {
+        Ok(())
+    }
+    async fn monitor(&self) -> MonitorReport {
+        self.monitor.clone()
+    }
+    async fn pid(&self) -> Option<u32> {
+        if self.hangs {
+            futures::future::pending().await
+        }
+        self.pid
+    }
+}
+
+#[async_trait::async_trait]
+impl TPlayerManagement for FakeInstance {
+    async fn get_player_count(&self) -> Result<u32, Error> {
+        self.player_count
+            .ok_or_else(|| color_eyre::eyre::eyre!("Server is not reachable").into())
+    }
+}
+
+#[async_trait::async_trait]
+impl TConfigurable for FakeInstance {
+    async fn uuid(&self) -> InstanceUuid {
+        self.uuid.clone()
+    }
+    async fn name(&self) -> String {
+        self.name.clone()
+    }
+    async fn game_type(&self) -> Game {
+        Game::Generic {
+            game_name: GameType::Generic,
+            game_display_name: "Fake".to_string(),
+        }
+    }
+    async fn version(&self) -> String {
+        String::new()
+    }
+    async fn description(&self) -> String {
+        String::new()
+    }
+    async fn port(&self) -> u32 {
+        *self.port.lock().unwrap()
+    }
+    async fn creation_time(&self) -> i64 {
+        0
+    }
+    async fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+    async fn auto_start(&self) -> bool {
+        false
+    }
+    async fn restart_on_crash(&self) -> bool {
+        false
+    }
+    async fn locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+    async fn set_name(&self, _: String) -> Result<(), Error> {
+        Err(unsupported("renamed"))
+    }
+    async fn set_description(&self, _: String) -> Result<(), Error> {
+        Err(unsupported("described"))
+    }
+    async fn set_port(&self, port: u32) -> Result<(), Error> {
+        *self.port.lock().unwrap() = port;
+        Ok(())
+    }
+    async fn set_locked(&self, locked: bool) -> Result<(), Error> {
+        self.locked.store(locked, Ordering::SeqCst);
+        Ok(())
+    }
+    async fn configurable_manifest(&self) -> ConfigurableManifest {
+        ConfigurableManifest::default()
+    }
+    async fn update_configurable(
+        &self,
+        _: &str,
+        _: &str,
+        _: ConfigurableValue,
+    ) -> Result<(), Error> {
+        Err(unsupported("configured"))
+    }
+}
+
+fn unsupported(what: &str) -> Error {
+    Error {
+        kind: ErrorKind::UnsupportedOperation,
+        source: color_eyre::eyre::eyre!("A fake instance can't be {what}"),
+    }
+}
@@ -12,6 +12,8 @@ use self::t_server::State;
 use self::{
     t_configurable::TConfigurable, t_macro::TMacro, t_player::TPlayerManagement, t_server::TServer,
 };
+use crate::instance_audit::{read_instance_audit, InstanceAudit};
+use crate::instance_crashes::{read_instance_crashes, InstanceCrashes};
 
 pub mod t_configurable;
 pub mod t_macro;
@@ -31,10 +33,54 @@ pub struct InstanceInfo {
     pub path: String,
     pub auto_start: bool,
     pub restart_on_crash: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub depends_on: Vec<InstanceUuid>,
+    #[serde(default)]
+    pub stop_command: Option<String>,
     pub state: State,
     pub player_count: Option<u32>,
     pub max_player_count: Option<u32>,
     pub player_list: Option<HashSet<Player>>,
+    #[serde(flatten)]
+    pub audit: InstanceAudit,
+    #[serde(flatten)]
+    pub crashes: InstanceCrashes,
+    /// the instance couldn't be read in time while listing, only `uuid` is accurate
+    #[serde(default)]
+    pub busy: bool,
+}
+
+impl InstanceInfo {
+    /// Stand-in for an instance held up by a long operation, the rest of its info is unknown
+    pub fn busy(uuid: InstanceUuid) -> Self {
+        Self {
+            name: uuid.to_string(),
+            uuid,
+            game_type: Game::Generic {
+                game_name: t_configurable::GameType::Generic,
+                game_display_name: "Unknown".to_string(),
+            },
+            description: "This instance is busy, try again later".to_string(),
+            version: String::new(),
+            port: 0,
+            creation_time: 0,
+            path: String::new(),
+            auto_start: false,
+            restart_on_crash: false,
+            locked: false,
+            depends_on: Vec::new(),
+            stop_command: None,
+            state: State::Error,
+            player_count: None,
+            max_player_count: None,
+            player_list: None,
+            audit: InstanceAudit::default(),
+            crashes: InstanceCrashes::default(),
+            busy: true,
+        }
+    }
 }
 use crate::generic::GenericInstance;
 use crate::minecraft::MinecraftInstance;
@@ -55,10 +101,16 @@ pub trait TInstance: TConfigurable + TMacro + TPlayerManagement + TServer + Clon
             path: self.path().await.display().to_string(),
             auto_start: self.auto_start().await,
             restart_on_crash: self.restart_on_crash().await,
+            locked: self.locked().await,
+            depends_on: self.depends_on().await,
+            stop_command: self.stop_command().await,
             state: self.state().await,
             player_count: self.get_player_count().await.ok(),
             max_player_count: self.get_max_player_count().await.ok(),
             player_list: self.get_player_list().await.ok(),
+            audit: read_instance_audit(&self.path().await).await,
+            crashes: read_instance_crashes(&self.path().await).await,
+            busy: false,
         }
     }
 }
@@ -8,6 +8,7 @@ use ts_rs::TS;
 
 use crate::error::Error;
 use crate::error::ErrorKind;
+use crate::error::FieldError;
 
 #[derive(Debug, Clone, Serialize, Deserialize, TS, PartialEq)]
 #[ts(export)]
@@ -522,18 +523,49 @@ pub struct SetupManifest {
 }
 
 impl SetupManifest {
+    /// Validate every field of the setup value, all the problems are reported in one error
     pub fn validate_setup_value(&self, value: &SetupValue) -> Result<(), Error> {
+        let errors = self.field_errors(value);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::fields(errors))
+        }
+    }
+
+    /// Problems with the setup value keyed by the setting (or section) id
+    pub fn field_errors(&self, value: &SetupValue) -> Vec<FieldError> {
+        let mut errors = value.name_errors();
         for (section_id, section_value) in value.setting_sections.iter() {
-            if let Some(section) = self.setting_sections.get(section_id) {
-                section.validate_section(section_value)?;
-            } else {
-                return Err(Error {
-                    kind: ErrorKind::BadRequest,
-                    source: eyre!("Section not found"),
-                });
+            let Some(section) = self.setting_sections.get(section_id) else {
+                errors.push(FieldError::new(section_id, "Section not found"));
+                continue;
+            };
+            for (setting_id, setting_value) in section_value.settings.iter() {
+                match section.settings.get(setting_id) {
+                    Some(setting) => {
+                        if let Err(e) = setting.validate_setting(&setting_value.value) {
+                            errors.push(FieldError::new(setting_id, e.source.to_string()));
+                        }
+                    }
+                    None => errors.push(FieldError::new(setting_id, "Setting not found")),
+                }
             }
         }
-        Ok(())
+        // required settings left out of the value entirely
+        for (section_id, section) in self.setting_sections.iter() {
+            for (setting_id, setting) in section.settings.iter() {
+                let provided = value
+                    .setting_sections
+                    .get(section_id)
+                    .map(|section_value| section_value.settings.contains_key(setting_id))
+                    .unwrap_or(false);
+                if setting.is_required && !provided {
+                    errors.push(FieldError::new(setting_id, "Setting is required"));
+                }
+            }
+        }
+        errors
     }
 
     pub fn validate_section(
@@ -563,6 +595,49 @@ pub struct SetupValue {
 }
 
 impl SetupValue {
+    /// A setup value of `settings`, each given with the id of its section
+    pub fn from_settings<'a>(
+        name: String,
+        description: Option<String>,
+        auto_start: bool,
+        restart_on_crash: bool,
+        settings: impl IntoIterator<Item = (&'a str, &'a str, ConfigurableValue)>,
+    ) -> Self {
+        let mut setting_sections: IndexMap<String, SectionManifestValue> = IndexMap::new();
+        for (section_id, setting_id, value) in settings {
+            setting_sections
+                .entry(section_id.to_string())
+                .or_insert_with(|| SectionManifestValue {
+                    settings: IndexMap::new(),
+                })
+                .settings
+                .insert(
+                    setting_id.to_string(),
+                    SettingManifestValue { value: Some(value) },
+                );
+        }
+        Self {
+            name,
+            description,
+            auto_start,
+            restart_on_crash,
+            setting_sections,
+        }
+    }
+
+    pub fn name_errors(&self) -> Vec<FieldError> {
+        if self.name.trim().is_empty() {
+            vec![FieldError::new("name", "Name cannot be empty")]
+        } else if self.name.len() > 100 {
+            vec![FieldError::new(
+                "name",
+                "Name cannot be longer than 100 characters",
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+
     pub fn get_unique_setting(&self, setting_id: &str) -> Option<&SettingManifestValue> {
         for section in self.setting_sections.values() {
             for (id, setting) in section.settings.iter() {
@@ -815,3 +890,124 @@ impl SettingLocalCache {
         &self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_manifest() -> SetupManifest {
+        let mut settings = IndexMap::new();
+        settings.insert(
+            "version".to_string(),
+            SettingManifest::new_value_with_type(
+                "version".to_string(),
+                "Version".to_string(),
+                "".to_string(),
+                Some(ConfigurableValue::Enum("1.19.4".to_string())),
+                ConfigurableValueType::Enum {
+                    options: vec!["1.19.4".to_string(), "1.20.1".to_string()],
+                },
+                None,
+                false,
+                true,
+            ),
+        );
+        settings.insert(
+            "port".to_string(),
+            SettingManifest::new_value_with_type(
+                "port".to_string(),
+                "Port".to_string(),
+                "".to_string(),
+                Some(ConfigurableValue::UnsignedInteger(25565)),
+                ConfigurableValueType::UnsignedInteger {
+                    min: Some(0),
+                    max: Some(65535),
+                },
+                None,
+                false,
+                true,
+            ),
+        );
+        settings.insert(
+            "min_ram".to_string(),
+            SettingManifest::new_required_value(
+                "min_ram".to_string(),
+                "Minimum RAM".to_string(),
+                "".to_string(),
+                ConfigurableValue::UnsignedInteger(1024),
+                None,
+                false,
+                true,
+            ),
+        );
+        let mut setting_sections = IndexMap::new();
+        setting_sections.insert(
+            "section_1".to_string(),
+            SectionManifest::new(
+                "section_1".to_string(),
+                "Basic Settings".to_string(),
+                "".to_string(),
+                settings,
+            ),
+        );
+        SetupManifest { setting_sections }
+    }
+
+    fn setup_value(name: &str, settings: Vec<(&str, ConfigurableValue)>) -> SetupValue {
+        let mut section = IndexMap::new();
+        for (id, value) in settings {
+            section.insert(id.to_string(), SettingManifestValue { value: Some(value) });
+        }
+        let mut setting_sections = IndexMap::new();
+        setting_sections.insert(
+            "section_1".to_string(),
+            SectionManifestValue { settings: section },
+        );
+        SetupValue {
+            name: name.to_string(),
+            description: None,
+            auto_start: false,
+            restart_on_crash: false,
+            setting_sections,
+        }
+    }
+
+    #[test]
+    fn test_setup_value_reports_all_field_errors() {
+        let manifest = setup_manifest();
+        let value = setup_value(
+            "",
+            vec![
+                ("version", ConfigurableValue::Enum("b1.7.3".to_string())),
+                ("port", ConfigurableValue::UnsignedInteger(70000)),
+                ("motd", ConfigurableValue::String("hi".to_string())),
+            ],
+        );
+        let error = manifest.validate_setup_value(&value).unwrap_err();
+        assert!(matches!(error.kind, ErrorKind::BadRequest));
+        let fields: Vec<&str> = error
+            .field_errors()
+            .unwrap()
+            .iter()
+            .map(|e| e.field.as_str())
+            .collect();
+        assert_eq!(fields, vec!["name", "version", "port", "motd", "min_ram"]);
+        let port_error = &error.field_errors().unwrap()[2];
+        assert_eq!(port_error.message, "Value is too large");
+    }
+
+    #[test]
+    fn test_valid_setup_value() {
+        let manifest = setup_manifest();
+        let value = setup_value(
+            "survival",
+            vec![
+                ("version", ConfigurableValue::Enum("1.20.1".to_string())),
+                ("port", ConfigurableValue::UnsignedInteger(25565)),
+                ("min_ram", ConfigurableValue::UnsignedInteger(2048)),
+            ],
+        );
+        assert!(manifest.validate_setup_value(&value).is_ok());
+        assert!(manifest.field_errors(&value).is_empty());
+    }
+}
@@ -91,6 +91,18 @@ pub trait TConfigurable {
     /// does start when lodestone starts
     async fn auto_start(&self) -> bool;
     async fn restart_on_crash(&self) -> bool;
+    /// destructive operations are refused while the instance is locked
+    async fn locked(&self) -> bool {
+        false
+    }
+    /// instances that must be running before this one starts
+    async fn depends_on(&self) -> Vec<InstanceUuid> {
+        Vec::new()
+    }
+    /// console command shutting the server down gracefully, `None` if it isn't stopped that way
+    async fn stop_command(&self) -> Option<String> {
+        None
+    }
     // setters
     async fn set_name(&self, name: String) -> Result<(), Error>;
     async fn set_description(&self, description: String) -> Result<(), Error>;
@@ -112,6 +124,24 @@ pub trait TConfigurable {
             source: eyre!("This instance does not support setting restart on crash"),
         })
     }
+    async fn set_locked(&self, _locked: bool) -> Result<(), Error> {
+        Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("This instance does not support locking"),
+        })
+    }
+    async fn set_depends_on(&self, _depends_on: Vec<InstanceUuid>) -> Result<(), Error> {
+        Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("This instance does not support dependencies"),
+        })
+    }
+    async fn set_stop_command(&self, _stop_command: String) -> Result<(), Error> {
+        Err(Error {
+            kind: ErrorKind::UnsupportedOperation,
+            source: eyre!("This instance does not support setting a stop command"),
+        })
+    }
     async fn set_backup_period(&self, _backup_period: Option<u32>) -> Result<(), Error> {
         Err(Error {
             kind: ErrorKind::UnsupportedOperation,
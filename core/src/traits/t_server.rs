@@ -8,14 +8,20 @@ use ts_rs::TS;
 use crate::events::CausedBy;
 use crate::Error;
 
+/// Clients match on the serialized names, keep them stable when renaming the variants
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS, Copy)]
 #[serde(rename = "InstanceState")]
 #[ts(export)]
 pub enum State {
+    #[serde(rename = "Starting")]
     Starting,
+    #[serde(rename = "Running")]
     Running,
+    #[serde(rename = "Stopping")]
     Stopping,
+    #[serde(rename = "Stopped")]
     Stopped,
+    #[serde(rename = "Error")]
     Error,
 }
 
@@ -146,4 +152,30 @@ pub trait TServer {
     async fn state(&self) -> State;
     async fn send_command(&self, command: &str, caused_by: CausedBy) -> Result<(), Error>;
     async fn monitor(&self) -> MonitorReport;
+    /// PID of the server process Lodestone spawned, `None` while there is none
+    async fn pid(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_serializes_to_stable_names() {
+        for (state, name) in [
+            (State::Starting, "Starting"),
+            (State::Running, "Running"),
+            (State::Stopping, "Stopping"),
+            (State::Stopped, "Stopped"),
+            (State::Error, "Error"),
+        ] {
+            let json = serde_json::to_string(&state).unwrap();
+            assert_eq!(json, format!("\"{name}\""));
+            assert_eq!(serde_json::from_str::<State>(&json).unwrap(), state);
+            assert_eq!(state.to_string(), name);
+        }
+        assert!(serde_json::from_str::<State>("\"running\"").is_err());
+    }
 }
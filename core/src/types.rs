@@ -180,6 +180,16 @@ impl DotLodestoneConfig {
     }
 }
 
+/// An instance directory that couldn't be restored on startup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct FailedInstanceLoad {
+    pub path: String,
+    /// `None` if the failure happened before the uuid could be read
+    pub uuid: Option<InstanceUuid>,
+    pub error: String,
+}
+
 #[test]
 fn test_instance_uuid() {
     let uuid1 = InstanceUuid::default();
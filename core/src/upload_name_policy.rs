@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+
+/// Absent while the instance uses the strict policy
+pub const SANITIZE_POLICY_FILE_NAME: &str = ".lodestone_sanitize_policy.json";
+
+/// How the names of uploaded files are sanitized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "lowercase")]
+#[ts(export)]
+pub enum SanitizePolicy {
+    /// characters that aren't allowed on some platform are removed
+    #[default]
+    Strict,
+    /// names are kept as is, only path separators and traversal are rejected
+    Lenient,
+}
+
+/// The name an uploaded file is saved under
+pub fn sanitize_upload_name(name: &str, policy: SanitizePolicy) -> Result<String, Error> {
+    let name = match policy {
+        SanitizePolicy::Strict => sanitize_filename::sanitize(name),
+        SanitizePolicy::Lenient => {
+            if name.contains(['/', '\\', '\0']) || name == "." || name == ".." {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!("Invalid file name {name}"),
+                });
+            }
+            name.to_string()
+        }
+    };
+    if name.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!("File name is empty"),
+        });
+    }
+    Ok(name)
+}
+
+pub async fn read_sanitize_policy(path_to_instance: &Path) -> SanitizePolicy {
+    read_sidecar(
+        path_to_instance,
+        SANITIZE_POLICY_FILE_NAME,
+        "sanitize policy",
+    )
+    .await
+    .unwrap_or_default()
+}
+
+pub async fn write_sanitize_policy(
+    path_to_instance: &Path,
+    policy: SanitizePolicy,
+) -> Result<(), Error> {
+    if policy == SanitizePolicy::default() {
+        return remove_sidecar(path_to_instance, SANITIZE_POLICY_FILE_NAME).await;
+    }
+    write_sidecar(
+        path_to_instance,
+        SANITIZE_POLICY_FILE_NAME,
+        "sanitize policy",
+        &policy,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_upload_names_under_each_policy() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path();
+        assert_eq!(read_sanitize_policy(path).await, SanitizePolicy::Strict);
+
+        for (name, strict) in [
+            (
+                "Sound Physics: Remastered?.zip",
+                "Sound Physics Remastered.zip",
+            ),
+            ("ops|whitelist \"backup\".json", "opswhitelist backup.json"),
+            ("Fréd's <world>", "Fréd's world"),
+            ("server.properties", "server.properties"),
+        ] {
+            assert_eq!(
+                sanitize_upload_name(name, SanitizePolicy::Strict).unwrap(),
+                strict
+            );
+            assert_eq!(
+                sanitize_upload_name(name, SanitizePolicy::Lenient).unwrap(),
+                name
+            );
+        }
+        for name in ["../start.sh", "config/evil.json", "..\\start.bat", "..", ""] {
+            let err = sanitize_upload_name(name, SanitizePolicy::Lenient).unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::BadRequest), "{name}");
+        }
+        assert_eq!(
+            sanitize_upload_name("../start.sh", SanitizePolicy::Strict).unwrap(),
+            "..start.sh"
+        );
+        assert!(sanitize_upload_name("..", SanitizePolicy::Strict).is_err());
+
+        write_sanitize_policy(path, SanitizePolicy::Lenient)
+            .await
+            .unwrap();
+        assert_eq!(read_sanitize_policy(path).await, SanitizePolicy::Lenient);
+        write_sanitize_policy(path, SanitizePolicy::Strict)
+            .await
+            .unwrap();
+        assert!(!path.join(SANITIZE_POLICY_FILE_NAME).exists());
+        assert_eq!(read_sanitize_policy(path).await, SanitizePolicy::Strict);
+    }
+}
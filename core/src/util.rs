@@ -1,12 +1,13 @@
 use color_eyre::eyre::{eyre, Context, ContextCompat};
 use std::collections::HashSet;
 use std::ffi::OsStr;
-use std::io::{Read, Write};
 
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
 
 use futures_util::StreamExt;
 use reqwest::Client;
@@ -22,7 +23,7 @@ pub struct Authentication {
     password: String,
 }
 
-use crate::error::Error;
+use crate::error::{Error, ErrorKind};
 use crate::prelude::path_to_tmp;
 #[derive(Debug, Clone, Serialize, Deserialize, TS)]
 #[ts(export)]
@@ -199,6 +200,98 @@ pub enum UnzipOption {
 pub fn unzip_file(
     file: impl AsRef<Path>,
     unzip_option: UnzipOption,
+) -> Result<HashSet<PathBuf>, Error> {
+    unzip_file_with_progress(file, unzip_option, &mut |_| {})
+}
+
+/// Number of entries in the archive, `None` when it can't be known without extracting it (tar.gz)
+pub fn archive_entry_count(file: impl AsRef<Path>) -> Option<u64> {
+    let file = file.as_ref();
+    if file.extension()? != "zip" {
+        return None;
+    }
+    let archive = zip::ZipArchive::new(std::fs::File::open(file).ok()?).ok()?;
+    Some(archive.len() as u64)
+}
+
+/// An entry of an archive as stored, its name isn't checked and may point outside of the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawArchiveEntry {
+    pub name: String,
+    /// uncompressed size in bytes
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// List the entries of a zip or tar.gz archive without extracting it
+pub fn list_archive_entries(file: impl AsRef<Path>) -> Result<Vec<RawArchiveEntry>, Error> {
+    let file = file.as_ref();
+    let file_extension = file
+        .extension()
+        .ok_or_else(|| eyre!("Failed to get file extension for {}", file.display()))?;
+    let archive_file =
+        std::fs::File::open(file).context(format!("Failed to open file {}", file.display()))?;
+    if file_extension == "zip" {
+        let mut archive = zip::ZipArchive::new(archive_file)
+            .context(format!("Failed to read archive {}", file.display()))?;
+        (0..archive.len())
+            .map(|i| {
+                let entry = archive
+                    .by_index_raw(i)
+                    .context(format!("Failed to read entry {i}"))?;
+                Ok(RawArchiveEntry {
+                    name: entry.name().to_string(),
+                    size: entry.size(),
+                    is_dir: entry.is_dir(),
+                })
+            })
+            .collect()
+    } else if file_extension == "gz" || file_extension == "tgz" {
+        let mut archive = Archive::new(GzDecoder::new(archive_file));
+        archive
+            .entries()
+            .context(format!("Failed to read archive {}", file.display()))?
+            .map(|entry| {
+                let entry = entry.context(format!("Failed to read archive {}", file.display()))?;
+                Ok(RawArchiveEntry {
+                    name: String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+                    size: entry.size(),
+                    is_dir: entry.header().entry_type().is_dir(),
+                })
+            })
+            .collect()
+    } else {
+        Err(eyre!("Unsupported extension for {}", file.display()).into())
+    }
+}
+
+/// Same as `unzip_file`, `on_entry` is called with the number of entries extracted so far
+/// after each zip entry is written. tar.gz archives are unpacked without progress
+pub fn unzip_file_with_progress(
+    file: impl AsRef<Path>,
+    unzip_option: UnzipOption,
+    on_entry: &mut dyn FnMut(u64),
+) -> Result<HashSet<PathBuf>, Error> {
+    unzip_file_cancellable(file, unzip_option, on_entry, &CancellationToken::new())
+}
+
+/// Error of a long running operation stopped through its cancellation token. A conflict like
+/// the other cancellations, the request itself was fine
+pub fn operation_cancelled() -> Error {
+    Error {
+        kind: ErrorKind::Conflict,
+        source: eyre!("Operation cancelled"),
+    }
+}
+
+/// Same as `unzip_file_with_progress`, stopping before the next zip entry once `cancel` is
+/// cancelled. The archive is extracted to a temporary directory first, a cancelled unzip leaves
+/// nothing at the destination. tar.gz archives are unpacked at once
+pub fn unzip_file_cancellable(
+    file: impl AsRef<Path>,
+    unzip_option: UnzipOption,
+    on_entry: &mut dyn FnMut(u64),
+    cancel: &CancellationToken,
 ) -> Result<HashSet<PathBuf>, Error> {
     let file = file.as_ref();
 
@@ -254,9 +347,18 @@ pub fn unzip_file(
             std::fs::File::open(file).context(format!("Failed to open file {}", file.display()))?;
         let mut archive = zip::ZipArchive::new(zip)
             .context(format!("Failed to decompress file {}", file.display()))?;
-        archive
-            .extract(temp_dest)
-            .context(format!("Failed to decompress file {}", file.display()))?;
+        for i in 0..archive.len() {
+            if cancel.is_cancelled() {
+                return Err(operation_cancelled());
+            }
+            extract_zip_entry(&mut archive, i, temp_dest)
+                .context(format!("Failed to decompress file {}", file.display()))?;
+            on_entry(i as u64 + 1);
+        }
+    }
+
+    if cancel.is_cancelled() {
+        return Err(operation_cancelled());
     }
 
     let mut ret: HashSet<PathBuf> = HashSet::new();
@@ -299,6 +401,46 @@ pub fn unzip_file(
     Ok(ret)
 }
 
+// same as `ZipArchive::extract`, one entry at a time
+fn extract_zip_entry(
+    archive: &mut zip::ZipArchive<std::fs::File>,
+    index: usize,
+    directory: &Path,
+) -> Result<(), Error> {
+    let mut entry = archive
+        .by_index(index)
+        .context(format!("Failed to read entry {index}"))?;
+    let outpath = directory.join(
+        entry
+            .enclosed_name()
+            .ok_or_else(|| eyre!("Invalid file path {}", entry.name()))?,
+    );
+
+    if entry.is_dir() {
+        std::fs::create_dir_all(&outpath)
+            .context(format!("Failed to create directory {}", outpath.display()))?;
+    } else {
+        if let Some(parent) = outpath.parent() {
+            std::fs::create_dir_all(parent)
+                .context(format!("Failed to create directory {}", parent.display()))?;
+        }
+        let mut outfile = std::fs::File::create(&outpath)
+            .context(format!("Failed to create file {}", outpath.display()))?;
+        std::io::copy(&mut entry, &mut outfile)
+            .context(format!("Failed to write file {}", outpath.display()))?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Some(mode) = entry.unix_mode() {
+            std::fs::set_permissions(&outpath, std::fs::Permissions::from_mode(mode)).context(
+                format!("Failed to set permissions of {}", outpath.display()),
+            )?;
+        }
+    }
+    Ok(())
+}
+
 pub async fn unzip_file_async(
     file: impl AsRef<Path>,
     unzip_option: UnzipOption,
@@ -312,12 +454,117 @@ pub async fn unzip_file_async(
         ))?
 }
 
+pub async fn unzip_file_async_with_progress(
+    file: impl AsRef<Path>,
+    unzip_option: UnzipOption,
+    mut on_entry: impl FnMut(u64) + Send + 'static,
+    cancel: CancellationToken,
+) -> Result<HashSet<PathBuf>, Error> {
+    let _file = file.as_ref().to_owned();
+    tokio::task::spawn_blocking(move || {
+        unzip_file_cancellable(_file, unzip_option, &mut on_entry, &cancel)
+    })
+    .await
+    .context(format!(
+        "Failed to unzip file {} in a blocking task",
+        file.as_ref().display()
+    ))?
+}
+
+/// Entries at least this large are written in the ZIP64 format.
+/// Deflate can grow incompressible data a little, so this leaves some room below 4 GiB
+const ZIP64_LARGE_FILE_THRESHOLD: u64 = u32::MAX as u64 - 64 * 1024 * 1024;
+
 pub fn zip_files(
     files: &[impl AsRef<Path>],
     dest: impl AsRef<Path>,
     overwrite_dest: bool,
 ) -> Result<PathBuf, Error> {
-    let dest = dest.as_ref();
+    zip_files_inner(
+        files,
+        dest.as_ref(),
+        overwrite_dest,
+        &mut |_| {},
+        &CancellationToken::new(),
+    )
+}
+
+/// What the files and directories under some paths add up to
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TreeSize {
+    /// sum of the sizes of the files
+    pub bytes: u64,
+    pub files: u64,
+    /// directories under the paths, not counting the paths themselves
+    pub dirs: u64,
+}
+
+/// Size of `paths` and everything under them, `bytes` is what zipping them archives. Symlinks
+/// aren't followed and entries that can't be read are left out
+pub fn tree_size(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> TreeSize {
+    let mut size = TreeSize::default();
+    for path in paths {
+        for entry in walkdir::WalkDir::new(path.as_ref())
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+        {
+            if entry.file_type().is_dir() {
+                if entry.depth() > 0 {
+                    size.dirs += 1;
+                }
+            } else if entry.file_type().is_file() {
+                size.files += 1;
+                size.bytes += entry.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            }
+        }
+    }
+    size
+}
+
+// files are archived in chunks this big, a cancel is noticed between them
+const ZIP_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Write the file at `path` to the entry started in `writer`. `archived` counts the bytes
+/// archived so far, `on_progress` gets it after every chunk
+fn archive_file(
+    path: &Path,
+    writer: &mut impl std::io::Write,
+    archived: &mut u64,
+    on_progress: &mut dyn FnMut(u64),
+    cancel: &CancellationToken,
+) -> Result<(), Error> {
+    use std::io::Read;
+    let mut file =
+        std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut buf = vec![0; ZIP_CHUNK_SIZE];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(operation_cancelled());
+        }
+        let read = match file.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                return Err(e).context(format!("Failed to read {}", path.display()))?;
+            }
+        };
+        writer
+            .write_all(&buf[..read])
+            .context(format!("Failed to write {} to archive", path.display()))?;
+        *archived += read as u64;
+        on_progress(*archived);
+    }
+}
+
+// a cancelled zip stops before its next chunk, the temporary archive is removed
+fn zip_files_inner(
+    files: &[impl AsRef<Path>],
+    dest: &Path,
+    overwrite_dest: bool,
+    on_progress: &mut dyn FnMut(u64),
+    cancel: &CancellationToken,
+) -> Result<PathBuf, Error> {
     std::fs::create_dir_all(dest.parent().context("Failed to get destination parent")?)
         .context(format!("Failed to create directory {}", dest.display()))?;
     let lodestone_tmp = path_to_tmp().clone();
@@ -328,40 +575,34 @@ pub fn zip_files(
     let tmp_archive = tempfile::NamedTempFile::new_in(lodestone_tmp)
         .context("Failed to create temporary file for zipping")?;
 
-    let mut buffer = Vec::new();
     let mut writer = zip::ZipWriter::new(&tmp_archive);
     let options = zip::write::FileOptions::default().unix_permissions(0o775);
+    // the zip64 extra field has to be reserved before the entry is written
+    let options_for = |path: &Path| -> Result<zip::write::FileOptions, Error> {
+        let len = std::fs::metadata(path)
+            .context(format!("Failed to get metadata for {}", path.display()))?
+            .len();
+        Ok(options.large_file(len > ZIP64_LARGE_FILE_THRESHOLD))
+    };
+    let mut archived = 0;
     for entry_path in files.iter().map(|f| f.as_ref()) {
+        let entry_base = entry_path
+            .parent()
+            .context(format!("Failed to get parent for {}", entry_path.display()))?;
         if entry_path.is_dir() {
-            writer
-                .add_directory(
-                    entry_path
-                        .file_name()
-                        .ok_or_else(|| eyre!("Entry has abnormal name"))?
-                        .to_str()
-                        .ok_or_else(|| eyre!("Entry has abnormal name"))?,
-                    options,
-                )
-                .context(format!(
-                    "Failed to create {} in archive",
-                    entry_path.display()
-                ))?;
-
+            // the walk yields the directory itself first, so it gets its own entry
             for child_entry in walkdir::WalkDir::new(entry_path)
                 .into_iter()
                 .filter_map(|e| e.ok())
             {
                 let child_entry_path = child_entry.path();
-                let child_entry_dest =
-                    child_entry_path
-                        .strip_prefix(entry_path.parent().context(format!(
-                            "Failed to get parent for {}",
-                            entry_path.display()
-                        ))?)
-                        .context(format!(
-                            "Failed to strip prefix for {}",
-                            child_entry_path.display()
-                        ))?;
+                let child_entry_dest = child_entry_path.strip_prefix(entry_base).context(
+                    format!("Failed to strip prefix for {}", child_entry_path.display()),
+                )?;
+                // the root of the archive has no entry of its own
+                if child_entry_dest.as_os_str().is_empty() {
+                    continue;
+                }
 
                 if child_entry_path.is_dir() {
                     writer
@@ -376,52 +617,47 @@ pub fn zip_files(
                     let child_entry_name = child_entry_dest.to_string_lossy();
 
                     writer
-                        .start_file(child_entry_name, options)
+                        .start_file(child_entry_name, options_for(child_entry_path)?)
                         .context(format!(
                             "Failed to create {} in archive",
                             child_entry_path.display()
                         ))?;
 
-                    let mut child_entry_file = std::fs::File::open(child_entry_path)
-                        .context(format!("Failed to open {}", child_entry_path.display()))?;
-                    child_entry_file
-                        .read_to_end(&mut buffer)
-                        .context(format!("Failed to read {}", child_entry_path.display()))?;
-                    writer.write_all(&buffer).context(format!(
-                        "Failed to write {} to archive",
-                        child_entry_path.display()
-                    ))?;
-                    buffer.clear();
+                    archive_file(
+                        child_entry_path,
+                        &mut writer,
+                        &mut archived,
+                        on_progress,
+                        cancel,
+                    )?;
                 }
             }
         }
 
         if entry_path.is_file() {
             let entry_name = entry_path
-                .file_name()
+                .strip_prefix(entry_base)
+                .ok()
+                .filter(|name| !name.as_os_str().is_empty())
                 .ok_or_else(|| eyre!("File to zip has no name"))?
                 .to_str()
                 .ok_or_else(|| eyre!("File to zip has abnormal name"))?;
 
-            writer.start_file(entry_name, options).context(format!(
-                "Failed to create {} in archive",
-                entry_path.display()
-            ))?;
-
-            let mut entry_file = std::fs::File::open(entry_path)
-                .context(format!("Failed to open {}", entry_path.display()))?;
-            entry_file
-                .read_to_end(&mut buffer)
-                .context(format!("Failed to read {}", entry_path.display()))?;
-            writer.write_all(&buffer).context(format!(
-                "Failed to write {} to archive",
-                entry_path.display()
-            ))?;
-            buffer.clear();
+            writer
+                .start_file(entry_name, options_for(entry_path)?)
+                .context(format!(
+                    "Failed to create {} in archive",
+                    entry_path.display()
+                ))?;
+
+            archive_file(entry_path, &mut writer, &mut archived, on_progress, cancel)?;
         }
     }
 
     writer.finish().context("Zip failed")?;
+    if cancel.is_cancelled() {
+        return Err(operation_cancelled());
+    }
     let dest = if overwrite_dest {
         dest.into()
     } else {
@@ -436,19 +672,24 @@ pub fn zip_files(
     Ok(dest)
 }
 
+/// Zip in a blocking task, `on_progress` gets the bytes archived so far, out of `tree_size`
 pub async fn zip_files_async(
     files: &[impl AsRef<Path>],
     dest: impl AsRef<Path>,
     overwrite_dest: bool,
+    mut on_progress: impl FnMut(u64) + Send + 'static,
+    cancel: CancellationToken,
 ) -> Result<PathBuf, Error> {
     let _files = files
         .iter()
         .map(|f| f.as_ref().to_owned())
         .collect::<Vec<_>>();
     let _dest = dest.as_ref().to_owned();
-    tokio::task::spawn_blocking(move || zip_files(&_files, &_dest, overwrite_dest))
-        .await
-        .context("Failed to spawn blocking task")?
+    tokio::task::spawn_blocking(move || {
+        zip_files_inner(&_files, &_dest, overwrite_dest, &mut on_progress, &cancel)
+    })
+    .await
+    .context("Failed to spawn blocking task")?
 }
 
 pub fn rand_alphanumeric(len: usize) -> String {
@@ -479,6 +720,64 @@ pub fn scoped_join_win_safe<R: AsRef<Path>, U: AsRef<Path>>(
     }
     Ok(ret)
 }
+
+// windows is limited by MAX_PATH unless long path support is enabled system wide
+#[cfg(windows)]
+pub const MAX_PATH_LENGTH: usize = 260;
+#[cfg(not(windows))]
+pub const MAX_PATH_LENGTH: usize = 4096;
+// most filesystems cap a single file name at 255
+pub const MAX_PATH_COMPONENT_LENGTH: usize = 255;
+
+fn path_length(path: &Path) -> usize {
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().count()
+    }
+    #[cfg(not(windows))]
+    {
+        path.as_os_str().len()
+    }
+}
+
+fn check_path_length_with_limit(
+    path: &Path,
+    max_path_length: usize,
+    max_component_length: usize,
+) -> Result<(), Error> {
+    let len = path_length(path);
+    if len > max_path_length {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "Path is too long ({len} > {max_path_length} characters): {}",
+                path.display()
+            ),
+        });
+    }
+    if let Some(component) = path
+        .components()
+        .find(|c| path_length(Path::new(c.as_os_str())) > max_component_length)
+    {
+        return Err(Error {
+            kind: ErrorKind::BadRequest,
+            source: eyre!(
+                "File name {} is too long (> {max_component_length} characters): {}",
+                component.as_os_str().to_string_lossy(),
+                path.display()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the path can be created on this platform,
+/// so that we can fail with a clear error instead of an opaque io error halfway through
+pub fn check_path_length(path: impl AsRef<Path>) -> Result<(), Error> {
+    check_path_length_with_limit(path.as_ref(), MAX_PATH_LENGTH, MAX_PATH_COMPONENT_LENGTH)
+}
+
 pub mod fs {
     use std::path::Path;
 
@@ -637,13 +936,78 @@ pub fn format_byte(mut bytes: u64) -> String {
     format!("{:.1} {}", bytes, unit)
 }
 
+/// Roughly how many progression updates a single transfer should produce
+pub const PROGRESS_UPDATE_TARGET: u64 = 100;
+/// Minimum time between two progression updates of the same transfer
+pub const PROGRESS_UPDATE_MIN_INTERVAL: Duration = Duration::from_millis(100);
+// step used when the total size of a transfer is unknown
+const PROGRESS_UNKNOWN_TOTAL_STEP: u64 = 1024 * 1024;
+
+/// Decides when a transfer is worth reporting progress for.
+///
+/// The step adapts to the total size so that tiny and huge transfers both produce
+/// about `PROGRESS_UPDATE_TARGET` updates, and updates are spaced by at least
+/// `PROGRESS_UPDATE_MIN_INTERVAL` so fast local copies don't flood the event broadcaster
+#[derive(Debug, Clone)]
+pub struct ProgressThrottle {
+    step: u64,
+    min_interval: Duration,
+    last_reported: u64,
+    last_sent: Option<Instant>,
+}
+
+impl ProgressThrottle {
+    pub fn new(total: Option<u64>) -> Self {
+        Self::with_target(total, PROGRESS_UPDATE_TARGET, PROGRESS_UPDATE_MIN_INTERVAL)
+    }
+
+    pub fn with_target(total: Option<u64>, target_updates: u64, min_interval: Duration) -> Self {
+        let step = match total {
+            Some(total) => (total / target_updates.max(1)).max(1),
+            None => PROGRESS_UNKNOWN_TOTAL_STEP,
+        };
+        Self {
+            step,
+            min_interval,
+            last_reported: 0,
+            last_sent: None,
+        }
+    }
+
+    /// Returns the amount progressed since the last report if an update should be sent,
+    /// `done` being the total amount transferred so far
+    pub fn report(&mut self, done: u64) -> Option<u64> {
+        self.report_at(done, Instant::now())
+    }
+
+    fn report_at(&mut self, done: u64, now: Instant) -> Option<u64> {
+        let progressed = done.saturating_sub(self.last_reported);
+        if progressed < self.step {
+            return None;
+        }
+        if let Some(last_sent) = self.last_sent {
+            if now.saturating_duration_since(last_sent) < self.min_interval {
+                return None;
+            }
+        }
+        self.last_reported = done;
+        self.last_sent = Some(now);
+        Some(progressed)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::prelude::init_paths;
-    use crate::util::{resolve_path_conflict, unzip_file, zip_files, UnzipOption};
+    use crate::util::{
+        archive_entry_count, check_path_length, check_path_length_with_limit,
+        resolve_path_conflict, tree_size, unzip_file, unzip_file_with_progress, zip_files,
+        ProgressThrottle, TreeSize, UnzipOption, MAX_PATH_LENGTH,
+    };
     use std::collections::HashSet;
     use std::io::Read;
     use std::path::PathBuf;
+    use std::time::{Duration, Instant};
     use tokio;
 
     #[tokio::test]
@@ -831,4 +1195,214 @@ mod tests {
         buf_reader.read_to_string(&mut contents).unwrap();
         assert_eq!(contents.trim(), "test2_test2_test1");
     }
+
+    #[test]
+    fn test_tree_size() {
+        let temp = tempfile::tempdir().unwrap();
+        let world = temp.path().join("world");
+        std::fs::create_dir_all(world.join("region")).unwrap();
+        std::fs::create_dir_all(world.join("playerdata")).unwrap();
+        std::fs::write(world.join("level.dat"), [0_u8; 100]).unwrap();
+        std::fs::write(world.join("region/r.0.0.mca"), [0_u8; 4096]).unwrap();
+        std::fs::write(temp.path().join("server.jar"), [0_u8; 1000]).unwrap();
+
+        // the paths themselves aren't counted as directories, a file path counts as a file
+        assert_eq!(
+            tree_size([&world, &temp.path().join("server.jar")]),
+            TreeSize {
+                bytes: 4196 + 1000,
+                files: 3,
+                dirs: 2,
+            }
+        );
+        assert_eq!(tree_size([world.join("playerdata")]), TreeSize::default());
+        assert_eq!(
+            tree_size([temp.path().join("missing")]),
+            TreeSize::default()
+        );
+    }
+
+    #[test]
+    fn test_check_path_length() {
+        let root = PathBuf::from("instances").join("my_server");
+        assert!(check_path_length_with_limit(&root.join("world/level.dat"), 64, 16).is_ok());
+
+        let nested = (0..10).fold(root.clone(), |acc, i| acc.join(format!("dir_{i}")));
+        let err = check_path_length_with_limit(&nested, 64, 16).unwrap_err();
+        assert!(matches!(err.kind, crate::error::ErrorKind::BadRequest));
+        assert!(err.to_string().contains("dir_9"));
+
+        let long_name = root.join("a".repeat(17));
+        assert!(check_path_length_with_limit(&long_name, 64, 16).is_err());
+
+        assert!(check_path_length(&root).is_ok());
+        assert!(check_path_length(root.join("a".repeat(MAX_PATH_LENGTH))).is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_check_path_length_windows() {
+        let temp = tempfile::tempdir().unwrap();
+        let nested = (0..30).fold(temp.path().to_path_buf(), |acc, i| {
+            acc.join(format!("modpack_config_{i}"))
+        });
+        assert!(nested.as_os_str().len() > MAX_PATH_LENGTH);
+        assert!(check_path_length(&nested).is_err());
+        assert!(check_path_length(temp.path().join("config.toml")).is_ok());
+    }
+
+    // feed a simulated transfer through the throttle and count the updates it lets through
+    fn count_progress_updates(total: u64, chunk: u64, time_per_chunk: Duration) -> (u64, u64) {
+        let mut throttle =
+            ProgressThrottle::with_target(Some(total), 100, Duration::from_millis(100));
+        let start = Instant::now();
+        let mut now = start;
+        let mut done = 0;
+        let mut updates = 0;
+        let mut reported = 0;
+        while done < total {
+            done = (done + chunk).min(total);
+            now += time_per_chunk;
+            if let Some(progressed) = throttle.report_at(done, now) {
+                updates += 1;
+                reported += progressed;
+            }
+        }
+        (updates, reported)
+    }
+
+    #[test]
+    fn test_progress_throttle_small_transfer() {
+        // 1 KB in 64 byte chunks over a slow link
+        let (updates, reported) = count_progress_updates(1024, 64, Duration::from_millis(200));
+        assert!((1..=100).contains(&updates), "{updates} updates");
+        assert!(reported <= 1024);
+
+        // the same transfer done instantly only produces a single update
+        let (updates, _) = count_progress_updates(1024, 64, Duration::ZERO);
+        assert_eq!(updates, 1);
+    }
+
+    #[test]
+    fn test_progress_throttle_large_transfer() {
+        let total = 10 * 1024 * 1024 * 1024;
+        // 10 GB in 8 MB chunks at ~800 MB/s
+        let (updates, reported) =
+            count_progress_updates(total, 8 * 1024 * 1024, Duration::from_millis(10));
+        assert!((50..=110).contains(&updates), "{updates} updates");
+        assert!(reported <= total);
+
+        // a fast local copy is capped by the minimum interval
+        let (updates, _) =
+            count_progress_updates(total, 8 * 1024 * 1024, Duration::from_micros(100));
+        assert!(updates <= 2, "{updates} updates");
+    }
+
+    #[test]
+    fn test_progress_throttle_unknown_total() {
+        let mut throttle = ProgressThrottle::new(None);
+        assert_eq!(throttle.report(1024), None);
+        assert_eq!(throttle.report(2 * 1024 * 1024), Some(2 * 1024 * 1024));
+    }
+
+    #[test]
+    #[ignore = "writes and extracts more than 65535 files"]
+    fn test_zip64_entry_count_round_trip() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let world = temp.path().join("world");
+        std::fs::create_dir_all(&world).unwrap();
+        // classic zip can't hold more than 65535 entries
+        let file_count = u16::MAX as usize + 10;
+        for i in 0..file_count {
+            std::fs::write(world.join(format!("{i}.dat")), "").unwrap();
+        }
+
+        let archive_path = zip_files(&[&world], temp.path().join("backup.zip"), true).unwrap();
+        let archive = zip::ZipArchive::new(std::fs::File::open(&archive_path).unwrap()).unwrap();
+        // one entry per file plus the directory itself
+        assert_eq!(archive.len(), file_count + 1);
+
+        let unzipped = temp.path().join("unzipped");
+        unzip_file(&archive_path, UnzipOption::ToDir(unzipped.clone())).unwrap();
+        assert_eq!(
+            std::fs::read_dir(unzipped.join("world")).unwrap().count(),
+            file_count
+        );
+    }
+
+    #[test]
+    fn test_unzip_progress_by_entry_count() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let world = temp.path().join("world");
+        std::fs::create_dir_all(world.join("region")).unwrap();
+        for i in 0..5 {
+            std::fs::write(world.join("region").join(format!("r.{i}.mca")), "region").unwrap();
+        }
+        std::fs::write(world.join("level.dat"), "level").unwrap();
+
+        let archive_path = zip_files(&[&world], temp.path().join("world.zip"), true).unwrap();
+        let total = archive_entry_count(&archive_path).unwrap();
+        // world, world/region, 5 region files and level.dat
+        assert_eq!(total, 8);
+
+        let mut reported = Vec::new();
+        let unzipped = temp.path().join("unzipped");
+        unzip_file_with_progress(
+            &archive_path,
+            UnzipOption::ToDir(unzipped.clone()),
+            &mut |done| reported.push(done),
+        )
+        .unwrap();
+        assert_eq!(reported, (1..=total).collect::<Vec<_>>());
+        assert_eq!(
+            std::fs::read_to_string(unzipped.join("world/region/r.4.mca")).unwrap(),
+            "region"
+        );
+
+        let tarball = temp.path().join("world.tar.gz");
+        std::fs::write(&tarball, "").unwrap();
+        assert_eq!(archive_entry_count(&tarball), None);
+    }
+
+    #[test]
+    #[ignore = "writes and extracts a file larger than 4 GiB"]
+    fn test_zip64_large_file_round_trip() {
+        let temp_lodestone_path = tempfile::tempdir().unwrap();
+        init_paths(temp_lodestone_path.path().to_path_buf());
+        let temp = tempfile::tempdir().unwrap();
+        let world = temp.path().join("world");
+        std::fs::create_dir_all(&world).unwrap();
+        let large_size = u32::MAX as u64 + 1024;
+        // sparse, so the fixture itself doesn't take up the disk space
+        std::fs::File::create(world.join("region.mca"))
+            .unwrap()
+            .set_len(large_size)
+            .unwrap();
+        std::fs::write(world.join("level.dat"), "level").unwrap();
+
+        let archive_path = zip_files(&[&world], temp.path().join("backup.zip"), true).unwrap();
+        let mut archive =
+            zip::ZipArchive::new(std::fs::File::open(&archive_path).unwrap()).unwrap();
+        assert_eq!(
+            archive.by_name("world/region.mca").unwrap().size(),
+            large_size
+        );
+
+        let unzipped = temp.path().join("unzipped");
+        unzip_file(&archive_path, UnzipOption::ToDir(unzipped.clone())).unwrap();
+        assert_eq!(
+            std::fs::metadata(unzipped.join("world/region.mca"))
+                .unwrap()
+                .len(),
+            large_size
+        );
+        assert_eq!(
+            std::fs::read_to_string(unzipped.join("world/level.dat")).unwrap(),
+            "level"
+        );
+    }
 }
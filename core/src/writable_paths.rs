@@ -0,0 +1,174 @@
+use std::path::{Component, Path, PathBuf};
+
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+use crate::error::{Error, ErrorKind};
+use crate::sidecar::{read_sidecar, remove_sidecar, write_sidecar};
+
+/// Absent while every path is writable
+pub const WRITABLE_PATHS_FILE_NAME: &str = ".lodestone_writable_paths.json";
+
+/// Directories of an instance users without the global file permission may write in, on top of
+/// the protected extension rules. Empty allows the whole instance
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[ts(export)]
+pub struct WritablePaths {
+    /// relative to the instance directory, e.g. `plugins` allows everything under `plugins/`
+    pub allowed: Vec<PathBuf>,
+}
+
+impl WritablePaths {
+    pub fn validate(&self) -> Result<(), Error> {
+        for prefix in &self.allowed {
+            let is_relative_subpath = prefix.components().next().is_some()
+                && prefix
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_)));
+            if !is_relative_subpath {
+                return Err(Error {
+                    kind: ErrorKind::BadRequest,
+                    source: eyre!(
+                        "{} is not a directory of the instance, paths are relative to it",
+                        prefix.display()
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `path`, joined to the instance directory `root`, is under an allowed directory
+    pub fn allows(&self, root: &Path, path: &Path) -> bool {
+        if self.allowed.is_empty() {
+            return true;
+        }
+        match path.strip_prefix(root) {
+            Ok(relative) => self
+                .allowed
+                .iter()
+                .any(|prefix| relative.starts_with(prefix)),
+            Err(_) => false,
+        }
+    }
+
+    /// Refuse the first of `paths` outside of the allowed directories
+    pub fn check<'a>(
+        &self,
+        root: &Path,
+        paths: impl IntoIterator<Item = &'a Path>,
+    ) -> Result<(), Error> {
+        match paths.into_iter().find(|path| !self.allows(root, path)) {
+            Some(path) => Err(Error {
+                kind: ErrorKind::PermissionDenied,
+                source: eyre!(
+                    "You can only write under {} in this instance, not to {}",
+                    self.allowed
+                        .iter()
+                        .map(|prefix| prefix.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    path.strip_prefix(root).unwrap_or(path).display()
+                ),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+pub async fn read_writable_paths(path_to_instance: &Path) -> WritablePaths {
+    match read_sidecar(path_to_instance, WRITABLE_PATHS_FILE_NAME, "writable paths").await {
+        Some(writable_paths) => writable_paths,
+        // read as nothing writable rather than everything, it is an access control
+        None if path_to_instance.join(WRITABLE_PATHS_FILE_NAME).exists() => WritablePaths {
+            allowed: vec![PathBuf::from(WRITABLE_PATHS_FILE_NAME).join("invalid")],
+        },
+        None => WritablePaths::default(),
+    }
+}
+
+pub async fn write_writable_paths(
+    path_to_instance: &Path,
+    writable_paths: &WritablePaths,
+) -> Result<(), Error> {
+    if writable_paths.allowed.is_empty() {
+        return remove_sidecar(path_to_instance, WRITABLE_PATHS_FILE_NAME).await;
+    }
+    write_sidecar(
+        path_to_instance,
+        WRITABLE_PATHS_FILE_NAME,
+        "writable paths",
+        writable_paths,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_are_limited_to_granted_subtrees() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        assert_eq!(read_writable_paths(root).await, WritablePaths::default());
+        // the current behavior, everything is writable
+        WritablePaths::default()
+            .check(root, [root.join("server.properties").as_path()])
+            .unwrap();
+
+        let writable_paths = WritablePaths {
+            allowed: vec![PathBuf::from("plugins"), PathBuf::from("config/")],
+        };
+        writable_paths.validate().unwrap();
+        write_writable_paths(root, &writable_paths).await.unwrap();
+        let writable_paths = read_writable_paths(root).await;
+
+        for allowed in [
+            "plugins",
+            "plugins/EssentialsX.jar",
+            "plugins/Essentials/config.yml",
+            "config/paper-global.yml",
+        ] {
+            assert!(
+                writable_paths.allows(root, &root.join(allowed)),
+                "{allowed}"
+            );
+        }
+        for denied in [
+            "server.properties",
+            "plugins2/evil.yml",
+            "world/level.dat",
+            ".lodestone_writable_paths.json",
+            "",
+        ] {
+            let err = writable_paths
+                .check(root, [root.join(denied).as_path()])
+                .unwrap_err();
+            assert!(matches!(err.kind, ErrorKind::PermissionDenied), "{denied}");
+        }
+        // a move or copy is refused if any of its paths is outside
+        assert!(writable_paths
+            .check(
+                root,
+                [
+                    root.join("plugins/a.yml").as_path(),
+                    root.join("a.yml").as_path()
+                ]
+            )
+            .is_err());
+
+        for invalid in ["../other-instance", "/etc", "plugins/../world", ""] {
+            let invalid = WritablePaths {
+                allowed: vec![PathBuf::from(invalid)],
+            };
+            assert!(invalid.validate().is_err(), "{:?}", invalid.allowed);
+        }
+
+        write_writable_paths(root, &WritablePaths::default())
+            .await
+            .unwrap();
+        assert!(!root.join(WRITABLE_PATHS_FILE_NAME).exists());
+    }
+}
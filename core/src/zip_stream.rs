@@ -0,0 +1,433 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use color_eyre::eyre::{eyre, Context};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use tokio_util::sync::CancellationToken;
+
+use crate::error::Error;
+use crate::util::operation_cancelled;
+
+// a zip written front to back can't go back to fill in an entry's header, its crc and sizes
+// follow the data in a descriptor and are repeated in the central directory
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x0807_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const ZIP64_END_SIGNATURE: u32 = 0x0606_4b50;
+const ZIP64_LOCATOR_SIGNATURE: u32 = 0x0706_4b50;
+const END_SIGNATURE: u32 = 0x0605_4b50;
+const ZIP64_EXTRA_TAG: u16 = 0x0001;
+const FLAG_DATA_DESCRIPTOR: u16 = 0x0008;
+const FLAG_UTF8_NAME: u16 = 0x0800;
+const VERSION_NEEDED: u16 = 20;
+const VERSION_NEEDED_ZIP64: u16 = 45;
+// made on unix, so the external attributes hold the permissions
+const VERSION_MADE_BY: u16 = (3 << 8) | VERSION_NEEDED_ZIP64;
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+// 1980-01-01 00:00, the time the archives zipped to disk get
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = (1 << 5) | 1;
+// the permissions the archives zipped to disk get
+const FILE_MODE: u32 = 0o100775;
+const DIR_MODE: u32 = 0o040775;
+const MAX_U16: u64 = u16::MAX as u64;
+const MAX_U32: u64 = u32::MAX as u64;
+// files are archived in chunks this big, a cancel is noticed between them
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// An entry already written, what its central directory record needs
+struct EntryRecord {
+    name: String,
+    flags: u16,
+    method: u16,
+    mode: u32,
+    crc: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+}
+
+/// Writer keeping count of the bytes written, the offsets of the records
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut Vec<u8>, value: u64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Zip archive written to a writer that can't seek, e.g. the body of a response
+struct ZipStreamWriter<W: Write> {
+    writer: CountingWriter<W>,
+    entries: Vec<EntryRecord>,
+}
+
+impl<W: Write> ZipStreamWriter<W> {
+    fn new(writer: W) -> Self {
+        Self {
+            writer: CountingWriter {
+                inner: writer,
+                written: 0,
+            },
+            entries: Vec::new(),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.writer
+            .write_all(buf)
+            .context("Failed to write the archive")?;
+        Ok(())
+    }
+
+    fn write_local_header(&mut self, name: &str, flags: u16, method: u16) -> Result<(), Error> {
+        let mut header = Vec::with_capacity(30 + name.len());
+        put_u32(&mut header, LOCAL_HEADER_SIGNATURE);
+        put_u16(&mut header, VERSION_NEEDED);
+        put_u16(&mut header, flags);
+        put_u16(&mut header, method);
+        put_u16(&mut header, DOS_TIME);
+        put_u16(&mut header, DOS_DATE);
+        // crc and sizes, in the data descriptor
+        put_u32(&mut header, 0);
+        put_u32(&mut header, 0);
+        put_u32(&mut header, 0);
+        put_u16(&mut header, name.len() as u16);
+        put_u16(&mut header, 0);
+        header.extend_from_slice(name.as_bytes());
+        self.write_all(&header)
+    }
+
+    fn add_directory(&mut self, name: &str) -> Result<(), Error> {
+        let name = format!("{}/", name.trim_end_matches('/'));
+        let offset = self.writer.written;
+        self.write_local_header(&name, FLAG_UTF8_NAME, METHOD_STORED)?;
+        self.entries.push(EntryRecord {
+            name,
+            flags: FLAG_UTF8_NAME,
+            method: METHOD_STORED,
+            mode: DIR_MODE,
+            crc: 0,
+            compressed_size: 0,
+            size: 0,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Deflate the file at `path` into the entry `name`. `archived` counts the bytes archived
+    /// so far, `on_progress` gets it after every chunk
+    fn add_file(
+        &mut self,
+        name: &str,
+        path: &Path,
+        archived: &mut u64,
+        on_progress: &mut dyn FnMut(u64),
+        cancel: &CancellationToken,
+    ) -> Result<(), Error> {
+        let mut file =
+            std::fs::File::open(path).context(format!("Failed to open {}", path.display()))?;
+        let flags = FLAG_DATA_DESCRIPTOR | FLAG_UTF8_NAME;
+        let offset = self.writer.written;
+        self.write_local_header(name, flags, METHOD_DEFLATED)?;
+
+        let data_start = self.writer.written;
+        let mut crc = Crc::new();
+        let mut size = 0;
+        let mut encoder = DeflateEncoder::new(&mut self.writer, Compression::default());
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            if cancel.is_cancelled() {
+                return Err(operation_cancelled());
+            }
+            let read = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(e).context(format!("Failed to read {}", path.display()))?;
+                }
+            };
+            crc.update(&buf[..read]);
+            size += read as u64;
+            encoder
+                .write_all(&buf[..read])
+                .context(format!("Failed to write {} to archive", path.display()))?;
+            *archived += read as u64;
+            on_progress(*archived);
+        }
+        encoder
+            .finish()
+            .context(format!("Failed to write {} to archive", path.display()))?;
+        let compressed_size = self.writer.written - data_start;
+
+        let mut descriptor = Vec::with_capacity(24);
+        put_u32(&mut descriptor, DATA_DESCRIPTOR_SIGNATURE);
+        put_u32(&mut descriptor, crc.sum());
+        if size >= MAX_U32 || compressed_size >= MAX_U32 {
+            put_u64(&mut descriptor, compressed_size);
+            put_u64(&mut descriptor, size);
+        } else {
+            put_u32(&mut descriptor, compressed_size as u32);
+            put_u32(&mut descriptor, size as u32);
+        }
+        self.write_all(&descriptor)?;
+        self.entries.push(EntryRecord {
+            name: name.to_string(),
+            flags,
+            method: METHOD_DEFLATED,
+            mode: FILE_MODE,
+            crc: crc.sum(),
+            compressed_size,
+            size,
+            offset,
+        });
+        Ok(())
+    }
+
+    /// Write the central directory, the archive is complete once it is
+    fn finish(mut self) -> Result<W, Error> {
+        let central_start = self.writer.written;
+        for entry in std::mem::take(&mut self.entries) {
+            // values that don't fit the record are in its zip64 extra field, in this order
+            let mut extra = Vec::new();
+            for value in [entry.size, entry.compressed_size, entry.offset] {
+                if value >= MAX_U32 {
+                    put_u64(&mut extra, value);
+                }
+            }
+            let mut record = Vec::with_capacity(46 + entry.name.len() + extra.len() + 4);
+            put_u32(&mut record, CENTRAL_HEADER_SIGNATURE);
+            put_u16(&mut record, VERSION_MADE_BY);
+            put_u16(
+                &mut record,
+                if extra.is_empty() {
+                    VERSION_NEEDED
+                } else {
+                    VERSION_NEEDED_ZIP64
+                },
+            );
+            put_u16(&mut record, entry.flags);
+            put_u16(&mut record, entry.method);
+            put_u16(&mut record, DOS_TIME);
+            put_u16(&mut record, DOS_DATE);
+            put_u32(&mut record, entry.crc);
+            put_u32(&mut record, entry.compressed_size.min(MAX_U32) as u32);
+            put_u32(&mut record, entry.size.min(MAX_U32) as u32);
+            put_u16(&mut record, entry.name.len() as u16);
+            put_u16(
+                &mut record,
+                if extra.is_empty() {
+                    0
+                } else {
+                    extra.len() as u16 + 4
+                },
+            );
+            // comment length, disk, internal attributes
+            put_u16(&mut record, 0);
+            put_u16(&mut record, 0);
+            put_u16(&mut record, 0);
+            put_u32(&mut record, entry.mode << 16);
+            put_u32(&mut record, entry.offset.min(MAX_U32) as u32);
+            record.extend_from_slice(entry.name.as_bytes());
+            if !extra.is_empty() {
+                put_u16(&mut record, ZIP64_EXTRA_TAG);
+                put_u16(&mut record, extra.len() as u16);
+                record.extend_from_slice(&extra);
+            }
+            self.write_all(&record)?;
+            self.entries.push(entry);
+        }
+        let central_size = self.writer.written - central_start;
+        let count = self.entries.len() as u64;
+
+        let mut end = Vec::new();
+        if count >= MAX_U16 || central_start >= MAX_U32 || central_size >= MAX_U32 {
+            let zip64_end = self.writer.written;
+            put_u32(&mut end, ZIP64_END_SIGNATURE);
+            // size of the rest of the record
+            put_u64(&mut end, 44);
+            put_u16(&mut end, VERSION_MADE_BY);
+            put_u16(&mut end, VERSION_NEEDED_ZIP64);
+            put_u32(&mut end, 0);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, count);
+            put_u64(&mut end, count);
+            put_u64(&mut end, central_size);
+            put_u64(&mut end, central_start);
+            put_u32(&mut end, ZIP64_LOCATOR_SIGNATURE);
+            put_u32(&mut end, 0);
+            put_u64(&mut end, zip64_end);
+            put_u32(&mut end, 1);
+        }
+        put_u32(&mut end, END_SIGNATURE);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, 0);
+        put_u16(&mut end, count.min(MAX_U16) as u16);
+        put_u16(&mut end, count.min(MAX_U16) as u16);
+        put_u32(&mut end, central_size.min(MAX_U32) as u32);
+        put_u32(&mut end, central_start.min(MAX_U32) as u32);
+        put_u16(&mut end, 0);
+        self.write_all(&end)?;
+        self.writer.flush().context("Failed to write the archive")?;
+        Ok(self.writer.inner)
+    }
+}
+
+/// Zip `files` to `dest` front to back, named relative to `base`, without staging the archive
+/// anywhere. `on_progress` gets the bytes archived so far, out of `tree_size`
+pub fn write_zip_stream<W: Write>(
+    files: &[impl AsRef<Path>],
+    base: &Path,
+    dest: W,
+    on_progress: &mut dyn FnMut(u64),
+    cancel: &CancellationToken,
+) -> Result<W, Error> {
+    let mut writer = ZipStreamWriter::new(dest);
+    let mut archived = 0;
+    let name_of = |path: &Path| -> Result<String, Error> {
+        let name = path
+            .strip_prefix(base)
+            .context(format!("Failed to strip prefix for {}", path.display()))?;
+        Ok(name
+            .to_str()
+            .ok_or_else(|| eyre!("File to zip has abnormal name"))?
+            .replace('\\', "/"))
+    };
+    for file in files.iter().map(|f| f.as_ref()) {
+        // the walk yields a directory itself first, so it gets its own entry
+        for entry in walkdir::WalkDir::new(file)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            let name = name_of(entry.path())?;
+            // the root of the archive has no entry of its own
+            if name.is_empty() {
+                continue;
+            }
+            if entry.file_type().is_dir() {
+                writer.add_directory(&name)?;
+            } else if entry.path().is_file() {
+                writer.add_file(&name, entry.path(), &mut archived, on_progress, cancel)?;
+            }
+        }
+    }
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::error::ErrorKind;
+
+    /// Something that can only be written to, like the body of a response
+    struct Sink(Vec<u8>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_streamed_zip_is_read_back() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        std::fs::create_dir_all(root.join("config/mod_a")).unwrap();
+        std::fs::write(root.join("server.properties"), "motd=hi").unwrap();
+        std::fs::write(root.join("config/mod_a/settings.toml"), "a = 1").unwrap();
+        std::fs::write(root.join("config/empty.toml"), "").unwrap();
+        let level: Vec<u8> = (0..3 * CHUNK_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        std::fs::write(root.join("level.dat"), &level).unwrap();
+
+        let mut progress = Vec::new();
+        let Sink(zipped) = write_zip_stream(
+            &[
+                root.join("config"),
+                root.join("level.dat"),
+                root.join("server.properties"),
+            ],
+            root,
+            Sink(Vec::new()),
+            &mut |archived| progress.push(archived),
+            &CancellationToken::new(),
+        )
+        .unwrap();
+        assert_eq!(progress.last(), Some(&(level.len() as u64 + 12)));
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(zipped)).unwrap();
+        let mut entries = BTreeMap::new();
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i).unwrap();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            entries.insert(entry.name().to_string(), (entry.is_dir(), content));
+        }
+        assert_eq!(
+            entries.keys().collect::<Vec<_>>(),
+            vec![
+                "config/",
+                "config/empty.toml",
+                "config/mod_a/",
+                "config/mod_a/settings.toml",
+                "level.dat",
+                "server.properties",
+            ]
+        );
+        assert!(entries["config/mod_a/"].0);
+        assert_eq!(entries["config/mod_a/settings.toml"].1, b"a = 1");
+        assert!(entries["config/empty.toml"].1.is_empty());
+        assert_eq!(entries["level.dat"].1, level);
+    }
+
+    #[test]
+    fn test_cancelled_stream_stops() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(temp.path().join("level.dat"), "level").unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = write_zip_stream(
+            &[temp.path().join("level.dat")],
+            temp.path(),
+            Sink(Vec::new()),
+            &mut |_| {},
+            &cancel,
+        )
+        .unwrap_err();
+        assert!(matches!(err.kind, ErrorKind::Conflict));
+        assert_eq!(err.source.to_string(), "Operation cancelled");
+    }
+}
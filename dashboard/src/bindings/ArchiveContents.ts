@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ArchiveContentEntry } from "./ArchiveContentEntry";
+
+export interface ArchiveContents { entries: Array<ArchiveContentEntry>, total_uncompressed_size: bigint, has_unsafe_entries: boolean, }
\ No newline at end of file
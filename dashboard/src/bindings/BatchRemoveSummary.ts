@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BatchRemoveFailure } from "./BatchRemoveFailure";
+
+export interface BatchRemoveSummary { removed: Array<string>, failed: Array<BatchRemoveFailure>, }
\ No newline at end of file
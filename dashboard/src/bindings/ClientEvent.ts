@@ -1,14 +1,7 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-import type { CausedBy } from './CausedBy';
-import type { EventInner } from './EventInner';
-import type { EventLevel } from './EventLevel';
-import type { Snowflake } from './Snowflake';
+import type { CausedBy } from "./CausedBy";
+import type { EventInner } from "./EventInner";
+import type { EventLevel } from "./EventLevel";
+import type { Snowflake } from "./Snowflake";
 
-export interface ClientEvent {
-  event_inner: EventInner;
-  details: string;
-  snowflake: Snowflake;
-  snowflake_str: string;
-  level: EventLevel;
-  caused_by: CausedBy;
-}
+export interface ClientEvent { event_inner: EventInner, details: string, snowflake: Snowflake, level: EventLevel, caused_by: CausedBy, correlation_id?: string, }
\ No newline at end of file
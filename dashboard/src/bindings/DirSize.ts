@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface DirSize { total_bytes: bigint, total_size: string, file_count: bigint, dir_count: bigint, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { ClientFile } from "./ClientFile";
+
+export interface FileTreeNode { entry: ClientFile, children: Array<FileTreeNode> | null, }
\ No newline at end of file
@@ -1,3 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { JarMirrors } from "./JarMirrors";
 
-export interface GlobalSettingsData { core_name: string, safe_mode: boolean, domain: string | null, playit_enabled: boolean, }
\ No newline at end of file
+export interface GlobalSettingsData { core_name: string, safe_mode: boolean, domain: string | null, playit_enabled: boolean, reachability_probe_url: string | null, max_fs_request_paths: number, temp_retention_secs: bigint, fs_read_timeout_secs: bigint, jar_mirrors: JarMirrors, fetch_allowed_hosts: Array<string>, storage_pools: Record<string, string>, }
\ No newline at end of file
@@ -1,22 +1,8 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
-import type { Game } from './Game';
-import type { InstanceState } from './InstanceState';
-import type { InstanceUuid } from './InstanceUuid';
-import type { Player } from './Player';
+import type { Game } from "./Game";
+import type { InstanceActor } from "./InstanceActor";
+import type { InstanceState } from "./InstanceState";
+import type { InstanceUuid } from "./InstanceUuid";
+import type { Player } from "./Player";
 
-export interface InstanceInfo {
-  uuid: InstanceUuid;
-  name: string;
-  game_type: Game;
-  description: string;
-  version: string;
-  port: number;
-  creation_time: bigint;
-  path: string;
-  auto_start: boolean;
-  restart_on_crash: boolean;
-  state: InstanceState;
-  player_count: number | null;
-  max_player_count: number | null;
-  player_list: Array<Player> | null;
-}
+export interface InstanceInfo { uuid: InstanceUuid, name: string, game_type: Game, description: string, version: string, port: number, creation_time: bigint, path: string, auto_start: boolean, restart_on_crash: boolean, locked: boolean, depends_on: Array<InstanceUuid>, stop_command: string | null, state: InstanceState, player_count: number | null, max_player_count: number | null, player_list: Array<Player> | null, created_by: InstanceActor | null, last_modified_by: InstanceActor | null, last_modified_at: bigint | null, crash_count: number, last_crash_at: bigint | null, busy: boolean, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface JarMirrors { vanilla: string | null, fabric: string | null, paper: string | null, forge: string | null, }
\ No newline at end of file
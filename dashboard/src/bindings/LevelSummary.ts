@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { SpawnPoint } from "./SpawnPoint";
+
+export interface LevelSummary { level_name: string | null, seed: string | null, spawn: SpawnPoint | null, version: string | null, data_version: number | null, hardcore: boolean | null, game_rules: Record<string, string>, }
\ No newline at end of file